@@ -0,0 +1,200 @@
+//! Structured answers for questions with a known analytic intent: the
+//! height, block times, throughput, fees and the mempool. Each matched
+//! metric is computed from the store as an [`AnalyticResult`] returned next
+//! to the prose, so dashboards read the value instead of parsing the
+//! answer, and is given to the model as a source to cite.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use dxid_config::AnalyticsSettings;
+use dxid_core::Block;
+use dxid_storage::{BlockStore, TxStore};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Height of the latest stored block.
+    ChainHeight,
+    /// Mean seconds between the blocks of the window.
+    BlockTime,
+    /// Mean transactions per block of the window.
+    Throughput,
+    /// Mean fee per transaction of the window.
+    AverageFee,
+    /// Transactions waiting for inclusion.
+    MempoolSize,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::ChainHeight => "chain_height",
+            Metric::BlockTime => "block_time",
+            Metric::Throughput => "throughput",
+            Metric::AverageFee => "average_fee",
+            Metric::MempoolSize => "mempool_size",
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            Metric::ChainHeight => "blocks",
+            Metric::BlockTime => "seconds",
+            Metric::Throughput => "transactions per block",
+            Metric::AverageFee => "per transaction",
+            Metric::MempoolSize => "transactions",
+        }
+    }
+}
+
+/// Word prefixes that ask for each metric, in the order results are returned.
+const KEYWORDS: [(Metric, &[&str]); 5] = [
+    (Metric::ChainHeight, &["height", "tip"]),
+    (Metric::BlockTime, &["interval", "time", "fast", "slow", "speed"]),
+    (Metric::Throughput, &["throughput", "tps", "busy", "activity", "volume"]),
+    (Metric::AverageFee, &["fee"]),
+    (Metric::MempoolSize, &["mempool", "pending", "backlog", "queue"]),
+];
+
+/// The metrics `question` asks for.
+pub fn intents(question: &str) -> Vec<Metric> {
+    let question = question.to_lowercase();
+    let words: Vec<&str> = question.split(|c: char| !c.is_ascii_alphanumeric()).collect();
+    KEYWORDS
+        .iter()
+        .filter(|(_, prefixes)| words.iter().any(|word| prefixes.iter().any(|p| word.starts_with(p))))
+        .map(|(metric, _)| *metric)
+        .collect()
+}
+
+/// One metric as returned with an answer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticResult {
+    pub metric: Metric,
+    pub value: f64,
+    pub unit: String,
+    /// Heights of the blocks the value was computed from, oldest first;
+    /// empty for the mempool.
+    pub evidence: Vec<u64>,
+}
+
+impl AnalyticResult {
+    fn new(metric: Metric, value: f64, evidence: Vec<u64>) -> Self {
+        let value = (value * 100.0).round() / 100.0;
+        Self { metric, value, unit: metric.unit().into(), evidence }
+    }
+
+    /// The result as a sentence, as the model is given it.
+    pub fn describe(&self) -> String {
+        let span = match self.evidence.as_slice() {
+            [] => String::new(),
+            [height] => format!(" at block {height}"),
+            [first, .., last] => format!(" over blocks {first} to {last}"),
+        };
+        format!("Metric {} is {} {}{span}.", self.metric.as_str(), self.value, self.unit)
+    }
+}
+
+pub struct Analytics {
+    blocks: Arc<dyn BlockStore>,
+    txs: Arc<dyn TxStore>,
+    settings: AnalyticsSettings,
+}
+
+impl Analytics {
+    pub fn new(blocks: Arc<dyn BlockStore>, txs: Arc<dyn TxStore>, settings: AnalyticsSettings) -> Self {
+        Self { blocks, txs, settings }
+    }
+
+    /// The metrics `question` asks for, leaving out those the store has too
+    /// few blocks or transactions for.
+    pub async fn answer(&self, question: &str) -> Result<Vec<AnalyticResult>> {
+        let metrics = intents(question);
+        let windowed = [Metric::BlockTime, Metric::Throughput, Metric::AverageFee];
+        let mut window = Vec::new();
+        if metrics.iter().any(|metric| windowed.contains(metric)) {
+            window = self.blocks.blocks_before(None, self.settings.window_blocks as i64).await?;
+            window.sort_by_key(|block| block.header.height);
+        }
+        let heights: Vec<u64> = window.iter().map(|block| block.header.height).collect();
+
+        let mut results = Vec::new();
+        for metric in metrics {
+            let result = match metric {
+                Metric::ChainHeight => self
+                    .blocks
+                    .best_height()
+                    .await?
+                    .map(|height| AnalyticResult::new(metric, height as f64, vec![height])),
+                Metric::BlockTime => {
+                    block_time(&window).map(|secs| AnalyticResult::new(metric, secs, heights.clone()))
+                }
+                Metric::Throughput if window.is_empty() => None,
+                Metric::Throughput => {
+                    let count = window.iter().map(|block| block.transactions.len()).sum::<usize>();
+                    Some(AnalyticResult::new(metric, count as f64 / window.len() as f64, heights.clone()))
+                }
+                Metric::AverageFee => {
+                    average_fee(&window).map(|fee| AnalyticResult::new(metric, fee, heights.clone()))
+                }
+                Metric::MempoolSize => {
+                    let pending = self.txs.pending_count().await?;
+                    Some(AnalyticResult::new(metric, pending as f64, Vec::new()))
+                }
+            };
+            results.extend(result);
+        }
+        Ok(results)
+    }
+}
+
+/// Mean seconds between consecutive blocks of `window`, oldest first.
+fn block_time(window: &[Block]) -> Option<f64> {
+    let [first, .., last] = window else {
+        return None;
+    };
+    let elapsed = last.header.timestamp.saturating_sub(first.header.timestamp);
+    Some(elapsed as f64 / (window.len() - 1) as f64)
+}
+
+fn average_fee(window: &[Block]) -> Option<f64> {
+    let fees: Vec<u64> = window.iter().flat_map(|block| block.transactions.iter().map(|tx| tx.fee)).collect();
+    (!fees.is_empty()).then(|| fees.iter().map(|&fee| fee as f64).sum::<f64>() / fees.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Blocks, Ledger};
+
+    #[tokio::test]
+    async fn computes_the_metrics_a_question_asks_for() {
+        let mut blocks = Blocks::with_payments(4);
+        for (height, block) in blocks.0.iter_mut() {
+            block.header.timestamp = 1_700_000_000 + height * 10 + height / 3;
+        }
+        let settings = AnalyticsSettings { enabled: true, window_blocks: 3 };
+        let analytics = Analytics::new(Arc::new(blocks), Arc::new(Ledger::default()), settings);
+
+        assert_eq!(intents("Tell me a joke"), []);
+        assert!(analytics.answer("Tell me a joke").await.unwrap().is_empty());
+
+        let results = analytics.answer("How busy is the chain, and what is the block time?").await.unwrap();
+        let metrics: Vec<Metric> = results.iter().map(|result| result.metric).collect();
+        assert_eq!(metrics, [Metric::BlockTime, Metric::Throughput]);
+        assert_eq!(results[0].value, 10.5);
+        assert_eq!(results[0].evidence, [1, 2, 3]);
+        assert_eq!(results[0].describe(), "Metric block_time is 10.5 seconds over blocks 1 to 3.");
+        assert_eq!((results[1].value, results[1].unit.as_str()), (1.0, "transactions per block"));
+
+        let results = analytics.answer("Current height, average fee and mempool?").await.unwrap();
+        assert_eq!(results[0].describe(), "Metric chain_height is 3 blocks at block 3.");
+        assert_eq!((results[1].metric, results[1].value), (Metric::AverageFee, 5.0));
+        assert_eq!((results[2].metric, results[2].value), (Metric::MempoolSize, 0.0));
+        assert!(results[2].evidence.is_empty());
+        let json = serde_json::to_value(&results[2]).unwrap();
+        assert_eq!(json["metric"], "mempool_size");
+    }
+}
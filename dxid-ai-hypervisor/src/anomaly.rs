@@ -0,0 +1,451 @@
+//! Chain anomaly detection. Each new block is reduced to a few metrics (the
+//! time since the previous block, fees, stake moved and value locked into
+//! the bridge), and each metric is judged against a rolling baseline of the
+//! blocks before it. A value that many standard deviations from the
+//! baseline mean raises an [`Alert`], once per excursion, which subscribers
+//! and webhooks receive and the configured model can explain.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use dxid_config::AnomalySettings;
+use dxid_core::bridge::{bridge_escrow_address, parse_bridge_memo};
+use dxid_core::events::{ChainWaker, EventBus};
+use dxid_core::staking::{parse_stake_memo, staking_pool_address, StakeAction};
+use dxid_core::{now_ts, Block, Transaction};
+use dxid_storage::BlockStore;
+use serde::Serialize;
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::indexer::summarize_block;
+use crate::llm::{ChatRequest, LlmProvider};
+
+/// Alerts kept for [`AnomalyMonitor::alerts`]; the oldest are dropped first.
+const MAX_ALERTS: usize = 1000;
+/// Alerts buffered per subscriber before a slow one starts missing some.
+const EVENT_CAPACITY: usize = 256;
+/// Smallest standard deviation a value is measured in, so a metric that has
+/// been constant (often zero) is not alarmed by the first unit it moves.
+const MIN_SPREAD: f64 = 1.0;
+
+const SUMMARY_PROMPT: &str = "You explain chain monitoring alerts to node operators. \
+In at most three sentences, say what the alert means and what could cause it, using only the facts given.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// The average of the latest `drift_blocks` block intervals, in seconds.
+    BlockInterval,
+    Fees,
+    /// Tokens bonded to or unbonding from validators.
+    StakeMovement,
+    /// Tokens locked in the bridge escrow for other chains.
+    BridgeVolume,
+}
+
+impl Metric {
+    fn describe(self) -> &'static str {
+        match self {
+            Metric::BlockInterval => "average block interval",
+            Metric::Fees => "fees",
+            Metric::StakeMovement => "stake moved",
+            Metric::BridgeVolume => "bridge volume",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    /// At least twice the alert threshold from the mean.
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub id: Uuid,
+    pub metric: Metric,
+    pub severity: Severity,
+    pub height: u64,
+    pub value: f64,
+    /// The baseline the value was judged against.
+    pub mean: f64,
+    pub std_dev: f64,
+    pub z_score: f64,
+    pub message: String,
+    /// The model's explanation, with `summarize` set.
+    pub summary: Option<String>,
+    pub at: u64,
+}
+
+/// What one block contributes to the watched metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockMetrics {
+    pub height: u64,
+    pub timestamp: u64,
+    pub fees: u64,
+    pub stake_moved: u64,
+    pub bridge_volume: u64,
+}
+
+pub fn block_metrics(block: &Block) -> BlockMetrics {
+    let pool = staking_pool_address();
+    let escrow = bridge_escrow_address();
+    let paid_to = |tx: &Transaction, address| -> u64 {
+        tx.outputs.iter().filter(|out| out.address == address).map(|out| out.amount).sum()
+    };
+    let mut metrics = BlockMetrics {
+        height: block.header.height,
+        timestamp: block.header.timestamp,
+        fees: 0,
+        stake_moved: 0,
+        bridge_volume: 0,
+    };
+    for tx in &block.transactions {
+        metrics.fees += tx.fee;
+        let memo = tx.memo.as_deref();
+        if let Some(stake) = memo.and_then(parse_stake_memo) {
+            metrics.stake_moved += match stake.action {
+                StakeAction::Stake | StakeAction::Delegate { .. } => paid_to(tx, pool),
+                StakeAction::Unstake { amount, .. } => amount,
+                StakeAction::WithdrawRewards => 0,
+            };
+        } else if memo.and_then(parse_bridge_memo).is_some() {
+            metrics.bridge_volume += paid_to(tx, escrow);
+        }
+    }
+    metrics
+}
+
+/// The latest values of one metric.
+struct Baseline {
+    values: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl Baseline {
+    fn new(capacity: usize) -> Self {
+        Self { values: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Adds `value`, returning the one it pushed out once full.
+    fn push(&mut self, value: f64) -> Option<f64> {
+        let dropped = if self.values.len() == self.capacity { self.values.pop_front() } else { None };
+        self.values.push_back(value);
+        dropped
+    }
+
+    fn mean(&self) -> f64 {
+        self.values.iter().sum::<f64>() / self.values.len() as f64
+    }
+
+    fn std_dev(&self, mean: f64) -> f64 {
+        let variance = self.values.iter().map(|value| (value - mean).powi(2)).sum::<f64>()
+            / (self.values.len() - 1) as f64;
+        variance.sqrt()
+    }
+}
+
+/// Judges blocks in height order against the blocks before them.
+pub struct AnomalyDetector {
+    settings: AnomalySettings,
+    intervals: Baseline,
+    /// The latest intervals, which join `intervals` as they leave.
+    recent_intervals: Baseline,
+    fees: Baseline,
+    stake: Baseline,
+    bridge: Baseline,
+    previous: Option<BlockMetrics>,
+    /// Metrics outside their baseline, alerted once until they return.
+    active: HashSet<Metric>,
+}
+
+impl AnomalyDetector {
+    pub fn new(settings: AnomalySettings) -> Self {
+        Self {
+            intervals: Baseline::new(settings.window),
+            recent_intervals: Baseline::new(settings.drift_blocks),
+            fees: Baseline::new(settings.window),
+            stake: Baseline::new(settings.window),
+            bridge: Baseline::new(settings.window),
+            previous: None,
+            active: HashSet::new(),
+            settings,
+        }
+    }
+
+    /// Adds the next block, returning the alerts it raises.
+    pub fn observe(&mut self, metrics: &BlockMetrics) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        if let Some(previous) = self.previous.filter(|previous| previous.height + 1 == metrics.height) {
+            let interval = metrics.timestamp.saturating_sub(previous.timestamp) as f64;
+            if let Some(settled) = self.recent_intervals.push(interval) {
+                self.intervals.push(settled);
+            }
+            if self.recent_intervals.values.len() == self.settings.drift_blocks {
+                // An average of n intervals varies sqrt(n) times less than one.
+                let scale = (self.settings.drift_blocks as f64).sqrt();
+                let drift = self.recent_intervals.mean();
+                alerts.extend(self.judge(Metric::BlockInterval, metrics.height, drift, scale));
+            }
+        }
+        self.previous = Some(*metrics);
+        for (metric, value) in [
+            (Metric::Fees, metrics.fees),
+            (Metric::StakeMovement, metrics.stake_moved),
+            (Metric::BridgeVolume, metrics.bridge_volume),
+        ] {
+            let value = value as f64;
+            alerts.extend(self.judge(metric, metrics.height, value, 1.0));
+            self.baseline(metric).push(value);
+        }
+        alerts
+    }
+
+    fn baseline(&mut self, metric: Metric) -> &mut Baseline {
+        match metric {
+            Metric::BlockInterval => &mut self.intervals,
+            Metric::Fees => &mut self.fees,
+            Metric::StakeMovement => &mut self.stake,
+            Metric::BridgeVolume => &mut self.bridge,
+        }
+    }
+
+    /// An alert when `value` is the first of an excursion from the baseline,
+    /// whose spread is divided by `scale`.
+    fn judge(&mut self, metric: Metric, height: u64, value: f64, scale: f64) -> Option<Alert> {
+        let threshold = self.settings.threshold;
+        let min_samples = self.settings.min_samples;
+        let baseline = self.baseline(metric);
+        let samples = baseline.values.len();
+        if samples < min_samples {
+            return None;
+        }
+        let mean = baseline.mean();
+        let std_dev = baseline.std_dev(mean);
+        let z_score = (value - mean) / (std_dev / scale).max(MIN_SPREAD);
+        if z_score.abs() < threshold {
+            self.active.remove(&metric);
+            return None;
+        }
+        if !self.active.insert(metric) {
+            return None;
+        }
+        let severity = if z_score.abs() >= 2.0 * threshold { Severity::Critical } else { Severity::Warning };
+        let message = format!(
+            "{} at block {height} is {value:.1}, {z_score:+.1} standard deviations from the mean of \
+             {mean:.1} over {samples} earlier blocks",
+            metric.describe()
+        );
+        Some(Alert {
+            id: Uuid::new_v4(),
+            metric,
+            severity,
+            height,
+            value,
+            mean,
+            std_dev,
+            z_score,
+            message,
+            summary: None,
+            at: now_ts(),
+        })
+    }
+}
+
+struct Progress {
+    detector: AnomalyDetector,
+    /// The next height to judge; unset until the baselines are warmed up.
+    next: Option<u64>,
+}
+
+/// Runs the detector over new blocks and publishes what it finds.
+pub struct AnomalyMonitor {
+    blocks: Arc<dyn BlockStore>,
+    settings: AnomalySettings,
+    llm: Option<Box<dyn LlmProvider>>,
+    progress: Mutex<Progress>,
+    alerts: RwLock<VecDeque<Alert>>,
+    events: broadcast::Sender<Alert>,
+    /// Wakes the monitoring task when blocks arrive, instead of polling.
+    chain_events: Option<EventBus>,
+}
+
+impl AnomalyMonitor {
+    pub fn new(blocks: Arc<dyn BlockStore>, settings: AnomalySettings) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let progress = Progress { detector: AnomalyDetector::new(settings.clone()), next: None };
+        Self {
+            blocks,
+            settings,
+            llm: None,
+            progress: Mutex::new(progress),
+            alerts: RwLock::new(VecDeque::new()),
+            events,
+            chain_events: None,
+        }
+    }
+
+    /// Asks `llm` to explain each alert. An alert is still published when
+    /// the model fails.
+    pub fn with_summaries(mut self, llm: Box<dyn LlmProvider>) -> Self {
+        self.llm = Some(llm);
+        self
+    }
+
+    /// Checks when `bus` announces the chain moved, rather than every
+    /// `poll_interval_ms`.
+    pub fn with_chain_events(mut self, bus: EventBus) -> Self {
+        self.chain_events = Some(bus);
+        self
+    }
+
+    /// Alerts as they are raised.
+    pub fn subscribe(&self) -> broadcast::Receiver<Alert> {
+        self.events.subscribe()
+    }
+
+    /// The latest `limit` alerts, newest first.
+    pub async fn alerts(&self, limit: usize) -> Vec<Alert> {
+        self.alerts.read().await.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Judges the blocks stored since the last check. The first check only
+    /// fills the baselines from recent history.
+    pub async fn check(&self) -> Result<Vec<Alert>> {
+        let mut progress = self.progress.lock().await;
+        let Some(best) = self.blocks.best_height().await? else {
+            return Ok(Vec::new());
+        };
+        let history = (self.settings.window + self.settings.drift_blocks) as u64;
+        let warming = progress.next.is_none();
+        let from = progress.next.unwrap_or_else(|| best.saturating_sub(history));
+        let mut raised = Vec::new();
+        for height in from..=best {
+            let Some(block) = self.blocks.get_block_by_height(height as i64).await? else {
+                break;
+            };
+            let alerts = progress.detector.observe(&block_metrics(&block));
+            if !warming {
+                raised.extend(alerts.into_iter().map(|alert| (alert, block.clone())));
+            }
+            progress.next = Some(height + 1);
+        }
+        drop(progress);
+
+        let mut published = Vec::with_capacity(raised.len());
+        for (mut alert, block) in raised {
+            warn!("chain anomaly: {}", alert.message);
+            if let Some(llm) = &self.llm {
+                alert.summary = summarize(llm.as_ref(), &alert, &block).await;
+            }
+            let mut alerts = self.alerts.write().await;
+            if alerts.len() == MAX_ALERTS {
+                alerts.pop_front();
+            }
+            alerts.push_back(alert.clone());
+            drop(alerts);
+            let _ = self.events.send(alert.clone());
+            published.push(alert);
+        }
+        Ok(published)
+    }
+
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let interval = Duration::from_millis(self.settings.poll_interval_ms);
+            let mut waker = ChainWaker::new(self.chain_events.as_ref(), interval);
+            loop {
+                if let Err(err) = self.check().await {
+                    warn!("anomaly check failed: {err:#}");
+                }
+                waker.wait().await;
+            }
+        })
+    }
+}
+
+async fn summarize(llm: &dyn LlmProvider, alert: &Alert, block: &Block) -> Option<String> {
+    let request = ChatRequest {
+        system: SUMMARY_PROMPT.into(),
+        history: Vec::new(),
+        user: format!("Alert: {}.\nBlock: {}", alert.message, summarize_block(block).swap_remove(0).text),
+    };
+    match llm.complete(&request).await {
+        Ok(summary) => Some(summary),
+        Err(err) => {
+            warn!("summarizing alert {} failed: {err}", alert.id);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dxid_core::staking::StakeMemo;
+    use dxid_core::TxOutput;
+
+    use super::*;
+    use crate::test_support::{block, spend, Blocks};
+
+    /// Blocks 30 seconds apart up to height 20 and 90 apart after it, with a
+    /// large stake at height 12.
+    fn history() -> Blocks {
+        let mut blocks = Blocks::default();
+        for height in 0..30u64 {
+            let mut tx = spend(None, Vec::new());
+            if height == 12 {
+                tx.memo = Some(StakeMemo { owner: [3; 32], action: StakeAction::Stake }.to_memo());
+                tx.outputs.push(TxOutput {
+                    address: staking_pool_address(),
+                    amount: 50_000,
+                    condition: None,
+                    spendable_after_height: None,
+                });
+            }
+            let mut block = block(height, vec![tx]);
+            block.header.timestamp = 1_700_000_000 + 30 * height.min(20) + 90 * height.saturating_sub(20);
+            blocks.0.insert(height, block);
+        }
+        blocks
+    }
+
+    #[tokio::test]
+    async fn alerts_once_per_excursion_from_the_baseline() {
+        let settings = AnomalySettings {
+            enabled: true,
+            window: 20,
+            min_samples: 5,
+            drift_blocks: 3,
+            ..AnomalySettings::default()
+        };
+        let blocks = history();
+        let mut detector = AnomalyDetector::new(settings.clone());
+        let mut raised = Vec::new();
+        for height in 0..30 {
+            raised.extend(detector.observe(&block_metrics(&blocks.0[&height])));
+        }
+        let found: Vec<_> = raised.iter().map(|alert| (alert.metric, alert.severity, alert.height)).collect();
+        assert_eq!(
+            found,
+            [
+                (Metric::StakeMovement, Severity::Critical, 12),
+                (Metric::BlockInterval, Severity::Critical, 21)
+            ]
+        );
+        assert!(raised[0].message.starts_with("stake moved at block 12 is 50000.0"), "{}", raised[0].message);
+        assert_eq!((raised[1].value, raised[1].mean), (50.0, 30.0));
+
+        // The first check only fills the baselines from recent history.
+        let monitor = AnomalyMonitor::new(Arc::new(blocks), settings);
+        assert!(monitor.check().await.unwrap().is_empty());
+        assert!(monitor.alerts(10).await.is_empty());
+        assert_eq!(monitor.progress.lock().await.next, Some(30));
+    }
+}
@@ -0,0 +1,303 @@
+//! Anthropic's messages API.
+
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+use crate::llm::{
+    error_message, send, ChatMessage, ChatRequest, ChatTurn, LlmError, LlmProvider, ToolCall, ToolSpec,
+};
+use crate::stream::{sse_tokens, StreamEvent, TokenStream};
+
+const ANTHROPIC_API_URL: &str = "https://api.anthropic.com/v1/messages";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Longest answer asked for; the API needs a limit.
+const MAX_TOKENS: u32 = 1024;
+const PROVIDER: &str = "anthropic";
+
+pub struct AnthropicChat {
+    client: Client,
+    api_key: String,
+    model: String,
+}
+
+impl AnthropicChat {
+    pub fn new(api_key: &str, model: &str) -> Self {
+        Self { client: Client::new(), api_key: api_key.to_string(), model: model.to_string() }
+    }
+
+    /// The system prompt is a field of its own rather than a message.
+    fn body(&self, request: &ChatRequest, stream: bool) -> Value {
+        json!({
+            "model": self.model,
+            "max_tokens": MAX_TOKENS,
+            "stream": stream,
+            "system": request.system,
+            "messages": messages(request)
+        })
+    }
+
+    /// Calls are `tool_use` blocks of an assistant message; their results
+    /// are `tool_result` blocks of the user message that follows.
+    fn tool_body(&self, request: &ChatRequest, history: &[ChatMessage], tools: &[ToolSpec]) -> Value {
+        let mut body = self.body(request, false);
+        let mut messages = messages(request);
+        for message in history {
+            match message {
+                ChatMessage::ToolCalls { text, calls } => {
+                    let mut content: Vec<Value> =
+                        text.iter().map(|text| json!({ "type": "text", "text": text })).collect();
+                    content.extend(calls.iter().map(|call| {
+                        json!({
+                            "type": "tool_use",
+                            "id": call.id,
+                            "name": call.name,
+                            "input": call.arguments
+                        })
+                    }));
+                    messages.push(json!({ "role": "assistant", "content": content }));
+                }
+                ChatMessage::ToolResult { call_id, content } => {
+                    let result = json!({ "type": "tool_result", "tool_use_id": call_id, "content": content });
+                    // Results of one turn's calls share a message.
+                    let last = messages.last_mut().filter(|last| last["role"] == "user");
+                    match last.and_then(|last| last["content"].as_array_mut()) {
+                        Some(blocks) => blocks.push(result),
+                        None => messages.push(json!({ "role": "user", "content": [result] })),
+                    }
+                }
+            }
+        }
+        let tools: Vec<Value> = tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.parameters
+                })
+            })
+            .collect();
+        body["messages"] = Value::Array(messages);
+        body["tools"] = Value::Array(tools);
+        body
+    }
+
+    async fn post(&self, body: &Value) -> Result<reqwest::Response, LlmError> {
+        let builder = self
+            .client
+            .post(ANTHROPIC_API_URL)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(body);
+        send(PROVIDER, builder, error).await
+    }
+
+    async fn reply(&self, body: &Value) -> Result<Value, LlmError> {
+        self.post(body)
+            .await?
+            .json()
+            .await
+            .map_err(|err| LlmError::Malformed { provider: PROVIDER, message: err.to_string() })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicChat {
+    fn name(&self) -> &'static str {
+        PROVIDER
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, LlmError> {
+        let body = self.reply(&self.body(request, false)).await?;
+        answer(&body).ok_or_else(|| LlmError::Malformed {
+            provider: PROVIDER,
+            message: "no text content in the message".into(),
+        })
+    }
+
+    async fn stream(&self, request: &ChatRequest) -> Result<TokenStream, LlmError> {
+        Ok(sse_tokens(self.post(&self.body(request, true)).await?, stream_event))
+    }
+
+    async fn converse(
+        &self,
+        request: &ChatRequest,
+        history: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatTurn, LlmError> {
+        let body = self.reply(&self.tool_body(request, history, tools)).await?;
+        turn(&body).ok_or_else(|| LlmError::Malformed {
+            provider: PROVIDER,
+            message: "neither text nor tool use in the message".into(),
+        })
+    }
+}
+
+fn messages(request: &ChatRequest) -> Vec<Value> {
+    let mut messages: Vec<Value> = request
+        .history
+        .iter()
+        .map(|(role, content)| json!({ "role": role.as_str(), "content": content }))
+        .collect();
+    messages.push(json!({ "role": "user", "content": request.user }));
+    messages
+}
+
+/// The text blocks of a message, joined.
+fn answer(body: &Value) -> Option<String> {
+    let blocks = body["content"].as_array()?;
+    let text: Vec<&str> = blocks
+        .iter()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect();
+    (!text.is_empty()).then(|| text.concat())
+}
+
+fn turn(body: &Value) -> Option<ChatTurn> {
+    let calls: Vec<ToolCall> = body["content"]
+        .as_array()?
+        .iter()
+        .filter(|block| block["type"] == "tool_use")
+        .map(|block| ToolCall {
+            id: block["id"].as_str().unwrap_or_default().to_string(),
+            name: block["name"].as_str().unwrap_or_default().to_string(),
+            arguments: block["input"].clone(),
+        })
+        .collect();
+    let text = answer(body);
+    if calls.is_empty() {
+        text.map(ChatTurn::Answer)
+    } else {
+        Some(ChatTurn::ToolCalls { text, calls })
+    }
+}
+
+fn stream_event(data: &str) -> anyhow::Result<StreamEvent> {
+    let event: Value = serde_json::from_str(data)?;
+    Ok(match event["type"].as_str() {
+        Some("content_block_delta") => match event["delta"]["text"].as_str() {
+            Some(text) if event["delta"]["type"] == "text_delta" => StreamEvent::Token(text.to_string()),
+            _ => StreamEvent::Skip,
+        },
+        Some("message_stop") => StreamEvent::Done,
+        Some("error") => {
+            let malformed = || LlmError::Malformed { provider: PROVIDER, message: data.to_string() };
+            return Err(typed_error(&event).unwrap_or_else(malformed).into());
+        }
+        _ => StreamEvent::Skip,
+    })
+}
+
+/// Errors carry a `type` naming what went wrong, also sent mid-stream where
+/// there is no status to go by.
+fn typed_error(body: &Value) -> Option<LlmError> {
+    let provider = PROVIDER;
+    let message = error_message(body, "");
+    Some(match body["error"]["type"].as_str()? {
+        "authentication_error" | "permission_error" => LlmError::Unauthorized { provider, message },
+        "billing_error" => LlmError::QuotaExceeded { provider, message },
+        "rate_limit_error" => LlmError::RateLimited { provider, message, retry_after: None },
+        "overloaded_error" | "api_error" | "timeout_error" => LlmError::Unavailable { provider, message },
+        _ => LlmError::InvalidRequest { provider, message },
+    })
+}
+
+fn error(provider: &'static str, status: StatusCode, raw: &str) -> LlmError {
+    let body: Value = serde_json::from_str(raw).unwrap_or_default();
+    typed_error(&body).unwrap_or_else(|| LlmError::from_status(provider, status, error_message(&body, raw)))
+}
+
+#[cfg(test)]
+mod tests {
+    use dxid_storage::SessionRole;
+
+    use super::*;
+
+    #[test]
+    fn maps_answers_stream_events_and_errors() {
+        let chat = AnthropicChat::new("key", "claude-model");
+        let request = ChatRequest { system: "Be brief.".into(), history: Vec::new(), user: "Height?".into() };
+        let body = chat.body(&request, false);
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["messages"], json!([{ "role": "user", "content": "Height?" }]));
+        let history = vec![(SessionRole::User, "Peers?".into()), (SessionRole::Assistant, "3 [S1].".into())];
+        let body = chat.body(&ChatRequest { history, ..request }, true);
+        assert_eq!(
+            body["messages"],
+            json!([
+                { "role": "user", "content": "Peers?" },
+                { "role": "assistant", "content": "3 [S1]." },
+                { "role": "user", "content": "Height?" }
+            ])
+        );
+
+        let message = json!({ "content": [
+            { "type": "text", "text": "Height " },
+            { "type": "tool_use", "id": "x" },
+            { "type": "text", "text": "42." }
+        ] });
+        assert_eq!(answer(&message).as_deref(), Some("Height 42."));
+        assert_eq!(answer(&json!({ "content": [] })), None);
+
+        let delta = r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"42"}}"#;
+        assert_eq!(stream_event(delta).unwrap(), StreamEvent::Token("42".into()));
+        assert_eq!(stream_event(r#"{"type":"message_start","message":{}}"#).unwrap(), StreamEvent::Skip);
+        assert_eq!(stream_event(r#"{"type":"message_stop"}"#).unwrap(), StreamEvent::Done);
+        let overloaded = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let err = stream_event(overloaded).unwrap_err();
+        assert!(err.downcast_ref::<LlmError>().is_some_and(LlmError::is_retryable));
+
+        // 529 is Anthropic's own status for overload; the type says so.
+        let err = error(PROVIDER, StatusCode::from_u16(529).unwrap(), overloaded);
+        assert!(matches!(err, LlmError::Unavailable { .. }));
+        let err = error(
+            PROVIDER,
+            StatusCode::BAD_REQUEST,
+            r#"{"type":"error","error":{"type":"billing_error","message":"Low balance"}}"#,
+        );
+        assert_eq!(err.to_string(), "anthropic quota or billing limit reached: Low balance");
+        assert!(matches!(error(PROVIDER, StatusCode::UNAUTHORIZED, "nope"), LlmError::Unauthorized { .. }));
+    }
+
+    #[test]
+    fn maps_tool_use_and_results() {
+        let chat = AnthropicChat::new("key", "claude-model");
+        let request =
+            ChatRequest { system: "Be brief.".into(), history: Vec::new(), user: "Blocks 1 and 2?".into() };
+        let call = |id: &str, height: u64| ToolCall {
+            id: id.into(),
+            name: "get_block".into(),
+            arguments: json!({ "height": height }),
+        };
+        let history = [
+            ChatMessage::ToolCalls { text: Some("Looking.".into()), calls: vec![call("a", 1), call("b", 2)] },
+            ChatMessage::ToolResult { call_id: "a".into(), content: "{}".into() },
+            ChatMessage::ToolResult { call_id: "b".into(), content: "{}".into() },
+        ];
+        let tool = ToolSpec { name: "get_block", description: "A block.", parameters: json!({}) };
+        let body = chat.tool_body(&request, &history, &[tool]);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[1]["content"][2],
+            json!({ "type": "tool_use", "id": "b", "name": "get_block", "input": { "height": 2 } })
+        );
+        assert_eq!(messages[2]["content"][1]["tool_use_id"], "b");
+        assert_eq!(body["tools"][0]["input_schema"], json!({}));
+
+        let reply = json!({ "stop_reason": "tool_use", "content": [
+            { "type": "text", "text": "Looking." },
+            { "type": "tool_use", "id": "a", "name": "get_block", "input": { "height": 1 } }
+        ] });
+        assert_eq!(
+            turn(&reply),
+            Some(ChatTurn::ToolCalls { text: Some("Looking.".into()), calls: vec![call("a", 1)] })
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! The log of what the hypervisor was asked and what it disclosed: every
+//! question, the sources sent with it, the tools it called and the answer,
+//! kept in the store for `retention_days`. Addresses, keys and configured
+//! secrets are masked before a record is stored, so the log itself can be
+//! reviewed without disclosing them again.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use dxid_config::AuditSettings;
+use dxid_core::now_ts;
+use dxid_crypto::address_from_string;
+use dxid_storage::{AiAuditRecord, AiAuditStore};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::context::ChainContext;
+use crate::tools::ToolAudit;
+
+/// Replaces each address in a redacted record.
+pub const REDACTED_ADDRESS: &str = "[address]";
+/// Replaces keys, seeds and configured secrets in a redacted record.
+pub const REDACTED_SECRET: &str = "[secret]";
+/// Configured secrets shorter than this are left alone, so a placeholder
+/// does not mask ordinary words.
+const MIN_SECRET_LEN: usize = 8;
+/// Hex runs at least this long are masked: 32-byte keys and seeds, and
+/// hashes too, which look the same.
+const MIN_HEX_SECRET: usize = 64;
+/// Prefixes of API and extended private keys.
+const KEY_PREFIXES: [&str; 3] = ["sk-", "sk_", "xprv"];
+/// Shortest API key masked by its prefix.
+const MIN_KEY_LEN: usize = 20;
+/// How often records past their retention are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Masks base58 addresses, API keys, long hex strings and the `secrets`
+/// it is given.
+pub struct Redactor {
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(mut secrets: Vec<String>) -> Self {
+        secrets.retain(|secret| secret.len() >= MIN_SECRET_LEN);
+        // Longest first, so a secret containing another is masked whole.
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        Self { secrets }
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in &self.secrets {
+            text = text.replace(secret.as_str(), REDACTED_SECRET);
+        }
+        let mut redacted = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find(is_word_char) {
+            redacted.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+            redacted.push_str(mask(&rest[..end]));
+            rest = &rest[end..];
+        }
+        redacted.push_str(rest);
+        redacted
+    }
+
+    /// `value` with every string in it redacted.
+    pub fn redact_value(&self, value: Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.redact(&text)),
+            Value::Array(values) => Value::Array(values.into_iter().map(|v| self.redact_value(v)).collect()),
+            Value::Object(fields) => {
+                Value::Object(fields.into_iter().map(|(key, v)| (key, self.redact_value(v))).collect())
+            }
+            value => value,
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn mask(word: &str) -> &str {
+    let hex = word.strip_prefix("0x").unwrap_or(word);
+    let key = word.len() >= MIN_KEY_LEN && KEY_PREFIXES.iter().any(|prefix| word.starts_with(prefix));
+    if key || (hex.len() >= MIN_HEX_SECRET && hex.chars().all(|c| c.is_ascii_hexdigit())) {
+        return REDACTED_SECRET;
+    }
+    // Base58 of 32 bytes is 32 to 44 characters.
+    if (32..=44).contains(&word.len()) && address_from_string(word).is_ok() {
+        return REDACTED_ADDRESS;
+    }
+    word
+}
+
+/// One question and what came of it, as handed to [`AuditLog::record`].
+pub struct AuditEntry<'a> {
+    pub session: Option<Uuid>,
+    pub provider: &'a str,
+    pub model: &'a str,
+    pub prompt: &'a str,
+    /// The sources sent with the question, unless it failed before.
+    pub context: Option<&'a ChainContext>,
+    pub tool_calls: &'a [ToolAudit],
+    pub response: &'a str,
+    pub error: Option<String>,
+    pub offline: bool,
+    pub streamed: bool,
+}
+
+impl<'a> AuditEntry<'a> {
+    /// An entry for `prompt` with nothing disclosed yet.
+    pub fn new(session: Option<Uuid>, provider: &'a str, model: &'a str, prompt: &'a str) -> Self {
+        Self {
+            session,
+            provider,
+            model,
+            prompt,
+            context: None,
+            tool_calls: &[],
+            response: "",
+            error: None,
+            offline: false,
+            streamed: false,
+        }
+    }
+
+    pub fn failed(self, error: Option<String>) -> Self {
+        Self { error, ..self }
+    }
+}
+
+pub struct AuditLog {
+    store: Arc<dyn AiAuditStore>,
+    settings: AuditSettings,
+    redactor: Option<Redactor>,
+}
+
+impl AuditLog {
+    /// A log kept in `store`; with `redact` set, `secrets` are masked along
+    /// with addresses and keys.
+    pub fn new(store: Arc<dyn AiAuditStore>, settings: AuditSettings, secrets: Vec<String>) -> Self {
+        let redactor = settings.redact.then(|| Redactor::new(secrets));
+        Self { store, settings, redactor }
+    }
+
+    /// Stores `entry`. A failure is logged rather than returned, so the
+    /// answer is not lost with it.
+    pub async fn record(&self, entry: AuditEntry<'_>) {
+        let context = entry.context.map_or(Value::Array(Vec::new()), |context| json!(context.sources));
+        let mut record = AiAuditRecord {
+            id: Uuid::new_v4(),
+            at: now_ts(),
+            session: entry.session,
+            provider: entry.provider.to_string(),
+            model: entry.model.to_string(),
+            prompt: entry.prompt.to_string(),
+            context,
+            tool_calls: json!(entry.tool_calls),
+            response: entry.response.to_string(),
+            offline: entry.offline,
+            streamed: entry.streamed,
+            error: entry.error,
+        };
+        if let Some(redactor) = &self.redactor {
+            record.prompt = redactor.redact(&record.prompt);
+            record.context = redactor.redact_value(record.context);
+            record.tool_calls = redactor.redact_value(record.tool_calls);
+            record.response = redactor.redact(&record.response);
+            record.error = record.error.map(|error| redactor.redact(&error));
+        }
+        if let Err(err) = self.store.append_ai_audit(&record).await {
+            warn!("could not store AI audit record {}: {err:#}", record.id);
+        }
+    }
+
+    /// Up to `limit` records from before time `before`, or the latest, newest first.
+    pub async fn records(&self, before: Option<u64>, limit: usize) -> Result<Vec<AiAuditRecord>> {
+        self.store.ai_audit(before, limit as i64).await
+    }
+
+    /// Deletes the records older than `retention_days`.
+    pub async fn prune(&self) -> Result<u64> {
+        if self.settings.retention_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = now_ts().saturating_sub(self.settings.retention_days * 24 * 3600);
+        self.store.prune_ai_audit(cutoff).await
+    }
+
+    /// Prunes the log every hour until the task is aborted.
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match self.prune().await {
+                    Ok(0) => {}
+                    Ok(pruned) => info!("pruned {pruned} AI audit records"),
+                    Err(err) => warn!("pruning the AI audit log failed: {err:#}"),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dxid_crypto::address_to_string;
+    use dxid_storage::MemoryAiAuditStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stores_records_with_addresses_and_secrets_masked() {
+        let address = address_to_string(&[4; 32]);
+        let redactor = Redactor::new(vec!["hunter2-long-password".into(), "short".into()]);
+        let text = format!(
+            "Pay {address} with key sk-abcdefghijklmnopqrstu, seed 0x{} and hunter2-long-password; short tx 1a2b.",
+            "ab".repeat(32)
+        );
+        assert_eq!(
+            redactor.redact(&text),
+            "Pay [address] with key [secret], seed [secret] and [secret]; short tx 1a2b."
+        );
+
+        let store = Arc::new(MemoryAiAuditStore::new());
+        let log = AuditLog::new(store.clone(), AuditSettings::default(), vec![]);
+        let mut context = ChainContext::default();
+        context.push("address", address.clone(), format!("Address {address} has a balance of 250."));
+        let prompt = format!("How much does {address} hold?");
+        log.record(AuditEntry {
+            context: Some(&context),
+            response: "250 [S1].",
+            offline: true,
+            ..AuditEntry::new(None, "offline", "", &prompt)
+        })
+        .await;
+
+        let records = log.records(None, 10).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].prompt, "How much does [address] hold?");
+        assert_eq!(records[0].context[0]["reference"], "[address]");
+        assert_eq!(records[0].context[0]["text"], "Address [address] has a balance of 250.");
+        assert!(records[0].offline && !records[0].streamed);
+        assert!(log.records(Some(records[0].at), 10).await.unwrap().is_empty());
+        assert_eq!(log.prune().await.unwrap(), 0);
+    }
+}
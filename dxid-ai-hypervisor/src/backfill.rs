@@ -0,0 +1,237 @@
+//! Re-embedding historical blocks, e.g. after switching embedding models or
+//! dimensions. A backfill walks a height range in batches into a namespace
+//! of its own, retrying failed batches with backoff, and records its
+//! progress in a checkpoint file so an interrupted run resumes where it
+//! stopped instead of starting over.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::indexer::ChainIndexer;
+
+#[derive(Debug, Clone)]
+pub struct BackfillOptions {
+    /// Blocks summarized and embedded between checkpoints.
+    pub blocks_per_batch: u64,
+    /// Retries of a failed batch before the backfill stops.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each one after.
+    pub retry_delay: Duration,
+    /// Where progress is kept; without one an interrupted run starts over.
+    pub checkpoint: Option<PathBuf>,
+}
+
+impl Default for BackfillOptions {
+    fn default() -> Self {
+        Self { blocks_per_batch: 20, max_retries: 5, retry_delay: Duration::from_secs(2), checkpoint: None }
+    }
+}
+
+/// Progress of a backfill, saved after every batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackfillCheckpoint {
+    pub namespace: String,
+    pub model: String,
+    pub dimensions: usize,
+    pub from_height: u64,
+    /// First height not yet embedded.
+    pub next_height: u64,
+    /// Snippets stored so far, over every run.
+    pub snippets: usize,
+}
+
+impl BackfillCheckpoint {
+    /// The checkpoint at `path`, `None` when there is none yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(
+                serde_json::from_slice(&bytes).with_context(|| format!("reading {}", path.display()))?,
+            )),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Whether this checkpoint is of the same backfill as `other`; the end
+    /// height may differ, so a resumed run can go further than the first.
+    fn matches(&self, other: &Self) -> bool {
+        self.namespace == other.namespace
+            && self.model == other.model
+            && self.dimensions == other.dimensions
+            && self.from_height == other.from_height
+    }
+}
+
+/// What a backfill run did.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BackfillReport {
+    pub namespace: String,
+    pub model: String,
+    pub from_height: u64,
+    pub to_height: u64,
+    /// Height this run picked up from, when it resumed a checkpoint.
+    pub resumed_from: Option<u64>,
+    /// Heights covered by this run, missing blocks included.
+    pub blocks: u64,
+    /// Snippets stored by this run.
+    pub snippets: usize,
+}
+
+impl ChainIndexer {
+    /// Summarizes and embeds the stored blocks from `from_height` to
+    /// `to_height` inclusive into `namespace`, resuming from
+    /// `options.checkpoint` when it holds an earlier run of the same backfill.
+    /// Point `[ai.chain_index] namespace` at it once it is done.
+    pub async fn embed_backfill(
+        &self,
+        namespace: &str,
+        from_height: u64,
+        to_height: u64,
+        options: &BackfillOptions,
+    ) -> Result<BackfillReport> {
+        if from_height > to_height {
+            bail!("from height {from_height} is above to height {to_height}");
+        }
+        let embedder = self.embedder();
+        let mut checkpoint = BackfillCheckpoint {
+            namespace: namespace.to_string(),
+            model: embedder.model().to_string(),
+            dimensions: embedder.dimensions(),
+            from_height,
+            next_height: from_height,
+            snippets: 0,
+        };
+        let mut resumed_from = None;
+        if let Some(path) = &options.checkpoint {
+            if let Some(saved) = BackfillCheckpoint::load(path)? {
+                if !saved.matches(&checkpoint) {
+                    bail!(
+                        "{} is a checkpoint of {} ({}, {} dimensions) from {}; remove it to start over",
+                        path.display(),
+                        saved.namespace,
+                        saved.model,
+                        saved.dimensions,
+                        saved.from_height
+                    );
+                }
+                resumed_from = Some(saved.next_height);
+                checkpoint = saved;
+            }
+        }
+
+        let mut report = BackfillReport {
+            namespace: namespace.to_string(),
+            model: checkpoint.model.clone(),
+            from_height,
+            to_height,
+            resumed_from,
+            blocks: 0,
+            snippets: 0,
+        };
+        let batch = options.blocks_per_batch.max(1);
+        while checkpoint.next_height <= to_height {
+            let from = checkpoint.next_height;
+            let to = to_height.min(from.saturating_add(batch - 1));
+            let stored = self.backfill_batch(namespace, from, to, options).await?;
+            checkpoint.next_height = to + 1;
+            checkpoint.snippets += stored;
+            report.blocks += to - from + 1;
+            report.snippets += stored;
+            if let Some(path) = &options.checkpoint {
+                checkpoint.save(path)?;
+            }
+            info!("backfilled {namespace} to height {to} of {to_height}");
+        }
+        Ok(report)
+    }
+
+    async fn backfill_batch(
+        &self,
+        namespace: &str,
+        from: u64,
+        to: u64,
+        options: &BackfillOptions,
+    ) -> Result<usize> {
+        let mut delay = options.retry_delay;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match self.index_blocks_into(namespace, from, to).await {
+                Ok(stored) => return Ok(stored),
+                Err(err) if attempts <= options.max_retries => {
+                    warn!("backfilling heights {from} to {to} failed, retrying in {delay:?}: {err:#}");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(err) => {
+                    return Err(err.context(format!(
+                        "backfill stopped at height {from} after {attempts} attempts; rerun to resume"
+                    )))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use dxid_config::ChainIndexSettings;
+    use dxid_storage::{MemoryVectorStore, VectorStore};
+    use dxid_vectors::normalize;
+
+    use super::*;
+    use crate::indexer::CHAIN_NAMESPACE;
+    use crate::test_support::{Blocks, LengthEmbedder};
+
+    #[tokio::test]
+    async fn resumes_an_interrupted_backfill_from_its_checkpoint() {
+        let path = std::env::temp_dir().join(format!("dxid-backfill-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let vectors = Arc::new(MemoryVectorStore::new());
+        let indexer = |embedder: LengthEmbedder| {
+            let blocks = Arc::new(Blocks::with_payments(6));
+            ChainIndexer::new(blocks, vectors.clone(), Arc::new(embedder), ChainIndexSettings::default())
+        };
+        let options = BackfillOptions {
+            blocks_per_batch: 2,
+            max_retries: 1,
+            retry_delay: Duration::ZERO,
+            checkpoint: Some(path.clone()),
+        };
+
+        // Two blocks of two snippets take two calls, so the second batch fails.
+        let err = indexer(LengthEmbedder::failing_after(3))
+            .embed_backfill("chain:v2", 0, 5, &options)
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("stopped at height 2 after 2 attempts"));
+        let saved = BackfillCheckpoint::load(&path).unwrap().unwrap();
+        assert_eq!((saved.next_height, saved.snippets), (2, 4));
+
+        let report =
+            indexer(LengthEmbedder::default()).embed_backfill("chain:v2", 0, 5, &options).await.unwrap();
+        assert_eq!((report.resumed_from, report.blocks, report.snippets), (Some(2), 4, 8));
+        let query = normalize(vec![60.0, 1.0]);
+        assert_eq!(vectors.knn_search("chain:v2", &query, 20).await.unwrap().len(), 12);
+        assert!(vectors.knn_search(CHAIN_NAMESPACE, &query, 20).await.unwrap().is_empty());
+
+        // A checkpoint of another backfill is not silently reused.
+        let err =
+            indexer(LengthEmbedder::default()).embed_backfill("chain:v3", 0, 5, &options).await.unwrap_err();
+        assert!(err.to_string().contains("remove it to start over"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
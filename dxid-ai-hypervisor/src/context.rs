@@ -0,0 +1,345 @@
+//! What the hypervisor tells the model before a question: the chain's
+//! status, its latest blocks, the indexed activity and identities most
+//! similar to the question, and the addresses and identities the question
+//! names. Each piece is a numbered source the model is asked to cite, so an
+//! answer can be checked against the chain it describes.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use anyhow::Result;
+use dxid_config::ContextSettings;
+use dxid_core::{Address, IdentityId};
+use dxid_crypto::{address_from_string, address_to_string};
+use dxid_storage::{BlockStore, IdentityStore, StateStore, TxStore, VectorStore};
+use dxid_vectors::{embed_all, normalize, Embedder};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::indexer::{attribute_list, summarize_block};
+use crate::search::similarity;
+
+/// Connected peers, read when a question is asked.
+pub type PeerCount = Arc<dyn Fn() -> usize + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContextSource {
+    /// `S1`, `S2`, ... as cited in answers.
+    pub id: String,
+    /// `status`, `block`, `activity`, `address`, `identity` or, for a
+    /// question with an analytic intent, `metric`; offline answers also
+    /// cite `validator` and `fees`.
+    pub kind: &'static str,
+    /// What the source describes: a block, embedding id, address or identity id.
+    pub reference: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChainContext {
+    pub sources: Vec<ContextSource>,
+}
+
+impl ChainContext {
+    pub(crate) fn push(&mut self, kind: &'static str, reference: String, text: String) {
+        let id = format!("S{}", self.sources.len() + 1);
+        self.sources.push(ContextSource { id, kind, reference, text });
+    }
+
+    /// One `[S1] text` line per source.
+    pub fn render(&self) -> String {
+        let lines: Vec<_> =
+            self.sources.iter().map(|source| format!("[{}] {}", source.id, source.text)).collect();
+        lines.join("\n")
+    }
+
+    /// The sources `answer` cites as `[S1]` or `[S1, S3]`, in source order.
+    pub fn cited(&self, answer: &str) -> Vec<ContextSource> {
+        let cited: BTreeSet<&str> = answer
+            .split('[')
+            .skip(1)
+            .filter_map(|part| part.split_once(']'))
+            .flat_map(|(ids, _)| ids.split(',').map(str::trim))
+            .collect();
+        self.sources.iter().filter(|source| cited.contains(source.id.as_str())).cloned().collect()
+    }
+}
+
+struct Retrieval {
+    embedder: Arc<dyn Embedder>,
+    vectors: Arc<dyn VectorStore>,
+    namespaces: Vec<String>,
+}
+
+pub struct ContextBuilder {
+    blocks: Arc<dyn BlockStore>,
+    state: Arc<dyn StateStore>,
+    txs: Arc<dyn TxStore>,
+    identities: Arc<dyn IdentityStore>,
+    settings: ContextSettings,
+    retrieval: Option<Retrieval>,
+    peers: Option<PeerCount>,
+}
+
+impl ContextBuilder {
+    pub fn new(
+        blocks: Arc<dyn BlockStore>,
+        state: Arc<dyn StateStore>,
+        txs: Arc<dyn TxStore>,
+        identities: Arc<dyn IdentityStore>,
+        settings: ContextSettings,
+    ) -> Self {
+        Self { blocks, state, txs, identities, settings, retrieval: None, peers: None }
+    }
+
+    /// Also retrieves the snippets in `namespaces` most similar to the
+    /// question; `embedder` must be the one they were embedded with.
+    pub fn with_retrieval(
+        mut self,
+        embedder: Arc<dyn Embedder>,
+        vectors: Arc<dyn VectorStore>,
+        namespaces: Vec<String>,
+    ) -> Self {
+        self.retrieval = Some(Retrieval { embedder, vectors, namespaces });
+        self
+    }
+
+    pub fn with_peer_count(mut self, peers: PeerCount) -> Self {
+        self.peers = Some(peers);
+        self
+    }
+
+    /// The sources for `question`. Retrieval is best effort: when the
+    /// embedder fails the other sources are still returned.
+    pub async fn build(&self, question: &str) -> Result<ChainContext> {
+        let mut context = ChainContext::default();
+        context.push("status", "status".into(), self.status().await?);
+        for block in self.blocks.blocks_before(None, self.settings.recent_blocks as i64).await? {
+            let summary = summarize_block(&block).swap_remove(0);
+            context.push("block", summary.id, summary.text);
+        }
+        if let Some(retrieval) = &self.retrieval {
+            match self.retrieve(retrieval, question).await {
+                Ok(found) => {
+                    for (kind, reference, text) in found {
+                        context.push(kind, reference, text);
+                    }
+                }
+                Err(err) => warn!("retrieving context failed: {err:#}"),
+            }
+        }
+        for reference in references(question, self.settings.max_references) {
+            match reference {
+                Reference::Address(address) => {
+                    let text = address_summary(self.state.as_ref(), self.txs.as_ref(), &address).await?;
+                    context.push("address", address_to_string(&address), text);
+                }
+                Reference::Identity(id) => {
+                    let text = self.identity(&id).await?;
+                    context.push("identity", id.to_string(), text);
+                }
+            }
+        }
+        Ok(context)
+    }
+
+    async fn status(&self) -> Result<String> {
+        let mut parts = vec![match self.blocks.best_height().await? {
+            Some(height) => format!("The chain is at height {height}"),
+            None => "No blocks are stored yet".to_string(),
+        }];
+        parts.push(format!("{} transactions are waiting for inclusion", self.txs.pending_count().await?));
+        if let Some(peers) = &self.peers {
+            parts.push(format!("{} peers are connected", peers()));
+        }
+        Ok(format!("{}.", parts.join(", ")))
+    }
+
+    /// `(kind, embedding id, text)` of the most similar snippets across the
+    /// namespaces, most similar first.
+    async fn retrieve(
+        &self,
+        retrieval: &Retrieval,
+        question: &str,
+    ) -> Result<Vec<(&'static str, String, String)>> {
+        let limit = self.settings.snippets;
+        if limit == 0 || retrieval.namespaces.is_empty() {
+            return Ok(Vec::new());
+        }
+        let vector = embed_all(retrieval.embedder.as_ref(), std::slice::from_ref(&question.to_string()))
+            .await?
+            .remove(0);
+        let vector = normalize(vector);
+        let mut found = Vec::new();
+        for namespace in &retrieval.namespaces {
+            for (embedding, distance) in
+                retrieval.vectors.knn_search_scored(namespace, &vector, limit as i64).await?
+            {
+                let similarity = similarity(distance);
+                if similarity >= self.settings.min_similarity {
+                    found.push((similarity, embedding));
+                }
+            }
+        }
+        found.sort_by(|a, b| b.0.total_cmp(&a.0));
+        found.truncate(limit);
+        Ok(found
+            .into_iter()
+            .map(|(_, embedding)| {
+                let (kind, text) = match embedding.metadata["text"].as_str() {
+                    Some(text) => ("activity", text.to_string()),
+                    None => ("identity", identity_snippet(&embedding.id.0, &embedding.metadata)),
+                };
+                (kind, embedding.id.0, text)
+            })
+            .collect())
+    }
+
+    async fn identity(&self, id: &IdentityId) -> Result<String> {
+        Ok(match self.identities.get_identity(id).await? {
+            Some(identity) => format!(
+                "Identity {id} is {:?} with {} keys and attributes {}.",
+                identity.status,
+                identity.public_keys.len(),
+                attribute_list(&identity)
+            ),
+            None => format!("Identity {id} is not registered."),
+        })
+    }
+}
+
+pub(crate) async fn address_summary(
+    state: &dyn StateStore,
+    txs: &dyn TxStore,
+    address: &Address,
+) -> Result<String> {
+    let balance = state.get_balance(address).await?;
+    let utxos = txs.list_utxos(address).await?;
+    Ok(format!(
+        "Address {} has a balance of {balance} in {} unspent outputs.",
+        address_to_string(address),
+        utxos.len()
+    ))
+}
+
+/// An identity embedded for duplicate detection, which stores its
+/// attributes rather than a text.
+fn identity_snippet(id: &str, metadata: &Value) -> String {
+    let status = if metadata["active"] == true { "active" } else { "revoked" };
+    let attributes: Vec<_> = metadata["attributes"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| {
+            format!("{key}={}", value.as_str().map_or_else(|| value.to_string(), str::to_string))
+        })
+        .collect();
+    format!("Identity {id} is {status} with attributes {}.", attributes.join(", "))
+}
+
+pub(crate) enum Reference {
+    Address(Address),
+    Identity(IdentityId),
+}
+
+/// Identity ids and addresses in `question`, first mentions first.
+pub(crate) fn references(question: &str, max: usize) -> Vec<Reference> {
+    let mut seen = BTreeSet::new();
+    question
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .filter(|word| seen.insert(*word))
+        .filter_map(|word| match IdentityId::parse_str(word) {
+            Ok(id) => Some(Reference::Identity(id)),
+            // Base58 of 32 bytes is 32 to 44 characters.
+            Err(_) if (32..=44).contains(&word.len()) => {
+                address_from_string(word).ok().map(Reference::Address)
+            }
+            Err(_) => None,
+        })
+        .take(max)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use dxid_core::{Identity, IdentityAttribute, IdentityStatus};
+    use dxid_storage::MemoryVectorStore;
+    use dxid_vectors::{Embedding, EmbeddingId};
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_support::{Blocks, Ledger, LengthEmbedder};
+
+    #[tokio::test]
+    async fn cites_status_blocks_retrieved_snippets_and_named_references() {
+        let alice = IdentityId::from_u128(7);
+        let attribute = IdentityAttribute { key: "country".into(), value: "DE".into(), embedding_ref: None };
+        let ledger = Arc::new(Ledger::default());
+        ledger.identities.lock().unwrap().insert(
+            alice,
+            Identity {
+                id: alice,
+                public_keys: vec![vec![1; 32]],
+                attributes: HashMap::from([("country".to_string(), attribute)]),
+                status: IdentityStatus::Active,
+            },
+        );
+        let address = [4; 32];
+        ledger.balances.lock().unwrap().insert(address, 250);
+
+        let question = format!("Who is {alice}, and what does {} own? {alice}", address_to_string(&address));
+        let vectors = Arc::new(MemoryVectorStore::new());
+        // The question embeds as [its length, 1]; the reward is too far from it.
+        for (id, values, text) in [
+            ("chain:activity:tx:1", [question.len() as f32, 1.0], "Transfer of 250."),
+            ("chain:activity:tx:2", [1.0, 9.0], "Reward."),
+        ] {
+            let embedding = Embedding {
+                id: EmbeddingId(id.into()),
+                namespace: "chain:activity".into(),
+                values: normalize(values.to_vec()),
+                metadata: json!({ "text": text }),
+            };
+            vectors.insert_embedding(&embedding).await.unwrap();
+        }
+        let context = ContextBuilder::new(
+            Arc::new(Blocks::with_payments(3)),
+            ledger.clone(),
+            ledger.clone(),
+            ledger,
+            ContextSettings { recent_blocks: 2, ..ContextSettings::default() },
+        )
+        .with_retrieval(Arc::new(LengthEmbedder::default()), vectors, vec!["chain:activity".into()])
+        .with_peer_count(Arc::new(|| 3));
+
+        let built = context.build(&question).await.unwrap();
+        let kinds: Vec<_> = built.sources.iter().map(|source| (source.id.as_str(), source.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("S1", "status"),
+                ("S2", "block"),
+                ("S3", "block"),
+                ("S4", "activity"),
+                ("S5", "identity"),
+                ("S6", "address")
+            ]
+        );
+        assert_eq!(
+            built.sources[0].text,
+            "The chain is at height 2, 0 transactions are waiting for inclusion, 3 peers are connected."
+        );
+        assert_eq!(built.sources[1].reference, "block:2");
+        assert_eq!(built.sources[3].reference, "chain:activity:tx:1");
+        assert!(built.sources[4].text.contains("is Active with 1 keys and attributes country=DE"));
+        assert!(built.sources[5].text.contains("balance of 250 in 0 unspent outputs"));
+        assert!(built.render().starts_with("[S1] The chain is at height 2"));
+
+        let cited = built.cited("Alice is German [S5] and holds 250 [S6, S4]; see [S9].");
+        let ids: Vec<_> = cited.iter().map(|source| source.id.as_str()).collect();
+        assert_eq!(ids, ["S4", "S5", "S6"]);
+    }
+}
@@ -0,0 +1,205 @@
+//! Duplicate identity detection for sybil screening. Every identity's
+//! attributes are embedded into the `identities` namespace, and each change
+//! is checked against its nearest neighbours there; active identities at or
+//! above the configured similarity are reported as possible duplicates.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+use anyhow::Result;
+use dxid_config::DedupSettings;
+use dxid_core::{now_ts, Identity, IdentityId, IdentityStatus, PossibleDuplicate};
+use dxid_storage::VectorStore;
+use dxid_vectors::{embed_all, normalize, Embedder, Embedding, EmbeddingId};
+use serde_json::json;
+use tokio::sync::{broadcast, RwLock};
+
+/// Namespace holding one attribute embedding per identity, keyed by its id.
+pub const DEDUP_NAMESPACE: &str = "identities";
+/// Reports kept for [`DedupService::reports`]; the oldest are dropped first.
+const MAX_REPORTS: usize = 1000;
+/// Reports buffered per subscriber before a slow one starts missing some.
+const EVENT_CAPACITY: usize = 256;
+
+/// What an identity is compared by: its attributes as sorted `key: value` lines.
+pub fn identity_text(identity: &Identity) -> String {
+    let mut attributes: Vec<_> = identity.attributes.values().collect();
+    attributes.sort_by(|a, b| a.key.cmp(&b.key));
+    attributes.iter().map(|a| format!("{}: {}", a.key, a.value)).collect::<Vec<_>>().join("\n")
+}
+
+pub struct DedupService {
+    embedder: Arc<dyn Embedder>,
+    store: Arc<dyn VectorStore>,
+    settings: DedupSettings,
+    reports: RwLock<VecDeque<PossibleDuplicate>>,
+    events: broadcast::Sender<PossibleDuplicate>,
+}
+
+impl DedupService {
+    /// `embedder` must produce vectors of the store's length; see
+    /// `dxid_storage::negotiate_dimensions`.
+    pub fn new(embedder: Arc<dyn Embedder>, store: Arc<dyn VectorStore>, settings: DedupSettings) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Self { embedder, store, settings, reports: RwLock::new(VecDeque::new()), events }
+    }
+
+    /// Reports as they are found.
+    pub fn subscribe(&self) -> broadcast::Receiver<PossibleDuplicate> {
+        self.events.subscribe()
+    }
+
+    /// Indexes `identity`'s current attributes and reports the active
+    /// identities that read like it, most similar first. Revoked identities
+    /// are indexed as such and neither reported nor checked.
+    pub async fn check(&self, identity: &Identity) -> Result<Vec<PossibleDuplicate>> {
+        if identity.attributes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let vector = embed_all(self.embedder.as_ref(), &[identity_text(identity)]).await?.remove(0);
+        let values = normalize(vector);
+        let active = identity.status == IdentityStatus::Active;
+        let mut found = Vec::new();
+        if active {
+            let own_id = identity.id.to_string();
+            let k = self.settings.max_candidates as i64 + 1;
+            for (candidate, distance) in self.store.knn_search_scored(DEDUP_NAMESPACE, &values, k).await? {
+                // Unit vectors at distance d have cosine similarity 1 - d²/2.
+                let similarity = 1.0 - distance * distance / 2.0;
+                if similarity < self.settings.threshold {
+                    break;
+                }
+                if candidate.id.0 == own_id || candidate.metadata["active"] != true {
+                    continue;
+                }
+                let Ok(duplicate_of) = candidate.id.0.parse::<IdentityId>() else {
+                    continue;
+                };
+                found.push(PossibleDuplicate {
+                    identity: identity.id,
+                    duplicate_of,
+                    similarity,
+                    detected_at: now_ts(),
+                });
+            }
+            found.truncate(self.settings.max_candidates);
+        }
+        let attributes: BTreeMap<_, _> =
+            identity.attributes.values().map(|a| (a.key.as_str(), a.value.as_str())).collect();
+        let embedding = Embedding {
+            id: EmbeddingId(identity.id.to_string()),
+            namespace: DEDUP_NAMESPACE.to_string(),
+            values,
+            metadata: json!({ "active": active, "attributes": attributes, "model": self.embedder.model() }),
+        };
+        self.store.insert_embedding(&embedding).await?;
+
+        let mut reports = self.reports.write().await;
+        for report in &found {
+            if reports.len() == MAX_REPORTS {
+                reports.pop_front();
+            }
+            reports.push_back(report.clone());
+            let _ = self.events.send(report.clone());
+        }
+        Ok(found)
+    }
+
+    /// The latest `limit` reports, newest first.
+    pub async fn reports(&self, limit: usize) -> Vec<PossibleDuplicate> {
+        self.reports.read().await.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Reports naming `id` on either side, newest first.
+    pub async fn reports_for(&self, id: &IdentityId) -> Vec<PossibleDuplicate> {
+        let reports = self.reports.read().await;
+        reports.iter().rev().filter(|r| r.identity == *id || r.duplicate_of == *id).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+    use dxid_core::IdentityAttribute;
+    use dxid_storage::MemoryVectorStore;
+
+    use super::*;
+
+    /// Counts of each lowercase letter, enough to tell names apart.
+    struct LetterEmbedder;
+
+    #[async_trait]
+    impl Embedder for LetterEmbedder {
+        fn model(&self) -> &str {
+            "letters"
+        }
+
+        fn dimensions(&self) -> usize {
+            26
+        }
+
+        fn max_batch(&self) -> usize {
+            8
+        }
+
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let mut counts = vec![0.0; 26];
+                    for b in text.bytes().filter(u8::is_ascii_lowercase) {
+                        counts[(b - b'a') as usize] += 1.0;
+                    }
+                    counts
+                })
+                .collect())
+        }
+    }
+
+    fn identity(attributes: &[(&str, &str)]) -> Identity {
+        let attributes: HashMap<_, _> = attributes
+            .iter()
+            .map(|(key, value)| {
+                let attribute =
+                    IdentityAttribute { key: key.to_string(), value: value.to_string(), embedding_ref: None };
+                (key.to_string(), attribute)
+            })
+            .collect();
+        Identity {
+            id: IdentityId::new_v4(),
+            public_keys: Vec::new(),
+            attributes,
+            status: IdentityStatus::Active,
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_active_look_alikes_above_the_threshold() {
+        let store = Arc::new(MemoryVectorStore::new());
+        let dedup = DedupService::new(Arc::new(LetterEmbedder), store, DedupSettings::default());
+        let mut events = dedup.subscribe();
+
+        let mut alice = identity(&[("name", "Alice Smith"), ("email", "alice@example.org")]);
+        let bob = identity(&[("name", "Robert Jones"), ("email", "bob@jones.net")]);
+        assert!(dedup.check(&alice).await.unwrap().is_empty());
+        assert!(dedup.check(&bob).await.unwrap().is_empty());
+        assert!(dedup.check(&identity(&[])).await.unwrap().is_empty());
+
+        let copy = identity(&[("name", "Alice Smith"), ("email", "alice.smith@example.org")]);
+        let found = dedup.check(&copy).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].identity, found[0].duplicate_of), (copy.id, alice.id));
+        assert!(found[0].similarity > 0.95 && found[0].similarity <= 1.0);
+        assert_eq!(events.recv().await.unwrap(), found[0]);
+        assert_eq!(dedup.reports_for(&alice.id).await, found);
+        assert!(dedup.reports_for(&bob.id).await.is_empty());
+
+        // Once revoked, alice is no longer reported and re-checking the copy finds nothing new.
+        alice.status = IdentityStatus::Revoked;
+        assert!(dedup.check(&alice).await.unwrap().is_empty());
+        assert!(dedup.check(&copy).await.unwrap().is_empty());
+        assert_eq!(dedup.reports(10).await.len(), 1);
+    }
+}
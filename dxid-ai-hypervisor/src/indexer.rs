@@ -0,0 +1,383 @@
+//! The hypervisor's memory of chain activity. New blocks are summarized as
+//! short English snippets (one for the block, one per transaction), which
+//! are embedded in batches into the configured namespace with their
+//! height, kind and text as metadata, so a question can be answered from
+//! the activity most similar to it. Identity changes, which happen off
+//! chain, are added as they are accepted.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dxid_config::ChainIndexSettings;
+use dxid_core::bridge::parse_bridge_memo;
+use dxid_core::contract::parse_contract_call;
+use dxid_core::events::{ChainWaker, EventBus};
+use dxid_core::staking::{parse_stake_memo, StakeAction};
+use dxid_core::{Address, Block, CryptoProvider, Identity, Transaction, TxHash};
+use dxid_crypto::{address_to_string, DefaultCryptoProvider};
+use dxid_storage::{BlockStore, VectorStore};
+use dxid_vectors::{embed_all, normalize, Embedder, Embedding, EmbeddingId};
+use serde_json::json;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Default namespace of the chain activity snippets.
+pub const CHAIN_NAMESPACE: &str = "chain:activity";
+/// Longest memo quoted in a transaction summary.
+const MAX_MEMO_CHARS: usize = 200;
+
+/// A summary of one piece of chain activity, ready to embed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainSnippet {
+    /// Stable so re-indexing a block replaces its snippets; stored under
+    /// `<namespace>:<id>` so namespaces never share an embedding.
+    pub id: String,
+    /// `block`, `reward`, `transfer`, `stake`, `bridge`, `contract` or `identity`.
+    pub kind: &'static str,
+    pub height: u64,
+    pub tx_hash: Option<TxHash>,
+    pub text: String,
+}
+
+impl ChainSnippet {
+    fn embedding(&self, namespace: &str, values: Vec<f32>, model: &str) -> Embedding {
+        Embedding {
+            id: EmbeddingId(format!("{namespace}:{}", self.id)),
+            namespace: namespace.to_string(),
+            values: normalize(values),
+            metadata: json!({
+                "height": self.height,
+                "kind": self.kind,
+                "tx_hash": self.tx_hash.map(hex::encode),
+                "text": self.text,
+                "model": model,
+            }),
+        }
+    }
+}
+
+fn amount_list(outputs: &[(Address, u64)]) -> String {
+    let parts: Vec<_> = outputs
+        .iter()
+        .map(|(address, amount)| format!("{amount} to {}", address_to_string(address)))
+        .collect();
+    parts.join(", ")
+}
+
+/// The snippets of `block`: the block itself, then each transaction.
+pub fn summarize_block(block: &Block) -> Vec<ChainSnippet> {
+    let header = &block.header;
+    let fees: u64 = block.transactions.iter().map(|tx| tx.fee).sum();
+    let time = DateTime::<Utc>::from_timestamp(header.timestamp as i64, 0)
+        .map_or_else(|| header.timestamp.to_string(), |time| time.to_rfc3339());
+    let mut snippets = vec![ChainSnippet {
+        id: format!("block:{}", header.height),
+        kind: "block",
+        height: header.height,
+        tx_hash: None,
+        text: format!(
+            "Block {} at {time} by validator {}: {} transactions, {fees} in fees, difficulty {}.",
+            header.height,
+            address_to_string(&header.validator),
+            block.transactions.len(),
+            header.difficulty
+        ),
+    }];
+    snippets.extend(block.transactions.iter().map(|tx| summarize_transaction(header.height, tx)));
+    snippets
+}
+
+/// What `tx` did, naming its senders, recipients and amounts.
+pub fn summarize_transaction(height: u64, tx: &Transaction) -> ChainSnippet {
+    let hash = tx.hash();
+    let crypto = DefaultCryptoProvider::new();
+    let senders: BTreeSet<Address> =
+        tx.inputs.iter().filter_map(|input| crypto.address_from_public_key(&input.public_key).ok()).collect();
+    let from = senders.iter().map(address_to_string).collect::<Vec<_>>().join(", ");
+    // Outputs back to a sender are change, not payments.
+    let paid: Vec<(Address, u64)> = tx
+        .outputs
+        .iter()
+        .filter(|out| !senders.contains(&out.address))
+        .map(|out| (out.address, out.amount))
+        .collect();
+    let total: u64 = paid.iter().map(|(_, amount)| amount).sum();
+    let memo = tx.memo.as_deref();
+    let (kind, what) = if tx.inputs.is_empty() {
+        ("reward", format!("paid {}", amount_list(&paid)))
+    } else if let Some(stake) = memo.and_then(parse_stake_memo) {
+        let owner = address_to_string(&stake.owner);
+        let what = match stake.action {
+            StakeAction::Stake => format!("{owner} staked {total}"),
+            StakeAction::Delegate { validator } => {
+                format!("{owner} delegated {total} to validator {}", address_to_string(&validator))
+            }
+            StakeAction::Unstake { validator, amount } => {
+                format!("{owner} started unbonding {amount} from validator {}", address_to_string(&validator))
+            }
+            StakeAction::WithdrawRewards => format!("{owner} withdrew staking rewards"),
+        };
+        ("stake", what)
+    } else if let Some((chain, recipient)) = memo.and_then(parse_bridge_memo) {
+        ("bridge", format!("{from} locked {total} for {recipient} on {chain}"))
+    } else if let Some(call) = memo.and_then(parse_contract_call) {
+        let contract = address_to_string(&call.contract);
+        ("contract", format!("{from} called {} on contract {contract} sending {total}", call.method))
+    } else {
+        let mut what = format!("{from} sent {}", amount_list(&paid));
+        if let Some(memo) = memo.filter(|memo| !memo.is_empty()) {
+            let memo: String = memo.chars().take(MAX_MEMO_CHARS).collect();
+            what.push_str(&format!(" with memo \"{memo}\""));
+        }
+        ("transfer", what)
+    };
+    ChainSnippet {
+        id: format!("tx:{}", hex::encode(hash)),
+        kind,
+        height,
+        tx_hash: Some(hash),
+        text: format!(
+            "{} {} at height {height}: {what}, fee {}.",
+            capitalize(kind),
+            hex::encode(hash),
+            tx.fee
+        ),
+    }
+}
+
+/// An identity after `change` (e.g. "registered", "rotated its key"),
+/// accepted while the chain was at `height`.
+pub fn summarize_identity(identity: &Identity, change: &str, height: u64) -> ChainSnippet {
+    ChainSnippet {
+        id: format!("identity:{}:{height}:{}", identity.id, change.replace(' ', "-")),
+        kind: "identity",
+        height,
+        tx_hash: None,
+        text: format!(
+            "Identity {} {change} at height {height}: status {:?}, {} keys, attributes {}.",
+            identity.id,
+            identity.status,
+            identity.public_keys.len(),
+            attribute_list(identity)
+        ),
+    }
+}
+
+/// `key=value` pairs sorted by key, or `none`.
+pub(crate) fn attribute_list(identity: &Identity) -> String {
+    let mut attributes: Vec<_> = identity.attributes.values().collect();
+    attributes.sort_by(|a, b| a.key.cmp(&b.key));
+    let attributes: Vec<_> = attributes.iter().map(|a| format!("{}={}", a.key, a.value)).collect();
+    if attributes.is_empty() {
+        "none".to_string()
+    } else {
+        attributes.join(", ")
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+/// Keeps the chain activity namespace up to date with the block store.
+pub struct ChainIndexer {
+    blocks: Arc<dyn BlockStore>,
+    vectors: Arc<dyn VectorStore>,
+    embedder: Arc<dyn Embedder>,
+    settings: ChainIndexSettings,
+    /// Next height to index; unset until the first pass, which starts after
+    /// the best stored block unless [`ChainIndexer::with_start_height`] says otherwise.
+    next: Mutex<Option<u64>>,
+    /// Wakes the indexing task when blocks arrive, instead of polling.
+    chain_events: Option<EventBus>,
+}
+
+impl ChainIndexer {
+    /// `embedder` must produce vectors of the store's length; see
+    /// `dxid_storage::negotiate_dimensions`.
+    pub fn new(
+        blocks: Arc<dyn BlockStore>,
+        vectors: Arc<dyn VectorStore>,
+        embedder: Arc<dyn Embedder>,
+        settings: ChainIndexSettings,
+    ) -> Self {
+        Self { blocks, vectors, embedder, settings, next: Mutex::new(None), chain_events: None }
+    }
+
+    /// Indexes from `height` instead of only blocks stored after the first pass.
+    pub fn with_start_height(mut self, height: u64) -> Self {
+        self.next = Mutex::new(Some(height));
+        self
+    }
+
+    /// Indexes when `bus` announces the chain moved, rather than every
+    /// `poll_interval_ms`.
+    pub fn with_chain_events(mut self, bus: EventBus) -> Self {
+        self.chain_events = Some(bus);
+        self
+    }
+
+    /// Indexes up to `blocks_per_pass` blocks not yet indexed; returns how
+    /// many snippets were stored.
+    pub async fn index_new_blocks(&self) -> Result<usize> {
+        let mut next = self.next.lock().await;
+        let Some(best) = self.blocks.best_height().await? else {
+            return Ok(0);
+        };
+        let from = *next.get_or_insert(best + 1);
+        if from > best {
+            return Ok(0);
+        }
+        let to = best.min(from + self.settings.blocks_per_pass.max(1) as u64 - 1);
+        let stored = self.index_blocks(from, to).await?;
+        *next = Some(to + 1);
+        Ok(stored)
+    }
+
+    /// Summarizes and embeds the stored blocks from `from` to `to`
+    /// inclusive, replacing earlier snippets of the same blocks; returns how
+    /// many snippets were stored.
+    pub async fn index_blocks(&self, from: u64, to: u64) -> Result<usize> {
+        self.index_blocks_into(&self.settings.namespace, from, to).await
+    }
+
+    /// [`ChainIndexer::index_blocks`] into `namespace` instead of the
+    /// configured one.
+    pub async fn index_blocks_into(&self, namespace: &str, from: u64, to: u64) -> Result<usize> {
+        let mut snippets = Vec::new();
+        for height in from..=to {
+            if let Some(block) = self.blocks.get_block_by_height(height as i64).await? {
+                snippets.extend(summarize_block(&block));
+            }
+        }
+        self.store(namespace, &snippets).await?;
+        Ok(snippets.len())
+    }
+
+    /// Records an accepted identity change.
+    pub async fn index_identity(&self, identity: &Identity, change: &str) -> Result<()> {
+        let height = self.blocks.best_height().await?.unwrap_or(0);
+        self.store(&self.settings.namespace, &[summarize_identity(identity, change, height)]).await
+    }
+
+    pub(crate) fn embedder(&self) -> &dyn Embedder {
+        self.embedder.as_ref()
+    }
+
+    async fn store(&self, namespace: &str, snippets: &[ChainSnippet]) -> Result<()> {
+        let texts: Vec<String> = snippets.iter().map(|snippet| snippet.text.clone()).collect();
+        let vectors = embed_all(self.embedder.as_ref(), &texts).await?;
+        for (snippet, values) in snippets.iter().zip(vectors) {
+            let embedding = snippet.embedding(namespace, values, self.embedder.model());
+            self.vectors.insert_embedding(&embedding).await?;
+        }
+        Ok(())
+    }
+
+    /// Indexes new blocks as the chain moves, catching up a pass at a time.
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let interval = Duration::from_millis(self.settings.poll_interval_ms);
+            let mut waker = ChainWaker::new(self.chain_events.as_ref(), interval);
+            loop {
+                loop {
+                    match self.index_new_blocks().await {
+                        Ok(0) => break,
+                        Ok(stored) => info!("embedded {stored} chain activity snippets"),
+                        Err(err) => {
+                            warn!("chain indexing failed: {err:#}");
+                            break;
+                        }
+                    }
+                }
+                waker.wait().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dxid_core::staking::StakeMemo;
+    use dxid_core::TxOutput;
+    use dxid_storage::MemoryVectorStore;
+
+    use super::*;
+    use crate::test_support::{block, spend, Blocks, LengthEmbedder};
+
+    #[test]
+    fn summaries_name_what_a_transaction_did() {
+        let sender = DefaultCryptoProvider::new().address_from_public_key(&[7; 32]).unwrap();
+        let transfer = spend(
+            Some("rent".into()),
+            vec![
+                TxOutput { address: [2; 32], amount: 40, condition: None, spendable_after_height: None },
+                TxOutput { address: sender, amount: 55, condition: None, spendable_after_height: None },
+            ],
+        );
+        let text = summarize_transaction(3, &transfer).text;
+        let expected = format!(
+            "Transfer {} at height 3: {} sent 40 to {} with memo \"rent\", fee 5.",
+            hex::encode(transfer.hash()),
+            address_to_string(&sender),
+            address_to_string(&[2; 32])
+        );
+        assert_eq!(text, expected);
+
+        let memo = StakeMemo { owner: sender, action: StakeAction::Delegate { validator: [4; 32] } };
+        let delegate = spend(
+            Some(memo.to_memo()),
+            vec![TxOutput { address: [8; 32], amount: 100, condition: None, spendable_after_height: None }],
+        );
+        let snippet = summarize_transaction(3, &delegate);
+        assert_eq!(snippet.kind, "stake");
+        let delegated = format!("delegated 100 to validator {}", address_to_string(&[4; 32]));
+        assert!(snippet.text.contains(&delegated));
+
+        let reward = Transaction {
+            inputs: vec![],
+            ..spend(
+                None,
+                vec![TxOutput {
+                    address: [2; 32],
+                    amount: 50,
+                    condition: None,
+                    spendable_after_height: None,
+                }],
+            )
+        };
+        assert_eq!(summarize_transaction(3, &reward).kind, "reward");
+        let summary = &summarize_block(&block(3, vec![transfer, reward]))[0];
+        assert!(summary.text.starts_with("Block 3 at 2023-11-14T22:13:20+00:00 by validator"));
+        assert!(summary.text.ends_with("2 transactions, 10 in fees, difficulty 1."));
+    }
+
+    #[tokio::test]
+    async fn indexes_blocks_stored_after_it_starts() {
+        let mut blocks = Blocks::default();
+        blocks.0.insert(0, block(0, vec![]));
+        let vectors = Arc::new(MemoryVectorStore::new());
+        let settings = ChainIndexSettings { blocks_per_pass: 2, ..ChainIndexSettings::default() };
+        let embedder = Arc::new(LengthEmbedder::default());
+        let indexer =
+            ChainIndexer::new(Arc::new(blocks), vectors.clone(), embedder, settings).with_start_height(1);
+        assert_eq!(indexer.index_new_blocks().await.unwrap(), 0);
+
+        let indexer = ChainIndexer { blocks: Arc::new(Blocks::with_payments(4)), ..indexer };
+        assert_eq!(indexer.index_new_blocks().await.unwrap(), 4);
+        assert_eq!(indexer.index_new_blocks().await.unwrap(), 2);
+        assert_eq!(indexer.index_new_blocks().await.unwrap(), 0);
+
+        let found = vectors.knn_search(CHAIN_NAMESPACE, &normalize(vec![60.0, 1.0]), 10).await.unwrap();
+        assert_eq!(found.len(), 6);
+        let heights: BTreeSet<u64> = found.iter().map(|e| e.metadata["height"].as_u64().unwrap()).collect();
+        assert_eq!(heights, BTreeSet::from([1, 2, 3]));
+        assert!(found.iter().all(|e| e.metadata["text"].as_str().is_some_and(|text| !text.is_empty())));
+        assert!(found.iter().any(|e| e.id.0 == "chain:activity:block:3"));
+    }
+}
@@ -1,51 +1,434 @@
-use anyhow::Result;
+pub mod analytics;
+pub mod anomaly;
+mod anthropic;
+pub mod audit;
+pub mod backfill;
+pub mod context;
+pub mod dedup;
+pub mod indexer;
+pub mod llm;
+pub mod offline;
+mod openai;
+pub mod search;
+pub mod session;
+pub mod stream;
+#[cfg(test)]
+mod test_support;
+pub mod tools;
+
+use anyhow::{anyhow, Result};
 use dxid_config::AiConfig;
-use dxid_storage::PgStore;
-use reqwest::Client;
-use serde_json::json;
-use std::sync::Arc;
+use dxid_storage::{AiAuditRecord, PgStore, SessionRecord};
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+use uuid::Uuid;
+
+pub use analytics::{AnalyticResult, Analytics, Metric};
+pub use anomaly::{Alert, AnomalyDetector, AnomalyMonitor};
+pub use anthropic::AnthropicChat;
+pub use audit::{AuditEntry, AuditLog, Redactor};
+pub use backfill::{BackfillOptions, BackfillReport};
+pub use context::{ChainContext, ContextBuilder, ContextSource, PeerCount};
+pub use dedup::DedupService;
+pub use indexer::ChainIndexer;
+pub use llm::{build_llm, ChatMessage, ChatRequest, ChatTurn, LlmError, LlmProvider, ToolCall, ToolSpec};
+pub use offline::{OfflineAnswers, OFFLINE_MARKER};
+pub use openai::OpenAiChat;
+pub use search::{HybridSearch, RankedResult, ScoreBreakdown, SearchQuery};
+pub use session::Sessions;
+pub use stream::TokenStream;
+pub use tools::{ChainTools, ToolAudit, ToolOutcome, ToolRunner};
+
+const SYSTEM_PROMPT: &str = "You are the dxid AI hypervisor providing concise chain analytics. \
+Answer from the numbered sources and cite each fact with its source id in brackets, e.g. [S2]. \
+If the sources do not cover the question, say so.";
+
+/// An answer, the sources it cites and the tools called for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Answer {
+    pub answer: String,
+    pub sources: Vec<ContextSource>,
+    pub tool_calls: Vec<ToolAudit>,
+    /// The session the exchange was recorded in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<Uuid>,
+    /// Answered by [`OfflineAnswers`] because no model could.
+    pub offline: bool,
+    /// Metrics for the analytic intents the question matched.
+    pub analytics: Vec<AnalyticResult>,
+}
+
+/// An answer streaming in, with the context it was asked in so the
+/// sources it cites can be picked out once it is complete.
+pub struct AnswerStream {
+    pub context: ChainContext,
+    pub tokens: TokenStream,
+    pub offline: bool,
+    pub analytics: Vec<AnalyticResult>,
+}
 
 pub struct Hypervisor {
-    cfg: AiConfig,
-    client: Client,
-    store: Arc<PgStore>,
+    llm: RwLock<Option<Arc<dyn LlmProvider>>>,
+    context: ContextBuilder,
+    tools: Option<ToolRunner>,
+    sessions: Option<Sessions>,
+    offline: Option<OfflineAnswers>,
+    audit: Option<Arc<AuditLog>>,
+    analytics: Option<Analytics>,
 }
 
 impl Hypervisor {
-    pub fn new(cfg: AiConfig, store: Arc<PgStore>) -> Self {
+    /// Asks the provider `cfg` selects, with context from `store` but
+    /// without retrieval; see [`ContextBuilder::with_retrieval`]. Sessions
+    /// are kept in `store`, tools are offered when `[ai.tools]` is enabled,
+    /// with `[ai.offline]` enabled a missing provider is answered for from
+    /// the store, with `[ai.audit]` enabled every question is logged there,
+    /// and with `[ai.analytics]` enabled metrics are returned with answers.
+    pub fn new(cfg: AiConfig, store: Arc<PgStore>) -> Result<Self> {
+        let context = ContextBuilder::new(
+            store.clone(),
+            store.clone(),
+            store.clone(),
+            store.clone(),
+            cfg.context.clone(),
+        );
+        let mut hypervisor = match build_llm(&cfg) {
+            Ok(llm) => Self::with_provider(llm, context),
+            Err(err) if cfg.offline.enabled => {
+                warn!("no AI provider, answering in offline mode: {err:#}");
+                Self::without_provider(context)
+            }
+            Err(err) => return Err(err),
+        };
+        hypervisor = hypervisor.with_sessions(Sessions::new(store.clone(), cfg.sessions.clone()));
+        if cfg.offline.enabled {
+            let offline = OfflineAnswers::new(
+                store.clone(),
+                store.clone(),
+                store.clone(),
+                store.clone(),
+                cfg.offline.clone(),
+            );
+            hypervisor = hypervisor.with_offline(offline);
+        }
+        if cfg.analytics.enabled {
+            let analytics = Analytics::new(store.clone(), store.clone(), cfg.analytics.clone());
+            hypervisor = hypervisor.with_analytics(analytics);
+        }
+        if cfg.audit.enabled {
+            let audit = AuditLog::new(store.clone(), cfg.audit.clone(), cfg.secrets());
+            hypervisor = hypervisor.with_audit(Arc::new(audit));
+        }
+        if !cfg.tools.enabled {
+            return Ok(hypervisor);
+        }
+        let tools = ChainTools::new(store.clone(), store.clone(), store.clone(), store);
+        Ok(hypervisor.with_tools(ToolRunner::new(tools, cfg.tools)))
+    }
+
+    pub fn with_provider(llm: Box<dyn LlmProvider>, context: ContextBuilder) -> Self {
+        Self {
+            llm: RwLock::new(Some(Arc::from(llm))),
+            context,
+            tools: None,
+            sessions: None,
+            offline: None,
+            audit: None,
+            analytics: None,
+        }
+    }
+
+    /// A hypervisor with no model, which answers only through
+    /// [`with_offline`](Self::with_offline).
+    pub fn without_provider(context: ContextBuilder) -> Self {
         Self {
-            cfg,
-            client: Client::new(),
-            store,
+            llm: RwLock::new(None),
+            context,
+            tools: None,
+            sessions: None,
+            offline: None,
+            audit: None,
+            analytics: None,
+        }
+    }
+
+    /// Lets the model call `tools` before answering. Streamed answers do
+    /// not use them.
+    pub fn with_tools(mut self, tools: ToolRunner) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Keeps conversations in `sessions`, so a question asked in one is
+    /// answered with what was said before.
+    pub fn with_sessions(mut self, sessions: Sessions) -> Self {
+        self.sessions = Some(sessions);
+        self
+    }
+
+    /// Answers from the store when no provider is configured or it cannot
+    /// be reached, marking the answers as offline.
+    pub fn with_offline(mut self, offline: OfflineAnswers) -> Self {
+        self.offline = Some(offline);
+        self
+    }
+
+    /// Logs every question and what was disclosed in answer to `audit`.
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Computes the metrics a question asks for and returns them with the
+    /// answer, as sources for the model too.
+    pub fn with_analytics(mut self, analytics: Analytics) -> Self {
+        self.analytics = Some(analytics);
+        self
+    }
+
+    /// Answers with `llm` from the next question on, or only offline with
+    /// `None`; questions being answered finish with the provider they began with.
+    pub fn set_provider(&self, llm: Option<Box<dyn LlmProvider>>) {
+        *self.llm.write().unwrap() = llm.map(Arc::from);
+    }
+
+    fn current(&self) -> Option<Arc<dyn LlmProvider>> {
+        self.llm.read().unwrap().clone()
+    }
+
+    fn llm(&self) -> Result<Arc<dyn LlmProvider>> {
+        self.current().ok_or_else(|| anyhow!("no AI provider is configured"))
+    }
+
+    /// The offline answers to fall back on after `err`, or `err` if it is
+    /// not one they stand in for.
+    fn fallback(&self, err: anyhow::Error) -> Result<&OfflineAnswers> {
+        let unavailable =
+            self.current().is_none() || err.downcast_ref::<LlmError>().is_some_and(LlmError::is_unavailable);
+        match &self.offline {
+            Some(offline) if unavailable => {
+                warn!("answering in offline mode: {err:#}");
+                Ok(offline)
+            }
+            _ => Err(err),
+        }
+    }
+
+    fn sessions(&self) -> Result<&Sessions> {
+        self.sessions.as_ref().ok_or_else(|| anyhow!("conversation sessions are not enabled"))
+    }
+
+    async fn metrics(&self, prompt: &str) -> Result<Vec<AnalyticResult>> {
+        match &self.analytics {
+            Some(analytics) => analytics.answer(prompt).await,
+            None => Ok(Vec::new()),
         }
     }
 
+    async fn request(
+        &self,
+        session: Option<Uuid>,
+        prompt: &str,
+        analytics: &[AnalyticResult],
+    ) -> Result<(ChainContext, ChatRequest)> {
+        let mut context = self.context.build(prompt).await?;
+        for result in analytics {
+            context.push("metric", result.metric.as_str().into(), result.describe());
+        }
+        let mut request = ChatRequest {
+            system: SYSTEM_PROMPT.into(),
+            history: Vec::new(),
+            user: format!("Sources:\n{}\n\nQuestion: {prompt}", context.render()),
+        };
+        if let Some(id) = session {
+            let session = self.sessions()?.open(id).await?;
+            if let Some(summary) = &session.summary {
+                request.system.push_str(&format!("\n\nEarlier in this conversation: {summary}"));
+            }
+            request.history = session::window(&session);
+        }
+        Ok((context, request))
+    }
+
     pub async fn query(&self, prompt: &str) -> Result<String> {
-        // Build synthetic context
-        let summary = json!({
-            "height": 0,
-            "peers": 0,
-            "prompt": prompt,
-        });
-        let body = json!({
-            "model": self.cfg.model,
-            "messages": [
-                {"role": "system", "content": "You are the dxid AI hypervisor providing concise chain analytics."},
-                {"role": "user", "content": format!("Context: {summary}. Question: {prompt}")}
-            ]
-        });
-        let resp = self
-            .client
-            .post("https://api.openai.com/v1/chat/completions")
-            .bearer_auth(&self.cfg.openai_api_key)
-            .json(&body)
-            .send()
-            .await?;
-        let val: serde_json::Value = resp.json().await?;
-        let answer = val["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("No answer")
-            .to_string();
+        Ok(self.ask(prompt).await?.answer)
+    }
+
+    /// Answers `prompt` with the sources the answer cites.
+    pub async fn ask(&self, prompt: &str) -> Result<Answer> {
+        self.ask_in(None, prompt).await
+    }
+
+    /// Like [`ask`](Self::ask), continuing `session` when given: earlier
+    /// messages are sent with the question, and the exchange is recorded.
+    pub async fn ask_in(&self, session: Option<Uuid>, prompt: &str) -> Result<Answer> {
+        let result = match self.answer(session, prompt).await {
+            Err(err) => match self.fallback(err) {
+                Ok(offline) => self.answer_offline(offline, session, prompt).await,
+                Err(err) => Err(err),
+            },
+            answered => answered,
+        };
+        let (context, answer) = match result {
+            Ok(answered) => answered,
+            Err(err) => {
+                let (provider, model) = self.provider(false);
+                let error = Some(format!("{err:#}"));
+                let entry = AuditEntry::new(session, provider, &model, prompt).failed(error);
+                self.audit(entry).await;
+                return Err(err);
+            }
+        };
+        let (provider, model) = self.provider(answer.offline);
+        let entry = AuditEntry {
+            context: Some(&context),
+            tool_calls: &answer.tool_calls,
+            response: &answer.answer,
+            offline: answer.offline,
+            ..AuditEntry::new(session, provider, &model, prompt)
+        };
+        self.audit(entry).await;
+        if let Some(id) = session {
+            self.remember(id, prompt, &answer.answer).await?;
+        }
         Ok(answer)
     }
+
+    async fn answer(&self, session: Option<Uuid>, prompt: &str) -> Result<(ChainContext, Answer)> {
+        let llm = self.llm()?;
+        let analytics = self.metrics(prompt).await?;
+        let (context, request) = self.request(session, prompt, &analytics).await?;
+        let (answer, tool_calls) = match &self.tools {
+            Some(tools) => tools.answer(llm.as_ref(), &request).await?,
+            None => (llm.complete(&request).await?, Vec::new()),
+        };
+        let sources = context.cited(&answer);
+        Ok((context, Answer { sources, answer, tool_calls, session, offline: false, analytics }))
+    }
+
+    async fn answer_offline(
+        &self,
+        offline: &OfflineAnswers,
+        session: Option<Uuid>,
+        prompt: &str,
+    ) -> Result<(ChainContext, Answer)> {
+        let (context, answer) = offline.answer(prompt).await?;
+        let sources = context.cited(&answer);
+        let analytics = self.metrics(prompt).await?;
+        Ok((context, Answer { answer, sources, tool_calls: Vec::new(), session, offline: true, analytics }))
+    }
+
+    async fn remember(&self, id: Uuid, prompt: &str, answer: &str) -> Result<()> {
+        self.sessions()?.record(self.current().as_deref(), id, prompt, answer).await
+    }
+
+    /// The provider and model an answer is logged as coming from.
+    fn provider(&self, offline: bool) -> (&'static str, String) {
+        match self.current() {
+            _ if offline => ("offline", String::new()),
+            Some(llm) => (llm.name(), llm.model().to_string()),
+            None => ("none", String::new()),
+        }
+    }
+
+    async fn audit(&self, entry: AuditEntry<'_>) {
+        if let Some(audit) = &self.audit {
+            audit.record(entry).await;
+        }
+    }
+
+    /// Audit records from before time `before`, newest first; `None` when
+    /// the audit log is disabled.
+    pub async fn audit_log(&self, before: Option<u64>, limit: usize) -> Result<Option<Vec<AiAuditRecord>>> {
+        match &self.audit {
+            Some(audit) => Ok(Some(audit.records(before, limit).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Session `id` with every message, or `None` if it has not been used.
+    pub async fn session(&self, id: &Uuid) -> Result<Option<SessionRecord>> {
+        self.sessions()?.get(id).await
+    }
+
+    /// The latest `limit` tool calls, newest first; `None` without tools.
+    pub async fn tool_audit(&self, limit: usize) -> Option<Vec<ToolAudit>> {
+        match &self.tools {
+            Some(tools) => Some(tools.audit(limit).await),
+            None => None,
+        }
+    }
+
+    /// Answers `prompt` token by token as the model writes. Dropping the
+    /// stream cancels the request.
+    pub async fn query_stream(&self, prompt: &str) -> Result<TokenStream> {
+        Ok(self.ask_stream(prompt).await?.tokens)
+    }
+
+    /// Like [`query_stream`](Self::query_stream), keeping the context for
+    /// [`ChainContext::cited`].
+    pub async fn ask_stream(&self, prompt: &str) -> Result<AnswerStream> {
+        self.ask_stream_in(None, prompt).await
+    }
+
+    /// Streams an answer in `session`; the caller hands the complete answer
+    /// to [`finish_stream`](Self::finish_stream). An offline answer arrives
+    /// as one token.
+    pub async fn ask_stream_in(&self, session: Option<Uuid>, prompt: &str) -> Result<AnswerStream> {
+        let result = match self.stream(session, prompt).await {
+            Err(err) => match self.fallback(err) {
+                Ok(offline) => {
+                    self.answer_offline(offline, session, prompt).await.map(|(context, answer)| {
+                        let Answer { answer: text, analytics, .. } = answer;
+                        let tokens: TokenStream = Box::pin(futures::stream::once(async move { Ok(text) }));
+                        AnswerStream { context, tokens, offline: true, analytics }
+                    })
+                }
+                Err(err) => Err(err),
+            },
+            streaming => streaming,
+        };
+        if let Err(err) = &result {
+            let (provider, model) = self.provider(false);
+            let error = Some(format!("{err:#}"));
+            let entry = AuditEntry { streamed: true, ..AuditEntry::new(session, provider, &model, prompt) };
+            self.audit(entry.failed(error)).await;
+        }
+        result
+    }
+
+    /// Audits a streamed answer once it has ended, with `text` as far as it
+    /// got and the `error` that ended it early, and records a complete one
+    /// in `session`.
+    pub async fn finish_stream(
+        &self,
+        session: Option<Uuid>,
+        prompt: &str,
+        stream: &AnswerStream,
+        text: &str,
+        error: Option<String>,
+    ) -> Result<()> {
+        let (provider, model) = self.provider(stream.offline);
+        let complete = error.is_none();
+        let entry = AuditEntry {
+            context: Some(&stream.context),
+            response: text,
+            offline: stream.offline,
+            streamed: true,
+            ..AuditEntry::new(session, provider, &model, prompt)
+        };
+        self.audit(entry.failed(error)).await;
+        match session {
+            Some(id) if complete => self.remember(id, prompt, text).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn stream(&self, session: Option<Uuid>, prompt: &str) -> Result<AnswerStream> {
+        let llm = self.llm()?;
+        let analytics = self.metrics(prompt).await?;
+        let (context, request) = self.request(session, prompt, &analytics).await?;
+        Ok(AnswerStream { context, tokens: llm.stream(&request).await?, offline: false, analytics })
+    }
 }
@@ -0,0 +1,192 @@
+//! Chat model providers. The hypervisor asks its questions through
+//! [`LlmProvider`]; each provider maps them onto its own API and maps the
+//! answers and errors back, so switching providers is a config change.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use dxid_config::{AiConfig, LlmProviderKind};
+use dxid_storage::SessionRole;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::anthropic::AnthropicChat;
+use crate::openai::OpenAiChat;
+use crate::stream::TokenStream;
+
+/// Longest provider error body quoted when it has no message of its own.
+const MAX_ERROR_BODY: usize = 200;
+
+/// One question: the instructions the model follows, the conversation so
+/// far and what it is asked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatRequest {
+    pub system: String,
+    /// Earlier messages, oldest first, starting with the user's.
+    pub history: Vec<(SessionRole, String)>,
+    pub user: String,
+}
+
+/// A tool the model may call, with a JSON schema of its arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: Value,
+}
+
+/// A call the model asked for.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolCall {
+    /// Assigned by the provider and sent back with the result.
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+/// What followed the question in a conversation with tools.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatMessage {
+    /// The model's calls, with any text it wrote alongside them.
+    ToolCalls { text: Option<String>, calls: Vec<ToolCall> },
+    /// The result of the call `call_id`, as JSON text.
+    ToolResult { call_id: String, content: String },
+}
+
+/// The model's reply in a conversation with tools.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatTurn {
+    Answer(String),
+    ToolCalls { text: Option<String>, calls: Vec<ToolCall> },
+}
+
+#[derive(Debug, Error)]
+pub enum LlmError {
+    #[error("{provider} rejected the credentials: {message}")]
+    Unauthorized { provider: &'static str, message: String },
+    #[error("{provider} quota or billing limit reached: {message}")]
+    QuotaExceeded { provider: &'static str, message: String },
+    #[error("{provider} is rate limiting requests: {message}")]
+    RateLimited { provider: &'static str, message: String, retry_after: Option<Duration> },
+    #[error("{provider} rejected the request: {message}")]
+    InvalidRequest { provider: &'static str, message: String },
+    #[error("{provider} is unavailable: {message}")]
+    Unavailable { provider: &'static str, message: String },
+    #[error("could not reach {provider}: {message}")]
+    Transport { provider: &'static str, message: String },
+    #[error("{provider} sent an unexpected response: {message}")]
+    Malformed { provider: &'static str, message: String },
+}
+
+impl LlmError {
+    /// Whether the same request may succeed later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::RateLimited { .. } | Self::Unavailable { .. } | Self::Transport { .. })
+    }
+
+    /// Whether the provider cannot answer anything for now, as opposed to
+    /// rejecting this request or answering it badly.
+    pub fn is_unavailable(&self) -> bool {
+        self.is_retryable() || matches!(self, Self::Unauthorized { .. } | Self::QuotaExceeded { .. })
+    }
+
+    /// The error for an HTTP `status` without anything more specific to go on.
+    pub(crate) fn from_status(provider: &'static str, status: StatusCode, message: String) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Unauthorized { provider, message },
+            StatusCode::PAYMENT_REQUIRED => Self::QuotaExceeded { provider, message },
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited { provider, message, retry_after: None },
+            status if status.is_server_error() => Self::Unavailable { provider, message },
+            _ => Self::InvalidRequest { provider, message },
+        }
+    }
+}
+
+/// A chat model behind some API.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short name for logs and errors, e.g. `openai`.
+    fn name(&self) -> &'static str;
+
+    fn model(&self) -> &str;
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, LlmError>;
+
+    /// Answers token by token as the model writes. Dropping the stream
+    /// cancels the request.
+    async fn stream(&self, request: &ChatRequest) -> Result<TokenStream, LlmError>;
+
+    /// One turn of a conversation in which the model may call `tools`;
+    /// `history` holds its earlier calls and their results.
+    async fn converse(
+        &self,
+        request: &ChatRequest,
+        history: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatTurn, LlmError>;
+}
+
+/// The provider `cfg` selects.
+pub fn build_llm(cfg: &AiConfig) -> anyhow::Result<Box<dyn LlmProvider>> {
+    Ok(match cfg.provider {
+        LlmProviderKind::Openai => {
+            if cfg.openai_api_key.is_empty() {
+                bail!("the openai provider needs ai.openai_api_key");
+            }
+            Box::new(OpenAiChat::openai(&cfg.openai_api_key, &cfg.model))
+        }
+        LlmProviderKind::Anthropic => {
+            let api_key = cfg
+                .anthropic_api_key
+                .as_deref()
+                .filter(|key| !key.is_empty())
+                .ok_or_else(|| anyhow!("the anthropic provider needs ai.anthropic_api_key"))?;
+            Box::new(AnthropicChat::new(api_key, &cfg.model))
+        }
+        LlmProviderKind::OpenaiCompatible => {
+            let endpoint = cfg
+                .endpoint
+                .as_deref()
+                .ok_or_else(|| anyhow!("the openai_compatible provider needs ai.endpoint"))?;
+            Box::new(OpenAiChat::compatible(endpoint, &cfg.model))
+        }
+    })
+}
+
+/// Sends `request`, turning failures into [`LlmError`]s; `error` reads the
+/// provider's error bodies.
+pub(crate) async fn send(
+    provider: &'static str,
+    request: RequestBuilder,
+    error: fn(&'static str, StatusCode, &str) -> LlmError,
+) -> Result<Response, LlmError> {
+    let resp =
+        request.send().await.map_err(|err| LlmError::Transport { provider, message: err.to_string() })?;
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let delay = resp
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok()?.parse().ok())
+        .map(Duration::from_secs);
+    let body = resp.text().await.unwrap_or_default();
+    let mut err = error(provider, status, &body);
+    if let LlmError::RateLimited { retry_after, .. } = &mut err {
+        *retry_after = delay;
+    }
+    Err(err)
+}
+
+/// `error.message` of a JSON error body, or the start of the body itself.
+pub(crate) fn error_message(body: &serde_json::Value, raw: &str) -> String {
+    match body["error"]["message"].as_str().or_else(|| body["error"].as_str()) {
+        Some(message) => message.to_string(),
+        None => raw.chars().take(MAX_ERROR_BODY).collect(),
+    }
+}
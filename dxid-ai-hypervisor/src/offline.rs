@@ -0,0 +1,265 @@
+//! Answers for when no model can: no provider is configured, or the one
+//! configured cannot be reached. Questions about the height, balances, the
+//! validator set and fees are answered from the store with templates, citing
+//! numbered sources like a model's answer; anything else gets the chain's
+//! status. Every answer starts with [`OFFLINE_MARKER`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use dxid_config::OfflineSettings;
+use dxid_core::builder::estimate_fee_rate;
+use dxid_core::staking::ValidatorStatus;
+use dxid_core::Address;
+use dxid_crypto::address_to_string;
+use dxid_storage::{BlockStore, StakingStore, StateStore, TxStore};
+
+use crate::context::{address_summary, references, ChainContext, Reference};
+
+/// Starts every offline answer.
+pub const OFFLINE_MARKER: &str = "[offline mode]";
+/// Said of a question no topic matched.
+const OFFLINE_TOPICS: &str =
+    "Offline, only questions about the height, balances, validators and fees are answered.";
+/// Most addresses in a question whose balances are looked up.
+const MAX_ADDRESSES: usize = 5;
+/// Most validators listed, the busiest first.
+const MAX_VALIDATORS: usize = 10;
+/// Pending transactions looked at for a fee estimate.
+const FEE_SAMPLE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Topic {
+    Height,
+    Balance,
+    Validators,
+    Fees,
+}
+
+/// Word prefixes that ask about each topic, in the order topics are answered.
+const KEYWORDS: [(Topic, &[&str]); 4] = [
+    (Topic::Height, &["height", "block", "tip", "latest", "sync"]),
+    (Topic::Balance, &["balance", "fund", "hold", "own", "wallet"]),
+    (Topic::Validators, &["validator", "stake", "staking", "proposer"]),
+    (Topic::Fees, &["fee", "gas", "mempool", "pending"]),
+];
+
+fn topics(question: &str) -> Vec<Topic> {
+    let question = question.to_lowercase();
+    let words: Vec<&str> = question.split(|c: char| !c.is_ascii_alphanumeric()).collect();
+    KEYWORDS
+        .iter()
+        .filter(|(_, prefixes)| words.iter().any(|word| prefixes.iter().any(|p| word.starts_with(p))))
+        .map(|(topic, _)| *topic)
+        .collect()
+}
+
+pub struct OfflineAnswers {
+    blocks: Arc<dyn BlockStore>,
+    state: Arc<dyn StateStore>,
+    txs: Arc<dyn TxStore>,
+    staking: Arc<dyn StakingStore>,
+    settings: OfflineSettings,
+}
+
+impl OfflineAnswers {
+    pub fn new(
+        blocks: Arc<dyn BlockStore>,
+        state: Arc<dyn StateStore>,
+        txs: Arc<dyn TxStore>,
+        staking: Arc<dyn StakingStore>,
+        settings: OfflineSettings,
+    ) -> Self {
+        Self { blocks, state, txs, staking, settings }
+    }
+
+    /// A templated answer to `question` with the sources it cites.
+    pub async fn answer(&self, question: &str) -> Result<(ChainContext, String)> {
+        let addresses: Vec<Address> = references(question, MAX_ADDRESSES)
+            .into_iter()
+            .filter_map(|reference| match reference {
+                Reference::Address(address) => Some(address),
+                Reference::Identity(_) => None,
+            })
+            .collect();
+        let mut topics = topics(question);
+        if !addresses.is_empty() && !topics.contains(&Topic::Balance) {
+            topics.push(Topic::Balance);
+        }
+        let understood = !topics.is_empty();
+        if !understood {
+            topics.push(Topic::Height);
+        }
+
+        let mut context = ChainContext::default();
+        let mut notes = Vec::new();
+        for topic in topics {
+            match topic {
+                Topic::Height => context.push("status", "status".into(), self.height().await?),
+                Topic::Balance if addresses.is_empty() => {
+                    notes.push("Name a base58 address to look up its balance.".to_string())
+                }
+                Topic::Balance => {
+                    for address in &addresses {
+                        let text = address_summary(self.state.as_ref(), self.txs.as_ref(), address).await?;
+                        context.push("address", address_to_string(address), text);
+                    }
+                }
+                Topic::Validators => self.validators(&mut context).await?,
+                Topic::Fees => context.push("fees", "fees".into(), self.fees().await?),
+            }
+        }
+
+        let mut lines =
+            vec![format!("{OFFLINE_MARKER} No AI model is available, so this is answered from the chain.")];
+        lines.extend(context.sources.iter().map(|source| format!("{} [{}]", source.text, source.id)));
+        if !understood {
+            notes.push(OFFLINE_TOPICS.into());
+        }
+        lines.extend(notes);
+        Ok((context, lines.join("\n")))
+    }
+
+    async fn height(&self) -> Result<String> {
+        let pending = self.txs.pending_count().await?;
+        Ok(match self.blocks.best_height().await? {
+            Some(height) => {
+                format!("The chain is at height {height} with {pending} transactions waiting for inclusion.")
+            }
+            None => format!("No blocks are stored yet; {pending} transactions are waiting for inclusion."),
+        })
+    }
+
+    /// The validators of the latest blocks with their bonded stake, one
+    /// source each.
+    async fn validators(&self, context: &mut ChainContext) -> Result<()> {
+        let blocks = self.blocks.blocks_before(None, self.settings.validator_blocks as i64).await?;
+        if blocks.is_empty() {
+            let text = "No blocks are stored yet, so no validators are known.".to_string();
+            context.push("validator", "validators".into(), text);
+            return Ok(());
+        }
+        let mut proposed: HashMap<Address, usize> = HashMap::new();
+        for block in &blocks {
+            *proposed.entry(block.header.validator).or_default() += 1;
+        }
+        let mut proposed: Vec<_> = proposed.into_iter().collect();
+        proposed.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (validator, count) in proposed.into_iter().take(MAX_VALIDATORS) {
+            let positions = self.staking.staking_positions_for(&validator).await?;
+            let status = ValidatorStatus::from_positions(validator, positions.iter().map(|(o, p)| (*o, p)));
+            let text = format!(
+                "Validator {} proposed {count} of the latest {} blocks and has {} bonded \
+                 ({} own, {} delegated by {} delegators).",
+                address_to_string(&validator),
+                blocks.len(),
+                status.total_stake(),
+                status.self_stake,
+                status.delegated,
+                status.delegators
+            );
+            context.push("validator", address_to_string(&validator), text);
+        }
+        Ok(())
+    }
+
+    async fn fees(&self) -> Result<String> {
+        let pending = self.txs.pending_count().await?;
+        let rate = estimate_fee_rate(&self.txs.pending_txs(FEE_SAMPLE).await?);
+        Ok(format!(
+            "The suggested fee rate is {rate} per byte, with {pending} transactions waiting for inclusion."
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use dxid_config::ContextSettings;
+
+    use super::*;
+    use crate::llm::{ChatMessage, ChatRequest, ChatTurn, LlmError, LlmProvider, ToolSpec};
+    use crate::stream::TokenStream;
+    use crate::test_support::{Blocks, Ledger};
+    use crate::{ContextBuilder, Hypervisor};
+
+    /// A provider that cannot be reached.
+    struct Down;
+
+    impl Down {
+        fn error() -> LlmError {
+            LlmError::Transport { provider: "down", message: "connection refused".into() }
+        }
+    }
+
+    #[async_trait]
+    impl LlmProvider for Down {
+        fn name(&self) -> &'static str {
+            "down"
+        }
+
+        fn model(&self) -> &str {
+            "down"
+        }
+
+        async fn complete(&self, _request: &ChatRequest) -> Result<String, LlmError> {
+            Err(Self::error())
+        }
+
+        async fn stream(&self, _request: &ChatRequest) -> Result<TokenStream, LlmError> {
+            Err(Self::error())
+        }
+
+        async fn converse(
+            &self,
+            _request: &ChatRequest,
+            _history: &[ChatMessage],
+            _tools: &[ToolSpec],
+        ) -> Result<ChatTurn, LlmError> {
+            Err(Self::error())
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_from_the_store_while_the_provider_is_down() {
+        let blocks = Arc::new(Blocks::with_payments(3));
+        let ledger = Arc::new(Ledger::default());
+        ledger.balances.lock().unwrap().insert([4; 32], 250);
+        let offline = || {
+            let settings = OfflineSettings::default();
+            OfflineAnswers::new(blocks.clone(), ledger.clone(), ledger.clone(), ledger.clone(), settings)
+        };
+
+        let question = format!("How much does {} have?", address_to_string(&[4; 32]));
+        let (context, answer) = offline().answer(&question).await.unwrap();
+        assert!(answer.starts_with(OFFLINE_MARKER));
+        assert!(answer.contains("has a balance of 250 in 0 unspent outputs. [S1]"), "{answer}");
+        assert_eq!(context.sources[0].kind, "address");
+
+        let (_, answer) = offline().answer("Which validators are staking, and what fee?").await.unwrap();
+        let validator = address_to_string(&[9; 32]);
+        assert!(answer.contains(&format!("Validator {validator} proposed 3 of the latest 3 blocks")));
+        assert!(answer.contains("fee rate is 1 per byte, with 0 transactions waiting"), "{answer}");
+
+        let (_, answer) = offline().answer("Tell me a joke").await.unwrap();
+        assert!(
+            answer.contains("at height 2 with 0") && answer.ends_with("validators and fees are answered.")
+        );
+
+        let context = ContextBuilder::new(
+            blocks.clone(),
+            ledger.clone(),
+            ledger.clone(),
+            ledger.clone(),
+            ContextSettings::default(),
+        );
+        let hypervisor = Hypervisor::with_provider(Box::new(Down), context).with_offline(offline());
+        let answer = hypervisor.ask("What is the height?").await.unwrap();
+        assert!(answer.offline && answer.answer.starts_with(OFFLINE_MARKER));
+        assert_eq!(answer.sources.len(), 1);
+
+        hypervisor.set_provider(None);
+        assert!(hypervisor.ask("What is the height?").await.unwrap().offline);
+    }
+}
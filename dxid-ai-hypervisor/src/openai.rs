@@ -0,0 +1,263 @@
+//! OpenAI's chat completions API, and servers that mimic its route.
+
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+use crate::llm::{
+    error_message, send, ChatMessage, ChatRequest, ChatTurn, LlmError, LlmProvider, ToolCall, ToolSpec,
+};
+use crate::stream::{chat_token, sse_tokens, StreamEvent, TokenStream, DONE};
+
+const OPENAI_API_URL: &str = "https://api.openai.com/v1";
+
+pub struct OpenAiChat {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+    model: String,
+    name: &'static str,
+}
+
+impl OpenAiChat {
+    pub fn openai(api_key: &str, model: &str) -> Self {
+        Self {
+            client: Client::new(),
+            url: format!("{OPENAI_API_URL}/chat/completions"),
+            api_key: Some(api_key.to_string()),
+            model: model.to_string(),
+            name: "openai",
+        }
+    }
+
+    /// A local server with OpenAI's `/chat/completions` route, such as
+    /// Ollama or vLLM. `base_url` is the part before the route, e.g.
+    /// `http://127.0.0.1:11434/v1`.
+    pub fn compatible(base_url: &str, model: &str) -> Self {
+        Self {
+            client: Client::new(),
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            api_key: None,
+            model: model.to_string(),
+            name: "openai_compatible",
+        }
+    }
+
+    fn body(&self, request: &ChatRequest, stream: bool) -> Value {
+        json!({ "model": self.model, "stream": stream, "messages": messages(request) })
+    }
+
+    /// Calls are assistant messages with `tool_calls`, whose arguments are
+    /// JSON text; each result is a `tool` message of its own.
+    fn tool_body(&self, request: &ChatRequest, history: &[ChatMessage], tools: &[ToolSpec]) -> Value {
+        let mut messages = messages(request);
+        messages.extend(history.iter().map(|message| match message {
+            ChatMessage::ToolCalls { text, calls } => {
+                let calls: Vec<Value> = calls
+                    .iter()
+                    .map(|call| {
+                        json!({
+                            "id": call.id,
+                            "type": "function",
+                            "function": { "name": call.name, "arguments": call.arguments.to_string() }
+                        })
+                    })
+                    .collect();
+                json!({ "role": "assistant", "content": text, "tool_calls": calls })
+            }
+            ChatMessage::ToolResult { call_id, content } => {
+                json!({ "role": "tool", "tool_call_id": call_id, "content": content })
+            }
+        }));
+        let tools: Vec<Value> = tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters
+                    }
+                })
+            })
+            .collect();
+        json!({ "model": self.model, "stream": false, "messages": messages, "tools": tools })
+    }
+
+    async fn post(&self, body: &Value) -> Result<reqwest::Response, LlmError> {
+        let mut builder = self.client.post(&self.url).json(body);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        send(self.name, builder, error).await
+    }
+
+    async fn reply(&self, body: &Value) -> Result<Value, LlmError> {
+        let provider = self.name;
+        self.post(body)
+            .await?
+            .json()
+            .await
+            .map_err(|err| LlmError::Malformed { provider, message: err.to_string() })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiChat {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, request: &ChatRequest) -> Result<String, LlmError> {
+        let body = self.reply(&self.body(request, false)).await?;
+        answer(&body).map(str::to_string).ok_or_else(|| LlmError::Malformed {
+            provider: self.name,
+            message: "no message content in the first choice".into(),
+        })
+    }
+
+    async fn stream(&self, request: &ChatRequest) -> Result<TokenStream, LlmError> {
+        Ok(sse_tokens(self.post(&self.body(request, true)).await?, stream_event))
+    }
+
+    async fn converse(
+        &self,
+        request: &ChatRequest,
+        history: &[ChatMessage],
+        tools: &[ToolSpec],
+    ) -> Result<ChatTurn, LlmError> {
+        let body = self.reply(&self.tool_body(request, history, tools)).await?;
+        turn(&body).ok_or_else(|| LlmError::Malformed {
+            provider: self.name,
+            message: "neither content nor tool calls in the first choice".into(),
+        })
+    }
+}
+
+fn messages(request: &ChatRequest) -> Vec<Value> {
+    let mut messages = vec![json!({ "role": "system", "content": request.system })];
+    messages.extend(
+        request.history.iter().map(|(role, content)| json!({ "role": role.as_str(), "content": content })),
+    );
+    messages.push(json!({ "role": "user", "content": request.user }));
+    messages
+}
+
+fn answer(body: &Value) -> Option<&str> {
+    body["choices"][0]["message"]["content"].as_str()
+}
+
+fn turn(body: &Value) -> Option<ChatTurn> {
+    let message = &body["choices"][0]["message"];
+    let text = message["content"].as_str().filter(|text| !text.is_empty()).map(str::to_string);
+    let calls: Vec<ToolCall> = message["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|call| {
+            let arguments = call["function"]["arguments"].as_str().unwrap_or("{}");
+            ToolCall {
+                id: call["id"].as_str().unwrap_or_default().to_string(),
+                name: call["function"]["name"].as_str().unwrap_or_default().to_string(),
+                // Left as text when it is not JSON, for the tool to reject.
+                arguments: serde_json::from_str(arguments).unwrap_or_else(|_| arguments.into()),
+            }
+        })
+        .collect();
+    if calls.is_empty() {
+        text.map(ChatTurn::Answer)
+    } else {
+        Some(ChatTurn::ToolCalls { text, calls })
+    }
+}
+
+fn stream_event(data: &str) -> anyhow::Result<StreamEvent> {
+    if data == DONE {
+        return Ok(StreamEvent::Done);
+    }
+    Ok(chat_token(data)?.map_or(StreamEvent::Skip, StreamEvent::Token))
+}
+
+/// OpenAI answers 429 both when rate limited and when out of credit; its
+/// error `code` tells them apart.
+fn error(provider: &'static str, status: StatusCode, raw: &str) -> LlmError {
+    let body: Value = serde_json::from_str(raw).unwrap_or_default();
+    let message = error_message(&body, raw);
+    match body["error"]["code"].as_str() {
+        Some("insufficient_quota") => LlmError::QuotaExceeded { provider, message },
+        Some("invalid_api_key") => LlmError::Unauthorized { provider, message },
+        _ => LlmError::from_status(provider, status, message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_answers_stream_events_and_errors() {
+        let chat = OpenAiChat::compatible("http://127.0.0.1:11434/v1/", "llama3");
+        assert_eq!(chat.url, "http://127.0.0.1:11434/v1/chat/completions");
+        let request = ChatRequest { system: "Be brief.".into(), history: Vec::new(), user: "Height?".into() };
+        let body = chat.body(&request, true);
+        assert_eq!(
+            (body["stream"].as_bool(), body["messages"][1]["content"].as_str()),
+            (Some(true), Some("Height?"))
+        );
+
+        let reply = json!({ "choices": [{ "message": { "role": "assistant", "content": "42" } }] });
+        assert_eq!(answer(&reply), Some("42"));
+        assert_eq!(stream_event(DONE).unwrap(), StreamEvent::Done);
+        let chunk = r#"{"choices":[{"delta":{"content":"4"}}]}"#;
+        assert_eq!(stream_event(chunk).unwrap(), StreamEvent::Token("4".into()));
+
+        let quota = r#"{"error":{"message":"You exceeded your quota","code":"insufficient_quota"}}"#;
+        let err = error("openai", StatusCode::TOO_MANY_REQUESTS, quota);
+        assert!(matches!(err, LlmError::QuotaExceeded { .. }) && !err.is_retryable());
+        let limited = r#"{"error":{"message":"Slow down","code":"rate_limit_exceeded"}}"#;
+        let err = error("openai", StatusCode::TOO_MANY_REQUESTS, limited);
+        assert_eq!(err.to_string(), "openai is rate limiting requests: Slow down");
+        assert!(err.is_retryable());
+        let err = error("openai_compatible", StatusCode::BAD_GATEWAY, "<html>bad gateway</html>");
+        assert!(
+            matches!(err, LlmError::Unavailable { ref message, .. } if message == "<html>bad gateway</html>")
+        );
+    }
+
+    #[test]
+    fn maps_tool_calls_and_results() {
+        let chat = OpenAiChat::openai("key", "gpt-4o-mini");
+        let request =
+            ChatRequest { system: "Be brief.".into(), history: Vec::new(), user: "Balance of abc?".into() };
+        let call = ToolCall {
+            id: "call_1".into(),
+            name: "get_balance".into(),
+            arguments: json!({ "address": "abc" }),
+        };
+        let history = [
+            ChatMessage::ToolCalls { text: None, calls: vec![call.clone()] },
+            ChatMessage::ToolResult { call_id: "call_1".into(), content: r#"{"balance":5}"#.into() },
+        ];
+        let tool = ToolSpec { name: "get_balance", description: "A balance.", parameters: json!({}) };
+        let body = chat.tool_body(&request, &history, &[tool]);
+        assert_eq!(body["messages"][2]["tool_calls"][0]["function"]["arguments"], r#"{"address":"abc"}"#);
+        assert_eq!(
+            body["messages"][3],
+            json!({ "role": "tool", "tool_call_id": "call_1", "content": r#"{"balance":5}"# })
+        );
+        assert_eq!(body["tools"][0]["function"]["name"], "get_balance");
+
+        let calls = json!({ "choices": [{ "message": { "content": null, "tool_calls": [
+            { "id": "call_1", "type": "function",
+              "function": { "name": "get_balance", "arguments": "{\"address\":\"abc\"}" } }
+        ] } }] });
+        assert_eq!(turn(&calls), Some(ChatTurn::ToolCalls { text: None, calls: vec![call] }));
+        let reply = json!({ "choices": [{ "message": { "content": "5 [S1]", "tool_calls": [] } }] });
+        assert_eq!(turn(&reply), Some(ChatTurn::Answer("5 [S1]".into())));
+    }
+}
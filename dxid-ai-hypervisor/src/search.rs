@@ -0,0 +1,210 @@
+//! Hybrid search: nearest neighbours by vector similarity, re-ranked with
+//! what their metadata says about them: how recent they are, whether the
+//! identity behind them is still active and how many of the asked-for
+//! attributes they have. Every result carries its component scores, so a
+//! ranking can be explained rather than just trusted.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use dxid_config::RankingSettings;
+use dxid_storage::{BlockStore, VectorStore};
+use dxid_vectors::{embed_all, normalize, Embedder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Results returned when a query does not say how many.
+const DEFAULT_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub namespace: String,
+    pub text: String,
+    /// Most results returned; 10 when unset.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Values a result should have, as a metadata field or identity
+    /// attribute of that name; compared ignoring ASCII case.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// A result's score and what it is made of. Components are from 0 to 1 and
+/// `None` when they do not apply: no height, no status, or no attributes
+/// asked for; those count as 0 in `total`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreBreakdown {
+    /// Cosine similarity to the query.
+    pub similarity: f32,
+    /// Halves every `recency_half_life` blocks below the best height.
+    pub recency: Option<f32>,
+    /// 1 for an active identity, 0 for a revoked one.
+    pub status: Option<f32>,
+    /// Share of the asked-for attributes matched.
+    pub attributes: Option<f32>,
+    /// The components weighted by `[ai.ranking]`.
+    pub total: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RankedResult {
+    pub id: String,
+    pub metadata: Value,
+    pub scores: ScoreBreakdown,
+}
+
+pub struct HybridSearch {
+    embedder: Arc<dyn Embedder>,
+    vectors: Arc<dyn VectorStore>,
+    blocks: Arc<dyn BlockStore>,
+    settings: RankingSettings,
+}
+
+impl HybridSearch {
+    /// `embedder` must produce vectors of the store's length; see
+    /// `dxid_storage::negotiate_dimensions`.
+    pub fn new(
+        embedder: Arc<dyn Embedder>,
+        vectors: Arc<dyn VectorStore>,
+        blocks: Arc<dyn BlockStore>,
+        settings: RankingSettings,
+    ) -> Self {
+        Self { embedder, vectors, blocks, settings }
+    }
+
+    /// The best `limit` of the query's `candidates` nearest neighbours in
+    /// its namespace, highest total score first.
+    pub async fn search(&self, query: &SearchQuery) -> Result<Vec<RankedResult>> {
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        let vector = embed_all(self.embedder.as_ref(), std::slice::from_ref(&query.text)).await?.remove(0);
+        let k = self.settings.candidates.max(limit) as i64;
+        let candidates = self.vectors.knn_search_scored(&query.namespace, &normalize(vector), k).await?;
+        let best_height = self.blocks.best_height().await?;
+        let mut results: Vec<RankedResult> = candidates
+            .into_iter()
+            .map(|(embedding, distance)| {
+                let scores = self.score(distance, &embedding.metadata, best_height, &query.attributes);
+                RankedResult { id: embedding.id.0, metadata: embedding.metadata, scores }
+            })
+            .collect();
+        results.sort_by(|a, b| b.scores.total.total_cmp(&a.scores.total));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    fn score(
+        &self,
+        distance: f32,
+        metadata: &Value,
+        best_height: Option<u64>,
+        attributes: &BTreeMap<String, String>,
+    ) -> ScoreBreakdown {
+        let similarity = similarity(distance);
+        let recency = metadata["height"].as_u64().zip(best_height).map(|(height, best)| {
+            let age = best.saturating_sub(height) as f32;
+            0.5f32.powf(age / self.settings.recency_half_life.max(1) as f32)
+        });
+        let status = metadata["active"].as_bool().map(|active| if active { 1.0 } else { 0.0 });
+        let matched = (!attributes.is_empty()).then(|| {
+            let matched = attributes.iter().filter(|(key, want)| has_attribute(metadata, key, want)).count();
+            matched as f32 / attributes.len() as f32
+        });
+        let weights = &self.settings;
+        let total = weights.similarity_weight * similarity
+            + weights.recency_weight * recency.unwrap_or(0.0)
+            + weights.status_weight * status.unwrap_or(0.0)
+            + weights.attribute_weight * matched.unwrap_or(0.0);
+        ScoreBreakdown { similarity, recency, status, attributes: matched, total }
+    }
+}
+
+/// Cosine similarity of two unit vectors `distance` apart.
+pub(crate) fn similarity(distance: f32) -> f32 {
+    1.0 - distance * distance / 2.0
+}
+
+fn has_attribute(metadata: &Value, key: &str, want: &str) -> bool {
+    let value =
+        metadata.get("attributes").and_then(|attributes| attributes.get(key)).or_else(|| metadata.get(key));
+    match value {
+        Some(Value::String(value)) => value.eq_ignore_ascii_case(want),
+        Some(Value::Null) | None => false,
+        // Numbers and booleans compare by value.
+        Some(value) => want.parse::<Value>().is_ok_and(|want| want == *value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dxid_storage::MemoryVectorStore;
+    use dxid_vectors::{Embedding, EmbeddingId};
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_support::{Blocks, LengthEmbedder};
+
+    async fn insert(
+        vectors: &MemoryVectorStore,
+        namespace: &str,
+        id: &str,
+        values: [f32; 2],
+        metadata: Value,
+    ) {
+        let embedding = Embedding {
+            id: EmbeddingId(id.into()),
+            namespace: namespace.into(),
+            values: normalize(values.to_vec()),
+            metadata,
+        };
+        vectors.insert_embedding(&embedding).await.unwrap();
+    }
+
+    fn query(namespace: &str, attributes: &[(&str, &str)]) -> SearchQuery {
+        SearchQuery {
+            namespace: namespace.into(),
+            // Embedded as [3, 1].
+            text: "abc".into(),
+            limit: None,
+            attributes: attributes.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn ranks_by_weighted_similarity_recency_status_and_attributes() {
+        let vectors = Arc::new(MemoryVectorStore::new());
+        insert(&vectors, "chain:activity", "old", [3.0, 1.0], json!({ "height": 0, "kind": "transfer" }))
+            .await;
+        insert(&vectors, "chain:activity", "new", [2.9, 1.0], json!({ "height": 100, "kind": "stake" }))
+            .await;
+        let identity =
+            |active: bool, country: &str| json!({ "active": active, "attributes": { "country": country } });
+        insert(&vectors, "identities", "close", [2.0, 1.0], identity(true, "DE")).await;
+        insert(&vectors, "identities", "revoked", [3.0, 1.0], identity(false, "DE")).await;
+        insert(&vectors, "identities", "elsewhere", [3.0, 1.0], identity(true, "FR")).await;
+
+        let settings = RankingSettings { recency_half_life: 10, ..RankingSettings::default() };
+        let search = HybridSearch::new(
+            Arc::new(LengthEmbedder::default()),
+            vectors,
+            Arc::new(Blocks::with_payments(101)),
+            settings,
+        );
+
+        // The exact match is 100 blocks older, ten half-lives.
+        let found = search.search(&query("chain:activity", &[])).await.unwrap();
+        let ids: Vec<_> = found.iter().map(|result| result.id.as_str()).collect();
+        assert_eq!(ids, ["new", "old"]);
+        assert_eq!(found[0].scores.recency, Some(1.0));
+        assert!(found[1].scores.recency.unwrap() < 0.001 && found[1].scores.similarity > 0.999);
+        let stakes = search.search(&query("chain:activity", &[("kind", "Stake")])).await.unwrap();
+        assert_eq!(stakes[0].scores.attributes, Some(1.0));
+
+        let found = search.search(&query("identities", &[("country", "de")])).await.unwrap();
+        let ids: Vec<_> = found.iter().map(|result| result.id.as_str()).collect();
+        assert_eq!(ids, ["close", "revoked", "elsewhere"]);
+        let close = &found[0].scores;
+        assert_eq!((close.recency, close.status, close.attributes), (None, Some(1.0), Some(1.0)));
+        assert!((close.total - (close.similarity + 0.2 + 0.5)).abs() < 1e-6);
+    }
+}
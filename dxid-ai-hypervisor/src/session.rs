@@ -0,0 +1,165 @@
+//! Conversations resumed by id. Each question and its answer are stored in
+//! the session; the latest `window` messages go to the model with the next
+//! question, and older ones are folded into a running summary so a long
+//! analysis stays within the model's context.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use dxid_config::SessionSettings;
+use dxid_core::now_ts;
+use dxid_storage::{SessionMessage, SessionRecord, SessionRole, SessionStore};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::llm::{ChatRequest, LlmProvider};
+
+const SUMMARY_PROMPT: &str =
+    "You keep a running summary of an operator's conversation about the dxid chain. \
+Merge the earlier summary and the new messages into one short paragraph. Keep addresses, identity ids, \
+heights and amounts exactly as written.";
+
+pub struct Sessions {
+    store: Arc<dyn SessionStore>,
+    settings: SessionSettings,
+}
+
+impl Sessions {
+    pub fn new(store: Arc<dyn SessionStore>, settings: SessionSettings) -> Self {
+        Self { store, settings }
+    }
+
+    pub async fn get(&self, id: &Uuid) -> Result<Option<SessionRecord>> {
+        self.store.get_session(id).await
+    }
+
+    /// Session `id`, or an empty one if it has not been used yet.
+    pub async fn open(&self, id: Uuid) -> Result<SessionRecord> {
+        Ok(self.store.get_session(&id).await?.unwrap_or_else(|| SessionRecord::new(id, now_ts())))
+    }
+
+    /// Adds a question and its answer to session `id`. Once more than
+    /// `window` messages are unsummarized, the oldest exchanges are folded
+    /// into the summary; without a model, or if it cannot summarize them,
+    /// they are dropped from the window all the same.
+    pub async fn record(
+        &self,
+        llm: Option<&dyn LlmProvider>,
+        id: Uuid,
+        question: &str,
+        answer: &str,
+    ) -> Result<()> {
+        let mut session = self.open(id).await?;
+        let at = now_ts();
+        session.messages.push(SessionMessage { role: SessionRole::User, content: question.into(), at });
+        session.messages.push(SessionMessage { role: SessionRole::Assistant, content: answer.into(), at });
+        session.updated_at = at;
+        let unsummarized = session.messages.len() - session.summarized;
+        if unsummarized > self.settings.window {
+            // Whole exchanges, so the window still starts with a question.
+            let over = unsummarized - self.settings.window;
+            let fold = over + over % 2;
+            let folded = &session.messages[session.summarized..session.summarized + fold];
+            if let Some(llm) = llm.filter(|_| self.settings.summarize) {
+                match summarize(llm, session.summary.as_deref(), folded).await {
+                    Ok(summary) => session.summary = Some(summary),
+                    Err(err) => warn!("summarizing session {id} failed: {err:#}"),
+                }
+            }
+            session.summarized += fold;
+        }
+        self.store.put_session(&session).await
+    }
+}
+
+/// The messages of `session` still sent to the model, oldest first.
+pub fn window(session: &SessionRecord) -> Vec<(SessionRole, String)> {
+    session.messages[session.summarized..].iter().map(|m| (m.role, m.content.clone())).collect()
+}
+
+async fn summarize(
+    llm: &dyn LlmProvider,
+    summary: Option<&str>,
+    messages: &[SessionMessage],
+) -> Result<String> {
+    let transcript: Vec<_> = messages.iter().map(|m| format!("{}: {}", m.role.as_str(), m.content)).collect();
+    let request = ChatRequest {
+        system: SUMMARY_PROMPT.into(),
+        history: Vec::new(),
+        user: format!(
+            "Earlier summary: {}\n\nNew messages:\n{}",
+            summary.unwrap_or("none"),
+            transcript.join("\n")
+        ),
+    };
+    Ok(llm.complete(&request).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use dxid_storage::MemorySessionStore;
+
+    use super::*;
+    use crate::llm::{ChatMessage, ChatTurn, LlmError, ToolSpec};
+    use crate::stream::TokenStream;
+
+    /// Summarizes by counting the lines it was given, remembering each request.
+    #[derive(Default)]
+    struct Counting(Mutex<Vec<ChatRequest>>);
+
+    #[async_trait]
+    impl LlmProvider for Counting {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn model(&self) -> &str {
+            "counting"
+        }
+
+        async fn complete(&self, request: &ChatRequest) -> Result<String, LlmError> {
+            self.0.lock().unwrap().push(request.clone());
+            Ok(format!("{} lines", request.user.lines().count()))
+        }
+
+        async fn stream(&self, _request: &ChatRequest) -> Result<TokenStream, LlmError> {
+            unimplemented!()
+        }
+
+        async fn converse(
+            &self,
+            _request: &ChatRequest,
+            _history: &[ChatMessage],
+            _tools: &[ToolSpec],
+        ) -> Result<ChatTurn, LlmError> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn folds_exchanges_beyond_the_window_into_a_summary() {
+        let store = Arc::new(MemorySessionStore::new());
+        let sessions = Sessions::new(store, SessionSettings { window: 3, summarize: true });
+        let llm = Counting::default();
+        let id = Uuid::from_u128(1);
+        assert_eq!(sessions.open(id).await.unwrap().messages, []);
+        assert!(sessions.get(&id).await.unwrap().is_none());
+
+        sessions.record(Some(&llm), id, "Height?", "42 [S1].").await.unwrap();
+        assert!(llm.0.lock().unwrap().is_empty());
+        sessions.record(Some(&llm), id, "Peers?", "3.").await.unwrap();
+
+        let session = sessions.get(&id).await.unwrap().unwrap();
+        assert_eq!(session.messages.len(), 4);
+        assert_eq!((session.summarized, session.summary.as_deref()), (2, Some("5 lines")));
+        assert_eq!(
+            window(&session),
+            [(SessionRole::User, "Peers?".into()), (SessionRole::Assistant, "3.".into())]
+        );
+        let asked = llm.0.lock().unwrap().remove(0);
+        assert!(asked.user.ends_with("New messages:\nuser: Height?\nassistant: 42 [S1]."), "{}", asked.user);
+    }
+}
@@ -0,0 +1,110 @@
+//! Streamed answers: providers send their answers as server-sent events,
+//! decoded here into tokens as the bytes arrive.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+
+use anyhow::{anyhow, Result};
+use futures::Stream;
+
+/// Tokens of an answer in the order the model produces them.
+pub type TokenStream = Pin<Box<dyn Stream<Item = Result<String>> + Send>>;
+
+/// What one event of a provider's stream means.
+#[derive(Debug, PartialEq)]
+pub enum StreamEvent {
+    Token(String),
+    /// Bookkeeping without text, such as a role or usage update.
+    Skip,
+    Done,
+}
+
+/// `data` of the event that ends a completion stream.
+pub const DONE: &str = "[DONE]";
+
+/// Splits a server-sent events body into the `data` of each event. Bytes are
+/// buffered until a whole line has arrived, so chunks may split anywhere.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    line: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// Feeds `chunk` and returns the events it completed.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        let mut events = Vec::new();
+        for &byte in chunk {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            let line = String::from_utf8_lossy(&self.line).trim_end_matches('\r').to_string();
+            self.line.clear();
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(std::mem::take(&mut self.data).join("\n"));
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data.push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+        }
+        events
+    }
+}
+
+/// The tokens of a streamed response, each event's `data` read by `parse`.
+/// Dropping the stream cancels the request.
+pub fn sse_tokens(resp: reqwest::Response, parse: fn(&str) -> Result<StreamEvent>) -> TokenStream {
+    let state = Some((resp, SseDecoder::default(), VecDeque::<String>::new()));
+    let tokens = futures::stream::unfold(state, move |state| async move {
+        let (mut resp, mut decoder, mut events) = state?;
+        loop {
+            if let Some(data) = events.pop_front() {
+                match parse(&data) {
+                    Ok(StreamEvent::Token(token)) => return Some((Ok(token), Some((resp, decoder, events)))),
+                    Ok(StreamEvent::Skip) => continue,
+                    Ok(StreamEvent::Done) => return None,
+                    Err(err) => return Some((Err(err), None)),
+                }
+            }
+            match resp.chunk().await {
+                Ok(Some(bytes)) => events.extend(decoder.push(&bytes)),
+                Ok(None) => return None,
+                Err(err) => return Some((Err(err.into()), None)),
+            }
+        }
+    });
+    Box::pin(tokens)
+}
+
+/// The text a chat completion chunk adds, if any.
+pub fn chat_token(data: &str) -> Result<Option<String>> {
+    let chunk: serde_json::Value = serde_json::from_str(data)?;
+    if let Some(error) = chunk.get("error") {
+        return Err(anyhow!("provider error: {}", error["message"].as_str().unwrap_or("unknown")));
+    }
+    Ok(chunk["choices"][0]["delta"]["content"].as_str().map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_events_split_across_chunks() {
+        let body = "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n: keep-alive\n\n\
+                    data: {\"choices\":[{\"delta\":{\"content\":\"Hé\"}}]}\r\n\r\n\
+                    data: {\"choices\":[{\"delta\":{\"content\":\"llo\"}}]}\n\ndata: [DONE]\n\n";
+        let mut decoder = SseDecoder::default();
+        let mut events = Vec::new();
+        for chunk in body.as_bytes().chunks(7) {
+            events.extend(decoder.push(chunk));
+        }
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[3], DONE);
+        let tokens: Vec<Option<String>> = events[..3].iter().map(|e| chat_token(e).unwrap()).collect();
+        assert_eq!(tokens, [None, Some("Hé".to_string()), Some("llo".to_string())]);
+        assert!(chat_token("{\"error\":{\"message\":\"rate limited\"}}").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use dxid_core::builder::Utxo;
+use dxid_core::staking::StakingPosition;
+use dxid_core::{Address, Block, BlockHeader, Identity, IdentityId, Transaction, TxHash, TxInput, TxOutput};
+use dxid_storage::{BlockStore, IdentityStore, StakingStore, StateStore, TxStore};
+use dxid_vectors::Embedder;
+
+/// Embeds a text as its length, enough to tell snippets apart.
+#[derive(Default)]
+pub struct LengthEmbedder {
+    /// Calls that succeed before every later one fails.
+    fail_after: Option<u32>,
+    calls: AtomicU32,
+}
+
+impl LengthEmbedder {
+    pub fn failing_after(calls: u32) -> Self {
+        Self { fail_after: Some(calls), ..Self::default() }
+    }
+}
+
+#[async_trait]
+impl Embedder for LengthEmbedder {
+    fn model(&self) -> &str {
+        "length"
+    }
+
+    fn dimensions(&self) -> usize {
+        2
+    }
+
+    fn max_batch(&self) -> usize {
+        3
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        if self.fail_after.is_some_and(|after| call >= after) {
+            return Err(anyhow!("rate limited"));
+        }
+        Ok(texts.iter().map(|text| vec![text.len() as f32, 1.0]).collect())
+    }
+}
+
+#[derive(Default)]
+pub struct Blocks(pub HashMap<u64, Block>);
+
+impl Blocks {
+    /// Blocks `0..count`, each with one payment.
+    pub fn with_payments(count: u64) -> Self {
+        let mut blocks = Self::default();
+        for height in 0..count {
+            let payment = spend(
+                None,
+                vec![TxOutput {
+                    address: [2; 32],
+                    amount: height,
+                    condition: None,
+                    spendable_after_height: None,
+                }],
+            );
+            blocks.0.insert(height, block(height, vec![payment]));
+        }
+        blocks
+    }
+}
+
+#[async_trait]
+impl BlockStore for Blocks {
+    async fn insert_block(&self, _block: &Block) -> Result<()> {
+        unimplemented!()
+    }
+
+    async fn get_block_by_height(&self, height: i64) -> Result<Option<Block>> {
+        Ok(self.0.get(&(height as u64)).cloned())
+    }
+
+    async fn best_height(&self) -> Result<Option<u64>> {
+        Ok(self.0.keys().max().copied())
+    }
+
+    async fn blocks_before(&self, before: Option<i64>, limit: i64) -> Result<Vec<Block>> {
+        let mut blocks: Vec<Block> = self
+            .0
+            .values()
+            .filter(|block| (block.header.height as i64) < before.unwrap_or(i64::MAX))
+            .cloned()
+            .collect();
+        blocks.sort_by_key(|block| std::cmp::Reverse(block.header.height));
+        blocks.truncate(limit as usize);
+        Ok(blocks)
+    }
+}
+
+/// Balances and identities; nothing is pending, unspent or staked.
+#[derive(Default)]
+pub struct Ledger {
+    pub balances: Mutex<HashMap<Address, u64>>,
+    pub identities: Mutex<HashMap<IdentityId, Identity>>,
+}
+
+#[async_trait]
+impl StateStore for Ledger {
+    async fn get_balance(&self, addr: &Address) -> Result<u64> {
+        Ok(self.balances.lock().unwrap().get(addr).copied().unwrap_or(0))
+    }
+
+    async fn set_balance(&self, addr: &Address, value: u64) -> Result<()> {
+        self.balances.lock().unwrap().insert(*addr, value);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TxStore for Ledger {
+    async fn list_utxos(&self, _addr: &Address) -> Result<Vec<Utxo>> {
+        Ok(Vec::new())
+    }
+
+    async fn unspent_output(&self, _tx: &TxHash, _index: u32) -> Result<Option<TxOutput>> {
+        Ok(None)
+    }
+
+    async fn coinbase_height(&self, _tx: &TxHash) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    async fn record_transaction(&self, _tx: &Transaction) -> Result<()> {
+        unimplemented!()
+    }
+
+    async fn insert_pending_tx(&self, _tx: &Transaction) -> Result<bool> {
+        unimplemented!()
+    }
+
+    async fn pending_txs(&self, _limit: i64) -> Result<Vec<Transaction>> {
+        Ok(Vec::new())
+    }
+
+    async fn remove_pending_txs(&self, _hashes: &[TxHash]) -> Result<()> {
+        unimplemented!()
+    }
+
+    async fn pending_count(&self) -> Result<u64> {
+        Ok(0)
+    }
+}
+
+#[async_trait]
+impl IdentityStore for Ledger {
+    async fn put_identity(&self, identity: &Identity) -> Result<()> {
+        self.identities.lock().unwrap().insert(identity.id, identity.clone());
+        Ok(())
+    }
+
+    async fn get_identity(&self, id: &IdentityId) -> Result<Option<Identity>> {
+        Ok(self.identities.lock().unwrap().get(id).cloned())
+    }
+
+    async fn list_identities(&self, _offset: i64, _limit: i64) -> Result<Vec<Identity>> {
+        unimplemented!()
+    }
+}
+
+#[async_trait]
+impl StakingStore for Ledger {
+    async fn put_staking_position(&self, _owner: &Address, _position: &StakingPosition) -> Result<()> {
+        unimplemented!()
+    }
+
+    async fn get_staking_position(&self, _owner: &Address) -> Result<Option<StakingPosition>> {
+        Ok(None)
+    }
+
+    async fn staking_positions_for(&self, _validator: &Address) -> Result<Vec<(Address, StakingPosition)>> {
+        Ok(Vec::new())
+    }
+}
+
+pub fn block(height: u64, transactions: Vec<Transaction>) -> Block {
+    let header = BlockHeader {
+        previous_hash: [0; 32],
+        merkle_root: [0; 32],
+        height,
+        timestamp: 1_700_000_000,
+        difficulty: 1,
+        nonce: 0,
+        validator: [9; 32],
+        stake_weight: 0,
+        state_root: [0; 32],
+    };
+    Block { header, transactions, pow_hash: [0; 32], validator_signature: Vec::new() }
+}
+
+/// A transaction spending from the key `[7; 32]`.
+pub fn spend(memo: Option<String>, outputs: Vec<TxOutput>) -> Transaction {
+    let input = TxInput {
+        previous_tx: [1; 32],
+        output_index: 0,
+        signature: vec![],
+        public_key: vec![7; 32],
+        witness: vec![],
+    };
+    Transaction { inputs: vec![input], outputs, fee: 5, nonce: 1, memo, lock_time: 0 }
+}
@@ -0,0 +1,481 @@
+//! Read-only tools the model may call while answering: balances, blocks,
+//! identity search and mempool statistics, run against the store. Calls are
+//! capped per question and every one is audited, whether it ran, failed or
+//! was refused.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Result};
+use dxid_config::ToolSettings;
+use dxid_core::{now_ts, Identity, IdentityId};
+use dxid_crypto::{address_from_string, address_to_string};
+use dxid_storage::{BlockStore, IdentityStore, StateStore, TxStore};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use tracing::info;
+
+use crate::dedup::{identity_text, DEDUP_NAMESPACE};
+use crate::indexer::summarize_block;
+use crate::llm::{ChatMessage, ChatRequest, ChatTurn, LlmProvider, ToolCall, ToolSpec};
+use crate::search::{HybridSearch, SearchQuery};
+
+/// Audit records kept for [`ToolRunner::audit`]; the oldest are dropped first.
+const MAX_AUDIT: usize = 1000;
+/// Most transaction summaries returned with a block.
+const MAX_BLOCK_TXS: usize = 20;
+/// Most identities returned by one search.
+const MAX_IDENTITY_MATCHES: usize = 10;
+/// Most identities read by a text search without an embedder.
+const MAX_IDENTITIES_SCANNED: i64 = 1000;
+/// Pending transactions read for mempool fee statistics.
+const MEMPOOL_SAMPLE: i64 = 500;
+
+/// The store behind the tools. Identity search ranks by similarity when
+/// given a [`HybridSearch`] and matches text otherwise.
+pub struct ChainTools {
+    blocks: Arc<dyn BlockStore>,
+    state: Arc<dyn StateStore>,
+    txs: Arc<dyn TxStore>,
+    identities: Arc<dyn IdentityStore>,
+    search: Option<Arc<HybridSearch>>,
+}
+
+#[derive(Deserialize)]
+struct BalanceArgs {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct BlockArgs {
+    height: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct IdentityArgs {
+    query: String,
+    limit: Option<usize>,
+}
+
+impl ChainTools {
+    pub fn new(
+        blocks: Arc<dyn BlockStore>,
+        state: Arc<dyn StateStore>,
+        txs: Arc<dyn TxStore>,
+        identities: Arc<dyn IdentityStore>,
+    ) -> Self {
+        Self { blocks, state, txs, identities, search: None }
+    }
+
+    pub fn with_search(mut self, search: Arc<HybridSearch>) -> Self {
+        self.search = Some(search);
+        self
+    }
+
+    pub fn specs(&self) -> Vec<ToolSpec> {
+        vec![
+            ToolSpec {
+                name: "get_balance",
+                description: "Balance and number of unspent outputs of an address.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": { "address": { "type": "string", "description": "Base58 address" } },
+                    "required": ["address"]
+                }),
+            },
+            ToolSpec {
+                name: "get_block",
+                description: "A block's header summary and its transactions, as sentences.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "height": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "The latest block if left out"
+                        }
+                    }
+                }),
+            },
+            ToolSpec {
+                name: "search_identities",
+                description: "Identities whose id or attributes match a query, with their status.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "Names, attribute values or an identity id"
+                        },
+                        "limit": { "type": "integer", "minimum": 1, "maximum": MAX_IDENTITY_MATCHES }
+                    },
+                    "required": ["query"]
+                }),
+            },
+            ToolSpec {
+                name: "mempool_stats",
+                description: "Transactions waiting for inclusion and their fees.",
+                parameters: json!({ "type": "object", "properties": {} }),
+            },
+        ]
+    }
+
+    /// Runs the tool `name`; nothing it does writes to the store.
+    pub async fn call(&self, name: &str, arguments: &Value) -> Result<Value> {
+        match name {
+            "get_balance" => self.balance(args(arguments)?).await,
+            "get_block" => self.block(args(arguments)?).await,
+            "search_identities" => self.search_identities(args(arguments)?).await,
+            "mempool_stats" => self.mempool_stats().await,
+            _ => bail!("no tool named {name}"),
+        }
+    }
+
+    async fn balance(&self, args: BalanceArgs) -> Result<Value> {
+        let address = address_from_string(&args.address).map_err(|_| anyhow!("not a base58 address"))?;
+        let balance = self.state.get_balance(&address).await?;
+        let utxos = self.txs.list_utxos(&address).await?;
+        Ok(json!({
+            "address": address_to_string(&address),
+            "balance": balance,
+            "unspent_outputs": utxos.len()
+        }))
+    }
+
+    async fn block(&self, args: BlockArgs) -> Result<Value> {
+        let height = match args.height {
+            Some(height) => height,
+            None => self.blocks.best_height().await?.ok_or_else(|| anyhow!("no blocks are stored yet"))?,
+        };
+        let block = self
+            .blocks
+            .get_block_by_height(height as i64)
+            .await?
+            .ok_or_else(|| anyhow!("no block at height {height}"))?;
+        let mut snippets = summarize_block(&block).into_iter().map(|snippet| snippet.text);
+        let summary = snippets.next().unwrap_or_default();
+        let transactions: Vec<String> = snippets.by_ref().take(MAX_BLOCK_TXS).collect();
+        Ok(json!({
+            "height": height,
+            "summary": summary,
+            "transactions": transactions,
+            "more_transactions": snippets.count()
+        }))
+    }
+
+    async fn search_identities(&self, args: IdentityArgs) -> Result<Value> {
+        let limit = args.limit.unwrap_or(5).clamp(1, MAX_IDENTITY_MATCHES);
+        let mut matches = Vec::new();
+        if let Ok(id) = args.query.trim().parse::<IdentityId>() {
+            matches.extend(
+                self.identities.get_identity(&id).await?.map(|identity| identity_json(&identity, None)),
+            );
+        } else if let Some(search) = &self.search {
+            let query = SearchQuery {
+                namespace: DEDUP_NAMESPACE.to_string(),
+                text: args.query,
+                limit: Some(limit),
+                attributes: BTreeMap::new(),
+            };
+            for result in search.search(&query).await? {
+                let Ok(id) = result.id.parse::<IdentityId>() else {
+                    continue;
+                };
+                if let Some(identity) = self.identities.get_identity(&id).await? {
+                    matches.push(identity_json(&identity, Some(result.scores.similarity)));
+                }
+            }
+        } else {
+            let query = args.query.to_lowercase();
+            let mut offset = 0;
+            while matches.len() < limit && offset < MAX_IDENTITIES_SCANNED {
+                let page = self.identities.list_identities(offset, 100).await?;
+                if page.is_empty() {
+                    break;
+                }
+                offset += page.len() as i64;
+                matches.extend(
+                    page.iter()
+                        .filter(|identity| identity_text(identity).to_lowercase().contains(&query))
+                        .map(|identity| identity_json(identity, None)),
+                );
+            }
+            matches.truncate(limit);
+        }
+        Ok(json!({ "identities": matches }))
+    }
+
+    async fn mempool_stats(&self) -> Result<Value> {
+        let pending = self.txs.pending_count().await?;
+        let mut fees: Vec<u64> =
+            self.txs.pending_txs(MEMPOOL_SAMPLE).await?.iter().map(|tx| tx.fee).collect();
+        fees.sort_unstable();
+        Ok(json!({
+            "pending": pending,
+            "sampled": fees.len(),
+            "total_fees": fees.iter().sum::<u64>(),
+            "median_fee": fees.get(fees.len() / 2),
+            "max_fee": fees.last()
+        }))
+    }
+}
+
+fn args<T: for<'de> Deserialize<'de>>(arguments: &Value) -> Result<T> {
+    serde_json::from_value(arguments.clone()).map_err(|err| anyhow!("bad arguments: {err}"))
+}
+
+fn identity_json(identity: &Identity, similarity: Option<f32>) -> Value {
+    let attributes: BTreeMap<_, _> =
+        identity.attributes.values().map(|a| (a.key.as_str(), a.value.as_str())).collect();
+    json!({
+        "id": identity.id,
+        "status": format!("{:?}", identity.status),
+        "keys": identity.public_keys.len(),
+        "attributes": attributes,
+        "similarity": similarity
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum ToolOutcome {
+    Ok,
+    Failed {
+        error: String,
+    },
+    /// Refused because the question's calls were used up.
+    OverBudget,
+}
+
+impl fmt::Display for ToolOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => f.write_str("ok"),
+            Self::Failed { error } => write!(f, "failed: {error}"),
+            Self::OverBudget => f.write_str("over budget"),
+        }
+    }
+}
+
+/// One tool call as it happened.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolAudit {
+    /// Numbers the questions answered since the node started; shared by
+    /// the calls made for one.
+    pub query: u64,
+    /// The model turn that asked for the call, from 1.
+    pub round: usize,
+    pub tool: String,
+    pub arguments: Value,
+    #[serde(flatten)]
+    pub outcome: ToolOutcome,
+    pub duration_ms: u64,
+    pub at: u64,
+}
+
+/// Lets the model call [`ChainTools`] until it answers, within the budget
+/// of `[ai.tools]`.
+pub struct ToolRunner {
+    tools: ChainTools,
+    settings: ToolSettings,
+    audit: RwLock<VecDeque<ToolAudit>>,
+    queries: AtomicU64,
+}
+
+impl ToolRunner {
+    pub fn new(tools: ChainTools, settings: ToolSettings) -> Self {
+        Self { tools, settings, audit: RwLock::new(VecDeque::new()), queries: AtomicU64::new(0) }
+    }
+
+    /// The answer to `request` and the calls made for it. Failed calls are
+    /// reported to the model, which may try something else; once the calls
+    /// are used up it is told so and has to answer with what it has.
+    pub async fn answer(
+        &self,
+        llm: &dyn LlmProvider,
+        request: &ChatRequest,
+    ) -> Result<(String, Vec<ToolAudit>)> {
+        let query = self.queries.fetch_add(1, Ordering::Relaxed) + 1;
+        let specs = self.tools.specs();
+        let mut history = Vec::new();
+        let mut calls_left = self.settings.max_calls;
+        let mut audits = Vec::new();
+        for round in 1..=self.settings.max_rounds {
+            let calls = match llm.converse(request, &history, &specs).await? {
+                ChatTurn::Answer(answer) => return Ok((answer, audits)),
+                ChatTurn::ToolCalls { text, calls } => {
+                    history.push(ChatMessage::ToolCalls { text, calls: calls.clone() });
+                    calls
+                }
+            };
+            for call in calls {
+                let (audit, content) = self.run(query, round, &call, &mut calls_left).await;
+                history.push(ChatMessage::ToolResult { call_id: call.id, content: content.to_string() });
+                audits.push(audit);
+            }
+        }
+        bail!("no answer within {} model turns", self.settings.max_rounds)
+    }
+
+    async fn run(
+        &self,
+        query: u64,
+        round: usize,
+        call: &ToolCall,
+        calls_left: &mut usize,
+    ) -> (ToolAudit, Value) {
+        let started = Instant::now();
+        let (outcome, content) = if *calls_left == 0 {
+            let content =
+                json!({ "error": "no tool calls left for this question; answer with what you have" });
+            (ToolOutcome::OverBudget, content)
+        } else {
+            *calls_left -= 1;
+            match self.tools.call(&call.name, &call.arguments).await {
+                Ok(result) => (ToolOutcome::Ok, result),
+                Err(err) => {
+                    let error = format!("{err:#}");
+                    (ToolOutcome::Failed { error: error.clone() }, json!({ "error": error }))
+                }
+            }
+        };
+        let audit = ToolAudit {
+            query,
+            round,
+            tool: call.name.clone(),
+            arguments: call.arguments.clone(),
+            outcome,
+            duration_ms: started.elapsed().as_millis() as u64,
+            at: now_ts(),
+        };
+        info!("ai query {query} round {round}: {} {} -> {}", audit.tool, audit.arguments, audit.outcome);
+        let mut log = self.audit.write().await;
+        if log.len() == MAX_AUDIT {
+            log.pop_front();
+        }
+        log.push_back(audit.clone());
+        (audit, content)
+    }
+
+    /// The latest `limit` calls, newest first.
+    pub async fn audit(&self, limit: usize) -> Vec<ToolAudit> {
+        self.audit.read().await.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::llm::LlmError;
+    use crate::stream::TokenStream;
+    use crate::test_support::{Blocks, Ledger};
+
+    /// Asks for the same calls every turn until it has seen `rounds` of results.
+    struct Scripted {
+        calls: Vec<ToolCall>,
+        rounds: usize,
+        seen: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for Scripted {
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+
+        fn model(&self) -> &str {
+            "scripted"
+        }
+
+        async fn complete(&self, _request: &ChatRequest) -> Result<String, LlmError> {
+            unimplemented!()
+        }
+
+        async fn stream(&self, _request: &ChatRequest) -> Result<TokenStream, LlmError> {
+            unimplemented!()
+        }
+
+        async fn converse(
+            &self,
+            _request: &ChatRequest,
+            history: &[ChatMessage],
+            _tools: &[ToolSpec],
+        ) -> Result<ChatTurn, LlmError> {
+            let results: Vec<_> = history
+                .iter()
+                .filter_map(|message| match message {
+                    ChatMessage::ToolResult { content, .. } => Some(content.clone()),
+                    ChatMessage::ToolCalls { .. } => None,
+                })
+                .collect();
+            if results.len() >= self.rounds * self.calls.len() {
+                *self.seen.lock().unwrap() = results;
+                return Ok(ChatTurn::Answer("done".into()));
+            }
+            Ok(ChatTurn::ToolCalls { text: None, calls: self.calls.clone() })
+        }
+    }
+
+    fn call(name: &str, arguments: Value) -> ToolCall {
+        ToolCall { id: format!("call-{name}"), name: name.into(), arguments }
+    }
+
+    #[tokio::test]
+    async fn runs_tools_within_budget_and_audits_every_call() {
+        let ledger = Arc::new(Ledger::default());
+        let address = [4; 32];
+        ledger.balances.lock().unwrap().insert(address, 250);
+        let tools =
+            ChainTools::new(Arc::new(Blocks::with_payments(3)), ledger.clone(), ledger.clone(), ledger);
+        let runner = ToolRunner::new(tools, ToolSettings { enabled: true, max_calls: 3, max_rounds: 5 });
+        let llm = Scripted {
+            calls: vec![
+                call("get_balance", json!({ "address": address_to_string(&address) })),
+                call("get_block", json!({})),
+                call("drop_table", json!({})),
+            ],
+            rounds: 2,
+            seen: Mutex::new(Vec::new()),
+        };
+        let request = ChatRequest { system: String::new(), history: Vec::new(), user: "Balance?".into() };
+
+        let (answer, audits) = runner.answer(&llm, &request).await.unwrap();
+        assert_eq!(answer, "done");
+        let seen = llm.seen.lock().unwrap().clone();
+        assert!(seen[0].contains(r#""balance":250"#));
+        assert!(seen[1].contains(r#""height":2"#) && seen[1].contains("Block 2 at"));
+        let outcomes: Vec<_> = audits.iter().map(|audit| (audit.round, audit.outcome.clone())).collect();
+        assert_eq!(
+            outcomes,
+            [
+                (1, ToolOutcome::Ok),
+                (1, ToolOutcome::Ok),
+                (1, ToolOutcome::Failed { error: "no tool named drop_table".into() }),
+                (2, ToolOutcome::OverBudget),
+                (2, ToolOutcome::OverBudget),
+                (2, ToolOutcome::OverBudget)
+            ]
+        );
+        assert!(seen[3].contains("no tool calls left"));
+        let logged = runner.audit(10).await;
+        assert_eq!((logged.len(), logged[0].tool.as_str(), logged[0].query), (6, "drop_table", 1));
+
+        // A model that never answers runs out of turns.
+        let endless = Scripted { rounds: 100, ..llm };
+        let err = runner.answer(&endless, &request).await.unwrap_err();
+        assert_eq!(err.to_string(), "no answer within 5 model turns");
+        let serialized = serde_json::to_value(&runner.audit(1).await[0]).unwrap();
+        assert_eq!(
+            (serialized["query"].as_u64(), serialized["outcome"].as_str()),
+            (Some(2), Some("over_budget"))
+        );
+    }
+}
@@ -1,14 +1,38 @@
-use std::path::PathBuf;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use dxid_ai_hypervisor::Hypervisor;
-use dxid_config::DxidConfig;
-use dxid_core::CrossChainMessage;
-use dxid_node::run_node;
-use dxid_wallet::WalletStore;
+use clap::{ArgGroup, Parser, Subcommand};
+use dxid_ai_hypervisor::{BackfillOptions, ChainIndexer, HybridSearch, Hypervisor, SearchQuery};
+use dxid_config::{DxidConfig, ExternalChainSettings, NetworkProfile};
+use dxid_contracts::{deploy_call, ContractAbi, UpgradePolicy};
+use dxid_core::contract::{ContractCall, TxReceipt};
+use dxid_core::genesis::{GenesisAllocation, GenesisConfig, GenesisValidator};
+use dxid_core::staking::ValidatorStatus;
+use dxid_core::{
+    build_oauth_like_challenge, verify_oauth_like_proof, Address, CrossChainMessage, CrossChainTx,
+    HalvingSchedule, IdentityId, OAuthLikeProofRequest, OAuthLikeProofResponse, TokenEconomics,
+};
+use dxid_interop::policy::BridgePolicy;
+use dxid_interop::registry::{build_adapter, build_snark, AdapterRegistry, RegisteredAdapter};
+use dxid_interop::relayer::{Relayer, RelayerConfig};
+use dxid_node::{run_node, NodeOptions};
+use dxid_storage::{negotiate_dimensions, BlockStore, PgStore};
+use dxid_vectors::{build_embedder, RateLimitedEmbedder};
+use dxid_wallet::{
+    address_from_bech32, verify_message, BridgeMessage, BridgeTransferState, IdentityChange, Keyfile,
+    MessageSignature, NodeClient, NodeStatus, PeerInfo, Wallet, WalletStore,
+};
+use serde_json::json;
 use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+mod output;
+
+use output::{failed, Output};
 
 #[derive(Parser)]
 #[command(name = "dxid", version, about = "dxid Layer-0 CLI")]
@@ -16,6 +40,12 @@ struct Cli {
     /// If set, show help instead of launching TUI when no subcommand is provided.
     #[arg(long, action = clap::ArgAction::SetTrue)]
     help_mode: bool,
+    /// REST endpoint of the node the TUI watches; defaults to `DXID_NODE_URL`, then the local node.
+    #[arg(long)]
+    node: Option<String>,
+    /// `json` prints one JSON document per result for scripts; see docs/cli_usage.md
+    #[arg(long, global = true, value_enum, default_value_t = Output::Table)]
+    output: Output,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -27,6 +57,16 @@ enum Commands {
         #[arg(long, default_value = "config/dxid.toml")]
         config: PathBuf,
     },
+    /// Check the node config and show it as the node would load it
+    Config {
+        #[command(subcommand)]
+        cmd: ConfigCmd,
+    },
+    /// Generate and check genesis files
+    Genesis {
+        #[command(subcommand)]
+        cmd: GenesisCmd,
+    },
     /// Start node
     Node {
         #[command(subcommand)]
@@ -37,20 +77,147 @@ enum Commands {
         #[command(subcommand)]
         cmd: WalletCmd,
     },
+    /// Transactions
+    Tx {
+        #[command(subcommand)]
+        cmd: TxCmd,
+    },
+    /// Staking and validator status
+    Stake {
+        #[command(subcommand)]
+        cmd: StakeCmd,
+    },
+    /// dxid identities and OAuth-like proofs
+    Identity {
+        #[command(subcommand)]
+        cmd: IdentityCmd,
+    },
+    /// Back up and restore wallet keys
+    Key {
+        #[command(subcommand)]
+        cmd: KeyCmd,
+    },
+    /// Deploy and call WASM contracts
+    Contract {
+        #[command(subcommand)]
+        cmd: ContractCmd,
+    },
+    /// Register external chains and follow bridge messages
+    Bridge {
+        #[command(subcommand)]
+        cmd: BridgeCmd,
+    },
+    /// Re-embed stored chain data and search it for the AI hypervisor
+    Embed {
+        #[command(subcommand)]
+        cmd: EmbedCmd,
+    },
     /// AI hypervisor query
     Ai {
         #[arg()]
         prompt: String,
+        /// Continue this conversation, recording the exchange in it
+        #[arg(long)]
+        session: Option<Uuid>,
     },
 }
 
 #[derive(Subcommand)]
-enum NodeCmd {
-    Start {
+enum ConfigCmd {
+    /// Load the config with `DXID__*` environment overrides and report every problem
+    Check {
+        #[arg(long, default_value = "config/dxid.toml")]
+        config: PathBuf,
+    },
+    /// Print the effective config: the file with environment overrides applied
+    Show {
         #[arg(long, default_value = "config/dxid.toml")]
         config: PathBuf,
+        /// Mask passwords, API keys, tokens and webhook secrets
+        #[arg(long)]
+        redact_secrets: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum GenesisCmd {
+    /// Build a genesis file from flags, a base file or prompts, and print its hash
+    New {
+        #[arg(long, default_value = "config/genesis.json")]
+        out: PathBuf,
+        /// Genesis file to start from; flags override its fields and add to its lists
+        #[arg(long)]
+        from: Option<PathBuf>,
+        #[arg(long)]
+        chain_id: Option<String>,
+        /// Initial balance as `<address>=<amount>`; repeatable
+        #[arg(long = "alloc")]
+        allocations: Vec<String>,
+        /// Validator and the stake it bonds to itself as `<address>=<stake>`; repeatable
+        #[arg(long = "validator")]
+        validators: Vec<String>,
+        #[arg(long)]
+        max_supply: Option<u64>,
+        /// Block reward before any halving
+        #[arg(long)]
+        base_reward: Option<u64>,
+        /// Blocks between reward halvings
+        #[arg(long)]
+        halving_interval: Option<u64>,
+        /// Issued supply that forces a halving
+        #[arg(long)]
+        supply_threshold: Option<u64>,
+        /// Share of each block reward paid to the treasury, in basis points
+        #[arg(long)]
+        treasury_bps: Option<u16>,
+        /// Address the treasury's share is paid to; needed for a nonzero `--treasury-bps`
+        #[arg(long)]
+        treasury: Option<String>,
+        /// Unix seconds of the genesis block; by default that of `--from`, else now
+        #[arg(long)]
+        timestamp: Option<u64>,
+        /// Never prompt, even on a terminal without validators given
+        #[arg(long)]
+        no_prompt: bool,
+        /// Replace an existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check a genesis file and print its summary and hash
+    Validate {
+        #[arg(default_value = "config/genesis.json")]
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum NodeCmd {
+    Start {
+        /// Config file; defaults to config/dxid.toml, or none for a dev chain
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// mainnet, testnet or devnet, overriding the file's chain.profile
+        #[arg(long, conflicts_with = "dev")]
+        profile: Option<NetworkProfile>,
+        /// Run a single-node devnet with instant blocks and prefunded accounts
+        #[arg(long)]
+        dev: bool,
+    },
+    /// Show height, sync state, peers and mempool of a running node
+    Status {
+        /// REST endpoint; defaults to `DXID_NODE_URL`, then the local node
+        #[arg(long)]
+        node: Option<String>,
+        /// Also show the stake bonded to this validator
+        #[arg(long)]
+        validator: Option<String>,
+        /// Refresh until interrupted
+        #[arg(long)]
+        watch: bool,
+        /// Seconds between refreshes with `--watch`
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
     },
-    Status,
 }
 
 #[derive(Subcommand)]
@@ -60,57 +227,712 @@ enum WalletCmd {
         name: String,
         #[arg(long)]
         password: String,
+        /// Replace an existing wallet of the same name (it is kept as a backup)
+        #[arg(long)]
+        force: bool,
     },
     List,
+    /// Re-encrypt a wallet under a new password
+    ChangePassword {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        old_password: String,
+        #[arg(long)]
+        new_password: String,
+    },
+    /// Sign text to prove control of a wallet address
+    SignMessage {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        /// Address to sign for; defaults to the wallet's main address
+        #[arg(long)]
+        address: Option<String>,
+        message: String,
+    },
+    /// Check a signature produced by `sign-message`
+    VerifyMessage {
+        #[arg(long)]
+        address: String,
+        #[arg(long)]
+        signature: String,
+        message: String,
+    },
+    /// Lock tokens in the bridge escrow for a recipient on another chain
+    BridgeSend {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        /// Destination chain id
+        #[arg(long)]
+        dest: String,
+        /// Recipient in the destination chain's address format
+        #[arg(long)]
+        recipient: String,
+        #[arg(long)]
+        amount: u64,
+        /// Fee per byte; defaults to the node's estimate
+        #[arg(long)]
+        fee_rate: Option<u64>,
+        /// Return once the lock is submitted instead of waiting for delivery
+        #[arg(long)]
+        no_wait: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TxCmd {
+    /// Pay an address from a wallet and submit the transaction to the node
+    Send {
+        /// Wallet to spend from
+        #[arg(long, default_value = "default")]
+        from: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        amount: u64,
+        /// Fee per byte; defaults to the node's estimate
+        #[arg(long)]
+        fee_rate: Option<u64>,
+        #[arg(long)]
+        memo: Option<String>,
+        /// Show the fee and change without submitting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum StakeCmd {
+    /// Bond stake to the wallet's own validator
+    Add {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        #[arg(long)]
+        amount: u64,
+        /// Fee per byte; defaults to the node's estimate
+        #[arg(long)]
+        fee_rate: Option<u64>,
+    },
+    /// Start unbonding stake from a validator
+    Remove {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        /// Validator to unbond from; defaults to the wallet's own address
+        #[arg(long)]
+        validator: Option<String>,
+        #[arg(long)]
+        amount: u64,
+        /// Fee per byte; defaults to the node's estimate
+        #[arg(long)]
+        fee_rate: Option<u64>,
+    },
+    /// Bond stake to another validator
+    Delegate {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        #[arg(long)]
+        validator: String,
+        #[arg(long)]
+        amount: u64,
+        /// Fee per byte; defaults to the node's estimate
+        #[arg(long)]
+        fee_rate: Option<u64>,
+    },
+    /// Show bonds, unbonding stake and pending rewards, or withdraw them
+    Rewards {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        /// Withdraw rewards and released stake to the wallet
+        #[arg(long)]
+        withdraw: bool,
+        /// Needed with `--withdraw`
+        #[arg(long)]
+        password: Option<String>,
+        /// Fee per byte; defaults to the node's estimate
+        #[arg(long)]
+        fee_rate: Option<u64>,
+    },
+    /// Stake bonded to a validator by itself and its delegators
+    Status {
+        /// Validator address; defaults to the wallet's own address
+        #[arg(long)]
+        validator: Option<String>,
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum IdentityCmd {
+    /// Create an identity keyed by the wallet and register it on the node
+    Create {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+    },
+    /// Show a published identity
+    Show {
+        /// Identity id; defaults to the wallet's identity
+        #[arg(long)]
+        id: Option<IdentityId>,
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+    },
+    /// Set an attribute of the wallet's identity
+    AttrSet {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        key: String,
+        value: String,
+    },
+    /// Move the identity to a new key, signed by the current one (HD wallets)
+    Rotate {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+    },
+    /// Revoke the wallet's identity; this cannot be undone
+    Revoke {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+    },
+    /// Print a proof request asking an identity for attributes, as JSON
+    Challenge {
+        /// Who is asking, e.g. the relying service's domain
+        #[arg(long)]
+        audience: String,
+        /// Attributes the answer may disclose
+        #[arg(long, value_delimiter = ',')]
+        scope: Vec<String>,
+    },
+    /// Answer a proof request with the wallet's identity, printing the response as JSON
+    Prove {
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        /// Attributes to disclose; each must be in the request's scope
+        #[arg(long, value_delimiter = ',')]
+        disclose: Vec<String>,
+        /// The request printed by `challenge`
+        request: String,
+    },
+    /// Check a proof response against its request and the published identity
+    Verify {
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        request: String,
+        response: String,
+    },
 }
 
-fn main() -> Result<()> {
+/// Secrets and passwords are typed at hidden prompts, never passed as
+/// arguments, so they stay out of the shell history.
+#[derive(Subcommand)]
+enum KeyCmd {
+    /// Show an HD wallet's mnemonic, or write the wallet's keys to an encrypted keyfile
+    #[command(group(ArgGroup::new("format").required(true).args(["mnemonic", "keyfile"])))]
+    Export {
+        #[arg(long, default_value = "default")]
+        name: String,
+        /// Show the mnemonic on the terminal, after confirmation
+        #[arg(long)]
+        mnemonic: bool,
+        /// File to create, encrypted under a new keyfile password
+        #[arg(long)]
+        keyfile: Option<PathBuf>,
+    },
+    /// Create a wallet from a hex secret key, a mnemonic or a keyfile
+    #[command(group(ArgGroup::new("source").required(true).args(["hex", "mnemonic", "keyfile"])))]
+    Import {
+        #[arg(long, default_value = "default")]
+        name: String,
+        /// Prompt for a 32-byte ed25519 secret key in hex
+        #[arg(long)]
+        hex: bool,
+        /// Prompt for a BIP-39 mnemonic
+        #[arg(long)]
+        mnemonic: bool,
+        /// Keyfile written by `key export --keyfile`
+        #[arg(long)]
+        keyfile: Option<PathBuf>,
+        /// Node to scan for the used addresses of a restored HD wallet
+        #[arg(long)]
+        scan: Option<String>,
+        /// Replace an existing wallet of the same name (it is kept as a backup)
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ContractCmd {
+    /// Deploy a contract from the wallet's main address and wait for its receipt
+    Deploy {
+        /// Contract code, as WASM or WAT
+        wasm: PathBuf,
+        /// JSON ABI of the methods the code exports
+        #[arg(long)]
+        abi: PathBuf,
+        /// Address that may upgrade the contract; immutable without one
+        #[arg(long)]
+        admin: Option<String>,
+        /// Salt of the contract address; random by default
+        #[arg(long)]
+        salt: Option<u64>,
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        /// Native tokens to fund the contract with
+        #[arg(long, default_value_t = 0)]
+        value: u64,
+        /// Most gas to pay for; defaults to the node's estimate plus a margin
+        #[arg(long)]
+        gas_limit: Option<u64>,
+        /// Fee per byte; defaults to the node's estimate
+        #[arg(long)]
+        fee_rate: Option<u64>,
+    },
+    /// Call a contract method from the wallet's main address and wait for its receipt
+    Call {
+        /// Contract address
+        contract: String,
+        method: String,
+        /// JSON array of the method's arguments, checked against the contract's ABI
+        #[arg(long, default_value = "[]")]
+        args: String,
+        #[arg(long, default_value = "default")]
+        name: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        /// Native tokens to send with the call
+        #[arg(long, default_value_t = 0)]
+        value: u64,
+        /// Most gas to pay for; defaults to the node's estimate plus a margin
+        #[arg(long)]
+        gas_limit: Option<u64>,
+        /// Fee per byte; defaults to the node's estimate
+        #[arg(long)]
+        fee_rate: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BridgeCmd {
+    /// Add an external chain adapter to the node config after a handshake with it
+    RegisterChain {
+        chain_id: String,
+        /// Adapter kind: json_rpc, grpc or bitcoin
+        #[arg(long)]
+        kind: String,
+        #[arg(long)]
+        endpoint: String,
+        /// Prompt for a bearer token to send to the endpoint
+        #[arg(long)]
+        auth_token: bool,
+        /// Confirmations required before inbound events from the chain are acted on
+        #[arg(long)]
+        confirmations: Option<u64>,
+        /// JSON object of adapter-specific options
+        #[arg(long)]
+        options: Option<String>,
+        /// Register without a handshake, e.g. while the chain is down
+        #[arg(long)]
+        no_connect: bool,
+        /// Replace the chain's existing entry
+        #[arg(long)]
+        force: bool,
+        #[arg(long, default_value = "config/dxid.toml")]
+        config: PathBuf,
+    },
+    /// Queue a test message to an external chain for the node's relayer
+    Send {
+        dest: String,
+        /// JSON payload of the message
+        #[arg(long, default_value = r#"{"ping":true}"#)]
+        payload: String,
+        #[arg(long, default_value_t = 0)]
+        fee: u64,
+        #[arg(long, default_value = "config/dxid.toml")]
+        config: PathBuf,
+    },
+    /// Show the relay status and audit log of a bridge message
+    Status {
+        id: Uuid,
+        /// REST endpoint; defaults to `DXID_NODE_URL`, then the local node
+        #[arg(long)]
+        node: Option<String>,
+        /// Refresh until the message is confirmed or fails
+        #[arg(long)]
+        watch: bool,
+    },
+    /// List external chains with their relay metrics, then queued messages
+    List {
+        /// REST endpoint; defaults to `DXID_NODE_URL`, then the local node
+        #[arg(long)]
+        node: Option<String>,
+        /// Only messages in this status: pending, submitted, confirmed or failed
+        #[arg(long)]
+        status: Option<BridgeTransferState>,
+        /// Most messages of each status to show
+        #[arg(long, default_value_t = 20)]
+        limit: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum EmbedCmd {
+    /// Summarize and embed stored blocks into a vector namespace, resuming an interrupted run
+    Backfill {
+        #[arg(long, default_value = "config/dxid.toml")]
+        config: PathBuf,
+        /// Namespace to fill; defaults to `[ai.chain_index] namespace`
+        #[arg(long)]
+        namespace: Option<String>,
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+        /// Last height, inclusive; defaults to the best stored block
+        #[arg(long)]
+        to: Option<u64>,
+        /// Progress file; defaults to `backfill-<namespace>.json` in the current directory
+        #[arg(long)]
+        checkpoint: Option<PathBuf>,
+        /// Most embedding requests per minute
+        #[arg(long, default_value_t = 60)]
+        rate: u32,
+        /// Blocks embedded between checkpoints
+        #[arg(long, default_value_t = 20)]
+        batch_blocks: u64,
+    },
+    /// Nearest neighbours of a text, ranked by `[ai.ranking]` with each score component
+    Search {
+        text: String,
+        #[arg(long, default_value = "config/dxid.toml")]
+        config: PathBuf,
+        /// Namespace to search, e.g. `identities`; defaults to `[ai.chain_index] namespace`
+        #[arg(long)]
+        namespace: Option<String>,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// `key=value` a result should have, as metadata or identity attribute; repeatable
+        #[arg(long = "attr")]
+        attributes: Vec<String>,
+    },
+}
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
+    let output = cli.output;
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => output.fail(&err),
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let output = cli.output;
     if cli.command.is_none() && !cli.help_mode {
-        return dxid_tui::launch_tui();
+        let config = dxid_tui::TuiConfig::default();
+        return dxid_tui::launch_tui_with(match cli.node {
+            Some(node) => config.with_endpoint(node),
+            None => config,
+        });
     }
-    match cli.command.unwrap_or(Commands::Init {
-        config: PathBuf::from("config/dxid.toml"),
-    }) {
-        Commands::Init { config } => init_config(config)?,
+    match cli.command.unwrap_or(Commands::Init { config: PathBuf::from("config/dxid.toml") }) {
+        Commands::Init { config } => init_config(output, config)?,
+        Commands::Config { cmd } => match cmd {
+            ConfigCmd::Check { config } => {
+                let mut cfg = DxidConfig::load_unresolved(&config)
+                    .map_err(|err| anyhow::anyhow!("cannot load {config:?}: {err:#}"))?;
+                let unresolved = cfg.resolve_secrets();
+                let mut issues = cfg.check();
+                // An unresolved reference is reported once, not again as an invalid value.
+                issues.retain(|issue| unresolved.iter().all(|secret| secret.path != issue.path));
+                issues.splice(0..0, unresolved);
+                let report: Vec<_> = issues
+                    .iter()
+                    .map(|issue| json!({ "path": issue.path, "message": issue.message }))
+                    .collect();
+                let report = json!({ "config": config, "valid": issues.is_empty(), "issues": report });
+                output.emit(report, || {
+                    for issue in &issues {
+                        println!("{issue}");
+                    }
+                    if issues.is_empty() {
+                        println!("{config:?} is valid");
+                    }
+                });
+                if !issues.is_empty() {
+                    return Err(failed(format!("{} problem(s) in {config:?}", issues.len())));
+                }
+            }
+            ConfigCmd::Show { config, redact_secrets } => {
+                let cfg = DxidConfig::load(&config)?;
+                let cfg = if redact_secrets { cfg.redacted() } else { cfg };
+                match output {
+                    Output::Json => println!("{}", serde_json::to_value(&cfg)?),
+                    Output::Table => print!("{}", toml::to_string_pretty(&cfg)?),
+                }
+            }
+        },
+        Commands::Genesis { cmd } => genesis(output, cmd)?,
         Commands::Node { cmd } => match cmd {
-            NodeCmd::Start { config } => {
+            NodeCmd::Start { config, profile, dev } => {
+                let config = config.or_else(|| (!dev).then(|| PathBuf::from("config/dxid.toml")));
                 let rt = Runtime::new()?;
-                rt.block_on(async move { run_node(config).await })?;
+                rt.block_on(async move { run_node(NodeOptions { config, profile, dev }).await })?;
             }
-            NodeCmd::Status => {
-                println!("Status endpoint not implemented; query /status REST");
+            NodeCmd::Status { node, validator, watch, interval } => {
+                let node = node_url(node);
+                let client = NodeClient::new(node.clone());
+                let validator = validator.as_deref().map(address_from_bech32).transpose()?;
+                let rt = Runtime::new()?;
+                rt.block_on(async move {
+                    if !watch {
+                        let report = node_report(&client, validator.as_ref()).await?;
+                        print_node_status(output, &node, &report);
+                        return Ok::<(), anyhow::Error>(());
+                    }
+                    loop {
+                        // In JSON mode each refresh is one line, for monitoring to follow.
+                        if !output.is_json() {
+                            // Clear the screen and home the cursor before each refresh.
+                            print!("\x1b[2J\x1b[H");
+                        }
+                        match node_report(&client, validator.as_ref()).await {
+                            Ok(report) => print_node_status(output, &node, &report),
+                            Err(err) => output
+                                .emit(json!({ "node": node, "error": format!("{err:#}") }), || {
+                                    println!("{node}: {err:#}")
+                                }),
+                        }
+                        if !output.is_json() {
+                            println!("\nRefreshing every {interval}s, Ctrl-C to stop");
+                        }
+                        tokio::time::sleep(std::time::Duration::from_secs(interval.max(1))).await;
+                    }
+                })?;
             }
         },
         Commands::Wallet { cmd } => match cmd {
-            WalletCmd::New { name, password } => {
-                let store = WalletStore::new(wallet_dir()?)?;
+            WalletCmd::New { name, password, force } => {
+                let store = WalletStore::new(wallet_dir()?)?.with_overwrite(force);
                 let wallet = store.create(&name, &password)?;
-                println!(
-                    "Created wallet {} address {}",
-                    wallet.name,
-                    dxid_crypto::address_to_string(&wallet.address)
-                );
+                let address = dxid_crypto::address_to_string(&wallet.address);
+                output.emit(json!({ "name": wallet.name, "address": address }), || {
+                    println!("Created wallet {} address {address}", wallet.name)
+                });
             }
             WalletCmd::List => {
                 let store = WalletStore::new(wallet_dir()?)?;
-                for w in store.list()? {
-                    println!(
-                        "{} -> {}",
-                        w.name,
-                        dxid_crypto::address_to_string(&w.address)
-                    );
+                let wallets = store.list()?;
+                let report: Vec<_> = wallets
+                    .iter()
+                    .map(|w| json!({ "name": w.name, "address": dxid_crypto::address_to_string(&w.address) }))
+                    .collect();
+                output.emit(json!(report), || {
+                    for w in &wallets {
+                        println!("{} -> {}", w.name, dxid_crypto::address_to_string(&w.address));
+                    }
+                });
+            }
+            WalletCmd::ChangePassword { name, old_password, new_password } => {
+                let store = WalletStore::new(wallet_dir()?)?;
+                store.rekey(&name, &old_password, &new_password)?;
+                output.emit(json!({ "name": name }), || println!("Changed password of wallet {name}"));
+            }
+            WalletCmd::SignMessage { name, password, address, message } => {
+                let store = WalletStore::new(wallet_dir()?)?;
+                let wallet = store.load(&name)?;
+                let address = address.as_deref().map(address_from_bech32).transpose()?;
+                let signature = store.sign_message(&wallet, &password, address, &message)?;
+                output.emit(json!({ "signature": signature.to_string() }), || println!("{signature}"));
+            }
+            WalletCmd::VerifyMessage { address, signature, message } => {
+                let address = address_from_bech32(&address)?;
+                let signature: MessageSignature = signature.parse()?;
+                let valid = verify_message(&address, &message, &signature)?;
+                output.emit(json!({ "valid": valid }), || {
+                    if valid {
+                        println!("Signature is valid")
+                    }
+                });
+                if !valid {
+                    return Err(failed("signature does not match address and message"));
                 }
             }
+            WalletCmd::BridgeSend { name, password, node, dest, recipient, amount, fee_rate, no_wait } => {
+                let client = NodeClient::new(node);
+                let store = WalletStore::new(wallet_dir()?)?.with_node(client.clone());
+                let mut wallet = store.load(&name)?;
+                let rt = Runtime::new()?;
+                rt.block_on(async move {
+                    let fee_rate = match fee_rate {
+                        Some(rate) => rate,
+                        None => client.fee_estimate().await?,
+                    };
+                    let send = store
+                        .bridge_send(&mut wallet, &password, &dest, &recipient, amount, fee_rate)
+                        .await?;
+                    let tx = hex::encode(send.sent.hash);
+                    let transfer = send.transfer.id;
+                    let locked = format!("Locked {amount} in tx {tx} as transfer {transfer}");
+                    let mut report = json!({
+                        "tx": tx,
+                        "transfer_id": transfer,
+                        "dest": dest,
+                        "amount": amount,
+                        "delivered": false,
+                    });
+                    if no_wait {
+                        output.emit(report, || println!("{locked}"));
+                        return Ok(());
+                    }
+                    output.note(locked);
+                    let status =
+                        store.await_bridge_delivery(&transfer, std::time::Duration::from_secs(5)).await?;
+                    report["delivered"] = true.into();
+                    report["attempts"] = status.attempts.into();
+                    report["receipt"] = status.receipt.clone().unwrap_or_default();
+                    output.emit(report, || {
+                        println!("Delivered to {dest} after {} attempt(s)", status.attempts);
+                        if let Some(receipt) = &status.receipt {
+                            println!("{receipt}");
+                        }
+                    });
+                    Ok::<(), anyhow::Error>(())
+                })?;
+            }
         },
-        Commands::Ai { prompt } => {
+        Commands::Tx { cmd } => match cmd {
+            TxCmd::Send { from, password, node, to, amount, fee_rate, memo, dry_run } => {
+                let recipients = [(address_from_bech32(&to)?, amount)];
+                let client = NodeClient::new(node);
+                let store = WalletStore::new(wallet_dir()?)?.with_node(client.clone());
+                let mut wallet = store.load(&from)?;
+                let rt = Runtime::new()?;
+                rt.block_on(async move {
+                    let fee_rate = match fee_rate {
+                        Some(rate) => rate,
+                        None => client.fee_estimate().await?,
+                    };
+                    if dry_run {
+                        let built =
+                            store.preview_payment(&wallet, &password, &recipients, fee_rate, memo).await?;
+                        let report = json!({
+                            "dry_run": true,
+                            "from": from,
+                            "to": to,
+                            "amount": amount,
+                            "inputs": built.spent.len(),
+                            "fee": built.fee,
+                            "fee_rate": fee_rate,
+                            "change": built.change,
+                        });
+                        output.emit(report, || {
+                            println!(
+                                "Would send {amount} to {to} from {} input(s): fee {} at {fee_rate}/byte, \
+                                 change {}",
+                                built.spent.len(),
+                                built.fee,
+                                built.change
+                            )
+                        });
+                        return Ok(());
+                    }
+                    let sent =
+                        store.send_payment(&mut wallet, &password, &recipients, fee_rate, memo).await?;
+                    let tx = hex::encode(sent.hash);
+                    let report = json!({
+                        "dry_run": false,
+                        "tx": tx,
+                        "from": from,
+                        "to": to,
+                        "amount": amount,
+                        "fee": sent.built.fee,
+                        "fee_rate": fee_rate,
+                        "change": sent.built.change,
+                    });
+                    output.emit(report, || {
+                        println!("Sent {amount} to {to} in tx {tx}");
+                        println!("Fee {} at {fee_rate}/byte, change {}", sent.built.fee, sent.built.change);
+                    });
+                    Ok::<(), anyhow::Error>(())
+                })?;
+            }
+        },
+        Commands::Stake { cmd } => stake(output, cmd)?,
+        Commands::Identity { cmd } => identity(output, cmd)?,
+        Commands::Key { cmd } => key(output, cmd)?,
+        Commands::Contract { cmd } => contract(output, cmd)?,
+        Commands::Bridge { cmd } => bridge(output, cmd)?,
+        Commands::Embed { cmd } => embed(output, cmd)?,
+        Commands::Ai { prompt, session } => {
             let cfg = DxidConfig::example();
             let rt = Runtime::new()?;
             rt.block_on(async move {
                 let store = Arc::new(dxid_storage::PgStore::connect(&cfg.db.url, cfg.db.pool_size).await?);
-                let hypervisor = Hypervisor::new(cfg.ai.clone(), store);
-                let ans = hypervisor.query(&prompt).await?;
-                println!("{ans}");
+                let hypervisor = Hypervisor::new(cfg.ai.clone(), store)?;
+                let answer = hypervisor.ask_in(session, &prompt).await?;
+                output.emit(json!(answer), || {
+                    println!("{}", answer.answer);
+                    for source in &answer.sources {
+                        println!("[{}] {}", source.id, source.text);
+                    }
+                    for call in &answer.tool_calls {
+                        println!("tool {}({}): {}", call.tool, call.arguments, call.outcome);
+                    }
+                    for result in &answer.analytics {
+                        println!("{} = {} {}", result.metric.as_str(), result.value, result.unit);
+                    }
+                });
                 Ok::<(), anyhow::Error>(())
             })?;
         }
@@ -118,9 +940,1073 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn init_config(path: PathBuf) -> Result<()> {
+/// What `node status` shows.
+struct NodeReport {
+    status: NodeStatus,
+    peers: Vec<PeerInfo>,
+    validator: Option<ValidatorStatus>,
+}
+
+async fn node_report(client: &NodeClient, validator: Option<&Address>) -> Result<NodeReport> {
+    let status = client.status().await?;
+    let peers = client.peers().await?;
+    let validator = match validator {
+        Some(validator) => Some(client.validator_status(validator).await?),
+        None => None,
+    };
+    Ok(NodeReport { status, peers, validator })
+}
+
+fn print_node_status(output: Output, node: &str, report: &NodeReport) {
+    let status = &report.status;
+    let synced = status.height >= status.network_height;
+    let peers: Vec<_> = report
+        .peers
+        .iter()
+        .map(|peer| json!({ "peer_id": peer.peer_id, "address": peer.address, "best_height": peer.best_height }))
+        .collect();
+    let json = json!({
+        "node": node,
+        "height": status.height,
+        "network_height": status.network_height,
+        "synced": synced,
+        "sync_progress": status.sync_progress(),
+        "mempool": status.mempool,
+        "peer_count": status.peers,
+        "peers": peers,
+        "validator": report.validator.as_ref().map(validator_json),
+    });
+    output.emit(json, || {
+        let sync = if synced {
+            "synced".to_string()
+        } else {
+            format!("syncing {:.1}%", status.sync_progress() * 100.0)
+        };
+        println!("Node {node}");
+        println!("Height {} / {} ({sync})", status.height, status.network_height);
+        println!("Mempool {} tx(s)", status.mempool);
+        println!("Peers {}", status.peers);
+        for peer in &report.peers {
+            println!("  {} {} at height {}", peer.peer_id, peer.address, peer.best_height);
+        }
+        if let Some(v) = &report.validator {
+            println!(
+                "Validator {}: self stake {}, delegated {} from {} delegator(s), unbonding {}",
+                dxid_crypto::address_to_string(&v.validator),
+                v.self_stake,
+                v.delegated,
+                v.delegators,
+                v.unbonding
+            );
+        }
+    });
+}
+
+fn validator_json(status: &ValidatorStatus) -> serde_json::Value {
+    json!({
+        "validator": dxid_crypto::address_to_string(&status.validator),
+        "self_stake": status.self_stake,
+        "delegated": status.delegated,
+        "total_stake": status.total_stake(),
+        "delegators": status.delegators,
+        "unbonding": status.unbonding,
+        "height": status.height,
+    })
+}
+
+fn stake(output: Output, cmd: StakeCmd) -> Result<()> {
+    let node = match &cmd {
+        StakeCmd::Add { node, .. }
+        | StakeCmd::Remove { node, .. }
+        | StakeCmd::Delegate { node, .. }
+        | StakeCmd::Rewards { node, .. }
+        | StakeCmd::Status { node, .. } => node.clone(),
+    };
+    let client = NodeClient::new(node);
+    let store = WalletStore::new(wallet_dir()?)?.with_node(client.clone());
+    let fee = |fee_rate: Option<u64>| {
+        let client = client.clone();
+        async move {
+            match fee_rate {
+                Some(rate) => Ok::<u64, anyhow::Error>(rate),
+                None => client.fee_estimate().await,
+            }
+        }
+    };
+    let rt = Runtime::new()?;
+    rt.block_on(async {
+        match cmd {
+            StakeCmd::Add { name, password, amount, fee_rate, .. } => {
+                let mut wallet = store.load(&name)?;
+                let sent = store.stake(&mut wallet, &password, amount, fee(fee_rate).await?).await?;
+                let tx = hex::encode(sent.hash);
+                output.emit(json!({ "tx": tx, "amount": amount }), || println!("Staked {amount} in tx {tx}"));
+            }
+            StakeCmd::Remove { name, password, validator, amount, fee_rate, .. } => {
+                let mut wallet = store.load(&name)?;
+                let validator = match validator {
+                    Some(validator) => address_from_bech32(&validator)?,
+                    None => wallet.address,
+                };
+                let fee_rate = fee(fee_rate).await?;
+                let sent = store.unstake(&mut wallet, &password, validator, amount, fee_rate).await?;
+                let tx = hex::encode(sent.hash);
+                let validator = dxid_crypto::address_to_string(&validator);
+                output.emit(json!({ "tx": tx, "validator": validator, "amount": amount }), || {
+                    println!("Unbonding {amount} in tx {tx}")
+                });
+            }
+            StakeCmd::Delegate { name, password, validator, amount, fee_rate, .. } => {
+                let mut wallet = store.load(&name)?;
+                let validator = address_from_bech32(&validator)?;
+                let fee_rate = fee(fee_rate).await?;
+                let sent = store.delegate(&mut wallet, &password, validator, amount, fee_rate).await?;
+                let tx = hex::encode(sent.hash);
+                let validator = dxid_crypto::address_to_string(&validator);
+                output.emit(json!({ "tx": tx, "validator": validator, "amount": amount }), || {
+                    println!("Delegated {amount} in tx {tx}")
+                });
+            }
+            StakeCmd::Rewards { name, withdraw, password, fee_rate, .. } => {
+                let mut wallet = store.load(&name)?;
+                if withdraw {
+                    let password =
+                        password.ok_or_else(|| anyhow::anyhow!("--password is needed to withdraw"))?;
+                    let fee_rate = fee(fee_rate).await?;
+                    let sent = store.withdraw_rewards(&mut wallet, &password, fee_rate).await?;
+                    let tx = hex::encode(sent.hash);
+                    output.emit(json!({ "tx": tx }), || println!("Withdrew rewards in tx {tx}"));
+                    return Ok(());
+                }
+                let position = store.staking_position(&wallet).await?;
+                let bonds: Vec<_> = position
+                    .bonds
+                    .iter()
+                    .map(|bond| {
+                        json!({
+                            "validator": dxid_crypto::address_to_string(&bond.validator),
+                            "amount": bond.amount,
+                        })
+                    })
+                    .collect();
+                let unbonding: Vec<_> = position
+                    .unbonding
+                    .iter()
+                    .map(|unbonding| {
+                        json!({
+                            "validator": dxid_crypto::address_to_string(&unbonding.validator),
+                            "amount": unbonding.amount,
+                            "release_height": unbonding.release_height,
+                        })
+                    })
+                    .collect();
+                let report = json!({
+                    "height": position.height,
+                    "bonded": position.bonded(),
+                    "bonds": bonds,
+                    "unbonding": unbonding,
+                    "rewards": position.rewards,
+                    "withdrawable": position.withdrawable(),
+                });
+                output.emit(report, || {
+                    println!("Bonded {} at height {}", position.bonded(), position.height);
+                    for bond in &position.bonds {
+                        println!("  {} to {}", bond.amount, dxid_crypto::address_to_string(&bond.validator));
+                    }
+                    for unbonding in &position.unbonding {
+                        let left = unbonding.release_height.saturating_sub(position.height);
+                        println!("  unbonding {} ({left} blocks left)", unbonding.amount);
+                    }
+                    println!("Rewards {}, withdrawable {}", position.rewards, position.withdrawable());
+                });
+            }
+            StakeCmd::Status { validator, name, .. } => {
+                let validator = match validator {
+                    Some(validator) => address_from_bech32(&validator)?,
+                    None => store.load(&name)?.address,
+                };
+                let status = client.validator_status(&validator).await?;
+                output.emit(validator_json(&status), || {
+                    println!("Validator {}", dxid_crypto::address_to_string(&status.validator));
+                    println!("Self stake {}, delegated {}", status.self_stake, status.delegated);
+                    println!("Total {} from {} delegator(s)", status.total_stake(), status.delegators);
+                    println!("Unbonding {} at height {}", status.unbonding, status.height);
+                });
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    })
+}
+
+fn identity(output: Output, cmd: IdentityCmd) -> Result<()> {
+    let store = WalletStore::new(wallet_dir()?)?;
+    let (name, password, node, change) = match cmd {
+        IdentityCmd::Create { name, password, node } => {
+            let client = NodeClient::new(node);
+            let mut wallet = store.load(&name)?;
+            if wallet.identity_id().is_none() {
+                store.create_identity(&mut wallet, &password)?;
+            }
+            let id = wallet.identity_id().expect("identity was just created");
+            let rt = Runtime::new()?;
+            return rt.block_on(async move {
+                if client.identity(&id).await?.is_some() {
+                    anyhow::bail!("wallet {name} already has registered identity {id}");
+                }
+                let register = IdentityChange::Register { public_key: Vec::new() };
+                let update = store.sign_identity_update(&mut wallet, &password, None, register)?;
+                client.update_identity(&update).await?;
+                output.emit(json!({ "identity_id": id }), || println!("Registered identity {id}"));
+                Ok::<(), anyhow::Error>(())
+            });
+        }
+        IdentityCmd::Show { id, name, node } => {
+            let id = match id {
+                Some(id) => id,
+                None => owned_identity(&store.load(&name)?)?,
+            };
+            let rt = Runtime::new()?;
+            let identity = rt
+                .block_on(NodeClient::new(node).identity(&id))?
+                .ok_or_else(|| anyhow::anyhow!("identity {id} is not registered"))?;
+            let mut attributes: Vec<_> = identity.attributes.values().collect();
+            attributes.sort_by(|a, b| a.key.cmp(&b.key));
+            let report = json!({
+                "id": identity.id,
+                "status": format!("{:?}", identity.status).to_lowercase(),
+                "public_key": identity.public_keys.last().map(hex::encode),
+                "rotations": identity.public_keys.len().saturating_sub(1),
+                "attributes": attributes
+                    .iter()
+                    .map(|attr| (attr.key.clone(), json!(attr.value)))
+                    .collect::<serde_json::Map<_, _>>(),
+            });
+            output.emit(report, || {
+                println!("Identity {} ({:?})", identity.id, identity.status);
+                if let Some(key) = identity.public_keys.last() {
+                    println!("Key {} ({} rotation(s))", hex::encode(key), identity.public_keys.len() - 1);
+                }
+                for attr in &attributes {
+                    println!("  {} = {}", attr.key, attr.value);
+                }
+            });
+            return Ok(());
+        }
+        IdentityCmd::AttrSet { name, password, node, key, value } => {
+            (name, password, node, IdentityChange::SetAttribute { key, value })
+        }
+        IdentityCmd::Revoke { name, password, node } => (name, password, node, IdentityChange::Revoke),
+        IdentityCmd::Rotate { name, password, node } => {
+            let mut wallet = store.load(&name)?;
+            let rotation = store.rotate_identity_key(&mut wallet, &password)?;
+            let rt = Runtime::new()?;
+            rt.block_on(NodeClient::new(node).rotate_identity(&rotation))?;
+            let key = hex::encode(&rotation.new_public_key);
+            let id = rotation.identity_id;
+            output.emit(json!({ "identity_id": id, "public_key": key }), || {
+                println!("Rotated identity {id} to key {key}")
+            });
+            return Ok(());
+        }
+        // Requests and responses are JSON in either mode.
+        IdentityCmd::Challenge { audience, scope } => {
+            let request = build_oauth_like_challenge(audience, scope);
+            println!("{}", serde_json::to_string(&request)?);
+            return Ok(());
+        }
+        IdentityCmd::Prove { name, password, disclose, request } => {
+            let request: OAuthLikeProofRequest = serde_json::from_str(&request)?;
+            let wallet = store.load(&name)?;
+            let disclose: Vec<&str> = disclose.iter().map(String::as_str).collect();
+            let response = store.answer_proof_request(&wallet, &password, &request, &disclose)?;
+            println!("{}", serde_json::to_string(&response)?);
+            return Ok(());
+        }
+        IdentityCmd::Verify { node, request, response } => {
+            let request: OAuthLikeProofRequest = serde_json::from_str(&request)?;
+            let response: OAuthLikeProofResponse = serde_json::from_str(&response)?;
+            let id = response.identity_id;
+            let rt = Runtime::new()?;
+            let identity = rt
+                .block_on(NodeClient::new(node).identity(&id))?
+                .ok_or_else(|| anyhow::anyhow!("identity {id} is not registered"))?;
+            let crypto = dxid_crypto::DefaultCryptoProvider::new();
+            let valid = verify_oauth_like_proof(&crypto, &identity, &request, &response)?;
+            let disclosed = &response.disclosed_attributes;
+            output.emit(json!({ "valid": valid, "identity_id": id, "disclosed": disclosed }), || {
+                if valid {
+                    println!("Proof is valid for identity {id}");
+                    for (key, value) in disclosed {
+                        println!("  {key} = {value}");
+                    }
+                }
+            });
+            if !valid {
+                return Err(failed(format!("proof does not match the request and identity {id}")));
+            }
+            return Ok(());
+        }
+    };
+    // Attribute changes and revocations are signed over the published state.
+    let client = NodeClient::new(node);
+    let mut wallet = store.load(&name)?;
+    let id = owned_identity(&wallet)?;
+    let rt = Runtime::new()?;
+    rt.block_on(async move {
+        let published =
+            client.identity(&id).await?.ok_or_else(|| anyhow::anyhow!("identity {id} is not registered"))?;
+        let update = store.sign_identity_update(&mut wallet, &password, Some(&published), change)?;
+        client.update_identity(&update).await?;
+        output.emit(json!({ "identity_id": id }), || println!("Updated identity {id}"));
+        Ok::<(), anyhow::Error>(())
+    })
+}
+
+fn owned_identity(wallet: &Wallet) -> Result<IdentityId> {
+    wallet
+        .identity_id()
+        .ok_or_else(|| anyhow::anyhow!("wallet {} owns no identity; run `dxid identity create`", wallet.name))
+}
+
+/// Addresses without coins scanned past the last used one when restoring.
+const SCAN_GAP_LIMIT: u32 = 20;
+
+fn key(output: Output, cmd: KeyCmd) -> Result<()> {
+    match cmd {
+        KeyCmd::Export { name, mnemonic, keyfile } => {
+            let store = WalletStore::new(wallet_dir()?)?;
+            let wallet = store.load(&name)?;
+            if mnemonic {
+                if !std::io::stdout().is_terminal() {
+                    anyhow::bail!("refusing to write a mnemonic anywhere but the terminal");
+                }
+                let password = prompt_secret(&format!("Password of wallet {name}"))?;
+                let phrase = store.export_mnemonic(&wallet, &password)?;
+                println!("Anyone who sees these words can spend everything in wallet {name}.");
+                if !confirm("Type the wallet name to show them: ", &name)? {
+                    anyhow::bail!("not confirmed");
+                }
+                output.emit(json!({ "name": name, "mnemonic": phrase.as_str() }), || println!("{phrase}"));
+            } else if let Some(path) = keyfile {
+                let password = prompt_secret(&format!("Password of wallet {name}"))?;
+                let keyfile_password = prompt_new_password("keyfile")?;
+                let keyfile = store.export_keyfile(&wallet, &password, &keyfile_password)?;
+                write_private(&path, &serde_json::to_vec_pretty(&keyfile)?)?;
+                output.emit(json!({ "name": name, "keyfile": path }), || {
+                    println!("Wrote the keys of wallet {name} to {}", path.display())
+                });
+            }
+        }
+        KeyCmd::Import { name, hex, mnemonic, keyfile, scan, force } => {
+            let store = WalletStore::new(wallet_dir()?)?.with_overwrite(force);
+            let (mut wallet, password) = if hex {
+                let secret = hex::decode(prompt_secret("Secret key (hex)")?.trim())?;
+                let password = prompt_new_password("wallet")?;
+                (store.import_secret(&name, &secret, &password)?, password)
+            } else if mnemonic {
+                let phrase = prompt_secret("Mnemonic")?;
+                let password = prompt_new_password("wallet")?;
+                (store.restore_hd(&name, phrase.trim(), &password)?, password)
+            } else {
+                let path = keyfile.expect("clap requires an import source");
+                let keyfile: Keyfile = serde_json::from_slice(&std::fs::read(&path)?)?;
+                let keyfile_password = prompt_secret("Keyfile password")?;
+                let password = prompt_new_password("wallet")?;
+                (store.import_keyfile(&name, &keyfile, &keyfile_password, &password)?, password)
+            };
+            let address = dxid_crypto::address_to_string(&wallet.address);
+            output.note(format_args!("Imported wallet {name} address {address}"));
+            let used = match (scan, wallet.hd.is_some()) {
+                (Some(node), true) => {
+                    let store = store.with_node(NodeClient::new(node));
+                    Some(Runtime::new()?.block_on(store.scan(&mut wallet, &password, SCAN_GAP_LIMIT))?)
+                }
+                _ => None,
+            };
+            output.emit(json!({ "name": name, "address": address, "used_addresses": used }), || {
+                if let Some(used) = used {
+                    println!("Found {used} used address(es)");
+                }
+            });
+        }
+    }
+    Ok(())
+}
+
+fn genesis(output: Output, cmd: GenesisCmd) -> Result<()> {
+    match cmd {
+        GenesisCmd::New {
+            out,
+            from,
+            chain_id,
+            allocations,
+            validators,
+            max_supply,
+            base_reward,
+            halving_interval,
+            supply_threshold,
+            treasury_bps,
+            treasury,
+            timestamp,
+            no_prompt,
+            force,
+        } => {
+            if out.exists() && !force {
+                anyhow::bail!("{out:?} already exists; pass --force to replace it");
+            }
+            let mut genesis = match from {
+                Some(path) => serde_json::from_slice(&std::fs::read(&path)?)?,
+                None => default_genesis(),
+            };
+            let economics = &mut genesis.economics;
+            economics.max_supply = max_supply.unwrap_or(economics.max_supply);
+            economics.base_reward = base_reward.unwrap_or(economics.base_reward);
+            economics.schedule.target_interval =
+                halving_interval.unwrap_or(economics.schedule.target_interval);
+            economics.schedule.supply_threshold =
+                supply_threshold.unwrap_or(economics.schedule.supply_threshold);
+            economics.treasury_ratio_bps = treasury_bps.unwrap_or(economics.treasury_ratio_bps);
+            if let Some(treasury) = &treasury {
+                economics.treasury = Some(address_from_bech32(treasury.trim())?);
+            }
+            genesis.chain_id = chain_id.unwrap_or(genesis.chain_id);
+            genesis.timestamp = timestamp.unwrap_or(genesis.timestamp);
+            for alloc in &allocations {
+                let (address, amount) = parse_address_amount(alloc)?;
+                genesis.allocations.push(GenesisAllocation { address, amount });
+            }
+            for validator in &validators {
+                let (address, stake) = parse_address_amount(validator)?;
+                genesis.validators.push(GenesisValidator { address, stake });
+            }
+            // Prompts would interleave with the JSON on stdout.
+            let interactive = !no_prompt && !output.is_json() && std::io::stdin().is_terminal();
+            if genesis.validators.is_empty() && interactive {
+                prompt_genesis(&mut genesis)?;
+            }
+            genesis.validate()?;
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&out, serde_json::to_string_pretty(&genesis)?)?;
+            print_genesis(output, &format!("Wrote genesis to {out:?}"), &out, &genesis)?;
+        }
+        GenesisCmd::Validate { file } => {
+            let genesis: GenesisConfig = serde_json::from_slice(&std::fs::read(&file)?)?;
+            genesis.validate()?;
+            print_genesis(output, &format!("{file:?} is a valid genesis"), &file, &genesis)?;
+        }
+    }
+    Ok(())
+}
+
+/// Genesis at the current time with the example config's supply and reward
+/// and no accounts.
+fn default_genesis() -> GenesisConfig {
+    let consensus = DxidConfig::example().consensus;
+    GenesisConfig {
+        chain_id: dxid_core::bridge::DXID_CHAIN_ID.into(),
+        timestamp: dxid_core::now_ts(),
+        economics: TokenEconomics {
+            max_supply: consensus.max_supply,
+            base_reward: consensus.base_reward,
+            schedule: HalvingSchedule {
+                target_interval: consensus.halving_interval,
+                supply_threshold: consensus.max_supply / 2,
+            },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        },
+        allocations: Vec::new(),
+        validators: Vec::new(),
+    }
+}
+
+/// Asks for the chain id, then validators and allocations until an empty line.
+fn prompt_genesis(genesis: &mut GenesisConfig) -> Result<()> {
+    let chain_id = read_line(&format!("Chain id [{}]: ", genesis.chain_id))?;
+    if !chain_id.is_empty() {
+        genesis.chain_id = chain_id;
+    }
+    println!("Validators as <address>=<stake>, an empty line to finish");
+    while let Some((address, stake)) = prompt_address_amount("validator: ")? {
+        genesis.validators.push(GenesisValidator { address, stake });
+    }
+    println!("Initial balances as <address>=<amount>, an empty line to finish");
+    while let Some((address, amount)) = prompt_address_amount("balance: ")? {
+        genesis.allocations.push(GenesisAllocation { address, amount });
+    }
+    Ok(())
+}
+
+/// Reads `<address>=<amount>` lines until one parses; `None` on an empty line.
+fn prompt_address_amount(prompt: &str) -> Result<Option<(Address, u64)>> {
+    loop {
+        let line = read_line(prompt)?;
+        if line.is_empty() {
+            return Ok(None);
+        }
+        match parse_address_amount(&line) {
+            Ok(pair) => return Ok(Some(pair)),
+            Err(err) => println!("{err:#}"),
+        }
+    }
+}
+
+fn parse_address_amount(text: &str) -> Result<(Address, u64)> {
+    let (address, amount) =
+        text.split_once('=').ok_or_else(|| anyhow::anyhow!("expected <address>=<amount>, got {text}"))?;
+    Ok((address_from_bech32(address.trim())?, amount.trim().parse()?))
+}
+
+/// Summary of `genesis`, read from or written to `file`, under `heading`.
+fn print_genesis(output: Output, heading: &str, file: &Path, genesis: &GenesisConfig) -> Result<()> {
+    let hash = hex::encode(genesis.hash(&dxid_crypto::DefaultCryptoProvider::new())?);
+    let stake: u64 = genesis.validators.iter().map(|validator| validator.stake).sum();
+    let total_supply = genesis.total_supply()?;
+    let report = json!({
+        "file": file,
+        "chain_id": genesis.chain_id,
+        "timestamp": genesis.timestamp,
+        "validators": genesis.validators.len(),
+        "stake": stake,
+        "allocations": genesis.allocations.len(),
+        "total_supply": total_supply,
+        "max_supply": genesis.economics.max_supply,
+        "hash": hash,
+    });
+    output.emit(report, || {
+        println!("{heading}");
+        println!("Chain {} at {}", genesis.chain_id, genesis.timestamp);
+        println!("{} validator(s) bonding {stake}", genesis.validators.len());
+        println!(
+            "{} allocation(s), {total_supply} issued of max supply {}",
+            genesis.allocations.len(),
+            genesis.economics.max_supply
+        );
+        println!("Genesis hash {hash}");
+    });
+    Ok(())
+}
+
+/// Percent added to the node's gas estimate, in case state changes before
+/// the call runs; unused gas is refunded.
+const GAS_MARGIN_PERCENT: u64 = 20;
+/// How long contract commands wait for their transaction's receipt.
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(120);
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+fn contract(output: Output, cmd: ContractCmd) -> Result<()> {
+    let (name, password, node, value, gas_limit, fee_rate) = match &cmd {
+        ContractCmd::Deploy { name, password, node, value, gas_limit, fee_rate, .. }
+        | ContractCmd::Call { name, password, node, value, gas_limit, fee_rate, .. } => {
+            (name.clone(), password.clone(), node.clone(), *value, *gas_limit, *fee_rate)
+        }
+    };
+    let client = NodeClient::new(node);
+    let store = WalletStore::new(wallet_dir()?)?.with_node(client.clone());
+    let mut wallet = store.load(&name)?;
+    let rt = Runtime::new()?;
+    rt.block_on(async {
+        let call = match cmd {
+            ContractCmd::Deploy { wasm, abi, admin, salt, .. } => {
+                let code = std::fs::read(&wasm)?;
+                let abi: ContractAbi = serde_json::from_slice(&std::fs::read(&abi)?)?;
+                let policy = match admin {
+                    Some(admin) => UpgradePolicy::Admin(address_from_bech32(&admin)?),
+                    None => UpgradePolicy::Immutable,
+                };
+                let salt = salt.unwrap_or_else(dxid_core::random_nonce);
+                let call = deploy_call(&wallet.address, code, &abi, &policy, salt);
+                output.note(format_args!("Deploying to {}", dxid_crypto::address_to_string(&call.contract)));
+                call
+            }
+            ContractCmd::Call { contract, method, args, .. } => {
+                let address = address_from_bech32(&contract)?;
+                let abi = client
+                    .contract_abi(&address)
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("no contract at {contract}"))?;
+                let abi: ContractAbi = serde_json::from_value(abi)?;
+                let args: Vec<serde_json::Value> = serde_json::from_str(&args)?;
+                let input = abi.encode_json_call(&method, &args)?;
+                ContractCall { contract: address, method, input }
+            }
+        };
+        let estimate = client.estimate_gas(&wallet.address, &call, value).await?;
+        let gas_limit = gas_limit.unwrap_or(estimate.gas_used + estimate.gas_used * GAS_MARGIN_PERCENT / 100);
+        output.note(format_args!("Estimated gas {}, paying for up to {gas_limit}", estimate.gas_used));
+        let fee_rate = match fee_rate {
+            Some(rate) => rate,
+            None => client.fee_estimate().await?,
+        };
+        let gas_fee = gas_limit.saturating_mul(estimate.gas_price);
+        let sent = store.call_contract(&mut wallet, &password, &call, value, gas_fee, fee_rate).await?;
+        let tx = hex::encode(sent.hash);
+        output.note(format_args!("Submitted tx {tx} with fee {}", sent.built.fee));
+        let receipt = wait_for_receipt(&client, &sent.hash).await?;
+        let report = json!({
+            "tx": tx,
+            "contract": dxid_crypto::address_to_string(&call.contract),
+            "method": call.method,
+            "gas_estimate": estimate.gas_used,
+            "gas_limit": gas_limit,
+            "fee": sent.built.fee,
+            "receipt": receipt_json(&receipt),
+        });
+        output.emit(report, || print_receipt(&receipt));
+        if !receipt.success {
+            let error = receipt.error.as_deref().unwrap_or("no error reported");
+            return Err(failed(format!("contract call in tx {tx} failed: {error}")));
+        }
+        Ok::<(), anyhow::Error>(())
+    })
+}
+
+async fn wait_for_receipt(client: &NodeClient, hash: &dxid_core::TxHash) -> Result<TxReceipt> {
+    let started = Instant::now();
+    loop {
+        if let Some(receipt) = client.tx_receipt(hash).await? {
+            return Ok(receipt);
+        }
+        if started.elapsed() >= RECEIPT_TIMEOUT {
+            anyhow::bail!("tx {} is not in a block after {}s", hex::encode(hash), RECEIPT_TIMEOUT.as_secs());
+        }
+        tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
+    }
+}
+
+fn receipt_json(receipt: &TxReceipt) -> serde_json::Value {
+    let events: Vec<_> = receipt
+        .events
+        .iter()
+        .map(|event| {
+            json!({
+                "topic": event.topic,
+                "contract": dxid_crypto::address_to_string(&event.contract),
+                "data": hex::encode(&event.data),
+            })
+        })
+        .collect();
+    let transfers: Vec<_> = receipt
+        .transfers
+        .iter()
+        .map(|(address, amount)| json!({ "address": dxid_crypto::address_to_string(address), "amount": amount }))
+        .collect();
+    json!({
+        "block_height": receipt.block_height,
+        "index": receipt.index,
+        "success": receipt.success,
+        "error": receipt.error,
+        "gas_used": receipt.gas_used,
+        "fee_paid": receipt.fee_paid,
+        "events": events,
+        "transfers": transfers,
+    })
+}
+
+fn print_receipt(receipt: &TxReceipt) {
+    println!("Included in block {} at index {}", receipt.block_height, receipt.index);
+    if receipt.success {
+        println!("Succeeded");
+    } else {
+        println!("Failed: {}", receipt.error.as_deref().unwrap_or("no error reported"));
+    }
+    println!("Gas used {}, fee paid {}", receipt.gas_used, receipt.fee_paid);
+    for event in &receipt.events {
+        println!(
+            "  event {} from {}: {}",
+            event.topic,
+            dxid_crypto::address_to_string(&event.contract),
+            hex::encode(&event.data)
+        );
+    }
+    for (address, amount) in &receipt.transfers {
+        println!("  transfer {amount} to {}", dxid_crypto::address_to_string(address));
+    }
+}
+
+/// How often `bridge status --watch` refreshes.
+const BRIDGE_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+fn bridge(output: Output, cmd: BridgeCmd) -> Result<()> {
+    let rt = Runtime::new()?;
+    match cmd {
+        BridgeCmd::RegisterChain {
+            chain_id,
+            kind,
+            endpoint,
+            auth_token,
+            confirmations,
+            options,
+            no_connect,
+            force,
+            config,
+        } => {
+            let auth_token = if auth_token { Some(prompt_secret("Auth token")?) } else { None };
+            let options: Option<serde_json::Value> =
+                options.as_deref().map(serde_json::from_str).transpose()?;
+            // Through serde so unset fields take the same defaults as in the config file.
+            let mut settings = json!({
+                "chain_id": chain_id,
+                "kind": kind,
+                "endpoint": endpoint,
+                "auth_token": auth_token,
+                "options": options.unwrap_or_default(),
+            });
+            if let Some(confirmations) = confirmations {
+                settings["confirmations"] = confirmations.into();
+            }
+            let settings: ExternalChainSettings = serde_json::from_value(settings)?;
+            let mut connected = None;
+            if !no_connect {
+                let snark = build_snark(settings.proof_backend)?;
+                let adapter = build_adapter(&settings, snark.clone())?;
+                let entry = RegisteredAdapter { settings: settings.clone(), adapter, snark };
+                let handle = rt.block_on(entry.adapter.connect(&entry.external_config()))?;
+                let meta = handle.metadata;
+                output.note(format_args!(
+                    "Connected to {} ({}) at height {}",
+                    meta.chain_id, meta.network, meta.latest_height
+                ));
+                connected = Some(json!({
+                    "chain_id": meta.chain_id,
+                    "network": meta.network,
+                    "height": meta.latest_height,
+                }));
+            }
+            // The file alone, so environment overrides are not written into it.
+            let mut cfg = DxidConfig::read_file(&config)?;
+            cfg.interop.register_chain(settings, force)?;
+            cfg.write_file(&config)?;
+            output.emit(json!({ "chain_id": chain_id, "config": config, "connected": connected }), || {
+                println!("Registered chain {chain_id} in {config:?}; restart the node to relay to it")
+            });
+        }
+        BridgeCmd::Send { dest, payload, fee, config } => {
+            let cfg = DxidConfig::load(&config)?;
+            if !cfg.interop.chains.iter().any(|chain| chain.chain_id == dest) {
+                anyhow::bail!("chain {dest} is not registered in {config:?}");
+            }
+            let payload: serde_json::Value = serde_json::from_str(&payload)?;
+            rt.block_on(async move {
+                let store = Arc::new(dxid_storage::PgStore::connect(&cfg.db.url, cfg.db.pool_size).await?);
+                // Only queues the message; the node's relayer proves and delivers it.
+                let relayer = Relayer::new(store, Arc::new(AdapterRegistry::new()), RelayerConfig::default())
+                    .with_policy(Arc::new(BridgePolicy::new(cfg.interop.policy.clone())));
+                let message = CrossChainMessage {
+                    id: Uuid::new_v4(),
+                    source: dxid_core::bridge::DXID_CHAIN_ID.into(),
+                    dest: dest.clone(),
+                    payload,
+                    nonce: 0,
+                    timestamp: dxid_core::now_ts(),
+                };
+                let id = message.id;
+                let nonce = relayer.enqueue(CrossChainTx { message, fee, proof: None }).await?;
+                output.emit(json!({ "id": id, "dest": dest, "nonce": nonce }), || {
+                    println!("Queued message {id} to {dest} with nonce {nonce}");
+                    println!("Follow it with `dxid bridge status {id} --watch`");
+                });
+                Ok::<(), anyhow::Error>(())
+            })?;
+        }
+        BridgeCmd::Status { id, node, watch } => {
+            let client = NodeClient::new(node_url(node));
+            rt.block_on(async move {
+                loop {
+                    let message = client
+                        .bridge_message(&id)
+                        .await?
+                        .ok_or_else(|| anyhow::anyhow!("the node has not queued message {id}"))?;
+                    let audit = client.bridge_audit(&id).await?;
+                    let mut report = bridge_message_json(&message);
+                    report["audit"] = audit
+                        .iter()
+                        .map(|event| {
+                            json!({
+                                "kind": event.kind,
+                                "chain": event.chain,
+                                "at_ms": event.at_ms,
+                                "duration_ms": event.duration_ms,
+                                "detail": event.detail,
+                            })
+                        })
+                        .collect();
+                    // In JSON mode each refresh is one line.
+                    if watch && !output.is_json() {
+                        // Clear the screen and home the cursor before each refresh.
+                        print!("\x1b[2J\x1b[H");
+                    }
+                    output.emit(report, || {
+                        print_bridge_message(&message);
+                        for event in &audit {
+                            println!(
+                                "  {} {} on {} in {}ms {}",
+                                event.at_ms, event.kind, event.chain, event.duration_ms, event.detail
+                            );
+                        }
+                    });
+                    if message.status == BridgeTransferState::Failed {
+                        return Err(failed(format!("message {id} failed")));
+                    }
+                    if !watch || message.status == BridgeTransferState::Confirmed {
+                        return Ok::<(), anyhow::Error>(());
+                    }
+                    if !output.is_json() {
+                        println!("\nRefreshing every {}s, Ctrl-C to stop", BRIDGE_WATCH_INTERVAL.as_secs());
+                    }
+                    tokio::time::sleep(BRIDGE_WATCH_INTERVAL).await;
+                }
+            })?;
+        }
+        BridgeCmd::List { node, status, limit } => {
+            let client = NodeClient::new(node_url(node));
+            rt.block_on(async move {
+                let metrics = client.bridge_metrics().await?;
+                let chains = client.bridge_chains().await?;
+                let messages = client.bridge_messages(status, limit).await?;
+                let metrics_of = |chain: &str| metrics.iter().find(|m| m.chain == chain);
+                let report = json!({
+                    "chains": chains
+                        .iter()
+                        .map(|chain| {
+                            json!({
+                                "chain_id": chain.chain_id,
+                                "kind": chain.kind,
+                                "endpoint": chain.endpoint,
+                                "metrics": metrics_of(&chain.chain_id).map(|m| json!({
+                                    "in_flight": m.in_flight,
+                                    "attempts": m.attempts,
+                                    "sent": m.sent,
+                                    "confirmed": m.confirmed,
+                                    "failed": m.failed,
+                                    "avg_confirmation_secs": m.avg_confirmation_secs,
+                                    "failure_rate": m.failure_rate,
+                                })),
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                    "messages": messages.iter().map(bridge_message_json).collect::<Vec<_>>(),
+                });
+                output.emit(report, || {
+                    for chain in &chains {
+                        print!("{} ({} at {})", chain.chain_id, chain.kind, chain.endpoint);
+                        match metrics_of(&chain.chain_id) {
+                            Some(m) => println!(
+                                ": {} in flight, {} sent, {} confirmed, {} failed, avg confirmation {:.1}s, \
+                                 {:.1}% of attempts failed",
+                                m.in_flight,
+                                m.sent,
+                                m.confirmed,
+                                m.failed,
+                                m.avg_confirmation_secs,
+                                m.failure_rate * 100.0
+                            ),
+                            None => println!(": nothing relayed yet"),
+                        }
+                    }
+                    if messages.is_empty() {
+                        println!("No queued messages");
+                    }
+                    for message in &messages {
+                        print_bridge_message(message);
+                    }
+                });
+                Ok::<(), anyhow::Error>(())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn embed(output: Output, cmd: EmbedCmd) -> Result<()> {
+    match cmd {
+        EmbedCmd::Backfill { config, namespace, from, to, checkpoint, rate, batch_blocks } => {
+            let cfg = DxidConfig::load(&config)?;
+            let namespace = namespace.unwrap_or_else(|| cfg.ai.chain_index.namespace.clone());
+            let checkpoint = checkpoint
+                .unwrap_or_else(|| PathBuf::from(format!("backfill-{}.json", namespace.replace(':', "-"))));
+            let rt = Runtime::new()?;
+            rt.block_on(async move {
+                let store = Arc::new(PgStore::connect(&cfg.db.url, cfg.db.pool_size).await?);
+                let embedder = build_embedder(&cfg.ai.embeddings, &cfg.ai.openai_api_key)?;
+                let mut embedder = RateLimitedEmbedder::new(embedder, rate);
+                negotiate_dimensions(store.as_ref(), &mut embedder).await?;
+                let to = match to {
+                    Some(to) => to,
+                    None => {
+                        store.best_height().await?.ok_or_else(|| anyhow::anyhow!("no blocks stored yet"))?
+                    }
+                };
+                output.note(format_args!(
+                    "Embedding heights {from} to {to} into {namespace}, checkpointing to {checkpoint:?}"
+                ));
+                let indexer =
+                    ChainIndexer::new(store.clone(), store, Arc::new(embedder), cfg.ai.chain_index.clone());
+                let options = BackfillOptions {
+                    blocks_per_batch: batch_blocks,
+                    checkpoint: Some(checkpoint),
+                    ..BackfillOptions::default()
+                };
+                let report = indexer.embed_backfill(&namespace, from, to, &options).await?;
+                output.emit(json!(report), || {
+                    if let Some(height) = report.resumed_from {
+                        println!("Resumed from height {height}");
+                    }
+                    println!(
+                        "Embedded {} snippets from {} blocks into {} with {}",
+                        report.snippets, report.blocks, report.namespace, report.model
+                    );
+                });
+                Ok::<(), anyhow::Error>(())
+            })?;
+        }
+        EmbedCmd::Search { text, config, namespace, limit, attributes } => {
+            let cfg = DxidConfig::load(&config)?;
+            let attributes = attributes
+                .iter()
+                .map(|attribute| {
+                    let (key, value) = attribute
+                        .split_once('=')
+                        .ok_or_else(|| anyhow::anyhow!("expected <key>=<value>, got {attribute}"))?;
+                    Ok((key.trim().to_string(), value.trim().to_string()))
+                })
+                .collect::<Result<_>>()?;
+            let query = SearchQuery {
+                namespace: namespace.unwrap_or_else(|| cfg.ai.chain_index.namespace.clone()),
+                text,
+                limit: Some(limit),
+                attributes,
+            };
+            let rt = Runtime::new()?;
+            rt.block_on(async move {
+                let store = Arc::new(PgStore::connect(&cfg.db.url, cfg.db.pool_size).await?);
+                let mut embedder = build_embedder(&cfg.ai.embeddings, &cfg.ai.openai_api_key)?;
+                negotiate_dimensions(store.as_ref(), embedder.as_mut()).await?;
+                let search =
+                    HybridSearch::new(Arc::from(embedder), store.clone(), store, cfg.ai.ranking.clone());
+                let results = search.search(&query).await?;
+                output.emit(json!(results), || {
+                    for result in &results {
+                        let scores = &result.scores;
+                        let score = |value: Option<f32>| value.map_or("-".to_string(), |v| format!("{v:.3}"));
+                        println!(
+                            "{:.3}  {}  similarity {:.3} recency {} status {} attributes {}",
+                            scores.total,
+                            result.id,
+                            scores.similarity,
+                            score(scores.recency),
+                            score(scores.status),
+                            score(scores.attributes)
+                        );
+                        if let Some(text) = result.metadata["text"].as_str() {
+                            println!("       {text}");
+                        }
+                    }
+                    if results.is_empty() {
+                        println!("Nothing found in {}", query.namespace);
+                    }
+                });
+                Ok::<(), anyhow::Error>(())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn bridge_message_json(message: &BridgeMessage) -> serde_json::Value {
+    let msg = &message.tx.message;
+    json!({
+        "id": msg.id,
+        "source": msg.source,
+        "dest": msg.dest,
+        "nonce": msg.nonce,
+        "status": message.status.as_str(),
+        "attempts": message.attempts,
+        "next_attempt_at": message.next_attempt_at,
+        "last_error": message.last_error,
+        "receipt": message.receipt,
+        "created_at": message.created_at,
+        "updated_at": message.updated_at,
+    })
+}
+
+fn print_bridge_message(message: &BridgeMessage) {
+    let msg = &message.tx.message;
+    println!(
+        "{} {} -> {} nonce {}: {} after {} attempt(s)",
+        msg.id,
+        msg.source,
+        msg.dest,
+        msg.nonce,
+        message.status.as_str(),
+        message.attempts
+    );
+    if let Some(error) = &message.last_error {
+        println!("  last error: {error}");
+    }
+    if let Some(receipt) = &message.receipt {
+        println!("  receipt: {receipt}");
+    }
+}
+
+/// `node`, else `DXID_NODE_URL`, else the local node.
+fn node_url(node: Option<String>) -> String {
+    node.or_else(|| std::env::var("DXID_NODE_URL").ok())
+        .unwrap_or_else(|| "http://127.0.0.1:8080".to_string())
+}
+
+/// Reads a secret from the terminal without echoing it.
+fn prompt_secret(prompt: &str) -> Result<String> {
+    Ok(rpassword::prompt_password(format!("{prompt}: "))?)
+}
+
+fn prompt_new_password(what: &str) -> Result<String> {
+    let password = prompt_secret(&format!("New {what} password"))?;
+    if password.is_empty() {
+        anyhow::bail!("the {what} password must not be empty");
+    }
+    if prompt_secret(&format!("Repeat the {what} password"))? != password {
+        anyhow::bail!("the passwords do not match");
+    }
+    Ok(password)
+}
+
+/// Whether the line typed after `prompt` is `expected`.
+fn confirm(prompt: &str, expected: &str) -> Result<bool> {
+    Ok(read_line(prompt)? == expected)
+}
+
+/// The line typed after `prompt`, trimmed.
+fn read_line(prompt: &str) -> Result<String> {
+    print!("{prompt}");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+/// Creates `path` readable only by the owner; an existing file is never replaced.
+fn write_private(path: &std::path::Path, data: &[u8]) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn init_config(output: Output, path: PathBuf) -> Result<()> {
     if path.exists() {
-        println!("Config already exists at {:?}", path);
+        output.emit(json!({ "config": path, "created": false }), || {
+            println!("Config already exists at {:?}", path)
+        });
         return Ok(());
     }
     let cfg = DxidConfig::example();
@@ -128,15 +2014,12 @@ fn init_config(path: PathBuf) -> Result<()> {
         std::fs::create_dir_all(parent)?;
     }
     std::fs::write(&path, toml::to_string_pretty(&cfg)?)?;
-    println!("Wrote config to {:?}", path);
+    output.emit(json!({ "config": path, "created": true }), || println!("Wrote config to {:?}", path));
     Ok(())
 }
 
 fn wallet_dir() -> Result<PathBuf> {
-    let dir = dirs::home_dir()
-        .unwrap_or(std::env::temp_dir())
-        .join(".dxid")
-        .join("wallets");
+    let dir = dirs::home_dir().unwrap_or(std::env::temp_dir()).join(".dxid").join("wallets");
     std::fs::create_dir_all(&dir)?;
     Ok(dir)
 }
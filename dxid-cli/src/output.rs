@@ -0,0 +1,73 @@
+//! `--output`: text for people, or one JSON document per result on stdout
+//! for scripts. In JSON mode progress lines go to stderr so stdout stays
+//! parseable, and errors are written to stderr as
+//! `{"error": ..., "exit_code": ...}`.
+//!
+//! Exit codes: 0 on success, 1 when the command could not run, 2 for bad
+//! arguments, and 3 when it ran but what it reports is a failure, such as a
+//! reverted contract call or a config with problems.
+
+use std::fmt;
+use std::process::ExitCode;
+
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    #[default]
+    Table,
+    Json,
+}
+
+/// The command could not run.
+pub const EXIT_ERROR: u8 = 1;
+/// The command ran, and what it reports is a failure.
+pub const EXIT_FAILED: u8 = 3;
+
+/// Error of a command that ran and reports a failure; exits with [`EXIT_FAILED`].
+#[derive(Debug)]
+pub struct Failed(pub String);
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Failed {}
+
+pub fn failed(message: impl Into<String>) -> anyhow::Error {
+    Failed(message.into()).into()
+}
+
+impl Output {
+    pub fn is_json(self) -> bool {
+        self == Output::Json
+    }
+
+    /// Prints `value` in JSON mode, else runs `table`.
+    pub fn emit(self, value: Value, table: impl FnOnce()) {
+        match self {
+            Output::Json => println!("{value}"),
+            Output::Table => table(),
+        }
+    }
+
+    /// A progress line, kept off stdout in JSON mode.
+    pub fn note(self, line: impl fmt::Display) {
+        match self {
+            Output::Json => eprintln!("{line}"),
+            Output::Table => println!("{line}"),
+        }
+    }
+
+    /// Reports `err` and returns the exit code it maps to.
+    pub fn fail(self, err: &anyhow::Error) -> ExitCode {
+        let code = if err.is::<Failed>() { EXIT_FAILED } else { EXIT_ERROR };
+        match self {
+            Output::Json => eprintln!("{}", json!({ "error": format!("{err:#}"), "exit_code": code })),
+            Output::Table => eprintln!("Error: {err:#}"),
+        }
+        ExitCode::from(code)
+    }
+}
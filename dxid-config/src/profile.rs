@@ -0,0 +1,192 @@
+//! Chain profiles. Mainnet, testnet and devnet each have their own chain
+//! id, network magic, ports, database and data directory, so nodes of
+//! several chains can run on one host without meeting. `chain.profile`
+//! picks the defaults; values the file sets still win.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkProfile {
+    #[default]
+    Mainnet,
+    Testnet,
+    /// A local chain for contract and wallet development.
+    Devnet,
+}
+
+impl NetworkProfile {
+    pub const ALL: [NetworkProfile; 3] = [Self::Mainnet, Self::Testnet, Self::Devnet];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mainnet => "mainnet",
+            Self::Testnet => "testnet",
+            Self::Devnet => "devnet",
+        }
+    }
+
+    /// The chain id genesis files of this profile carry.
+    pub fn chain_id(self) -> &'static str {
+        match self {
+            Self::Mainnet => "dxid",
+            Self::Testnet => "dxid-testnet",
+            Self::Devnet => "dxid-devnet",
+        }
+    }
+
+    /// Tags the gossip topics, so nodes only hear peers of the same chain;
+    /// the bytes spell `dxid`, `dxtn` and `dxdv`.
+    pub fn magic(self) -> u32 {
+        match self {
+            Self::Mainnet => 0x6478_6964,
+            Self::Testnet => 0x6478_746e,
+            Self::Devnet => 0x6478_6476,
+        }
+    }
+
+    /// Added to each mainnet port.
+    fn port_offset(self) -> u16 {
+        match self {
+            Self::Mainnet => 0,
+            Self::Testnet => 100,
+            Self::Devnet => 200,
+        }
+    }
+
+    /// The values this profile sets unless the file does, as a TOML document.
+    pub fn defaults(self) -> toml::Table {
+        let port = |mainnet: u16| mainnet + self.port_offset();
+        let database = match self {
+            Self::Mainnet => "dxid".to_string(),
+            _ => format!("dxid_{}", self.name()),
+        };
+        let chain = ChainConfig::for_profile(self);
+        let mut defaults = toml::Table::new();
+        let mut section = |name: &str, values: toml::Value| {
+            defaults.insert(name.into(), values);
+        };
+        section("chain", toml::Value::try_from(&chain).expect("the chain section serializes to TOML"));
+        section("db", table([("url", format!("postgres://localhost:5432/{database}").into())]));
+        section(
+            "api",
+            table([
+                ("rest_addr", format!("0.0.0.0:{}", port(8080)).into()),
+                ("grpc_addr", format!("0.0.0.0:{}", port(50051)).into()),
+            ]),
+        );
+        section("network", table([("listen_addr", format!("/ip4/0.0.0.0/tcp/{}", port(7000)).into())]));
+        defaults
+    }
+}
+
+fn table<const N: usize>(values: [(&str, toml::Value); N]) -> toml::Value {
+    toml::Value::Table(values.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+impl fmt::Display for NetworkProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for NetworkProfile {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.name() == name)
+            .ok_or_else(|| format!("{name:?} is not mainnet, testnet or devnet"))
+    }
+}
+
+/// Which chain the node belongs to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ChainConfig {
+    pub profile: NetworkProfile,
+    /// Must match the genesis file's.
+    pub chain_id: String,
+    pub magic: u32,
+    /// Where the node keeps files of its own, such as the genesis of a dev chain.
+    pub data_dir: PathBuf,
+}
+
+impl ChainConfig {
+    pub fn for_profile(profile: NetworkProfile) -> Self {
+        Self {
+            profile,
+            chain_id: profile.chain_id().into(),
+            magic: profile.magic(),
+            data_dir: PathBuf::from("data").join(profile.name()),
+        }
+    }
+
+    /// The genesis file used when `consensus.genesis` is not set.
+    pub fn genesis_path(&self) -> PathBuf {
+        self.data_dir.join("genesis.json")
+    }
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self::for_profile(NetworkProfile::default())
+    }
+}
+
+/// Lays `defaults` under `file`: keys the file lacks are taken from them.
+pub(crate) fn merge(file: &mut toml::Table, defaults: toml::Table) {
+    for (key, default) in defaults {
+        match (file.get_mut(&key), default) {
+            (Some(toml::Value::Table(file)), toml::Value::Table(default)) => merge(file, default),
+            (Some(_), _) => {}
+            (None, default) => {
+                file.insert(key, default);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DxidConfig;
+
+    #[test]
+    fn profiles_set_their_defaults_under_the_file() {
+        let dir = std::env::temp_dir().join(format!("dxid-profile-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dxid.toml");
+        std::fs::write(&path, "[chain]\nprofile = \"testnet\"\n[api]\nrest_addr = \"127.0.0.1:9000\"\n")
+            .unwrap();
+
+        for cfg in [DxidConfig::load(&path).unwrap(), DxidConfig::read_file(&path).unwrap()] {
+            assert_eq!(cfg.chain, ChainConfig::for_profile(NetworkProfile::Testnet));
+            assert_eq!(cfg.db.url, "postgres://localhost:5432/dxid_testnet");
+            assert_eq!(
+                (cfg.api.rest_addr.as_str(), cfg.api.grpc_addr.as_str()),
+                ("127.0.0.1:9000", "0.0.0.0:50151")
+            );
+            assert_eq!(cfg.network.listen_addr, "/ip4/0.0.0.0/tcp/7100");
+        }
+        let devnet = DxidConfig::load_as(Some(&path), Some(NetworkProfile::Devnet)).unwrap();
+        assert_eq!(
+            (devnet.chain.chain_id.as_str(), devnet.api.rest_addr.as_str()),
+            ("dxid-devnet", "127.0.0.1:9000")
+        );
+        assert_eq!(devnet.chain.genesis_path(), PathBuf::from("data/devnet/genesis.json"));
+        let mainnet = DxidConfig::load_as(None, None).unwrap();
+        assert_eq!(
+            (mainnet.chain, mainnet.api.rest_addr),
+            (ChainConfig::default(), DxidConfig::default().api.rest_addr)
+        );
+        assert_eq!("devnet".parse(), Ok(NetworkProfile::Devnet));
+        assert!("regtest".parse::<NetworkProfile>().is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+//! What changed between two loads of the config file, split into the
+//! sections a running node applies in place and those it needs a restart
+//! for.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::DxidConfig;
+
+/// `ai` keys that select and authenticate the provider.
+const AI_PROVIDER_KEYS: [&str; 5] = ["provider", "model", "endpoint", "openai_api_key", "anthropic_api_key"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigChanges {
+    /// `log.level` changed.
+    pub log: bool,
+    /// `api.rate_limit` changed.
+    pub rate_limit: bool,
+    /// The AI provider, model, endpoint or API keys changed.
+    pub ai_provider: bool,
+    /// Other sections that changed, which take effect on the next start.
+    pub restart: Vec<&'static str>,
+}
+
+impl ConfigChanges {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl DxidConfig {
+    /// What differs in `new`, comparing resolved secrets.
+    pub fn changes(&self, new: &DxidConfig) -> ConfigChanges {
+        let ai_provider = |cfg: &DxidConfig| {
+            let ai = to_value(&cfg.ai);
+            AI_PROVIDER_KEYS.map(|key| ai[key].clone())
+        };
+        let restart = [
+            ("chain", to_value(&self.chain), to_value(&new.chain)),
+            ("db", to_value(&self.db), to_value(&new.db)),
+            ("api", without(&self.api, &["rate_limit"]), without(&new.api, &["rate_limit"])),
+            ("consensus", to_value(&self.consensus), to_value(&new.consensus)),
+            ("network", to_value(&self.network), to_value(&new.network)),
+            ("lifecycle", to_value(&self.lifecycle), to_value(&new.lifecycle)),
+            ("ai", without(&self.ai, &AI_PROVIDER_KEYS), without(&new.ai, &AI_PROVIDER_KEYS)),
+            ("interop", to_value(&self.interop), to_value(&new.interop)),
+        ];
+        ConfigChanges {
+            log: self.log != new.log,
+            rate_limit: self.api.rate_limit != new.api.rate_limit,
+            ai_provider: ai_provider(self) != ai_provider(new),
+            restart: restart.into_iter().filter(|(_, old, new)| old != new).map(|(name, ..)| name).collect(),
+        }
+    }
+}
+
+fn to_value(section: &impl Serialize) -> Value {
+    serde_json::to_value(section).expect("config sections serialize to JSON")
+}
+
+/// `section` without its `keys`.
+fn without(section: &impl Serialize, keys: &[&str]) -> Value {
+    let mut value = to_value(section);
+    if let Value::Object(fields) = &mut value {
+        for key in keys {
+            fields.remove(*key);
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConfigErrors, ConfigIssue, LlmProviderKind};
+
+    #[test]
+    fn partial_files_validate_and_changes_are_sorted_by_how_they_apply() {
+        let cfg: DxidConfig = toml::from_str("[ai]\nmodel = \"gpt-4o\"\n[log]\nlevel = \"debug\"\n").unwrap();
+        assert_eq!((cfg.db.pool_size, cfg.ai.model.as_str()), (5, "gpt-4o"));
+        assert_eq!(cfg.validate(), Ok(()));
+
+        let mut new = cfg.clone();
+        new.log.level = "info,dxid_rpc=trace".into();
+        new.api.rate_limit.requests_per_second = 10;
+        new.ai.provider = LlmProviderKind::Anthropic;
+        new.ai.anthropic_api_key = Some("key".into());
+        let changes = cfg.changes(&new);
+        assert!(changes.log && changes.rate_limit && changes.ai_provider && changes.restart.is_empty());
+
+        new.ai.tools.max_rounds += 1;
+        new.network.listen_addr = "/ip4/0.0.0.0/tcp/8080".into();
+        assert_eq!(cfg.changes(&new).restart, ["network", "ai"]);
+        assert!(cfg.changes(&cfg.clone()).is_empty());
+
+        new.log.level = "loud".into();
+        let issue = |path: &str, message: &str| ConfigIssue { path: path.into(), message: message.into() };
+        assert_eq!(
+            new.validate(),
+            Err(ConfigErrors(vec![
+                issue("network.listen_addr", "port 8080 is already used by api.rest_addr"),
+                issue("log.level", "\"loud\" is not a level such as info or info,dxid_rpc=debug"),
+            ]))
+        );
+    }
+}
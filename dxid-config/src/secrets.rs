@@ -0,0 +1,238 @@
+//! References to secrets kept outside the config file. A secret value may be
+//! written as `env:NAME`, `file:/path` or `vault:mount/path#key`, and
+//! [`DxidConfig::load`] replaces it with what it refers to. The reference is
+//! remembered, so serializing the config writes it back instead of the
+//! value, and `Debug` shows the config with its secrets masked.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{ConfigIssue, DxidConfig};
+
+/// Field of a Vault secret read when a reference names none.
+const DEFAULT_VAULT_KEY: &str = "value";
+const VAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a secret is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretRef {
+    /// An environment variable.
+    Env(String),
+    /// A file, such as a mounted container secret; one trailing newline is dropped.
+    File(PathBuf),
+    /// A key of a Vault KV v2 secret, read from `VAULT_ADDR` with `VAULT_TOKEN`.
+    Vault { mount: String, path: String, key: String },
+}
+
+impl SecretRef {
+    /// The reference `value` is written as, or `None` for a plain value.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (scheme, target) = value.split_once(':')?;
+        if target.is_empty() {
+            return None;
+        }
+        match scheme {
+            "env" => Some(Self::Env(target.to_string())),
+            "file" => Some(Self::File(PathBuf::from(target))),
+            "vault" => {
+                let (location, key) = target.split_once('#').unwrap_or((target, DEFAULT_VAULT_KEY));
+                let (mount, path) = location.split_once('/')?;
+                (!mount.is_empty() && !path.is_empty() && !key.is_empty()).then(|| Self::Vault {
+                    mount: mount.to_string(),
+                    path: path.to_string(),
+                    key: key.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The secret this refers to.
+    pub fn resolve(&self) -> Result<String> {
+        let value = match self {
+            Self::Env(name) => {
+                std::env::var(name).map_err(|_| anyhow!("environment variable {name} is not set"))?
+            }
+            Self::File(path) => {
+                let value = std::fs::read_to_string(path).with_context(|| format!("cannot read {path:?}"))?;
+                let value = value.strip_suffix('\n').unwrap_or(&value);
+                value.strip_suffix('\r').unwrap_or(value).to_string()
+            }
+            Self::Vault { mount, path, key } => read_vault(mount, path, key)?,
+        };
+        if value.is_empty() {
+            bail!("{self} is empty");
+        }
+        Ok(value)
+    }
+}
+
+impl fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Env(name) => write!(f, "env:{name}"),
+            Self::File(path) => write!(f, "file:{}", path.display()),
+            Self::Vault { mount, path, key } => write!(f, "vault:{mount}/{path}#{key}"),
+        }
+    }
+}
+
+fn read_vault(mount: &str, path: &str, key: &str) -> Result<String> {
+    let addr = std::env::var("VAULT_ADDR").map_err(|_| anyhow!("VAULT_ADDR is not set"))?;
+    let token = std::env::var("VAULT_TOKEN").map_err(|_| anyhow!("VAULT_TOKEN is not set"))?;
+    let url = format!("{}/v1/{mount}/data/{path}", addr.trim_end_matches('/'));
+    let body: serde_json::Value = ureq::get(&url)
+        .set("X-Vault-Token", &token)
+        .timeout(VAULT_TIMEOUT)
+        .call()
+        .with_context(|| format!("cannot read {mount}/{path} from vault"))?
+        .into_json()?;
+    match &body["data"]["data"][key] {
+        serde_json::Value::String(value) => Ok(value.clone()),
+        _ => bail!("vault secret {mount}/{path} has no string key {key}"),
+    }
+}
+
+impl DxidConfig {
+    /// Every value that may be a secret reference, with its dotted path.
+    fn secret_fields(&mut self) -> Vec<(String, &mut String)> {
+        let mut fields = vec![("db.url".to_string(), &mut self.db.url)];
+        if let Some(key) = &mut self.consensus.producer.validator_key {
+            fields.push(("consensus.producer.validator_key".into(), key));
+        }
+        fields.push(("ai.openai_api_key".into(), &mut self.ai.openai_api_key));
+        if let Some(key) = &mut self.ai.anthropic_api_key {
+            fields.push(("ai.anthropic_api_key".into(), key));
+        }
+        for (i, chain) in self.interop.chains.iter_mut().enumerate() {
+            let path = format!("interop.chains[{i}]");
+            if let Some(token) = &mut chain.auth_token {
+                fields.push((format!("{path}.auth_token"), token));
+            }
+            if let Some(password) = &mut chain.password {
+                fields.push((format!("{path}.password"), password));
+            }
+            for (name, value) in &mut chain.headers {
+                fields.push((format!("{path}.headers.{name}"), value));
+            }
+        }
+        for (i, webhook) in self.interop.webhooks.iter_mut().enumerate() {
+            fields.push((format!("interop.webhooks[{i}].secret"), &mut webhook.secret));
+        }
+        fields
+    }
+
+    /// Replaces each secret reference with the value it refers to, returning
+    /// the references that could not be resolved; those are left as written.
+    pub fn resolve_secrets(&mut self) -> Vec<ConfigIssue> {
+        let mut references = Vec::new();
+        let mut issues = Vec::new();
+        for (path, value) in self.secret_fields() {
+            let Some(reference) = SecretRef::parse(value) else {
+                continue;
+            };
+            match reference.resolve() {
+                Ok(secret) => {
+                    references.push((path, std::mem::replace(value, secret)));
+                }
+                Err(err) => issues.push(ConfigIssue { path, message: format!("{err:#}") }),
+            }
+        }
+        self.references.extend(references);
+        issues
+    }
+
+    /// Copy with resolved secrets put back as the references they were
+    /// written as.
+    pub fn unresolved(&self) -> Self {
+        let mut cfg = self.clone();
+        let references = std::mem::take(&mut cfg.references);
+        for (path, value) in cfg.secret_fields() {
+            if let Some(reference) = references.get(&path) {
+                *value = reference.clone();
+            }
+        }
+        cfg
+    }
+}
+
+impl Serialize for DxidConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let cfg = self.unresolved();
+        let mut state = serializer.serialize_struct("DxidConfig", 9)?;
+        state.serialize_field("chain", &cfg.chain)?;
+        state.serialize_field("db", &cfg.db)?;
+        state.serialize_field("api", &cfg.api)?;
+        state.serialize_field("consensus", &cfg.consensus)?;
+        state.serialize_field("network", &cfg.network)?;
+        state.serialize_field("log", &cfg.log)?;
+        state.serialize_field("lifecycle", &cfg.lifecycle)?;
+        state.serialize_field("ai", &cfg.ai)?;
+        state.serialize_field("interop", &cfg.interop)?;
+        state.end()
+    }
+}
+
+impl fmt::Debug for DxidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cfg = self.redacted();
+        f.debug_struct("DxidConfig")
+            .field("chain", &cfg.chain)
+            .field("db", &cfg.db)
+            .field("api", &cfg.api)
+            .field("consensus", &cfg.consensus)
+            .field("network", &cfg.network)
+            .field("log", &cfg.log)
+            .field("lifecycle", &cfg.lifecycle)
+            .field("ai", &cfg.ai)
+            .field("interop", &cfg.interop)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_references_and_writes_them_back() {
+        assert_eq!(SecretRef::parse("set-me"), None);
+        assert_eq!(SecretRef::parse("postgres://user:pw@localhost/dxid"), None);
+        assert_eq!(
+            SecretRef::parse("vault:kv/dxid/openai"),
+            Some(SecretRef::Vault { mount: "kv".into(), path: "dxid/openai".into(), key: "value".into() })
+        );
+        assert_eq!(SecretRef::parse("vault:kv#key"), None);
+
+        let dir = std::env::temp_dir().join(format!("dxid-secrets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("db_url");
+        std::fs::write(&file, "postgres://dxid:from-file@db:5432/dxid\n").unwrap();
+        std::env::set_var("DXID_TEST_OPENAI_KEY", "sk-from-the-environment");
+
+        let mut cfg = DxidConfig::example();
+        cfg.db.url = format!("file:{}", file.display());
+        cfg.ai.openai_api_key = "env:DXID_TEST_OPENAI_KEY".into();
+        cfg.ai.anthropic_api_key = Some("env:DXID_TEST_UNSET_KEY".into());
+        let issues = cfg.resolve_secrets();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].to_string(),
+            "ai.anthropic_api_key: environment variable DXID_TEST_UNSET_KEY is not set"
+        );
+        assert_eq!(cfg.db.url, "postgres://dxid:from-file@db:5432/dxid");
+        assert_eq!(cfg.ai.openai_api_key, "sk-from-the-environment");
+
+        let written = toml::to_string(&cfg).unwrap();
+        assert!(written.contains("openai_api_key = \"env:DXID_TEST_OPENAI_KEY\""));
+        assert!(!written.contains("from-file") && !written.contains("sk-from"));
+        let shown = format!("{cfg:?}");
+        assert!(!shown.contains("from-file") && !shown.contains("sk-from"));
+        assert!(toml::from_str::<DxidConfig>(&written).unwrap().unresolved().db.url.starts_with("file:"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,14 +1,17 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
-use dxid_core::{merkle_root, now_ts, Address, Block, BlockHeader, CryptoProvider, Transaction};
+use dxid_core::{merkle_root, now_ts, Address, Block, BlockHash, BlockHeader, CryptoProvider, Transaction};
 use dxid_crypto::DefaultCryptoProvider;
 use parking_lot::RwLock;
 use rand::{seq::IteratorRandom, Rng};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use tracing::{debug, info};
 
+/// Seconds a block's timestamp may run ahead of this node's clock.
+const MAX_FUTURE_DRIFT: u64 = 15;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsensusConfig {
     pub pow_target_spacing: u64,
@@ -26,11 +29,16 @@ pub struct ConsensusState {
 
 #[async_trait]
 pub trait ConsensusEngine: Send + Sync {
+    /// Mines a block of `transactions` on `previous`, weighted by
+    /// `stake_weight`, the validator's stake after `previous`, and
+    /// committing to `state_root`, the root of the state after `previous`.
     fn propose_block(
         &self,
         previous: &BlockHeader,
         transactions: Vec<Transaction>,
         validator: Address,
+        stake_weight: u64,
+        state_root: BlockHash,
     ) -> Result<Block>;
     fn validate_block(&self, block: &Block) -> Result<()>;
     fn stake(&self, addr: Address, amount: u64) -> Result<()>;
@@ -87,6 +95,101 @@ impl<C: CryptoProvider> HybridConsensus<C> {
         }
         None
     }
+
+    /// Signs `block` as its validator: the signature field holds the
+    /// validator's public key followed by its signature over the header hash.
+    pub fn seal(&self, block: &mut Block, public_key: &[u8], secret_key: &[u8]) -> Result<()> {
+        let signature = self.crypto.sign_message(secret_key, &self.crypto.hash_block_header(&block.header))?;
+        block.validator_signature = [public_key, &signature].concat();
+        Ok(())
+    }
+
+    fn check_seal(&self, block: &Block) -> Result<()> {
+        if block.validator_signature.len() < 32 {
+            bail!("block {} is not sealed", block.header.height);
+        }
+        let (public_key, signature) = block.validator_signature.split_at(32);
+        if self.crypto.address_from_public_key(public_key)? != block.header.validator {
+            bail!("block {} is sealed by another key than its validator's", block.header.height);
+        }
+        let hash = self.crypto.hash_block_header(&block.header);
+        if !self.crypto.verify_signature(public_key, &hash, signature)? {
+            bail!("block {} has a bad validator signature", block.header.height);
+        }
+        Ok(())
+    }
+
+    /// Checks `block` as the successor of `parent`: that it links to it,
+    /// its merkle root, proof of work at the current difficulty, a timestamp
+    /// neither before the parent's nor ahead of the clock, and that it is
+    /// sealed by the validator `stakes` elect for its round of `round_secs`
+    /// and weighted by that validator's stake.
+    pub fn verify_successor(
+        &self,
+        parent: &BlockHeader,
+        block: &Block,
+        stakes: &BTreeMap<Address, u64>,
+        round_secs: u64,
+    ) -> Result<()> {
+        let header = &block.header;
+        let parent_hash = self.crypto.hash_block_header(parent);
+        if header.previous_hash != parent_hash || header.height != parent.height + 1 {
+            bail!("block {} does not follow its parent", header.height);
+        }
+        if header.merkle_root != merkle_root(&block.transactions) {
+            bail!("merkle mismatch");
+        }
+        let difficulty = self.state.read().difficulty;
+        if header.difficulty != difficulty {
+            bail!("difficulty {} is not the expected {difficulty}", header.difficulty);
+        }
+        let target = self.target_from_difficulty(difficulty);
+        if block.pow_hash != self.crypto.hash_block_header(header) || self.pow_hash(header) >= target {
+            bail!("pow target not met");
+        }
+        if header.timestamp < parent.timestamp || header.timestamp > now_ts() + MAX_FUTURE_DRIFT {
+            bail!("block {} has timestamp {} out of range", header.height, header.timestamp);
+        }
+        let round = (header.timestamp - parent.timestamp) / round_secs.max(1);
+        if elect_proposer(stakes, &parent_hash, round) != Some(header.validator) {
+            bail!("validator of block {} was not elected for round {round}", header.height);
+        }
+        let stake = stakes.get(&header.validator).copied().unwrap_or(0);
+        if header.stake_weight != stake {
+            bail!(
+                "block {} has stake weight {} but its validator has {stake}",
+                header.height,
+                header.stake_weight
+            );
+        }
+        self.check_seal(block)
+    }
+}
+
+/// Validator elected to propose the block after `previous_hash` in `round`,
+/// picked by stake from a seed every node derives alike. A new round starts
+/// each time an elected proposer lets its turn pass.
+pub fn elect_proposer(
+    stakes: &BTreeMap<Address, u64>,
+    previous_hash: &BlockHash,
+    round: u64,
+) -> Option<Address> {
+    let total: u128 = stakes.values().map(|stake| *stake as u128).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(previous_hash);
+    hasher.update(&round.to_le_bytes());
+    let seed: [u8; 16] = hasher.finalize().as_bytes()[..16].try_into().expect("16 bytes");
+    let mut pick = u128::from_le_bytes(seed) % total;
+    for (validator, stake) in stakes {
+        if pick < *stake as u128 {
+            return Some(*validator);
+        }
+        pick -= *stake as u128;
+    }
+    None
 }
 
 #[async_trait]
@@ -96,6 +199,8 @@ impl<C: CryptoProvider> ConsensusEngine for HybridConsensus<C> {
         previous: &BlockHeader,
         transactions: Vec<Transaction>,
         validator: Address,
+        stake_weight: u64,
+        state_root: BlockHash,
     ) -> Result<Block> {
         let mut header = BlockHeader {
             previous_hash: self.crypto.hash_block_header(previous),
@@ -105,7 +210,8 @@ impl<C: CryptoProvider> ConsensusEngine for HybridConsensus<C> {
             difficulty: self.state.read().difficulty,
             nonce: 0,
             validator,
-            stake_weight: *self.state.read().stakes.get(&validator).unwrap_or(&0),
+            stake_weight,
+            state_root,
         };
         let target = self.target_from_difficulty(header.difficulty);
         let mut rng = rand::thread_rng();
@@ -202,10 +308,13 @@ mod tests {
             outputs: vec![TxOutput {
                 address: addr,
                 amount: 10,
+                condition: None,
+                spendable_after_height: None,
             }],
             fee: 0,
             nonce: 0,
             memo: None,
+            lock_time: 0,
         };
         let genesis_header = BlockHeader {
             previous_hash: [0u8; 32],
@@ -216,10 +325,71 @@ mod tests {
             nonce: 0,
             validator: addr,
             stake_weight: 0,
+            state_root: [0u8; 32],
         };
         let block = engine
-            .propose_block(&genesis_header, vec![tx], addr)
+            .propose_block(&genesis_header, vec![tx], addr, 100, [0u8; 32])
             .unwrap();
         engine.validate_block(&block).unwrap();
     }
+
+    #[test]
+    fn proposers_are_elected_by_stake_alike_on_every_node() {
+        let stakes = BTreeMap::from([([1u8; 32], 1), ([2u8; 32], 0), ([3u8; 32], 3)]);
+        let elected: Vec<Address> =
+            (0..400).filter_map(|round| elect_proposer(&stakes, &[7u8; 32], round)).collect();
+        assert_eq!(elected.len(), 400);
+        assert_eq!(elect_proposer(&stakes, &[7u8; 32], 5), elect_proposer(&stakes, &[7u8; 32], 5));
+        let heavy = elected.iter().filter(|validator| **validator == [3u8; 32]).count();
+        assert!(elected.iter().all(|validator| *validator != [2u8; 32]) && (250..350).contains(&heavy));
+        assert_eq!(elect_proposer(&BTreeMap::new(), &[7u8; 32], 0), None);
+    }
+
+    #[test]
+    fn successors_must_be_mined_and_sealed_by_the_elected_validator() {
+        let crypto = Arc::new(DefaultCryptoProvider::new());
+        let config = ConsensusConfig {
+            pow_target_spacing: 30,
+            difficulty_window: 10,
+            max_supply: 0,
+            base_reward: 0,
+        };
+        let engine = HybridConsensus::new(crypto.clone(), config);
+        engine.state.write().difficulty = 1;
+        let key = generate_ed25519();
+        let validator = crypto.address_from_public_key(&key.public_key).unwrap();
+        let stakes = BTreeMap::from([(validator, 10)]);
+        let parent = BlockHeader {
+            previous_hash: [0u8; 32],
+            merkle_root: merkle_root(&[]),
+            height: 0,
+            timestamp: now_ts() - 60,
+            difficulty: 1,
+            nonce: 0,
+            validator,
+            stake_weight: 0,
+            state_root: [0u8; 32],
+        };
+
+        let mut block = engine.propose_block(&parent, vec![], validator, 10, [0u8; 32]).unwrap();
+        assert!(engine.verify_successor(&parent, &block, &stakes, 30).is_err());
+        engine.seal(&mut block, &key.public_key, &key.secret_key).unwrap();
+        engine.verify_successor(&parent, &block, &stakes, 30).unwrap();
+
+        let other = BTreeMap::from([([9u8; 32], 10)]);
+        assert!(engine.verify_successor(&parent, &block, &other, 30).is_err());
+        let mut forged = block.clone();
+        forged.validator_signature[40] ^= 1;
+        assert!(engine.verify_successor(&parent, &forged, &stakes, 30).is_err());
+        let mut moved = block.clone();
+        moved.header.timestamp = parent.timestamp - 1;
+        assert!(engine.verify_successor(&parent, &moved, &stakes, 30).is_err());
+
+        // The weight is the validator's stake, no more and no less.
+        let restaked = BTreeMap::from([(validator, 11)]);
+        assert!(engine.verify_successor(&parent, &block, &restaked, 30).is_err());
+        let mut light = engine.propose_block(&parent, vec![], validator, 9, [0u8; 32]).unwrap();
+        engine.seal(&mut light, &key.public_key, &key.secret_key).unwrap();
+        assert!(engine.verify_successor(&parent, &light, &stakes, 30).is_err());
+    }
 }
@@ -0,0 +1,318 @@
+//! Contract ABI: the methods a contract exposes and the types of their
+//! arguments and return values, plus the binary encoding calls use.
+//!
+//! Values are encoded back to back: `bool` as one byte, `u64`/`i64` as 8
+//! little-endian bytes, `address` as its 32 bytes, and `string`, `bytes` and
+//! `list` as a little-endian `u32` length followed by the contents.
+
+use dxid_core::Address;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::access::AccessRule;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AbiType {
+    Bool,
+    U64,
+    I64,
+    String,
+    Bytes,
+    Address,
+    List(Box<AbiType>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AbiParam {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: AbiType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AbiMethod {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<AbiParam>,
+    #[serde(default)]
+    pub returns: Option<AbiType>,
+    /// Who may call the method; anyone when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<AccessRule>,
+}
+
+/// Published with the code when a contract is deployed. Only methods listed
+/// here can be called.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContractAbi {
+    pub methods: Vec<AbiMethod>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbiValue {
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    String(String),
+    Bytes(Vec<u8>),
+    Address(Address),
+    List(Vec<AbiValue>),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AbiError {
+    #[error("contract has no method {0} in its ABI")]
+    UnknownMethod(String),
+    #[error("{method} takes {expected} argument(s), got {got}")]
+    ArgCount { method: String, expected: usize, got: usize },
+    #[error("argument {arg} must be {expected:?}")]
+    TypeMismatch { arg: String, expected: AbiType },
+    #[error("malformed encoding: {0}")]
+    Malformed(String),
+}
+
+impl ContractAbi {
+    pub fn method(&self, name: &str) -> Result<&AbiMethod, AbiError> {
+        self.methods.iter().find(|m| m.name == name).ok_or_else(|| AbiError::UnknownMethod(name.to_string()))
+    }
+
+    /// Encodes `args` for a call to `method`, checking them against the ABI.
+    pub fn encode_call(&self, method: &str, args: &[AbiValue]) -> Result<Vec<u8>, AbiError> {
+        let method = self.method(method)?;
+        if args.len() != method.args.len() {
+            return Err(AbiError::ArgCount {
+                method: method.name.clone(),
+                expected: method.args.len(),
+                got: args.len(),
+            });
+        }
+        let mut out = Vec::new();
+        for (param, value) in method.args.iter().zip(args) {
+            if !value.is(&param.ty) {
+                return Err(AbiError::TypeMismatch { arg: param.name.clone(), expected: param.ty.clone() });
+            }
+            value.encode(&mut out);
+        }
+        Ok(out)
+    }
+
+    /// Like [`ContractAbi::encode_call`], taking the arguments as JSON: numbers,
+    /// booleans, strings, hex strings for `bytes` and `address`, and arrays.
+    pub fn encode_json_call(&self, method: &str, args: &[Value]) -> Result<Vec<u8>, AbiError> {
+        let params = &self.method(method)?.args;
+        if args.len() != params.len() {
+            return Err(AbiError::ArgCount {
+                method: method.to_string(),
+                expected: params.len(),
+                got: args.len(),
+            });
+        }
+        let values = params
+            .iter()
+            .zip(args)
+            .map(|(param, json)| {
+                AbiValue::from_json(&param.ty, json).ok_or_else(|| AbiError::TypeMismatch {
+                    arg: param.name.clone(),
+                    expected: param.ty.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.encode_call(method, &values)
+    }
+
+    /// Decodes and checks call input, so a bad call fails before it reaches
+    /// the contract.
+    pub fn decode_call(&self, method: &str, input: &[u8]) -> Result<Vec<AbiValue>, AbiError> {
+        let types: Vec<AbiType> = self.method(method)?.args.iter().map(|p| p.ty.clone()).collect();
+        decode_values(&types, input)
+    }
+
+    /// Decodes what `method` returned; `None` for methods without a return type.
+    pub fn decode_return(&self, method: &str, output: &[u8]) -> Result<Option<AbiValue>, AbiError> {
+        let Some(ty) = &self.method(method)?.returns else {
+            return Ok(None);
+        };
+        let mut reader = Reader(output);
+        let value = reader.value(ty)?;
+        reader.finish()?;
+        Ok(Some(value))
+    }
+}
+
+impl AbiValue {
+    pub fn is(&self, ty: &AbiType) -> bool {
+        match (self, ty) {
+            (AbiValue::Bool(_), AbiType::Bool)
+            | (AbiValue::U64(_), AbiType::U64)
+            | (AbiValue::I64(_), AbiType::I64)
+            | (AbiValue::String(_), AbiType::String)
+            | (AbiValue::Bytes(_), AbiType::Bytes)
+            | (AbiValue::Address(_), AbiType::Address) => true,
+            (AbiValue::List(items), AbiType::List(item)) => items.iter().all(|v| v.is(item)),
+            _ => false,
+        }
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            AbiValue::Bool(b) => out.push(*b as u8),
+            AbiValue::U64(n) => out.extend_from_slice(&n.to_le_bytes()),
+            AbiValue::I64(n) => out.extend_from_slice(&n.to_le_bytes()),
+            AbiValue::String(s) => encode_bytes(s.as_bytes(), out),
+            AbiValue::Bytes(b) => encode_bytes(b, out),
+            AbiValue::Address(a) => out.extend_from_slice(a),
+            AbiValue::List(items) => {
+                out.extend_from_slice(&(items.len() as u32).to_le_bytes());
+                items.iter().for_each(|item| item.encode(out));
+            }
+        }
+    }
+
+    pub fn from_json(ty: &AbiType, json: &Value) -> Option<Self> {
+        Some(match ty {
+            AbiType::Bool => AbiValue::Bool(json.as_bool()?),
+            AbiType::U64 => AbiValue::U64(json.as_u64()?),
+            AbiType::I64 => AbiValue::I64(json.as_i64()?),
+            AbiType::String => AbiValue::String(json.as_str()?.to_string()),
+            AbiType::Bytes => AbiValue::Bytes(hex::decode(json.as_str()?).ok()?),
+            AbiType::Address => AbiValue::Address(hex::decode(json.as_str()?).ok()?.try_into().ok()?),
+            AbiType::List(item) => AbiValue::List(
+                json.as_array()?.iter().map(|v| Self::from_json(item, v)).collect::<Option<Vec<_>>>()?,
+            ),
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            AbiValue::Bool(b) => Value::from(*b),
+            AbiValue::U64(n) => Value::from(*n),
+            AbiValue::I64(n) => Value::from(*n),
+            AbiValue::String(s) => Value::from(s.as_str()),
+            AbiValue::Bytes(b) => Value::from(hex::encode(b)),
+            AbiValue::Address(a) => Value::from(hex::encode(a)),
+            AbiValue::List(items) => Value::Array(items.iter().map(AbiValue::to_json).collect()),
+        }
+    }
+}
+
+/// Decodes values of `types` laid out back to back, as in call input.
+pub fn decode_values(types: &[AbiType], bytes: &[u8]) -> Result<Vec<AbiValue>, AbiError> {
+    let mut reader = Reader(bytes);
+    let values = types.iter().map(|ty| reader.value(ty)).collect::<Result<Vec<_>, _>>()?;
+    reader.finish()?;
+    Ok(values)
+}
+
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], AbiError> {
+        if self.0.len() < len {
+            return Err(AbiError::Malformed(format!("needs {len} more byte(s), {} left", self.0.len())));
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], AbiError> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn len(&mut self) -> Result<usize, AbiError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn value(&mut self, ty: &AbiType) -> Result<AbiValue, AbiError> {
+        Ok(match ty {
+            AbiType::Bool => match self.array::<1>()? {
+                [0] => AbiValue::Bool(false),
+                [1] => AbiValue::Bool(true),
+                [b] => return Err(AbiError::Malformed(format!("{b} is not a bool"))),
+            },
+            AbiType::U64 => AbiValue::U64(u64::from_le_bytes(self.array()?)),
+            AbiType::I64 => AbiValue::I64(i64::from_le_bytes(self.array()?)),
+            AbiType::String => {
+                let len = self.len()?;
+                let bytes = self.take(len)?.to_vec();
+                AbiValue::String(
+                    String::from_utf8(bytes)
+                        .map_err(|_| AbiError::Malformed("string is not UTF-8".into()))?,
+                )
+            }
+            AbiType::Bytes => {
+                let len = self.len()?;
+                AbiValue::Bytes(self.take(len)?.to_vec())
+            }
+            AbiType::Address => AbiValue::Address(self.array()?),
+            AbiType::List(item) => {
+                let count = self.len()?;
+                // Every item takes at least one byte, so a count past the
+                // remaining input is malformed rather than a huge allocation.
+                if count > self.0.len() {
+                    return Err(AbiError::Malformed(format!(
+                        "list of {count} items in {} bytes",
+                        self.0.len()
+                    )));
+                }
+                AbiValue::List((0..count).map(|_| self.value(item)).collect::<Result<Vec<_>, _>>()?)
+            }
+        })
+    }
+
+    fn finish(&self) -> Result<(), AbiError> {
+        match self.0.len() {
+            0 => Ok(()),
+            left => Err(AbiError::Malformed(format!("{left} trailing byte(s)"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abi() -> ContractAbi {
+        serde_json::from_value(serde_json::json!({
+            "methods": [
+                {"name": "transfer", "args": [
+                    {"name": "to", "type": "address"},
+                    {"name": "amount", "type": "u64"},
+                    {"name": "tags", "type": {"list": "string"}}
+                ], "returns": "bool"},
+                {"name": "total", "returns": "u64"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn calls_roundtrip_and_bad_args_are_rejected() {
+        let abi = abi();
+        let args = vec![
+            AbiValue::Address([7u8; 32]),
+            AbiValue::U64(5),
+            AbiValue::List(vec![AbiValue::String("gift".into())]),
+        ];
+        let input = abi.encode_call("transfer", &args).unwrap();
+        assert_eq!(abi.decode_call("transfer", &input).unwrap(), args);
+        let json =
+            [serde_json::json!(hex::encode([7u8; 32])), serde_json::json!(5), serde_json::json!(["gift"])];
+        assert_eq!(abi.encode_json_call("transfer", &json).unwrap(), input);
+        assert_eq!(abi.decode_return("total", &9u64.to_le_bytes()).unwrap(), Some(AbiValue::U64(9)));
+
+        assert!(matches!(abi.encode_call("mint", &[]), Err(AbiError::UnknownMethod(_))));
+        assert!(matches!(abi.encode_call("transfer", &args[..2]), Err(AbiError::ArgCount { .. })));
+        let swapped = [args[1].clone(), args[0].clone(), args[2].clone()];
+        assert!(matches!(abi.encode_call("transfer", &swapped), Err(AbiError::TypeMismatch { .. })));
+        assert!(matches!(abi.decode_call("transfer", &input[..40]), Err(AbiError::Malformed(_))));
+        assert!(matches!(abi.decode_call("total", &[0]), Err(AbiError::Malformed(_))));
+    }
+}
@@ -0,0 +1,91 @@
+//! Method-level access rules, declared in a contract's ABI and checked by
+//! the runtime before the call reaches the contract.
+
+use std::collections::HashMap;
+
+use dxid_core::{authorize_identity_proof, Address, Identity};
+use serde::{Deserialize, Serialize};
+
+/// Who may call a method. Methods without a rule are open to everyone.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessRule {
+    /// Only the contract's owner: the admin of its upgrade policy. Contracts
+    /// without an admin have no owner, so nobody may call the method.
+    Owner,
+    /// Only the listed addresses.
+    Allowlist(Vec<Address>),
+    /// Only callers whose active identity has attribute `key`, equal to
+    /// `value` when one is given.
+    IdentityAttribute {
+        key: String,
+        #[serde(default)]
+        value: Option<String>,
+    },
+}
+
+/// Resolves the identity an address belongs to, for identity-gated methods.
+pub trait IdentityLookup: Send + Sync {
+    fn identity_of(&self, address: &Address) -> Option<Identity>;
+}
+
+impl IdentityLookup for HashMap<Address, Identity> {
+    fn identity_of(&self, address: &Address) -> Option<Identity> {
+        self.get(address).cloned()
+    }
+}
+
+impl AccessRule {
+    pub fn allows(
+        &self,
+        caller: &Address,
+        owner: Option<&Address>,
+        identities: Option<&dyn IdentityLookup>,
+    ) -> bool {
+        match self {
+            AccessRule::Owner => owner == Some(caller),
+            AccessRule::Allowlist(allowed) => allowed.contains(caller),
+            AccessRule::IdentityAttribute { key, value } => {
+                let Some(identity) = identities.and_then(|lookup| lookup.identity_of(caller)) else {
+                    return false;
+                };
+                let matches = |attr: &dxid_core::IdentityAttribute| value.iter().all(|v| attr.value == *v);
+                authorize_identity_proof(&identity, Some((key.as_str(), &matches)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_check_owner_allowlist_and_identity_attributes() {
+        let (owner, member, stranger) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+        assert!(AccessRule::Owner.allows(&owner, Some(&owner), None));
+        assert!(!AccessRule::Owner.allows(&member, Some(&owner), None));
+        assert!(!AccessRule::Owner.allows(&owner, None, None));
+        assert!(AccessRule::Allowlist(vec![member]).allows(&member, None, None));
+        assert!(!AccessRule::Allowlist(vec![member]).allows(&stranger, None, None));
+
+        let mut identity = dxid_core::new_identity(vec![9u8; 32]);
+        dxid_core::add_attribute(
+            &mut identity,
+            dxid_core::IdentityAttribute { key: "role".into(), value: "auditor".into(), embedding_ref: None },
+        );
+        let identities: HashMap<Address, Identity> = [(member, identity.clone())].into();
+        let auditor = AccessRule::IdentityAttribute { key: "role".into(), value: Some("auditor".into()) };
+        let admin = AccessRule::IdentityAttribute { key: "role".into(), value: Some("admin".into()) };
+        let any_role = AccessRule::IdentityAttribute { key: "role".into(), value: None };
+        assert!(auditor.allows(&member, None, Some(&identities)));
+        assert!(any_role.allows(&member, None, Some(&identities)));
+        assert!(!admin.allows(&member, None, Some(&identities)));
+        assert!(!auditor.allows(&stranger, None, Some(&identities)));
+        assert!(!auditor.allows(&member, None, None));
+
+        dxid_core::revoke_identity(&mut identity);
+        let revoked: HashMap<Address, Identity> = [(member, identity)].into();
+        assert!(!auditor.allows(&member, None, Some(&revoked)));
+    }
+}
@@ -0,0 +1,399 @@
+//! Built-in credential contract: non-fungible attestations bound to an
+//! [`IdentityId`]. The caller of `init` administers the instance and
+//! authorizes issuers; [`CredentialClient`] builds the calls and
+//! [`authorize_credential`] checks a credential against its identity.
+//!
+//! Methods:
+//!
+//! - `init()`, once; `add_issuer(issuer)` and `remove_issuer(issuer)`, admin only.
+//! - `issue(identity, kind, data, expires_at) -> u64`, issuers only;
+//!   `expires_at` is a block height, 0 for no expiry.
+//! - `revoke(id)`, by the credential's issuer or the admin.
+//! - `credential(id) -> bytes` (a [`Credential`] encoding), `is_valid(id) -> bool`,
+//!   `credentials_of(identity) -> list<u64>`, `is_issuer(address) -> bool`.
+//!
+//! `issue` emits `issued` of `(id, identity, kind, issuer)` and `revoke`
+//! emits `revoked` of `(id)`.
+
+use dxid_core::contract::{ContractCall, ContractEvent};
+use dxid_core::{authorize_identity_proof, Address, Identity, IdentityId};
+
+use crate::abi::{decode_values, AbiError, AbiType, AbiValue, ContractAbi};
+use crate::native::{
+    abi_method as method, arg_address, arg_bytes, arg_string, arg_u64, encode_values, get_u64, revert,
+    set_u64, NativeContract,
+};
+use crate::wasm::{CallContext, CallOutcome, ContractError, ContractState};
+
+/// Gas of a credential call that only reads.
+pub const CREDENTIAL_READ_GAS: u64 = 1_000;
+/// Gas of a credential call that writes.
+pub const CREDENTIAL_WRITE_GAS: u64 = 10_000;
+
+const ADMIN_KEY: &[u8] = b"admin";
+const NEXT_ID_KEY: &[u8] = b"next_id";
+
+fn issuer_key(issuer: &Address) -> Vec<u8> {
+    [b"issuer/".as_slice(), issuer].concat()
+}
+
+fn credential_key(id: u64) -> Vec<u8> {
+    [b"credential/".as_slice(), &id.to_be_bytes()].concat()
+}
+
+fn holder_prefix(identity: &IdentityId) -> Vec<u8> {
+    [b"holder/".as_slice(), identity.as_bytes()].concat()
+}
+
+/// An issued credential, as returned by the `credential` method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credential {
+    pub id: u64,
+    pub identity: IdentityId,
+    pub kind: String,
+    pub data: Vec<u8>,
+    pub issuer: Address,
+    /// Block height of issuance.
+    pub issued_at: u64,
+    /// Block height from which the credential is expired; 0 for never.
+    pub expires_at: u64,
+    pub revoked: bool,
+}
+
+impl Credential {
+    fn layout() -> [AbiType; 8] {
+        use AbiType::{Address as A, Bool, Bytes, String as S, U64};
+        [U64, S, S, Bytes, A, U64, U64, Bool]
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        encode_values(&[
+            AbiValue::U64(self.id),
+            AbiValue::String(self.identity.to_string()),
+            AbiValue::String(self.kind.clone()),
+            AbiValue::Bytes(self.data.clone()),
+            AbiValue::Address(self.issuer),
+            AbiValue::U64(self.issued_at),
+            AbiValue::U64(self.expires_at),
+            AbiValue::Bool(self.revoked),
+        ])
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, AbiError> {
+        let values = decode_values(&Self::layout(), bytes)?;
+        let identity = IdentityId::parse_str(&arg_string(&values[1]))
+            .map_err(|_| AbiError::Malformed("credential identity is not a UUID".into()))?;
+        Ok(Self {
+            id: arg_u64(&values[0]),
+            identity,
+            kind: arg_string(&values[2]),
+            data: arg_bytes(&values[3]),
+            issuer: arg_address(&values[4]),
+            issued_at: arg_u64(&values[5]),
+            expires_at: arg_u64(&values[6]),
+            revoked: values[7] == AbiValue::Bool(true),
+        })
+    }
+
+    /// Not revoked and not expired at `height`.
+    pub fn is_valid_at(&self, height: u64) -> bool {
+        !self.revoked && (self.expires_at == 0 || height < self.expires_at)
+    }
+}
+
+/// Whether `identity` can present `credential` as a `kind` attestation at
+/// `height`: the identity passes [`authorize_identity_proof`], holds the
+/// credential, and the credential is of that kind and still valid.
+pub fn authorize_credential(identity: &Identity, credential: &Credential, kind: &str, height: u64) -> bool {
+    authorize_identity_proof(identity, None)
+        && credential.identity == identity.id
+        && credential.kind == kind
+        && credential.is_valid_at(height)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CredentialContract;
+
+impl CredentialContract {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn admin(state: &ContractState) -> Option<Address> {
+        state.storage.get(ADMIN_KEY).and_then(|bytes| bytes.as_slice().try_into().ok())
+    }
+
+    fn load(state: &ContractState, id: u64) -> Result<Credential, ContractError> {
+        let bytes =
+            state.storage.get(&credential_key(id)).ok_or_else(|| revert(format!("no credential {id}")))?;
+        Ok(Credential::decode(bytes).expect("stored credentials decode"))
+    }
+
+    fn parse_identity(value: &AbiValue) -> Result<IdentityId, ContractError> {
+        IdentityId::parse_str(&arg_string(value)).map_err(|_| revert("identity is not a UUID"))
+    }
+}
+
+impl NativeContract for CredentialContract {
+    fn name(&self) -> &'static str {
+        "credential"
+    }
+
+    fn abi(&self) -> ContractAbi {
+        use AbiType::{Address as A, Bool, Bytes, String as S, U64};
+        ContractAbi {
+            methods: vec![
+                method("init", &[], None),
+                method("add_issuer", &[("issuer", A)], None),
+                method("remove_issuer", &[("issuer", A)], None),
+                method(
+                    "issue",
+                    &[("identity", S), ("kind", S), ("data", Bytes), ("expires_at", U64)],
+                    Some(U64),
+                ),
+                method("revoke", &[("id", U64)], None),
+                method("credential", &[("id", U64)], Some(Bytes)),
+                method("is_valid", &[("id", U64)], Some(Bool)),
+                method("credentials_of", &[("identity", S)], Some(AbiType::List(Box::new(U64)))),
+                method("is_issuer", &[("issuer", A)], Some(Bool)),
+            ],
+        }
+    }
+
+    fn call(
+        &self,
+        state: &mut ContractState,
+        ctx: &CallContext,
+        method: &str,
+        args: &[AbiValue],
+    ) -> Result<CallOutcome, ContractError> {
+        let admin = Self::admin(state);
+        if method != "init" && admin.is_none() {
+            return Err(revert("credential contract is not initialized"));
+        }
+        let is_admin = admin == Some(ctx.caller);
+        let read = |value: AbiValue| CallOutcome {
+            output: encode_values(&[value]),
+            gas_used: CREDENTIAL_READ_GAS,
+            ..CallOutcome::default()
+        };
+        let write = |output: Vec<u8>, event: Option<(&str, Vec<u8>)>| CallOutcome {
+            output,
+            events: event
+                .into_iter()
+                .map(|(topic, data)| ContractEvent { contract: ctx.contract, topic: topic.into(), data })
+                .collect(),
+            gas_used: CREDENTIAL_WRITE_GAS,
+            ..CallOutcome::default()
+        };
+        match method {
+            "init" if admin.is_some() => Err(revert("credential contract is already initialized")),
+            "init" => {
+                state.storage.insert(ADMIN_KEY.to_vec(), ctx.caller.to_vec());
+                Ok(write(Vec::new(), None))
+            }
+            "add_issuer" | "remove_issuer" if !is_admin => Err(revert("only the admin manages issuers")),
+            "add_issuer" => {
+                state.storage.insert(issuer_key(&arg_address(&args[0])), vec![1]);
+                Ok(write(Vec::new(), None))
+            }
+            "remove_issuer" => {
+                state.storage.remove(&issuer_key(&arg_address(&args[0])));
+                Ok(write(Vec::new(), None))
+            }
+            "issue" => {
+                if !state.storage.contains_key(&issuer_key(&ctx.caller)) {
+                    return Err(revert("caller is not an authorized issuer"));
+                }
+                let identity = Self::parse_identity(&args[0])?;
+                let expires_at = arg_u64(&args[3]);
+                if expires_at != 0 && expires_at <= ctx.block_height {
+                    return Err(revert("credential would already be expired"));
+                }
+                let id = get_u64(state, NEXT_ID_KEY) + 1;
+                let credential = Credential {
+                    id,
+                    identity,
+                    kind: arg_string(&args[1]),
+                    data: arg_bytes(&args[2]),
+                    issuer: ctx.caller,
+                    issued_at: ctx.block_height,
+                    expires_at,
+                    revoked: false,
+                };
+                state.storage.insert(credential_key(id), credential.encode());
+                state
+                    .storage
+                    .insert([holder_prefix(&identity), id.to_be_bytes().to_vec()].concat(), Vec::new());
+                set_u64(state, NEXT_ID_KEY.to_vec(), id);
+                let event = encode_values(&[
+                    AbiValue::U64(id),
+                    args[0].clone(),
+                    args[1].clone(),
+                    AbiValue::Address(ctx.caller),
+                ]);
+                Ok(write(encode_values(&[AbiValue::U64(id)]), Some(("issued", event))))
+            }
+            "revoke" => {
+                let mut credential = Self::load(state, arg_u64(&args[0]))?;
+                if credential.issuer != ctx.caller && !is_admin {
+                    return Err(revert("only the issuer or the admin can revoke"));
+                }
+                if credential.revoked {
+                    return Err(revert("credential is already revoked"));
+                }
+                credential.revoked = true;
+                state.storage.insert(credential_key(credential.id), credential.encode());
+                Ok(write(Vec::new(), Some(("revoked", encode_values(&[args[0].clone()])))))
+            }
+            "credential" => Ok(read(AbiValue::Bytes(Self::load(state, arg_u64(&args[0]))?.encode()))),
+            "is_valid" => {
+                let credential = Self::load(state, arg_u64(&args[0]))?;
+                Ok(read(AbiValue::Bool(credential.is_valid_at(ctx.block_height))))
+            }
+            "credentials_of" => {
+                let prefix = holder_prefix(&Self::parse_identity(&args[0])?);
+                let ids = state
+                    .storage
+                    .range(prefix.clone()..)
+                    .take_while(|(key, _)| key.starts_with(&prefix))
+                    .map(|(key, _)| {
+                        AbiValue::U64(u64::from_be_bytes(key[prefix.len()..].try_into().unwrap()))
+                    })
+                    .collect();
+                Ok(read(AbiValue::List(ids)))
+            }
+            "is_issuer" => {
+                Ok(read(AbiValue::Bool(state.storage.contains_key(&issuer_key(&arg_address(&args[0]))))))
+            }
+            other => Err(ContractError::NoMethod(other.to_string())),
+        }
+    }
+}
+
+/// Builds calls to a deployed [`CredentialContract`] and decodes what it returns.
+#[derive(Debug, Clone)]
+pub struct CredentialClient {
+    pub contract: Address,
+    abi: ContractAbi,
+}
+
+impl CredentialClient {
+    pub fn new(contract: Address) -> Self {
+        Self { contract, abi: CredentialContract.abi() }
+    }
+
+    fn call(&self, method: &str, args: &[AbiValue]) -> ContractCall {
+        let input = self.abi.encode_call(method, args).expect("credential calls match the credential ABI");
+        ContractCall { contract: self.contract, method: method.to_string(), input }
+    }
+
+    pub fn init(&self) -> ContractCall {
+        self.call("init", &[])
+    }
+
+    pub fn add_issuer(&self, issuer: Address) -> ContractCall {
+        self.call("add_issuer", &[AbiValue::Address(issuer)])
+    }
+
+    pub fn remove_issuer(&self, issuer: Address) -> ContractCall {
+        self.call("remove_issuer", &[AbiValue::Address(issuer)])
+    }
+
+    pub fn issue(&self, identity: IdentityId, kind: &str, data: Vec<u8>, expires_at: u64) -> ContractCall {
+        self.call(
+            "issue",
+            &[
+                AbiValue::String(identity.to_string()),
+                AbiValue::String(kind.into()),
+                AbiValue::Bytes(data),
+                AbiValue::U64(expires_at),
+            ],
+        )
+    }
+
+    pub fn revoke(&self, id: u64) -> ContractCall {
+        self.call("revoke", &[AbiValue::U64(id)])
+    }
+
+    pub fn credential(&self, id: u64) -> ContractCall {
+        self.call("credential", &[AbiValue::U64(id)])
+    }
+
+    pub fn is_valid(&self, id: u64) -> ContractCall {
+        self.call("is_valid", &[AbiValue::U64(id)])
+    }
+
+    pub fn credentials_of(&self, identity: IdentityId) -> ContractCall {
+        self.call("credentials_of", &[AbiValue::String(identity.to_string())])
+    }
+
+    /// Decodes the id returned by `issue`.
+    pub fn decode_id(&self, output: &[u8]) -> Result<u64, AbiError> {
+        match self.abi.decode_return("issue", output)? {
+            Some(AbiValue::U64(id)) => Ok(id),
+            _ => Err(AbiError::Malformed("issue returns an id".into())),
+        }
+    }
+
+    /// Decodes the output of `credential`.
+    pub fn decode_credential(&self, output: &[u8]) -> Result<Credential, AbiError> {
+        match self.abi.decode_return("credential", output)? {
+            Some(AbiValue::Bytes(bytes)) => Credential::decode(&bytes),
+            _ => Err(AbiError::Malformed("credential returns bytes".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        state: &mut ContractState,
+        caller: Address,
+        height: u64,
+        call: &ContractCall,
+    ) -> Result<CallOutcome, ContractError> {
+        let contract = CredentialContract::new();
+        let args = contract.abi().decode_call(&call.method, &call.input).unwrap();
+        let ctx =
+            CallContext { contract: call.contract, caller, block_height: height, ..CallContext::default() };
+        contract.call(state, &ctx, &call.method, &args)
+    }
+
+    #[test]
+    fn issued_credentials_authorize_their_identity_until_revoked_or_expired() {
+        let (admin, issuer, other) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+        let client = CredentialClient::new([9u8; 32]);
+        let mut state = ContractState::default();
+        let identity = dxid_core::new_identity(vec![7u8; 32]);
+        run(&mut state, admin, 1, &client.init()).unwrap();
+        assert!(run(&mut state, issuer, 1, &client.issue(identity.id, "kyc", vec![], 0)).is_err());
+        assert!(run(&mut state, issuer, 1, &client.add_issuer(issuer)).is_err());
+        run(&mut state, admin, 1, &client.add_issuer(issuer)).unwrap();
+
+        let issued =
+            run(&mut state, issuer, 5, &client.issue(identity.id, "kyc", b"level=2".to_vec(), 100)).unwrap();
+        let id = client.decode_id(&issued.output).unwrap();
+        assert_eq!(issued.events[0].topic, "issued");
+        let output = run(&mut state, other, 6, &client.credential(id)).unwrap().output;
+        let credential = client.decode_credential(&output).unwrap();
+        assert_eq!((credential.identity, credential.issuer, credential.issued_at), (identity.id, issuer, 5));
+        assert!(authorize_credential(&identity, &credential, "kyc", 50));
+        assert!(!authorize_credential(&identity, &credential, "kyc", 100));
+        assert!(!authorize_credential(&identity, &credential, "email", 50));
+        let mut revoked_identity = identity.clone();
+        dxid_core::revoke_identity(&mut revoked_identity);
+        assert!(!authorize_credential(&revoked_identity, &credential, "kyc", 50));
+
+        let listed = run(&mut state, other, 6, &client.credentials_of(identity.id)).unwrap().output;
+        assert_eq!(listed, encode_values(&[AbiValue::List(vec![AbiValue::U64(id)])]));
+        assert!(run(&mut state, other, 7, &client.revoke(id)).is_err());
+        run(&mut state, issuer, 7, &client.revoke(id)).unwrap();
+        let output = run(&mut state, other, 8, &client.credential(id)).unwrap().output;
+        assert!(!authorize_credential(&identity, &client.decode_credential(&output).unwrap(), "kyc", 8));
+        let valid = run(&mut state, other, 8, &client.is_valid(id)).unwrap().output;
+        assert_eq!(valid, vec![0]);
+    }
+}
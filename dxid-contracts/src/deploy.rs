@@ -0,0 +1,55 @@
+//! Deployment from a transaction. A contract is deployed by a call to the
+//! reserved [`DEPLOY_METHOD`] at the address it will live at, so deployments
+//! go on chain as ordinary signed transactions and land in receipts. The
+//! address is derived from the deployer, the code and a salt, so nobody can
+//! take the address another deployer's code will get.
+//!
+//! Events: `deployed` of `(code_hash)` as bytes.
+
+use dxid_core::contract::ContractCall;
+use dxid_core::Address;
+
+use crate::abi::{AbiType, AbiValue, ContractAbi};
+use crate::native::encode_values;
+use crate::upgrade::UpgradePolicy;
+
+/// Reserved method name of deployment calls; contract ABIs cannot use it.
+pub const DEPLOY_METHOD: &str = "__deploy";
+/// Gas a deployment uses per byte of code.
+pub const DEPLOY_GAS_PER_BYTE: u64 = 10;
+
+pub(crate) fn deploy_input_types() -> [AbiType; 4] {
+    [AbiType::Bytes, AbiType::String, AbiType::String, AbiType::U64]
+}
+
+/// Address `deployer` deploys `code` at with `salt`.
+pub fn contract_address(deployer: &Address, code: &[u8], salt: u64) -> Address {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"dxid/contract-address");
+    hasher.update(deployer);
+    hasher.update(blake3::hash(code).as_bytes());
+    hasher.update(&salt.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Builds the call by which `deployer` deploys `code` with `abi` and
+/// `policy` at [`contract_address`]; the transaction must be signed by
+/// `deployer`.
+pub fn deploy_call(
+    deployer: &Address,
+    code: Vec<u8>,
+    abi: &ContractAbi,
+    policy: &UpgradePolicy,
+    salt: u64,
+) -> ContractCall {
+    let contract = contract_address(deployer, &code, salt);
+    let abi = serde_json::to_string(abi).expect("ABI serializes");
+    let policy = serde_json::to_string(policy).expect("policy serializes");
+    let input = encode_values(&[
+        AbiValue::Bytes(code),
+        AbiValue::String(abi),
+        AbiValue::String(policy),
+        AbiValue::U64(salt),
+    ]);
+    ContractCall { contract, method: DEPLOY_METHOD.to_string(), input }
+}
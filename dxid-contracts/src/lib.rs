@@ -4,6 +4,37 @@ use serde_json::Value;
 use std::collections::HashMap;
 use tokio::sync::RwLock;
 
+pub mod abi;
+pub mod access;
+pub mod credential;
+pub mod deploy;
+pub mod native;
+pub mod precompile;
+pub mod runtime;
+pub mod schedule;
+pub mod snapshot;
+pub mod testing;
+pub mod token;
+pub mod upgrade;
+pub mod wasm;
+
+pub use abi::{AbiError, AbiMethod, AbiParam, AbiType, AbiValue, ContractAbi};
+pub use access::{AccessRule, IdentityLookup};
+pub use credential::{authorize_credential, Credential, CredentialClient, CredentialContract};
+pub use deploy::{contract_address, deploy_call, DEPLOY_GAS_PER_BYTE, DEPLOY_METHOD};
+pub use dxid_core::contract::ContractEvent;
+pub use native::NativeContract;
+pub use precompile::{default_precompiles, Precompile, SnarkVerifyPrecompile};
+pub use runtime::{RuntimeCheckpoint, WasmRuntime, DEFAULT_CALL_GAS};
+pub use schedule::{schedule_call, unschedule_call, Schedule, SCHEDULE_METHOD, UNSCHEDULE_METHOD};
+pub use snapshot::{state_root, StateSnapshot};
+pub use testing::{assert_reverts, TestHost};
+pub use token::{TokenClient, TokenContract};
+pub use upgrade::{upgrade_call, CodeVersion, UpgradePolicy, UPGRADE_METHOD};
+pub use wasm::{
+    CallContext, CallOutcome, ContractError, ContractState, ScheduleRequest, WasmContract, WasmEngine,
+};
+
 #[async_trait]
 pub trait Contract: Send + Sync {
     fn id(&self) -> &str;
@@ -0,0 +1,107 @@
+//! Contracts implemented in Rust and shipped with the node. They are
+//! deployed and called like WASM contracts, with the same ABI checks,
+//! state and receipts, but run natively at a fixed gas cost per method.
+
+use std::sync::Arc;
+
+use dxid_core::Address;
+
+use crate::abi::{AbiMethod, AbiParam, AbiType, AbiValue, ContractAbi};
+use crate::credential::CredentialContract;
+use crate::token::TokenContract;
+use crate::wasm::{CallContext, CallOutcome, ContractError, ContractState};
+
+pub trait NativeContract: Send + Sync {
+    /// Name the contract is persisted under; see [`builtin`].
+    fn name(&self) -> &'static str;
+
+    fn abi(&self) -> ContractAbi;
+
+    /// Runs `method` with `args`, already checked against [`NativeContract::abi`].
+    /// The runtime discards the state changes of a call that fails.
+    fn call(
+        &self,
+        state: &mut ContractState,
+        ctx: &CallContext,
+        method: &str,
+        args: &[AbiValue],
+    ) -> Result<CallOutcome, ContractError>;
+}
+
+/// The built-in contract persisted as `name`.
+pub fn builtin(name: &str) -> Option<Arc<dyn NativeContract>> {
+    match name {
+        "token" => Some(Arc::new(TokenContract::new())),
+        "credential" => Some(Arc::new(CredentialContract::new())),
+        _ => None,
+    }
+}
+
+/// Reads a little-endian `u64` stored under `key`, zero when absent.
+pub(crate) fn get_u64(state: &ContractState, key: &[u8]) -> u64 {
+    state
+        .storage
+        .get(key)
+        .and_then(|bytes| bytes.as_slice().try_into().ok())
+        .map(u64::from_le_bytes)
+        .unwrap_or(0)
+}
+
+/// Stores `value` under `key`, removing the key when it is zero.
+pub(crate) fn set_u64(state: &mut ContractState, key: Vec<u8>, value: u64) {
+    if value == 0 {
+        state.storage.remove(&key);
+    } else {
+        state.storage.insert(key, value.to_le_bytes().to_vec());
+    }
+}
+
+pub(crate) fn encode_values(values: &[AbiValue]) -> Vec<u8> {
+    let mut out = Vec::new();
+    values.iter().for_each(|v| v.encode(&mut out));
+    out
+}
+
+pub(crate) fn abi_method(name: &str, args: &[(&str, AbiType)], returns: Option<AbiType>) -> AbiMethod {
+    AbiMethod {
+        name: name.to_string(),
+        args: args.iter().map(|(name, ty)| AbiParam { name: name.to_string(), ty: ty.clone() }).collect(),
+        returns,
+        access: None,
+    }
+}
+
+/// A caller error, reported as a reverted call.
+pub(crate) fn revert(reason: impl Into<String>) -> ContractError {
+    ContractError::Reverted(reason.into())
+}
+
+// Arguments reach native contracts already checked against their ABI.
+
+pub(crate) fn arg_address(value: &AbiValue) -> Address {
+    match value {
+        AbiValue::Address(a) => *a,
+        _ => unreachable!("arguments are checked against the ABI"),
+    }
+}
+
+pub(crate) fn arg_u64(value: &AbiValue) -> u64 {
+    match value {
+        AbiValue::U64(n) => *n,
+        _ => unreachable!("arguments are checked against the ABI"),
+    }
+}
+
+pub(crate) fn arg_string(value: &AbiValue) -> String {
+    match value {
+        AbiValue::String(s) => s.clone(),
+        _ => unreachable!("arguments are checked against the ABI"),
+    }
+}
+
+pub(crate) fn arg_bytes(value: &AbiValue) -> Vec<u8> {
+    match value {
+        AbiValue::Bytes(b) => b.clone(),
+        _ => unreachable!("arguments are checked against the ABI"),
+    }
+}
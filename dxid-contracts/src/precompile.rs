@@ -0,0 +1,206 @@
+//! Precompiled contracts: cryptographic primitives too costly to run as WASM,
+//! executed natively behind the `precompile` host function for a fixed gas
+//! cost that depends only on the input's size. Every [`WasmEngine`] has
+//! `blake3`, `poseidon` and `bls_aggregate_verify`; `snark_verify` needs a
+//! verifying key every node shares, so it is added with
+//! [`WasmEngine::with_precompile`].
+//!
+//! Inputs and outputs:
+//! - `blake3`: any bytes; returns the 32-byte hash.
+//! - `poseidon`: 1 to 12 concatenated 32-byte big-endian BN254 field
+//!   elements; returns the circom-compatible hash in the same encoding.
+//! - `bls_aggregate_verify`: ABI-encoded `(list<bytes> public_keys,
+//!   list<bytes> messages, bytes signature)`; returns an ABI `bool`.
+//! - `snark_verify`: ABI-encoded `(bytes proof, bytes digest)`; returns an
+//!   ABI `bool`.
+//!
+//! [`WasmEngine`]: crate::wasm::WasmEngine
+//! [`WasmEngine::with_precompile`]: crate::wasm::WasmEngine::with_precompile
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use dxid_crypto::poseidon::{poseidon_hash, POSEIDON_MAX_INPUTS};
+use dxid_crypto::{SnarkProof, ZkSnarkBackend};
+
+use crate::abi::{decode_values, AbiType, AbiValue};
+use crate::native::{arg_bytes, encode_values, revert};
+use crate::wasm::ContractError;
+
+pub const BLAKE3_GAS: u64 = 60;
+/// Added to [`BLAKE3_GAS`] per 32 bytes hashed.
+pub const BLAKE3_WORD_GAS: u64 = 12;
+/// Per field element hashed.
+pub const POSEIDON_GAS: u64 = 5_000;
+/// Per pairing; an aggregate of `n` signatures takes `n + 1`.
+pub const BLS_PAIRING_GAS: u64 = 100_000;
+pub const SNARK_VERIFY_GAS: u64 = 250_000;
+
+/// A native function contracts call by name through the `precompile` host
+/// function.
+pub trait Precompile: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Gas charged up front for running on `input`, whatever the result.
+    fn gas(&self, input: &[u8]) -> u64;
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, ContractError>;
+}
+
+/// Precompiles by name.
+pub type Precompiles = BTreeMap<&'static str, Arc<dyn Precompile>>;
+
+/// The precompiles every engine starts with.
+pub fn default_precompiles() -> Precompiles {
+    let all: [Arc<dyn Precompile>; 3] =
+        [Arc::new(Blake3Precompile), Arc::new(PoseidonPrecompile), Arc::new(BlsAggregateVerifyPrecompile)];
+    all.into_iter().map(|p| (p.name(), p)).collect()
+}
+
+pub struct Blake3Precompile;
+
+impl Precompile for Blake3Precompile {
+    fn name(&self) -> &'static str {
+        "blake3"
+    }
+
+    fn gas(&self, input: &[u8]) -> u64 {
+        BLAKE3_GAS + BLAKE3_WORD_GAS * input.chunks(32).len() as u64
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, ContractError> {
+        Ok(blake3::hash(input).as_bytes().to_vec())
+    }
+}
+
+pub struct PoseidonPrecompile;
+
+impl Precompile for PoseidonPrecompile {
+    fn name(&self) -> &'static str {
+        "poseidon"
+    }
+
+    fn gas(&self, input: &[u8]) -> u64 {
+        POSEIDON_GAS * input.chunks(32).len().max(1) as u64
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, ContractError> {
+        let elements: Result<Vec<[u8; 32]>, _> = input.chunks(32).map(<[u8; 32]>::try_from).collect();
+        let elements = match elements {
+            Ok(elements) if (1..=POSEIDON_MAX_INPUTS).contains(&elements.len()) => elements,
+            _ => return Err(revert(format!("poseidon takes 1 to {POSEIDON_MAX_INPUTS} 32-byte elements"))),
+        };
+        poseidon_hash(&elements).map(|hash| hash.to_vec()).map_err(|e| revert(e.to_string()))
+    }
+}
+
+pub struct BlsAggregateVerifyPrecompile;
+
+fn bls_input_types() -> [AbiType; 3] {
+    let keys = AbiType::List(Box::new(AbiType::Bytes));
+    [keys.clone(), keys, AbiType::Bytes]
+}
+
+fn arg_list(value: &AbiValue) -> Vec<Vec<u8>> {
+    match value {
+        AbiValue::List(items) => items.iter().map(arg_bytes).collect(),
+        _ => unreachable!("decoded against the input types"),
+    }
+}
+
+impl Precompile for BlsAggregateVerifyPrecompile {
+    fn name(&self) -> &'static str {
+        "bls_aggregate_verify"
+    }
+
+    fn gas(&self, input: &[u8]) -> u64 {
+        // Priced by the most keys the input could hold, so it is known
+        // before decoding: a compressed key is 48 bytes.
+        BLS_PAIRING_GAS * (input.len() as u64 / 48 + 1)
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, ContractError> {
+        let args = decode_values(&bls_input_types(), input).map_err(|e| revert(e.to_string()))?;
+        let (keys, messages) = (arg_list(&args[0]), arg_list(&args[1]));
+        let valid = dxid_crypto::bls::aggregate_verify(&keys, &messages, &arg_bytes(&args[2]));
+        Ok(encode_values(&[AbiValue::Bool(valid)]))
+    }
+}
+
+/// Verifies proofs against the verifying key of `backend`.
+pub struct SnarkVerifyPrecompile {
+    backend: Arc<dyn ZkSnarkBackend>,
+}
+
+impl SnarkVerifyPrecompile {
+    pub fn new(backend: Arc<dyn ZkSnarkBackend>) -> Self {
+        Self { backend }
+    }
+}
+
+impl Precompile for SnarkVerifyPrecompile {
+    fn name(&self) -> &'static str {
+        "snark_verify"
+    }
+
+    fn gas(&self, _input: &[u8]) -> u64 {
+        SNARK_VERIFY_GAS
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, ContractError> {
+        let args =
+            decode_values(&[AbiType::Bytes, AbiType::Bytes], input).map_err(|e| revert(e.to_string()))?;
+        let digest: [u8; 32] =
+            arg_bytes(&args[1]).try_into().map_err(|_| revert("digest must be 32 bytes"))?;
+        let proof = SnarkProof { proof: arg_bytes(&args[0]), public_inputs: Vec::new() };
+        let valid = self.backend.verify_digest(&proof, &digest).is_ok();
+        Ok(encode_values(&[AbiValue::Bool(valid)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_precompiles_hash_and_verify() {
+        let precompiles = default_precompiles();
+        let blake = &precompiles["blake3"];
+        assert_eq!(blake.run(b"abc").unwrap(), blake3::hash(b"abc").as_bytes().to_vec());
+        assert_eq!((blake.gas(b""), blake.gas(&[0u8; 33])), (60, 84));
+
+        let poseidon = &precompiles["poseidon"];
+        let mut input = [0u8; 64];
+        input[31] = 1;
+        input[63] = 2;
+        let hash = poseidon.run(&input).unwrap();
+        assert_eq!(hex::encode(hash), "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a");
+        assert!(poseidon.run(&input[..40]).is_err());
+        assert_eq!(poseidon.gas(&input), 2 * POSEIDON_GAS);
+
+        let bls = &precompiles["bls_aggregate_verify"];
+        let secret = [7u8; 32];
+        let call = |message: &[u8]| {
+            let keys = AbiValue::List(vec![AbiValue::Bytes(dxid_crypto::bls::public_key(&secret))]);
+            let messages = AbiValue::List(vec![AbiValue::Bytes(message.to_vec())]);
+            let signature = AbiValue::Bytes(dxid_crypto::bls::sign(&secret, b"vote"));
+            encode_values(&[keys, messages, signature])
+        };
+        assert_eq!(bls.run(&call(b"vote")).unwrap(), encode_values(&[AbiValue::Bool(true)]));
+        assert_eq!(bls.run(&call(b"veto")).unwrap(), encode_values(&[AbiValue::Bool(false)]));
+        assert!(bls.run(&[1, 2, 3]).is_err());
+        assert!(bls.gas(&call(b"vote")) >= 2 * BLS_PAIRING_GAS);
+    }
+
+    #[test]
+    fn snark_verify_checks_proofs_against_the_backend_key() {
+        let backend = Arc::new(dxid_crypto::Groth16Backend::new().unwrap());
+        let digest = [4u8; 32];
+        let proof = backend.prove_digest(&digest).unwrap();
+        let snark = SnarkVerifyPrecompile::new(backend);
+        let input = |digest: [u8; 32]| {
+            encode_values(&[AbiValue::Bytes(proof.proof.clone()), AbiValue::Bytes(digest.to_vec())])
+        };
+        assert_eq!(snark.run(&input(digest)).unwrap(), encode_values(&[AbiValue::Bool(true)]));
+        assert_eq!(snark.run(&input([5u8; 32])).unwrap(), encode_values(&[AbiValue::Bool(false)]));
+        assert!(snark.run(&encode_values(&[AbiValue::Bytes(vec![]), AbiValue::Bytes(vec![1])])).is_err());
+    }
+}
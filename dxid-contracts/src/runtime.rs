@@ -0,0 +1,897 @@
+//! Deployed WASM and native contracts and their state, run on behalf of the
+//! chain's execution engine.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use dxid_core::contract::{
+    ContractCall, ContractCallEnv, ContractCallResult, ContractEvent, ContractRuntime, SavedStorage,
+    ScheduledCall,
+};
+use dxid_core::Address;
+use dxid_storage::{ContractRecord, ContractStore, ContractVersionRecord};
+use parking_lot::Mutex;
+
+use crate::abi::{decode_values, AbiType, AbiValue, ContractAbi};
+use crate::access::IdentityLookup;
+use crate::deploy::{contract_address, deploy_input_types, DEPLOY_GAS_PER_BYTE, DEPLOY_METHOD};
+use crate::native::{arg_bytes, arg_string, arg_u64, builtin, encode_values, NativeContract};
+use crate::schedule::{schedule_input_types, Schedule, MAX_SCHEDULES, SCHEDULE_METHOD, UNSCHEDULE_METHOD};
+use crate::snapshot::{state_root, StateSnapshot};
+use crate::upgrade::{upgrade_input_types, CodeVersion, UpgradePolicy, UPGRADE_METHOD};
+use crate::wasm::{CallContext, CallOutcome, ContractError, ContractState, WasmContract, WasmEngine};
+
+/// Gas available to a single contract call.
+pub const DEFAULT_CALL_GAS: u64 = 1_000_000;
+
+#[derive(Clone)]
+enum Code {
+    Wasm(WasmContract),
+    Native(Arc<dyn NativeContract>),
+}
+
+#[derive(Clone)]
+struct Deployed {
+    code: Code,
+    /// WASM bytes of `code`, kept for the audit history; empty for native contracts.
+    source: Vec<u8>,
+    abi: ContractAbi,
+    state: ContractState,
+    policy: UpgradePolicy,
+    /// Governance votes by proposed code hash.
+    votes: HashMap<[u8; 32], BTreeSet<Address>>,
+    history: Vec<CodeVersion>,
+    schedules: Vec<Schedule>,
+    next_schedule_id: u64,
+}
+
+impl Deployed {
+    fn new(code: Code, source: Vec<u8>, abi: ContractAbi, policy: UpgradePolicy) -> Self {
+        Self {
+            code,
+            source,
+            abi,
+            state: ContractState::default(),
+            policy,
+            votes: HashMap::new(),
+            history: Vec::new(),
+            schedules: Vec::new(),
+            next_schedule_id: 0,
+        }
+    }
+
+    /// Callbacks the contract may still register at `height`, after dropping
+    /// the one-shot callbacks that already ran.
+    fn schedule_slots(&mut self, height: u64) -> usize {
+        self.schedules.retain(|s| !s.is_expired(height));
+        MAX_SCHEDULES.saturating_sub(self.schedules.len())
+    }
+
+    fn add_schedule(&mut self, contract: Address, schedule: Schedule) -> ContractEvent {
+        let values = [
+            AbiValue::U64(schedule.id),
+            AbiValue::String(schedule.method.clone()),
+            AbiValue::U64(schedule.at_height),
+            AbiValue::U64(schedule.interval),
+        ];
+        self.next_schedule_id = schedule.id + 1;
+        self.schedules.push(schedule);
+        ContractEvent { contract, topic: "scheduled".to_string(), data: encode_values(&values) }
+    }
+
+    /// Registers the callbacks a successful call asked for and turns its
+    /// outcome into the chain's result.
+    fn finish(&mut self, contract: Address, outcome: CallOutcome) -> ContractCallResult {
+        let mut events = outcome.events;
+        for request in outcome.schedules {
+            let schedule = Schedule {
+                id: self.next_schedule_id,
+                method: request.method,
+                at_height: request.at_height,
+                interval: request.interval,
+                gas_limit: request.gas_limit,
+            };
+            events.push(self.add_schedule(contract, schedule));
+        }
+        ContractCallResult {
+            output: outcome.output,
+            events,
+            transfers: outcome.transfers,
+            gas_used: outcome.gas_used,
+        }
+    }
+}
+
+fn check_policy(policy: &UpgradePolicy) -> Result<()> {
+    if let UpgradePolicy::Governance { voters, threshold } = policy {
+        if *threshold == 0 || *threshold > voters.len() {
+            return Err(anyhow!("governance threshold {threshold} of {} voters", voters.len()));
+        }
+    }
+    Ok(())
+}
+
+/// Code with `hash` from the blob store, checked against the hash.
+async fn fetch_code<S: ContractStore + ?Sized>(store: &S, hash: &[u8; 32]) -> Result<Vec<u8>> {
+    let code = store
+        .get_code(hash)
+        .await?
+        .ok_or_else(|| anyhow!("code {} is missing from the blob store", hex::encode(hash)))?;
+    if blake3::hash(&code).as_bytes() != hash {
+        return Err(anyhow!("stored code does not match its hash {}", hex::encode(hash)));
+    }
+    Ok(code)
+}
+
+/// Every contract as it was at [`WasmRuntime::checkpoint`].
+pub struct RuntimeCheckpoint(HashMap<Address, Deployed>);
+
+pub struct WasmRuntime {
+    engine: WasmEngine,
+    gas_limit: u64,
+    contracts: Mutex<HashMap<Address, Deployed>>,
+    identities: Option<Arc<dyn IdentityLookup>>,
+}
+
+impl WasmRuntime {
+    pub fn new(engine: WasmEngine) -> Self {
+        Self { engine, gas_limit: DEFAULT_CALL_GAS, contracts: Mutex::new(HashMap::new()), identities: None }
+    }
+
+    pub fn with_gas_limit(mut self, gas_limit: u64) -> Self {
+        self.gas_limit = gas_limit;
+        self
+    }
+
+    /// Resolves callers' identities for identity-gated methods; without it
+    /// those methods reject every caller.
+    pub fn with_identities(mut self, identities: Arc<dyn IdentityLookup>) -> Self {
+        self.identities = Some(identities);
+        self
+    }
+
+    /// Compiles `code` and deploys it at `address` with empty state, after
+    /// checking that the code exports every method `abi` lists. Returns the
+    /// code hash. The contract cannot be upgraded.
+    pub fn deploy(&self, address: Address, code: &[u8], abi: ContractAbi) -> Result<[u8; 32]> {
+        self.deploy_with_policy(address, code, abi, UpgradePolicy::Immutable)
+    }
+
+    /// Like [`WasmRuntime::deploy`], declaring who may upgrade the contract.
+    pub fn deploy_with_policy(
+        &self,
+        address: Address,
+        code: &[u8],
+        abi: ContractAbi,
+        policy: UpgradePolicy,
+    ) -> Result<[u8; 32]> {
+        check_policy(&policy)?;
+        let compiled = self.compile_checked(code, &abi)?;
+        let hash = compiled.code_hash();
+        self.insert(address, Deployed::new(Code::Wasm(compiled), code.to_vec(), abi, policy))?;
+        Ok(hash)
+    }
+
+    /// Deploys a built-in contract at `address` with empty state.
+    pub fn deploy_native(&self, address: Address, contract: Arc<dyn NativeContract>) -> Result<()> {
+        let abi = contract.abi();
+        self.insert(address, Deployed::new(Code::Native(contract), Vec::new(), abi, UpgradePolicy::Immutable))
+    }
+
+    fn compile_checked(&self, code: &[u8], abi: &ContractAbi) -> Result<WasmContract> {
+        let code = self.engine.compile(code)?;
+        if let Some(missing) = abi.methods.iter().find(|m| !code.has_method(&m.name)) {
+            return Err(anyhow!("ABI lists {} but the code does not export it", missing.name));
+        }
+        let reserved = [DEPLOY_METHOD, UPGRADE_METHOD, SCHEDULE_METHOD, UNSCHEDULE_METHOD];
+        if let Some(name) = reserved.iter().find(|name| abi.method(name).is_ok()) {
+            return Err(anyhow!("{name} is a reserved method name"));
+        }
+        Ok(code)
+    }
+
+    fn insert(&self, address: Address, deployed: Deployed) -> Result<()> {
+        let mut contracts = self.contracts.lock();
+        if contracts.contains_key(&address) {
+            return Err(anyhow!("a contract is already deployed at this address"));
+        }
+        contracts.insert(address, deployed);
+        Ok(())
+    }
+
+    /// What [`WasmRuntime::load`] needs to restore the contract at `address`.
+    pub fn record(&self, address: &Address) -> Option<ContractRecord> {
+        let contracts = self.contracts.lock();
+        let deployed = contracts.get(address)?;
+        let (code_hash, native) = match &deployed.code {
+            Code::Wasm(code) => (Some(code.code_hash()), None),
+            Code::Native(contract) => (None, Some(contract.name().to_string())),
+        };
+        let history = deployed
+            .history
+            .iter()
+            .map(|version| ContractVersionRecord {
+                code_hash: version.code_hash,
+                abi: serde_json::to_value(&version.abi).expect("ABI serializes"),
+                replaced_at: version.replaced_at,
+            })
+            .collect();
+        Some(ContractRecord {
+            address: *address,
+            code_hash,
+            native,
+            abi: serde_json::to_value(&deployed.abi).expect("ABI serializes"),
+            upgrade_policy: serde_json::to_value(&deployed.policy).expect("policy serializes"),
+            history,
+        })
+    }
+
+    /// Saves the contract at `address` to `store`, with its current and
+    /// replaced code in the blob store. Call after deploying or upgrading.
+    pub async fn persist<S: ContractStore + ?Sized>(&self, store: &S, address: &Address) -> Result<()> {
+        let record = self.record(address).ok_or_else(|| anyhow!("no contract at address"))?;
+        let blobs: Vec<([u8; 32], Vec<u8>)> = {
+            let contracts = self.contracts.lock();
+            let deployed = &contracts[address];
+            let current = record.code_hash.map(|hash| (hash, deployed.source.clone()));
+            current
+                .into_iter()
+                .chain(deployed.history.iter().map(|v| (v.code_hash, v.code.clone())))
+                .collect()
+        };
+        for (hash, code) in &blobs {
+            store.put_code(hash, code).await?;
+        }
+        store.put_contract(&record).await
+    }
+
+    /// Rebuilds the runtime from the contracts in `store`, recompiling each
+    /// one's code after checking it against the recorded hash. Contracts
+    /// start with empty state.
+    pub async fn load<S: ContractStore + ?Sized>(engine: WasmEngine, store: &S) -> Result<Self> {
+        let runtime = Self::new(engine);
+        for record in store.list_contracts().await? {
+            let abi: ContractAbi = serde_json::from_value(record.abi)?;
+            let policy: UpgradePolicy = serde_json::from_value(record.upgrade_policy)?;
+            let (code, source) = match (record.native, record.code_hash) {
+                (Some(name), _) => {
+                    let contract =
+                        builtin(&name).ok_or_else(|| anyhow!("no built-in contract named {name}"))?;
+                    (Code::Native(contract), Vec::new())
+                }
+                (None, Some(hash)) => {
+                    let source = fetch_code(store, &hash).await?;
+                    (Code::Wasm(runtime.compile_checked(&source, &abi)?), source)
+                }
+                (None, None) => return Err(anyhow!("contract record has neither code nor a built-in name")),
+            };
+            let mut deployed = Deployed::new(code, source, abi, policy);
+            for version in record.history {
+                deployed.history.push(CodeVersion {
+                    code: fetch_code(store, &version.code_hash).await?,
+                    code_hash: version.code_hash,
+                    abi: serde_json::from_value(version.abi)?,
+                    replaced_at: version.replaced_at,
+                });
+            }
+            runtime.insert(record.address, deployed)?;
+        }
+        Ok(runtime)
+    }
+
+    /// The contracts as they are now, to [`WasmRuntime::restore`] after
+    /// calls whose effects must not be kept.
+    pub fn checkpoint(&self) -> RuntimeCheckpoint {
+        RuntimeCheckpoint(self.contracts.lock().clone())
+    }
+
+    /// Puts every contract back as it was at `checkpoint`.
+    pub fn restore(&self, checkpoint: RuntimeCheckpoint) {
+        *self.contracts.lock() = checkpoint.0;
+    }
+
+    /// Addresses of the deployed contracts.
+    pub fn addresses(&self) -> Vec<Address> {
+        self.contracts.lock().keys().copied().collect()
+    }
+
+    /// The ABI of the code the contract at `address` currently runs.
+    pub fn abi(&self, address: &Address) -> Option<ContractAbi> {
+        self.contracts.lock().get(address).map(|d| d.abi.clone())
+    }
+
+    pub fn state(&self, address: &Address) -> Option<ContractState> {
+        self.contracts.lock().get(address).map(|d| d.state.clone())
+    }
+
+    /// Storage of the contract at `address` with its state root, labelled
+    /// with `height`, the last height the chain applied.
+    pub fn snapshot(&self, address: &Address, height: u64) -> Option<StateSnapshot> {
+        let contracts = self.contracts.lock();
+        let deployed = contracts.get(address)?;
+        let code_hash = match &deployed.code {
+            Code::Wasm(code) => Some(code.code_hash()),
+            Code::Native(_) => None,
+        };
+        Some(StateSnapshot::new(*address, height, code_hash, &deployed.state.storage))
+    }
+
+    /// Commitment to the storage of the contract at `address`.
+    pub fn state_root(&self, address: &Address) -> Option<[u8; 32]> {
+        self.contracts.lock().get(address).map(|d| state_root(&d.state.storage))
+    }
+
+    /// Fills the storage of the freshly deployed contract at `address` from
+    /// `snapshot`, after checking its entries against its state root, and
+    /// returns the root. The balance is not part of the state and stays put.
+    pub fn import_state(&self, address: &Address, snapshot: &StateSnapshot) -> Result<[u8; 32]> {
+        let storage = snapshot.verified_storage()?;
+        let mut contracts = self.contracts.lock();
+        let deployed = contracts.get_mut(address).ok_or_else(|| anyhow!("no contract at address"))?;
+        if !deployed.state.storage.is_empty() {
+            return Err(anyhow!("contract already has state"));
+        }
+        deployed.state.storage = storage;
+        Ok(snapshot.state_root)
+    }
+
+    /// Runs `f` on the state of the contract at `address`, for tooling that
+    /// edits state outside a call.
+    pub(crate) fn with_state<R>(
+        &self,
+        address: &Address,
+        f: impl FnOnce(&mut ContractState) -> R,
+    ) -> Option<R> {
+        self.contracts.lock().get_mut(address).map(|d| f(&mut d.state))
+    }
+
+    pub fn upgrade_policy(&self, address: &Address) -> Option<UpgradePolicy> {
+        self.contracts.lock().get(address).map(|d| d.policy.clone())
+    }
+
+    /// Code the contract at `address` ran before each upgrade, oldest first.
+    pub fn code_history(&self, address: &Address) -> Vec<CodeVersion> {
+        self.contracts.lock().get(address).map(|d| d.history.clone()).unwrap_or_default()
+    }
+
+    /// Callbacks registered by the contract at `address`, in registration order.
+    pub fn schedules(&self, address: &Address) -> Vec<Schedule> {
+        self.contracts.lock().get(address).map(|d| d.schedules.clone()).unwrap_or_default()
+    }
+
+    /// Gas `call` would use if it ran with `env` now. It runs on a copy of
+    /// the contract, and deployments are checked but not kept, so nothing it
+    /// does sticks.
+    pub fn estimate_gas(&self, env: &ContractCallEnv, call: &ContractCall) -> Result<u64> {
+        if call.method == DEPLOY_METHOD {
+            if self.contracts.lock().contains_key(&call.contract) {
+                return Err(anyhow!("a contract is already deployed at this address"));
+            }
+            return Ok(self.prepare_deploy(env, call)?.1.gas_used);
+        }
+        let deployed = self.contracts.lock().get(&call.contract).cloned();
+        let mut deployed = deployed.ok_or_else(|| anyhow!("no contract at address"))?;
+        Ok(self.call_deployed(&mut deployed, env, call)?.gas_used)
+    }
+
+    /// Checks and compiles the contract a [`DEPLOY_METHOD`] call deploys,
+    /// returning it with the call's result.
+    fn prepare_deploy(
+        &self,
+        env: &ContractCallEnv,
+        call: &ContractCall,
+    ) -> Result<(Deployed, ContractCallResult)> {
+        let args = decode_values(&deploy_input_types(), &call.input)?;
+        let (source, salt) = (arg_bytes(&args[0]), arg_u64(&args[3]));
+        if contract_address(&env.caller, &source, salt) != call.contract {
+            return Err(anyhow!("contract address does not derive from the deployer, code and salt"));
+        }
+        let gas_used = DEPLOY_GAS_PER_BYTE.saturating_mul(source.len() as u64);
+        if gas_used > env.gas_limit.map_or(self.gas_limit, |limit| limit.min(self.gas_limit)) {
+            return Err(ContractError::OutOfGas.into());
+        }
+        let abi: ContractAbi = serde_json::from_str(&arg_string(&args[1]))?;
+        let policy: UpgradePolicy = serde_json::from_str(&arg_string(&args[2]))?;
+        check_policy(&policy)?;
+        let code = self.compile_checked(&source, &abi)?;
+        let event = ContractEvent {
+            contract: call.contract,
+            topic: "deployed".to_string(),
+            data: encode_values(&[AbiValue::Bytes(code.code_hash().to_vec())]),
+        };
+        let result = ContractCallResult { events: vec![event], gas_used, ..ContractCallResult::default() };
+        Ok((Deployed::new(Code::Wasm(code), source, abi, policy), result))
+    }
+
+    /// Handles the admin's [`SCHEDULE_METHOD`] and [`UNSCHEDULE_METHOD`] calls.
+    fn manage_schedules(
+        deployed: &mut Deployed,
+        ctx: &CallContext,
+        method: &str,
+        input: &[u8],
+    ) -> Result<ContractCallResult> {
+        if !matches!(&deployed.policy, UpgradePolicy::Admin(admin) if *admin == ctx.caller) {
+            return Err(anyhow!("only the contract admin can manage its callbacks"));
+        }
+        if method == UNSCHEDULE_METHOD {
+            let id = arg_u64(&decode_values(&[AbiType::U64], input)?[0]);
+            let count = deployed.schedules.len();
+            deployed.schedules.retain(|s| s.id != id);
+            if deployed.schedules.len() == count {
+                return Err(anyhow!("no callback {id}"));
+            }
+            let event = ContractEvent {
+                contract: ctx.contract,
+                topic: "unscheduled".to_string(),
+                data: encode_values(&[AbiValue::U64(id)]),
+            };
+            return Ok(ContractCallResult { events: vec![event], ..ContractCallResult::default() });
+        }
+        let args = decode_values(&schedule_input_types(), input)?;
+        let schedule = Schedule {
+            id: deployed.next_schedule_id,
+            method: arg_string(&args[0]),
+            at_height: arg_u64(&args[1]),
+            interval: arg_u64(&args[2]),
+            gas_limit: arg_u64(&args[3]),
+        };
+        if !matches!(&deployed.code, Code::Wasm(code) if code.has_method(&schedule.method)) {
+            return Err(anyhow!("{} is not an exported method", schedule.method));
+        }
+        if schedule.at_height <= ctx.block_height {
+            return Err(anyhow!("callbacks must start in a later block"));
+        }
+        if ctx.schedule_slots == 0 {
+            return Err(anyhow!("contract has {MAX_SCHEDULES} callbacks already"));
+        }
+        let output = encode_values(&[AbiValue::U64(schedule.id)]);
+        let event = deployed.add_schedule(ctx.contract, schedule);
+        Ok(ContractCallResult { output, events: vec![event], ..ContractCallResult::default() })
+    }
+
+    fn upgrade(
+        &self,
+        deployed: &mut Deployed,
+        ctx: &CallContext,
+        input: &[u8],
+    ) -> Result<ContractCallResult> {
+        let Code::Wasm(current) = &deployed.code else {
+            return Err(anyhow!("built-in contracts cannot be upgraded"));
+        };
+        let old_hash = current.code_hash();
+        let args = decode_values(&upgrade_input_types(), input)?;
+        let (source, migrate) = (arg_bytes(&args[0]), arg_string(&args[2]));
+        let abi: ContractAbi = serde_json::from_str(&arg_string(&args[1]))?;
+        let code = self.compile_checked(&source, &abi)?;
+        let new_hash = code.code_hash();
+        let event = |topic: &str, values: &[AbiValue]| ContractEvent {
+            contract: ctx.contract,
+            topic: topic.to_string(),
+            data: encode_values(values),
+        };
+
+        let mut votes = deployed.votes.get(&new_hash).cloned().unwrap_or_default();
+        match &deployed.policy {
+            UpgradePolicy::Immutable => return Err(anyhow!("contract is not upgradeable")),
+            UpgradePolicy::Admin(admin) if *admin != ctx.caller => {
+                return Err(anyhow!("only the contract admin can upgrade it"))
+            }
+            UpgradePolicy::Admin(_) => {}
+            UpgradePolicy::Governance { voters, .. } if !voters.contains(&ctx.caller) => {
+                return Err(anyhow!("caller is not an upgrade voter"))
+            }
+            UpgradePolicy::Governance { threshold, .. } => {
+                votes.insert(ctx.caller);
+                if votes.len() < *threshold {
+                    let count = AbiValue::U64(votes.len() as u64);
+                    deployed.votes.insert(new_hash, votes);
+                    let vote = event("upgrade_vote", &[AbiValue::Bytes(new_hash.to_vec()), count]);
+                    return Ok(ContractCallResult { events: vec![vote], ..ContractCallResult::default() });
+                }
+            }
+        }
+
+        let mut state = deployed.state.clone();
+        let mut migrated = CallOutcome::default();
+        if !migrate.is_empty() {
+            abi.decode_call(&migrate, &[])?;
+            migrated = self.engine.call(&code, &mut state, ctx, &migrate, &[])?;
+        }
+        let old = std::mem::replace(&mut deployed.source, source);
+        let old_abi = std::mem::replace(&mut deployed.abi, abi);
+        deployed.history.push(CodeVersion {
+            code_hash: old_hash,
+            code: old,
+            abi: old_abi,
+            replaced_at: ctx.block_height,
+        });
+        // Callbacks into methods the new code dropped could only fail.
+        deployed.schedules.retain(|s| code.has_method(&s.method));
+        deployed.code = Code::Wasm(code);
+        deployed.state = state;
+        deployed.votes.clear();
+        let mut result = deployed.finish(ctx.contract, migrated);
+        let hashes = [AbiValue::Bytes(old_hash.to_vec()), AbiValue::Bytes(new_hash.to_vec())];
+        result.events.push(event("upgraded", &hashes));
+        Ok(result)
+    }
+
+    fn call_deployed(
+        &self,
+        deployed: &mut Deployed,
+        env: &ContractCallEnv,
+        call: &ContractCall,
+    ) -> Result<ContractCallResult> {
+        let ctx = CallContext {
+            contract: call.contract,
+            caller: env.caller,
+            block_height: env.block_height,
+            timestamp: env.timestamp,
+            seed: env.seed,
+            value: env.value,
+            gas_limit: env.gas_limit.map_or(self.gas_limit, |limit| limit.min(self.gas_limit)),
+            schedule_slots: deployed.schedule_slots(env.block_height),
+        };
+        // The chain's balance is authoritative; the state only mirrors it.
+        deployed.state.balance = env.balance;
+        if call.method == UPGRADE_METHOD {
+            return self.upgrade(deployed, &ctx, &call.input);
+        }
+        if call.method == SCHEDULE_METHOD || call.method == UNSCHEDULE_METHOD {
+            return Self::manage_schedules(deployed, &ctx, &call.method, &call.input);
+        }
+        let args = deployed.abi.decode_call(&call.method, &call.input)?;
+        if let Some(rule) = &deployed.abi.method(&call.method)?.access {
+            let owner = match &deployed.policy {
+                UpgradePolicy::Admin(admin) => Some(admin),
+                _ => None,
+            };
+            if !rule.allows(&env.caller, owner, self.identities.as_deref()) {
+                return Err(anyhow!("caller may not call {}", call.method));
+            }
+        }
+        let outcome = match &deployed.code {
+            Code::Wasm(code) => {
+                self.engine.call(code, &mut deployed.state, &ctx, &call.method, &call.input)?
+            }
+            Code::Native(contract) => {
+                // Run on a copy so a failed call cannot leave partial writes.
+                let mut state = deployed.state.clone();
+                let outcome = contract.call(&mut state, &ctx, &call.method, &args)?;
+                if outcome.gas_used > ctx.gas_limit {
+                    return Err(ContractError::OutOfGas.into());
+                }
+                deployed.state = state;
+                outcome
+            }
+        };
+        Ok(deployed.finish(call.contract, outcome))
+    }
+}
+
+impl ContractRuntime for WasmRuntime {
+    fn call(&self, env: &ContractCallEnv, call: &ContractCall) -> Result<ContractCallResult> {
+        if call.method == DEPLOY_METHOD {
+            let (deployed, result) = self.prepare_deploy(env, call)?;
+            self.insert(call.contract, deployed)?;
+            return Ok(result);
+        }
+        let mut contracts = self.contracts.lock();
+        let deployed = contracts.get_mut(&call.contract).ok_or_else(|| anyhow!("no contract at address"))?;
+        self.call_deployed(deployed, env, call)
+    }
+
+    fn scheduled_calls(&self, height: u64) -> Vec<ScheduledCall> {
+        let contracts = self.contracts.lock();
+        let mut addresses: Vec<&Address> = contracts.keys().collect();
+        addresses.sort();
+        addresses
+            .into_iter()
+            .flat_map(|address| {
+                contracts[address].schedules.iter().filter(|s| s.is_due(height)).map(|s| ScheduledCall {
+                    contract: *address,
+                    method: s.method.clone(),
+                    gas_limit: s.gas_limit,
+                })
+            })
+            .collect()
+    }
+
+    /// Runs a due callback with no input, past the ABI and access checks:
+    /// the method only has to be exported.
+    fn call_scheduled(&self, env: &ContractCallEnv, call: &ContractCall) -> Result<ContractCallResult> {
+        let mut contracts = self.contracts.lock();
+        let deployed = contracts.get_mut(&call.contract).ok_or_else(|| anyhow!("no contract at address"))?;
+        if !deployed.schedules.iter().any(|s| s.method == call.method && s.is_due(env.block_height)) {
+            return Err(anyhow!("{} is not due at height {}", call.method, env.block_height));
+        }
+        let ctx = CallContext {
+            contract: call.contract,
+            caller: call.contract,
+            block_height: env.block_height,
+            timestamp: env.timestamp,
+            seed: env.seed,
+            value: 0,
+            gas_limit: env.gas_limit.map_or(self.gas_limit, |limit| limit.min(self.gas_limit)),
+            schedule_slots: deployed.schedule_slots(env.block_height),
+        };
+        let Code::Wasm(code) = &deployed.code else {
+            return Err(anyhow!("built-in contracts have no callbacks"));
+        };
+        deployed.state.balance = env.balance;
+        let outcome = self.engine.call(code, &mut deployed.state, &ctx, &call.method, &[])?;
+        Ok(deployed.finish(call.contract, outcome))
+    }
+    fn save_storage(&self) -> Option<SavedStorage> {
+        Some(SavedStorage(Arc::new(self.checkpoint())))
+    }
+
+    fn restore_storage(&self, saved: &SavedStorage) {
+        let checkpoint = saved.0.downcast_ref::<RuntimeCheckpoint>().expect("saved by a WasmRuntime");
+        *self.contracts.lock() = checkpoint.0.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::{AbiMethod, AbiParam, AbiType, AbiValue};
+    use crate::access::AccessRule;
+
+    const ECHO: &str = r#"
+        (module
+          (import "env" "input_len" (func $input_len (result i32)))
+          (import "env" "read_input" (func $read_input (param i32)))
+          (import "env" "set_return" (func $ret (param i32 i32)))
+          (memory (export "memory") 1)
+          (func (export "echo")
+            (call $read_input (i32.const 0))
+            (call $ret (i32.const 0) (call $input_len)))
+          (func (export "internal")))
+    "#;
+
+    const V2: &str = r#"
+        (module
+          (import "env" "storage_set" (func $set (param i32 i32 i32 i32)))
+          (import "env" "set_return" (func $ret (param i32 i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "v2")
+          (func (export "echo") (call $ret (i32.const 0) (i32.const 2)))
+          (func (export "migrate") (call $set (i32.const 0) (i32.const 2) (i32.const 0) (i32.const 2))))
+    "#;
+
+    const TICKER: &str = r#"
+        (module
+          (import "env" "schedule" (func $schedule (param i32 i32 i64 i64 i64) (result i32)))
+          (import "env" "storage_set" (func $set (param i32 i32 i32 i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "tick")
+          (func (export "start")
+            (drop (call $schedule (i32.const 0) (i32.const 4) (i64.const 5) (i64.const 2) (i64.const 10000))))
+          (func (export "tick") (call $set (i32.const 0) (i32.const 4) (i32.const 0) (i32.const 4))))
+    "#;
+
+    fn abi(method: &str) -> ContractAbi {
+        let arg = AbiParam { name: "n".into(), ty: AbiType::U64 };
+        ContractAbi {
+            methods: vec![AbiMethod {
+                name: method.into(),
+                args: vec![arg],
+                returns: Some(AbiType::U64),
+                access: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn calls_are_checked_against_the_deployed_abi() {
+        let runtime = WasmRuntime::new(WasmEngine::new().unwrap());
+        assert!(runtime.deploy([1u8; 32], ECHO.as_bytes(), abi("missing")).is_err());
+        runtime.deploy([1u8; 32], ECHO.as_bytes(), abi("echo")).unwrap();
+        let abi = runtime.abi(&[1u8; 32]).unwrap();
+
+        let input = abi.encode_call("echo", &[AbiValue::U64(9)]).unwrap();
+        let call = ContractCall { contract: [1u8; 32], method: "echo".into(), input };
+        let env = ContractCallEnv { caller: [2u8; 32], block_height: 1, ..ContractCallEnv::default() };
+        let result = runtime.call(&env, &call).unwrap();
+        assert_eq!(abi.decode_return("echo", &result.output).unwrap(), Some(AbiValue::U64(9)));
+
+        let short = ContractCall { input: vec![9], ..call.clone() };
+        assert!(runtime.call(&env, &short).unwrap_err().to_string().contains("malformed"));
+        let internal = ContractCall { method: "internal".into(), input: Vec::new(), ..call };
+        assert!(runtime.call(&env, &internal).is_err());
+    }
+
+    #[test]
+    fn native_contracts_run_behind_the_same_checks() {
+        let runtime = WasmRuntime::new(WasmEngine::new().unwrap());
+        let token = crate::token::TokenClient::new([3u8; 32]);
+        runtime.deploy_native([3u8; 32], Arc::new(crate::token::TokenContract::new())).unwrap();
+        let env = ContractCallEnv { caller: [2u8; 32], block_height: 1, ..ContractCallEnv::default() };
+        runtime.call(&env, &token.init("Coin", "CN", 0)).unwrap();
+        runtime.call(&env, &token.mint([2u8; 32], 5)).unwrap();
+        let state = runtime.state(&[3u8; 32]).unwrap();
+        assert!(runtime.call(&env, &token.transfer([4u8; 32], 6)).is_err());
+        assert_eq!(runtime.state(&[3u8; 32]).unwrap(), state);
+        assert!(runtime.call(&env, &ContractCall { input: vec![1], ..token.total_supply() }).is_err());
+    }
+
+    #[test]
+    fn upgrades_follow_the_declared_policy_and_keep_history() {
+        let runtime = WasmRuntime::new(WasmEngine::new().unwrap());
+        let (admin, other) = ([5u8; 32], [6u8; 32]);
+        let policy = UpgradePolicy::Admin(admin);
+        let v1 = runtime.deploy_with_policy([1u8; 32], ECHO.as_bytes(), abi("echo"), policy).unwrap();
+        let no_args =
+            |name: &str| AbiMethod { name: name.into(), args: Vec::new(), returns: None, access: None };
+        let v2_abi = ContractAbi { methods: vec![no_args("echo"), no_args("migrate")] };
+        let env = |caller| ContractCallEnv { caller, block_height: 7, ..ContractCallEnv::default() };
+
+        let upgrade = crate::upgrade_call([1u8; 32], V2.as_bytes().to_vec(), &v2_abi, Some("migrate"));
+        assert!(runtime.call(&env(other), &upgrade).is_err());
+        let result = runtime.call(&env(admin), &upgrade).unwrap();
+        let upgraded = result.events.last().unwrap();
+        assert_eq!(upgraded.topic, "upgraded");
+        assert_eq!(&upgraded.data[4..36], v1.as_slice());
+        let state = runtime.state(&[1u8; 32]).unwrap();
+        assert_eq!(state.storage.get(b"v2".as_slice()), Some(&b"v2".to_vec()));
+        let history = runtime.code_history(&[1u8; 32]);
+        assert_eq!((history.len(), history[0].code_hash, history[0].replaced_at), (1, v1, 7));
+        assert_eq!(history[0].code, ECHO.as_bytes());
+        let echo = ContractCall { contract: [1u8; 32], method: "echo".into(), input: Vec::new() };
+        assert_eq!(runtime.call(&env(other), &echo).unwrap().output, b"v2");
+
+        let voters: BTreeSet<Address> = [[7u8; 32], [8u8; 32], [9u8; 32]].into();
+        let policy = UpgradePolicy::Governance { voters, threshold: 2 };
+        runtime.deploy_with_policy([2u8; 32], ECHO.as_bytes(), abi("echo"), policy).unwrap();
+        let upgrade = crate::upgrade_call([2u8; 32], V2.as_bytes().to_vec(), &v2_abi, None);
+        assert!(runtime.call(&env(other), &upgrade).is_err());
+        let vote = runtime.call(&env([7u8; 32]), &upgrade).unwrap();
+        assert_eq!(vote.events[0].topic, "upgrade_vote");
+        assert!(runtime.code_history(&[2u8; 32]).is_empty());
+        runtime.call(&env([8u8; 32]), &upgrade).unwrap();
+        assert_eq!(runtime.code_history(&[2u8; 32]).len(), 1);
+
+        runtime.deploy([3u8; 32], ECHO.as_bytes(), abi("echo")).unwrap();
+        let upgrade = crate::upgrade_call([3u8; 32], V2.as_bytes().to_vec(), &v2_abi, None);
+        assert!(runtime.call(&env(admin), &upgrade).is_err());
+    }
+
+    #[test]
+    fn contracts_deploy_from_calls_at_derived_addresses() {
+        let runtime = WasmRuntime::new(WasmEngine::new().unwrap());
+        let (deployer, other) = ([5u8; 32], [6u8; 32]);
+        let deploy =
+            crate::deploy_call(&deployer, ECHO.as_bytes().to_vec(), &abi("echo"), &Default::default(), 1);
+        let env = |caller| ContractCallEnv { caller, block_height: 3, ..ContractCallEnv::default() };
+        assert!(runtime.call(&env(other), &deploy).is_err());
+        let starved = ContractCallEnv { gas_limit: Some(10), ..env(deployer) };
+        assert!(runtime.call(&starved, &deploy).is_err());
+
+        let gas = runtime.estimate_gas(&env(deployer), &deploy).unwrap();
+        assert_eq!(gas, DEPLOY_GAS_PER_BYTE * ECHO.len() as u64);
+        assert!(runtime.addresses().is_empty());
+        let result = runtime.call(&env(deployer), &deploy).unwrap();
+        assert_eq!((result.gas_used, result.events[0].topic.as_str()), (gas, "deployed"));
+        assert!(runtime.call(&env(deployer), &deploy).is_err());
+        assert!(runtime.estimate_gas(&env(deployer), &deploy).is_err());
+
+        let echo = ContractCall { contract: deploy.contract, method: "echo".into(), input: vec![0; 8] };
+        assert!(runtime.estimate_gas(&env(other), &echo).unwrap() > 0);
+        assert_eq!(runtime.state(&deploy.contract), Some(ContractState::default()));
+    }
+
+    #[tokio::test]
+    async fn deployed_contracts_survive_a_restart() {
+        let store = dxid_storage::MemoryContractStore::new();
+        let runtime = WasmRuntime::new(WasmEngine::new().unwrap());
+        let policy = UpgradePolicy::Admin([5u8; 32]);
+        let hash = runtime.deploy_with_policy([1u8; 32], ECHO.as_bytes(), abi("echo"), policy).unwrap();
+        runtime.deploy_native([2u8; 32], Arc::new(crate::token::TokenContract::new())).unwrap();
+        for address in runtime.addresses() {
+            runtime.persist(&store, &address).await.unwrap();
+        }
+
+        let restarted = WasmRuntime::load(WasmEngine::new().unwrap(), &store).await.unwrap();
+        assert_eq!(restarted.record(&[1u8; 32]), runtime.record(&[1u8; 32]));
+        assert_eq!(restarted.record(&[1u8; 32]).unwrap().code_hash, Some(hash));
+        assert_eq!(restarted.upgrade_policy(&[1u8; 32]), Some(UpgradePolicy::Admin([5u8; 32])));
+        assert_eq!(restarted.abi(&[2u8; 32]), runtime.abi(&[2u8; 32]));
+
+        let tampered = dxid_storage::MemoryContractStore::new();
+        let record = runtime.record(&[1u8; 32]).unwrap();
+        tampered
+            .put_code(&record.code_hash.unwrap(), b"(module (memory (export \"memory\") 1))")
+            .await
+            .unwrap();
+        tampered.put_contract(&record).await.unwrap();
+        assert!(WasmRuntime::load(WasmEngine::new().unwrap(), &tampered).await.is_err());
+    }
+
+    #[test]
+    fn access_rules_are_enforced_before_dispatch() {
+        let (owner, member) = ([5u8; 32], [6u8; 32]);
+        let mut identity = dxid_core::new_identity(vec![9u8; 32]);
+        let attribute =
+            dxid_core::IdentityAttribute { key: "kyc".into(), value: "yes".into(), embedding_ref: None };
+        dxid_core::add_attribute(&mut identity, attribute);
+        let identities: HashMap<Address, dxid_core::Identity> = [(member, identity)].into();
+        let runtime = WasmRuntime::new(WasmEngine::new().unwrap()).with_identities(Arc::new(identities));
+        let gated = |rule: AccessRule| {
+            let mut abi = abi("echo");
+            abi.methods[0].access = Some(rule);
+            abi
+        };
+        let kyc = AccessRule::IdentityAttribute { key: "kyc".into(), value: None };
+        let policy = UpgradePolicy::Admin(owner);
+        runtime.deploy_with_policy([1u8; 32], ECHO.as_bytes(), gated(AccessRule::Owner), policy).unwrap();
+        runtime.deploy([2u8; 32], ECHO.as_bytes(), gated(kyc)).unwrap();
+
+        let input = 9u64.to_le_bytes().to_vec();
+        let call = |contract| ContractCall { contract, method: "echo".into(), input: input.clone() };
+        let env = |caller| ContractCallEnv { caller, ..ContractCallEnv::default() };
+        assert!(runtime.call(&env(owner), &call([1u8; 32])).is_ok());
+        assert!(runtime.call(&env(member), &call([1u8; 32])).is_err());
+        assert!(runtime.call(&env(member), &call([2u8; 32])).is_ok());
+        assert!(runtime.call(&env(owner), &call([2u8; 32])).is_err());
+    }
+
+    #[test]
+    fn contracts_and_admins_schedule_callbacks() {
+        let runtime = WasmRuntime::new(WasmEngine::new().unwrap());
+        let (admin, other, contract) = ([5u8; 32], [6u8; 32], [1u8; 32]);
+        let start = AbiMethod { name: "start".into(), args: Vec::new(), returns: None, access: None };
+        let abi = ContractAbi { methods: vec![start] };
+        let policy = UpgradePolicy::Admin(admin);
+        runtime.deploy_with_policy(contract, TICKER.as_bytes(), abi, policy).unwrap();
+        let env =
+            |caller, block_height| ContractCallEnv { caller, block_height, ..ContractCallEnv::default() };
+
+        let start = ContractCall { contract, method: "start".into(), input: Vec::new() };
+        let result = runtime.call(&env(other, 1), &start).unwrap();
+        assert_eq!(result.events[0].topic, "scheduled");
+        let due = |height| runtime.scheduled_calls(height);
+        assert!(due(4).is_empty() && due(6).is_empty());
+        assert_eq!(due(5), vec![ScheduledCall { contract, method: "tick".into(), gas_limit: 10_000 }]);
+        assert_eq!(due(7).len(), 1);
+
+        let tick = ContractCall { contract, method: "tick".into(), input: Vec::new() };
+        assert!(runtime.call(&env(other, 5), &tick).is_err());
+        assert!(runtime.call_scheduled(&env(contract, 6), &tick).is_err());
+        runtime.call_scheduled(&env(contract, 5), &tick).unwrap();
+        assert!(runtime.state(&contract).unwrap().storage.contains_key(b"tick".as_slice()));
+
+        let once = crate::schedule_call(contract, "tick", 9, 0, 500);
+        assert!(runtime.call(&env(other, 5), &once).is_err());
+        assert!(runtime.call(&env(admin, 5), &crate::schedule_call(contract, "missing", 9, 0, 500)).is_err());
+        assert!(runtime.call(&env(admin, 5), &crate::schedule_call(contract, "tick", 5, 0, 500)).is_err());
+        assert_eq!(runtime.call(&env(admin, 5), &once).unwrap().output, 1u64.to_le_bytes());
+        assert_eq!(due(9).len(), 2);
+        runtime.call(&env(admin, 5), &crate::unschedule_call(contract, 0)).unwrap();
+        assert_eq!(due(9), vec![ScheduledCall { contract, method: "tick".into(), gas_limit: 500 }]);
+        assert!(due(11).is_empty());
+    }
+
+    #[test]
+    fn state_snapshots_move_into_new_deployments() {
+        let runtime = WasmRuntime::new(WasmEngine::new().unwrap());
+        let no_args =
+            |name: &str| AbiMethod { name: name.into(), args: Vec::new(), returns: None, access: None };
+        let v2_abi = ContractAbi { methods: vec![no_args("echo"), no_args("migrate")] };
+        runtime.deploy([1u8; 32], V2.as_bytes(), v2_abi.clone()).unwrap();
+        let migrate = ContractCall { contract: [1u8; 32], method: "migrate".into(), input: Vec::new() };
+        runtime.call(&ContractCallEnv::default(), &migrate).unwrap();
+
+        let snapshot = runtime.snapshot(&[1u8; 32], 3).unwrap();
+        assert_eq!((snapshot.height, snapshot.entries.len()), (3, 1));
+        assert_eq!(Some(snapshot.state_root), runtime.state_root(&[1u8; 32]));
+        runtime.deploy([2u8; 32], ECHO.as_bytes(), abi("echo")).unwrap();
+        let mut tampered = snapshot.clone();
+        tampered.entries[0].1 = b"v3".to_vec();
+        assert!(runtime.import_state(&[2u8; 32], &tampered).is_err());
+        assert_eq!(runtime.import_state(&[2u8; 32], &snapshot).unwrap(), snapshot.state_root);
+        assert_eq!(runtime.state_root(&[2u8; 32]), Some(snapshot.state_root));
+        assert!(runtime.import_state(&[2u8; 32], &snapshot).is_err());
+    }
+}
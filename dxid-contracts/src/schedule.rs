@@ -0,0 +1,100 @@
+//! Scheduled callbacks. A contract asks for one of its exported `() -> ()`
+//! methods to run in a later block, once or every `interval` blocks, through
+//! the `schedule` host function; its admin can do the same, or cancel a
+//! callback, with calls to the reserved [`SCHEDULE_METHOD`] and
+//! [`UNSCHEDULE_METHOD`]. The block producer runs due callbacks after the
+//! block's transactions with the contract as caller, paying for the gas out
+//! of the contract's balance, so a contract with recurring work such as
+//! vesting releases must be kept funded.
+//!
+//! Events: `scheduled` of `(id, method, at_height, interval)` and
+//! `unscheduled` of `(id)`.
+
+use dxid_core::contract::ContractCall;
+use dxid_core::Address;
+
+use crate::abi::{AbiType, AbiValue};
+use crate::native::encode_values;
+
+/// Reserved method name of admin calls registering a callback.
+pub const SCHEDULE_METHOD: &str = "__schedule";
+/// Reserved method name of admin calls cancelling a callback.
+pub const UNSCHEDULE_METHOD: &str = "__unschedule";
+/// Callbacks a contract may have registered at once.
+pub const MAX_SCHEDULES: usize = 16;
+
+/// A registered callback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// Unique per contract, in registration order.
+    pub id: u64,
+    pub method: String,
+    /// Height of the first run.
+    pub at_height: u64,
+    /// Blocks between runs; 0 runs once.
+    pub interval: u64,
+    pub gas_limit: u64,
+}
+
+impl Schedule {
+    /// Whether the callback runs in the block at `height`.
+    pub fn is_due(&self, height: u64) -> bool {
+        match height.checked_sub(self.at_height) {
+            Some(0) => true,
+            Some(since) => self.interval > 0 && since % self.interval == 0,
+            None => false,
+        }
+    }
+
+    /// Whether the callback has no runs left from `height` on.
+    pub fn is_expired(&self, height: u64) -> bool {
+        self.interval == 0 && self.at_height < height
+    }
+}
+
+pub(crate) fn schedule_input_types() -> [AbiType; 4] {
+    [AbiType::String, AbiType::U64, AbiType::U64, AbiType::U64]
+}
+
+/// Builds the admin call running `method` of `contract` at `at_height` and
+/// then every `interval` blocks, with up to `gas_limit` gas per run. The
+/// call returns the callback's id as a `u64`.
+pub fn schedule_call(
+    contract: Address,
+    method: &str,
+    at_height: u64,
+    interval: u64,
+    gas_limit: u64,
+) -> ContractCall {
+    let input = encode_values(&[
+        AbiValue::String(method.to_string()),
+        AbiValue::U64(at_height),
+        AbiValue::U64(interval),
+        AbiValue::U64(gas_limit),
+    ]);
+    ContractCall { contract, method: SCHEDULE_METHOD.to_string(), input }
+}
+
+/// Builds the admin call cancelling callback `id` of `contract`.
+pub fn unschedule_call(contract: Address, id: u64) -> ContractCall {
+    ContractCall {
+        contract,
+        method: UNSCHEDULE_METHOD.to_string(),
+        input: encode_values(&[AbiValue::U64(id)]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedules_fall_due_at_their_height_and_interval() {
+        let once = Schedule { id: 0, method: "tick".into(), at_height: 5, interval: 0, gas_limit: 1 };
+        assert!(!once.is_due(4) && once.is_due(5) && !once.is_due(6));
+        assert!(!once.is_expired(5) && once.is_expired(6));
+        let every = Schedule { interval: 3, ..once };
+        assert!(every.is_due(5) && !every.is_due(6) && every.is_due(8) && every.is_due(11));
+        assert!(!every.is_expired(100));
+    }
+}
@@ -0,0 +1,96 @@
+//! Contract state snapshots, for migrating state into a new deployment and
+//! for debugging. A snapshot lists every key-value pair of a contract's
+//! storage with a [`state_root`] committing to all of them, so whoever
+//! imports it, or audits the migration later, can check nothing was changed
+//! or dropped on the way.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use blake3::Hasher;
+use dxid_core::Address;
+use serde::{Deserialize, Serialize};
+
+/// A contract's full storage as of a block height.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StateSnapshot {
+    pub contract: Address,
+    pub height: u64,
+    /// Code the contract ran when the snapshot was taken; `None` for built-in contracts.
+    pub code_hash: Option<[u8; 32]>,
+    /// Storage entries in key order.
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+    /// [`state_root`] of `entries`.
+    pub state_root: [u8; 32],
+}
+
+/// Commitment to a contract's storage: a hash over every entry in key order,
+/// each length-prefixed so no two storages hash alike.
+pub fn state_root(storage: &BTreeMap<Vec<u8>, Vec<u8>>) -> [u8; 32] {
+    let mut hasher = Hasher::new();
+    hasher.update(b"dxid/contract-state");
+    hasher.update(&(storage.len() as u64).to_le_bytes());
+    for (key, value) in storage {
+        hasher.update(&(key.len() as u64).to_le_bytes());
+        hasher.update(key);
+        hasher.update(&(value.len() as u64).to_le_bytes());
+        hasher.update(value);
+    }
+    hasher.finalize().into()
+}
+
+impl StateSnapshot {
+    pub fn new(
+        contract: Address,
+        height: u64,
+        code_hash: Option<[u8; 32]>,
+        storage: &BTreeMap<Vec<u8>, Vec<u8>>,
+    ) -> Self {
+        Self {
+            contract,
+            height,
+            code_hash,
+            entries: storage.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            state_root: state_root(storage),
+        }
+    }
+
+    /// The entries as storage, after checking them against the state root.
+    pub fn verified_storage(&self) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+        let storage: BTreeMap<_, _> = self.entries.iter().cloned().collect();
+        if storage.len() != self.entries.len() {
+            return Err(anyhow!("snapshot repeats a key"));
+        }
+        if state_root(&storage) != self.state_root {
+            return Err(anyhow!("snapshot entries do not match its state root"));
+        }
+        Ok(storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_commit_to_every_entry() {
+        let storage: BTreeMap<Vec<u8>, Vec<u8>> =
+            [(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), vec![])].into();
+        let snapshot = StateSnapshot::new([1u8; 32], 7, None, &storage);
+        assert_eq!(snapshot.verified_storage().unwrap(), storage);
+        assert_ne!(state_root(&storage), state_root(&BTreeMap::new()));
+
+        let shifted: BTreeMap<Vec<u8>, Vec<u8>> = [(b"a1".to_vec(), vec![]), (b"b".to_vec(), vec![])].into();
+        assert_ne!(state_root(&storage), state_root(&shifted));
+
+        let mut tampered = snapshot.clone();
+        tampered.entries[0].1 = b"2".to_vec();
+        assert!(tampered.verified_storage().is_err());
+        let mut dropped = snapshot.clone();
+        dropped.entries.pop();
+        assert!(dropped.verified_storage().is_err());
+        let mut repeated = snapshot;
+        repeated.entries[1].0 = b"a".to_vec();
+        assert!(repeated.verified_storage().is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! An in-memory host for unit-testing contracts without a node. [`TestHost`]
+//! wraps a [`WasmRuntime`] with mock chain balances and a block context,
+//! moves call value and contract payouts the way the execution engine does,
+//! captures every event, and runs scheduled callbacks as blocks advance.
+//! Gas is free: calls are limited only by the runtime's per-call gas limit.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use dxid_core::contract::{
+    ContractCall, ContractCallEnv, ContractCallResult, ContractEvent, ContractRuntime,
+};
+use dxid_core::Address;
+
+use crate::abi::{AbiValue, ContractAbi};
+use crate::native::{encode_values, NativeContract};
+use crate::runtime::WasmRuntime;
+use crate::upgrade::UpgradePolicy;
+use crate::wasm::WasmEngine;
+
+pub struct TestHost {
+    runtime: WasmRuntime,
+    balances: HashMap<Address, u64>,
+    height: u64,
+    timestamp: u64,
+    seed: [u8; 32],
+    events: Vec<ContractEvent>,
+}
+
+impl TestHost {
+    /// A host at height 1 with no contracts and no balances.
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_runtime(WasmRuntime::new(WasmEngine::new()?)))
+    }
+
+    /// A host around `runtime`, e.g. one built with a gas limit or identities.
+    pub fn with_runtime(runtime: WasmRuntime) -> Self {
+        Self {
+            runtime,
+            balances: HashMap::new(),
+            height: 1,
+            timestamp: 0,
+            seed: [0u8; 32],
+            events: Vec::new(),
+        }
+    }
+
+    pub fn runtime(&self) -> &WasmRuntime {
+        &self.runtime
+    }
+
+    pub fn deploy(&mut self, address: Address, code: &[u8], abi: ContractAbi) -> Result<[u8; 32]> {
+        self.runtime.deploy(address, code, abi)
+    }
+
+    pub fn deploy_with_policy(
+        &mut self,
+        address: Address,
+        code: &[u8],
+        abi: ContractAbi,
+        policy: UpgradePolicy,
+    ) -> Result<[u8; 32]> {
+        self.runtime.deploy_with_policy(address, code, abi, policy)
+    }
+
+    pub fn deploy_native(&mut self, address: Address, contract: Arc<dyn NativeContract>) -> Result<()> {
+        self.runtime.deploy_native(address, contract)
+    }
+
+    /// Credits `amount` to `address` out of thin air.
+    pub fn fund(&mut self, address: Address, amount: u64) {
+        *self.balances.entry(address).or_insert(0) += amount;
+    }
+
+    pub fn balance(&self, address: &Address) -> u64 {
+        self.balances.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Moves to block `height` with `timestamp`, without running callbacks.
+    pub fn set_block(&mut self, height: u64, timestamp: u64) {
+        self.height = height;
+        self.timestamp = timestamp;
+    }
+
+    /// The seed later calls see from `random_seed`.
+    pub fn set_seed(&mut self, seed: [u8; 32]) {
+        self.seed = seed;
+    }
+
+    /// Moves `blocks` blocks ahead, one second apart, running the callbacks
+    /// due at each height in order. Returns each callback's method and result.
+    pub fn advance(&mut self, blocks: u64) -> Vec<(String, Result<ContractCallResult>)> {
+        let mut ran = Vec::new();
+        for _ in 0..blocks {
+            self.set_block(self.height + 1, self.timestamp + 1);
+            for scheduled in self.runtime.scheduled_calls(self.height) {
+                let call = ContractCall {
+                    contract: scheduled.contract,
+                    method: scheduled.method,
+                    input: Vec::new(),
+                };
+                let env = self.env(call.contract, &call, 0, Some(scheduled.gas_limit));
+                let result = self.runtime.call_scheduled(&env, &call).and_then(|r| self.settle(&call, r));
+                ran.push((call.method, result));
+            }
+        }
+        ran
+    }
+
+    pub fn call(&mut self, caller: Address, call: &ContractCall) -> Result<ContractCallResult> {
+        self.call_with_value(caller, call, 0)
+    }
+
+    /// Calls with `value` paid from `caller` to the contract; a failed call
+    /// returns it, as the chain does.
+    pub fn call_with_value(
+        &mut self,
+        caller: Address,
+        call: &ContractCall,
+        value: u64,
+    ) -> Result<ContractCallResult> {
+        let held = self.balance(&caller);
+        if held < value {
+            return Err(anyhow!("caller holds {held} but sends {value}"));
+        }
+        self.balances.insert(caller, held - value);
+        self.fund(call.contract, value);
+        let env = self.env(caller, call, value, None);
+        let result = self.runtime.call(&env, call).and_then(|r| self.settle(call, r));
+        if result.is_err() {
+            *self.balances.get_mut(&call.contract).expect("funded above") -= value;
+            self.fund(caller, value);
+        }
+        result
+    }
+
+    /// Calls `method` with `args` encoded against the contract's ABI and
+    /// decodes what it returns.
+    pub fn call_method(
+        &mut self,
+        caller: Address,
+        contract: Address,
+        method: &str,
+        args: &[AbiValue],
+    ) -> Result<Option<AbiValue>> {
+        let abi = self.runtime.abi(&contract).ok_or_else(|| anyhow!("no contract at address"))?;
+        let call =
+            ContractCall { contract, method: method.to_string(), input: abi.encode_call(method, args)? };
+        let result = self.call(caller, &call)?;
+        Ok(abi.decode_return(method, &result.output)?)
+    }
+
+    pub fn storage(&self, contract: &Address, key: &[u8]) -> Option<Vec<u8>> {
+        self.runtime.state(contract)?.storage.get(key).cloned()
+    }
+
+    /// Writes `key` of a contract's storage directly, to set up a test.
+    pub fn set_storage(&mut self, contract: &Address, key: &[u8], value: &[u8]) -> Result<()> {
+        self.runtime
+            .with_state(contract, |state| state.storage.insert(key.to_vec(), value.to_vec()))
+            .map(|_| ())
+            .ok_or_else(|| anyhow!("no contract at address"))
+    }
+
+    /// Every event emitted so far, oldest first.
+    pub fn events(&self) -> &[ContractEvent] {
+        &self.events
+    }
+
+    pub fn take_events(&mut self) -> Vec<ContractEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Panics unless some captured event has `topic` and data encoding `values`.
+    #[track_caller]
+    pub fn assert_emitted(&self, topic: &str, values: &[AbiValue]) {
+        let data = encode_values(values);
+        if !self.events.iter().any(|e| e.topic == topic && e.data == data) {
+            let topics: Vec<&str> = self.events.iter().map(|e| e.topic.as_str()).collect();
+            panic!("no {topic} event with {values:?}; emitted: {topics:?}");
+        }
+    }
+
+    /// Panics unless `contract` stores `value` under `key`.
+    #[track_caller]
+    pub fn assert_storage(&self, contract: &Address, key: &[u8], value: Option<&[u8]>) {
+        assert_eq!(self.storage(contract, key).as_deref(), value, "storage key {}", hex::encode(key));
+    }
+
+    fn env(
+        &self,
+        caller: Address,
+        call: &ContractCall,
+        value: u64,
+        gas_limit: Option<u64>,
+    ) -> ContractCallEnv {
+        ContractCallEnv {
+            caller,
+            block_height: self.height,
+            timestamp: self.timestamp,
+            seed: self.seed,
+            gas_limit,
+            value,
+            balance: self.balance(&call.contract),
+        }
+    }
+
+    /// Pays a successful call's transfers and keeps its events.
+    fn settle(&mut self, call: &ContractCall, result: ContractCallResult) -> Result<ContractCallResult> {
+        let total: u64 = result.transfers.iter().map(|(_, amount)| amount).sum();
+        let held = self.balance(&call.contract);
+        if total > held {
+            return Err(anyhow!("contract pays out {total} but holds {held}"));
+        }
+        self.balances.insert(call.contract, held - total);
+        for (to, amount) in &result.transfers {
+            self.fund(*to, *amount);
+        }
+        self.events.extend(result.events.iter().cloned());
+        Ok(result)
+    }
+}
+
+/// Panics unless `result` failed with an error mentioning `reason`.
+#[track_caller]
+pub fn assert_reverts<T: Debug>(result: Result<T>, reason: &str) {
+    match result {
+        Ok(value) => panic!("expected failure with {reason:?}, got {value:?}"),
+        Err(err) => assert!(err.to_string().contains(reason), "expected {reason:?}, got {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::AbiMethod;
+    use crate::token::{TokenClient, TokenContract};
+
+    const VAULT: &str = r#"
+        (module
+          (import "env" "caller" (func $caller (param i32)))
+          (import "env" "balance" (func $balance (result i64)))
+          (import "env" "transfer" (func $transfer (param i32 i64) (result i32)))
+          (import "env" "storage_get" (func $get (param i32 i32 i32 i32) (result i32)))
+          (import "env" "emit_event" (func $emit (param i32 i32 i32 i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "drained")
+          (data (i32.const 16) "open")
+          (func (export "drain")
+            (if (i32.lt_s (call $get (i32.const 16) (i32.const 4) (i32.const 64) (i32.const 8)) (i32.const 0))
+              (then unreachable))
+            (call $caller (i32.const 32))
+            (drop (call $transfer (i32.const 32) (call $balance)))
+            (call $emit (i32.const 0) (i32.const 7) (i32.const 32) (i32.const 32))))
+    "#;
+
+    #[test]
+    fn host_moves_value_and_captures_events() {
+        let (vault, user) = ([1u8; 32], [2u8; 32]);
+        let mut host = TestHost::new().unwrap();
+        let drain = AbiMethod { name: "drain".into(), args: Vec::new(), returns: None, access: None };
+        host.deploy(vault, VAULT.as_bytes(), ContractAbi { methods: vec![drain] }).unwrap();
+        host.fund(user, 10);
+        let call = ContractCall { contract: vault, method: "drain".into(), input: Vec::new() };
+
+        assert_reverts(host.call_with_value(user, &call, 4), "unreachable");
+        assert_eq!((host.balance(&user), host.balance(&vault)), (10, 0));
+        assert!(host.events().is_empty());
+
+        host.set_storage(&vault, b"open", b"yes").unwrap();
+        host.assert_storage(&vault, b"open", Some(b"yes".as_slice()));
+        host.call_with_value(user, &call, 4).unwrap();
+        assert_eq!((host.balance(&user), host.balance(&vault)), (10, 0));
+        host.assert_emitted("drained", &[AbiValue::Address(user)]);
+        assert_eq!(host.take_events().len(), 1);
+        assert_reverts(host.call_with_value(user, &call, 11), "holds 10");
+    }
+
+    #[test]
+    fn host_calls_methods_through_the_abi() {
+        let (token, minter, holder) = ([3u8; 32], [4u8; 32], [5u8; 32]);
+        let mut host = TestHost::new().unwrap();
+        host.deploy_native(token, Arc::new(TokenContract::new())).unwrap();
+        let client = TokenClient::new(token);
+        host.call(minter, &client.init("Coin", "CN", 2)).unwrap();
+        host.call(minter, &client.mint(holder, 50)).unwrap();
+        let balance = host.call_method(holder, token, "balance_of", &[AbiValue::Address(holder)]).unwrap();
+        assert_eq!(balance, Some(AbiValue::U64(50)));
+        let wrong = host.call_method(holder, token, "balance_of", &[AbiValue::U64(1)]);
+        assert_reverts(wrong, "must be Address");
+        assert_eq!(host.call_method(holder, token, "decimals", &[]).unwrap(), Some(AbiValue::U64(2)));
+        assert_eq!(host.advance(3).len(), 0);
+        assert_eq!(host.height(), 4);
+    }
+}
@@ -0,0 +1,319 @@
+//! Built-in fungible token. Deploy one instance per token with
+//! [`crate::WasmRuntime::deploy_native`]; the caller of `init` becomes its
+//! minter. [`TokenClient`] builds the calls and decodes the results.
+//!
+//! Methods:
+//!
+//! - `init(name, symbol, decimals)`, once.
+//! - `mint(to, amount)`, minter only; `burn(amount)` from the caller.
+//! - `transfer(to, amount)`, `approve(spender, amount)` and
+//!   `transfer_from(owner, to, amount)` spending an allowance.
+//! - `balance_of(owner)`, `allowance(owner, spender)`, `total_supply()`,
+//!   `name()`, `symbol()`, `decimals()`, `minter()`.
+//!
+//! Every balance change emits a `transfer` event of `(from, to, amount)`,
+//! with the zero address for mints and burns; `approve` emits `approval`
+//! of `(owner, spender, amount)`.
+
+use dxid_core::contract::{ContractCall, ContractEvent};
+use dxid_core::Address;
+
+use crate::abi::{AbiError, AbiType, AbiValue, ContractAbi};
+use crate::native::{
+    abi_method as method, arg_address as address, arg_string as string, arg_u64 as amount, encode_values,
+    get_u64, revert, set_u64, NativeContract,
+};
+use crate::wasm::{CallContext, CallOutcome, ContractError, ContractState};
+
+/// Gas of a token call that only reads.
+pub const TOKEN_READ_GAS: u64 = 1_000;
+/// Gas of a token call that writes.
+pub const TOKEN_WRITE_GAS: u64 = 5_000;
+
+const NAME_KEY: &[u8] = b"name";
+const SYMBOL_KEY: &[u8] = b"symbol";
+const DECIMALS_KEY: &[u8] = b"decimals";
+const MINTER_KEY: &[u8] = b"minter";
+const SUPPLY_KEY: &[u8] = b"supply";
+
+fn balance_key(owner: &Address) -> Vec<u8> {
+    [b"balance/".as_slice(), owner].concat()
+}
+
+fn allowance_key(owner: &Address, spender: &Address) -> Vec<u8> {
+    [b"allowance/".as_slice(), owner, spender].concat()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenContract;
+
+impl TokenContract {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl TokenContract {
+    /// Moves `amount` from `from` to `to`; the zero address mints or burns.
+    fn move_balance(
+        state: &mut ContractState,
+        ctx: &CallContext,
+        from: Address,
+        to: Address,
+        amount: u64,
+    ) -> Result<ContractEvent, ContractError> {
+        let zero = [0u8; 32];
+        let supply = get_u64(state, SUPPLY_KEY);
+        let from_balance = if from == zero { amount } else { get_u64(state, &balance_key(&from)) };
+        if amount > from_balance {
+            return Err(revert(format!("balance {from_balance} is below {amount}")));
+        }
+        let to_balance = if to == zero { 0 } else { get_u64(state, &balance_key(&to)) };
+        let new_supply = match (from == zero, to == zero) {
+            (true, _) => supply.checked_add(amount).ok_or_else(|| revert("supply overflow"))?,
+            (_, true) => supply - amount,
+            _ => supply,
+        };
+        let new_to = to_balance.checked_add(amount).ok_or_else(|| revert("balance overflow"))?;
+        if from != zero {
+            set_u64(state, balance_key(&from), from_balance - amount);
+        }
+        if to != zero {
+            set_u64(state, balance_key(&to), new_to);
+        }
+        set_u64(state, SUPPLY_KEY.to_vec(), new_supply);
+        let data = encode_values(&[AbiValue::Address(from), AbiValue::Address(to), AbiValue::U64(amount)]);
+        Ok(ContractEvent { contract: ctx.contract, topic: "transfer".into(), data })
+    }
+
+    fn string_at(state: &ContractState, key: &[u8]) -> AbiValue {
+        AbiValue::String(
+            String::from_utf8_lossy(state.storage.get(key).map(Vec::as_slice).unwrap_or(&[])).into(),
+        )
+    }
+
+    fn minter(state: &ContractState) -> Option<Address> {
+        state.storage.get(MINTER_KEY).and_then(|bytes| bytes.as_slice().try_into().ok())
+    }
+}
+
+impl NativeContract for TokenContract {
+    fn name(&self) -> &'static str {
+        "token"
+    }
+
+    fn abi(&self) -> ContractAbi {
+        use AbiType::{Address as A, String as S, U64};
+        ContractAbi {
+            methods: vec![
+                method("init", &[("name", S), ("symbol", S), ("decimals", U64)], None),
+                method("mint", &[("to", A), ("amount", U64)], None),
+                method("burn", &[("amount", U64)], None),
+                method("transfer", &[("to", A), ("amount", U64)], None),
+                method("approve", &[("spender", A), ("amount", U64)], None),
+                method("transfer_from", &[("owner", A), ("to", A), ("amount", U64)], None),
+                method("balance_of", &[("owner", A)], Some(U64)),
+                method("allowance", &[("owner", A), ("spender", A)], Some(U64)),
+                method("total_supply", &[], Some(U64)),
+                method("name", &[], Some(S)),
+                method("symbol", &[], Some(S)),
+                method("decimals", &[], Some(U64)),
+                method("minter", &[], Some(A)),
+            ],
+        }
+    }
+
+    fn call(
+        &self,
+        state: &mut ContractState,
+        ctx: &CallContext,
+        method: &str,
+        args: &[AbiValue],
+    ) -> Result<CallOutcome, ContractError> {
+        let zero = [0u8; 32];
+        let initialized = Self::minter(state).is_some();
+        let read = |value: AbiValue| CallOutcome {
+            output: encode_values(&[value]),
+            gas_used: TOKEN_READ_GAS,
+            ..CallOutcome::default()
+        };
+        if method != "init" && !initialized {
+            return Err(revert("token is not initialized"));
+        }
+        let event = match method {
+            "init" if initialized => return Err(revert("token is already initialized")),
+            "init" => {
+                state.storage.insert(NAME_KEY.to_vec(), string(&args[0]).into_bytes());
+                state.storage.insert(SYMBOL_KEY.to_vec(), string(&args[1]).into_bytes());
+                set_u64(state, DECIMALS_KEY.to_vec(), amount(&args[2]));
+                state.storage.insert(MINTER_KEY.to_vec(), ctx.caller.to_vec());
+                None
+            }
+            "mint" if Self::minter(state) != Some(ctx.caller) => {
+                return Err(revert("only the minter can mint"))
+            }
+            "mint" => Some(Self::move_balance(state, ctx, zero, address(&args[0]), amount(&args[1]))?),
+            "burn" => Some(Self::move_balance(state, ctx, ctx.caller, zero, amount(&args[0]))?),
+            "transfer" => {
+                Some(Self::move_balance(state, ctx, ctx.caller, address(&args[0]), amount(&args[1]))?)
+            }
+            "approve" => {
+                let (spender, value) = (address(&args[0]), amount(&args[1]));
+                set_u64(state, allowance_key(&ctx.caller, &spender), value);
+                let data = encode_values(&[AbiValue::Address(ctx.caller), args[0].clone(), args[1].clone()]);
+                Some(ContractEvent { contract: ctx.contract, topic: "approval".into(), data })
+            }
+            "transfer_from" => {
+                let (owner, to, value) = (address(&args[0]), address(&args[1]), amount(&args[2]));
+                let key = allowance_key(&owner, &ctx.caller);
+                let allowed = get_u64(state, &key);
+                if value > allowed {
+                    return Err(revert(format!("allowance {allowed} is below {value}")));
+                }
+                let event = Self::move_balance(state, ctx, owner, to, value)?;
+                set_u64(state, key, allowed - value);
+                Some(event)
+            }
+            "balance_of" => return Ok(read(AbiValue::U64(get_u64(state, &balance_key(&address(&args[0])))))),
+            "allowance" => {
+                let key = allowance_key(&address(&args[0]), &address(&args[1]));
+                return Ok(read(AbiValue::U64(get_u64(state, &key))));
+            }
+            "total_supply" => return Ok(read(AbiValue::U64(get_u64(state, SUPPLY_KEY)))),
+            "name" => return Ok(read(Self::string_at(state, NAME_KEY))),
+            "symbol" => return Ok(read(Self::string_at(state, SYMBOL_KEY))),
+            "decimals" => return Ok(read(AbiValue::U64(get_u64(state, DECIMALS_KEY)))),
+            "minter" => return Ok(read(AbiValue::Address(Self::minter(state).unwrap_or(zero)))),
+            other => return Err(ContractError::NoMethod(other.to_string())),
+        };
+        Ok(CallOutcome {
+            events: event.into_iter().collect(),
+            gas_used: TOKEN_WRITE_GAS,
+            ..CallOutcome::default()
+        })
+    }
+}
+
+/// Builds calls to a deployed [`TokenContract`] and decodes what it returns.
+/// Calls go on chain as the memo of a transaction from the caller.
+#[derive(Debug, Clone)]
+pub struct TokenClient {
+    pub contract: Address,
+    abi: ContractAbi,
+}
+
+impl TokenClient {
+    pub fn new(contract: Address) -> Self {
+        Self { contract, abi: TokenContract.abi() }
+    }
+
+    fn call(&self, method: &str, args: &[AbiValue]) -> ContractCall {
+        let input = self.abi.encode_call(method, args).expect("token calls match the token ABI");
+        ContractCall { contract: self.contract, method: method.to_string(), input }
+    }
+
+    pub fn init(&self, name: &str, symbol: &str, decimals: u64) -> ContractCall {
+        self.call(
+            "init",
+            &[AbiValue::String(name.into()), AbiValue::String(symbol.into()), AbiValue::U64(decimals)],
+        )
+    }
+
+    pub fn mint(&self, to: Address, amount: u64) -> ContractCall {
+        self.call("mint", &[AbiValue::Address(to), AbiValue::U64(amount)])
+    }
+
+    pub fn burn(&self, amount: u64) -> ContractCall {
+        self.call("burn", &[AbiValue::U64(amount)])
+    }
+
+    pub fn transfer(&self, to: Address, amount: u64) -> ContractCall {
+        self.call("transfer", &[AbiValue::Address(to), AbiValue::U64(amount)])
+    }
+
+    pub fn approve(&self, spender: Address, amount: u64) -> ContractCall {
+        self.call("approve", &[AbiValue::Address(spender), AbiValue::U64(amount)])
+    }
+
+    pub fn transfer_from(&self, owner: Address, to: Address, amount: u64) -> ContractCall {
+        self.call("transfer_from", &[AbiValue::Address(owner), AbiValue::Address(to), AbiValue::U64(amount)])
+    }
+
+    pub fn balance_of(&self, owner: Address) -> ContractCall {
+        self.call("balance_of", &[AbiValue::Address(owner)])
+    }
+
+    pub fn allowance(&self, owner: Address, spender: Address) -> ContractCall {
+        self.call("allowance", &[AbiValue::Address(owner), AbiValue::Address(spender)])
+    }
+
+    pub fn total_supply(&self) -> ContractCall {
+        self.call("total_supply", &[])
+    }
+
+    /// Decodes the output of a `u64` query such as `balance_of`.
+    pub fn decode_amount(&self, method: &str, output: &[u8]) -> Result<u64, AbiError> {
+        match self.abi.decode_return(method, output)? {
+            Some(AbiValue::U64(n)) => Ok(n),
+            _ => Err(AbiError::Malformed(format!("{method} does not return an amount"))),
+        }
+    }
+
+    /// Decodes a `transfer` event into `(from, to, amount)`.
+    pub fn decode_transfer(event: &ContractEvent) -> Option<(Address, Address, u64)> {
+        if event.topic != "transfer" || event.data.len() != 72 {
+            return None;
+        }
+        let from = event.data[..32].try_into().ok()?;
+        let to = event.data[32..64].try_into().ok()?;
+        Some((from, to, u64::from_le_bytes(event.data[64..].try_into().ok()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        state: &mut ContractState,
+        caller: Address,
+        call: &ContractCall,
+    ) -> Result<CallOutcome, ContractError> {
+        let token = TokenContract::new();
+        let args = token.abi().decode_call(&call.method, &call.input).unwrap();
+        let ctx = CallContext { contract: call.contract, caller, block_height: 1, ..CallContext::default() };
+        token.call(state, &ctx, &call.method, &args)
+    }
+
+    #[test]
+    fn token_mints_transfers_and_spends_allowances() {
+        let (minter, alice, bob) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+        let client = TokenClient::new([9u8; 32]);
+        let mut state = ContractState::default();
+        assert!(run(&mut state, minter, &client.mint(alice, 1)).is_err());
+        run(&mut state, minter, &client.init("Layer Coin", "LYR", 6)).unwrap();
+        assert!(run(&mut state, alice, &client.init("Other", "OTH", 0)).is_err());
+        assert!(run(&mut state, alice, &client.mint(alice, 1)).is_err());
+
+        let minted = run(&mut state, minter, &client.mint(alice, 100)).unwrap();
+        assert_eq!(TokenClient::decode_transfer(&minted.events[0]), Some(([0u8; 32], alice, 100)));
+        run(&mut state, alice, &client.transfer(bob, 30)).unwrap();
+        let before = state.clone();
+        assert!(run(&mut state, bob, &client.transfer(alice, 31)).is_err());
+        assert_eq!(state, before);
+
+        run(&mut state, alice, &client.approve(bob, 50)).unwrap();
+        assert!(run(&mut state, bob, &client.transfer_from(alice, bob, 51)).is_err());
+        run(&mut state, bob, &client.transfer_from(alice, bob, 20)).unwrap();
+        run(&mut state, bob, &client.burn(10)).unwrap();
+
+        let amount = |state: &mut ContractState, call: ContractCall| {
+            let output = run(state, alice, &call).unwrap().output;
+            client.decode_amount(&call.method, &output).unwrap()
+        };
+        assert_eq!(amount(&mut state, client.balance_of(alice)), 50);
+        assert_eq!(amount(&mut state, client.balance_of(bob)), 40);
+        assert_eq!(amount(&mut state, client.allowance(alice, bob)), 30);
+        assert_eq!(amount(&mut state, client.total_supply()), 90);
+    }
+}
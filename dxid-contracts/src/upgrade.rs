@@ -0,0 +1,68 @@
+//! Contract upgrades. A contract deployed with an [`UpgradePolicy`] other than
+//! `Immutable` can swap its code through a call to the reserved
+//! [`UPGRADE_METHOD`], so upgrades go on chain as ordinary signed
+//! transactions and land in receipts. The contract keeps its address, state
+//! and balance; the replaced code is kept as a [`CodeVersion`] for audit.
+//!
+//! An upgrade may name a `migrate` method of the new code, taking no
+//! arguments, that runs against the existing state before the swap; if it
+//! fails the upgrade does not happen.
+//!
+//! Events: `upgraded` of `(old_code_hash, new_code_hash)` as bytes, and under
+//! governance `upgrade_vote` of `(new_code_hash, votes)` until the threshold
+//! is met.
+
+use std::collections::BTreeSet;
+
+use dxid_core::contract::ContractCall;
+use dxid_core::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::abi::{AbiType, AbiValue, ContractAbi};
+use crate::native::encode_values;
+
+/// Reserved method name of upgrade calls; contract ABIs cannot use it.
+pub const UPGRADE_METHOD: &str = "__upgrade";
+
+/// Who may upgrade a contract, fixed at deployment.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpgradePolicy {
+    #[default]
+    Immutable,
+    /// The declared admin upgrades alone.
+    Admin(Address),
+    /// An upgrade applies once `threshold` distinct voters have sent the same code.
+    Governance { voters: BTreeSet<Address>, threshold: usize },
+}
+
+/// Code a contract ran before an upgrade.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeVersion {
+    pub code_hash: [u8; 32],
+    pub code: Vec<u8>,
+    pub abi: ContractAbi,
+    /// Block height of the upgrade that replaced it.
+    pub replaced_at: u64,
+}
+
+pub(crate) fn upgrade_input_types() -> [AbiType; 3] {
+    [AbiType::Bytes, AbiType::String, AbiType::String]
+}
+
+/// Builds the call that upgrades `contract` to `code` with `abi`, running
+/// `migrate` on the new code first when given.
+pub fn upgrade_call(
+    contract: Address,
+    code: Vec<u8>,
+    abi: &ContractAbi,
+    migrate: Option<&str>,
+) -> ContractCall {
+    let abi = serde_json::to_string(abi).expect("ABI serializes");
+    let input = encode_values(&[
+        AbiValue::Bytes(code),
+        AbiValue::String(abi),
+        AbiValue::String(migrate.unwrap_or_default().to_string()),
+    ]);
+    ContractCall { contract, method: UPGRADE_METHOD.to_string(), input }
+}
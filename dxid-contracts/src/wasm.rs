@@ -0,0 +1,641 @@
+//! WASM contract execution on wasmtime.
+//!
+//! A contract is a module exporting `memory` and one `() -> ()` function per
+//! method. Everything else goes through host functions imported from `env`:
+//!
+//! - `input_len() -> i32`, `read_input(ptr)`: the call's input bytes.
+//! - `set_return(ptr, len)`: the call's output bytes.
+//! - `storage_get(key_ptr, key_len, out_ptr, out_cap) -> i32`: copies up to
+//!   `out_cap` bytes of the value and returns its full length, or -1.
+//! - `storage_set(key_ptr, key_len, val_ptr, val_len)`.
+//! - `caller(out_ptr)`: the 32-byte caller address.
+//! - `block_height() -> i64`, `block_timestamp() -> i64`: the block's header values.
+//! - `random_seed(out_ptr)`: the call's 32-byte seed, derived from the previous
+//!   block hash and the transaction hash.
+//! - `balance() -> i64`: the contract balance, value sent with the call included.
+//! - `call_value() -> i64`: native tokens sent with the call.
+//! - `emit_event(topic_ptr, topic_len, data_ptr, data_len)`.
+//! - `transfer(to_ptr, amount) -> i32`: pays from the contract balance;
+//!   returns 0, or 1 if the balance is too low.
+//! - `schedule(method_ptr, method_len, at_height, interval, gas_limit) -> i32`:
+//!   asks for exported method `method` to run at block `at_height`, then
+//!   every `interval` blocks unless it is 0; returns 0, or 1 if the method is
+//!   not exported, `at_height` is not a later block or the contract has
+//!   no free schedule slot.
+//! - `precompile(name_ptr, name_len, input_ptr, input_len, out_ptr, out_cap) -> i32`:
+//!   runs the named [`Precompile`] on the input for its fixed gas cost and
+//!   copies up to `out_cap` bytes of its output to `out_ptr`; returns the
+//!   full output length, or -1 if there is no such precompile or it rejects
+//!   the input.
+//!
+//! Execution is metered with fuel (one unit per instruction, plus a charge
+//! per host call and byte moved), NaNs are canonicalized, threads and SIMD
+//! are off, and code importing anything but the host functions above (WASI,
+//! clocks, randomness, host I/O) is rejected at compile time, so a call has
+//! the same result on every node.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use dxid_core::contract::ContractEvent;
+use dxid_core::Address;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use wasmtime::{
+    Caller, Config, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+};
+
+use crate::precompile::{default_precompiles, Precompile, Precompiles};
+
+/// Gas charged for every host call, on top of the per-byte charge.
+pub const HOST_CALL_GAS: u64 = 100;
+/// Gas charged per byte read from or written to contract storage and events.
+pub const BYTE_GAS: u64 = 1;
+/// Largest input, output, key, value or event a call may move across the host boundary.
+pub const MAX_IO_BYTES: usize = 64 * 1024;
+/// Functions contracts may import from `env`; see the module docs.
+pub const HOST_FUNCTIONS: &[&str] = &[
+    "input_len",
+    "read_input",
+    "set_return",
+    "storage_get",
+    "storage_set",
+    "caller",
+    "block_height",
+    "block_timestamp",
+    "random_seed",
+    "balance",
+    "call_value",
+    "emit_event",
+    "transfer",
+    "schedule",
+    "precompile",
+];
+/// Linear memory limit of a contract instance.
+pub const MAX_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ContractError {
+    #[error("invalid contract code: {0}")]
+    InvalidCode(String),
+    #[error("contract has no method {0}")]
+    NoMethod(String),
+    #[error("out of gas")]
+    OutOfGas,
+    #[error("contract trapped: {0}")]
+    Trap(String),
+    /// A native contract refused the call.
+    #[error("contract reverted: {0}")]
+    Reverted(String),
+}
+
+/// Durable state of one deployed contract.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContractState {
+    pub storage: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Native tokens held by the contract.
+    pub balance: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallContext {
+    pub contract: Address,
+    pub caller: Address,
+    pub block_height: u64,
+    pub timestamp: u64,
+    pub seed: [u8; 32],
+    /// Native tokens sent with the call, already part of the state's balance.
+    pub value: u64,
+    pub gas_limit: u64,
+    /// Callbacks the contract may still register with `schedule`.
+    pub schedule_slots: usize,
+}
+
+/// What a successful call produced. State changes are already applied to
+/// the [`ContractState`]; `transfers` are payments out of its balance that
+/// the caller must credit to the recipients.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallOutcome {
+    pub output: Vec<u8>,
+    pub events: Vec<ContractEvent>,
+    pub transfers: Vec<(Address, u64)>,
+    /// Callbacks the contract asked to be run in later blocks.
+    pub schedules: Vec<ScheduleRequest>,
+    pub gas_used: u64,
+}
+
+/// A callback a contract registered with the `schedule` host function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleRequest {
+    pub method: String,
+    pub at_height: u64,
+    /// Blocks between runs; 0 runs once.
+    pub interval: u64,
+    pub gas_limit: u64,
+}
+
+/// Compiled contract code.
+#[derive(Clone)]
+pub struct WasmContract {
+    module: Module,
+    code_hash: [u8; 32],
+}
+
+impl WasmContract {
+    pub fn code_hash(&self) -> [u8; 32] {
+        self.code_hash
+    }
+
+    /// Whether the code exports `name` as a callable `() -> ()` method.
+    pub fn has_method(&self, name: &str) -> bool {
+        match self.module.get_export(name) {
+            Some(wasmtime::ExternType::Func(ty)) => ty.params().len() == 0 && ty.results().len() == 0,
+            _ => false,
+        }
+    }
+}
+
+struct HostState {
+    ctx: CallContext,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    storage: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Previous value of every key written, oldest first, to undo a failed call.
+    journal: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    balance: u64,
+    events: Vec<ContractEvent>,
+    transfers: Vec<(Address, u64)>,
+    schedules: Vec<ScheduleRequest>,
+    precompiles: Arc<Precompiles>,
+    limits: StoreLimits,
+}
+
+pub struct WasmEngine {
+    engine: Engine,
+    linker: Linker<HostState>,
+    precompiles: Arc<Precompiles>,
+}
+
+impl WasmEngine {
+    pub fn new() -> anyhow::Result<Self> {
+        let mut config = Config::new();
+        config
+            .consume_fuel(true)
+            .cranelift_nan_canonicalization(true)
+            .wasm_threads(false)
+            .wasm_simd(false)
+            .wasm_relaxed_simd(false);
+        let engine = Engine::new(&config)?;
+        let linker = host_linker(&engine)?;
+        Ok(Self { engine, linker, precompiles: Arc::new(default_precompiles()) })
+    }
+
+    /// Makes `precompile` available to contracts, replacing any of the same name.
+    pub fn with_precompile(mut self, precompile: Arc<dyn Precompile>) -> Self {
+        Arc::make_mut(&mut self.precompiles).insert(precompile.name(), precompile);
+        self
+    }
+
+    /// Validates and compiles `code`, binary WASM or its text format.
+    pub fn compile(&self, code: &[u8]) -> Result<WasmContract, ContractError> {
+        let module =
+            Module::new(&self.engine, code).map_err(|e| ContractError::InvalidCode(e.to_string()))?;
+        if !matches!(module.get_export("memory"), Some(wasmtime::ExternType::Memory(_))) {
+            return Err(ContractError::InvalidCode("module does not export memory".into()));
+        }
+        let host_function = |import: &wasmtime::ImportType<'_>| {
+            import.module() == "env"
+                && HOST_FUNCTIONS.contains(&import.name())
+                && matches!(import.ty(), wasmtime::ExternType::Func(_))
+        };
+        if let Some(import) = module.imports().find(|import| !host_function(import)) {
+            return Err(ContractError::InvalidCode(format!(
+                "imports {}::{}, which is not a host function",
+                import.module(),
+                import.name()
+            )));
+        }
+        Ok(WasmContract { module, code_hash: blake3::hash(code).into() })
+    }
+
+    /// Runs `method` of `contract` against `state`. On error the state is
+    /// left as it was.
+    pub fn call(
+        &self,
+        contract: &WasmContract,
+        state: &mut ContractState,
+        ctx: &CallContext,
+        method: &str,
+        input: &[u8],
+    ) -> Result<CallOutcome, ContractError> {
+        if input.len() > MAX_IO_BYTES {
+            return Err(ContractError::Trap(format!("input of {} bytes is too large", input.len())));
+        }
+        let host = HostState {
+            ctx: ctx.clone(),
+            input: input.to_vec(),
+            output: Vec::new(),
+            storage: std::mem::take(&mut state.storage),
+            journal: Vec::new(),
+            balance: state.balance,
+            events: Vec::new(),
+            transfers: Vec::new(),
+            schedules: Vec::new(),
+            precompiles: self.precompiles.clone(),
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).instances(1).build(),
+        };
+        let mut store = Store::new(&self.engine, host);
+        store.limiter(|host| &mut host.limits);
+        let result = self.run(&mut store, contract, ctx.gas_limit, method);
+        let gas_used = store.fuel_consumed().unwrap_or(0);
+        let mut host = store.into_data();
+        if let Err(err) = result {
+            for (key, previous) in host.journal.into_iter().rev() {
+                match previous {
+                    Some(value) => host.storage.insert(key, value),
+                    None => host.storage.remove(&key),
+                };
+            }
+            state.storage = host.storage;
+            return Err(err);
+        }
+        state.storage = host.storage;
+        state.balance = host.balance;
+        Ok(CallOutcome {
+            output: host.output,
+            events: host.events,
+            transfers: host.transfers,
+            schedules: host.schedules,
+            gas_used,
+        })
+    }
+
+    fn run(
+        &self,
+        store: &mut Store<HostState>,
+        contract: &WasmContract,
+        gas_limit: u64,
+        method: &str,
+    ) -> Result<(), ContractError> {
+        store.add_fuel(gas_limit).map_err(|e| ContractError::Trap(e.to_string()))?;
+        let instance = self.linker.instantiate(&mut *store, &contract.module).map_err(trap)?;
+        let func = instance
+            .get_typed_func::<(), ()>(&mut *store, method)
+            .map_err(|_| ContractError::NoMethod(method.to_string()))?;
+        func.call(&mut *store, ()).map_err(trap)
+    }
+}
+
+fn trap(err: anyhow::Error) -> ContractError {
+    if err.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel)
+        || matches!(err.downcast_ref::<ContractError>(), Some(ContractError::OutOfGas))
+    {
+        return ContractError::OutOfGas;
+    }
+    ContractError::Trap(err.to_string())
+}
+
+fn charge(caller: &mut Caller<'_, HostState>, bytes: usize) -> anyhow::Result<()> {
+    let gas = HOST_CALL_GAS.saturating_add(BYTE_GAS.saturating_mul(bytes as u64));
+    caller.consume_fuel(gas).map(|_| ()).map_err(|_| ContractError::OutOfGas.into())
+}
+
+fn memory(caller: &mut Caller<'_, HostState>) -> anyhow::Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| anyhow!("contract does not export memory"))
+}
+
+fn read(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> anyhow::Result<Vec<u8>> {
+    let len = len as u32 as usize;
+    if len > MAX_IO_BYTES {
+        return Err(anyhow!("{len} bytes exceeds the host I/O limit"));
+    }
+    let mut buf = vec![0u8; len];
+    memory(caller)?.read(&*caller, ptr as u32 as usize, &mut buf)?;
+    Ok(buf)
+}
+
+fn write(caller: &mut Caller<'_, HostState>, ptr: i32, data: &[u8]) -> anyhow::Result<()> {
+    memory(caller)?.write(&mut *caller, ptr as u32 as usize, data)?;
+    Ok(())
+}
+
+fn host_linker(engine: &Engine) -> anyhow::Result<Linker<HostState>> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap("env", "input_len", |caller: Caller<'_, HostState>| caller.data().input.len() as i32)?;
+    linker.func_wrap("env", "read_input", |mut caller: Caller<'_, HostState>, ptr: i32| {
+        let input = caller.data().input.clone();
+        charge(&mut caller, input.len())?;
+        write(&mut caller, ptr, &input)
+    })?;
+    linker.func_wrap("env", "set_return", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+        charge(&mut caller, len as u32 as usize)?;
+        caller.data_mut().output = read(&mut caller, ptr, len)?;
+        Ok(())
+    })?;
+    linker.func_wrap(
+        "env",
+        "storage_get",
+        |mut caller: Caller<'_, HostState>, key_ptr: i32, key_len: i32, out_ptr: i32, out_cap: i32| {
+            let key = read(&mut caller, key_ptr, key_len)?;
+            let Some(value) = caller.data().storage.get(&key).cloned() else {
+                charge(&mut caller, key.len())?;
+                return Ok(-1);
+            };
+            charge(&mut caller, key.len() + value.len())?;
+            let copied = value.len().min(out_cap as u32 as usize);
+            write(&mut caller, out_ptr, &value[..copied])?;
+            Ok(value.len() as i32)
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "storage_set",
+        |mut caller: Caller<'_, HostState>, key_ptr: i32, key_len: i32, val_ptr: i32, val_len: i32| {
+            let key = read(&mut caller, key_ptr, key_len)?;
+            let value = read(&mut caller, val_ptr, val_len)?;
+            charge(&mut caller, key.len() + value.len())?;
+            let host = caller.data_mut();
+            let previous = host.storage.insert(key.clone(), value);
+            host.journal.push((key, previous));
+            Ok(())
+        },
+    )?;
+    linker.func_wrap("env", "caller", |mut caller: Caller<'_, HostState>, out_ptr: i32| {
+        charge(&mut caller, 32)?;
+        let address = caller.data().ctx.caller;
+        write(&mut caller, out_ptr, &address)
+    })?;
+    linker.func_wrap("env", "block_height", |caller: Caller<'_, HostState>| {
+        caller.data().ctx.block_height as i64
+    })?;
+    linker.func_wrap("env", "block_timestamp", |caller: Caller<'_, HostState>| {
+        caller.data().ctx.timestamp as i64
+    })?;
+    linker.func_wrap("env", "random_seed", |mut caller: Caller<'_, HostState>, out_ptr: i32| {
+        charge(&mut caller, 32)?;
+        let seed = caller.data().ctx.seed;
+        write(&mut caller, out_ptr, &seed)
+    })?;
+    linker.func_wrap("env", "balance", |caller: Caller<'_, HostState>| caller.data().balance as i64)?;
+    linker.func_wrap("env", "call_value", |caller: Caller<'_, HostState>| caller.data().ctx.value as i64)?;
+    linker.func_wrap(
+        "env",
+        "emit_event",
+        |mut caller: Caller<'_, HostState>, topic_ptr: i32, topic_len: i32, data_ptr: i32, data_len: i32| {
+            let topic = String::from_utf8(read(&mut caller, topic_ptr, topic_len)?)
+                .map_err(|_| anyhow!("event topic is not UTF-8"))?;
+            let data = read(&mut caller, data_ptr, data_len)?;
+            charge(&mut caller, topic.len() + data.len())?;
+            let contract = caller.data().ctx.contract;
+            caller.data_mut().events.push(ContractEvent { contract, topic, data });
+            Ok(())
+        },
+    )?;
+    linker.func_wrap("env", "transfer", |mut caller: Caller<'_, HostState>, to_ptr: i32, amount: i64| {
+        charge(&mut caller, 32)?;
+        let to: Address = read(&mut caller, to_ptr, 32)?.try_into().expect("read 32 bytes");
+        let amount = amount as u64;
+        let host = caller.data_mut();
+        if amount > host.balance {
+            return Ok(1);
+        }
+        host.balance -= amount;
+        host.transfers.push((to, amount));
+        Ok(0)
+    })?;
+    linker.func_wrap(
+        "env",
+        "schedule",
+        |mut caller: Caller<'_, HostState>,
+         method_ptr: i32,
+         method_len: i32,
+         at_height: i64,
+         interval: i64,
+         gas_limit: i64| {
+            charge(&mut caller, method_len as u32 as usize)?;
+            let method = String::from_utf8(read(&mut caller, method_ptr, method_len)?)
+                .map_err(|_| anyhow!("method name is not UTF-8"))?;
+            let exported = match caller.get_export(&method) {
+                Some(wasmtime::Extern::Func(func)) => {
+                    let ty = func.ty(&caller);
+                    ty.params().len() == 0 && ty.results().len() == 0
+                }
+                _ => false,
+            };
+            let at_height = at_height as u64;
+            let host = caller.data();
+            let full = host.schedules.len() >= host.ctx.schedule_slots;
+            if !exported || full || at_height <= host.ctx.block_height {
+                return Ok(1);
+            }
+            let request =
+                ScheduleRequest { method, at_height, interval: interval as u64, gas_limit: gas_limit as u64 };
+            caller.data_mut().schedules.push(request);
+            Ok(0)
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "precompile",
+        |mut caller: Caller<'_, HostState>,
+         name_ptr: i32,
+         name_len: i32,
+         input_ptr: i32,
+         input_len: i32,
+         out_ptr: i32,
+         out_cap: i32| {
+            let name = read(&mut caller, name_ptr, name_len)?;
+            let input = read(&mut caller, input_ptr, input_len)?;
+            charge(&mut caller, name.len() + input.len())?;
+            let found = std::str::from_utf8(&name).ok().and_then(|name| caller.data().precompiles.get(name));
+            let Some(precompile) = found.cloned() else {
+                return Ok(-1);
+            };
+            caller.consume_fuel(precompile.gas(&input)).map_err(|_| ContractError::OutOfGas)?;
+            let Ok(output) = precompile.run(&input) else {
+                return Ok(-1);
+            };
+            charge(&mut caller, output.len())?;
+            let copied = output.len().min(out_cap as u32 as usize);
+            write(&mut caller, out_ptr, &output[..copied])?;
+            Ok(output.len() as i32)
+        },
+    )?;
+    Ok(linker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER: &str = r#"
+        (module
+          (import "env" "storage_get" (func $get (param i32 i32 i32 i32) (result i32)))
+          (import "env" "storage_set" (func $set (param i32 i32 i32 i32)))
+          (import "env" "emit_event" (func $emit (param i32 i32 i32 i32)))
+          (import "env" "set_return" (func $ret (param i32 i32)))
+          (import "env" "input_len" (func $input_len (result i32)))
+          (import "env" "read_input" (func $read_input (param i32)))
+          (import "env" "caller" (func $caller (param i32)))
+          (import "env" "transfer" (func $transfer (param i32 i64) (result i32)))
+          (import "env" "block_height" (func $height (result i64)))
+          (import "env" "balance" (func $balance (result i64)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "count")
+          (data (i32.const 16) "incremented")
+          (func $bump
+            (i64.store (i32.const 32) (i64.const 0))
+            (drop (call $get (i32.const 0) (i32.const 5) (i32.const 32) (i32.const 8)))
+            (i64.store (i32.const 32) (i64.add (i64.load (i32.const 32)) (i64.const 1)))
+            (call $set (i32.const 0) (i32.const 5) (i32.const 32) (i32.const 8))
+            (call $emit (i32.const 16) (i32.const 11) (i32.const 32) (i32.const 8))
+            (call $ret (i32.const 32) (i32.const 8)))
+          (func (export "increment") (call $bump))
+          (func (export "bump_and_pay")
+            (call $bump)
+            (call $caller (i32.const 64))
+            (if (call $transfer (i32.const 64) (i64.const 100)) (then unreachable)))
+          (func (export "echo")
+            (call $read_input (i32.const 128))
+            (call $ret (i32.const 128) (call $input_len)))
+          (func (export "height")
+            (i64.store (i32.const 32) (call $height))
+            (call $ret (i32.const 32) (i32.const 8)))
+          (func (export "balance")
+            (i64.store (i32.const 32) (call $balance))
+            (call $ret (i32.const 32) (i32.const 8)))
+          (func (export "spin") (loop $l (br $l))))
+    "#;
+
+    const SANDBOXED: &str = r#"
+        (module
+          (import "env" "storage_set" (func $set (param i32 i32 i32 i32)))
+          (import "env" "block_timestamp" (func $time (result i64)))
+          (import "env" "random_seed" (func $seed (param i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "nan")
+          (data (i32.const 8) "time")
+          (data (i32.const 16) "seed")
+          (func (export "run")
+            (f32.store (i32.const 64) (f32.div (f32.load (i32.const 128)) (f32.load (i32.const 128))))
+            (call $set (i32.const 0) (i32.const 3) (i32.const 64) (i32.const 4))
+            (i64.store (i32.const 64) (call $time))
+            (call $set (i32.const 8) (i32.const 4) (i32.const 64) (i32.const 8))
+            (call $seed (i32.const 64))
+            (call $set (i32.const 16) (i32.const 4) (i32.const 64) (i32.const 32))))
+    "#;
+
+    fn ctx() -> CallContext {
+        CallContext {
+            contract: [1u8; 32],
+            caller: [2u8; 32],
+            block_height: 42,
+            timestamp: 1_700_000_000,
+            seed: [3u8; 32],
+            gas_limit: 100_000,
+            ..CallContext::default()
+        }
+    }
+
+    #[test]
+    fn runs_metered_calls_and_reverts_failures() {
+        let engine = WasmEngine::new().unwrap();
+        let contract = engine.compile(COUNTER.as_bytes()).unwrap();
+        let mut state = ContractState::default();
+
+        engine.call(&contract, &mut state, &ctx(), "increment", &[]).unwrap();
+        let mut replica = state.clone();
+        let second = engine.call(&contract, &mut state, &ctx(), "increment", &[]).unwrap();
+        assert_eq!(engine.call(&contract, &mut replica, &ctx(), "increment", &[]).unwrap(), second);
+        assert!(second.gas_used > 0);
+        assert_eq!(second.output, 2u64.to_le_bytes());
+        assert_eq!(second.events[0].topic, "incremented");
+        assert_eq!(engine.call(&contract, &mut state, &ctx(), "echo", b"hi").unwrap().output, b"hi");
+        assert_eq!(
+            engine.call(&contract, &mut state, &ctx(), "height", &[]).unwrap().output,
+            42u64.to_le_bytes()
+        );
+
+        let before = state.clone();
+        assert!(matches!(
+            engine.call(&contract, &mut state, &ctx(), "bump_and_pay", &[]),
+            Err(ContractError::Trap(_))
+        ));
+        assert_eq!(state, before);
+        state.balance = 150;
+        let paid = engine.call(&contract, &mut state, &ctx(), "bump_and_pay", &[]).unwrap();
+        assert_eq!((paid.transfers, state.balance), (vec![([2u8; 32], 100)], 50));
+        assert_eq!(
+            engine.call(&contract, &mut state, &ctx(), "balance", &[]).unwrap().output,
+            50u64.to_le_bytes()
+        );
+
+        assert!(matches!(
+            engine.call(&contract, &mut state, &ctx(), "spin", &[]),
+            Err(ContractError::OutOfGas)
+        ));
+        assert!(matches!(
+            engine.call(&contract, &mut state, &ctx(), "nope", &[]),
+            Err(ContractError::NoMethod(_))
+        ));
+        assert!(engine.compile(b"(module)").is_err());
+    }
+
+    #[test]
+    fn separate_engines_agree_and_only_host_functions_link() {
+        let (a, b) = (WasmEngine::new().unwrap(), WasmEngine::new().unwrap());
+        let (mut state_a, mut state_b) = (ContractState::default(), ContractState::default());
+        let out_a =
+            a.call(&a.compile(SANDBOXED.as_bytes()).unwrap(), &mut state_a, &ctx(), "run", &[]).unwrap();
+        let out_b =
+            b.call(&b.compile(SANDBOXED.as_bytes()).unwrap(), &mut state_b, &ctx(), "run", &[]).unwrap();
+        assert_eq!((out_a, &state_a), (out_b, &state_b));
+        // 0/0 is the canonical NaN, whatever the hardware produces.
+        assert_eq!(state_a.storage[b"nan".as_slice()], 0x7fc0_0000u32.to_le_bytes());
+        assert_eq!(state_a.storage[b"time".as_slice()], 1_700_000_000u64.to_le_bytes());
+        assert_eq!(state_a.storage[b"seed".as_slice()], [3u8; 32]);
+
+        let wasi = r#"(module
+          (import "wasi_snapshot_preview1" "clock_time_get" (func (param i32 i64 i32) (result i32)))
+          (memory (export "memory") 1))"#;
+        assert!(matches!(a.compile(wasi.as_bytes()), Err(ContractError::InvalidCode(_))));
+        let unknown = r#"(module (import "env" "now" (func (result i64))) (memory (export "memory") 1))"#;
+        assert!(matches!(a.compile(unknown.as_bytes()), Err(ContractError::InvalidCode(_))));
+    }
+
+    #[test]
+    fn precompiles_run_for_their_fixed_gas() {
+        const HASHER: &str = r#"
+            (module
+              (import "env" "precompile" (func $pre (param i32 i32 i32 i32 i32 i32) (result i32)))
+              (import "env" "set_return" (func $ret (param i32 i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 0) "blake3")
+              (data (i32.const 8) "sha1")
+              (data (i32.const 16) "abc")
+              (func (export "hash")
+                (call $ret (i32.const 32) (call $pre (i32.const 0) (i32.const 6) (i32.const 16) (i32.const 3)
+                  (i32.const 32) (i32.const 32))))
+              (func (export "unknown")
+                (if (i32.ne (call $pre (i32.const 8) (i32.const 4) (i32.const 16) (i32.const 3)
+                  (i32.const 32) (i32.const 32)) (i32.const -1)) (then unreachable))))
+        "#;
+        let engine = WasmEngine::new().unwrap();
+        let contract = engine.compile(HASHER.as_bytes()).unwrap();
+        let mut state = ContractState::default();
+        let hashed = engine.call(&contract, &mut state, &ctx(), "hash", &[]).unwrap();
+        assert_eq!(hashed.output, blake3::hash(b"abc").as_bytes());
+        assert!(hashed.gas_used > crate::precompile::BLAKE3_GAS);
+        engine.call(&contract, &mut state, &ctx(), "unknown", &[]).unwrap();
+
+        let short = CallContext { gas_limit: crate::precompile::BLAKE3_GAS, ..ctx() };
+        assert!(matches!(
+            engine.call(&contract, &mut state, &short, "hash", &[]),
+            Err(ContractError::OutOfGas)
+        ));
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use blake3::Hasher;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    now_ts, Address, ChainId, ChainState, CrossChainMessage, Transaction, TxHash, TxInput, TxOutput,
+};
+
+/// Chain id dxid uses for itself in cross-chain messages.
+pub const DXID_CHAIN_ID: &str = "dxid";
+
+/// Memo prefix marking escrow outputs: `bridge:<dest_chain>:<recipient>`.
+pub const BRIDGE_MEMO_PREFIX: &str = "bridge:";
+
+/// Memo prefix of transactions that deliver an inbound message; the rest of
+/// the memo is the JSON-encoded [`CrossChainMessage`].
+pub const DELIVERY_MEMO_PREFIX: &str = "bridge-delivery:";
+
+/// Memo prefix of transactions that release escrowed tokens for a burn; the
+/// rest of the memo is the JSON-encoded [`ProvenMessage`] of the burn.
+pub const RELEASE_MEMO_PREFIX: &str = "bridge-release:";
+
+/// Address holding all natively locked tokens. It is not derived from a key,
+/// so escrowed outputs can only leave through a [`release_transaction`].
+pub fn bridge_escrow_address() -> Address {
+    blake3::hash(b"dxid/bridge-escrow").into()
+}
+
+/// A lock recorded on dxid that still needs a mint on the destination chain.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BridgeTransfer {
+    pub id: Uuid,
+    /// Owner of the lock transaction's first input, which signed it.
+    pub sender: Address,
+    pub dest: ChainId,
+    /// Recipient on the destination chain, in its native address format.
+    pub recipient: String,
+    pub amount: u64,
+    pub lock_tx: TxHash,
+}
+
+impl BridgeTransfer {
+    /// The mint message for this lock. Its id is the lock id, so queueing
+    /// the same lock twice cannot produce a second mint.
+    pub fn mint_message(&self) -> CrossChainMessage {
+        CrossChainMessage {
+            id: self.id,
+            source: DXID_CHAIN_ID.into(),
+            dest: self.dest.clone(),
+            payload: serde_json::json!({
+                "type": "mint",
+                "recipient": self.recipient,
+                "amount": self.amount,
+                "lock_tx": hex::encode(self.lock_tx),
+            }),
+            // Assigned by the relayer on enqueue.
+            nonce: 0,
+            timestamp: now_ts(),
+        }
+    }
+}
+
+/// A verified burn on a remote chain that releases escrowed tokens.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BridgeRelease {
+    /// Id of the burn message; each id releases at most once.
+    pub id: Uuid,
+    pub source: ChainId,
+    /// Source-chain nonce of the burn; each `(source, nonce)` releases at most once.
+    pub nonce: u64,
+    pub recipient: Address,
+    pub amount: u64,
+}
+
+/// An inbound message with the proof its source chain attached, carried by
+/// the transactions that act on it so every node can check it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProvenMessage {
+    pub message: CrossChainMessage,
+    /// The source backend's proof, in its own encoding.
+    pub proof: serde_json::Value,
+}
+
+/// Checks inbound messages for the [`crate::ExecutionEngine`]; the interop
+/// layer implements it with each source chain's proof backend.
+pub trait InboundVerifier: Send + Sync {
+    /// Checks that `msg` left its source chain for dxid under `proof`, and
+    /// returns the release it authorises if it is a burn.
+    fn verify_inbound(
+        &self,
+        msg: &CrossChainMessage,
+        proof: &serde_json::Value,
+    ) -> Result<Option<BridgeRelease>>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BridgeLedger {
+    /// Native tokens currently minted on each remote chain.
+    pub escrowed: HashMap<ChainId, u64>,
+    pub processed: HashSet<Uuid>,
+    /// `(source, nonce)` of every burn released so far.
+    pub released_nonces: HashSet<(ChainId, u64)>,
+    /// `(source, nonce)` of every other inbound message delivered so far.
+    pub delivered: HashSet<(ChainId, u64)>,
+    /// Locks awaiting relay with the height of the block that made each,
+    /// oldest first.
+    pub outbox: Vec<(u64, BridgeTransfer)>,
+}
+
+impl BridgeLedger {
+    pub fn outstanding(&self, chain: &str) -> u64 {
+        self.escrowed.get(chain).copied().unwrap_or(0)
+    }
+
+    pub fn total_escrowed(&self) -> u64 {
+        self.escrowed.values().sum()
+    }
+
+    /// Records `transfer`, locked by a transaction of the block at `height`.
+    pub fn lock(&mut self, transfer: BridgeTransfer, height: u64) -> Result<()> {
+        if !self.processed.insert(transfer.id) {
+            return Err(anyhow!("bridge transfer {} already locked", transfer.id));
+        }
+        let entry = self.escrowed.entry(transfer.dest.clone()).or_insert(0);
+        *entry = entry.checked_add(transfer.amount).ok_or_else(|| anyhow!("escrow overflow"))?;
+        self.outbox.push((height, transfer));
+        Ok(())
+    }
+
+    pub fn release(&mut self, release: &BridgeRelease) -> Result<()> {
+        if self.processed.contains(&release.id) {
+            return Err(anyhow!("bridge release {} already processed", release.id));
+        }
+        if self.released_nonces.contains(&(release.source.clone(), release.nonce)) {
+            return Err(anyhow!("nonce {} from {} already released", release.nonce, release.source));
+        }
+        let outstanding = self.outstanding(&release.source);
+        if release.amount > outstanding {
+            return Err(anyhow!(
+                "release of {} exceeds {} outstanding on {}",
+                release.amount,
+                outstanding,
+                release.source
+            ));
+        }
+        self.escrowed.insert(release.source.clone(), outstanding - release.amount);
+        self.processed.insert(release.id);
+        self.released_nonces.insert((release.source.clone(), release.nonce));
+        Ok(())
+    }
+
+    /// Records the delivery of `msg`, which must be its first.
+    pub fn deliver(&mut self, msg: &CrossChainMessage) -> Result<()> {
+        if !self.delivered.insert((msg.source.clone(), msg.nonce)) {
+            return Err(anyhow!("nonce {} from {} already delivered", msg.nonce, msg.source));
+        }
+        Ok(())
+    }
+
+    /// Takes the locks of blocks up to `height`, which can no longer be
+    /// reverted. Later ones stay queued, since a reorg may still drop them.
+    pub fn take_final(&mut self, height: u64) -> Vec<(u64, BridgeTransfer)> {
+        let count = self.outbox.iter().take_while(|(locked, _)| *locked <= height).count();
+        self.outbox.drain(..count).collect()
+    }
+}
+
+/// Parses an escrow memo into `(dest_chain, recipient)`.
+pub fn parse_bridge_memo(memo: &str) -> Option<(ChainId, String)> {
+    let rest = memo.strip_prefix(BRIDGE_MEMO_PREFIX)?;
+    let (chain, recipient) = rest.split_once(':')?;
+    if chain.is_empty() || recipient.is_empty() {
+        return None;
+    }
+    Some((chain.to_string(), recipient.to_string()))
+}
+
+/// Id of the lock created by output `index` of `tx_hash`, stable so the
+/// destination can reject a second mint for the same lock.
+pub fn lock_id(tx_hash: &TxHash, index: u32) -> Uuid {
+    let mut hasher = Hasher::new();
+    hasher.update(b"dxid/bridge-lock");
+    hasher.update(tx_hash);
+    hasher.update(&index.to_le_bytes());
+    let digest: [u8; 32] = hasher.finalize().into();
+    Uuid::from_bytes(digest[..16].try_into().unwrap())
+}
+
+/// Wraps a proven inbound message that is not a burn in a transaction with
+/// no inputs or outputs, which records it on chain once.
+pub fn delivery_transaction(proven: &ProvenMessage) -> Transaction {
+    Transaction {
+        inputs: vec![],
+        outputs: vec![],
+        fee: 0,
+        nonce: inbound_nonce(&proven.message),
+        memo: Some(format!("{DELIVERY_MEMO_PREFIX}{}", serde_json::to_string(proven).unwrap())),
+        lock_time: 0,
+    }
+}
+
+/// The proven message a delivery transaction carries, if it is one.
+pub fn parse_delivery(tx: &Transaction) -> Option<ProvenMessage> {
+    let body = tx.memo.as_deref()?.strip_prefix(DELIVERY_MEMO_PREFIX)?;
+    serde_json::from_str(body).ok()
+}
+
+/// Nonce of a transaction carrying `msg`, one per `(source, nonce)`, so the
+/// mempool holds a single transaction for each inbound message.
+fn inbound_nonce(msg: &CrossChainMessage) -> u64 {
+    let mut hasher = Hasher::new();
+    hasher.update(b"dxid/bridge-inbound");
+    hasher.update(msg.source.as_bytes());
+    hasher.update(&msg.nonce.to_le_bytes());
+    u64::from_le_bytes(hasher.finalize().as_bytes()[..8].try_into().unwrap())
+}
+
+/// Escrow outputs the next block may spend, as `(tx, index, amount)` in
+/// hash order, so every node picks the same ones.
+pub fn escrow_outputs(state: &ChainState) -> Vec<(TxHash, u32, u64)> {
+    let escrow = bridge_escrow_address();
+    let mut outputs: Vec<_> = state
+        .pending_utxos
+        .iter()
+        .flat_map(|(hash, outputs)| {
+            outputs.iter().enumerate().filter_map(move |(index, output)| {
+                let spendable = output.check_spendable(state.next_height()).is_ok();
+                (output.address == escrow && output.amount > 0 && spendable).then_some((
+                    *hash,
+                    index as u32,
+                    output.amount,
+                ))
+            })
+        })
+        .collect();
+    outputs.sort_unstable();
+    outputs
+}
+
+/// The transaction releasing `release`, authorised by the burn in `proven`:
+/// it spends escrow outputs of `state`, pays the recipient and returns the
+/// rest to the escrow, without a fee.
+pub fn release_transaction(
+    state: &ChainState,
+    proven: &ProvenMessage,
+    release: &BridgeRelease,
+) -> Result<Transaction> {
+    let mut inputs = Vec::new();
+    let mut total = 0u64;
+    for (previous_tx, output_index, amount) in escrow_outputs(state) {
+        if total >= release.amount {
+            break;
+        }
+        let (signature, public_key, witness) = (Vec::new(), Vec::new(), Vec::new());
+        inputs.push(TxInput { previous_tx, output_index, signature, public_key, witness });
+        total = total.checked_add(amount).ok_or_else(|| anyhow!("escrow overflow"))?;
+    }
+    if total < release.amount {
+        return Err(anyhow!("the escrow holds {total}, less than the {} to release", release.amount));
+    }
+    let pay = |address, amount| TxOutput { address, amount, condition: None, spendable_after_height: None };
+    let mut outputs = vec![pay(release.recipient, release.amount)];
+    if total > release.amount {
+        outputs.push(pay(bridge_escrow_address(), total - release.amount));
+    }
+    Ok(Transaction {
+        inputs,
+        outputs,
+        fee: 0,
+        nonce: inbound_nonce(&proven.message),
+        memo: Some(format!("{RELEASE_MEMO_PREFIX}{}", serde_json::to_string(proven)?)),
+        lock_time: 0,
+    })
+}
+
+/// The proven burn a release transaction carries in its memo, if it is one.
+pub fn parse_release(tx: &Transaction) -> Option<ProvenMessage> {
+    let body = tx.memo.as_deref()?.strip_prefix(RELEASE_MEMO_PREFIX)?;
+    serde_json::from_str(body).ok()
+}
+
+/// Checks that a release transaction spending `inputs` of escrow pays
+/// `release` to its recipient, returns the rest to the escrow and pays no fee.
+pub(crate) fn check_release(tx: &Transaction, release: &BridgeRelease, inputs: u64) -> Result<()> {
+    let (paid, change) = tx.outputs.split_first().ok_or_else(|| anyhow!("release pays no one"))?;
+    let plain = |output: &TxOutput| output.condition.is_none() && output.spendable_after_height.is_none();
+    if paid.address != release.recipient || paid.amount != release.amount || !plain(paid) {
+        return Err(anyhow!("release does not pay {} to the burn's recipient", release.amount));
+    }
+    let escrow = bridge_escrow_address();
+    if change.len() > 1 || change.iter().any(|output| output.address != escrow || !plain(output)) {
+        return Err(anyhow!("release change must go back to the escrow"));
+    }
+    let returned = change.first().map_or(0, |output| output.amount);
+    if tx.fee != 0 || release.amount.checked_add(returned) != Some(inputs) {
+        return Err(anyhow!("release must move exactly the {inputs} it spends, without a fee"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_is_bounded_and_single_use() {
+        let mut ledger = BridgeLedger::default();
+        let lock = BridgeTransfer {
+            id: lock_id(&[1u8; 32], 0),
+            sender: [3u8; 32],
+            dest: "eth".into(),
+            recipient: "0xabc".into(),
+            amount: 100,
+            lock_tx: [1u8; 32],
+        };
+        ledger.lock(lock.clone(), 1).unwrap();
+        assert!(ledger.lock(lock, 1).is_err());
+
+        let release = BridgeRelease {
+            id: Uuid::new_v4(),
+            source: "eth".into(),
+            nonce: 1,
+            recipient: [2u8; 32],
+            amount: 60,
+        };
+        ledger.release(&release).unwrap();
+        assert_eq!(ledger.outstanding("eth"), 40);
+        assert!(ledger.release(&release).is_err());
+        let replayed = BridgeRelease { id: Uuid::new_v4(), amount: 1, ..release.clone() };
+        assert!(ledger.release(&replayed).is_err());
+
+        let too_much = BridgeRelease { id: Uuid::new_v4(), nonce: 2, amount: 41, ..release };
+        assert!(ledger.release(&too_much).is_err());
+        assert_eq!(ledger.outstanding("eth"), 40);
+    }
+
+    #[test]
+    fn parses_memo() {
+        assert_eq!(parse_bridge_memo("bridge:eth:0xabc"), Some(("eth".into(), "0xabc".into())));
+        assert_eq!(parse_bridge_memo("bridge:eth:"), None);
+        assert_eq!(parse_bridge_memo("hello"), None);
+    }
+}
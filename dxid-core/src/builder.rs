@@ -0,0 +1,492 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{signing_message, Address, CryptoProvider, Transaction, TxHash, TxInput, TxOutput};
+
+/// An unspent output, as reported by a node for the address that owns it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Utxo {
+    pub tx_hash: TxHash,
+    pub index: u32,
+    pub address: Address,
+    pub amount: u64,
+}
+
+/// An unsigned transaction with the coins it spends and what it pays in fees.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuiltTx {
+    pub tx: Transaction,
+    pub spent: Vec<Utxo>,
+    pub fee: u64,
+    /// Amount returned to the change address; 0 when no change output was added.
+    pub change: u64,
+}
+
+/// What a coin selection has to pay for.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionTarget {
+    /// Sum of the payment outputs.
+    pub amount: u64,
+    pub fee_rate: u64,
+    pub outputs: usize,
+    pub memo_len: usize,
+}
+
+impl SelectionTarget {
+    /// Fee for a transaction spending `inputs` coins, with or without a change output.
+    pub fn fee(&self, inputs: usize, change: bool) -> u64 {
+        let outputs = self.outputs + change as usize;
+        self.fee_rate.saturating_mul(estimate_size(inputs, outputs, self.memo_len) as u64)
+    }
+
+    /// What `inputs` coins must add up to when no change is returned.
+    pub fn needed(&self, inputs: usize) -> u64 {
+        self.amount.saturating_add(self.fee(inputs, false))
+    }
+
+    /// Change at or below this is left to the fee rather than becoming an output.
+    pub fn dust(&self) -> u64 {
+        self.fee_rate.saturating_mul(estimate_size(1, 0, 0) as u64)
+    }
+}
+
+/// Chooses which coins pay for a transaction. Returns `None` when `utxos`
+/// cannot cover `target`.
+pub trait CoinSelector: fmt::Debug + Send + Sync {
+    fn select(&self, utxos: &[Utxo], target: &SelectionTarget) -> Option<Vec<Utxo>>;
+}
+
+/// Takes the largest coins until the payments, fee and a change output are covered.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFirst;
+
+impl CoinSelector for LargestFirst {
+    fn select(&self, utxos: &[Utxo], target: &SelectionTarget) -> Option<Vec<Utxo>> {
+        let mut sorted = utxos.to_vec();
+        sorted.sort_by_key(|utxo| Reverse(utxo.amount));
+        let mut selected = Vec::new();
+        let mut total = 0u64;
+        for utxo in sorted {
+            if total >= target.amount.saturating_add(target.fee(selected.len(), true)) {
+                break;
+            }
+            total = total.checked_add(utxo.amount)?;
+            selected.push(utxo);
+        }
+        (!selected.is_empty() && total >= target.needed(selected.len())).then_some(selected)
+    }
+}
+
+/// Searches for a set of coins that pays the target with no change output,
+/// falling back to [`LargestFirst`] when none exists within `max_tries`.
+#[derive(Debug, Clone, Copy)]
+pub struct BranchAndBound {
+    pub max_tries: usize,
+}
+
+impl Default for BranchAndBound {
+    fn default() -> Self {
+        Self { max_tries: 100_000 }
+    }
+}
+
+impl CoinSelector for BranchAndBound {
+    fn select(&self, utxos: &[Utxo], target: &SelectionTarget) -> Option<Vec<Utxo>> {
+        // Each extra input costs the same, so compare coins by what they add
+        // after paying for themselves and look for a sum inside the window
+        // where any leftover would be folded into the fee anyway.
+        let per_input = target.fee(2, false).saturating_sub(target.fee(1, false));
+        let base = target.needed(1).saturating_sub(per_input);
+        let window = target.fee(1, true) - target.fee(1, false) + target.dust();
+        let mut coins: Vec<&Utxo> = utxos.iter().filter(|u| u.amount > per_input).collect();
+        coins.sort_by_key(|utxo| Reverse(utxo.amount));
+        let values: Vec<u64> = coins.iter().map(|u| u.amount - per_input).collect();
+        let mut remaining: Vec<u64> = values
+            .iter()
+            .rev()
+            .scan(0u64, |acc, v| {
+                *acc = acc.saturating_add(*v);
+                Some(*acc)
+            })
+            .collect();
+        remaining.reverse();
+
+        let mut search = Search { values: &values, remaining: &remaining, tries: self.max_tries, best: None };
+        search.run(0, 0, &mut Vec::new(), base, base.saturating_add(window));
+        match search.best {
+            Some((_, picked)) => Some(picked.into_iter().map(|i| coins[i].clone()).collect()),
+            None => LargestFirst.select(utxos, target),
+        }
+    }
+}
+
+struct Search<'a> {
+    values: &'a [u64],
+    remaining: &'a [u64],
+    tries: usize,
+    /// Excess over the target and the coins giving it.
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl Search<'_> {
+    fn run(&mut self, index: usize, sum: u64, picked: &mut Vec<usize>, low: u64, high: u64) {
+        if self.tries == 0 || sum > high || self.best.as_ref().is_some_and(|(excess, _)| *excess == 0) {
+            return;
+        }
+        self.tries -= 1;
+        if sum >= low {
+            let better = match &self.best {
+                Some((excess, _)) => sum - low < *excess,
+                None => true,
+            };
+            if better {
+                self.best = Some((sum - low, picked.clone()));
+            }
+            return;
+        }
+        if index == self.values.len() || sum.saturating_add(self.remaining[index]) < low {
+            return;
+        }
+        picked.push(index);
+        self.run(index + 1, sum.saturating_add(self.values[index]), picked, low, high);
+        picked.pop();
+        self.run(index + 1, sum, picked, low, high);
+    }
+}
+
+/// Pays from a single address when one can cover the target, so a payment
+/// does not link the wallet's addresses together; otherwise [`LargestFirst`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrivacyFirst;
+
+impl CoinSelector for PrivacyFirst {
+    fn select(&self, utxos: &[Utxo], target: &SelectionTarget) -> Option<Vec<Utxo>> {
+        let mut by_address: BTreeMap<Address, Vec<Utxo>> = BTreeMap::new();
+        for utxo in utxos {
+            by_address.entry(utxo.address).or_default().push(utxo.clone());
+        }
+        by_address
+            .values()
+            .filter_map(|coins| LargestFirst.select(coins, target))
+            .min_by_key(|picked| picked.iter().map(|u| u.amount).sum::<u64>())
+            .or_else(|| LargestFirst.select(utxos, target))
+    }
+}
+
+/// Selects coins for a set of payments and adds change.
+///
+/// Coins are chosen by a [`CoinSelector`], largest first unless another is
+/// set. The fee is `fee_rate` per byte of the signed transaction; change too
+/// small to be worth spending later is left to the fee instead of creating a
+/// dust output.
+#[derive(Debug, Clone)]
+pub struct TxBuilder {
+    utxos: Vec<Utxo>,
+    outputs: Vec<TxOutput>,
+    change: Address,
+    fee_rate: u64,
+    extra_fee: u64,
+    nonce: u64,
+    memo: Option<String>,
+    lock_time: u64,
+    selector: Arc<dyn CoinSelector>,
+}
+
+impl TxBuilder {
+    pub fn new(change: Address) -> Self {
+        Self {
+            utxos: Vec::new(),
+            outputs: Vec::new(),
+            change,
+            fee_rate: 1,
+            extra_fee: 0,
+            nonce: 0,
+            memo: None,
+            lock_time: 0,
+            selector: Arc::new(LargestFirst),
+        }
+    }
+
+    pub fn selector(mut self, selector: Arc<dyn CoinSelector>) -> Self {
+        self.selector = selector;
+        self
+    }
+
+    pub fn utxos(mut self, utxos: impl IntoIterator<Item = Utxo>) -> Self {
+        self.utxos.extend(utxos);
+        self
+    }
+
+    pub fn pay(mut self, address: Address, amount: u64) -> Self {
+        self.outputs.push(TxOutput { address, amount, condition: None, spendable_after_height: None });
+        self
+    }
+
+    pub fn fee_rate(mut self, fee_rate: u64) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    /// Fee paid on top of the per-byte fee, such as a contract call's gas.
+    pub fn extra_fee(mut self, extra_fee: u64) -> Self {
+        self.extra_fee = extra_fee;
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    /// Keeps the transaction out of blocks below `height`.
+    pub fn lock_time(mut self, height: u64) -> Self {
+        self.lock_time = height;
+        self
+    }
+
+    pub fn memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    pub fn build(self) -> Result<BuiltTx> {
+        // A memo-only transaction (e.g. an unstake) still pays its fee.
+        if self.outputs.is_empty() && self.memo.is_none() {
+            return Err(anyhow!("transaction has no recipients"));
+        }
+        let paid = self
+            .outputs
+            .iter()
+            .try_fold(0u64, |acc, out| acc.checked_add(out.amount))
+            .ok_or_else(|| anyhow!("output overflow"))?;
+        // Coins must cover the extra fee like another payment.
+        let amount = paid.checked_add(self.extra_fee).ok_or_else(|| anyhow!("output overflow"))?;
+        let target = SelectionTarget {
+            amount,
+            fee_rate: self.fee_rate,
+            outputs: self.outputs.len(),
+            memo_len: self.memo.as_ref().map_or(0, String::len),
+        };
+        let Some(selected) = self.selector.select(&self.utxos, &target) else {
+            let have = self.utxos.iter().fold(0u64, |acc, u| acc.saturating_add(u.amount));
+            return Err(anyhow!(
+                "insufficient funds: have {have}, need {}",
+                target.needed(self.utxos.len().max(1))
+            ));
+        };
+        let total = selected
+            .iter()
+            .try_fold(0u64, |acc, u| acc.checked_add(u.amount))
+            .ok_or_else(|| anyhow!("input overflow"))?;
+        if selected.is_empty() || total < target.needed(selected.len()) {
+            return Err(anyhow!("coin selection does not cover {}", target.needed(selected.len())));
+        }
+        let fee_with_change = target.fee(selected.len(), true);
+        let change = total.saturating_sub(amount).saturating_sub(fee_with_change);
+        let mut outputs = self.outputs;
+        let (fee, change) = if total >= amount.saturating_add(fee_with_change) && change > target.dust() {
+            outputs.push(TxOutput {
+                address: self.change,
+                amount: change,
+                condition: None,
+                spendable_after_height: None,
+            });
+            (fee_with_change + self.extra_fee, change)
+        } else {
+            (total - paid, 0)
+        };
+        let inputs = selected
+            .iter()
+            .map(|utxo| TxInput {
+                previous_tx: utxo.tx_hash,
+                output_index: utxo.index,
+                signature: vec![],
+                public_key: vec![],
+                witness: vec![],
+            })
+            .collect();
+        let tx = Transaction {
+            inputs,
+            outputs,
+            fee,
+            nonce: self.nonce,
+            memo: self.memo,
+            lock_time: self.lock_time,
+        };
+        Ok(BuiltTx { tx, spent: selected, fee, change })
+    }
+}
+
+/// Upper bound on the encoded size of a signed transaction of this shape.
+pub fn estimate_size(inputs: usize, outputs: usize, memo_len: usize) -> usize {
+    let input = TxInput {
+        previous_tx: [u8::MAX; 32],
+        output_index: u32::MAX,
+        signature: vec![u8::MAX; 64],
+        public_key: vec![u8::MAX; 32],
+        witness: vec![],
+    };
+    let tx = Transaction {
+        inputs: vec![input; inputs],
+        outputs: vec![
+            TxOutput {
+                address: [u8::MAX; 32],
+                amount: u64::MAX,
+                condition: None,
+                spendable_after_height: None
+            };
+            outputs
+        ],
+        fee: u64::MAX,
+        nonce: u64::MAX,
+        memo: (memo_len > 0).then(|| "x".repeat(memo_len)),
+        lock_time: u64::MAX,
+    };
+    serde_json::to_vec(&tx).map(|bytes| bytes.len()).unwrap_or(0)
+}
+
+/// Fee rate to offer for prompt inclusion: the median per-byte fee of the
+/// waiting transactions, and never below 1.
+pub fn estimate_fee_rate(pending: &[Transaction]) -> u64 {
+    let mut rates: Vec<u64> = pending
+        .iter()
+        .filter_map(|tx| {
+            let size = serde_json::to_vec(tx).ok()?.len() as u64;
+            (size > 0).then(|| tx.fee / size)
+        })
+        .collect();
+    rates.sort_unstable();
+    rates.get(rates.len() / 2).copied().unwrap_or(1).max(1)
+}
+
+/// Signs every input of `tx` with one key.
+pub fn sign_inputs<C: CryptoProvider>(
+    crypto: &C,
+    tx: &mut Transaction,
+    public_key: &[u8],
+    secret_key: &[u8],
+) -> Result<()> {
+    let keys = vec![(public_key.to_vec(), secret_key.to_vec()); tx.inputs.len()];
+    sign_inputs_with(crypto, tx, &keys)
+}
+
+/// Signs input `i` of `tx` with the `(public, secret)` pair `keys[i]`.
+pub fn sign_inputs_with<C: CryptoProvider>(
+    crypto: &C,
+    tx: &mut Transaction,
+    keys: &[(Vec<u8>, Vec<u8>)],
+) -> Result<()> {
+    if keys.len() != tx.inputs.len() {
+        return Err(anyhow!("{} keys for {} inputs", keys.len(), tx.inputs.len()));
+    }
+    for (input, (public_key, _)) in tx.inputs.iter_mut().zip(keys) {
+        input.public_key = public_key.clone();
+    }
+    let sighash = tx.signing_hash();
+    for (input, (_, secret_key)) in tx.inputs.iter_mut().zip(keys) {
+        input.signature = crypto.sign_message(secret_key, &signing_message(input, &sighash))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxo(n: u8, amount: u64) -> Utxo {
+        Utxo { tx_hash: [n; 32], index: 0, address: [1u8; 32], amount }
+    }
+
+    #[test]
+    fn selects_largest_first_and_returns_change() {
+        let built = TxBuilder::new([1u8; 32])
+            .utxos(vec![utxo(1, 5_000), utxo(2, 50_000), utxo(3, 20_000)])
+            .pay([2u8; 32], 30_000)
+            .fee_rate(2)
+            .build()
+            .unwrap();
+        assert_eq!(built.spent, vec![utxo(2, 50_000)]);
+        assert_eq!(built.fee, 2 * estimate_size(1, 2, 0) as u64);
+        assert_eq!(built.change, 50_000 - 30_000 - built.fee);
+        assert_eq!(built.tx.outputs[1].address, [1u8; 32]);
+    }
+
+    #[test]
+    fn folds_dust_change_into_the_fee() {
+        let fee = estimate_size(1, 1, 0) as u64;
+        let built = TxBuilder::new([1u8; 32])
+            .utxos(vec![utxo(1, 1_000 + fee + 5)])
+            .pay([2u8; 32], 1_000)
+            .build()
+            .unwrap();
+        assert_eq!((built.change, built.fee, built.tx.outputs.len()), (0, fee + 5, 1));
+
+        let short = TxBuilder::new([1u8; 32]).utxos(vec![utxo(1, 1_000)]).pay([2u8; 32], 1_000).build();
+        assert!(short.is_err());
+    }
+
+    #[test]
+    fn extra_fee_is_paid_on_top() {
+        let built = TxBuilder::new([1u8; 32])
+            .utxos(vec![utxo(1, 50_000)])
+            .pay([2u8; 32], 30_000)
+            .extra_fee(5_000)
+            .build()
+            .unwrap();
+        assert_eq!(built.fee, estimate_size(1, 2, 0) as u64 + 5_000);
+        assert_eq!(built.change, 50_000 - 30_000 - built.fee);
+        let short = TxBuilder::new([1u8; 32]).utxos(vec![utxo(1, 50_000)]).pay([2u8; 32], 30_000);
+        assert!(short.extra_fee(20_000).build().is_err());
+    }
+
+    #[test]
+    fn branch_and_bound_avoids_change() {
+        let fee = estimate_size(2, 1, 0) as u64;
+        let coins = vec![utxo(1, 60_000), utxo(2, 7_000), utxo(3, 3_000 + fee), utxo(4, 5_000)];
+        let built = TxBuilder::new([1u8; 32])
+            .utxos(coins.clone())
+            .pay([2u8; 32], 10_000)
+            .selector(Arc::new(BranchAndBound::default()))
+            .build()
+            .unwrap();
+        assert_eq!((built.change, built.fee), (0, fee));
+        assert_eq!(built.spent, vec![utxo(2, 7_000), utxo(3, 3_000 + fee)]);
+
+        let largest = TxBuilder::new([1u8; 32]).utxos(coins).pay([2u8; 32], 10_000).build().unwrap();
+        assert_eq!(largest.spent, vec![utxo(1, 60_000)]);
+        assert!(largest.change > 0);
+    }
+
+    #[test]
+    fn privacy_first_spends_from_one_address() {
+        let at = |n: u8, address: u8, amount: u64| Utxo {
+            tx_hash: [n; 32],
+            index: 0,
+            address: [address; 32],
+            amount,
+        };
+        let coins = vec![at(1, 1, 9_000), at(2, 2, 6_000), at(3, 2, 6_000)];
+        let built = TxBuilder::new([9u8; 32])
+            .utxos(coins)
+            .pay([5u8; 32], 10_000)
+            .selector(Arc::new(PrivacyFirst))
+            .build()
+            .unwrap();
+        assert!(built.spent.iter().all(|u| u.address == [2u8; 32]));
+    }
+
+    #[test]
+    fn fee_estimate_is_median_rate() {
+        let tx =
+            |fee| Transaction { inputs: vec![], outputs: vec![], fee, nonce: 0, memo: None, lock_time: 0 };
+        assert_eq!(estimate_fee_rate(&[]), 1);
+        let pending = [tx(100_000), tx(500_000), tx(300_000)];
+        let size = serde_json::to_vec(&pending[2]).unwrap().len() as u64;
+        assert_eq!(estimate_fee_rate(&pending), 300_000 / size);
+    }
+}
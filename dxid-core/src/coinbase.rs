@@ -0,0 +1,166 @@
+//! Coinbase transactions. Every block starts with one: it has no inputs and
+//! pays the block's reward and the fees its transactions paid as ordinary
+//! outputs, so they are spent like any other once they mature. The
+//! treasury named in the economics takes its share of both, and the shares
+//! of the validator's delegators are paid into the staking pool, where they
+//! wait to be withdrawn.
+
+use crate::bridge::bridge_escrow_address;
+use crate::error::CoreError;
+use crate::staking::staking_pool_address;
+use crate::{Address, ChainState, Transaction, TxOutput};
+
+/// Memo prefix of coinbases; the rest is the block height, which keeps
+/// every coinbase's hash distinct.
+pub const COINBASE_MEMO_PREFIX: &str = "coinbase:";
+
+/// Blocks a coinbase's outputs wait before they can be spent, so a reorg
+/// cannot take away coins that were already passed on.
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// What the coinbase of a block must pay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Payout {
+    /// The block reward and the fees, in all.
+    pub total: u64,
+    /// Of the total, the treasury's share.
+    pub treasury: u64,
+    /// Where the treasury's share goes; without a treasury there is none.
+    pub treasury_address: Option<Address>,
+    /// Of the total, the share of the validator's delegators.
+    pub delegated: u64,
+}
+
+impl Payout {
+    /// Outputs paying the delegators' share into the staking pool, the
+    /// treasury its share and `validator` the rest.
+    pub fn outputs(&self, validator: Address) -> Vec<TxOutput> {
+        let kept = self.total - self.treasury - self.delegated;
+        let treasury = self.treasury_address.map(|address| (address, self.treasury));
+        [Some((staking_pool_address(), self.delegated)), treasury, Some((validator, kept))]
+            .into_iter()
+            .flatten()
+            .filter(|(_, amount)| *amount > 0)
+            .map(|(address, amount)| TxOutput {
+                address,
+                amount,
+                condition: None,
+                spendable_after_height: None,
+            })
+            .collect()
+    }
+}
+
+/// The coinbase of the block at `height`, paying `outputs`.
+pub fn coinbase_transaction(height: u64, outputs: Vec<TxOutput>) -> Transaction {
+    Transaction {
+        inputs: vec![],
+        outputs,
+        fee: 0,
+        nonce: height,
+        memo: Some(format!("{COINBASE_MEMO_PREFIX}{height}")),
+        lock_time: 0,
+    }
+}
+
+pub fn is_coinbase(tx: &Transaction) -> bool {
+    tx.inputs.is_empty() && tx.memo.as_deref().is_some_and(|memo| memo.starts_with(COINBASE_MEMO_PREFIX))
+}
+
+/// Adds the outputs of `coinbase`, the coinbase of the block at `height`,
+/// which must pay `payout` in plain outputs: none may carry a spending
+/// condition or time lock or pay the bridge escrow.
+pub fn apply_coinbase(
+    state: &mut ChainState,
+    coinbase: &Transaction,
+    height: u64,
+    payout: &Payout,
+) -> Result<(), CoreError> {
+    let escrow = bridge_escrow_address();
+    let plain = |out: &TxOutput| out.condition.is_none() && out.spendable_after_height.is_none();
+    if coinbase.outputs.iter().any(|out| !plain(out) || out.address == escrow) {
+        return Err(CoreError::CoinbaseOutput { height });
+    }
+    let paid = coinbase
+        .outputs
+        .iter()
+        .try_fold(0u64, |sum, out| sum.checked_add(out.amount))
+        .ok_or(CoreError::Overflow("output"))?;
+    if paid != payout.total {
+        return Err(CoreError::CoinbaseTotal { paid, expected: payout.total });
+    }
+    let paid_to = |address: Address| -> u64 {
+        coinbase.outputs.iter().filter(|out| out.address == address).map(|out| out.amount).sum()
+    };
+    let pooled = paid_to(staking_pool_address());
+    if pooled != payout.delegated {
+        return Err(CoreError::CoinbasePool { paid: pooled, expected: payout.delegated });
+    }
+    let treasury = payout.treasury_address.map_or(0, paid_to);
+    if treasury != payout.treasury {
+        return Err(CoreError::CoinbaseTreasury { paid: treasury, expected: payout.treasury });
+    }
+    for out in &coinbase.outputs {
+        state.touch_balance(&out.address);
+        let entry = state.balances.entry(out.address).or_insert(0);
+        *entry = entry.checked_add(out.amount).ok_or(CoreError::Overflow("balance"))?;
+    }
+    let hash = coinbase.hash();
+    state.touch_outputs(&hash);
+    state.touch_coinbase(&hash);
+    state.pending_utxos.insert(hash, coinbase.outputs.clone());
+    state.coinbases.insert(hash, height);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coinbases_pay_exactly_the_payout_with_the_shares_split_off() {
+        let (pool, treasury) = (staking_pool_address(), [8; 32]);
+        let out =
+            |address, amount| TxOutput { address, amount, condition: None, spendable_after_height: None };
+        let payout = Payout { total: 50, treasury: 5, treasury_address: Some(treasury), delegated: 10 };
+        let mut state = ChainState::default();
+        let short = coinbase_transaction(7, vec![out([1; 32], 40)]);
+        assert_eq!(
+            apply_coinbase(&mut state, &short, 7, &payout).unwrap_err().to_string(),
+            "coinbase pays 40, expected 50"
+        );
+        let unpooled = coinbase_transaction(7, vec![out([1; 32], 45), out(treasury, 5)]);
+        assert_eq!(
+            apply_coinbase(&mut state, &unpooled, 7, &payout),
+            Err(CoreError::CoinbasePool { paid: 0, expected: 10 })
+        );
+        let untaxed = coinbase_transaction(7, vec![out([1; 32], 40), out(pool, 10)]);
+        assert!(apply_coinbase(&mut state, &untaxed, 7, &payout).is_err());
+        let mut locked = payout.outputs([1; 32]);
+        locked[2].spendable_after_height = Some(1_000);
+        let locked = coinbase_transaction(7, locked);
+        assert_eq!(
+            apply_coinbase(&mut state, &locked, 7, &payout),
+            Err(CoreError::CoinbaseOutput { height: 7 })
+        );
+        let mut escrowed = payout.outputs([1; 32]);
+        escrowed[2].address = bridge_escrow_address();
+        let escrowed = coinbase_transaction(7, escrowed);
+        assert_eq!(
+            apply_coinbase(&mut state, &escrowed, 7, &payout),
+            Err(CoreError::CoinbaseOutput { height: 7 })
+        );
+        assert!(state.balances.is_empty());
+
+        let coinbase = coinbase_transaction(7, payout.outputs([1; 32]));
+        assert!(
+            is_coinbase(&coinbase)
+                && coinbase.hash() != coinbase_transaction(8, coinbase.outputs.clone()).hash()
+        );
+        apply_coinbase(&mut state, &coinbase, 7, &payout).unwrap();
+        let balance = |address| state.balances[&address];
+        assert_eq!((balance([1; 32]), balance(pool), balance(treasury)), (35, 10, 5));
+        assert_eq!(state.coinbases[&coinbase.hash()], 7);
+        assert_eq!(state.pending_utxos[&coinbase.hash()], coinbase.outputs);
+    }
+}
@@ -0,0 +1,229 @@
+//! Spending conditions. An output may carry a [`SpendCondition`] instead of
+//! paying the holder of a key: it then pays to [`SpendCondition::address`],
+//! and the input spending it must meet the condition, with its signatures in
+//! `signature` and the preimage of a hash lock in `witness`. Conditions
+//! combine into escrows and hash time locked contracts, see
+//! [`SpendCondition::htlc`].
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::encoding::{encode_bytes, encode_list, CanonicalEncode};
+use crate::error::CoreError;
+use crate::multisig::MultisigPolicy;
+use crate::{Address, CryptoProvider, TxInput, TxOutput};
+
+/// Prefixes the encoding hashed into a condition's address.
+const ADDRESS_TAG: &[u8] = b"dxid/condition/v1";
+
+/// Conditions nested below the outermost one.
+pub const MAX_CONDITION_DEPTH: usize = 4;
+/// Largest encoded condition, which bounds the signatures a spend checks.
+pub const MAX_CONDITION_BYTES: usize = 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendCondition {
+    /// A signature by this ed25519 public key.
+    Key(Vec<u8>),
+    /// Signatures of `threshold` cosigners, as
+    /// [`encode_signatures`](crate::multisig::encode_signatures) writes them.
+    Multisig(MultisigPolicy),
+    /// `then`, in blocks from `height` on.
+    AfterHeight { height: u64, then: Box<SpendCondition> },
+    /// `then`, with the preimage of `hash` as the witness. The hash is
+    /// SHA-256, so one secret can unlock hash locks on other chains too.
+    HashLock { hash: [u8; 32], then: Box<SpendCondition> },
+    /// Any one of these.
+    Any(Vec<SpendCondition>),
+}
+
+impl SpendCondition {
+    /// A hash time locked contract: `recipient` claims the coins with the
+    /// preimage of `hash`, or `sender` takes them back from `timeout` on.
+    pub fn htlc(recipient: Vec<u8>, sender: Vec<u8>, hash: [u8; 32], timeout: u64) -> Self {
+        Self::Any(vec![
+            Self::HashLock { hash, then: Box::new(Self::Key(recipient)) },
+            Self::AfterHeight { height: timeout, then: Box::new(Self::Key(sender)) },
+        ])
+    }
+
+    pub fn address(&self) -> Address {
+        let mut bytes = ADDRESS_TAG.to_vec();
+        self.encode_to(&mut bytes);
+        blake3::hash(&bytes).into()
+    }
+
+    /// An output of `amount` locked by the condition.
+    pub fn output(self, amount: u64) -> TxOutput {
+        TxOutput { address: self.address(), amount, condition: Some(self), spendable_after_height: None }
+    }
+
+    /// Checks that outputs may carry the condition.
+    pub fn validate(&self) -> Result<(), CoreError> {
+        if self.canonical_bytes().len() > MAX_CONDITION_BYTES {
+            return Err(CoreError::InvalidCondition("too large"));
+        }
+        self.validate_at(0)
+    }
+
+    fn validate_at(&self, depth: usize) -> Result<(), CoreError> {
+        if depth > MAX_CONDITION_DEPTH {
+            return Err(CoreError::InvalidCondition("nested too deep"));
+        }
+        match self {
+            Self::Key(key) if key.is_empty() => Err(CoreError::InvalidCondition("empty key")),
+            Self::Key(_) => Ok(()),
+            // Deserializing skips `MultisigPolicy::new`, so the policy must
+            // be what it would have made.
+            Self::Multisig(policy) => match MultisigPolicy::new(policy.threshold, policy.public_keys.clone())
+            {
+                Ok(checked) if checked == *policy => Ok(()),
+                _ => Err(CoreError::InvalidCondition("invalid multisig policy")),
+            },
+            Self::AfterHeight { then, .. } | Self::HashLock { then, .. } => then.validate_at(depth + 1),
+            Self::Any(branches) if branches.is_empty() => Err(CoreError::InvalidCondition("no branches")),
+            Self::Any(branches) => branches.iter().try_for_each(|branch| branch.validate_at(depth + 1)),
+        }
+    }
+
+    /// Checks that `input`, in a block at `height`, meets the condition.
+    /// `msg` is what its signatures sign, see [`signing_message`](crate::signing_message).
+    pub fn check<C: CryptoProvider>(
+        &self,
+        crypto: &C,
+        input: &TxInput,
+        height: u64,
+        msg: &[u8],
+    ) -> Result<(), CoreError> {
+        match self {
+            // A malformed signature is as good as a missing one.
+            Self::Key(key) => match crypto.verify_signature(key, msg, &input.signature) {
+                Ok(true) => Ok(()),
+                Ok(false) | Err(_) => Err(CoreError::InvalidSignature),
+            },
+            Self::Multisig(policy) => match policy.verify(crypto, msg, &input.signature) {
+                Ok(true) => Ok(()),
+                Ok(false) | Err(_) => Err(CoreError::MultisigThreshold),
+            },
+            Self::AfterHeight { height: unlock, .. } if height < *unlock => {
+                Err(CoreError::Timelocked { until: *unlock })
+            }
+            Self::HashLock { hash, .. } if Sha256::digest(&input.witness).as_slice() != hash => {
+                Err(CoreError::WrongPreimage)
+            }
+            Self::AfterHeight { then, .. } | Self::HashLock { then, .. } => {
+                then.check(crypto, input, height, msg)
+            }
+            Self::Any(branches) => {
+                if branches.iter().any(|branch| branch.check(crypto, input, height, msg).is_ok()) {
+                    Ok(())
+                } else {
+                    Err(CoreError::ConditionNotMet)
+                }
+            }
+        }
+    }
+}
+
+/// A tag byte for the variant, then its fields.
+impl CanonicalEncode for SpendCondition {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Key(key) => {
+                out.push(0);
+                encode_bytes(key, out);
+            }
+            Self::Multisig(policy) => {
+                out.push(1);
+                encode_bytes(&policy.encode(), out);
+            }
+            Self::AfterHeight { height, then } => {
+                out.push(2);
+                height.encode_to(out);
+                then.encode_to(out);
+            }
+            Self::HashLock { hash, then } => {
+                out.push(3);
+                hash.encode_to(out);
+                then.encode_to(out);
+            }
+            Self::Any(branches) => {
+                out.push(4);
+                encode_list(branches, out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BlockHeader;
+    use anyhow::Result;
+
+    /// Signs by prefixing the message with the key.
+    struct PrefixCrypto;
+
+    impl CryptoProvider for PrefixCrypto {
+        fn address_from_public_key(&self, pk: &[u8]) -> Result<Address> {
+            Ok(blake3::hash(pk).into())
+        }
+
+        fn verify_signature(&self, pk: &[u8], msg: &[u8], sig: &[u8]) -> Result<bool> {
+            Ok(sig == [pk, msg].concat())
+        }
+
+        fn sign_message(&self, sk: &[u8], msg: &[u8]) -> Result<Vec<u8>> {
+            Ok([sk, msg].concat())
+        }
+
+        fn hash_block_header(&self, header: &BlockHeader) -> [u8; 32] {
+            header.hash()
+        }
+    }
+
+    #[test]
+    fn htlc_pays_the_preimage_or_refunds_after_the_timeout() {
+        let (recipient, sender, msg) = (vec![1u8; 32], vec![2u8; 32], b"spend".as_slice());
+        let secret = b"swap secret".to_vec();
+        let htlc =
+            SpendCondition::htlc(recipient.clone(), sender.clone(), Sha256::digest(&secret).into(), 100);
+        htlc.validate().unwrap();
+        assert_eq!(htlc.clone().output(5).address, htlc.address());
+        let signed = |key: &[u8], witness: &[u8]| TxInput {
+            previous_tx: [0; 32],
+            output_index: 0,
+            signature: PrefixCrypto.sign_message(key, msg).unwrap(),
+            public_key: vec![],
+            witness: witness.to_vec(),
+        };
+        let check = |input: &TxInput, height| htlc.check(&PrefixCrypto, input, height, msg);
+
+        assert_eq!(check(&signed(&recipient, &secret), 1), Ok(()));
+        assert_eq!(check(&signed(&recipient, b"guess"), 1), Err(CoreError::ConditionNotMet));
+        assert_eq!(check(&signed(&sender, &[]), 99), Err(CoreError::ConditionNotMet));
+        assert_eq!(check(&signed(&sender, &[]), 100), Ok(()));
+        let SpendCondition::Any(branches) = &htlc else { unreachable!() };
+        assert_eq!(
+            branches[1].check(&PrefixCrypto, &signed(&sender, &[]), 99, msg),
+            Err(CoreError::Timelocked { until: 100 })
+        );
+        assert_eq!(
+            branches[0].check(&PrefixCrypto, &signed(&sender, &secret), 1, msg),
+            Err(CoreError::InvalidSignature)
+        );
+
+        // Two of three for an escrow, which changes the address.
+        let keys = vec![recipient, sender, vec![3u8; 32]];
+        let escrow = SpendCondition::Multisig(MultisigPolicy::new(2, keys).unwrap());
+        escrow.validate().unwrap();
+        assert_ne!(escrow.address(), htlc.address());
+        let mut nested = SpendCondition::Key(vec![4u8; 32]);
+        for height in 0..=MAX_CONDITION_DEPTH as u64 {
+            nested = SpendCondition::AfterHeight { height, then: Box::new(nested) };
+        }
+        assert_eq!(nested.validate(), Err(CoreError::InvalidCondition("nested too deep")));
+        assert!(SpendCondition::Any(vec![]).validate().is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use blake3::Hasher;
+use serde::{Deserialize, Serialize};
+
+use crate::{Address, BlockHash, ChainState, TxHash, TxOutput};
+
+/// Memo prefix of contract calls; the rest is a JSON [`ContractCall`].
+pub const CONTRACT_MEMO_PREFIX: &str = "contract:";
+
+/// Fee per unit of contract gas unless the engine sets another price.
+pub const DEFAULT_GAS_PRICE: u64 = 1;
+
+/// A call to `method` of the contract at `contract`, made by the owner of
+/// the transaction's first input. Outputs of the transaction paying the
+/// contract address are the value sent with the call.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContractCall {
+    pub contract: Address,
+    pub method: String,
+    #[serde(default)]
+    pub input: Vec<u8>,
+}
+
+impl ContractCall {
+    pub fn to_memo(&self) -> String {
+        format!("{CONTRACT_MEMO_PREFIX}{}", serde_json::to_string(self).expect("contract call serializes"))
+    }
+}
+
+pub fn parse_contract_call(memo: &str) -> Option<ContractCall> {
+    serde_json::from_str(memo.strip_prefix(CONTRACT_MEMO_PREFIX)?).ok()
+}
+
+/// A structured log line emitted by a contract.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContractEvent {
+    pub contract: Address,
+    pub topic: String,
+    pub data: Vec<u8>,
+}
+
+/// What a contract call runs with. Contracts get no wall clock or entropy
+/// of their own; `timestamp` and `seed` are their deterministic stand-ins.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContractCallEnv {
+    pub caller: Address,
+    pub block_height: u64,
+    /// Timestamp of the block the call is in, as its header records it.
+    pub timestamp: u64,
+    /// Per-call pseudo-random seed, see [`contract_seed`].
+    pub seed: [u8; 32],
+    /// Gas the transaction's fee pays for; `None` when gas is free and the
+    /// runtime's own limit applies.
+    pub gas_limit: Option<u64>,
+    /// Native tokens sent with the call.
+    pub value: u64,
+    /// Contract balance, `value` included. The chain's balances are the
+    /// source of truth; runtimes must not let a contract spend more.
+    pub balance: u64,
+}
+
+/// Seed of the contract call in transaction `tx_hash`: known to every node
+/// once the previous block is fixed, distinct per transaction, and not
+/// choosable by the block producer without changing the chain it builds on.
+pub fn contract_seed(previous_hash: &BlockHash, tx_hash: &TxHash) -> [u8; 32] {
+    let mut hasher = Hasher::new();
+    hasher.update(b"dxid/contract-seed");
+    hasher.update(previous_hash);
+    hasher.update(tx_hash);
+    hasher.finalize().into()
+}
+
+/// What running a contract call produced.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContractCallResult {
+    pub output: Vec<u8>,
+    pub events: Vec<ContractEvent>,
+    /// Payments out of the contract balance.
+    pub transfers: Vec<(Address, u64)>,
+    pub gas_used: u64,
+}
+
+/// Executes contract calls for the [`crate::ExecutionEngine`]. A failed call
+/// must leave contract state untouched; the transaction still applies, the
+/// value sent is refunded and the receipt records the failure.
+pub trait ContractRuntime: Send + Sync {
+    fn call(&self, env: &ContractCallEnv, call: &ContractCall) -> Result<ContractCallResult>;
+
+    /// Callbacks due in the block at `height`, in the order they must run.
+    /// Must depend only on chain history, so every node gets the same list.
+    fn scheduled_calls(&self, _height: u64) -> Vec<ScheduledCall> {
+        Vec::new()
+    }
+
+    /// Runs a callback from [`ContractRuntime::scheduled_calls`]. The contract
+    /// is its own caller; runtimes may skip checks meant for outside callers.
+    fn call_scheduled(&self, env: &ContractCallEnv, call: &ContractCall) -> Result<ContractCallResult> {
+        self.call(env, call)
+    }
+
+    /// Saves the storage of every contract, so a block that fails or is
+    /// reverted can put it back. Runtimes keeping no state save nothing.
+    fn save_storage(&self) -> Option<SavedStorage> {
+        None
+    }
+
+    /// Puts contract storage back as [`ContractRuntime::save_storage`] saved it.
+    fn restore_storage(&self, _saved: &SavedStorage) {}
+}
+
+/// Contract storage saved by a runtime, which only that runtime can read.
+#[derive(Clone)]
+pub struct SavedStorage(pub Arc<dyn Any + Send + Sync>);
+
+impl fmt::Debug for SavedStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SavedStorage")
+    }
+}
+
+/// A contract callback the block producer runs after a block's transactions,
+/// paying for its gas out of the contract's own balance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledCall {
+    pub contract: Address,
+    pub method: String,
+    /// Most gas the callback may use; the contract's balance may allow less.
+    pub gas_limit: u64,
+}
+
+/// Receipt hash of the `index`-th scheduled call of the block at `height`,
+/// which has no transaction of its own.
+pub fn scheduled_call_hash(height: u64, index: u32) -> TxHash {
+    let mut hasher = Hasher::new();
+    hasher.update(b"dxid/scheduled-call");
+    hasher.update(&height.to_le_bytes());
+    hasher.update(&index.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Pays `transfers` out of `contract`'s balance into new outputs keyed by
+/// the calling transaction. Contract addresses have no key, so contract
+/// funds move only this way and stay part of the balances total.
+pub fn apply_contract_transfers(
+    state: &mut ChainState,
+    tx_hash: &TxHash,
+    contract: &Address,
+    transfers: &[(Address, u64)],
+) -> Result<()> {
+    if transfers.is_empty() {
+        return Ok(());
+    }
+    let total = transfers
+        .iter()
+        .try_fold(0u64, |sum, (_, amount)| sum.checked_add(*amount))
+        .ok_or_else(|| anyhow!("transfer overflow"))?;
+    let held = state.balances.get(contract).copied().unwrap_or(0);
+    if total > held {
+        return Err(anyhow!("contract pays out {total} but holds {held}"));
+    }
+    state.touch_balance(contract);
+    state.balances.insert(*contract, held - total);
+    let mut outputs = Vec::with_capacity(transfers.len());
+    for (to, amount) in transfers {
+        state.touch_balance(to);
+        let entry = state.balances.entry(*to).or_insert(0);
+        *entry = entry.checked_add(*amount).ok_or_else(|| anyhow!("balance overflow"))?;
+        outputs.push(TxOutput {
+            address: *to,
+            amount: *amount,
+            condition: None,
+            spendable_after_height: None,
+        });
+    }
+    let mut hasher = Hasher::new();
+    hasher.update(b"dxid/contract-transfer");
+    hasher.update(tx_hash);
+    let hash: TxHash = hasher.finalize().into();
+    state.touch_outputs(&hash);
+    state.pending_utxos.insert(hash, outputs);
+    Ok(())
+}
+
+/// Returns the part of a contract call's fee its gas did not use to `caller`,
+/// as a new output keyed by the calling transaction.
+pub fn refund_unused_fee(
+    state: &mut ChainState,
+    tx_hash: &TxHash,
+    caller: &Address,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    state.touch_balance(caller);
+    let entry = state.balances.entry(*caller).or_insert(0);
+    *entry = entry.checked_add(amount).ok_or_else(|| anyhow!("balance overflow"))?;
+    let mut hasher = Hasher::new();
+    hasher.update(b"dxid/fee-refund");
+    hasher.update(tx_hash);
+    let hash: TxHash = hasher.finalize().into();
+    state.touch_outputs(&hash);
+    state.pending_utxos.insert(
+        hash,
+        vec![TxOutput { address: *caller, amount, condition: None, spendable_after_height: None }],
+    );
+    Ok(())
+}
+
+/// Outcome of one transaction in a block, stored next to the block.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TxReceipt {
+    pub tx_hash: TxHash,
+    pub block_height: u64,
+    /// Position of the transaction in its block.
+    pub index: u32,
+    /// False when the transaction's contract call failed.
+    pub success: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub gas_used: u64,
+    /// Fee the contract call consumed and the block producer collects; the
+    /// rest of the transaction's fee was refunded to the caller.
+    #[serde(default)]
+    pub fee_paid: u64,
+    #[serde(default)]
+    pub events: Vec<ContractEvent>,
+    /// Payments the contract made, or the refund of the value sent with a failed call.
+    #[serde(default)]
+    pub transfers: Vec<(Address, u64)>,
+}
+
+impl TxReceipt {
+    pub fn new(tx_hash: TxHash, block_height: u64, index: u32) -> Self {
+        Self {
+            tx_hash,
+            block_height,
+            index,
+            success: true,
+            error: None,
+            gas_used: 0,
+            fee_paid: 0,
+            events: Vec::new(),
+            transfers: Vec::new(),
+        }
+    }
+
+    /// Events with `topic`, in emission order.
+    pub fn events_with_topic<'a>(&'a self, topic: &'a str) -> impl Iterator<Item = &'a ContractEvent> + 'a {
+        self.events.iter().filter(move |e| e.topic == topic)
+    }
+}
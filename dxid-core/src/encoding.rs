@@ -0,0 +1,232 @@
+//! Canonical binary encoding of the types that are hashed. Transaction
+//! hashes, merkle roots and block header hashes are taken over these bytes,
+//! so they do not depend on a serializer's field order or number formatting.
+//!
+//! Integers are fixed-width little-endian, byte strings, strings and lists
+//! are prefixed with their length as a `u32`, and an optional value is a `0`
+//! byte, or a `1` byte followed by the value. Fields are encoded in
+//! declaration order; a change to any of these types must keep the encoding
+//! of existing values or it changes every hash.
+
+use crate::{Block, BlockHeader, Transaction, TxInput, TxOutput};
+
+pub trait CanonicalEncode {
+    /// Appends the canonical encoding of `self` to `out`.
+    fn encode_to(&self, out: &mut Vec<u8>);
+
+    fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_to(&mut out);
+        out
+    }
+
+    /// Blake3 hash of [`canonical_bytes`](Self::canonical_bytes).
+    fn canonical_hash(&self) -> [u8; 32] {
+        blake3::hash(&self.canonical_bytes()).into()
+    }
+}
+
+impl CanonicalEncode for u32 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl CanonicalEncode for u64 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+/// Fixed-length hashes and addresses carry no length prefix.
+impl CanonicalEncode for [u8; 32] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl CanonicalEncode for str {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_bytes(self.as_bytes(), out);
+    }
+}
+
+impl<T: CanonicalEncode + ?Sized> CanonicalEncode for Option<&T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode_to(out);
+            }
+        }
+    }
+}
+
+/// Appends `bytes` with its length.
+pub fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    encode_len(bytes.len(), out);
+    out.extend_from_slice(bytes);
+}
+
+/// Appends the number of `items` and then each of them.
+pub fn encode_list<T: CanonicalEncode>(items: &[T], out: &mut Vec<u8>) {
+    encode_len(items.len(), out);
+    for item in items {
+        item.encode_to(out);
+    }
+}
+
+pub(crate) fn encode_len(len: usize, out: &mut Vec<u8>) {
+    let len = u32::try_from(len).expect("encoded length fits in a u32");
+    len.encode_to(out);
+}
+
+impl CanonicalEncode for TxInput {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.previous_tx.encode_to(out);
+        self.output_index.encode_to(out);
+        encode_bytes(&self.signature, out);
+        encode_bytes(&self.public_key, out);
+    }
+}
+
+/// The condition is left to the transaction or state leaf holding the output.
+impl CanonicalEncode for TxOutput {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.address.encode_to(out);
+        self.amount.encode_to(out);
+    }
+}
+
+impl CanonicalEncode for Transaction {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_list(&self.inputs, out);
+        encode_list(&self.outputs, out);
+        self.fee.encode_to(out);
+        self.nonce.encode_to(out);
+        self.memo.as_deref().encode_to(out);
+        // Output conditions and input witnesses follow only when there are
+        // any, and then the time locks only when there are any, which keeps
+        // the encoding of transactions from before them.
+        let conditions: Vec<_> = self.outputs.iter().map(|output| output.condition.as_ref()).collect();
+        let heights: Vec<_> =
+            self.outputs.iter().map(|output| output.spendable_after_height.as_ref()).collect();
+        let witnessed = self.inputs.iter().any(|input| !input.witness.is_empty());
+        let locked = self.lock_time != 0 || heights.iter().any(Option::is_some);
+        if locked || witnessed || conditions.iter().any(Option::is_some) {
+            encode_list(&conditions, out);
+            encode_len(self.inputs.len(), out);
+            for input in &self.inputs {
+                encode_bytes(&input.witness, out);
+            }
+        }
+        if locked {
+            self.lock_time.encode_to(out);
+            encode_list(&heights, out);
+        }
+    }
+}
+
+impl CanonicalEncode for BlockHeader {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.previous_hash.encode_to(out);
+        self.merkle_root.encode_to(out);
+        self.height.encode_to(out);
+        self.timestamp.encode_to(out);
+        self.difficulty.encode_to(out);
+        self.nonce.encode_to(out);
+        self.validator.encode_to(out);
+        self.stake_weight.encode_to(out);
+        // Left out while zero, which keeps the encoding of headers from
+        // before state roots.
+        if self.state_root != [0; 32] {
+            self.state_root.encode_to(out);
+        }
+    }
+}
+
+impl CanonicalEncode for Block {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.header.encode_to(out);
+        encode_list(&self.transactions, out);
+        self.pow_hash.encode_to(out);
+        encode_bytes(&self.validator_signature, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_root;
+
+    fn transaction(nonce: u64, memo: Option<&str>) -> Transaction {
+        Transaction {
+            inputs: vec![TxInput {
+                previous_tx: [1; 32],
+                output_index: 2,
+                signature: vec![3; 4],
+                public_key: vec![5; 2],
+                witness: vec![],
+            }],
+            outputs: vec![TxOutput {
+                address: [6; 32],
+                amount: 700,
+                condition: None,
+                spendable_after_height: None,
+            }],
+            fee: 8,
+            nonce,
+            memo: memo.map(str::to_string),
+            lock_time: 0,
+        }
+    }
+
+    /// Golden vectors: these bytes and hashes must never change, or
+    /// existing transactions and blocks no longer match their hashes.
+    #[test]
+    fn encodes_and_hashes_golden_vectors() {
+        let tx = transaction(9, Some("hi"));
+        let expected = [
+            "01000000",
+            &"01".repeat(32),
+            "02000000",
+            "04000000",
+            "03030303",
+            "02000000",
+            "0505",
+            "01000000",
+            &"06".repeat(32),
+            "bc02000000000000",
+            "0800000000000000",
+            "0900000000000000",
+            "01",
+            "02000000",
+            "6869",
+        ]
+        .concat();
+        assert_eq!(hex::encode(tx.canonical_bytes()), expected);
+        assert_eq!(hex::encode(tx.hash()), "acbb3eb3589c74eae6e715c72b942e8a1bd1373b5a7e84624f757a4c34843a26");
+        assert_eq!(transaction(9, None).canonical_bytes().last(), Some(&0));
+
+        let header = BlockHeader {
+            previous_hash: [1; 32],
+            merkle_root: merkle_root(&[tx.clone(), transaction(10, None)]),
+            height: 3,
+            timestamp: 1_700_000_000,
+            difficulty: 4,
+            nonce: 5,
+            validator: [6; 32],
+            stake_weight: 7,
+            state_root: [0; 32],
+        };
+        assert_eq!(hex::encode(header.merkle_root), "92bca59dd4b534ad53ff10ea19c5b3e6f3883cfb31379cdde878095754b286d9");
+        assert_eq!(header.canonical_bytes().len(), 32 * 3 + 8 * 5);
+        assert_eq!(hex::encode(header.hash()), "7b39b4cb6b3cc61b7bea147a76535c1dd7041af02f02344a61b37592c6b4c2f2");
+        let committed = BlockHeader { state_root: [8; 32], ..header.clone() };
+        assert_eq!(committed.canonical_bytes().len(), 32 * 4 + 8 * 5);
+
+        let block = Block { header, transactions: vec![tx], pow_hash: [0; 32], validator_signature: vec![] };
+        assert_eq!(hex::encode(block.canonical_hash()), "77b9a19e8d4d0bafd159dc40cf732bef5648812bc2a4c1b27f36addb6e5a900e");
+    }
+}
@@ -0,0 +1,122 @@
+//! Why a block or transaction does not apply. Execution returns a
+//! [`CoreError`] rather than a message, so callers can tell a double spend
+//! from a bad signature; the RPC layer picks its status code from it.
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CoreError {
+    #[error("invalid merkle root")]
+    InvalidMerkleRoot,
+    #[error("unexpected height {height}, expected {expected}")]
+    UnexpectedHeight { height: u64, expected: u64 },
+    #[error("block {height} does not extend the tip")]
+    NotOnTip { height: u64 },
+    #[error("block {height} has the wrong state root")]
+    WrongStateRoot { height: u64 },
+    #[error("block {height} has no coinbase")]
+    MissingCoinbase { height: u64 },
+    #[error("block {height} does not start with its coinbase")]
+    MisplacedCoinbase { height: u64 },
+    #[error("block {height} has more than one coinbase")]
+    ExtraCoinbase { height: u64 },
+    #[error("coinbase pays {paid}, expected {expected}")]
+    CoinbaseTotal { paid: u64, expected: u64 },
+    #[error("coinbase pays {paid} into the staking pool, expected {expected}")]
+    CoinbasePool { paid: u64, expected: u64 },
+    #[error("coinbase pays {paid} to the treasury, expected {expected}")]
+    CoinbaseTreasury { paid: u64, expected: u64 },
+    #[error("coinbase of block {height} pays a locked, conditional or escrowed output")]
+    CoinbaseOutput { height: u64 },
+
+    #[error("empty transaction")]
+    EmptyTransaction,
+    #[error("double spend detected")]
+    DoubleSpend,
+    #[error("coinbase output spent before it matures")]
+    ImmatureCoinbase,
+    #[error("missing previous tx")]
+    MissingPreviousTx,
+    #[error("missing output index")]
+    MissingOutput,
+    #[error("escrowed outputs can only be released by the bridge")]
+    EscrowedInput,
+    #[error("staked outputs can only leave through a withdrawal")]
+    StakedInput,
+    #[error("input not owned by multisig policy")]
+    NotMultisigOwner,
+    #[error("multisig threshold not met")]
+    MultisigThreshold,
+    #[error("input not owned by signer")]
+    NotSigner,
+    #[error("signature invalid")]
+    InvalidSignature,
+    #[error("inputs of {inputs} do not cover outputs and fee of {needed}")]
+    InsufficientInputs { inputs: u64, needed: u64 },
+    /// A sum of amounts, named here, does not fit in a u64.
+    #[error("{0} overflow")]
+    Overflow(&'static str),
+    #[error("escrow output without bridge memo")]
+    MissingBridgeMemo,
+    #[error("escrow output without a signed input")]
+    UnsignedBridgeLock,
+    #[error("bridge transactions are not enabled")]
+    BridgeDisabled,
+    #[error("a release spends only escrowed outputs")]
+    ReleaseInput,
+    #[error("a delivery moves no funds and pays no fee")]
+    InvalidDelivery,
+    /// The inbound verifier refuses the message a bridge transaction carries.
+    #[error("inbound message refused: {0}")]
+    InboundRefused(String),
+    #[error("pool output without stake memo")]
+    MissingStakeMemo,
+    #[error("staking owner does not sign the transaction")]
+    StakeOwnerNotSigner,
+    #[error("invalid spending condition: {0}")]
+    InvalidCondition(&'static str),
+    #[error("output does not pay to its condition's address")]
+    ConditionAddress,
+    #[error("output is locked until height {until}")]
+    Timelocked { until: u64 },
+    #[error("witness is not the preimage of the hash lock")]
+    WrongPreimage,
+    #[error("no branch of the spending condition is met")]
+    ConditionNotMet,
+    #[error("transaction is locked until height {lock_time}")]
+    TransactionLocked { lock_time: u64 },
+    #[error("contract calls are not enabled")]
+    ContractsDisabled,
+    #[error("contract call without a signed input")]
+    UnsignedContractCall,
+    /// The staking ledger refuses the transaction's stake memo.
+    #[error("{0}")]
+    Staking(String),
+    /// The bridge ledger refuses the transaction's escrow lock.
+    #[error("{0}")]
+    Bridge(String),
+    /// A contract pays out more than it holds.
+    #[error("{0}")]
+    ContractPayout(String),
+    /// The crypto provider failed, which says nothing about the block.
+    #[error("crypto provider failed: {0}")]
+    Crypto(String),
+}
+
+impl CoreError {
+    pub(crate) fn staking(err: anyhow::Error) -> Self {
+        Self::Staking(format!("{err:#}"))
+    }
+
+    pub(crate) fn bridge(err: anyhow::Error) -> Self {
+        Self::Bridge(format!("{err:#}"))
+    }
+
+    pub(crate) fn contract_payout(err: anyhow::Error) -> Self {
+        Self::ContractPayout(format!("{err:#}"))
+    }
+
+    pub(crate) fn crypto(err: anyhow::Error) -> Self {
+        Self::Crypto(format!("{err:#}"))
+    }
+}
@@ -0,0 +1,123 @@
+//! Notifications the node's subsystems share: blocks joining the main
+//! chain, reorgs, transactions entering the mempool and peers coming and
+//! going. The node publishes them on one [`EventBus`] and every subsystem
+//! holding a clone subscribes, instead of polling the store for changes.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{Interval, MissedTickBehavior};
+
+use crate::contract::TxReceipt;
+use crate::{Block, BlockHash, Transaction, TxHash};
+
+/// Events buffered for each subscriber before it lags.
+pub const EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum NodeEvent {
+    /// A block joined the main chain, after it was stored.
+    Block { block: Block, receipts: Vec<TxReceipt> },
+    /// The main chain left the blocks above `fork_height`, tip first; the
+    /// blocks of the new branch follow as `Block` events.
+    Reorg { fork_height: u64, reverted: Vec<BlockHash> },
+    /// A transaction entered the mempool.
+    Transaction { hash: TxHash, tx: Transaction },
+    /// A peer connected, or its last connection closed.
+    Peer { peer: String, connected: bool },
+}
+
+impl NodeEvent {
+    /// Whether the event moves the main chain.
+    pub fn moves_chain(&self) -> bool {
+        matches!(self, NodeEvent::Block { .. } | NodeEvent::Reorg { .. })
+    }
+}
+
+/// A typed broadcast channel of [`NodeEvent`]s; clones share it.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<NodeEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(EVENT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        Self { sender: broadcast::channel(capacity).0 }
+    }
+
+    /// Sends `event` to every subscriber; it is dropped when there are none.
+    pub fn publish(&self, event: NodeEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Wakes a task that follows the chain: whenever the main chain moves if it
+/// has a bus, otherwise, or once the bus is gone, every `interval`.
+pub struct ChainWaker {
+    events: Option<broadcast::Receiver<NodeEvent>>,
+    ticker: Interval,
+}
+
+impl ChainWaker {
+    pub fn new(bus: Option<&EventBus>, interval: Duration) -> Self {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        Self { events: bus.map(EventBus::subscribe), ticker }
+    }
+
+    /// Resolves at the next wake-up with the event that moved the chain, or
+    /// `None` when woken by the interval. Missed events count as a move,
+    /// since one of them may have been.
+    pub async fn wait(&mut self) -> Option<NodeEvent> {
+        loop {
+            let Some(events) = &mut self.events else {
+                self.ticker.tick().await;
+                return None;
+            };
+            match events.recv().await {
+                Ok(event) if event.moves_chain() => return Some(event),
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => return None,
+                Err(RecvError::Closed) => self.events = None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn wakes_followers_when_the_main_chain_moves() {
+        let bus = EventBus::new(4);
+        let mut waker = ChainWaker::new(Some(&bus), Duration::from_secs(3600));
+        let mut events = bus.subscribe();
+        bus.publish(NodeEvent::Peer { peer: "12D3Koo".into(), connected: true });
+        bus.publish(NodeEvent::Reorg { fork_height: 4, reverted: vec![[5; 32]] });
+        assert!(matches!(waker.wait().await, Some(NodeEvent::Reorg { fork_height: 4, .. })));
+
+        let event = serde_json::to_value(events.recv().await.unwrap()).unwrap();
+        assert_eq!(event, serde_json::json!({ "event": "peer", "peer": "12D3Koo", "connected": true }));
+        assert!(events.recv().await.unwrap().moves_chain());
+
+        // Without the bus it falls back to its interval.
+        drop(bus);
+        waker.ticker = tokio::time::interval(Duration::from_millis(1));
+        waker.wait().await;
+        assert!(waker.events.is_none());
+    }
+}
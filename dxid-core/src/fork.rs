@@ -0,0 +1,832 @@
+//! Fork handling. A [`ChainTree`] keeps every block received within the
+//! last `max_depth` blocks, whichever branch it is on, with the cumulative
+//! weight of the chain it ends. Applying a block through it also records a
+//! [`BlockUndo`] of the changes the block made, each with the value it
+//! replaced, so [`ExecutionEngine::reorg_to`] can revert the main chain back
+//! to where another branch forks off and apply that branch instead. The
+//! undo also holds the contract storage from before the block, when the
+//! engine runs contracts.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+
+use crate::bridge::{BridgeRelease, BridgeTransfer};
+use crate::contract::{SavedStorage, TxReceipt};
+use crate::staking::Unbonding;
+use crate::{
+    Address, Block, BlockHash, BlockHeader, ChainId, ChainState, CrossChainMessage, CryptoProvider,
+    ExecutionEngine, TxHash, TxOutput,
+};
+
+/// Blocks below the tip that can still be reverted by default.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
+/// Weight a block adds to its chain: its proof-of-work difficulty plus the
+/// stake backing its validator, so the heavier chain is the one more work
+/// and stake went into.
+pub fn block_weight(header: &BlockHeader) -> u128 {
+    header.difficulty as u128 + header.stake_weight as u128 + 1
+}
+
+/// One change a block made to the state, with what it replaced; `None`
+/// where the entry did not exist.
+#[derive(Debug, Clone)]
+pub(crate) enum Change {
+    Balance(Address, Option<u64>),
+    Outputs(TxHash, Option<Vec<TxOutput>>),
+    Coinbase(TxHash, Option<u64>),
+    Bond((Address, Address), Option<u64>),
+    Rewards(Address, Option<u64>),
+    /// An unbonding of the owner was queued last.
+    Unbonded(Address),
+    /// The unbonding at this index was withdrawn.
+    Withdrawn(usize, Unbonding),
+    Escrowed(ChainId, Option<u64>),
+    Processed(Uuid),
+    Released(ChainId, u64),
+    Delivered(ChainId, u64),
+    /// A lock was added to the end of the outbox.
+    Locked,
+}
+
+/// What applying one block changed, enough to put the state back.
+#[derive(Debug, Clone)]
+pub struct BlockUndo {
+    hash: BlockHash,
+    /// Changes in the order the block made them.
+    changes: Vec<Change>,
+    total_issued: u64,
+    issued_rewards: u64,
+    staking_height: u64,
+    tip_height: u64,
+    tip_hash: Option<BlockHash>,
+    /// Contract storage before the block, if the runtime saved it.
+    contracts: Option<SavedStorage>,
+}
+
+impl BlockUndo {
+    /// Header hash of the block this undoes.
+    pub fn hash(&self) -> BlockHash {
+        self.hash
+    }
+}
+
+fn put<K: Eq + std::hash::Hash, V>(map: &mut HashMap<K, V>, key: K, value: Option<V>) {
+    match value {
+        Some(value) => map.insert(key, value),
+        None => map.remove(&key),
+    };
+}
+
+/// Records the changes of a block while it is applied with an undo. Every
+/// mutation of the state during [`ExecutionEngine::execute_block`] goes
+/// through these, so nothing needs copying up front.
+impl ChainState {
+    fn record(&mut self, change: impl FnOnce(&Self) -> Change) {
+        if let Some(mut journal) = self.journal.take() {
+            journal.push(change(self));
+            self.journal = Some(journal);
+        }
+    }
+
+    /// Notes `address`'s balance before it changes.
+    pub(crate) fn touch_balance(&mut self, address: &Address) {
+        self.record(|state| Change::Balance(*address, state.balances.get(address).copied()));
+    }
+
+    /// Notes the outputs of `hash` before they change.
+    pub(crate) fn touch_outputs(&mut self, hash: &TxHash) {
+        self.record(|state| Change::Outputs(*hash, state.pending_utxos.get(hash).cloned()));
+    }
+
+    pub(crate) fn touch_coinbase(&mut self, hash: &TxHash) {
+        self.record(|state| Change::Coinbase(*hash, state.coinbases.get(hash).copied()));
+    }
+
+    fn touch_bond(&mut self, owner: Address, validator: Address) {
+        let key = (owner, validator);
+        self.record(|state| Change::Bond(key, state.staking.bonds.get(&key).copied()));
+    }
+
+    fn touch_rewards(&mut self, owner: Address) {
+        self.record(|state| Change::Rewards(owner, state.staking.rewards.get(&owner).copied()));
+    }
+
+    fn touch_escrowed(&mut self, chain: &ChainId) {
+        self.record(|state| Change::Escrowed(chain.clone(), state.bridge.escrowed.get(chain).copied()));
+    }
+
+    pub(crate) fn bond(&mut self, owner: Address, validator: Address, amount: u64) -> Result<()> {
+        self.touch_bond(owner, validator);
+        self.staking.bond(owner, validator, amount)
+    }
+
+    pub(crate) fn unbond(&mut self, owner: Address, validator: Address, amount: u64) -> Result<()> {
+        self.touch_bond(owner, validator);
+        self.staking.unbond(owner, validator, amount)?;
+        self.record(|_| Change::Unbonded(owner));
+        Ok(())
+    }
+
+    /// [`crate::staking::StakingLedger::distribute`], noting the rewards of
+    /// `validator`'s delegators first.
+    pub(crate) fn distribute(&mut self, validator: &Address, reward: u64) -> u64 {
+        if self.journal.is_some() {
+            let delegators: Vec<Address> = self
+                .staking
+                .bonds
+                .keys()
+                .filter(|(owner, v)| v == validator && owner != validator)
+                .map(|(owner, _)| *owner)
+                .collect();
+            for owner in delegators {
+                self.touch_rewards(owner);
+            }
+        }
+        self.staking.distribute(validator, reward)
+    }
+
+    /// [`crate::staking::StakingLedger::withdraw`], noting `owner`'s rewards
+    /// and the unbondings it releases first.
+    pub(crate) fn withdraw(&mut self, owner: &Address) -> u64 {
+        self.touch_rewards(*owner);
+        if self.journal.is_some() {
+            let height = self.staking.height;
+            // Last first, so that undoing them in reverse puts each back at its index.
+            let released: Vec<(usize, Unbonding)> = self
+                .staking
+                .unbonding
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|(_, u)| &u.owner == owner && u.release_height <= height)
+                .map(|(index, u)| (index, u.clone()))
+                .collect();
+            for (index, unbonding) in released {
+                self.record(|_| Change::Withdrawn(index, unbonding));
+            }
+        }
+        self.staking.withdraw(owner)
+    }
+
+    pub(crate) fn lock(&mut self, transfer: BridgeTransfer, height: u64) -> Result<()> {
+        if !self.bridge.processed.contains(&transfer.id) {
+            self.record(|_| Change::Processed(transfer.id));
+        }
+        self.touch_escrowed(&transfer.dest);
+        self.bridge.lock(transfer, height)?;
+        self.record(|_| Change::Locked);
+        Ok(())
+    }
+
+    pub(crate) fn release(&mut self, release: &BridgeRelease) -> Result<()> {
+        if !self.bridge.processed.contains(&release.id) {
+            self.record(|_| Change::Processed(release.id));
+        }
+        if !self.bridge.released_nonces.contains(&(release.source.clone(), release.nonce)) {
+            self.record(|_| Change::Released(release.source.clone(), release.nonce));
+        }
+        self.touch_escrowed(&release.source);
+        self.bridge.release(release)
+    }
+
+    pub(crate) fn deliver(&mut self, msg: &CrossChainMessage) -> Result<()> {
+        if !self.bridge.delivered.contains(&(msg.source.clone(), msg.nonce)) {
+            self.record(|_| Change::Delivered(msg.source.clone(), msg.nonce));
+        }
+        self.bridge.deliver(msg)
+    }
+
+    /// Puts back what `changes` replaced, last change first.
+    fn unwind(&mut self, changes: Vec<Change>) {
+        for change in changes.into_iter().rev() {
+            match change {
+                Change::Balance(address, value) => put(&mut self.balances, address, value),
+                Change::Outputs(hash, value) => put(&mut self.pending_utxos, hash, value),
+                Change::Coinbase(hash, value) => put(&mut self.coinbases, hash, value),
+                Change::Bond(key, value) => put(&mut self.staking.bonds, key, value),
+                Change::Rewards(owner, value) => put(&mut self.staking.rewards, owner, value),
+                Change::Unbonded(_) => {
+                    self.staking.unbonding.pop();
+                }
+                Change::Withdrawn(index, unbonding) => self.staking.unbonding.insert(index, unbonding),
+                Change::Escrowed(chain, value) => put(&mut self.bridge.escrowed, chain, value),
+                Change::Processed(id) => {
+                    self.bridge.processed.remove(&id);
+                }
+                Change::Released(source, nonce) => {
+                    self.bridge.released_nonces.remove(&(source, nonce));
+                }
+                Change::Delivered(source, nonce) => {
+                    self.bridge.delivered.remove(&(source, nonce));
+                }
+                Change::Locked => {
+                    self.bridge.outbox.pop();
+                }
+            }
+        }
+    }
+}
+
+/// Blocks a [`ExecutionEngine::reorg_to`] took off and put on the main chain.
+#[derive(Debug, Clone, Default)]
+pub struct Reorg {
+    /// Reverted blocks, old tip first.
+    pub reverted: Vec<Block>,
+    /// Applied blocks, lowest first, with their receipts.
+    pub applied: Vec<(Block, Vec<TxReceipt>)>,
+    /// Balances from before the reorg of the addresses its blocks touched,
+    /// `None` for addresses that had none.
+    pub prior_balances: HashMap<Address, Option<u64>>,
+    /// Owners whose bonds, unbondings or rewards its blocks touched.
+    pub stakers: HashSet<Address>,
+}
+
+impl Reorg {
+    /// Notes what the block `undo` is for touched. The balances it replaced
+    /// are the ones before the reorg unless `current` holds them instead,
+    /// as while its block is still applied.
+    fn touch(&mut self, undo: &BlockUndo, current: Option<&HashMap<Address, u64>>) {
+        for change in &undo.changes {
+            match change {
+                Change::Balance(address, before) => {
+                    let before = current.map_or(*before, |balances| balances.get(address).copied());
+                    self.prior_balances.entry(*address).or_insert(before);
+                }
+                Change::Bond((owner, _), _) | Change::Rewards(owner, _) | Change::Unbonded(owner) => {
+                    self.stakers.insert(*owner);
+                }
+                Change::Withdrawn(_, unbonding) => {
+                    self.stakers.insert(unbonding.owner);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    block: Block,
+    /// Weight of the chain ending at this block, from the first block the tree kept.
+    weight: u128,
+    /// Set while the block is applied on the main chain.
+    undo: Option<BlockUndo>,
+}
+
+/// Recent blocks of every known branch and which of them are applied.
+#[derive(Debug)]
+pub struct ChainTree {
+    nodes: HashMap<BlockHash, Node>,
+    /// Applied blocks, lowest first.
+    main: Vec<BlockHash>,
+    max_depth: usize,
+}
+
+impl Default for ChainTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChainTree {
+    pub fn new() -> Self {
+        Self { nodes: HashMap::new(), main: Vec::new(), max_depth: DEFAULT_MAX_DEPTH }
+    }
+
+    /// Keeps `depth` blocks below the tip revertible; older ones are final.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Stores `block` on the branch of its parent. The first block stored
+    /// needs no parent; later ones need theirs to be known.
+    pub fn insert(&mut self, crypto: &impl CryptoProvider, block: Block) -> Result<BlockHash> {
+        let hash = crypto.hash_block_header(&block.header);
+        if self.nodes.contains_key(&hash) {
+            return Ok(hash);
+        }
+        let weight = if self.nodes.is_empty() {
+            block_weight(&block.header)
+        } else {
+            let parent = self
+                .nodes
+                .get(&block.header.previous_hash)
+                .ok_or_else(|| anyhow!("parent of block {} is unknown", block.header.height))?;
+            if block.header.height != parent.block.header.height + 1 {
+                return Err(anyhow!("block {} does not follow its parent", block.header.height));
+            }
+            parent.weight + block_weight(&block.header)
+        };
+        self.nodes.insert(hash, Node { block, weight, undo: None });
+        Ok(hash)
+    }
+
+    /// Starts an empty tree at `block`, which the state is already at, as
+    /// after a genesis block built into the state.
+    pub fn start_at(&mut self, crypto: &impl CryptoProvider, block: Block) -> Result<BlockHash> {
+        if !self.nodes.is_empty() {
+            return Err(anyhow!("the tree already holds blocks"));
+        }
+        let hash = self.insert(crypto, block)?;
+        self.main.push(hash);
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &BlockHash) -> Option<&Block> {
+        self.nodes.get(hash).map(|node| &node.block)
+    }
+
+    pub fn contains(&self, hash: &BlockHash) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    /// What undoes `hash`, while it is applied and not yet final.
+    pub fn undo(&self, hash: &BlockHash) -> Option<&BlockUndo> {
+        self.nodes.get(hash).and_then(|node| node.undo.as_ref())
+    }
+
+    /// Weight of the chain ending at `hash`.
+    pub fn weight(&self, hash: &BlockHash) -> Option<u128> {
+        self.nodes.get(hash).map(|node| node.weight)
+    }
+
+    /// The last applied block.
+    pub fn tip(&self) -> Option<BlockHash> {
+        self.main.last().copied()
+    }
+
+    /// Height of the lowest applied block held. It and the blocks below it
+    /// can no longer be reverted, so what they did is final.
+    pub fn final_height(&self) -> Option<u64> {
+        self.main.first().map(|hash| self.nodes[hash].block.header.height)
+    }
+
+    /// Applied blocks still held, lowest first.
+    pub fn main_chain(&self) -> &[BlockHash] {
+        &self.main
+    }
+
+    /// End of the heaviest known chain. The applied tip wins ties, so only a
+    /// strictly heavier branch is worth a reorg.
+    pub fn best(&self) -> Option<BlockHash> {
+        let heaviest = self.nodes.iter().max_by(|(a, x), (b, y)| x.weight.cmp(&y.weight).then(b.cmp(a)));
+        match (self.tip(), heaviest) {
+            (Some(tip), Some((_, node))) if node.weight <= self.nodes[&tip].weight => Some(tip),
+            (_, heaviest) => heaviest.map(|(hash, _)| *hash),
+        }
+    }
+
+    fn is_main(&self, hash: &BlockHash) -> bool {
+        let (Some(node), Some(first)) = (self.nodes.get(hash), self.main.first()) else {
+            return false;
+        };
+        let base = self.nodes[first].block.header.height;
+        let height = node.block.header.height;
+        height >= base && self.main.get((height - base) as usize) == Some(hash)
+    }
+
+    /// Blocks from where `tip`'s branch leaves the main chain up to `tip`,
+    /// lowest first, and how many main chain blocks lie below the fork.
+    fn branch_to(&self, tip: &BlockHash) -> Result<(usize, Vec<BlockHash>)> {
+        let mut branch = Vec::new();
+        let mut hash = *tip;
+        while !self.is_main(&hash) {
+            let node = self.nodes.get(&hash).ok_or_else(|| anyhow!("branch leaves the blocks kept"))?;
+            branch.push(hash);
+            if !self.nodes.contains_key(&node.block.header.previous_hash) {
+                if self.main.is_empty() {
+                    branch.reverse();
+                    return Ok((0, branch));
+                }
+                return Err(anyhow!("branch does not join the main chain within {} blocks", self.max_depth));
+            }
+            hash = node.block.header.previous_hash;
+        }
+        branch.reverse();
+        let kept =
+            self.main.iter().position(|main| *main == hash).expect("fork point is on the main chain") + 1;
+        Ok((kept, branch))
+    }
+
+    /// Drops `hash` and every block built on it.
+    fn discard(&mut self, hash: &BlockHash) {
+        let mut nodes: Vec<(u64, BlockHash, BlockHash)> = self
+            .nodes
+            .iter()
+            .map(|(hash, node)| (node.block.header.height, *hash, node.block.header.previous_hash))
+            .collect();
+        nodes.sort_unstable();
+        let mut dropped = HashSet::from([*hash]);
+        for (_, hash, parent) in nodes {
+            if dropped.contains(&parent) {
+                dropped.insert(hash);
+            }
+        }
+        self.nodes.retain(|hash, _| !dropped.contains(hash));
+    }
+
+    /// Makes blocks deeper than `max_depth` final: they can no longer be
+    /// reverted, and branches forking below them are dropped.
+    fn prune(&mut self) {
+        let Some(excess) = self.main.len().checked_sub(self.max_depth + 1).filter(|excess| *excess > 0)
+        else {
+            return;
+        };
+        for hash in self.main.drain(..excess) {
+            self.nodes.remove(&hash);
+        }
+        let root = self.main[0];
+        if let Some(node) = self.nodes.get_mut(&root) {
+            node.undo = None;
+        }
+        let mut nodes: Vec<(u64, BlockHash, BlockHash)> = self
+            .nodes
+            .iter()
+            .map(|(hash, node)| (node.block.header.height, *hash, node.block.header.previous_hash))
+            .collect();
+        nodes.sort_unstable();
+        let mut kept = HashSet::from([root]);
+        for (_, hash, parent) in nodes {
+            if kept.contains(&parent) {
+                kept.insert(hash);
+            }
+        }
+        self.nodes.retain(|hash, _| kept.contains(hash));
+    }
+}
+
+impl<C: CryptoProvider> ExecutionEngine<'_, C> {
+    /// Applies `block` like [`ExecutionEngine::execute_block`], also returning
+    /// what undoes it. A block that fails leaves `state`, and the storage of
+    /// the contracts it called, as they were.
+    pub fn execute_block_with_undo(
+        &self,
+        state: &mut ChainState,
+        block: &Block,
+    ) -> Result<(Vec<TxReceipt>, BlockUndo)> {
+        let mut undo = BlockUndo {
+            hash: self.crypto.hash_block_header(&block.header),
+            changes: Vec::new(),
+            total_issued: state.total_issued,
+            issued_rewards: state.issued_rewards,
+            staking_height: state.staking.height,
+            tip_height: state.tip_height,
+            tip_hash: state.tip_hash,
+            contracts: self.contracts.and_then(|runtime| runtime.save_storage()),
+        };
+        state.journal = Some(Vec::new());
+        let outcome = self.execute_block(state, block);
+        undo.changes = state.journal.take().unwrap_or_default();
+        match outcome {
+            Ok(receipts) => Ok((receipts, undo)),
+            Err(err) => {
+                self.undo(state, undo);
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Reverts the tip block, which `undo` must have been recorded for.
+    pub fn revert_block(&self, state: &mut ChainState, undo: BlockUndo) -> Result<()> {
+        if state.tip_hash != Some(undo.hash) {
+            return Err(anyhow!("undo is not for the tip block"));
+        }
+        self.undo(state, undo);
+        Ok(())
+    }
+
+    fn undo(&self, state: &mut ChainState, undo: BlockUndo) {
+        state.unwind(undo.changes);
+        state.total_issued = undo.total_issued;
+        state.issued_rewards = undo.issued_rewards;
+        state.staking.height = undo.staking_height;
+        state.tip_height = undo.tip_height;
+        state.tip_hash = undo.tip_hash;
+        self.restore_contracts(undo.contracts.as_ref());
+    }
+
+    fn restore_contracts(&self, saved: Option<&SavedStorage>) {
+        if let (Some(runtime), Some(saved)) = (self.contracts, saved) {
+            runtime.restore_storage(saved);
+        }
+    }
+
+    /// Makes `tip` the tip of `state`: reverts the main chain of `tree` down
+    /// to where `tip`'s branch forks off and applies the branch. If a block
+    /// of the branch fails, it and the blocks built on it are dropped from
+    /// the tree and the old main chain is restored.
+    pub fn reorg_to(&self, state: &mut ChainState, tree: &mut ChainTree, tip: &BlockHash) -> Result<Reorg> {
+        let (kept, branch) = tree.branch_to(tip)?;
+        if tree.main[kept..].iter().any(|hash| tree.nodes[hash].undo.is_none()) {
+            return Err(anyhow!("branch forks below the last {} blocks", tree.max_depth));
+        }
+        let mut reorg = Reorg::default();
+        let old = tree.main.split_off(kept);
+        for hash in old.iter().rev() {
+            let undo = tree.nodes.get_mut(hash).and_then(|node| node.undo.take()).expect("checked above");
+            reorg.touch(&undo, Some(&state.balances));
+            self.revert_block(state, undo)?;
+            reorg.reverted.push(tree.nodes[hash].block.clone());
+        }
+        for hash in &branch {
+            let block = &tree.nodes[hash].block;
+            let height = block.header.height;
+            match self.execute_block_with_undo(state, block) {
+                Ok((receipts, undo)) => {
+                    reorg.touch(&undo, None);
+                    tree.nodes.get_mut(hash).expect("branch blocks are kept").undo = Some(undo);
+                    tree.main.push(*hash);
+                    reorg.applied.push((tree.nodes[hash].block.clone(), receipts));
+                }
+                Err(err) => {
+                    while tree.main.len() > kept {
+                        let applied = tree.main.pop().expect("longer than the blocks kept");
+                        let undo = tree.nodes.get_mut(&applied).and_then(|node| node.undo.take());
+                        self.revert_block(state, undo.expect("applied blocks have an undo"))?;
+                    }
+                    for hash in &old {
+                        let (_, undo) = self.execute_block_with_undo(state, &tree.nodes[hash].block)?;
+                        tree.nodes.get_mut(hash).expect("old main blocks are kept").undo = Some(undo);
+                        tree.main.push(*hash);
+                    }
+                    tree.discard(hash);
+                    return Err(err.context(format!("block {height} of the new branch is invalid")));
+                }
+            }
+        }
+        tree.prune();
+        Ok(reorg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::bridge::bridge_escrow_address;
+    use crate::coinbase::coinbase_transaction;
+    use crate::contract::{
+        ContractCall, ContractCallEnv, ContractCallResult, ContractRuntime, ScheduledCall,
+    };
+    use crate::staking::{staking_pool_address, StakeAction, StakeMemo};
+    use crate::state_tree::state_root;
+    use crate::{merkle_root, HalvingSchedule, TokenEconomics, Transaction, TxInput};
+
+    struct HeaderHash;
+
+    impl CryptoProvider for HeaderHash {
+        fn address_from_public_key(&self, pk: &[u8]) -> Result<Address> {
+            Ok(*blake3::hash(pk).as_bytes())
+        }
+
+        fn verify_signature(&self, _pk: &[u8], _msg: &[u8], _sig: &[u8]) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn sign_message(&self, _sk: &[u8], msg: &[u8]) -> Result<Vec<u8>> {
+            Ok(msg.to_vec())
+        }
+
+        fn hash_block_header(&self, header: &BlockHeader) -> BlockHash {
+            header.hash()
+        }
+    }
+
+    /// A block on `parent`, whose chain leaves `state`, paying its validator
+    /// the reward of 50.
+    fn block(parent: Option<&Block>, state: &ChainState, validator: u8, difficulty: u64) -> Block {
+        let height = parent.map_or(0, |parent| parent.header.height + 1);
+        let transactions = vec![coinbase_transaction(
+            height,
+            vec![TxOutput {
+                address: [validator; 32],
+                amount: 50,
+                condition: None,
+                spendable_after_height: None,
+            }],
+        )];
+        Block {
+            header: BlockHeader {
+                previous_hash: parent.map_or([0; 32], |parent| parent.header.hash()),
+                merkle_root: merkle_root(&transactions),
+                height,
+                timestamp: 0,
+                difficulty,
+                nonce: 0,
+                validator: [validator; 32],
+                stake_weight: 0,
+                state_root: state_root(state),
+            },
+            transactions,
+            pow_hash: [0; 32],
+            validator_signature: vec![],
+        }
+    }
+
+    #[test]
+    fn reorgs_to_the_heavier_branch_and_back_out_of_a_bad_one() {
+        let crypto = HeaderHash;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 50,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let engine = ExecutionEngine::new(&crypto, economics);
+        let mut state = ChainState::default();
+        let mut tree = ChainTree::new().with_max_depth(3);
+
+        let applied = |state: &ChainState, block: &Block| {
+            let mut state = state.clone();
+            engine.apply_block(&mut state, block).unwrap();
+            state
+        };
+        let genesis = block(None, &state, 9, 1);
+        let on_genesis = applied(&state, &genesis);
+        let a1 = block(Some(&genesis), &on_genesis, 1, 1);
+        let a2 = block(Some(&a1), &applied(&on_genesis, &a1), 1, 1);
+        let b1 = block(Some(&genesis), &on_genesis, 2, 5);
+        for block in [&genesis, &a1, &a2, &b1] {
+            tree.insert(&crypto, block.clone()).unwrap();
+        }
+        let a2_hash = a2.header.hash();
+        let reorg = engine.reorg_to(&mut state, &mut tree, &a2_hash).unwrap();
+        assert_eq!(reorg.applied.len(), 3);
+        let on_a = state.clone();
+        assert_eq!(on_a.balances[&[1; 32]], 100);
+
+        // b1 alone outweighs a1 and a2.
+        assert_eq!(tree.best(), Some(b1.header.hash()));
+        let reorg = engine.reorg_to(&mut state, &mut tree, &b1.header.hash()).unwrap();
+        let reverted: Vec<BlockHash> = reorg.reverted.iter().map(|block| block.header.hash()).collect();
+        assert_eq!(reverted, [a2_hash, a1.header.hash()]);
+        assert_eq!((state.tip_height, state.balances.get(&[1; 32])), (1, None));
+        assert_eq!((state.balances[&[2; 32]], state.total_issued), (50, 100));
+
+        // A branch whose block does not apply leaves the state on b1 and is dropped.
+        let mut bad = block(Some(&a2), &state, 3, 9);
+        bad.header.merkle_root = [1; 32];
+        let bad_hash = tree.insert(&crypto, bad).unwrap();
+        let child = block(tree.get(&bad_hash).cloned().as_ref(), &state, 3, 1);
+        let child = tree.insert(&crypto, child).unwrap();
+        assert!(engine.reorg_to(&mut state, &mut tree, &child).is_err());
+        assert_eq!(state.tip_hash, Some(b1.header.hash()));
+        assert!(!tree.contains(&bad_hash) && !tree.contains(&child));
+
+        engine.reorg_to(&mut state, &mut tree, &a2_hash).unwrap();
+        assert_eq!((state.balances.clone(), state.tip_hash), (on_a.balances, on_a.tip_hash));
+
+        // Past the depth, the fork off genesis is final and b1 dropped.
+        let mut tip = a2;
+        for _ in 0..2 {
+            tip = block(Some(&tip), &state, 1, 1);
+            let hash = tree.insert(&crypto, tip.clone()).unwrap();
+            engine.reorg_to(&mut state, &mut tree, &hash).unwrap();
+        }
+        assert_eq!(tree.main_chain().len(), 4);
+        assert!(!tree.contains(&genesis.header.hash()) && !tree.contains(&b1.header.hash()));
+    }
+
+    /// Counts, in its storage, the blocks whose callback it ran.
+    #[derive(Default)]
+    struct BlockCounter(Mutex<u64>);
+
+    impl ContractRuntime for BlockCounter {
+        fn call(&self, _env: &ContractCallEnv, _call: &ContractCall) -> Result<ContractCallResult> {
+            *self.0.lock().unwrap() += 1;
+            Ok(ContractCallResult::default())
+        }
+
+        fn scheduled_calls(&self, _height: u64) -> Vec<ScheduledCall> {
+            vec![ScheduledCall { contract: [7; 32], method: "tick".into(), gas_limit: 1 }]
+        }
+
+        fn save_storage(&self) -> Option<SavedStorage> {
+            Some(SavedStorage(Arc::new(*self.0.lock().unwrap())))
+        }
+
+        fn restore_storage(&self, saved: &SavedStorage) {
+            *self.0.lock().unwrap() = *saved.0.downcast_ref::<u64>().expect("saved by this runtime");
+        }
+    }
+
+    #[test]
+    fn reverted_and_failed_blocks_put_contract_storage_back() {
+        let crypto = HeaderHash;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 50,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let counter = BlockCounter::default();
+        let engine = ExecutionEngine::new(&crypto, economics).with_contracts(&counter);
+        let mut state = ChainState::default();
+        // Enough balance for the callback to run; it uses no gas.
+        state.balances.insert([7; 32], 1);
+        let mut tree = ChainTree::new();
+
+        let genesis = block(None, &state, 9, 1);
+        let hash = tree.insert(&crypto, genesis.clone()).unwrap();
+        engine.reorg_to(&mut state, &mut tree, &hash).unwrap();
+        let a1 = block(Some(&genesis), &state, 1, 1);
+        let a1_hash = tree.insert(&crypto, a1.clone()).unwrap();
+        engine.reorg_to(&mut state, &mut tree, &a1_hash).unwrap();
+        assert_eq!(*counter.0.lock().unwrap(), 2);
+
+        // The coinbase is checked after the callback has run.
+        let mut bad = block(Some(&a1), &state, 1, 1);
+        bad.transactions[0].outputs[0].amount = 51;
+        bad.header.merkle_root = merkle_root(&bad.transactions);
+        assert!(engine.execute_block_with_undo(&mut state, &bad).is_err());
+        assert_eq!(*counter.0.lock().unwrap(), 2);
+
+        let undo = tree.undo(&a1_hash).cloned().unwrap();
+        engine.revert_block(&mut state, undo).unwrap();
+        assert_eq!((*counter.0.lock().unwrap(), state.tip_hash), (1, Some(hash)));
+    }
+
+    #[test]
+    fn reverting_blocks_puts_stakes_rewards_and_locks_back() {
+        let crypto = HeaderHash;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 50,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let engine = ExecutionEngine::new(&crypto, economics);
+        let owner = crypto.address_from_public_key(b"owner").unwrap();
+        let validator = [1; 32];
+        let coin = TxOutput { address: owner, amount: 100, condition: None, spendable_after_height: None };
+        let mut state = ChainState::default();
+        state.pending_utxos.insert([1; 32], vec![coin.clone(); 4]);
+        state.balances.insert(owner, 400);
+        let genesis = block(None, &state, 9, 1);
+        engine.apply_block(&mut state, &genesis).unwrap();
+
+        let spend = |index: u32, address: Address, memo: String| Transaction {
+            inputs: vec![TxInput {
+                previous_tx: [1; 32],
+                output_index: index,
+                signature: vec![],
+                public_key: b"owner".to_vec(),
+                witness: vec![],
+            }],
+            outputs: vec![TxOutput { address, ..coin.clone() }],
+            fee: 0,
+            nonce: index as u64,
+            memo: Some(memo),
+            lock_time: 0,
+        };
+        let stake = |action| StakeMemo { owner, action }.to_memo();
+        let next = |state: &ChainState, transactions| {
+            let header = BlockHeader {
+                previous_hash: state.tip_hash.unwrap(),
+                merkle_root: [0; 32],
+                height: state.next_height(),
+                timestamp: 0,
+                difficulty: 1,
+                nonce: 0,
+                validator,
+                stake_weight: 0,
+                state_root: state_root(state),
+            };
+            engine.complete_block(state, header, transactions).unwrap()
+        };
+        let ledgers = |state: &ChainState| {
+            let (staking, bridge) = (&state.staking, &state.bridge);
+            let staked = (staking.bonds.clone(), staking.unbonding.clone(), staking.rewards.clone());
+            (staked, bridge.escrowed.clone(), bridge.processed.clone(), bridge.outbox.clone())
+        };
+        let before = (ledgers(&state), state.balances.clone(), state.pending_utxos.clone());
+
+        // The delegation earns half of the first block's reward.
+        let delegate = spend(0, staking_pool_address(), stake(StakeAction::Delegate { validator }));
+        let lock = spend(1, bridge_escrow_address(), "bridge:eth:0xabc".into());
+        let first = next(&state, vec![delegate, lock]);
+        let (_, first_undo) = engine.execute_block_with_undo(&mut state, &first).unwrap();
+        assert_eq!((state.staking.rewards[&owner], state.bridge.outbox.len()), (50, 1));
+        let after_first = (ledgers(&state), state.balances.clone(), state.pending_utxos.clone());
+
+        let unstake = spend(2, owner, stake(StakeAction::Unstake { validator, amount: 40 }));
+        let withdraw = spend(3, owner, stake(StakeAction::WithdrawRewards));
+        let second = next(&state, vec![unstake, withdraw]);
+        let (_, second_undo) = engine.execute_block_with_undo(&mut state, &second).unwrap();
+        assert_eq!((state.staking.unbonding.len(), state.staking.validator_stake(&validator)), (1, 60));
+
+        engine.revert_block(&mut state, second_undo).unwrap();
+        assert!((ledgers(&state), state.balances.clone(), state.pending_utxos.clone()) == after_first);
+        engine.revert_block(&mut state, first_undo).unwrap();
+        assert!((ledgers(&state), state.balances.clone(), state.pending_utxos.clone()) == before);
+        assert_eq!((state.tip_height, state.tip_hash), (0, Some(genesis.header.hash())));
+    }
+}
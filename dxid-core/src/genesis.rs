@@ -0,0 +1,290 @@
+//! The genesis block and the file it is generated from: the chain id, the
+//! token economics, initial allocations and the validators' initial stake.
+//! Addresses in the file are base58 strings.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::bridge::bridge_escrow_address;
+use crate::staking::staking_pool_address;
+use crate::state_tree::EMPTY_ROOT;
+use crate::{
+    merkle_root, Address, Block, BlockHash, BlockHeader, ChainId, ChainState, CryptoProvider, TokenEconomics,
+    Transaction, TxOutput,
+};
+
+/// Memo of the genesis transaction.
+pub const GENESIS_MEMO: &str = "genesis";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisAllocation {
+    #[serde(with = "base58_address")]
+    pub address: Address,
+    pub amount: u64,
+}
+
+/// A validator and the stake it bonds to itself at genesis.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisValidator {
+    #[serde(with = "base58_address")]
+    pub address: Address,
+    pub stake: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisConfig {
+    pub chain_id: ChainId,
+    /// Unix seconds of the genesis block.
+    pub timestamp: u64,
+    pub economics: TokenEconomics,
+    #[serde(default)]
+    pub allocations: Vec<GenesisAllocation>,
+    #[serde(default)]
+    pub validators: Vec<GenesisValidator>,
+}
+
+impl GenesisConfig {
+    /// Checks the economics, that there is at least one validator, that no
+    /// address is listed twice or with nothing, and that allocations and
+    /// stake fit in the max supply.
+    pub fn validate(&self) -> Result<()> {
+        if self.chain_id.trim().is_empty() {
+            return Err(anyhow!("chain id is empty"));
+        }
+        let economics = &self.economics;
+        if economics.base_reward > economics.max_supply {
+            return Err(anyhow!("base reward exceeds the max supply"));
+        }
+        if economics.treasury_ratio_bps > 10_000 {
+            return Err(anyhow!("treasury ratio is over 10000 bps"));
+        }
+        match economics.treasury {
+            None if economics.treasury_ratio_bps > 0 => {
+                return Err(anyhow!("a treasury ratio needs a treasury address"));
+            }
+            Some(treasury) if [bridge_escrow_address(), staking_pool_address()].contains(&treasury) => {
+                return Err(anyhow!("the treasury cannot be the escrow or the staking pool"));
+            }
+            _ => {}
+        }
+        if self.validators.is_empty() {
+            return Err(anyhow!("genesis needs at least one validator"));
+        }
+        let reserved = [bridge_escrow_address(), staking_pool_address()];
+        let mut allocated = HashSet::new();
+        for alloc in &self.allocations {
+            if alloc.amount == 0 {
+                return Err(anyhow!("allocation to {} is zero", base58_address::encode(&alloc.address)));
+            }
+            if reserved.contains(&alloc.address) || !allocated.insert(alloc.address) {
+                return Err(anyhow!("{} cannot be allocated to", base58_address::encode(&alloc.address)));
+            }
+        }
+        let mut validators = HashSet::new();
+        for validator in &self.validators {
+            if validator.stake == 0 {
+                return Err(anyhow!("validator {} has no stake", base58_address::encode(&validator.address)));
+            }
+            if !validators.insert(validator.address) {
+                return Err(anyhow!(
+                    "validator {} is listed twice",
+                    base58_address::encode(&validator.address)
+                ));
+            }
+        }
+        let total = self.total_supply()?;
+        if total > economics.max_supply {
+            return Err(anyhow!("genesis issues {total}, over the max supply of {}", economics.max_supply));
+        }
+        Ok(())
+    }
+
+    /// Allocations plus stake: the supply issued at genesis.
+    pub fn total_supply(&self) -> Result<u64> {
+        self.allocations
+            .iter()
+            .map(|alloc| alloc.amount)
+            .chain(self.validators.iter().map(|validator| validator.stake))
+            .try_fold(0u64, |sum, amount| sum.checked_add(amount))
+            .ok_or_else(|| anyhow!("genesis supply overflows"))
+    }
+
+    /// The genesis block: one transaction without inputs paying the
+    /// allocations and, into the staking pool, each validator's stake. Its
+    /// validator is the first one listed.
+    pub fn block(&self) -> Result<Block> {
+        self.validate()?;
+        let pool = staking_pool_address();
+        let outputs = self
+            .allocations
+            .iter()
+            .map(|alloc| TxOutput {
+                address: alloc.address,
+                amount: alloc.amount,
+                condition: None,
+                spendable_after_height: None,
+            })
+            .chain(self.validators.iter().map(|validator| TxOutput {
+                address: pool,
+                amount: validator.stake,
+                condition: None,
+                spendable_after_height: None,
+            }))
+            .collect();
+        let tx = Transaction {
+            inputs: vec![],
+            outputs,
+            fee: 0,
+            nonce: 0,
+            memo: Some(GENESIS_MEMO.into()),
+            lock_time: 0,
+        };
+        let transactions = vec![tx];
+        Ok(Block {
+            header: BlockHeader {
+                previous_hash: [0u8; 32],
+                merkle_root: merkle_root(&transactions),
+                height: 0,
+                timestamp: self.timestamp,
+                difficulty: 1,
+                nonce: 0,
+                validator: self.validators[0].address,
+                stake_weight: self.validators.iter().map(|validator| validator.stake).sum(),
+                state_root: EMPTY_ROOT,
+            },
+            transactions,
+            pow_hash: [0u8; 32],
+            validator_signature: vec![],
+        })
+    }
+
+    /// Hash of the genesis block header, which identifies the chain.
+    pub fn hash<C: CryptoProvider + ?Sized>(&self, crypto: &C) -> Result<BlockHash> {
+        Ok(crypto.hash_block_header(&self.block()?.header))
+    }
+
+    /// State after the genesis block: allocations spendable as outputs of the
+    /// genesis transaction, each validator's stake bonded to itself, and the
+    /// genesis block as the tip.
+    pub fn initial_state(&self) -> Result<ChainState> {
+        let block = self.block()?;
+        let tx = &block.transactions[0];
+        let mut state = ChainState::default();
+        for out in &tx.outputs {
+            *state.balances.entry(out.address).or_insert(0) += out.amount;
+        }
+        state.pending_utxos.insert(tx.hash(), tx.outputs.clone());
+        for validator in &self.validators {
+            state.staking.bond(validator.address, validator.address, validator.stake)?;
+        }
+        state.total_issued = self.total_supply()?;
+        state.tip_hash = Some(block.header.hash());
+        Ok(state)
+    }
+}
+
+pub(crate) mod base58_address {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::Address;
+
+    pub fn encode(address: &Address) -> String {
+        bs58::encode(address).into_string()
+    }
+
+    pub fn serialize<S: Serializer>(address: &Address, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode(address))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Address, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let bytes = bs58::decode(&text).into_vec().map_err(D::Error::custom)?;
+        bytes.try_into().map_err(|_| D::Error::custom(format!("{text} is not a 32-byte address")))
+    }
+
+    /// An address that may be left out.
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        use crate::Address;
+
+        pub fn serialize<S: Serializer>(address: &Option<Address>, serializer: S) -> Result<S::Ok, S::Error> {
+            match address {
+                Some(address) => super::serialize(address, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Address>, D::Error> {
+            #[derive(Deserialize)]
+            struct Wrapped(#[serde(with = "super")] Address);
+            Ok(Option::<Wrapped>::deserialize(deserializer)?.map(|Wrapped(address)| address))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HalvingSchedule;
+
+    fn genesis() -> GenesisConfig {
+        GenesisConfig {
+            chain_id: "dxid-test".into(),
+            timestamp: 1_700_000_000,
+            economics: TokenEconomics {
+                max_supply: 1_000,
+                base_reward: 10,
+                schedule: HalvingSchedule { target_interval: 100, supply_threshold: 500 },
+                treasury_ratio_bps: 500,
+                treasury: Some([3u8; 32]),
+            },
+            allocations: vec![GenesisAllocation { address: [1u8; 32], amount: 300 }],
+            validators: vec![GenesisValidator { address: [2u8; 32], stake: 200 }],
+        }
+    }
+
+    #[test]
+    fn genesis_builds_state_and_rejects_bad_files() {
+        let config = genesis();
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains(&bs58::encode([1u8; 32]).into_string()));
+        assert!(json.contains(&bs58::encode([3u8; 32]).into_string()));
+        let parsed: GenesisConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.economics.treasury, Some([3u8; 32]));
+        assert_eq!(
+            parsed.block().unwrap().transactions[0].hash(),
+            config.block().unwrap().transactions[0].hash()
+        );
+
+        let state = config.initial_state().unwrap();
+        assert_eq!(state.balances[&[1u8; 32]], 300);
+        assert_eq!(state.balances[&staking_pool_address()], 200);
+        assert_eq!(state.staking.validator_stake(&[2u8; 32]), 200);
+        assert_eq!(state.total_issued, 500);
+        assert_eq!((state.next_height(), state.tip_hash), (1, Some(config.block().unwrap().header.hash())));
+
+        let mut over = genesis();
+        over.allocations[0].amount = 900;
+        assert!(over.validate().is_err());
+        let mut twice = genesis();
+        twice.validators.push(twice.validators[0].clone());
+        assert!(twice.validate().is_err());
+        let mut none = genesis();
+        none.validators.clear();
+        assert!(none.validate().is_err());
+        let mut pool = genesis();
+        pool.allocations[0].address = staking_pool_address();
+        assert!(pool.validate().is_err());
+        let mut untreasured = genesis();
+        untreasured.economics.treasury = None;
+        assert!(untreasured.validate().is_err());
+        untreasured.economics.treasury_ratio_bps = 0;
+        untreasured.validate().unwrap();
+        let mut pooled = genesis();
+        pooled.economics.treasury = Some(staking_pool_address());
+        assert!(pooled.validate().is_err());
+    }
+}
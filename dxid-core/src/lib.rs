@@ -1,13 +1,45 @@
+pub mod bridge;
+pub mod builder;
+pub mod coinbase;
+pub mod condition;
+pub mod contract;
+pub mod encoding;
+pub mod error;
+pub mod events;
+pub mod fork;
+pub mod genesis;
+pub mod mempool;
+pub mod multisig;
+pub mod staking;
+pub mod state_tree;
+
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use blake3::Hasher;
 use chrono::{DateTime, Utc};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
+use bridge::{
+    bridge_escrow_address, check_release, lock_id, parse_bridge_memo, parse_delivery, parse_release,
+    BridgeLedger, BridgeRelease, BridgeTransfer, InboundVerifier, ProvenMessage,
+};
+use coinbase::{apply_coinbase, coinbase_transaction, is_coinbase, Payout, COINBASE_MATURITY};
+use condition::SpendCondition;
+use contract::{
+    apply_contract_transfers, contract_seed, parse_contract_call, refund_unused_fee, scheduled_call_hash,
+    ContractCall, ContractCallEnv, ContractRuntime, TxReceipt, DEFAULT_GAS_PRICE,
+};
+use encoding::CanonicalEncode;
+use error::CoreError;
+use mempool::UtxoView;
+use multisig::{MultisigOutput, MultisigPolicy};
+use staking::{apply_stake_memo, parse_stake_memo, staking_pool_address, StakingLedger};
+use state_tree::state_root;
+
 /// Address is derived from a public key hash and is 32 bytes.
 pub type Address = [u8; 32];
 pub type TxHash = [u8; 32];
@@ -37,18 +69,64 @@ pub struct Identity {
     pub status: IdentityStatus,
 }
 
+/// Two identities whose attributes are similar enough that they may belong
+/// to the same person.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PossibleDuplicate {
+    /// The identity whose change was checked.
+    pub identity: IdentityId,
+    pub duplicate_of: IdentityId,
+    /// Cosine similarity of their attribute embeddings, up to 1.
+    pub similarity: f32,
+    pub detected_at: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TxInput {
     pub previous_tx: TxHash,
     pub output_index: u32,
     pub signature: Vec<u8>,
     pub public_key: Vec<u8>,
+    /// What the spent output's condition asks for besides signatures: the
+    /// preimage of a hash lock.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub witness: Vec<u8>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TxOutput {
     pub address: Address,
     pub amount: u64,
+    /// Who may spend the output when it is not the holder of a key; the
+    /// output then pays to the condition's address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<SpendCondition>,
+    /// The output may only be spent in blocks above this height, as in a
+    /// vesting schedule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spendable_after_height: Option<u64>,
+}
+
+impl TxOutput {
+    /// Checks that a condition the output carries is valid and paid to.
+    pub fn check_condition(&self) -> Result<(), CoreError> {
+        let Some(condition) = &self.condition else {
+            return Ok(());
+        };
+        condition.validate()?;
+        if condition.address() != self.address {
+            return Err(CoreError::ConditionAddress);
+        }
+        Ok(())
+    }
+
+    /// Checks that a block at `height` may spend the output.
+    pub fn check_spendable(&self, height: u64) -> Result<(), CoreError> {
+        match self.spendable_after_height {
+            Some(after) if height <= after => Err(CoreError::Timelocked { until: after.saturating_add(1) }),
+            _ => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,15 +136,40 @@ pub struct Transaction {
     pub fee: u64,
     pub nonce: u64,
     pub memo: Option<String>,
+    /// Height from which the transaction may be in a block; 0 for any.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub lock_time: u64,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
 }
 
 impl Transaction {
+    /// Blake3 of the transaction's canonical encoding; see [`encoding`].
     pub fn hash(&self) -> TxHash {
-        let mut hasher = Hasher::new();
-        let encoded = serde_json::to_vec(self).unwrap();
-        hasher.update(&encoded);
-        hasher.finalize().into()
+        self.canonical_hash()
     }
+
+    /// Hash committed to by input signatures: the transaction with every
+    /// signature and witness cleared, so signing one input does not change it.
+    pub fn signing_hash(&self) -> TxHash {
+        let mut unsigned = self.clone();
+        for input in &mut unsigned.inputs {
+            input.signature.clear();
+            input.witness.clear();
+        }
+        unsigned.hash()
+    }
+}
+
+/// Bytes signed for `input`: the outpoint it spends and the signing hash.
+pub fn signing_message(input: &TxInput, sighash: &TxHash) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(68);
+    msg.extend_from_slice(&input.previous_tx);
+    msg.extend_from_slice(&input.output_index.to_le_bytes());
+    msg.extend_from_slice(sighash);
+    msg
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -79,6 +182,16 @@ pub struct BlockHeader {
     pub nonce: u64,
     pub validator: Address,
     pub stake_weight: u64,
+    /// Root of the state the block applies to, see [`state_tree`].
+    #[serde(default)]
+    pub state_root: BlockHash,
+}
+
+impl BlockHeader {
+    /// Blake3 of the header's canonical encoding, which identifies the block.
+    pub fn hash(&self) -> BlockHash {
+        self.canonical_hash()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -129,6 +242,10 @@ pub struct TokenEconomics {
     pub base_reward: u64,
     pub schedule: HalvingSchedule,
     pub treasury_ratio_bps: u16,
+    /// Address the treasury's share of rewards and fees is paid to, whose
+    /// key the chain's governance holds; no share is taken without one.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "genesis::base58_address::option")]
+    pub treasury: Option<Address>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -139,6 +256,26 @@ pub struct ChainState {
     pub total_issued: u64,
     pub issued_rewards: u64,
     pub pending_utxos: HashMap<TxHash, Vec<TxOutput>>,
+    /// Height of the block each coinbase is in, for the maturity of its outputs.
+    pub coinbases: HashMap<TxHash, u64>,
+    pub bridge: BridgeLedger,
+    pub staking: StakingLedger,
+    /// Height of the last applied block; see [`ChainState::next_height`].
+    pub tip_height: u64,
+    /// Header hash of the last applied block, `None` until genesis is applied.
+    pub tip_hash: Option<BlockHash>,
+    /// Changes of the block being applied with an undo, oldest first.
+    pub(crate) journal: Option<Vec<fork::Change>>,
+}
+
+impl ChainState {
+    /// Height the next block must have: 0 before genesis, then one past the tip.
+    pub fn next_height(&self) -> u64 {
+        match self.tip_hash {
+            Some(_) => self.tip_height + 1,
+            None => 0,
+        }
+    }
 }
 
 #[async_trait]
@@ -152,11 +289,33 @@ pub trait CryptoProvider: Send + Sync + 'static {
 pub struct ExecutionEngine<'a, C: CryptoProvider> {
     pub crypto: &'a C,
     pub economics: TokenEconomics,
+    /// Runs contract calls; without one, transactions calling a contract are rejected.
+    pub contracts: Option<&'a dyn ContractRuntime>,
+    /// Fee per unit of contract gas; 0 makes contract gas free.
+    pub gas_price: u64,
+    /// Checks the inbound messages bridge transactions carry; without one,
+    /// they are rejected.
+    pub inbound: Option<&'a dyn InboundVerifier>,
 }
 
 impl<'a, C: CryptoProvider> ExecutionEngine<'a, C> {
     pub fn new(crypto: &'a C, economics: TokenEconomics) -> Self {
-        Self { crypto, economics }
+        Self { crypto, economics, contracts: None, gas_price: DEFAULT_GAS_PRICE, inbound: None }
+    }
+
+    pub fn with_contracts(mut self, contracts: &'a dyn ContractRuntime) -> Self {
+        self.contracts = Some(contracts);
+        self
+    }
+
+    pub fn with_inbound(mut self, inbound: &'a dyn InboundVerifier) -> Self {
+        self.inbound = Some(inbound);
+        self
+    }
+
+    pub fn with_gas_price(mut self, gas_price: u64) -> Self {
+        self.gas_price = gas_price;
+        self
     }
 
     pub fn current_reward(&self, height: u64, total_issued: u64) -> u64 {
@@ -177,114 +336,499 @@ impl<'a, C: CryptoProvider> ExecutionEngine<'a, C> {
             .unwrap_or(0)
     }
 
-    pub fn apply_block(&self, state: &mut ChainState, block: &Block) -> Result<()> {
+    pub fn apply_block(&self, state: &mut ChainState, block: &Block) -> Result<(), CoreError> {
+        self.execute_block(state, block).map(|_| ())
+    }
+
+    /// Applies `block` and returns a receipt per transaction, in block order.
+    pub fn execute_block(&self, state: &mut ChainState, block: &Block) -> Result<Vec<TxReceipt>, CoreError> {
         // Verify block hash target (PoW) and validator signature are performed upstream.
         let merkle = merkle_root(&block.transactions);
         if merkle != block.header.merkle_root {
-            return Err(anyhow!("invalid merkle root"));
+            return Err(CoreError::InvalidMerkleRoot);
         }
-        // ensure monotonic height
-        if block.header.height != 0 && block.header.height != self.next_height(state)? {
-            return Err(anyhow!("unexpected height"));
+        // Blocks apply in order, each on top of the tip.
+        let height = block.header.height;
+        let expected = state.next_height();
+        if height != expected {
+            return Err(CoreError::UnexpectedHeight { height, expected });
         }
+        if block.header.previous_hash != state.tip_hash.unwrap_or([0u8; 32]) {
+            return Err(CoreError::NotOnTip { height });
+        }
+        if block.header.state_root != state_root(state) {
+            return Err(CoreError::WrongStateRoot { height });
+        }
+        // Only the outputs of a coinbase are up to the validator.
+        let (coinbase, transactions) =
+            block.transactions.split_first().ok_or(CoreError::MissingCoinbase { height })?;
+        if coinbase.hash() != coinbase_transaction(height, coinbase.outputs.clone()).hash() {
+            return Err(CoreError::MisplacedCoinbase { height });
+        }
+        let mut receipts = vec![TxReceipt::new(coinbase.hash(), height, 0)];
+        let fees = self.execute_transactions(state, &block.header, transactions, &mut receipts)?;
+        let payout = self.settle_reward(state, &block.header, fees);
+        apply_coinbase(state, coinbase, height, &payout)?;
+        state.tip_height = height;
+        state.tip_hash = Some(self.crypto.hash_block_header(&block.header));
+        Ok(receipts)
+    }
+
+    /// Completes a block of `transactions` on `header` for its validator:
+    /// runs them on a copy of `state` and puts first the coinbase paying
+    /// the reward and fees to the validator, the treasury and the staking
+    /// pool for its delegators. The merkle root is set; the rest of the
+    /// header is left as given.
+    pub fn complete_block(
+        &self,
+        state: &ChainState,
+        mut header: BlockHeader,
+        mut transactions: Vec<Transaction>,
+    ) -> Result<Block, CoreError> {
+        let mut state = state.clone();
+        let mut receipts = vec![TxReceipt::new([0u8; 32], header.height, 0)];
+        let fees = self.execute_transactions(&mut state, &header, &transactions, &mut receipts)?;
+        let payout = self.settle_reward(&mut state, &header, fees);
+        transactions.insert(0, coinbase_transaction(header.height, payout.outputs(header.validator)));
+        header.merkle_root = merkle_root(&transactions);
+        Ok(Block { header, transactions, pow_hash: [0u8; 32], validator_signature: vec![] })
+    }
+
+    /// Applies the transactions after a block's coinbase and then its
+    /// scheduled calls, adding their receipts, and returns the fees paid.
+    fn execute_transactions(
+        &self,
+        state: &mut ChainState,
+        header: &BlockHeader,
+        transactions: &[Transaction],
+        receipts: &mut Vec<TxReceipt>,
+    ) -> Result<u64, CoreError> {
+        state.staking.height = header.height;
         let mut spent: HashSet<(TxHash, u32)> = HashSet::new();
-        for tx in &block.transactions {
-            self.apply_transaction(state, tx, &mut spent)?;
-        }
-        let reward = self.current_reward(block.header.height, state.total_issued);
-        let treasury_cut = reward * self.economics.treasury_ratio_bps as u64 / 10_000;
-        let miner_reward = reward.saturating_sub(treasury_cut);
-        Self::credit(state, &block.header.validator, miner_reward)?;
-        state.total_issued = (state.total_issued + reward).min(self.economics.max_supply);
+        for tx in transactions {
+            if is_coinbase(tx) {
+                return Err(CoreError::ExtraCoinbase { height: header.height });
+            }
+            let mut receipt = TxReceipt::new(tx.hash(), header.height, receipts.len() as u32);
+            self.apply_transaction(state, header, tx, &mut spent, &mut receipt)?;
+            receipts.push(receipt);
+        }
+        self.run_scheduled_calls(state, header, receipts)?;
+        receipts
+            .iter()
+            .try_fold(0u64, |sum, r| sum.checked_add(r.fee_paid))
+            .ok_or(CoreError::Overflow("fee"))
+    }
+
+    /// Issues the reward of the block on `header`, up to the max supply,
+    /// and splits it with the `fees` paid in the block: the treasury takes
+    /// its ratio and the rest is shared among the validator's delegators.
+    /// Returns what the coinbase must pay.
+    fn settle_reward(&self, state: &mut ChainState, header: &BlockHeader, fees: u64) -> Payout {
+        let reward = self
+            .current_reward(header.height, state.total_issued)
+            .min(self.economics.max_supply.saturating_sub(state.total_issued));
+        // Fees are paid out of spent inputs, not issued, so they add to the
+        // payout but not to the supply.
+        let total = reward.saturating_add(fees);
+        let treasury_address = self.economics.treasury;
+        let treasury = match treasury_address {
+            Some(_) => (total as u128 * self.economics.treasury_ratio_bps as u128 / 10_000) as u64,
+            None => 0,
+        };
+        let kept = state.distribute(&header.validator, total - treasury);
+        state.total_issued += reward;
         state.issued_rewards += reward;
+        Payout { total, treasury, treasury_address, delegated: total - treasury - kept }
+    }
+
+    /// Runs the contract callbacks due at `header`'s height after its
+    /// transactions. Each pays for its gas out of the contract's balance, and
+    /// one the balance cannot pay any gas for is skipped with a failed receipt.
+    fn run_scheduled_calls(
+        &self,
+        state: &mut ChainState,
+        header: &BlockHeader,
+        receipts: &mut Vec<TxReceipt>,
+    ) -> Result<(), CoreError> {
+        let Some(runtime) = self.contracts else {
+            return Ok(());
+        };
+        for scheduled in runtime.scheduled_calls(header.height) {
+            let index = receipts.len() as u32;
+            let hash = scheduled_call_hash(header.height, index);
+            let mut receipt = TxReceipt::new(hash, header.height, index);
+            let contract = scheduled.contract;
+            let held = state.balances.get(&contract).copied().unwrap_or(0);
+            state.touch_balance(&contract);
+            let affordable = held.checked_div(self.gas_price).unwrap_or(u64::MAX);
+            let limit = scheduled.gas_limit.min(affordable);
+            if limit == 0 {
+                receipt.success = false;
+                receipt.error = Some("contract balance cannot pay for gas".into());
+                receipts.push(receipt);
+                continue;
+            }
+            // Cannot overflow: the limit is at most what the balance pays for.
+            let reserved = limit * self.gas_price;
+            let env = ContractCallEnv {
+                caller: contract,
+                block_height: header.height,
+                timestamp: header.timestamp,
+                seed: contract_seed(&header.previous_hash, &hash),
+                gas_limit: Some(limit),
+                value: 0,
+                balance: held - reserved,
+            };
+            let call = ContractCall { contract, method: scheduled.method, input: Vec::new() };
+            let outcome = runtime.call_scheduled(&env, &call).and_then(|result| match result.gas_used {
+                used if used > limit => Err(anyhow!("callback used {used} gas but can pay for {limit}")),
+                _ => Ok(result),
+            });
+            match outcome {
+                Ok(result) => {
+                    receipt.fee_paid = result.gas_used * self.gas_price;
+                    state.balances.insert(contract, held - receipt.fee_paid);
+                    apply_contract_transfers(state, &hash, &contract, &result.transfers)
+                        .map_err(CoreError::contract_payout)?;
+                    receipt.gas_used = result.gas_used;
+                    receipt.events = result.events;
+                    receipt.transfers = result.transfers;
+                }
+                Err(err) => {
+                    receipt.fee_paid = reserved;
+                    state.balances.insert(contract, held - reserved);
+                    receipt.success = false;
+                    receipt.error = Some(err.to_string());
+                }
+            }
+            receipts.push(receipt);
+        }
         Ok(())
     }
 
     fn apply_transaction(
         &self,
         state: &mut ChainState,
+        header: &BlockHeader,
         tx: &Transaction,
         spent: &mut HashSet<(TxHash, u32)>,
-    ) -> Result<()> {
+        receipt: &mut TxReceipt,
+    ) -> Result<(), CoreError> {
         let tx_hash = tx.hash();
+        let sighash = tx.signing_hash();
         let mut input_total = 0u64;
+        let mut owners = HashSet::new();
+        let mut caller = None;
+        if let Some(proven) = parse_delivery(tx) {
+            return self.apply_delivery(state, tx, &proven);
+        }
         if tx.inputs.is_empty() && tx.outputs.is_empty() {
-            return Err(anyhow!("empty transaction"));
+            return Err(CoreError::EmptyTransaction);
+        }
+        if header.height < tx.lock_time {
+            return Err(CoreError::TransactionLocked { lock_time: tx.lock_time });
         }
+        let escrow = bridge_escrow_address();
+        // A release spends escrow outputs on the strength of a verified burn.
+        let release = match parse_release(tx) {
+            Some(proven) => Some(self.verify_release(&proven)?),
+            None => None,
+        };
         for input in &tx.inputs {
             if !spent.insert((input.previous_tx, input.output_index)) {
-                return Err(anyhow!("double spend detected"));
+                return Err(CoreError::DoubleSpend);
+            }
+            if let Some(mined) = state.coinbases.get(&input.previous_tx) {
+                if header.height < mined + COINBASE_MATURITY {
+                    return Err(CoreError::ImmatureCoinbase);
+                }
             }
             let prev_outputs = state
                 .pending_utxos
                 .get(&input.previous_tx)
-                .ok_or_else(|| anyhow!("missing previous tx"))?;
+                .ok_or(CoreError::MissingPreviousTx)?;
             let output = prev_outputs
                 .get(input.output_index as usize)
-                .ok_or_else(|| anyhow!("missing output index"))?;
-            let pk_hash = self.crypto.address_from_public_key(&input.public_key)?;
-            if pk_hash != output.address {
-                return Err(anyhow!("input not owned by signer"));
+                .ok_or(CoreError::MissingOutput)?;
+            match (output.address == escrow, release.is_some()) {
+                (true, false) => return Err(CoreError::EscrowedInput),
+                (false, true) => return Err(CoreError::ReleaseInput),
+                _ => {}
             }
-            let mut msg = Vec::new();
-            msg.extend_from_slice(&input.previous_tx);
-            msg.extend_from_slice(&input.output_index.to_le_bytes());
-            msg.extend_from_slice(&tx_hash);
-            if !self.crypto.verify_signature(&input.public_key, &msg, &input.signature)? {
-                return Err(anyhow!("signature invalid"));
+            if output.address == staking_pool_address() {
+                return Err(CoreError::StakedInput);
+            }
+            output.check_spendable(header.height)?;
+            if release.is_some() {
+                // The escrow has no key; the verified burn authorises the spend.
+                input_total = input_total
+                    .checked_add(output.amount)
+                    .ok_or(CoreError::Overflow("input"))?;
+                continue;
+            }
+            owners.insert(output.address);
+            caller.get_or_insert(output.address);
+            match &output.condition {
+                Some(condition) => {
+                    condition.check(self.crypto, input, header.height, &signing_message(input, &sighash))?
+                }
+                None => {
+                    let shared = MultisigPolicy::decode(&input.public_key)
+                        .map(|policy| policy.address())
+                        .or_else(|| MultisigOutput::decode(&input.public_key).map(|m| m.address()));
+                    match shared {
+                        Some(address) if address != output.address => {
+                            return Err(CoreError::NotMultisigOwner)
+                        }
+                        Some(_) => {}
+                        None => {
+                            let signer = self
+                                .crypto
+                                .address_from_public_key(&input.public_key)
+                                .map_err(CoreError::crypto)?;
+                            if signer != output.address {
+                                return Err(CoreError::NotSigner);
+                            }
+                        }
+                    }
+                    verify_input_signature(self.crypto, input, &sighash)?;
+                }
             }
             input_total = input_total
                 .checked_add(output.amount)
-                .ok_or_else(|| anyhow!("input overflow"))?;
+                .ok_or(CoreError::Overflow("input"))?;
         }
         let mut output_total = 0u64;
         for out in &tx.outputs {
+            out.check_condition()?;
             output_total = output_total
                 .checked_add(out.amount)
-                .ok_or_else(|| anyhow!("output overflow"))?;
+                .ok_or(CoreError::Overflow("output"))?;
+        }
+        let needed = output_total.checked_add(tx.fee).ok_or(CoreError::Overflow("output"))?;
+        if input_total < needed {
+            return Err(CoreError::InsufficientInputs { inputs: input_total, needed });
         }
-        if input_total < output_total + tx.fee {
-            return Err(anyhow!("insufficient input amount"));
+        // Whatever the inputs hold beyond the outputs is paid as fee, so
+        // the block's coinbase collects it.
+        let surplus = input_total - needed;
+        receipt.fee_paid = tx.fee + surplus;
+        if let Some(release) = &release {
+            check_release(tx, release, input_total).map_err(CoreError::bridge)?;
+            state.release(release).map_err(CoreError::bridge)?;
+            // The escrow's balance follows its outputs; the change is credited back below.
+            let held = state.balances.get(&escrow).copied().unwrap_or(0);
+            state.touch_balance(&escrow);
+            state.balances.insert(escrow, held.saturating_sub(input_total));
         }
         // Update balances and UTXO set
-        for out in &tx.outputs {
+        for (index, out) in tx.outputs.iter().enumerate() {
+            if out.address == escrow && release.is_none() {
+                let (dest, recipient) = tx
+                    .memo
+                    .as_deref()
+                    .and_then(parse_bridge_memo)
+                    .ok_or(CoreError::MissingBridgeMemo)?;
+                let sender = caller.ok_or(CoreError::UnsignedBridgeLock)?;
+                let transfer = BridgeTransfer {
+                    id: lock_id(&tx_hash, index as u32),
+                    sender,
+                    dest,
+                    recipient,
+                    amount: out.amount,
+                    lock_tx: tx_hash,
+                };
+                state.lock(transfer, header.height).map_err(CoreError::bridge)?;
+            }
             Self::credit(state, &out.address, out.amount)?;
         }
+        let pool = staking_pool_address();
+        let pooled: u64 = tx
+            .outputs
+            .iter()
+            .filter(|out| out.address == pool)
+            .map(|out| out.amount)
+            .sum();
+        match tx.memo.as_deref().and_then(parse_stake_memo) {
+            Some(memo) if !owners.contains(&memo.owner) => {
+                return Err(CoreError::StakeOwnerNotSigner);
+            }
+            Some(memo) => apply_stake_memo(state, &tx_hash, &memo, pooled).map_err(CoreError::staking)?,
+            None if pooled > 0 => return Err(CoreError::MissingStakeMemo),
+            None => {}
+        }
+        if let Some(call) = tx.memo.as_deref().and_then(parse_contract_call) {
+            let runtime = self.contracts.ok_or(CoreError::ContractsDisabled)?;
+            let caller = caller.ok_or(CoreError::UnsignedContractCall)?;
+            let value: u64 = tx
+                .outputs
+                .iter()
+                .filter(|out| out.address == call.contract)
+                .map(|out| out.amount)
+                .sum();
+            let env = ContractCallEnv {
+                caller,
+                block_height: header.height,
+                timestamp: header.timestamp,
+                seed: contract_seed(&header.previous_hash, &tx_hash),
+                value,
+                balance: state.balances.get(&call.contract).copied().unwrap_or(0),
+                gas_limit: tx.fee.checked_div(self.gas_price),
+            };
+            let outcome = runtime.call(&env, &call).and_then(|result| match env.gas_limit {
+                Some(limit) if result.gas_used > limit => {
+                    Err(anyhow!("call used {} gas but the fee pays for {limit}", result.gas_used))
+                }
+                _ => Ok(result),
+            });
+            match outcome {
+                Ok(result) => {
+                    apply_contract_transfers(state, &tx_hash, &call.contract, &result.transfers)
+                        .map_err(CoreError::contract_payout)?;
+                    if self.gas_price > 0 {
+                        // Cannot overflow: gas_used is within the fee's gas limit.
+                        let gas_fee = result.gas_used * self.gas_price;
+                        refund_unused_fee(state, &tx_hash, &caller, tx.fee - gas_fee)
+                            .map_err(CoreError::contract_payout)?;
+                        receipt.fee_paid = gas_fee + surplus;
+                    }
+                    receipt.gas_used = result.gas_used;
+                    receipt.events = result.events;
+                    receipt.transfers = result.transfers;
+                }
+                Err(err) => {
+                    // A failed call reports no gas used, so it forfeits the whole fee.
+                    let refund = if value > 0 { vec![(caller, value)] } else { Vec::new() };
+                    apply_contract_transfers(state, &tx_hash, &call.contract, &refund)
+                        .map_err(CoreError::contract_payout)?;
+                    receipt.success = false;
+                    receipt.error = Some(err.to_string());
+                    receipt.transfers = refund;
+                }
+            }
+        }
         // Remove spent outputs
         for input in &tx.inputs {
+            state.touch_outputs(&input.previous_tx);
             if let Some(prev_outputs) = state.pending_utxos.get_mut(&input.previous_tx) {
                 if input.output_index as usize >= prev_outputs.len() {
-                    return Err(anyhow!("output index out of bounds"));
+                    return Err(CoreError::MissingOutput);
                 }
                 prev_outputs[input.output_index as usize].amount = 0;
             }
         }
+        state.touch_outputs(&tx_hash);
         state
             .pending_utxos
             .insert(tx_hash, tx.outputs.clone());
         Ok(())
     }
 
-    fn credit(state: &mut ChainState, addr: &Address, amount: u64) -> Result<()> {
+    /// The release the burn carried by a release transaction authorises.
+    fn verify_release(&self, proven: &ProvenMessage) -> Result<BridgeRelease, CoreError> {
+        match self.verify_inbound(proven)? {
+            Some(release) => Ok(release),
+            None => Err(CoreError::InboundRefused(format!("message {} is not a burn", proven.message.id))),
+        }
+    }
+
+    /// Records the inbound message a delivery transaction carries. A burn
+    /// is only delivered by the release it authorises.
+    fn apply_delivery(
+        &self,
+        state: &mut ChainState,
+        tx: &Transaction,
+        proven: &ProvenMessage,
+    ) -> Result<(), CoreError> {
+        if !tx.inputs.is_empty() || !tx.outputs.is_empty() || tx.fee != 0 {
+            return Err(CoreError::InvalidDelivery);
+        }
+        if self.verify_inbound(proven)?.is_some() {
+            let id = proven.message.id;
+            return Err(CoreError::InboundRefused(format!("burn {id} is delivered by a release")));
+        }
+        state.deliver(&proven.message).map_err(CoreError::bridge)
+    }
+
+    fn verify_inbound(&self, proven: &ProvenMessage) -> Result<Option<BridgeRelease>, CoreError> {
+        let verifier = self.inbound.ok_or(CoreError::BridgeDisabled)?;
+        verifier
+            .verify_inbound(&proven.message, &proven.proof)
+            .map_err(|err| CoreError::InboundRefused(format!("{err:#}")))
+    }
+
+    fn credit(state: &mut ChainState, addr: &Address, amount: u64) -> Result<(), CoreError> {
+        state.touch_balance(addr);
         let entry = state.balances.entry(*addr).or_insert(0);
         *entry = entry
             .checked_add(amount)
-            .ok_or_else(|| anyhow!("balance overflow"))?;
+            .ok_or(CoreError::Overflow("balance"))?;
         Ok(())
     }
+}
 
-    fn next_height(&self, state: &ChainState) -> Result<u64> {
-        // Approximate next height by counting blocks from issued rewards + transactions.
-        // In a real system height tracking would be stored; here we derive from UTXO length.
-        let computed = state
-            .pending_utxos
-            .len()
-            .try_into()
-            .unwrap_or_default();
-        Ok(computed)
+/// Checks the signature of `input`, by its key or, for a multisig policy or
+/// output, its cosigners, over the signing hash of its transaction.
+pub fn verify_input_signature<C: CryptoProvider>(
+    crypto: &C,
+    input: &TxInput,
+    sighash: &TxHash,
+) -> Result<(), CoreError> {
+    let msg = signing_message(input, sighash);
+    let verified = if let Some(policy) = MultisigPolicy::decode(&input.public_key) {
+        policy.verify(crypto, &msg, &input.signature)
+    } else if let Some(multisig) = MultisigOutput::decode(&input.public_key) {
+        multisig.verify(crypto, &msg, &input.signature)
+    } else {
+        return match crypto.verify_signature(&input.public_key, &msg, &input.signature) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(CoreError::InvalidSignature),
+            Err(err) => Err(CoreError::crypto(err)),
+        };
+    };
+    match verified {
+        Ok(true) => Ok(()),
+        // A malformed witness is as good as a missing signature.
+        Ok(false) | Err(_) => Err(CoreError::MultisigThreshold),
     }
 }
 
+/// Checks `tx`, offered for the next block of `utxos`, against the outputs
+/// its inputs spend: its outputs' conditions, its lock time, and for each
+/// input that the output is unspent, matured if a coinbase paid it and past
+/// its time lock, and that its spending condition or signature holds.
+/// Nodes make these checks before a signed transaction enters the mempool.
+pub fn check_signed_transaction<C: CryptoProvider>(
+    crypto: &C,
+    tx: &Transaction,
+    utxos: &dyn UtxoView,
+) -> Result<(), CoreError> {
+    let height = utxos.next_height();
+    for output in &tx.outputs {
+        output.check_condition()?;
+    }
+    if tx.lock_time > height {
+        return Err(CoreError::TransactionLocked { lock_time: tx.lock_time });
+    }
+    let sighash = tx.signing_hash();
+    for input in &tx.inputs {
+        let output = utxos.unspent(&input.previous_tx, input.output_index).ok_or(CoreError::MissingOutput)?;
+        if let Some(mined) = utxos.coinbase_height(&input.previous_tx) {
+            if height < mined + COINBASE_MATURITY {
+                return Err(CoreError::ImmatureCoinbase);
+            }
+        }
+        output.check_spendable(height)?;
+        match &output.condition {
+            Some(condition) => condition.check(crypto, input, height, &signing_message(input, &sighash))?,
+            None if input.public_key.is_empty() => return Err(CoreError::NotSigner),
+            None => verify_input_signature(crypto, input, &sighash)?,
+        }
+    }
+    Ok(())
+}
+
 pub fn merkle_root(transactions: &[Transaction]) -> BlockHash {
     if transactions.is_empty() {
         return [0u8; 32];
@@ -393,9 +937,68 @@ pub fn build_oauth_like_challenge(audience: String, scope: Vec<String>) -> OAuth
     }
 }
 
+const OAUTH_PROOF_DOMAIN: &str = "dxid/oauth-like-proof/v1";
+
+/// Bytes an identity key signs to answer `request`: the whole request, who
+/// answers, when, and exactly which attributes are disclosed.
+pub fn oauth_like_proof_message(
+    request: &OAuthLikeProofRequest,
+    identity_id: &IdentityId,
+    issued_at: &DateTime<Utc>,
+    disclosed: &HashMap<String, String>,
+) -> Vec<u8> {
+    let disclosed: BTreeMap<&String, &String> = disclosed.iter().collect();
+    serde_json::to_vec(&(
+        OAUTH_PROOF_DOMAIN,
+        &request.audience,
+        &request.scope,
+        request.nonce,
+        &request.challenge,
+        identity_id,
+        issued_at,
+        disclosed,
+    ))
+    .expect("proof message serializes")
+}
+
+/// Checks `response` against the request it answers and the identity's
+/// current (most recently rotated) key. Disclosed attributes must be in
+/// the requested scope and match the identity's values.
+pub fn verify_oauth_like_proof<C: CryptoProvider + ?Sized>(
+    crypto: &C,
+    identity: &Identity,
+    request: &OAuthLikeProofRequest,
+    response: &OAuthLikeProofResponse,
+) -> Result<bool> {
+    if response.identity_id != identity.id || identity.status != IdentityStatus::Active {
+        return Ok(false);
+    }
+    let disclosed_ok = response.disclosed_attributes.iter().all(|(key, value)| {
+        request.scope.contains(key)
+            && identity
+                .attributes
+                .get(key)
+                .is_some_and(|attr| &attr.value == value)
+    });
+    let Some(current_key) = identity.public_keys.last() else {
+        return Ok(false);
+    };
+    if !disclosed_ok {
+        return Ok(false);
+    }
+    let message = oauth_like_proof_message(
+        request,
+        &response.identity_id,
+        &response.issued_at,
+        &response.disclosed_attributes,
+    );
+    crypto.verify_signature(current_key, &message, &response.signature)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use contract::{ContractCallResult, ContractEvent, ScheduledCall};
 
     struct DummyCrypto;
 
@@ -415,8 +1018,7 @@ mod tests {
         }
 
         fn hash_block_header(&self, header: &BlockHeader) -> BlockHash {
-            let bytes = serde_json::to_vec(header).unwrap();
-            blake3::hash(&bytes).into()
+            header.hash()
         }
     }
 
@@ -427,10 +1029,13 @@ mod tests {
             outputs: vec![TxOutput {
                 address: [1u8; 32],
                 amount: 10,
+                condition: None,
+                spendable_after_height: None,
             }],
             fee: 0,
             nonce: 0,
             memo: None,
+            lock_time: 0,
         };
         let root = merkle_root(&[tx.clone()]);
         assert_eq!(root, tx.hash());
@@ -447,36 +1052,840 @@ mod tests {
                 supply_threshold: 1_000_000_000,
             },
             treasury_ratio_bps: 500,
+            treasury: Some([7u8; 32]),
         };
         let engine = ExecutionEngine::new(&crypto, economics);
         let mut state = ChainState::default();
-        let tx = Transaction {
-            inputs: vec![],
-            outputs: vec![TxOutput {
-                address: [2u8; 32],
-                amount: 10,
-            }],
-            fee: 0,
-            nonce: 1,
-            memo: Some("genesis".into()),
+        // The reward of 500,000 less the treasury's 5%; no one delegates.
+        let treasury = TxOutput {
+            address: [7u8; 32],
+            amount: 25_000,
+            condition: None,
+            spendable_after_height: None,
+        };
+        let validator = TxOutput {
+            address: [9u8; 32],
+            amount: 475_000,
+            condition: None,
+            spendable_after_height: None,
         };
+        let transactions = vec![coinbase_transaction(0, vec![treasury, validator])];
         let block = Block {
             header: BlockHeader {
                 previous_hash: [0u8; 32],
-                merkle_root: merkle_root(&[tx.clone()]),
+                merkle_root: merkle_root(&transactions),
                 height: 0,
                 timestamp: now_ts(),
                 difficulty: 1,
                 nonce: 0,
                 validator: [9u8; 32],
                 stake_weight: 1,
+                state_root: state_root(&state),
             },
-            transactions: vec![tx],
+            transactions,
             pow_hash: [0u8; 32],
             validator_signature: vec![],
         };
         engine.apply_block(&mut state, &block).unwrap();
-        assert!(state.total_issued > 0);
-        assert_eq!(state.balances.get(&[2u8; 32]).cloned().unwrap_or(0), 10);
+        assert_eq!(state.total_issued, 500_000);
+        assert_eq!(state.balances[&[9u8; 32]], 475_000);
+        assert_eq!(state.balances[&[7u8; 32]], 25_000);
+        assert_eq!((state.tip_height, state.tip_hash), (0, Some(block.header.hash())));
+    }
+
+    /// A block of just the coinbase, paying the reward of 50.
+    fn empty_block(height: u64, previous_hash: BlockHash) -> Block {
+        let reward = TxOutput {
+            address: [9u8; 32],
+            amount: 50,
+            condition: None,
+            spendable_after_height: None,
+        };
+        let transactions = vec![coinbase_transaction(height, vec![reward])];
+        Block {
+            header: BlockHeader {
+                previous_hash,
+                merkle_root: merkle_root(&transactions),
+                height,
+                timestamp: 1_700_000_000 + height,
+                difficulty: 1,
+                nonce: 0,
+                validator: [9u8; 32],
+                stake_weight: 1,
+                state_root: [0u8; 32],
+            },
+            transactions,
+            pow_hash: [0u8; 32],
+            validator_signature: vec![],
+        }
+    }
+
+    #[test]
+    fn applies_blocks_only_on_top_of_the_tip() {
+        let crypto = DummyCrypto;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 50,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let engine = ExecutionEngine::new(&crypto, economics);
+        let mut state = ChainState::default();
+        assert_eq!(state.next_height(), 0);
+        assert!(engine.apply_block(&mut state, &empty_block(1, [0u8; 32])).is_err());
+
+        let genesis = empty_block(0, [0u8; 32]);
+        engine.apply_block(&mut state, &genesis).unwrap();
+        assert_eq!((state.tip_height, state.tip_hash), (0, Some(genesis.header.hash())));
+        assert_eq!(state.next_height(), 1);
+
+        // Out of order: a replay, a gap and a block on another parent.
+        let before = state.clone();
+        let err = engine.apply_block(&mut state, &genesis).unwrap_err();
+        assert_eq!(err, CoreError::UnexpectedHeight { height: 0, expected: 1 });
+        assert!(engine.apply_block(&mut state, &empty_block(2, genesis.header.hash())).is_err());
+        let err = engine.apply_block(&mut state, &empty_block(1, [7u8; 32])).unwrap_err();
+        assert_eq!(err, CoreError::NotOnTip { height: 1 });
+        assert_eq!((state.tip_height, state.total_issued), (before.tip_height, before.total_issued));
+
+        // The block must commit to the state it applies to.
+        let mut one = empty_block(1, genesis.header.hash());
+        let err = engine.apply_block(&mut state, &one).unwrap_err();
+        assert_eq!(err.to_string(), "block 1 has the wrong state root");
+        one.header.state_root = state_root(&state);
+        engine.apply_block(&mut state, &one).unwrap();
+        assert_eq!((state.tip_height, state.tip_hash), (1, Some(one.header.hash())));
+        assert_eq!(state.total_issued, 100);
+    }
+
+    /// Accepts burns proven with `"ok"`, releasing their amount to [3; 32].
+    struct BurnVerifier;
+
+    impl InboundVerifier for BurnVerifier {
+        fn verify_inbound(
+            &self,
+            msg: &CrossChainMessage,
+            proof: &serde_json::Value,
+        ) -> Result<Option<BridgeRelease>> {
+            if proof != "ok" {
+                return Err(anyhow!("bad proof"));
+            }
+            let release = BridgeRelease {
+                id: msg.id,
+                source: msg.source.clone(),
+                nonce: msg.nonce,
+                recipient: [3u8; 32],
+                amount: msg.payload["amount"].as_u64().unwrap_or(0),
+            };
+            Ok((msg.payload["type"] == "burn").then_some(release))
+        }
+    }
+
+    #[test]
+    fn releases_spend_the_escrow() {
+        let crypto = DummyCrypto;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 0,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let escrow = bridge_escrow_address();
+        let locked =
+            |amount| TxOutput { address: escrow, amount, condition: None, spendable_after_height: None };
+        let mut state = ChainState::default();
+        state.pending_utxos.insert([1u8; 32], vec![locked(300), locked(200)]);
+        state.balances.insert(escrow, 500);
+        let lock = BridgeTransfer {
+            id: lock_id(&[1u8; 32], 0),
+            sender: [2u8; 32],
+            dest: "eth".into(),
+            recipient: "0xabc".into(),
+            amount: 500,
+            lock_tx: [1u8; 32],
+        };
+        state.bridge.lock(lock, 0).unwrap();
+        let burn = |nonce, amount: u64| ProvenMessage {
+            message: CrossChainMessage {
+                id: Uuid::new_v4(),
+                source: "eth".into(),
+                dest: bridge::DXID_CHAIN_ID.into(),
+                payload: serde_json::json!({ "type": "burn", "amount": amount }),
+                nonce,
+                timestamp: 0,
+            },
+            proof: serde_json::json!("ok"),
+        };
+        let proven = burn(1, 350);
+        let release = BurnVerifier.verify_inbound(&proven.message, &proven.proof).unwrap().unwrap();
+        let tx = bridge::release_transaction(&state, &proven, &release).unwrap();
+        assert_eq!(tx.inputs.len(), 2);
+        let header = BlockHeader {
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            height: 0,
+            timestamp: 0,
+            difficulty: 1,
+            nonce: 0,
+            validator: [9u8; 32],
+            stake_weight: 1,
+            state_root: state_root(&state),
+        };
+        let without = ExecutionEngine::new(&crypto, economics.clone());
+        let err = without.complete_block(&state, header.clone(), vec![tx.clone()]).unwrap_err();
+        assert_eq!(err, CoreError::BridgeDisabled);
+
+        // Paying the recipient more, or taking a fee, does not balance with the burn.
+        let engine = ExecutionEngine::new(&crypto, economics).with_inbound(&BurnVerifier);
+        let mut greedy = tx.clone();
+        greedy.outputs[0].amount = 500;
+        greedy.outputs.truncate(1);
+        assert!(engine.complete_block(&state, header.clone(), vec![greedy]).is_err());
+        let mut forged = tx.clone();
+        forged.memo = Some(forged.memo.unwrap().replace("\"ok\"", "\"no\""));
+        assert!(matches!(
+            engine.complete_block(&state, header.clone(), vec![forged]),
+            Err(CoreError::InboundRefused(_))
+        ));
+        let mut spend = tx.clone();
+        spend.memo = None;
+        let err = engine.complete_block(&state, header.clone(), vec![spend]).unwrap_err();
+        assert_eq!(err, CoreError::EscrowedInput);
+
+        let block = engine.complete_block(&state, header.clone(), vec![tx.clone()]).unwrap();
+        engine.execute_block(&mut state, &block).unwrap();
+        assert_eq!((state.balances[&[3u8; 32]], state.balances[&escrow]), (350, 150));
+        assert_eq!(state.bridge.outstanding("eth"), 150);
+        assert_eq!(bridge::escrow_outputs(&state), vec![(tx.hash(), 1, 150)]);
+
+        // A second burn under the same nonce, or one bigger than the escrow,
+        // releases nothing more.
+        let replay = burn(1, 100);
+        let release = BurnVerifier.verify_inbound(&replay.message, &replay.proof).unwrap().unwrap();
+        let replay = bridge::release_transaction(&state, &replay, &release).unwrap();
+        let next = BlockHeader { height: 1, state_root: state_root(&state), ..header };
+        assert!(engine.complete_block(&state, next, vec![replay]).is_err());
+        let too_much = burn(2, 200);
+        let release = BurnVerifier.verify_inbound(&too_much.message, &too_much.proof).unwrap().unwrap();
+        assert!(bridge::release_transaction(&state, &too_much, &release).is_err());
+    }
+
+    #[test]
+    fn deliveries_record_each_message_once() {
+        let crypto = DummyCrypto;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 0,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let mut state = ChainState::default();
+        let message = |kind: &str| ProvenMessage {
+            message: CrossChainMessage {
+                id: Uuid::new_v4(),
+                source: "eth".into(),
+                dest: bridge::DXID_CHAIN_ID.into(),
+                payload: serde_json::json!({ "type": kind, "amount": 5 }),
+                nonce: 4,
+                timestamp: 0,
+            },
+            proof: serde_json::json!("ok"),
+        };
+        let tx = bridge::delivery_transaction(&message("note"));
+        let header = BlockHeader {
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            height: 0,
+            timestamp: 0,
+            difficulty: 1,
+            nonce: 0,
+            validator: [9u8; 32],
+            stake_weight: 1,
+            state_root: state_root(&state),
+        };
+        let without = ExecutionEngine::new(&crypto, economics.clone());
+        let err = without.complete_block(&state, header.clone(), vec![tx.clone()]).unwrap_err();
+        assert_eq!(err, CoreError::BridgeDisabled);
+
+        let engine = ExecutionEngine::new(&crypto, economics).with_inbound(&BurnVerifier);
+        let burn = bridge::delivery_transaction(&message("burn"));
+        let err = engine.complete_block(&state, header.clone(), vec![burn]).unwrap_err();
+        assert!(matches!(err, CoreError::InboundRefused(_)));
+        let mut paying = tx.clone();
+        paying.fee = 1;
+        let err = engine.complete_block(&state, header.clone(), vec![paying]).unwrap_err();
+        assert_eq!(err, CoreError::InvalidDelivery);
+
+        let block = engine.complete_block(&state, header.clone(), vec![tx]).unwrap();
+        engine.execute_block(&mut state, &block).unwrap();
+        assert!(state.bridge.delivered.contains(&("eth".to_string(), 4)));
+
+        // The same (source, nonce) under another message id is not delivered again.
+        let again = bridge::delivery_transaction(&message("note"));
+        let next = BlockHeader { height: 1, state_root: state_root(&state), ..header };
+        assert!(engine.complete_block(&state, next, vec![again]).is_err());
+    }
+
+    struct EchoRuntime;
+
+    impl ContractRuntime for EchoRuntime {
+        fn call(&self, env: &ContractCallEnv, call: &ContractCall) -> Result<ContractCallResult> {
+            if call.method == "fail" {
+                return Err(anyhow!("contract trapped"));
+            }
+            let event = ContractEvent {
+                contract: call.contract,
+                topic: call.method.clone(),
+                data: env.caller.to_vec(),
+            };
+            Ok(ContractCallResult {
+                output: Vec::new(),
+                events: vec![event],
+                transfers: vec![(env.caller, env.balance / 2)],
+                gas_used: 3,
+            })
+        }
+    }
+
+    #[test]
+    fn contract_calls_produce_receipts() {
+        let crypto = DummyCrypto;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 0,
+            schedule: HalvingSchedule { target_interval: 10, supply_threshold: 1_000_000 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let public_key = vec![5u8; 32];
+        let owner = crypto.address_from_public_key(&public_key).unwrap();
+        let mut state = ChainState::default();
+        let coin = TxOutput { address: owner, amount: 10, condition: None, spendable_after_height: None };
+        state.pending_utxos.insert([1u8; 32], vec![coin.clone(), coin]);
+        let call = |index: u32, method: &str| Transaction {
+            inputs: vec![TxInput {
+                previous_tx: [1u8; 32],
+                output_index: index,
+                signature: vec![],
+                public_key: public_key.clone(),
+                witness: vec![],
+            }],
+            outputs: vec![
+                TxOutput { address: owner, amount: 1, condition: None, spendable_after_height: None },
+                TxOutput { address: [3u8; 32], amount: 4, condition: None, spendable_after_height: None },
+            ],
+            fee: 5,
+            nonce: index as u64,
+            memo: Some(ContractCall { contract: [3u8; 32], method: method.into(), input: vec![] }.to_memo()),
+            lock_time: 0,
+        };
+        let header = BlockHeader {
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            height: 0,
+            timestamp: 0,
+            difficulty: 1,
+            nonce: 0,
+            validator: [9u8; 32],
+            stake_weight: 1,
+            state_root: state_root(&state),
+        };
+        let engine = ExecutionEngine::new(&crypto, economics.clone()).with_contracts(&EchoRuntime);
+        let transactions = vec![call(0, "ping"), call(1, "fail")];
+        let block = engine.complete_block(&state, header.clone(), transactions).unwrap();
+        let fee = TxOutput { address: [9u8; 32], amount: 8, condition: None, spendable_after_height: None };
+        assert_eq!(block.transactions[0].outputs, [fee]);
+
+        let without = ExecutionEngine::new(&crypto, economics);
+        assert!(without.execute_block(&mut state.clone(), &block).is_err());
+        let receipts = engine.execute_block(&mut state, &block).unwrap();
+        assert_eq!(receipts[1].tx_hash, block.transactions[1].hash());
+        assert_eq!(receipts[1].events_with_topic("ping").next().unwrap().data, owner.to_vec());
+        assert_eq!((receipts[1].success, receipts[1].gas_used, receipts[1].fee_paid), (true, 3, 3));
+        assert_eq!(receipts[1].transfers, vec![(owner, 2)]);
+        assert_eq!((receipts[2].index, receipts[2].success, receipts[2].fee_paid), (2, false, 5));
+        assert_eq!(receipts[2].transfers, vec![(owner, 4)]);
+        // Owner: two outputs of 1, a payout of 2, the refunded value of 4 and
+        // 2 of unused fee; the validator collects the 8 of fees consumed.
+        let balance = |address: &Address| state.balances[address];
+        assert_eq!((balance(&owner), balance(&[3u8; 32]), balance(&[9u8; 32])), (10, 2, 8));
+        assert_eq!(state.balances.values().sum::<u64>(), 20);
+
+        let short = engine.with_gas_price(2);
+        let mut state = ChainState::default();
+        let funding = TxOutput { address: owner, amount: 10, condition: None, spendable_after_height: None };
+        state.pending_utxos.insert([1u8; 32], vec![funding]);
+        let header = BlockHeader { state_root: state_root(&state), ..header };
+        let block = short.complete_block(&state, header, vec![call(0, "ping")]).unwrap();
+        let receipts = short.execute_block(&mut state, &block).unwrap();
+        assert!(receipts[1].error.as_deref().unwrap().contains("fee pays for 2"));
+    }
+
+    #[test]
+    fn coinbase_outputs_mature_before_they_are_spent() {
+        let crypto = DummyCrypto;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 50,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let engine = ExecutionEngine::new(&crypto, economics);
+        let public_key = vec![5u8; 32];
+        let validator = crypto.address_from_public_key(&public_key).unwrap();
+        let mut state = ChainState::default();
+        let header = |state: &ChainState| BlockHeader {
+            previous_hash: state.tip_hash.unwrap_or([0u8; 32]),
+            merkle_root: [0u8; 32],
+            height: state.next_height(),
+            timestamp: 0,
+            difficulty: 1,
+            nonce: 0,
+            validator,
+            stake_weight: 1,
+            state_root: state_root(state),
+        };
+        let genesis = engine.complete_block(&state, header(&state), vec![]).unwrap();
+        let mut greedy = genesis.clone();
+        greedy.transactions[0].outputs[0].amount = 51;
+        greedy.header.merkle_root = merkle_root(&greedy.transactions);
+        let err = engine.apply_block(&mut state.clone(), &greedy).unwrap_err();
+        assert_eq!(err.to_string(), "coinbase pays 51, expected 50");
+        let second = coinbase_transaction(0, Vec::new());
+        assert!(engine.complete_block(&state, header(&state), vec![second]).is_err());
+        engine.apply_block(&mut state, &genesis).unwrap();
+        let coinbase = genesis.transactions[0].hash();
+        let reward = TxOutput {
+            address: validator,
+            amount: 50,
+            condition: None,
+            spendable_after_height: None,
+        };
+        assert_eq!(state.pending_utxos[&coinbase], [reward]);
+
+        let spend = Transaction {
+            inputs: vec![TxInput {
+                previous_tx: coinbase,
+                output_index: 0,
+                signature: vec![],
+                public_key,
+                witness: vec![],
+            }],
+            outputs: vec![TxOutput {
+                address: [2u8; 32],
+                amount: 50,
+                condition: None,
+                spendable_after_height: None,
+            }],
+            fee: 0,
+            nonce: 0,
+            memo: None,
+            lock_time: 0,
+        };
+        let err = engine.complete_block(&state, header(&state), vec![spend.clone()]).unwrap_err();
+        assert_eq!(err, CoreError::ImmatureCoinbase);
+        while state.next_height() < COINBASE_MATURITY {
+            let block = engine.complete_block(&state, header(&state), vec![]).unwrap();
+            engine.apply_block(&mut state, &block).unwrap();
+        }
+        let block = engine.complete_block(&state, header(&state), vec![spend]).unwrap();
+        engine.apply_block(&mut state, &block).unwrap();
+        assert_eq!(state.balances[&[2u8; 32]], 50);
+    }
+
+    #[test]
+    fn fees_are_paid_to_the_validator_and_treasury_without_changing_the_supply() {
+        let crypto = DummyCrypto;
+        let economics = TokenEconomics {
+            max_supply: 180,
+            base_reward: 50,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 1_000,
+            treasury: Some([7u8; 32]),
+        };
+        let engine = ExecutionEngine::new(&crypto, economics);
+        let public_key = vec![5u8; 32];
+        let owner = crypto.address_from_public_key(&public_key).unwrap();
+        let mut state = ChainState::default();
+        let coin = TxOutput { address: owner, amount: 100, condition: None, spendable_after_height: None };
+        state.pending_utxos.insert([1u8; 32], vec![coin]);
+        state.total_issued = 100;
+        let unspent =
+            |state: &ChainState| state.pending_utxos.values().flatten().map(|out| out.amount).sum::<u64>();
+        let header = |state: &ChainState| BlockHeader {
+            previous_hash: state.tip_hash.unwrap_or([0u8; 32]),
+            merkle_root: [0u8; 32],
+            height: state.next_height(),
+            timestamp: 0,
+            difficulty: 1,
+            nonce: 0,
+            validator: [9u8; 32],
+            stake_weight: 1,
+            state_root: state_root(state),
+        };
+
+        // A fee of 8 and 2 more the outputs leave over.
+        let tx = Transaction {
+            inputs: vec![TxInput {
+                previous_tx: [1u8; 32],
+                output_index: 0,
+                signature: vec![],
+                public_key,
+                witness: vec![],
+            }],
+            outputs: vec![
+                TxOutput { address: [2u8; 32], amount: 60, condition: None, spendable_after_height: None },
+                TxOutput { address: owner, amount: 30, condition: None, spendable_after_height: None },
+            ],
+            fee: 8,
+            nonce: 0,
+            memo: None,
+            lock_time: 0,
+        };
+        let block = engine.complete_block(&state, header(&state), vec![tx]).unwrap();
+        let treasury = TxOutput {
+            address: [7u8; 32],
+            amount: 6,
+            condition: None,
+            spendable_after_height: None,
+        };
+        let validator = TxOutput {
+            address: [9u8; 32],
+            amount: 54,
+            condition: None,
+            spendable_after_height: None,
+        };
+        assert_eq!(block.transactions[0].outputs, [treasury, validator]);
+        let receipts = engine.execute_block(&mut state, &block).unwrap();
+        assert_eq!(receipts[1].fee_paid, 10);
+        assert_eq!((unspent(&state), state.total_issued), (150, 150));
+
+        // The reward stops at the max supply; fees do not add to it.
+        for issued in [180, 180] {
+            let block = engine.complete_block(&state, header(&state), vec![]).unwrap();
+            engine.apply_block(&mut state, &block).unwrap();
+            assert_eq!((unspent(&state), state.total_issued), (issued, issued));
+        }
+    }
+
+    #[test]
+    fn conditioned_outputs_are_spent_by_meeting_their_condition() {
+        use sha2::{Digest, Sha256};
+
+        let crypto = DummyCrypto;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 0,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let engine = ExecutionEngine::new(&crypto, economics);
+        let public_key = vec![5u8; 32];
+        let owner = crypto.address_from_public_key(&public_key).unwrap();
+        let mut state = ChainState::default();
+        let funding = TxOutput { address: owner, amount: 10, condition: None, spendable_after_height: None };
+        state.pending_utxos.insert([1u8; 32], vec![funding]);
+        let header = |state: &ChainState| BlockHeader {
+            previous_hash: state.tip_hash.unwrap_or([0u8; 32]),
+            merkle_root: [0u8; 32],
+            height: state.next_height(),
+            timestamp: 0,
+            difficulty: 1,
+            nonce: 0,
+            validator: [9u8; 32],
+            stake_weight: 1,
+            state_root: state_root(state),
+        };
+        let spend = |previous_tx, public_key: &[u8], witness: &[u8], outputs| Transaction {
+            inputs: vec![TxInput {
+                previous_tx,
+                output_index: 0,
+                signature: vec![],
+                public_key: public_key.to_vec(),
+                witness: witness.to_vec(),
+            }],
+            outputs,
+            fee: 0,
+            nonce: 0,
+            memo: None,
+            lock_time: 0,
+        };
+        let secret = b"swap secret";
+        let htlc = SpendCondition::htlc(vec![6u8; 32], public_key.clone(), Sha256::digest(secret).into(), 5);
+
+        // The output must pay to its condition's address.
+        let mut misaddressed = htlc.clone().output(10);
+        misaddressed.address = owner;
+        let lock = spend([1u8; 32], &public_key, &[], vec![misaddressed]);
+        let err = engine.complete_block(&state, header(&state), vec![lock]).unwrap_err();
+        assert_eq!(err, CoreError::ConditionAddress);
+        let lock = spend([1u8; 32], &public_key, &[], vec![htlc.clone().output(10)]);
+        let locked = lock.hash();
+        let block = engine.complete_block(&state, header(&state), vec![lock]).unwrap();
+        engine.apply_block(&mut state, &block).unwrap();
+        assert_eq!(state.pending_utxos[&locked][0].condition, Some(htlc));
+
+        // Claimed with the preimage; a guess neither claims nor, before the
+        // timeout, refunds.
+        let paid = TxOutput { address: [6u8; 32], amount: 10, condition: None, spendable_after_height: None };
+        let claim = |witness: &[u8]| spend(locked, &[], witness, vec![paid.clone()]);
+        let err = engine.complete_block(&state, header(&state), vec![claim(b"guess")]).unwrap_err();
+        assert_eq!(err, CoreError::ConditionNotMet);
+        assert_ne!(claim(b"guess").hash(), claim(secret).hash());
+        assert_eq!(claim(b"guess").signing_hash(), claim(secret).signing_hash());
+        let block = engine.complete_block(&state, header(&state), vec![claim(secret)]).unwrap();
+        engine.apply_block(&mut state, &block).unwrap();
+        assert_eq!(state.balances[&[6u8; 32]], 10);
+    }
+
+    #[test]
+    fn multisig_outputs_are_spent_with_keys_of_their_cosigners() {
+        use multisig::encode_cosignatures;
+
+        let crypto = DummyCrypto;
+        let keys: Vec<Vec<u8>> = (1..=3u8).map(|i| vec![i; 32]).collect();
+        let addresses = keys.iter().map(|pk| crypto.address_from_public_key(pk).unwrap()).collect();
+        let shared = MultisigOutput::new(2, addresses).unwrap();
+        let funding =
+            TxOutput { address: shared.address(), amount: 10, condition: None, spendable_after_height: None };
+        let mut state = ChainState::default();
+        state.pending_utxos.insert([1u8; 32], vec![funding]);
+        state.tip_hash = Some([0u8; 32]);
+        let spend = |revealed: &MultisigOutput, signers: &[(usize, &[u8])]| {
+            let signatures: Vec<_> = signers
+                .iter()
+                .map(|(i, pk)| {
+                    let index = revealed.cosigner_index(&crypto.address_from_public_key(&keys[*i]).unwrap());
+                    (index.unwrap(), pk.to_vec(), vec![1u8; 64])
+                })
+                .collect();
+            Transaction {
+                inputs: vec![TxInput {
+                    previous_tx: [1u8; 32],
+                    output_index: 0,
+                    signature: encode_cosignatures(&signatures),
+                    public_key: revealed.encode(),
+                    witness: vec![],
+                }],
+                outputs: vec![TxOutput {
+                    address: [3u8; 32],
+                    amount: 10,
+                    condition: None,
+                    spendable_after_height: None,
+                }],
+                fee: 0,
+                nonce: 0,
+                memo: None,
+                lock_time: 0,
+            }
+        };
+        let check = |tx: &Transaction| check_signed_transaction(&crypto, tx, &state);
+
+        check(&spend(&shared, &[(0, &keys[0]), (2, &keys[2])])).unwrap();
+        assert_eq!(check(&spend(&shared, &[(0, &keys[0])])), Err(CoreError::MultisigThreshold));
+        // Each signature must come with the key its cosigner's address hashes.
+        let stranger = [9u8; 32];
+        let forged = spend(&shared, &[(0, &keys[0]), (2, &stranger)]);
+        assert_eq!(check(&forged), Err(CoreError::MultisigThreshold));
+
+        // Revealing another output is refused even with enough signatures.
+        let other = MultisigOutput::new(1, shared.cosigners.clone()).unwrap();
+        let tx = spend(&other, &[(0, &keys[0])]);
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 0,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let engine = ExecutionEngine::new(&crypto, economics);
+        let header = BlockHeader {
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            height: state.next_height(),
+            timestamp: 0,
+            difficulty: 1,
+            nonce: 0,
+            validator: [9u8; 32],
+            stake_weight: 1,
+            state_root: state_root(&state),
+        };
+        let err = engine.complete_block(&state, header, vec![tx]).unwrap_err();
+        assert_eq!(err, CoreError::NotMultisigOwner);
+    }
+
+    #[test]
+    fn offered_transactions_are_checked_against_what_they_spend() {
+        let crypto = DummyCrypto;
+        let vesting =
+            TxOutput { address: [1u8; 32], amount: 10, condition: None, spendable_after_height: Some(4) };
+        let mut state = ChainState::default();
+        state.pending_utxos.insert([2u8; 32], vec![vesting]);
+        let at = |state: &mut ChainState, height: u64| {
+            state.tip_height = height - 1;
+            state.tip_hash = Some([0u8; 32]);
+        };
+        let input = TxInput {
+            previous_tx: [2u8; 32],
+            output_index: 0,
+            signature: vec![1],
+            public_key: vec![5u8; 32],
+            witness: vec![],
+        };
+        let mut tx = Transaction {
+            inputs: vec![input],
+            outputs: vec![TxOutput {
+                address: [3u8; 32],
+                amount: 9,
+                condition: None,
+                spendable_after_height: None,
+            }],
+            fee: 1,
+            nonce: 0,
+            memo: None,
+            lock_time: 6,
+        };
+        let check = |tx: &Transaction, state: &ChainState| check_signed_transaction(&crypto, tx, state);
+        at(&mut state, 5);
+        assert_eq!(check(&tx, &state), Err(CoreError::TransactionLocked { lock_time: 6 }));
+        tx.lock_time = 0;
+        at(&mut state, 4);
+        assert_eq!(check(&tx, &state), Err(CoreError::Timelocked { until: 5 }));
+        at(&mut state, 5);
+        check(&tx, &state).unwrap();
+        tx.inputs[0].output_index = 1;
+        assert_eq!(check(&tx, &state), Err(CoreError::MissingOutput));
+        tx.inputs[0].output_index = 0;
+
+        // A coinbase output waits until it matures.
+        state.coinbases.insert([2u8; 32], 3);
+        assert_eq!(check(&tx, &state), Err(CoreError::ImmatureCoinbase));
+        at(&mut state, 3 + COINBASE_MATURITY);
+        check(&tx, &state).unwrap();
+        tx.inputs[0].public_key.clear();
+        assert_eq!(check(&tx, &state), Err(CoreError::NotSigner));
+    }
+
+    #[test]
+    fn locked_transactions_and_outputs_wait_for_their_height() {
+        let crypto = DummyCrypto;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 0,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let engine = ExecutionEngine::new(&crypto, economics);
+        let public_key = vec![5u8; 32];
+        let owner = crypto.address_from_public_key(&public_key).unwrap();
+        let mut state = ChainState::default();
+        let funding = TxOutput { address: owner, amount: 10, condition: None, spendable_after_height: None };
+        state.pending_utxos.insert([1u8; 32], vec![funding]);
+        let header = |state: &ChainState| BlockHeader {
+            previous_hash: state.tip_hash.unwrap_or([0u8; 32]),
+            merkle_root: [0u8; 32],
+            height: state.next_height(),
+            timestamp: 0,
+            difficulty: 1,
+            nonce: 0,
+            validator: [9u8; 32],
+            stake_weight: 1,
+            state_root: state_root(state),
+        };
+        let spend = |previous_tx, lock_time, spendable_after_height| Transaction {
+            inputs: vec![TxInput {
+                previous_tx,
+                output_index: 0,
+                signature: vec![],
+                public_key: public_key.clone(),
+                witness: vec![],
+            }],
+            outputs: vec![TxOutput { address: owner, amount: 10, condition: None, spendable_after_height }],
+            fee: 0,
+            nonce: 0,
+            memo: None,
+            lock_time,
+        };
+
+        // A vesting output, paid by a transaction that waits for block 1.
+        let early = spend([1u8; 32], 1, Some(2));
+        let err = engine.complete_block(&state, header(&state), vec![early.clone()]).unwrap_err();
+        assert_eq!(err, CoreError::TransactionLocked { lock_time: 1 });
+        assert_ne!(early.hash(), spend([1u8; 32], 0, Some(2)).hash());
+        let block = engine.complete_block(&state, header(&state), vec![]).unwrap();
+        engine.apply_block(&mut state, &block).unwrap();
+        let vesting = early.hash();
+        let block = engine.complete_block(&state, header(&state), vec![early]).unwrap();
+        engine.apply_block(&mut state, &block).unwrap();
+        assert_eq!(state.pending_utxos[&vesting][0].spendable_after_height, Some(2));
+
+        // Block 2 is still too early for the output; block 3 spends it.
+        let release = spend(vesting, 0, None);
+        let released = release.hash();
+        let err = engine.complete_block(&state, header(&state), vec![release.clone()]).unwrap_err();
+        assert_eq!(err, CoreError::Timelocked { until: 3 });
+        let block = engine.complete_block(&state, header(&state), vec![]).unwrap();
+        engine.apply_block(&mut state, &block).unwrap();
+        let block = engine.complete_block(&state, header(&state), vec![release]).unwrap();
+        engine.apply_block(&mut state, &block).unwrap();
+        assert_eq!(state.pending_utxos[&released][0].amount, 10);
+    }
+
+    struct TickRuntime;
+
+    impl ContractRuntime for TickRuntime {
+        fn call(&self, env: &ContractCallEnv, call: &ContractCall) -> Result<ContractCallResult> {
+            assert_eq!((env.caller, env.value), (call.contract, 0));
+            Ok(ContractCallResult { transfers: vec![([6u8; 32], 1)], gas_used: 3, ..Default::default() })
+        }
+
+        fn scheduled_calls(&self, height: u64) -> Vec<ScheduledCall> {
+            let tick = |contract| ScheduledCall { contract, method: "tick".into(), gas_limit: 4 };
+            if height == 0 {
+                vec![tick([3u8; 32]), tick([4u8; 32])]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
+    #[test]
+    fn scheduled_calls_pay_gas_from_the_contract_balance() {
+        let crypto = DummyCrypto;
+        let economics = TokenEconomics {
+            max_supply: 1_000_000,
+            base_reward: 0,
+            schedule: HalvingSchedule { target_interval: 10, supply_threshold: 1_000_000 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let mut state = ChainState::default();
+        state.balances.insert([3u8; 32], 10);
+        let header = BlockHeader {
+            previous_hash: [0u8; 32],
+            merkle_root: [0u8; 32],
+            height: 0,
+            timestamp: 0,
+            difficulty: 1,
+            nonce: 0,
+            validator: [9u8; 32],
+            stake_weight: 1,
+            state_root: state_root(&state),
+        };
+        let engine = ExecutionEngine::new(&crypto, economics).with_contracts(&TickRuntime).with_gas_price(2);
+        let block = engine.complete_block(&state, header, vec![]).unwrap();
+        let receipts = engine.execute_block(&mut state, &block).unwrap();
+        assert_eq!(receipts[1].tx_hash, contract::scheduled_call_hash(0, 1));
+        assert_eq!((receipts[1].success, receipts[1].gas_used, receipts[1].fee_paid), (true, 3, 6));
+        assert!(receipts[2].error.as_deref().unwrap().contains("cannot pay"));
+        // The contract paid 6 of gas to the validator and 1 to the payee.
+        let balance = |address: &Address| state.balances.get(address).copied().unwrap_or(0);
+        assert_eq!((balance(&[3u8; 32]), balance(&[6u8; 32]), balance(&[9u8; 32])), (3, 1, 6));
     }
 }
@@ -0,0 +1,690 @@
+//! Transactions waiting for a block. Admission checks a transaction against
+//! the unspent outputs and what is already waiting: its inputs must be
+//! unspent and cover its outputs and fee, its fee rate must meet the
+//! minimum, and it may not spend an output or reuse a sender's nonce that a
+//! waiting transaction does unless it pays enough more to replace it. When
+//! the pool is full the lowest fee rates are evicted for a better one.
+//! Inputs must also be spendable in the next block: coinbase outputs
+//! mature and time locks passed, as a block applying them would check.
+//! Bridge releases and deliveries pay no fee: they go ahead of everything
+//! else and are only turned away when the pool holds nothing else to evict.
+//!
+//! Signatures, and the inbound messages releases and deliveries carry, are
+//! not checked here; callers verify them before admitting.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::bridge::{bridge_escrow_address, parse_delivery, parse_release};
+use crate::coinbase::COINBASE_MATURITY;
+use crate::staking::staking_pool_address;
+use crate::{now_ts, Address, Block, ChainState, Transaction, TxHash, TxInput, TxOutput};
+
+/// Unspent outputs a transaction may spend, as of the block that would
+/// include it.
+pub trait UtxoView {
+    /// Output `index` of transaction `tx`, if it exists and is unspent.
+    fn unspent(&self, tx: &TxHash, index: u32) -> Option<TxOutput>;
+    /// Height of the block whose coinbase `tx` is, if it is one.
+    fn coinbase_height(&self, tx: &TxHash) -> Option<u64>;
+    /// Height of the block a transaction offered now would be in.
+    fn next_height(&self) -> u64;
+}
+
+/// Spent outputs are kept in the state with their amount cleared.
+impl UtxoView for ChainState {
+    fn unspent(&self, tx: &TxHash, index: u32) -> Option<TxOutput> {
+        let output = self.pending_utxos.get(tx)?.get(index as usize)?;
+        (output.amount > 0).then(|| output.clone())
+    }
+
+    fn coinbase_height(&self, tx: &TxHash) -> Option<u64> {
+        self.coinbases.get(tx).copied()
+    }
+
+    fn next_height(&self) -> u64 {
+        ChainState::next_height(self)
+    }
+}
+
+/// First height a block may spend `output`, which `input` spends: after its
+/// time lock and, for a coinbase output, once it matures.
+pub fn spendable_from(utxos: &dyn UtxoView, input: &TxInput, output: &TxOutput) -> u64 {
+    let unlocked = output.spendable_after_height.map_or(0, |after| after.saturating_add(1));
+    let matured =
+        utxos.coinbase_height(&input.previous_tx).map_or(0, |mined| mined.saturating_add(COINBASE_MATURITY));
+    unlocked.max(matured)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct MempoolConfig {
+    pub max_txs: usize,
+    /// Total encoded size of the waiting transactions.
+    pub max_bytes: usize,
+    pub max_tx_bytes: usize,
+    /// Lowest fee per encoded byte admitted.
+    pub min_fee_rate: u64,
+    /// Transactions one address may have waiting.
+    pub max_per_sender: usize,
+    /// How much higher, in percent, a replacement's fee rate must be.
+    pub replace_bump_percent: u64,
+    /// Seconds a transaction may wait before [`Mempool::expire`] drops it.
+    pub max_age_secs: u64,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self {
+            max_txs: 5_000,
+            max_bytes: 5 * 1024 * 1024,
+            max_tx_bytes: 100 * 1024,
+            min_fee_rate: 1,
+            max_per_sender: 25,
+            replace_bump_percent: 10,
+            max_age_secs: 3 * 24 * 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MempoolError {
+    #[error("transaction is already waiting")]
+    AlreadyKnown,
+    #[error("invalid transaction: {0}")]
+    Invalid(&'static str),
+    #[error("transaction is {size} bytes, over the limit of {max}")]
+    TooLarge { size: usize, max: usize },
+    #[error("input {} of {} is missing or spent", .index, hex::encode(.tx))]
+    MissingInput { tx: TxHash, index: u32 },
+    #[error("input {} of {} cannot be spent before height {until}", .index, hex::encode(.tx))]
+    Locked { tx: TxHash, index: u32, until: u64 },
+    #[error("inputs of {inputs} do not cover outputs and fee of {needed}")]
+    InsufficientInputs { inputs: u64, needed: u64 },
+    #[error("fee rate {rate} is below the minimum of {min}")]
+    FeeTooLow { rate: u64, min: u64 },
+    #[error("conflicts with waiting transaction {} and does not pay enough to replace it", hex::encode(.0))]
+    Conflict(TxHash),
+    #[error("sender already has {0} transactions waiting")]
+    SenderLimit(usize),
+    #[error("mempool is full and fee rate {0} does not outbid any waiting transaction")]
+    Full(u64),
+}
+
+/// What admitting a transaction changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Admitted {
+    pub hash: TxHash,
+    /// Waiting transactions it conflicted with and replaced.
+    pub replaced: Vec<TxHash>,
+    /// Waiting transactions dropped to make room for it.
+    pub evicted: Vec<TxHash>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    tx: Transaction,
+    sender: Address,
+    size: usize,
+    fee_rate: u64,
+    added_at: u64,
+    seq: u64,
+}
+
+impl Entry {
+    /// Orders entries lowest fee rate first and, within a rate, newest first.
+    fn priority(&self, hash: TxHash) -> (u64, Reverse<u64>, TxHash) {
+        (self.fee_rate, Reverse(self.seq), hash)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Mempool {
+    config: MempoolConfig,
+    entries: HashMap<TxHash, Entry>,
+    by_priority: BTreeSet<(u64, Reverse<u64>, TxHash)>,
+    /// The waiting transaction spending each output.
+    spends: HashMap<(TxHash, u32), TxHash>,
+    nonces: HashMap<(Address, u64), TxHash>,
+    per_sender: HashMap<Address, usize>,
+    bytes: usize,
+    next_seq: u64,
+}
+
+impl Mempool {
+    pub fn new(config: MempoolConfig) -> Self {
+        Self { config, ..Self::default() }
+    }
+
+    pub fn config(&self) -> &MempoolConfig {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Encoded size of everything waiting.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn contains(&self, hash: &TxHash) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &TxHash) -> Option<&Transaction> {
+        self.entries.get(hash).map(|entry| &entry.tx)
+    }
+
+    /// Every waiting transaction, highest fee rate first.
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.by_priority.iter().rev().map(|(_, _, hash)| &self.entries[hash].tx)
+    }
+
+    /// Admits `tx` if it may spend its inputs in `utxos`.
+    pub fn admit(&mut self, tx: Transaction, utxos: &dyn UtxoView) -> Result<Admitted, MempoolError> {
+        let hash = tx.hash();
+        if self.entries.contains_key(&hash) {
+            return Err(MempoolError::AlreadyKnown);
+        }
+        if parse_release(&tx).is_some() {
+            return self.admit_release(hash, tx, utxos);
+        }
+        if parse_delivery(&tx).is_some() {
+            return self.admit_delivery(hash, tx);
+        }
+        if tx.inputs.is_empty() || tx.outputs.is_empty() {
+            return Err(MempoolError::Invalid("transaction needs inputs and outputs"));
+        }
+        let size = encoded_size(&tx);
+        if size > self.config.max_tx_bytes {
+            return Err(MempoolError::TooLarge { size, max: self.config.max_tx_bytes });
+        }
+
+        let mut outpoints = HashSet::new();
+        let mut inputs = 0u64;
+        let mut sender = None;
+        for input in &tx.inputs {
+            let outpoint = (input.previous_tx, input.output_index);
+            if !outpoints.insert(outpoint) {
+                return Err(MempoolError::Invalid("spends an output twice"));
+            }
+            let output = spendable_input(utxos, input)?;
+            if output.address == bridge_escrow_address() || output.address == staking_pool_address() {
+                return Err(MempoolError::Invalid("spends an escrowed or staked output"));
+            }
+            sender.get_or_insert(output.address);
+            inputs = inputs.checked_add(output.amount).ok_or(MempoolError::Invalid("input overflow"))?;
+        }
+        let sender = sender.expect("a transaction with inputs has a sender");
+        let needed = tx
+            .outputs
+            .iter()
+            .try_fold(tx.fee, |sum, out| sum.checked_add(out.amount))
+            .ok_or(MempoolError::Invalid("output overflow"))?;
+        if inputs < needed {
+            return Err(MempoolError::InsufficientInputs { inputs, needed });
+        }
+        let fee_rate = tx.fee / size as u64;
+        if fee_rate < self.config.min_fee_rate {
+            return Err(MempoolError::FeeTooLow { rate: fee_rate, min: self.config.min_fee_rate });
+        }
+
+        let mut conflicts: Vec<TxHash> =
+            outpoints.iter().filter_map(|outpoint| self.spends.get(outpoint)).copied().collect();
+        conflicts.extend(self.nonces.get(&(sender, tx.nonce)));
+        conflicts.sort_unstable();
+        conflicts.dedup();
+        self.check_replacement(&tx, fee_rate, &conflicts)?;
+
+        let replaced_from_sender =
+            conflicts.iter().filter(|hash| self.entries[*hash].sender == sender).count();
+        let waiting = self.per_sender.get(&sender).copied().unwrap_or(0) - replaced_from_sender;
+        if waiting >= self.config.max_per_sender {
+            return Err(MempoolError::SenderLimit(waiting));
+        }
+        let evicted = self.eviction_for(size, fee_rate, &conflicts)?;
+
+        for hash in conflicts.iter().chain(&evicted) {
+            self.remove(hash);
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let entry = Entry { tx, sender, size, fee_rate, added_at: now_ts(), seq };
+        self.insert(hash, entry);
+        Ok(Admitted { hash, replaced: conflicts, evicted })
+    }
+
+    /// Admits a release, which spends escrow outputs into exactly its
+    /// outputs. Releases are charged to the escrow, ranked above any fee
+    /// rate and conflict with whatever spends their outputs or carries the
+    /// same inbound message.
+    fn admit_release(
+        &mut self,
+        hash: TxHash,
+        tx: Transaction,
+        utxos: &dyn UtxoView,
+    ) -> Result<Admitted, MempoolError> {
+        let size = encoded_size(&tx);
+        if size > self.config.max_tx_bytes {
+            return Err(MempoolError::TooLarge { size, max: self.config.max_tx_bytes });
+        }
+        let escrow = bridge_escrow_address();
+        let mut outpoints = HashSet::new();
+        let mut inputs = 0u64;
+        for input in &tx.inputs {
+            let outpoint = (input.previous_tx, input.output_index);
+            if !outpoints.insert(outpoint) {
+                return Err(MempoolError::Invalid("spends an output twice"));
+            }
+            let output = spendable_input(utxos, input)?;
+            if output.address != escrow {
+                return Err(MempoolError::Invalid("a release spends only escrowed outputs"));
+            }
+            if let Some(waiting) = self.spends.get(&outpoint) {
+                return Err(MempoolError::Conflict(*waiting));
+            }
+            inputs = inputs.checked_add(output.amount).ok_or(MempoolError::Invalid("input overflow"))?;
+        }
+        let outputs = tx
+            .outputs
+            .iter()
+            .try_fold(tx.fee, |sum, out| sum.checked_add(out.amount))
+            .ok_or(MempoolError::Invalid("output overflow"))?;
+        if tx.inputs.is_empty() || tx.fee != 0 || outputs != inputs {
+            return Err(MempoolError::Invalid("a release moves exactly the escrow it spends, without a fee"));
+        }
+        self.insert_inbound(hash, tx, size)
+    }
+
+    /// Admits a delivery, which carries an inbound message and moves no
+    /// funds. Whether the chain already has the message is left to the
+    /// block that would include it.
+    fn admit_delivery(&mut self, hash: TxHash, tx: Transaction) -> Result<Admitted, MempoolError> {
+        let size = encoded_size(&tx);
+        if size > self.config.max_tx_bytes {
+            return Err(MempoolError::TooLarge { size, max: self.config.max_tx_bytes });
+        }
+        if !tx.inputs.is_empty() || !tx.outputs.is_empty() || tx.fee != 0 {
+            return Err(MempoolError::Invalid("a delivery moves no funds and pays no fee"));
+        }
+        self.insert_inbound(hash, tx, size)
+    }
+
+    /// Adds a release or delivery, unless one for the same inbound message
+    /// is waiting.
+    fn insert_inbound(
+        &mut self,
+        hash: TxHash,
+        tx: Transaction,
+        size: usize,
+    ) -> Result<Admitted, MempoolError> {
+        let escrow = bridge_escrow_address();
+        if let Some(waiting) = self.nonces.get(&(escrow, tx.nonce)) {
+            return Err(MempoolError::Conflict(*waiting));
+        }
+        let evicted = self.eviction_for(size, u64::MAX, &[])?;
+        for hash in &evicted {
+            self.remove(hash);
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let entry = Entry { tx, sender: escrow, size, fee_rate: u64::MAX, added_at: now_ts(), seq };
+        self.insert(hash, entry);
+        Ok(Admitted { hash, replaced: Vec::new(), evicted })
+    }
+
+    /// A replacement must beat each transaction it conflicts with by the
+    /// bump in fee rate and pay more in fees than all of them together.
+    fn check_replacement(
+        &self,
+        tx: &Transaction,
+        fee_rate: u64,
+        conflicts: &[TxHash],
+    ) -> Result<(), MempoolError> {
+        let bump = 100 + self.config.replace_bump_percent;
+        let mut replaced_fees = 0u64;
+        for hash in conflicts {
+            let entry = &self.entries[hash];
+            if fee_rate.saturating_mul(100) < entry.fee_rate.saturating_mul(bump) {
+                return Err(MempoolError::Conflict(*hash));
+            }
+            replaced_fees = replaced_fees.saturating_add(entry.tx.fee);
+        }
+        match conflicts.first() {
+            Some(hash) if tx.fee <= replaced_fees => Err(MempoolError::Conflict(*hash)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The waiting transactions to drop, lowest fee rate first, so one of
+    /// `size` bytes fits once `replaced` are gone.
+    fn eviction_for(
+        &self,
+        size: usize,
+        fee_rate: u64,
+        replaced: &[TxHash],
+    ) -> Result<Vec<TxHash>, MempoolError> {
+        let mut count = self.entries.len() - replaced.len() + 1;
+        let mut bytes =
+            self.bytes + size - replaced.iter().map(|hash| self.entries[hash].size).sum::<usize>();
+        let mut evicted = Vec::new();
+        let mut candidates = self.by_priority.iter().filter(|(.., hash)| !replaced.contains(hash));
+        while count > self.config.max_txs || bytes > self.config.max_bytes {
+            match candidates.next() {
+                Some((rate, _, hash)) if *rate < fee_rate => {
+                    count -= 1;
+                    bytes -= self.entries[hash].size;
+                    evicted.push(*hash);
+                }
+                _ => return Err(MempoolError::Full(fee_rate)),
+            }
+        }
+        Ok(evicted)
+    }
+
+    fn insert(&mut self, hash: TxHash, entry: Entry) {
+        for input in &entry.tx.inputs {
+            self.spends.insert((input.previous_tx, input.output_index), hash);
+        }
+        self.nonces.insert((entry.sender, entry.tx.nonce), hash);
+        *self.per_sender.entry(entry.sender).or_insert(0) += 1;
+        self.bytes += entry.size;
+        self.by_priority.insert(entry.priority(hash));
+        self.entries.insert(hash, entry);
+    }
+
+    /// Drops `hash`, returning it if it was waiting.
+    pub fn remove(&mut self, hash: &TxHash) -> Option<Transaction> {
+        let entry = self.entries.remove(hash)?;
+        for input in &entry.tx.inputs {
+            self.spends.remove(&(input.previous_tx, input.output_index));
+        }
+        self.nonces.remove(&(entry.sender, entry.tx.nonce));
+        if let Some(count) = self.per_sender.get_mut(&entry.sender) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_sender.remove(&entry.sender);
+            }
+        }
+        self.bytes -= entry.size;
+        self.by_priority.remove(&entry.priority(*hash));
+        Some(entry.tx)
+    }
+
+    /// Transactions for a block of at most `max_bytes`, highest fee rate first.
+    pub fn select(&self, max_bytes: usize) -> Vec<Transaction> {
+        let mut room = max_bytes;
+        let mut selected = Vec::new();
+        for (_, _, hash) in self.by_priority.iter().rev() {
+            let entry = &self.entries[hash];
+            if entry.size <= room {
+                room -= entry.size;
+                selected.push(entry.tx.clone());
+            }
+        }
+        selected
+    }
+
+    /// Drops the transactions `block` includes and those spending an output
+    /// it spends, returning their hashes.
+    pub fn remove_block(&mut self, block: &Block) -> Vec<TxHash> {
+        let mut dropped = Vec::new();
+        for tx in &block.transactions {
+            let hash = tx.hash();
+            let conflicting = tx
+                .inputs
+                .iter()
+                .filter_map(|input| self.spends.get(&(input.previous_tx, input.output_index)));
+            let mut stale: Vec<TxHash> = conflicting.copied().collect();
+            stale.push(hash);
+            for hash in stale {
+                if self.remove(&hash).is_some() {
+                    dropped.push(hash);
+                }
+            }
+        }
+        dropped
+    }
+
+    /// Drops what has waited longer than `max_age_secs` at time `now`.
+    pub fn expire(&mut self, now: u64) -> Vec<TxHash> {
+        let cutoff = now.saturating_sub(self.config.max_age_secs);
+        let expired: Vec<TxHash> =
+            self.entries.iter().filter(|(_, entry)| entry.added_at < cutoff).map(|(hash, _)| *hash).collect();
+        for hash in &expired {
+            self.remove(hash);
+        }
+        expired
+    }
+}
+
+/// The output `input` spends, if it is unspent and the next block may spend it.
+fn spendable_input(utxos: &dyn UtxoView, input: &TxInput) -> Result<TxOutput, MempoolError> {
+    let (tx, index) = (input.previous_tx, input.output_index);
+    let output = utxos.unspent(&tx, index).ok_or(MempoolError::MissingInput { tx, index })?;
+    let until = spendable_from(utxos, input, &output);
+    if utxos.next_height() < until {
+        return Err(MempoolError::Locked { tx, index, until });
+    }
+    Ok(output)
+}
+
+/// Size fee rates are measured against, as in [`crate::builder::estimate_fee_rate`].
+fn encoded_size(tx: &Transaction) -> usize {
+    serde_json::to_vec(tx).map(|bytes| bytes.len()).unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::{delivery_transaction, ProvenMessage, RELEASE_MEMO_PREFIX};
+    use crate::{CrossChainMessage, TxInput};
+
+    fn funded(owners: &[(u8, u64)]) -> ChainState {
+        let mut state = ChainState::default();
+        for (n, amount) in owners {
+            state.pending_utxos.insert(
+                [*n; 32],
+                vec![TxOutput {
+                    address: [*n; 32],
+                    amount: *amount,
+                    condition: None,
+                    spendable_after_height: None,
+                }],
+            );
+        }
+        state
+    }
+
+    fn spend(coins: &[u8], fee: u64, nonce: u64) -> Transaction {
+        Transaction {
+            inputs: coins
+                .iter()
+                .map(|n| TxInput {
+                    previous_tx: [*n; 32],
+                    output_index: 0,
+                    signature: vec![],
+                    public_key: vec![],
+                    witness: vec![],
+                })
+                .collect(),
+            outputs: vec![TxOutput {
+                address: [99; 32],
+                amount: 1_000,
+                condition: None,
+                spendable_after_height: None,
+            }],
+            fee,
+            nonce,
+            memo: None,
+            lock_time: 0,
+        }
+    }
+
+    #[test]
+    fn admits_by_fee_and_replaces_conflicts_and_evicts_the_cheapest() {
+        let size = encoded_size(&spend(&[1], 1_000, 0)) as u64;
+        let state = funded(&[(1, 1_000_000), (2, 1_000_000), (3, 1_000_000), (4, 10)]);
+        let config = MempoolConfig { max_txs: 2, ..MempoolConfig::default() };
+        let mut pool = Mempool::new(config);
+
+        let cheap = spend(&[1], size, 0);
+        let dear = spend(&[2], size * 5, 0);
+        assert!(pool.admit(cheap.clone(), &state).unwrap().evicted.is_empty());
+        pool.admit(dear.clone(), &state).unwrap();
+        assert_eq!(pool.admit(cheap.clone(), &state), Err(MempoolError::AlreadyKnown));
+        assert!(matches!(
+            pool.admit(spend(&[4], size, 0), &state),
+            Err(MempoolError::InsufficientInputs { .. })
+        ));
+        assert!(matches!(pool.admit(spend(&[5], size, 0), &state), Err(MempoolError::MissingInput { .. })));
+        assert!(matches!(
+            pool.admit(spend(&[3], 0, 0), &state),
+            Err(MempoolError::FeeTooLow { rate: 0, .. })
+        ));
+
+        // Spending what `cheap` spends needs a higher fee rate to replace it,
+        // and so does reusing its sender's nonce.
+        assert_eq!(pool.admit(spend(&[1], size + 1, 1), &state), Err(MempoolError::Conflict(cheap.hash())));
+        let mut same_nonce = spend(&[1], size * 2, 0);
+        same_nonce.outputs[0].amount = 999;
+        let replaced = pool.admit(same_nonce.clone(), &state).unwrap();
+        assert_eq!(replaced.replaced, [cheap.hash()]);
+
+        // Full: a better offer evicts the cheapest, a worse one is turned away.
+        assert_eq!(pool.admit(spend(&[3], size, 0), &state), Err(MempoolError::Full(1)));
+        let better = spend(&[3], size * 3, 0);
+        assert_eq!(pool.admit(better.clone(), &state).unwrap().evicted, [same_nonce.hash()]);
+        assert_eq!(
+            pool.transactions().map(Transaction::hash).collect::<Vec<_>>(),
+            [dear.hash(), better.hash()]
+        );
+        assert_eq!(
+            pool.select(size as usize + 10).iter().map(Transaction::hash).collect::<Vec<_>>(),
+            [dear.hash()]
+        );
+
+        let block = Block {
+            header: crate::BlockHeader {
+                previous_hash: [0; 32],
+                merkle_root: [0; 32],
+                height: 1,
+                timestamp: 0,
+                difficulty: 0,
+                nonce: 0,
+                validator: [0; 32],
+                stake_weight: 0,
+                state_root: [0; 32],
+            },
+            transactions: vec![dear.clone(), spend(&[3], size * 9, 7)],
+            pow_hash: [0; 32],
+            validator_signature: vec![],
+        };
+        let mut dropped = pool.remove_block(&block);
+        dropped.sort_unstable();
+        let mut expected = vec![dear.hash(), better.hash()];
+        expected.sort_unstable();
+        assert_eq!(dropped, expected);
+        assert!(pool.is_empty() && pool.bytes() == 0);
+
+        pool.admit(cheap, &state).unwrap();
+        assert!(pool.expire(now_ts()).is_empty());
+        assert_eq!(pool.expire(now_ts() + MempoolConfig::default().max_age_secs + 1).len(), 1);
+    }
+
+    #[test]
+    fn refuses_inputs_the_next_block_could_not_spend() {
+        let size = encoded_size(&spend(&[1], 1_000, 0)) as u64;
+        let mut state = funded(&[(1, 1_000_000), (2, 1_000_000)]);
+        state.coinbases.insert([1; 32], 5);
+        state.pending_utxos.get_mut(&[2; 32]).unwrap()[0].spendable_after_height = Some(20);
+        state.tip_hash = Some([0; 32]);
+        state.tip_height = 19;
+        let mut pool = Mempool::new(MempoolConfig::default());
+
+        let matures = 5 + COINBASE_MATURITY;
+        assert_eq!(
+            pool.admit(spend(&[1], size, 0), &state),
+            Err(MempoolError::Locked { tx: [1; 32], index: 0, until: matures })
+        );
+        assert_eq!(
+            pool.admit(spend(&[2], size, 0), &state),
+            Err(MempoolError::Locked { tx: [2; 32], index: 0, until: 21 })
+        );
+        state.tip_height = 20;
+        pool.admit(spend(&[2], size, 0), &state).unwrap();
+        state.tip_height = matures - 1;
+        pool.admit(spend(&[1], size, 0), &state).unwrap();
+    }
+
+    #[test]
+    fn releases_go_first_and_conflict_by_escrow_output() {
+        let escrow = bridge_escrow_address();
+        let mut state = funded(&[(1, 1_000_000)]);
+        let locked = TxOutput { address: escrow, amount: 500, condition: None, spendable_after_height: None };
+        state.pending_utxos.insert([7; 32], vec![locked]);
+        let release = |amount: u64, nonce: u64| {
+            let mut tx = spend(&[7], 0, nonce);
+            tx.outputs[0].amount = amount;
+            let message = CrossChainMessage {
+                id: uuid::Uuid::nil(),
+                source: "eth".into(),
+                dest: "dxid".into(),
+                payload: serde_json::json!({ "type": "burn" }),
+                nonce,
+                timestamp: 0,
+            };
+            let proven = ProvenMessage { message, proof: serde_json::Value::Null };
+            tx.memo = Some(format!("{RELEASE_MEMO_PREFIX}{}", serde_json::to_string(&proven).unwrap()));
+            tx
+        };
+        let mut pool = Mempool::new(MempoolConfig { max_txs: 1, ..MempoolConfig::default() });
+        let size = encoded_size(&spend(&[1], 1_000, 0)) as u64;
+        let paying = spend(&[1], size * 2, 0);
+        pool.admit(paying.clone(), &state).unwrap();
+
+        assert!(matches!(pool.admit(release(400, 1), &state), Err(MempoolError::Invalid(_))));
+        let admitted = pool.admit(release(500, 1), &state).unwrap();
+        assert_eq!(admitted.evicted, [paying.hash()]);
+        assert_eq!(pool.admit(release(500, 2), &state), Err(MempoolError::Conflict(admitted.hash)));
+        assert!(matches!(pool.admit(paying, &state), Err(MempoolError::Full(_))));
+
+        let mut stolen = spend(&[7], 0, 3);
+        stolen.outputs[0].amount = 500;
+        assert!(matches!(pool.admit(stolen, &state), Err(MempoolError::Invalid(_))));
+    }
+
+    #[test]
+    fn deliveries_move_nothing_and_conflict_by_message() {
+        let state = funded(&[(1, 1_000_000)]);
+        let proven = |nonce: u64| ProvenMessage {
+            message: CrossChainMessage {
+                id: uuid::Uuid::new_v4(),
+                source: "eth".into(),
+                dest: "dxid".into(),
+                payload: serde_json::json!({ "type": "note" }),
+                nonce,
+                timestamp: 0,
+            },
+            proof: serde_json::Value::Null,
+        };
+        let mut pool = Mempool::new(MempoolConfig::default());
+        let mut paying = delivery_transaction(&proven(1));
+        paying.fee = 10;
+        assert!(matches!(pool.admit(paying, &state), Err(MempoolError::Invalid(_))));
+        let admitted = pool.admit(delivery_transaction(&proven(1)), &state).unwrap();
+        let again = delivery_transaction(&proven(1));
+        assert_eq!(pool.admit(again, &state), Err(MempoolError::Conflict(admitted.hash)));
+        pool.admit(delivery_transaction(&proven(2)), &state).unwrap();
+        assert_eq!(pool.len(), 2);
+    }
+}
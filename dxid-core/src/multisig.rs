@@ -0,0 +1,303 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{Address, CryptoProvider};
+
+/// Marks an input `public_key` that reveals a multisig policy instead of a key.
+const POLICY_TAG: &[u8] = b"dxid/multisig/v1";
+/// Marks an input `public_key` that reveals a [`MultisigOutput`].
+const OUTPUT_TAG: &[u8] = b"dxid/multisig-output/v1";
+
+pub const MAX_COSIGNERS: usize = 15;
+
+/// M-of-N spending policy. Outputs pay to [`MultisigPolicy::address`]; the
+/// spending input reveals the policy in `public_key` and carries at least
+/// `threshold` signatures from distinct cosigners in `signature`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultisigPolicy {
+    pub threshold: u8,
+    /// Cosigner ed25519 public keys, sorted so every cosigner derives the same address.
+    pub public_keys: Vec<Vec<u8>>,
+}
+
+impl MultisigPolicy {
+    pub fn new(threshold: u8, mut public_keys: Vec<Vec<u8>>) -> Result<Self> {
+        public_keys.sort();
+        public_keys.dedup();
+        if public_keys.is_empty() || public_keys.len() > MAX_COSIGNERS {
+            return Err(anyhow!("multisig needs 1 to {MAX_COSIGNERS} distinct cosigners"));
+        }
+        if threshold == 0 || threshold as usize > public_keys.len() {
+            return Err(anyhow!("threshold {threshold} invalid for {} cosigners", public_keys.len()));
+        }
+        if public_keys.iter().any(|pk| pk.is_empty() || pk.len() > u8::MAX as usize) {
+            return Err(anyhow!("invalid cosigner key length"));
+        }
+        Ok(Self { threshold, public_keys })
+    }
+
+    pub fn address(&self) -> Address {
+        blake3::hash(&self.encode()).into()
+    }
+
+    /// Witness form placed in the spending input's `public_key`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = POLICY_TAG.to_vec();
+        out.push(self.threshold);
+        out.push(self.public_keys.len() as u8);
+        for pk in &self.public_keys {
+            out.push(pk.len() as u8);
+            out.extend_from_slice(pk);
+        }
+        out
+    }
+
+    /// Parses [`MultisigPolicy::encode`] output; `None` for anything else,
+    /// including plain public keys.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut rest = bytes.strip_prefix(POLICY_TAG)?;
+        let (&threshold, tail) = rest.split_first()?;
+        let (&count, tail) = tail.split_first()?;
+        rest = tail;
+        let mut public_keys = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (&len, tail) = rest.split_first()?;
+            if tail.len() < len as usize {
+                return None;
+            }
+            let (pk, tail) = tail.split_at(len as usize);
+            public_keys.push(pk.to_vec());
+            rest = tail;
+        }
+        let policy = Self::new(threshold, public_keys).ok()?;
+        // Only the canonical (sorted, exact-length) encoding is accepted.
+        (rest.is_empty() && policy.encode() == bytes).then_some(policy)
+    }
+
+    pub fn key_index(&self, public_key: &[u8]) -> Option<u8> {
+        self.public_keys.iter().position(|pk| pk == public_key).map(|i| i as u8)
+    }
+
+    /// Checks that `witness` holds at least `threshold` valid signatures over
+    /// `msg` from distinct cosigners.
+    pub fn verify<C: CryptoProvider>(&self, crypto: &C, msg: &[u8], witness: &[u8]) -> Result<bool> {
+        let signatures = decode_signatures(witness).ok_or_else(|| anyhow!("malformed multisig witness"))?;
+        let mut signers = HashSet::new();
+        for (index, sig) in signatures {
+            let Some(pk) = self.public_keys.get(index as usize) else {
+                return Ok(false);
+            };
+            if !signers.insert(index) || !crypto.verify_signature(pk, msg, &sig)? {
+                return Ok(false);
+            }
+        }
+        Ok(signers.len() >= self.threshold as usize)
+    }
+}
+
+/// M-of-N output over cosigner addresses rather than keys, so cosigners are
+/// named by the addresses they already share and reveal a key only when they
+/// sign. Outputs pay to [`MultisigOutput::address`], the hash of the
+/// threshold and addresses; the spending input reveals the output in
+/// `public_key` and carries, for at least `threshold` distinct cosigners, a
+/// key hashing to the cosigner's address and its signature in `signature`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultisigOutput {
+    pub threshold: u8,
+    /// Cosigner addresses, sorted so every cosigner derives the same address.
+    pub cosigners: Vec<Address>,
+}
+
+impl MultisigOutput {
+    pub fn new(threshold: u8, mut cosigners: Vec<Address>) -> Result<Self> {
+        cosigners.sort();
+        cosigners.dedup();
+        if cosigners.is_empty() || cosigners.len() > MAX_COSIGNERS {
+            return Err(anyhow!("multisig needs 1 to {MAX_COSIGNERS} distinct cosigners"));
+        }
+        if threshold == 0 || threshold as usize > cosigners.len() {
+            return Err(anyhow!("threshold {threshold} invalid for {} cosigners", cosigners.len()));
+        }
+        Ok(Self { threshold, cosigners })
+    }
+
+    pub fn address(&self) -> Address {
+        blake3::hash(&self.encode()).into()
+    }
+
+    /// Witness form placed in the spending input's `public_key`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = OUTPUT_TAG.to_vec();
+        out.push(self.threshold);
+        out.push(self.cosigners.len() as u8);
+        for address in &self.cosigners {
+            out.extend_from_slice(address);
+        }
+        out
+    }
+
+    /// Parses [`MultisigOutput::encode`] output; `None` for anything else.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let rest = bytes.strip_prefix(OUTPUT_TAG)?;
+        let (&threshold, rest) = rest.split_first()?;
+        let (&count, rest) = rest.split_first()?;
+        if rest.len() != count as usize * 32 {
+            return None;
+        }
+        let cosigners = rest.chunks(32).map(|chunk| chunk.try_into().expect("32-byte chunks")).collect();
+        let output = Self::new(threshold, cosigners).ok()?;
+        (output.encode() == bytes).then_some(output)
+    }
+
+    pub fn cosigner_index(&self, address: &Address) -> Option<u8> {
+        self.cosigners.iter().position(|a| a == address).map(|i| i as u8)
+    }
+
+    /// Checks that `witness` holds at least `threshold` valid signatures over
+    /// `msg`, each by a key whose address is a distinct cosigner's.
+    pub fn verify<C: CryptoProvider>(&self, crypto: &C, msg: &[u8], witness: &[u8]) -> Result<bool> {
+        let signatures = decode_cosignatures(witness).ok_or_else(|| anyhow!("malformed multisig witness"))?;
+        let mut signers = HashSet::new();
+        for (index, pk, sig) in signatures {
+            let Some(cosigner) = self.cosigners.get(index as usize) else {
+                return Ok(false);
+            };
+            if crypto.address_from_public_key(&pk)? != *cosigner
+                || !signers.insert(index)
+                || !crypto.verify_signature(&pk, msg, &sig)?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(signers.len() >= self.threshold as usize)
+    }
+}
+
+/// Signature witness: `(cosigner index, signature)` pairs.
+pub fn encode_signatures(signatures: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let mut out = vec![signatures.len() as u8];
+    for (index, sig) in signatures {
+        out.push(*index);
+        out.push(sig.len() as u8);
+        out.extend_from_slice(sig);
+    }
+    out
+}
+
+pub fn decode_signatures(bytes: &[u8]) -> Option<Vec<(u8, Vec<u8>)>> {
+    let (&count, mut rest) = bytes.split_first()?;
+    let mut out = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (&index, tail) = rest.split_first()?;
+        let (&len, tail) = tail.split_first()?;
+        if tail.len() < len as usize {
+            return None;
+        }
+        let (sig, tail) = tail.split_at(len as usize);
+        out.push((index, sig.to_vec()));
+        rest = tail;
+    }
+    rest.is_empty().then_some(out)
+}
+
+/// A [`MultisigOutput`] cosigner's index, public key and signature.
+pub type Cosignature = (u8, Vec<u8>, Vec<u8>);
+
+/// [`MultisigOutput`] witness: the cosignatures in order.
+pub fn encode_cosignatures(signatures: &[Cosignature]) -> Vec<u8> {
+    let mut out = vec![signatures.len() as u8];
+    for (index, pk, sig) in signatures {
+        out.push(*index);
+        out.push(pk.len() as u8);
+        out.extend_from_slice(pk);
+        out.push(sig.len() as u8);
+        out.extend_from_slice(sig);
+    }
+    out
+}
+
+pub fn decode_cosignatures(bytes: &[u8]) -> Option<Vec<Cosignature>> {
+    let (&count, mut rest) = bytes.split_first()?;
+    let mut out = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (&index, tail) = rest.split_first()?;
+        let (pk, tail) = split_prefixed(tail)?;
+        let (sig, tail) = split_prefixed(tail)?;
+        out.push((index, pk.to_vec(), sig.to_vec()));
+        rest = tail;
+    }
+    rest.is_empty().then_some(out)
+}
+
+/// Splits a length-prefixed field off the front of `bytes`.
+fn split_prefixed(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (&len, tail) = bytes.split_first()?;
+    (tail.len() >= len as usize).then(|| tail.split_at(len as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_roundtrips_and_is_order_independent() {
+        let a = MultisigPolicy::new(2, vec![vec![3u8; 32], vec![1u8; 32], vec![2u8; 32]]).unwrap();
+        let b = MultisigPolicy::new(2, vec![vec![1u8; 32], vec![2u8; 32], vec![3u8; 32]]).unwrap();
+        assert_eq!(a.address(), b.address());
+        assert_eq!(MultisigPolicy::decode(&a.encode()), Some(a.clone()));
+        assert!(MultisigPolicy::decode(&[1u8; 32]).is_none());
+        assert!(MultisigPolicy::new(4, a.public_keys.clone()).is_err());
+
+        let sigs = vec![(0, vec![9u8; 64]), (2, vec![8u8; 64])];
+        assert_eq!(decode_signatures(&encode_signatures(&sigs)), Some(sigs));
+    }
+    /// Signs by prefixing the message with the key; addresses hash the key.
+    struct PrefixCrypto;
+
+    impl CryptoProvider for PrefixCrypto {
+        fn address_from_public_key(&self, pk: &[u8]) -> Result<Address> {
+            Ok(blake3::hash(pk).into())
+        }
+
+        fn verify_signature(&self, pk: &[u8], msg: &[u8], sig: &[u8]) -> Result<bool> {
+            Ok(sig == [pk, msg].concat())
+        }
+
+        fn sign_message(&self, sk: &[u8], msg: &[u8]) -> Result<Vec<u8>> {
+            Ok([sk, msg].concat())
+        }
+
+        fn hash_block_header(&self, header: &crate::BlockHeader) -> [u8; 32] {
+            header.hash()
+        }
+    }
+
+    #[test]
+    fn address_output_needs_threshold_keys_of_its_cosigners() {
+        let keys: Vec<Vec<u8>> = (1..=3u8).map(|i| vec![i; 32]).collect();
+        let addresses: Vec<Address> = keys.iter().map(|pk| blake3::hash(pk).into()).collect();
+        let output = MultisigOutput::new(2, addresses.iter().rev().copied().collect()).unwrap();
+        assert_eq!(output, MultisigOutput::new(2, addresses.clone()).unwrap());
+        assert_eq!(MultisigOutput::decode(&output.encode()), Some(output.clone()));
+        assert!(MultisigOutput::decode(&MultisigPolicy::new(2, keys.clone()).unwrap().encode()).is_none());
+        assert_ne!(output.address(), MultisigPolicy::new(2, keys.clone()).unwrap().address());
+        assert!(MultisigOutput::new(4, addresses.clone()).is_err());
+
+        let msg = b"spend".as_slice();
+        let signed = |i: usize| {
+            let index = output.cosigner_index(&addresses[i]).unwrap();
+            (index, keys[i].clone(), PrefixCrypto.sign_message(&keys[i], msg).unwrap())
+        };
+        let verify = |sigs: &[Cosignature]| output.verify(&PrefixCrypto, msg, &encode_cosignatures(sigs));
+        assert_eq!(decode_cosignatures(&encode_cosignatures(&[signed(0)])), Some(vec![signed(0)]));
+        assert!(verify(&[signed(0), signed(2)]).unwrap());
+        assert!(!verify(&[signed(0)]).unwrap());
+        assert!(!verify(&[signed(0), signed(0)]).unwrap());
+        // A key that is not the cosigner's at that index does not count.
+        let (index, _, sig) = signed(1);
+        let stranger = vec![9u8; 32];
+        assert!(!verify(&[signed(0), (index, stranger, sig)]).unwrap());
+    }
+}
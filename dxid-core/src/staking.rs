@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use blake3::Hasher;
+use serde::{Deserialize, Serialize};
+
+use crate::{Address, ChainState, TxHash, TxOutput};
+
+/// Memo prefix of staking transactions; the rest is a JSON [`StakeMemo`].
+pub const STAKE_MEMO_PREFIX: &str = "stake:";
+
+/// Blocks between an unstake and the tokens becoming withdrawable.
+pub const UNBONDING_PERIOD: u64 = 10_080;
+
+/// Address holding all bonded tokens. Like the bridge escrow it has no key;
+/// tokens leave it only through [`apply_stake_withdrawal`].
+pub fn staking_pool_address() -> Address {
+    blake3::hash(b"dxid/staking-pool").into()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum StakeAction {
+    /// Bonds the transaction's pool outputs to the owner's own validator.
+    Stake,
+    /// Bonds the transaction's pool outputs to `validator`.
+    Delegate { validator: Address },
+    /// Starts unbonding `amount` from `validator`.
+    Unstake { validator: Address, amount: u64 },
+    /// Pays out rewards and fully unbonded stake.
+    WithdrawRewards,
+}
+
+/// What a staking transaction does and for whom. `owner` must own one of
+/// the transaction's inputs.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StakeMemo {
+    pub owner: Address,
+    #[serde(flatten)]
+    pub action: StakeAction,
+}
+
+impl StakeMemo {
+    pub fn to_memo(&self) -> String {
+        format!("{STAKE_MEMO_PREFIX}{}", serde_json::to_string(self).expect("stake memo serializes"))
+    }
+}
+
+pub fn parse_stake_memo(memo: &str) -> Option<StakeMemo> {
+    serde_json::from_str(memo.strip_prefix(STAKE_MEMO_PREFIX)?).ok()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Bond {
+    pub validator: Address,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Unbonding {
+    pub owner: Address,
+    pub validator: Address,
+    pub amount: u64,
+    /// First height at which the amount can be withdrawn.
+    pub release_height: u64,
+}
+
+impl Unbonding {
+    pub fn blocks_remaining(&self, height: u64) -> u64 {
+        self.release_height.saturating_sub(height)
+    }
+}
+
+/// One owner's stake, as shown to wallets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StakingPosition {
+    pub bonds: Vec<Bond>,
+    pub unbonding: Vec<Unbonding>,
+    /// Rewards earned and not yet withdrawn.
+    pub rewards: u64,
+    /// Height the position was taken at.
+    pub height: u64,
+}
+
+impl StakingPosition {
+    pub fn bonded(&self) -> u64 {
+        self.bonds.iter().map(|b| b.amount).sum()
+    }
+
+    /// Unbonded stake that can be withdrawn now, with the rewards.
+    pub fn withdrawable(&self) -> u64 {
+        let released = self.unbonding.iter().filter(|u| u.release_height <= self.height).map(|u| u.amount);
+        released.sum::<u64>().saturating_add(self.rewards)
+    }
+}
+
+/// Stake bonded to one validator, summed over its owners' positions.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ValidatorStatus {
+    pub validator: Address,
+    /// Bonded by the validator itself.
+    pub self_stake: u64,
+    /// Bonded by other owners.
+    pub delegated: u64,
+    pub delegators: usize,
+    /// Being unbonded from the validator.
+    pub unbonding: u64,
+    /// Height of the newest position summed.
+    pub height: u64,
+}
+
+impl ValidatorStatus {
+    /// Sums what each `(owner, position)` has bonded to or is unbonding from `validator`.
+    pub fn from_positions<'a>(
+        validator: Address,
+        positions: impl IntoIterator<Item = (Address, &'a StakingPosition)>,
+    ) -> Self {
+        let mut status = Self { validator, ..Self::default() };
+        for (owner, position) in positions {
+            let bonded: u64 =
+                position.bonds.iter().filter(|b| b.validator == validator).map(|b| b.amount).sum();
+            if owner == validator {
+                status.self_stake = status.self_stake.saturating_add(bonded);
+            } else if bonded > 0 {
+                status.delegated = status.delegated.saturating_add(bonded);
+                status.delegators += 1;
+            }
+            let unbonding = position.unbonding.iter().filter(|u| u.validator == validator).map(|u| u.amount);
+            status.unbonding = status.unbonding.saturating_add(unbonding.sum());
+            status.height = status.height.max(position.height);
+        }
+        status
+    }
+
+    pub fn total_stake(&self) -> u64 {
+        self.self_stake.saturating_add(self.delegated)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StakingLedger {
+    /// Bonded amount by `(owner, validator)`.
+    pub bonds: HashMap<(Address, Address), u64>,
+    pub unbonding: Vec<Unbonding>,
+    pub rewards: HashMap<Address, u64>,
+    /// Height of the block being applied.
+    pub height: u64,
+}
+
+impl StakingLedger {
+    pub fn bond(&mut self, owner: Address, validator: Address, amount: u64) -> Result<()> {
+        let entry = self.bonds.entry((owner, validator)).or_insert(0);
+        *entry = entry.checked_add(amount).ok_or_else(|| anyhow!("bond overflow"))?;
+        Ok(())
+    }
+
+    pub fn unbond(&mut self, owner: Address, validator: Address, amount: u64) -> Result<()> {
+        let bonded = self.bonds.get(&(owner, validator)).copied().unwrap_or(0);
+        if amount == 0 || amount > bonded {
+            return Err(anyhow!("cannot unstake {amount} of {bonded} bonded"));
+        }
+        if bonded == amount {
+            self.bonds.remove(&(owner, validator));
+        } else {
+            self.bonds.insert((owner, validator), bonded - amount);
+        }
+        let release_height = self.height.saturating_add(UNBONDING_PERIOD);
+        self.unbonding.push(Unbonding { owner, validator, amount, release_height });
+        Ok(())
+    }
+
+    /// Total bonded to `validator`, its own stake included.
+    pub fn validator_stake(&self, validator: &Address) -> u64 {
+        self.bonds.iter().filter(|((_, v), _)| v == validator).map(|(_, amount)| amount).sum()
+    }
+
+    /// Total bonded to each validator, ordered by address.
+    pub fn validator_stakes(&self) -> BTreeMap<Address, u64> {
+        let mut stakes = BTreeMap::new();
+        for ((_, validator), amount) in &self.bonds {
+            let stake = stakes.entry(*validator).or_insert(0u64);
+            *stake = stake.saturating_add(*amount);
+        }
+        stakes
+    }
+
+    /// Shares `reward` between the delegators of `validator` by stake and
+    /// returns the part that stays with the validator itself.
+    pub fn distribute(&mut self, validator: &Address, reward: u64) -> u64 {
+        let total = self.validator_stake(validator) as u128;
+        if total == 0 {
+            return reward;
+        }
+        let mut delegated = 0u64;
+        for ((owner, v), amount) in &self.bonds {
+            if v != validator || owner == validator {
+                continue;
+            }
+            let share = (reward as u128 * *amount as u128 / total) as u64;
+            *self.rewards.entry(*owner).or_insert(0) += share;
+            delegated += share;
+        }
+        reward - delegated
+    }
+
+    /// Takes `owner`'s rewards and released unbondings.
+    pub fn withdraw(&mut self, owner: &Address) -> u64 {
+        let height = self.height;
+        let mut payout = self.rewards.remove(owner).unwrap_or(0);
+        self.unbonding.retain(|u| {
+            let released = &u.owner == owner && u.release_height <= height;
+            if released {
+                payout = payout.saturating_add(u.amount);
+            }
+            !released
+        });
+        payout
+    }
+
+    pub fn position(&self, owner: &Address) -> StakingPosition {
+        let mut bonds: Vec<Bond> = self
+            .bonds
+            .iter()
+            .filter(|((o, _), _)| o == owner)
+            .map(|((_, validator), amount)| Bond { validator: *validator, amount: *amount })
+            .collect();
+        bonds.sort_by_key(|b| b.validator);
+        StakingPosition {
+            bonds,
+            unbonding: self.unbonding.iter().filter(|u| &u.owner == owner).cloned().collect(),
+            rewards: self.rewards.get(owner).copied().unwrap_or(0),
+            height: self.height,
+        }
+    }
+
+    /// Owners whose position differs in `after`, ignoring the height.
+    pub fn changed_positions(&self, after: &StakingLedger) -> Vec<Address> {
+        let mut owners: Vec<Address> = [self, after]
+            .into_iter()
+            .flat_map(|ledger| {
+                let bonded = ledger.bonds.keys().map(|(owner, _)| *owner);
+                bonded.chain(ledger.unbonding.iter().map(|u| u.owner)).chain(ledger.rewards.keys().copied())
+            })
+            .collect();
+        owners.sort_unstable();
+        owners.dedup();
+        owners.retain(|owner| {
+            let (mut old, new) = (self.position(owner), after.position(owner));
+            old.height = new.height;
+            old != new
+        });
+        owners
+    }
+}
+
+/// Applies the staking part of transaction `tx_hash`: `pool_amount` is what
+/// it paid into the staking pool.
+pub fn apply_stake_memo(
+    state: &mut ChainState,
+    tx_hash: &TxHash,
+    memo: &StakeMemo,
+    pool_amount: u64,
+) -> Result<()> {
+    match &memo.action {
+        StakeAction::Stake | StakeAction::Delegate { .. } if pool_amount == 0 => {
+            Err(anyhow!("staking transaction pays nothing into the pool"))
+        }
+        StakeAction::Stake => state.bond(memo.owner, memo.owner, pool_amount),
+        StakeAction::Delegate { validator } => state.bond(memo.owner, *validator, pool_amount),
+        _ if pool_amount > 0 => Err(anyhow!("only stake and delegate pay into the pool")),
+        StakeAction::Unstake { validator, amount } => state.unbond(memo.owner, *validator, *amount),
+        StakeAction::WithdrawRewards => apply_stake_withdrawal(state, tx_hash, &memo.owner).map(|_| ()),
+    }
+}
+
+/// Pays `owner`'s withdrawable stake and rewards into a new output, keyed by
+/// the withdrawing transaction. Returns the amount paid.
+pub fn apply_stake_withdrawal(state: &mut ChainState, tx_hash: &TxHash, owner: &Address) -> Result<u64> {
+    let payout = state.withdraw(owner);
+    if payout == 0 {
+        return Err(anyhow!("nothing to withdraw"));
+    }
+    let mut hasher = Hasher::new();
+    hasher.update(b"dxid/stake-withdrawal");
+    hasher.update(tx_hash);
+    let hash: TxHash = hasher.finalize().into();
+    state.touch_balance(owner);
+    let entry = state.balances.entry(*owner).or_insert(0);
+    *entry = entry.checked_add(payout).ok_or_else(|| anyhow!("balance overflow"))?;
+    state.touch_outputs(&hash);
+    state.pending_utxos.insert(
+        hash,
+        vec![TxOutput { address: *owner, amount: payout, condition: None, spendable_after_height: None }],
+    );
+    Ok(payout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stake_unbonds_and_shares_rewards() {
+        let (validator, delegator) = ([1u8; 32], [2u8; 32]);
+        let mut state = ChainState::default();
+        let stake = StakeMemo { owner: validator, action: StakeAction::Stake };
+        let delegate = StakeMemo { owner: delegator, action: StakeAction::Delegate { validator } };
+        assert_eq!(parse_stake_memo(&delegate.to_memo()), Some(delegate.clone()));
+        apply_stake_memo(&mut state, &[0u8; 32], &stake, 300).unwrap();
+        apply_stake_memo(&mut state, &[1u8; 32], &delegate, 100).unwrap();
+        assert!(apply_stake_memo(&mut state, &[2u8; 32], &delegate, 0).is_err());
+
+        assert_eq!(state.staking.distribute(&validator, 40), 30);
+        assert_eq!(state.staking.position(&delegator).rewards, 10);
+
+        let unstake = StakeMemo { owner: delegator, action: StakeAction::Unstake { validator, amount: 60 } };
+        state.staking.height = 5;
+        apply_stake_memo(&mut state, &[3u8; 32], &unstake, 0).unwrap();
+        let position = state.staking.position(&delegator);
+        assert_eq!(position.bonded(), 40);
+        assert_eq!(position.unbonding[0].blocks_remaining(5), UNBONDING_PERIOD);
+        assert_eq!(position.withdrawable(), 10);
+
+        let withdraw = StakeMemo { owner: delegator, action: StakeAction::WithdrawRewards };
+        let before = state.staking.clone();
+        apply_stake_memo(&mut state, &[4u8; 32], &withdraw, 0).unwrap();
+        assert_eq!(before.changed_positions(&state.staking), [delegator]);
+        assert_eq!(state.balances[&delegator], 10);
+        assert!(apply_stake_memo(&mut state, &[5u8; 32], &withdraw, 0).is_err());
+
+        let positions = [validator, delegator].map(|owner| (owner, state.staking.position(&owner)));
+        let status = ValidatorStatus::from_positions(validator, positions.iter().map(|(o, p)| (*o, p)));
+        assert_eq!((status.self_stake, status.delegated, status.delegators), (300, 40, 1));
+        assert_eq!((status.total_stake(), status.unbonding, status.height), (340, 60, 5));
+        assert_eq!(state.staking.validator_stakes(), BTreeMap::from([(validator, 340)]));
+
+        state.staking.height = 5 + UNBONDING_PERIOD;
+        assert_eq!(apply_stake_withdrawal(&mut state, &[6u8; 32], &delegator).unwrap(), 60);
+        assert!(state.staking.position(&delegator).unbonding.is_empty());
+    }
+}
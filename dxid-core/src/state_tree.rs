@@ -0,0 +1,273 @@
+//! Commitment to the chain state: a sparse Merkle tree over the balances,
+//! unspent outputs and identities. Each block header carries the root of
+//! the state it applies to, so a light client holding a header can check
+//! a single entry with [`verify_inclusion`] and a proof from
+//! [`prove_inclusion`].
+//!
+//! A leaf sits at the path given by the bits of its key's hash, most
+//! significant first. An empty subtree hashes to zero and a subtree holding
+//! one leaf to that leaf's hash, so a proof lists the siblings only down to
+//! where the leaf's own subtree starts.
+
+use std::collections::BTreeMap;
+
+use blake3::Hasher;
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::{encode_bytes, encode_len, CanonicalEncode};
+use crate::{Address, BlockHash, ChainState, Identity, IdentityId, IdentityStatus, TxHash, TxOutput};
+
+/// Root of the empty state, as before genesis.
+pub const EMPTY_ROOT: BlockHash = [0; 32];
+
+type Path = [u8; 32];
+
+/// A leaf's path and hash.
+type Node = (Path, BlockHash);
+
+/// Names an entry of the state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StateKey {
+    Balance(Address),
+    Output { tx_hash: TxHash, index: u32 },
+    Identity(IdentityId),
+}
+
+impl StateKey {
+    /// Where the entry sits in the tree.
+    pub fn path(&self) -> Path {
+        let mut hasher = Hasher::new();
+        match self {
+            StateKey::Balance(address) => {
+                hasher.update(b"dxid/state/balance");
+                hasher.update(address);
+            }
+            StateKey::Output { tx_hash, index } => {
+                hasher.update(b"dxid/state/output");
+                hasher.update(tx_hash);
+                hasher.update(&index.to_le_bytes());
+            }
+            StateKey::Identity(id) => {
+                hasher.update(b"dxid/state/identity");
+                hasher.update(id.as_bytes());
+            }
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// An entry of the state with its value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StateLeaf {
+    Balance { address: Address, amount: u64 },
+    Output { tx_hash: TxHash, index: u32, output: TxOutput },
+    Identity(Identity),
+}
+
+impl StateLeaf {
+    pub fn key(&self) -> StateKey {
+        match self {
+            StateLeaf::Balance { address, .. } => StateKey::Balance(*address),
+            StateLeaf::Output { tx_hash, index, .. } => StateKey::Output { tx_hash: *tx_hash, index: *index },
+            StateLeaf::Identity(identity) => StateKey::Identity(identity.id),
+        }
+    }
+
+    /// The entry at `key`, if the state holds one. Zero balances and
+    /// outputs, which is how spent ones are kept, are not entries.
+    pub fn get(state: &ChainState, key: &StateKey) -> Option<Self> {
+        match key {
+            StateKey::Balance(address) => state
+                .balances
+                .get(address)
+                .filter(|amount| **amount > 0)
+                .map(|amount| StateLeaf::Balance { address: *address, amount: *amount }),
+            StateKey::Output { tx_hash, index } => state
+                .pending_utxos
+                .get(tx_hash)
+                .and_then(|outputs| outputs.get(*index as usize))
+                .filter(|output| output.amount > 0)
+                .map(|output| StateLeaf::Output { tx_hash: *tx_hash, index: *index, output: output.clone() }),
+            StateKey::Identity(id) => state.identities.get(id).cloned().map(StateLeaf::Identity),
+        }
+    }
+
+    fn hash(&self) -> BlockHash {
+        let mut out = vec![0];
+        self.key().path().encode_to(&mut out);
+        match self {
+            StateLeaf::Balance { amount, .. } => amount.encode_to(&mut out),
+            StateLeaf::Output { output, .. } => {
+                output.encode_to(&mut out);
+                if let Some(condition) = &output.condition {
+                    condition.encode_to(&mut out);
+                }
+                // Marked with a byte no condition starts with.
+                if let Some(height) = output.spendable_after_height {
+                    out.push(u8::MAX);
+                    height.encode_to(&mut out);
+                }
+            }
+            StateLeaf::Identity(identity) => encode_identity(identity, &mut out),
+        }
+        blake3::hash(&out).into()
+    }
+}
+
+/// Attributes are encoded in key order, so the hash does not depend on a
+/// map's iteration order.
+fn encode_identity(identity: &Identity, out: &mut Vec<u8>) {
+    encode_bytes(identity.id.as_bytes(), out);
+    encode_len(identity.public_keys.len(), out);
+    for key in &identity.public_keys {
+        encode_bytes(key, out);
+    }
+    let attributes: BTreeMap<_, _> = identity.attributes.iter().collect();
+    encode_len(attributes.len(), out);
+    for (key, attribute) in attributes {
+        key.encode_to(out);
+        attribute.value.encode_to(out);
+        attribute.embedding_ref.as_deref().encode_to(out);
+    }
+    out.push(match identity.status {
+        IdentityStatus::Active => 0,
+        IdentityStatus::Revoked => 1,
+    });
+}
+
+/// Sibling hashes on the path from the root down to a leaf.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateProof {
+    pub siblings: Vec<BlockHash>,
+}
+
+/// Root of the tree over `state`.
+pub fn state_root(state: &ChainState) -> BlockHash {
+    subtree_hash(&leaves(state), 0)
+}
+
+/// The entry at `key` and a proof of it against [`state_root`] of `state`.
+pub fn prove_inclusion(state: &ChainState, key: &StateKey) -> Option<(StateLeaf, StateProof)> {
+    let leaf = StateLeaf::get(state, key)?;
+    let mut siblings = Vec::new();
+    collect_siblings(&leaves(state), &key.path(), 0, &mut siblings);
+    Some((leaf, StateProof { siblings }))
+}
+
+/// Whether `proof` shows `leaf` is an entry of the state with `root`.
+pub fn verify_inclusion(root: &BlockHash, leaf: &StateLeaf, proof: &StateProof) -> bool {
+    if proof.siblings.len() > 256 {
+        return false;
+    }
+    let path = leaf.key().path();
+    let mut hash = leaf.hash();
+    for (depth, sibling) in proof.siblings.iter().enumerate().rev() {
+        hash = if bit(&path, depth) { node_hash(sibling, &hash) } else { node_hash(&hash, sibling) };
+    }
+    hash == *root
+}
+
+/// Every entry's path and leaf hash, by path.
+fn leaves(state: &ChainState) -> Vec<Node> {
+    let balances = state.balances.keys().map(|address| StateKey::Balance(*address));
+    let outputs = state.pending_utxos.iter().flat_map(|(tx_hash, outputs)| {
+        (0..outputs.len() as u32).map(|index| StateKey::Output { tx_hash: *tx_hash, index })
+    });
+    let identities = state.identities.keys().map(|id| StateKey::Identity(*id));
+    let mut leaves: Vec<_> = balances
+        .chain(outputs)
+        .chain(identities)
+        .filter_map(|key| StateLeaf::get(state, &key))
+        .map(|leaf| (leaf.key().path(), leaf.hash()))
+        .collect();
+    leaves.sort_unstable();
+    leaves
+}
+
+fn subtree_hash(leaves: &[Node], depth: usize) -> BlockHash {
+    match leaves {
+        [] => EMPTY_ROOT,
+        [(_, leaf)] => *leaf,
+        _ => {
+            let (left, right) = split(leaves, depth);
+            node_hash(&subtree_hash(left, depth + 1), &subtree_hash(right, depth + 1))
+        }
+    }
+}
+
+/// Pushes the siblings of the subtrees on the way to `path` until one holds
+/// at most a single leaf.
+fn collect_siblings(leaves: &[Node], path: &Path, depth: usize, siblings: &mut Vec<BlockHash>) {
+    if leaves.len() < 2 {
+        return;
+    }
+    let (left, right) = split(leaves, depth);
+    let (own, other) = if bit(path, depth) { (right, left) } else { (left, right) };
+    siblings.push(subtree_hash(other, depth + 1));
+    collect_siblings(own, path, depth + 1, siblings);
+}
+
+/// Leaves sorted by path, split on the bit at `depth`.
+fn split(leaves: &[Node], depth: usize) -> (&[Node], &[Node]) {
+    leaves.split_at(leaves.partition_point(|(path, _)| !bit(path, depth)))
+}
+
+fn bit(path: &Path, depth: usize) -> bool {
+    path[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+fn node_hash(left: &BlockHash, right: &BlockHash) -> BlockHash {
+    let mut hasher = Hasher::new();
+    hasher.update(&[1]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{add_attribute, new_identity, IdentityAttribute};
+
+    #[test]
+    fn proves_balances_outputs_and_identities_against_the_root() {
+        let mut state = ChainState::default();
+        assert_eq!(state_root(&state), EMPTY_ROOT);
+        for byte in 1..=20u8 {
+            state.balances.insert([byte; 32], byte as u64 * 10);
+        }
+        state.balances.insert([99; 32], 0);
+        let coin =
+            |amount| TxOutput { address: [1; 32], amount, condition: None, spendable_after_height: None };
+        state.pending_utxos.insert([7; 32], vec![coin(0), coin(5)]);
+        let mut identity = new_identity(vec![3; 32]);
+        add_attribute(
+            &mut identity,
+            IdentityAttribute { key: "name".into(), value: "ada".into(), embedding_ref: None },
+        );
+        state.identities.insert(identity.id, identity.clone());
+        let root = state_root(&state);
+
+        let keys = [
+            StateKey::Balance([4; 32]),
+            StateKey::Output { tx_hash: [7; 32], index: 1 },
+            StateKey::Identity(identity.id),
+        ];
+        for key in &keys {
+            let (leaf, proof) = prove_inclusion(&state, key).unwrap();
+            assert!(verify_inclusion(&root, &leaf, &proof), "{key:?}");
+            assert!(!verify_inclusion(&[1; 32], &leaf, &proof));
+        }
+        // Spent outputs and zero balances are not entries.
+        assert!(prove_inclusion(&state, &StateKey::Output { tx_hash: [7; 32], index: 0 }).is_none());
+        assert!(prove_inclusion(&state, &StateKey::Balance([99; 32])).is_none());
+
+        // A changed value neither verifies nor keeps the root.
+        let (_, proof) = prove_inclusion(&state, &keys[0]).unwrap();
+        let forged = StateLeaf::Balance { address: [4; 32], amount: 41 };
+        assert!(!verify_inclusion(&root, &forged, &proof));
+        identity.attributes.get_mut("name").unwrap().value = "bob".into();
+        state.identities.insert(identity.id, identity);
+        assert_ne!(state_root(&state), root);
+    }
+}
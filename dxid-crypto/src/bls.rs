@@ -0,0 +1,104 @@
+//! BLS signatures over BLS12-381 in the min-pk variant Ethereum uses: 48-byte
+//! compressed G1 public keys, 96-byte compressed G2 signatures, and messages
+//! hashed to G2 under the proof-of-possession ciphersuite. Callers must only
+//! aggregate keys whose possession has been proven, or a rogue key can forge
+//! an aggregate.
+
+use ark_bls12_381::{g2, Bls12_381, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_ec::hashing::curve_maps::wb::WBMap;
+use ark_ec::hashing::map_to_curve_hasher::MapToCurveBasedHasher;
+use ark_ec::hashing::HashToCurve;
+use ark_ec::pairing::Pairing;
+use ark_ec::{AffineRepr, CurveGroup, Group};
+use ark_ff::field_hashers::DefaultFieldHasher;
+use ark_ff::{PrimeField, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use sha2::Sha256;
+
+/// Domain separation tag of the proof-of-possession ciphersuite.
+pub const BLS_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+type G2Hasher = MapToCurveBasedHasher<G2Projective, DefaultFieldHasher<Sha256, 128>, WBMap<g2::Config>>;
+
+fn hash_to_g2(message: &[u8]) -> Option<G2Affine> {
+    G2Hasher::new(BLS_DST).ok()?.hash(message).ok()
+}
+
+fn secret_scalar(secret: &[u8; 32]) -> Fr {
+    Fr::from_be_bytes_mod_order(secret)
+}
+
+fn compress<T: CanonicalSerialize>(point: &T) -> Vec<u8> {
+    let mut out = Vec::new();
+    point.serialize_compressed(&mut out).expect("writing to a vec");
+    out
+}
+
+/// The public key of `secret`, a big-endian scalar.
+pub fn public_key(secret: &[u8; 32]) -> Vec<u8> {
+    compress(&(G1Projective::generator() * secret_scalar(secret)).into_affine())
+}
+
+pub fn sign(secret: &[u8; 32], message: &[u8]) -> Vec<u8> {
+    let point = hash_to_g2(message).expect("hashing to G2 cannot fail with the fixed tag");
+    compress(&(point * secret_scalar(secret)).into_affine())
+}
+
+/// Sums `signatures` into one, or `None` if any is not a valid signature point.
+pub fn aggregate(signatures: &[Vec<u8>]) -> Option<Vec<u8>> {
+    let mut sum = G2Projective::zero();
+    for signature in signatures {
+        sum += G2Affine::deserialize_compressed(signature.as_slice()).ok()?;
+    }
+    Some(compress(&sum.into_affine()))
+}
+
+/// Whether `signature` aggregates a signature by each of `public_keys` on
+/// the message at the same position in `messages`. A single signature is an
+/// aggregate of one.
+pub fn aggregate_verify(public_keys: &[Vec<u8>], messages: &[Vec<u8>], signature: &[u8]) -> bool {
+    if public_keys.is_empty() || public_keys.len() != messages.len() {
+        return false;
+    }
+    let Ok(signature) = G2Affine::deserialize_compressed(signature) else {
+        return false;
+    };
+    // e(g1, sig) == prod e(pk_i, H(m_i)), checked as one multi-pairing.
+    let mut g1 = vec![-G1Affine::generator()];
+    let mut g2 = vec![signature];
+    for (key, message) in public_keys.iter().zip(messages) {
+        match (G1Affine::deserialize_compressed(key.as_slice()), hash_to_g2(message)) {
+            (Ok(key), Some(point)) if !key.is_zero() => {
+                g1.push(key);
+                g2.push(point);
+            }
+            _ => return false,
+        }
+    }
+    Bls12_381::multi_pairing(g1, g2).is_zero()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_verify_only_with_their_keys_and_messages() {
+        let secrets = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        let keys: Vec<Vec<u8>> = secrets.iter().map(public_key).collect();
+        let messages: Vec<Vec<u8>> = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let signatures: Vec<Vec<u8>> = secrets.iter().zip(&messages).map(|(s, m)| sign(s, m)).collect();
+        assert_eq!((keys[0].len(), signatures[0].len()), (48, 96));
+        assert!(aggregate_verify(&keys[..1], &messages[..1], &signatures[0]));
+
+        let aggregated = aggregate(&signatures).unwrap();
+        assert!(aggregate_verify(&keys, &messages, &aggregated));
+        assert!(!aggregate_verify(&keys[..2], &messages[..2], &aggregated));
+        let swapped = vec![messages[1].clone(), messages[0].clone(), messages[2].clone()];
+        assert!(!aggregate_verify(&keys, &swapped, &aggregated));
+        assert!(!aggregate_verify(&keys, &messages, &signatures[0]));
+        assert!(!aggregate_verify(&[], &[], &aggregated));
+        assert!(!aggregate_verify(&keys, &messages, &[0u8; 96]));
+        assert!(aggregate(&[vec![1u8; 96]]).is_none());
+    }
+}
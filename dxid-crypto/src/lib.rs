@@ -18,6 +18,9 @@ use winterfell::math::{fields::f64::BaseElement, FieldElement, ToElements};
 use winterfell::{ProofOptions, StarkProof, Prover, Trace};
 use std::convert::TryInto;
 
+pub mod bls;
+pub mod poseidon;
+
 #[derive(Debug, Clone)]
 pub struct KeyMaterial {
     pub public_key: Vec<u8>,
@@ -34,6 +37,15 @@ pub fn generate_ed25519() -> KeyMaterial {
     }
 }
 
+/// Rebuilds a key pair from a 32-byte ed25519 secret, e.g. one derived from a seed.
+pub fn ed25519_from_secret(secret: &[u8; 32]) -> KeyMaterial {
+    let signing = SigningKey::from_bytes(secret);
+    KeyMaterial {
+        public_key: signing.verifying_key().to_bytes().to_vec(),
+        secret_key: secret.to_vec(),
+    }
+}
+
 pub struct DefaultCryptoProvider;
 
 impl DefaultCryptoProvider {
@@ -69,8 +81,7 @@ impl CryptoProvider for DefaultCryptoProvider {
     }
 
     fn hash_block_header(&self, header: &BlockHeader) -> BlockHash {
-        let encoded = serde_json::to_vec(header).unwrap();
-        blake3::hash(&encoded).into()
+        header.hash()
     }
 }
 
@@ -118,12 +129,27 @@ pub trait ZkStarkBackend: Send + Sync {
 
 #[async_trait]
 pub trait ZkSnarkBackend: Send + Sync {
-    fn prove_message(&self, msg: &CrossChainMessage) -> std::result::Result<SnarkProof, SnarkError>;
+    /// Proves knowledge of a 32-byte commitment (a message hash or a batch root).
+    fn prove_digest(&self, digest: &[u8; 32]) -> std::result::Result<SnarkProof, SnarkError>;
+    fn verify_digest(&self, proof: &SnarkProof, digest: &[u8; 32]) -> std::result::Result<(), SnarkError>;
+
+    fn prove_message(&self, msg: &CrossChainMessage) -> std::result::Result<SnarkProof, SnarkError> {
+        self.prove_digest(&message_digest(msg))
+    }
+
     fn verify_message(
         &self,
         proof: &SnarkProof,
         msg: &CrossChainMessage,
-    ) -> std::result::Result<(), SnarkError>;
+    ) -> std::result::Result<(), SnarkError> {
+        self.verify_digest(proof, &message_digest(msg))
+    }
+}
+
+/// Commitment to a cross-chain message used as the SNARK statement and as a
+/// batch merkle leaf.
+pub fn message_digest(msg: &CrossChainMessage) -> [u8; 32] {
+    blake3::hash(serde_json::to_string(msg).unwrap().as_bytes()).into()
 }
 
 // --- STARK backend (Winterfell Fibonacci example) ---
@@ -303,9 +329,10 @@ impl<F: PrimeField> ConstraintSynthesizer<F> for SumCircuit<F> {
         let a_var = cs.new_witness_variable(|| Ok(self.a))?;
         let b_var = cs.new_witness_variable(|| Ok(self.b))?;
         let c_var = cs.new_input_variable(|| Ok(self.c))?;
+        // (a + b) * 1 = c
         cs.enforce_constraint(
-            ark_relations::r1cs::LinearCombination::from(a_var),
-            ark_relations::r1cs::LinearCombination::from(b_var),
+            ark_relations::r1cs::LinearCombination::from(a_var) + b_var,
+            ark_relations::r1cs::LinearCombination::from(ark_relations::r1cs::Variable::One),
             ark_relations::r1cs::LinearCombination::from(c_var),
         )?;
         Ok(())
@@ -340,9 +367,8 @@ impl Default for Groth16Backend {
 
 #[async_trait]
 impl ZkSnarkBackend for Groth16Backend {
-    fn prove_message(&self, msg: &CrossChainMessage) -> std::result::Result<SnarkProof, SnarkError> {
-        let hash = blake3::hash(serde_json::to_string(msg).unwrap().as_bytes());
-        let a_val = u64::from_le_bytes(hash.as_bytes()[0..8].try_into().unwrap());
+    fn prove_digest(&self, digest: &[u8; 32]) -> std::result::Result<SnarkProof, SnarkError> {
+        let a_val = u64::from_le_bytes(digest[0..8].try_into().unwrap());
         let a = ark_bls12_381::Fr::from(a_val);
         let b = ark_bls12_381::Fr::from(1u64);
         let c = a + b;
@@ -356,25 +382,17 @@ impl ZkSnarkBackend for Groth16Backend {
             .map_err(|e| SnarkError::Serialization(e.to_string()))?;
         Ok(SnarkProof {
             proof: proof_bytes,
-            public_inputs: vec![a_val as u128, 1, (a_val + 1) as u128],
+            public_inputs: vec![a_val as u128 + 1],
         })
     }
 
-    fn verify_message(
-        &self,
-        proof: &SnarkProof,
-        msg: &CrossChainMessage,
-    ) -> std::result::Result<(), SnarkError> {
-        let hash = blake3::hash(serde_json::to_string(msg).unwrap().as_bytes());
-        let a_val = u64::from_le_bytes(hash.as_bytes()[0..8].try_into().unwrap());
-        let a = ark_bls12_381::Fr::from(a_val);
-        let b = ark_bls12_381::Fr::from(1u64);
-        let c = a + b;
-        let public_inputs = vec![a, b, c];
+    fn verify_digest(&self, proof: &SnarkProof, digest: &[u8; 32]) -> std::result::Result<(), SnarkError> {
+        let a_val = u64::from_le_bytes(digest[0..8].try_into().unwrap());
+        let c = ark_bls12_381::Fr::from(a_val) + ark_bls12_381::Fr::from(1u64);
         let mut cursor = &proof.proof[..];
         let proof: Proof<Bls12_381> = Proof::deserialize_uncompressed(&mut cursor)
             .map_err(|e| SnarkError::Serialization(e.to_string()))?;
-        let ok = Groth16::<Bls12_381, LibsnarkReduction>::verify_proof(&self.vk, &proof, &public_inputs)
+        let ok = Groth16::<Bls12_381, LibsnarkReduction>::verify_proof(&self.vk, &proof, &[c])
             .map_err(|e| SnarkError::Verification(e.to_string()))?;
         if ok {
             Ok(())
@@ -401,7 +419,7 @@ pub fn address_from_string(s: &str) -> Result<Address> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use dxid_core::{BlockHeader, ChainMetadata, CrossChainMessage};
+    use dxid_core::{chain_metadata, CrossChainMessage};
 
     #[test]
     fn sign_and_verify() {
@@ -425,6 +443,8 @@ mod tests {
         };
         let proof = backend.prove_message(&msg).unwrap();
         backend.verify_message(&proof, &msg).unwrap();
+        let other = CrossChainMessage { nonce: 2, ..msg };
+        assert!(backend.verify_message(&proof, &other).is_err());
     }
 
     #[test]
@@ -0,0 +1,36 @@
+//! Poseidon over the BN254 scalar field with the circom parameters, so hashes
+//! match circuits built with circomlib.
+
+use anyhow::{anyhow, Result};
+use ark_bn254::Fr;
+use light_poseidon::{Poseidon, PoseidonBytesHasher};
+
+/// Most inputs a single Poseidon hash takes.
+pub const POSEIDON_MAX_INPUTS: usize = 12;
+
+/// Hashes 1 to [`POSEIDON_MAX_INPUTS`] field elements, each 32 bytes
+/// big-endian and below the field modulus, into one in the same encoding.
+pub fn poseidon_hash(inputs: &[[u8; 32]]) -> Result<[u8; 32]> {
+    let mut hasher = Poseidon::<Fr>::new_circom(inputs.len()).map_err(|e| anyhow!("poseidon: {e}"))?;
+    let inputs: Vec<&[u8]> = inputs.iter().map(|input| input.as_slice()).collect();
+    hasher.hash_bytes_be(&inputs).map_err(|e| anyhow!("poseidon: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(n: u8) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        out[31] = n;
+        out
+    }
+
+    #[test]
+    fn matches_circomlib() {
+        let hash = poseidon_hash(&[element(1), element(2)]).unwrap();
+        assert_eq!(hex::encode(hash), "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a");
+        assert!(poseidon_hash(&[]).is_err());
+        assert!(poseidon_hash(&[[0xff; 32]]).is_err());
+    }
+}
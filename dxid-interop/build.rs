@@ -0,0 +1,5 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tonic_build::configure()
+        .compile(&["proto/bridge.proto"], &["proto"])?;
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use dxid_core::CrossChainMessage;
+use dxid_crypto::{message_digest, SnarkProof, ZkSnarkBackend};
+use serde::{Deserialize, Serialize};
+
+use crate::InteropError;
+
+pub type BatchHash = [u8; 32];
+
+/// One SNARK over the merkle root of a set of outbound messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchProof {
+    pub root: BatchHash,
+    pub size: u32,
+    pub proof: SnarkProof,
+}
+
+/// Merkle branch tying one message to a [`BatchProof`] root.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MessageInclusion {
+    pub index: u32,
+    pub branch: Vec<BatchHash>,
+}
+
+fn hash_node(left: &BatchHash, right: &BatchHash) -> BatchHash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"dxid/batch-node");
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Merkle root over `leaves`, duplicating the last node on odd levels.
+pub fn batch_root(leaves: &[BatchHash]) -> BatchHash {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level.chunks(2).map(|pair| hash_node(&pair[0], pair.get(1).unwrap_or(&pair[0]))).collect();
+    }
+    level[0]
+}
+
+pub fn batch_branch(leaves: &[BatchHash], mut index: usize) -> Vec<BatchHash> {
+    let mut branch = Vec::new();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let sibling = if index % 2 == 0 { index + 1 } else { index - 1 };
+        branch.push(*level.get(sibling).unwrap_or(&level[index]));
+        level = level.chunks(2).map(|pair| hash_node(&pair[0], pair.get(1).unwrap_or(&pair[0]))).collect();
+        index /= 2;
+    }
+    branch
+}
+
+impl MessageInclusion {
+    pub fn compute_root(&self, leaf: &BatchHash) -> BatchHash {
+        let mut acc = *leaf;
+        let mut index = self.index;
+        for sibling in &self.branch {
+            acc = if index % 2 == 0 { hash_node(&acc, sibling) } else { hash_node(sibling, &acc) };
+            index /= 2;
+        }
+        acc
+    }
+}
+
+/// Proves `msgs` with a single SNARK. Inclusions are returned in input order.
+pub fn prove_batch(
+    snark: &dyn ZkSnarkBackend,
+    msgs: &[CrossChainMessage],
+) -> Result<(BatchProof, Vec<MessageInclusion>), InteropError> {
+    if msgs.is_empty() {
+        return Err(InteropError::Other("cannot prove an empty batch".into()));
+    }
+    let leaves: Vec<BatchHash> = msgs.iter().map(message_digest).collect();
+    let root = batch_root(&leaves);
+    let proof = snark.prove_digest(&root).map_err(|e| InteropError::Proof(e.to_string()))?;
+    let inclusions = (0..leaves.len())
+        .map(|i| MessageInclusion { index: i as u32, branch: batch_branch(&leaves, i) })
+        .collect();
+    Ok((BatchProof { root, size: leaves.len() as u32, proof }, inclusions))
+}
+
+/// Verifies one message against a batch proof and its merkle branch.
+pub fn verify_batched(
+    snark: &dyn ZkSnarkBackend,
+    batch: &BatchProof,
+    msg: &CrossChainMessage,
+    inclusion: &MessageInclusion,
+) -> Result<(), InteropError> {
+    if inclusion.index >= batch.size || inclusion.compute_root(&message_digest(msg)) != batch.root {
+        return Err(InteropError::Proof(format!("message {} is not in batch", msg.id)));
+    }
+    snark.verify_digest(&batch.proof, &batch.root).map_err(|e| InteropError::Proof(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dxid_crypto::Groth16Backend;
+    use uuid::Uuid;
+
+    fn message(nonce: u64) -> CrossChainMessage {
+        CrossChainMessage {
+            id: Uuid::new_v4(),
+            source: "dxid".into(),
+            dest: "eth".into(),
+            payload: serde_json::json!({ "n": nonce }),
+            nonce,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn every_message_verifies_against_one_proof() {
+        let snark = Groth16Backend::new().unwrap();
+        let msgs: Vec<_> = (0..5).map(message).collect();
+        let (batch, inclusions) = prove_batch(&snark, &msgs).unwrap();
+        for (msg, inclusion) in msgs.iter().zip(&inclusions) {
+            verify_batched(&snark, &batch, msg, inclusion).unwrap();
+        }
+        assert!(verify_batched(&snark, &batch, &message(9), &inclusions[0]).is_err());
+        assert!(verify_batched(&snark, &batch, &msgs[1], &inclusions[0]).is_err());
+    }
+}
@@ -0,0 +1,882 @@
+use async_trait::async_trait;
+use dxid_config::HttpPolicy;
+use dxid_core::{now_ts, Address, ChainMetadata, CrossChainMessage};
+use dxid_crypto::{address_to_string, SnarkProof};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::headers::{ChainHeader, HeaderEvent, HeaderHash, HeaderTracker};
+use crate::transport::{Endpoint, HttpRpcClient, RpcAuth};
+use crate::{
+    ChainAdapter, ExternalChainConfig, ExternalChainHandle, ExternalStateQuery, ExternalStateResponse,
+    InteropError, TxReceipt,
+};
+
+/// Marker that prefixes the OP_RETURN payload of a bridge deposit.
+pub const BRIDGE_TAG: &[u8] = b"DXID";
+pub const BITCOIN_CHAIN_ID: &str = "bitcoin";
+
+/// Blocks between difficulty retargets.
+const RETARGET_INTERVAL: u64 = 2016;
+/// Testnet lets a block this many seconds after its parent use the easiest target.
+const MIN_DIFFICULTY_GAP: u32 = 20 * 60;
+/// The easiest target on testnet.
+const MIN_DIFFICULTY_BITS: u32 = 0x1d00_ffff;
+
+pub type Hash256 = [u8; 32];
+
+pub fn sha256d(data: &[u8]) -> Hash256 {
+    let first = Sha256::digest(data);
+    Sha256::digest(first).into()
+}
+
+/// Bitcoin hashes are displayed byte-reversed relative to their internal order.
+pub fn hash_to_hex(hash: &Hash256) -> String {
+    let mut rev = *hash;
+    rev.reverse();
+    hex::encode(rev)
+}
+
+pub fn hash_from_hex(s: &str) -> Result<Hash256, InteropError> {
+    let bytes = hex::decode(s).map_err(|e| InteropError::Other(format!("bad hash hex: {e}")))?;
+    let mut out: Hash256 =
+        bytes.try_into().map_err(|_| InteropError::Other("hash must be 32 bytes".into()))?;
+    out.reverse();
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BitcoinHeader {
+    pub version: i32,
+    pub prev_blockhash: Hash256,
+    pub merkle_root: Hash256,
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BitcoinHeader {
+    pub fn parse(bytes: &[u8]) -> Result<Self, InteropError> {
+        if bytes.len() != 80 {
+            return Err(InteropError::Other(format!("header must be 80 bytes, got {}", bytes.len())));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        Ok(Self {
+            version: u32_at(0) as i32,
+            prev_blockhash: bytes[4..36].try_into().unwrap(),
+            merkle_root: bytes[36..68].try_into().unwrap(),
+            time: u32_at(68),
+            bits: u32_at(72),
+            nonce: u32_at(76),
+        })
+    }
+
+    pub fn serialize(&self) -> [u8; 80] {
+        let mut out = [0u8; 80];
+        out[0..4].copy_from_slice(&self.version.to_le_bytes());
+        out[4..36].copy_from_slice(&self.prev_blockhash);
+        out[36..68].copy_from_slice(&self.merkle_root);
+        out[68..72].copy_from_slice(&self.time.to_le_bytes());
+        out[72..76].copy_from_slice(&self.bits.to_le_bytes());
+        out[76..80].copy_from_slice(&self.nonce.to_le_bytes());
+        out
+    }
+
+    pub fn hash(&self) -> Hash256 {
+        sha256d(&self.serialize())
+    }
+
+    /// Expands the compact `bits` field into a big-endian 256-bit target.
+    pub fn target(&self) -> Option<[u8; 32]> {
+        let exponent = (self.bits >> 24) as usize;
+        let mantissa = self.bits & 0x007f_ffff;
+        if self.bits & 0x0080_0000 != 0 || exponent > 32 {
+            return None;
+        }
+        let mut target = [0u8; 32];
+        if exponent <= 3 {
+            let value = mantissa >> (8 * (3 - exponent));
+            target[28..32].copy_from_slice(&value.to_be_bytes());
+        } else {
+            let bytes = mantissa.to_be_bytes();
+            let start = 32 - exponent;
+            for (i, b) in bytes[1..4].iter().enumerate() {
+                if let Some(slot) = target.get_mut(start + i) {
+                    *slot = *b;
+                }
+            }
+        }
+        Some(target)
+    }
+
+    pub fn check_pow(&self) -> bool {
+        let Some(target) = self.target() else {
+            return false;
+        };
+        let mut hash = self.hash();
+        hash.reverse();
+        hash <= target
+    }
+}
+
+/// `target` times four, saturating, or divided by four.
+fn scale_target(target: &[u8; 32], up: bool) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    if up {
+        if target[0] >> 6 != 0 {
+            return [u8::MAX; 32];
+        }
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = target[i] << 2 | target.get(i + 1).map_or(0, |next| next >> 6);
+        }
+    } else {
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = target[i] >> 2 | i.checked_sub(1).map_or(0, |prev| target[prev] << 6);
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MerkleProof {
+    /// Transaction id in internal byte order.
+    pub txid: Hash256,
+    pub index: u32,
+    pub branch: Vec<Hash256>,
+}
+
+impl MerkleProof {
+    pub fn compute_root(&self) -> Hash256 {
+        let mut acc = self.txid;
+        let mut idx = self.index;
+        for sibling in &self.branch {
+            let mut buf = [0u8; 64];
+            if idx & 1 == 0 {
+                buf[..32].copy_from_slice(&acc);
+                buf[32..].copy_from_slice(sibling);
+            } else {
+                buf[..32].copy_from_slice(sibling);
+                buf[32..].copy_from_slice(&acc);
+            }
+            acc = sha256d(&buf);
+            idx >>= 1;
+        }
+        acc
+    }
+
+    pub fn verify(&self, merkle_root: &Hash256) -> bool {
+        &self.compute_root() == merkle_root
+    }
+}
+
+/// Builds the merkle branch for `index` from the full list of block txids,
+/// duplicating the last hash of odd levels like Bitcoin Core does.
+pub fn merkle_branch(txids: &[Hash256], index: usize) -> Vec<Hash256> {
+    let mut branch = Vec::new();
+    let mut level = txids.to_vec();
+    let mut idx = index;
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            level.push(*level.last().unwrap());
+        }
+        branch.push(level[idx ^ 1]);
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut buf = [0u8; 64];
+                buf[..32].copy_from_slice(&pair[0]);
+                buf[32..].copy_from_slice(&pair[1]);
+                sha256d(&buf)
+            })
+            .collect();
+        idx /= 2;
+    }
+    branch
+}
+
+#[derive(Debug, Clone)]
+pub struct BitcoinTxOut {
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BitcoinTx {
+    pub txid: Hash256,
+    pub outputs: Vec<BitcoinTxOut>,
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], InteropError> {
+        let end = self.pos.checked_add(n).filter(|end| *end <= self.data.len());
+        let end = end.ok_or_else(|| InteropError::Other("truncated transaction".into()))?;
+        let out = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, InteropError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, InteropError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> Result<u64, InteropError> {
+        Ok(match self.u8()? {
+            0xfd => u16::from_le_bytes(self.take(2)?.try_into().unwrap()) as u64,
+            0xfe => u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as u64,
+            0xff => u64::from_le_bytes(self.take(8)?.try_into().unwrap()),
+            n => n as u64,
+        })
+    }
+}
+
+/// Parses a raw (optionally segwit) transaction. The txid is computed over the
+/// legacy serialization so it matches what appears in block merkle trees.
+pub fn parse_transaction(raw: &[u8]) -> Result<BitcoinTx, InteropError> {
+    let mut cur = Cursor { data: raw, pos: 0 };
+    cur.take(4)?;
+    let segwit = raw.len() > 6 && raw[4] == 0x00 && raw[5] == 0x01;
+    if segwit {
+        cur.take(2)?;
+    }
+    let io_start = cur.pos;
+    let input_count = cur.varint()?;
+    for _ in 0..input_count {
+        cur.take(36)?;
+        let script_len = cur.varint()? as usize;
+        cur.take(script_len)?;
+        cur.take(4)?;
+    }
+    let output_count = cur.varint()?;
+    let mut outputs = Vec::with_capacity(output_count as usize);
+    for _ in 0..output_count {
+        let value = cur.u64()?;
+        let script_len = cur.varint()? as usize;
+        outputs.push(BitcoinTxOut { value, script_pubkey: cur.take(script_len)?.to_vec() });
+    }
+    let io_end = cur.pos;
+    if segwit {
+        for _ in 0..input_count {
+            let items = cur.varint()?;
+            for _ in 0..items {
+                let len = cur.varint()? as usize;
+                cur.take(len)?;
+            }
+        }
+    }
+    let lock_time = cur.take(4)?;
+    let mut legacy = Vec::with_capacity(raw.len());
+    legacy.extend_from_slice(&raw[0..4]);
+    legacy.extend_from_slice(&raw[io_start..io_end]);
+    legacy.extend_from_slice(lock_time);
+    Ok(BitcoinTx { txid: sha256d(&legacy), outputs })
+}
+
+/// Returns the data pushed by an `OP_RETURN <push>` script, if any.
+pub fn op_return_data(script: &[u8]) -> Option<&[u8]> {
+    let (&op, rest) = script.split_first()?;
+    if op != 0x6a {
+        return None;
+    }
+    let (&push, rest) = rest.split_first()?;
+    let (len, data) = match push {
+        1..=75 => (push as usize, rest),
+        0x4c => {
+            let (&len, data) = rest.split_first()?;
+            (len as usize, data)
+        }
+        _ => return None,
+    };
+    data.get(..len)
+}
+
+/// Bridge commitment carried in OP_RETURN: `DXID || recipient address`.
+pub fn extract_commitment(tx: &BitcoinTx) -> Option<Address> {
+    tx.outputs.iter().find_map(|out| {
+        let data = op_return_data(&out.script_pubkey)?;
+        let recipient = data.strip_prefix(BRIDGE_TAG)?;
+        recipient.try_into().ok()
+    })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BitcoinBackendKind {
+    Bitcoind,
+    Electrum,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BitcoinConfig {
+    pub endpoint: String,
+    pub backend: BitcoinBackendKind,
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+    pub network: String,
+    /// Hex scriptPubKey of the bridge custody output that deposits must pay to.
+    pub deposit_script: String,
+    pub min_confirmations: u64,
+    /// Height of the trusted checkpoint header that sync starts from.
+    pub start_height: u64,
+    /// Hash of the checkpoint header, as explorers display it.
+    pub checkpoint_hash: String,
+    /// Compact target of the checkpoint header.
+    pub checkpoint_bits: u32,
+    /// Number of recent headers kept for reorg detection and SPV checks.
+    #[serde(default = "default_header_window")]
+    pub header_window: usize,
+    #[serde(default)]
+    pub http: HttpPolicy,
+}
+
+fn default_header_window() -> usize {
+    2016
+}
+
+/// Raw access to a Bitcoin data source. Hashes and headers are returned as hex.
+#[async_trait]
+pub trait BitcoinBackend: Send + Sync {
+    async fn tip_height(&self) -> Result<u64, InteropError>;
+    async fn header_hex(&self, height: u64) -> Result<String, InteropError>;
+    async fn raw_transaction_hex(&self, txid: &str) -> Result<String, InteropError>;
+    async fn merkle_proof(&self, txid: &str, height: u64) -> Result<MerkleProof, InteropError>;
+    async fn call(&self, method: &str, params: Value) -> Result<Value, InteropError>;
+}
+
+pub struct BitcoindBackend {
+    rpc: HttpRpcClient,
+}
+
+impl BitcoindBackend {
+    pub fn new(endpoint: String, auth: Option<(String, String)>, policy: HttpPolicy) -> Self {
+        let auth = auth.map_or(RpcAuth::None, |(user, pass)| RpcAuth::Basic(user, pass));
+        Self { rpc: HttpRpcClient::new(endpoint, auth, policy) }
+    }
+}
+
+#[async_trait]
+impl BitcoinBackend for BitcoindBackend {
+    async fn tip_height(&self) -> Result<u64, InteropError> {
+        let v = self.call("getblockcount", serde_json::json!([])).await?;
+        v.as_u64().ok_or_else(|| InteropError::Other("getblockcount: not a number".into()))
+    }
+
+    async fn header_hex(&self, height: u64) -> Result<String, InteropError> {
+        let hash = self.call("getblockhash", serde_json::json!([height])).await?;
+        let header = self.call("getblockheader", serde_json::json!([hash, false])).await?;
+        as_string(header, "getblockheader")
+    }
+
+    async fn raw_transaction_hex(&self, txid: &str) -> Result<String, InteropError> {
+        let raw = self.call("getrawtransaction", serde_json::json!([txid, false])).await?;
+        as_string(raw, "getrawtransaction")
+    }
+
+    async fn merkle_proof(&self, txid: &str, height: u64) -> Result<MerkleProof, InteropError> {
+        let hash = self.call("getblockhash", serde_json::json!([height])).await?;
+        let block = self.call("getblock", serde_json::json!([hash, 1])).await?;
+        let txids = block["tx"]
+            .as_array()
+            .ok_or_else(|| InteropError::Other("getblock: missing tx list".into()))?
+            .iter()
+            .map(|t| hash_from_hex(t.as_str().unwrap_or_default()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let target = hash_from_hex(txid)?;
+        let index = txids
+            .iter()
+            .position(|t| *t == target)
+            .ok_or_else(|| InteropError::Proof(format!("tx {txid} not in block {height}")))?;
+        Ok(MerkleProof { txid: target, index: index as u32, branch: merkle_branch(&txids, index) })
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, InteropError> {
+        let body = self
+            .rpc
+            .post_json(&serde_json::json!({
+                "jsonrpc": "1.0",
+                "id": "dxid",
+                "method": method,
+                "params": params
+            }))
+            .await?;
+        rpc_result(body, method)
+    }
+}
+
+/// Electrum protocol client: newline-delimited JSON-RPC over TCP.
+pub struct ElectrumBackend {
+    address: String,
+    endpoint: Endpoint,
+}
+
+impl ElectrumBackend {
+    pub fn new(address: String, policy: HttpPolicy) -> Self {
+        Self { endpoint: Endpoint::new(address.clone(), policy), address }
+    }
+
+    async fn call_once(&self, request: &[u8]) -> Result<Value, InteropError> {
+        let stream =
+            TcpStream::connect(&self.address).await.map_err(|e| InteropError::Transport(e.to_string()))?;
+        let (read, mut write) = stream.into_split();
+        write.write_all(request).await.map_err(|e| InteropError::Transport(e.to_string()))?;
+        let mut reply = String::new();
+        BufReader::new(read)
+            .read_line(&mut reply)
+            .await
+            .map_err(|e| InteropError::Transport(e.to_string()))?;
+        serde_json::from_str(&reply).map_err(|e| InteropError::Decode(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl BitcoinBackend for ElectrumBackend {
+    async fn tip_height(&self) -> Result<u64, InteropError> {
+        let v = self.call("blockchain.headers.subscribe", serde_json::json!([])).await?;
+        v["height"].as_u64().ok_or_else(|| InteropError::Other("headers.subscribe: missing height".into()))
+    }
+
+    async fn header_hex(&self, height: u64) -> Result<String, InteropError> {
+        let v = self.call("blockchain.block.header", serde_json::json!([height])).await?;
+        as_string(v, "blockchain.block.header")
+    }
+
+    async fn raw_transaction_hex(&self, txid: &str) -> Result<String, InteropError> {
+        let v = self.call("blockchain.transaction.get", serde_json::json!([txid])).await?;
+        as_string(v, "blockchain.transaction.get")
+    }
+
+    async fn merkle_proof(&self, txid: &str, height: u64) -> Result<MerkleProof, InteropError> {
+        let v = self.call("blockchain.transaction.get_merkle", serde_json::json!([txid, height])).await?;
+        let branch = v["merkle"]
+            .as_array()
+            .ok_or_else(|| InteropError::Other("get_merkle: missing branch".into()))?
+            .iter()
+            .map(|h| hash_from_hex(h.as_str().unwrap_or_default()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let index = v["pos"].as_u64().ok_or_else(|| InteropError::Other("get_merkle: missing pos".into()))?;
+        Ok(MerkleProof { txid: hash_from_hex(txid)?, index: index as u32, branch })
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, InteropError> {
+        let mut line = serde_json::to_vec(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": method,
+            "params": params
+        }))
+        .map_err(|e| InteropError::Other(e.to_string()))?;
+        line.push(b'\n');
+        let body = self.endpoint.call(|| self.call_once(&line)).await?;
+        rpc_result(body, method)
+    }
+}
+
+fn rpc_result(body: Value, method: &str) -> Result<Value, InteropError> {
+    if !body["error"].is_null() {
+        return Err(InteropError::Other(format!("{method}: {}", body["error"])));
+    }
+    Ok(body["result"].clone())
+}
+
+fn as_string(v: Value, method: &str) -> Result<String, InteropError> {
+    v.as_str()
+        .map(str::to_string)
+        .ok_or_else(|| InteropError::Other(format!("{method}: expected hex string")))
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, InteropError> {
+    hex::decode(s).map_err(|e| InteropError::Other(format!("bad hex: {e}")))
+}
+
+/// A verified header together with its height, as tracked by the light client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitcoinBlock {
+    pub height: u64,
+    pub header: BitcoinHeader,
+}
+
+impl ChainHeader for BitcoinBlock {
+    fn height(&self) -> u64 {
+        self.height
+    }
+
+    fn hash(&self) -> HeaderHash {
+        self.header.hash()
+    }
+
+    fn parent_hash(&self) -> HeaderHash {
+        self.header.prev_blockhash
+    }
+}
+
+pub struct BitcoinSpvAdapter {
+    config: BitcoinConfig,
+    backend: Box<dyn BitcoinBackend>,
+    deposit_script: Vec<u8>,
+    checkpoint: Hash256,
+    headers: RwLock<HeaderTracker<BitcoinBlock>>,
+}
+
+impl BitcoinSpvAdapter {
+    pub fn new(config: BitcoinConfig) -> Result<Self, InteropError> {
+        let backend: Box<dyn BitcoinBackend> = match config.backend {
+            BitcoinBackendKind::Bitcoind => {
+                let auth = config.rpc_user.clone().zip(config.rpc_password.clone());
+                Box::new(BitcoindBackend::new(config.endpoint.clone(), auth, config.http.clone()))
+            }
+            BitcoinBackendKind::Electrum => {
+                Box::new(ElectrumBackend::new(config.endpoint.clone(), config.http.clone()))
+            }
+        };
+        Self::with_backend(config, backend)
+    }
+
+    pub fn with_backend(
+        config: BitcoinConfig,
+        backend: Box<dyn BitcoinBackend>,
+    ) -> Result<Self, InteropError> {
+        let deposit_script = decode_hex(&config.deposit_script)?;
+        let checkpoint = hash_from_hex(&config.checkpoint_hash)?;
+        let headers = HeaderTracker::new(config.header_window, config.min_confirmations);
+        Ok(Self { config, backend, deposit_script, checkpoint, headers: RwLock::new(headers) })
+    }
+
+    async fn fetch_block(&self, height: u64) -> Result<BitcoinBlock, InteropError> {
+        let raw = decode_hex(&self.backend.header_hex(height).await?)?;
+        let header = BitcoinHeader::parse(&raw)?;
+        if !header.check_pow() {
+            return Err(InteropError::Proof(format!("header {height} does not meet its target")));
+        }
+        Ok(BitcoinBlock { height, header })
+    }
+
+    /// Checks the target `block` declares. The header at `start_height` must
+    /// be the checkpoint; later ones keep their period's target except at a
+    /// retarget, which moves it by at most a factor of four, so a forged chain
+    /// needs as much work as the real one. The exact retarget, which needs the
+    /// period's first header, is not recomputed.
+    fn check_difficulty(
+        &self,
+        tracker: &HeaderTracker<BitcoinBlock>,
+        block: &BitcoinBlock,
+    ) -> Result<(), InteropError> {
+        let (height, header) = (block.height, &block.header);
+        if height == self.config.start_height {
+            if header.hash() != self.checkpoint || header.bits != self.config.checkpoint_bits {
+                return Err(InteropError::Proof(format!("header {height} is not the checkpoint")));
+            }
+            return Ok(());
+        }
+        // A header without a tracked parent does not link, and is not ingested.
+        let Some(parent) = height.checked_sub(1).and_then(|h| tracker.get(h)) else {
+            return Ok(());
+        };
+        let testnet = matches!(self.config.network.as_str(), "testnet" | "testnet3" | "testnet4");
+        if height % RETARGET_INTERVAL == 0 {
+            let (Some(target), Some(previous)) = (header.target(), parent.header.target()) else {
+                return Err(InteropError::Proof(format!("header {height} has an invalid target")));
+            };
+            if target > scale_target(&previous, true) || target < scale_target(&previous, false) {
+                return Err(InteropError::Proof(format!(
+                    "header {height} retargets by more than a factor of four"
+                )));
+            }
+            return Ok(());
+        }
+        if testnet
+            && header.bits == MIN_DIFFICULTY_BITS
+            && header.time > parent.header.time.saturating_add(MIN_DIFFICULTY_GAP)
+        {
+            return Ok(());
+        }
+        // On testnet the period's target is that of the last block not mined
+        // at the easiest one.
+        let mut reference = parent;
+        while testnet
+            && reference.header.bits == MIN_DIFFICULTY_BITS
+            && reference.height % RETARGET_INTERVAL != 0
+        {
+            match reference.height.checked_sub(1).and_then(|h| tracker.get(h)) {
+                Some(previous) => reference = previous,
+                None => break,
+            }
+        }
+        if header.bits != reference.header.bits {
+            return Err(InteropError::Proof(format!(
+                "header {height} changes the target outside a retarget"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Pulls headers up to the remote tip, walking back to the fork point when
+    /// the remote chain has reorganized. Returns the new tip height.
+    pub async fn sync_headers(&self) -> Result<u64, InteropError> {
+        let remote_tip = self.backend.tip_height().await?;
+        let mut tracker = self.headers.write().await;
+        let mut next = tracker.tip().map(|t| t.height + 1).unwrap_or(self.config.start_height);
+        while next <= remote_tip {
+            let block = self.fetch_block(next).await?;
+            self.check_difficulty(&tracker, &block)?;
+            match tracker.ingest(block) {
+                HeaderEvent::Extended | HeaderEvent::Duplicate => next += 1,
+                HeaderEvent::Reorg { fork_height, dropped } => {
+                    info!("bitcoin reorg at {fork_height}, {} headers replaced", dropped.len());
+                    next += 1;
+                }
+                HeaderEvent::Unlinked => {
+                    let base = tracker.base_height().unwrap_or(self.config.start_height);
+                    if next <= base {
+                        return Err(InteropError::Proof(format!(
+                            "bitcoin reorg deeper than the {}-header window",
+                            self.config.header_window
+                        )));
+                    }
+                    next -= 1;
+                }
+            }
+        }
+        let tip = tracker.tip().map(|t| t.height).unwrap_or(self.config.start_height);
+        debug!("bitcoin headers synced to {tip}");
+        Ok(tip)
+    }
+
+    /// Verifies a deposit by SPV proof against the synced headers and turns it
+    /// into a message addressed to dxid. The containing block must be on the
+    /// canonical chain with at least `min_confirmations`.
+    pub async fn verify_deposit(&self, txid: &str, height: u64) -> Result<CrossChainMessage, InteropError> {
+        let header = {
+            let tracker = self.headers.read().await;
+            let block = tracker
+                .get(height)
+                .ok_or_else(|| InteropError::Proof(format!("no tracked header at height {height}")))?;
+            let confirmations = tracker.confirmations(height, &block.hash()).unwrap_or(0);
+            if !tracker.is_final(height, &block.hash()) {
+                return Err(InteropError::Proof(format!(
+                    "deposit has {confirmations} confirmations, need {}",
+                    self.config.min_confirmations
+                )));
+            }
+            block.header.clone()
+        };
+        let raw = decode_hex(&self.backend.raw_transaction_hex(txid).await?)?;
+        let tx = parse_transaction(&raw)?;
+        let proof = self.backend.merkle_proof(txid, height).await?;
+        if proof.txid != tx.txid || !proof.verify(&header.merkle_root) {
+            return Err(InteropError::Proof("merkle proof does not match header".into()));
+        }
+        let message = deposit_message(&tx, &self.deposit_script, height, proof.index, header.time)?;
+        info!("verified bitcoin deposit {txid} at height {height}");
+        Ok(message)
+    }
+}
+
+/// Builds the inbound message for a verified deposit transaction.
+pub fn deposit_message(
+    tx: &BitcoinTx,
+    deposit_script: &[u8],
+    height: u64,
+    index: u32,
+    block_time: u32,
+) -> Result<CrossChainMessage, InteropError> {
+    let recipient = extract_commitment(tx)
+        .ok_or_else(|| InteropError::Proof("transaction carries no bridge commitment".into()))?;
+    let amount: u64 = tx.outputs.iter().filter(|o| o.script_pubkey == deposit_script).map(|o| o.value).sum();
+    if amount == 0 {
+        return Err(InteropError::Proof("transaction pays nothing to the deposit script".into()));
+    }
+    Ok(CrossChainMessage {
+        id: Uuid::from_bytes(tx.txid[..16].try_into().unwrap()),
+        source: BITCOIN_CHAIN_ID.into(),
+        dest: "dxid".into(),
+        payload: serde_json::json!({
+            "type": "deposit",
+            "txid": hash_to_hex(&tx.txid),
+            "block_height": height,
+            "amount_sats": amount,
+            "recipient": address_to_string(&recipient),
+        }),
+        nonce: (height << 32) | index as u64,
+        timestamp: block_time as u64,
+    })
+}
+
+#[async_trait]
+impl ChainAdapter for BitcoinSpvAdapter {
+    async fn connect(&self, config: &ExternalChainConfig) -> Result<ExternalChainHandle, InteropError> {
+        let tip = self.sync_headers().await?;
+        Ok(ExternalChainHandle {
+            id: Uuid::new_v4(),
+            metadata: ChainMetadata {
+                chain_id: config.name.clone(),
+                rpc_endpoint: self.config.endpoint.clone(),
+                latest_height: tip,
+                network: self.config.network.clone(),
+                extra: serde_json::json!({ "synced_at": now_ts() }),
+            },
+        })
+    }
+
+    async fn send_message(
+        &self,
+        _proof: &SnarkProof,
+        _msg: &CrossChainMessage,
+    ) -> Result<TxReceipt, InteropError> {
+        Err(InteropError::Other("bitcoin adapter only ingests deposits".into()))
+    }
+
+    async fn query_state(&self, query: &ExternalStateQuery) -> Result<ExternalStateResponse, InteropError> {
+        let result = self.backend.call(&query.method, query.params.clone()).await?;
+        Ok(ExternalStateResponse { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+
+    fn raw_tx(outputs: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut raw = vec![1, 0, 0, 0, 1];
+        raw.extend_from_slice(&[7u8; 36]);
+        raw.extend_from_slice(&[0, 0xff, 0xff, 0xff, 0xff]);
+        raw.push(outputs.len() as u8);
+        for (value, script) in outputs {
+            raw.extend_from_slice(&value.to_le_bytes());
+            raw.push(script.len() as u8);
+            raw.extend_from_slice(script);
+        }
+        raw.extend_from_slice(&[0, 0, 0, 0]);
+        raw
+    }
+
+    #[test]
+    fn genesis_header_pow() {
+        let header = BitcoinHeader::parse(&hex::decode(GENESIS_HEADER).unwrap()).unwrap();
+        assert_eq!(
+            hash_to_hex(&header.hash()),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+        assert!(header.check_pow());
+    }
+
+    #[test]
+    fn deposit_spv_flow() {
+        let deposit_script = vec![0x51];
+        let mut commitment = vec![0x6a, 36];
+        commitment.extend_from_slice(BRIDGE_TAG);
+        commitment.extend_from_slice(&[3u8; 32]);
+        let tx = parse_transaction(&raw_tx(&[(50_000, deposit_script.clone()), (0, commitment)])).unwrap();
+        assert_eq!(extract_commitment(&tx), Some([3u8; 32]));
+
+        let txids = vec![[1u8; 32], tx.txid, [2u8; 32]];
+        let proof = MerkleProof { txid: tx.txid, index: 1, branch: merkle_branch(&txids, 1) };
+        let root = MerkleProof { txid: txids[0], index: 0, branch: merkle_branch(&txids, 0) }.compute_root();
+        assert!(proof.verify(&root));
+
+        let msg = deposit_message(&tx, &deposit_script, 100, 1, 0).unwrap();
+        assert_eq!(msg.payload["amount_sats"], 50_000);
+        assert!(deposit_message(&tx, &[0x52], 100, 1, 0).is_err());
+    }
+
+    /// Serves a fixed header chain starting at height 0.
+    struct HeaderChain(Vec<BitcoinHeader>);
+
+    #[async_trait]
+    impl BitcoinBackend for HeaderChain {
+        async fn tip_height(&self) -> Result<u64, InteropError> {
+            Ok(self.0.len() as u64 - 1)
+        }
+
+        async fn header_hex(&self, height: u64) -> Result<String, InteropError> {
+            Ok(hex::encode(self.0[height as usize].serialize()))
+        }
+
+        async fn raw_transaction_hex(&self, _txid: &str) -> Result<String, InteropError> {
+            Err(InteropError::Other("no transactions".into()))
+        }
+
+        async fn merkle_proof(&self, _txid: &str, _height: u64) -> Result<MerkleProof, InteropError> {
+            Err(InteropError::Other("no transactions".into()))
+        }
+
+        async fn call(&self, _method: &str, _params: Value) -> Result<Value, InteropError> {
+            Err(InteropError::Other("no calls".into()))
+        }
+    }
+
+    /// Mines headers at `bits` on top of `prev`, ten minutes apart.
+    fn mine(prev: Option<&BitcoinHeader>, bits: &[u32]) -> Vec<BitcoinHeader> {
+        let mut chain: Vec<BitcoinHeader> = prev.into_iter().cloned().collect();
+        for &bits in bits {
+            let parent = chain.last();
+            let mut header = BitcoinHeader {
+                version: 4,
+                prev_blockhash: parent.map_or([0; 32], BitcoinHeader::hash),
+                merkle_root: [0; 32],
+                time: parent.map_or(1_700_000_000, |p| p.time + 600),
+                bits,
+                nonce: 0,
+            };
+            while !header.check_pow() {
+                header.nonce += 1;
+            }
+            chain.push(header);
+        }
+        chain.split_off(prev.is_some() as usize)
+    }
+
+    fn spv(chain: Vec<BitcoinHeader>, checkpoint: &BitcoinHeader) -> BitcoinSpvAdapter {
+        let config = BitcoinConfig {
+            endpoint: "127.0.0.1:50001".into(),
+            backend: BitcoinBackendKind::Electrum,
+            rpc_user: None,
+            rpc_password: None,
+            network: "regtest".into(),
+            deposit_script: "51".into(),
+            min_confirmations: 1,
+            start_height: 0,
+            checkpoint_hash: hash_to_hex(&checkpoint.hash()),
+            checkpoint_bits: checkpoint.bits,
+            header_window: 16,
+            http: HttpPolicy::default(),
+        };
+        BitcoinSpvAdapter::with_backend(config, Box::new(HeaderChain(chain))).unwrap()
+    }
+
+    #[tokio::test]
+    async fn headers_link_to_the_checkpoint_and_keep_its_target() {
+        const EASY: u32 = 0x207f_ffff;
+        let chain = mine(None, &[EASY; 4]);
+        assert_eq!(spv(chain.clone(), &chain[0]).sync_headers().await.unwrap(), 3);
+
+        // The same target, but not from the checkpoint.
+        let forged = mine(Some(&chain[0]), &[EASY; 4]);
+        assert!(spv(forged, &chain[0]).sync_headers().await.is_err());
+
+        // An easier target outside a retarget.
+        let mut eased = mine(None, &[0x1f7f_ffff; 2]);
+        eased.extend(mine(eased.last(), &[EASY]));
+        let err = spv(eased.clone(), &eased[0]).sync_headers().await.unwrap_err();
+        assert!(err.to_string().contains("outside a retarget"), "{err}");
+
+        let target = BitcoinHeader { bits: 0x1f7f_ffff, ..chain[0].clone() }.target().unwrap();
+        assert_eq!(scale_target(&scale_target(&target, true), false), target);
+        assert!(scale_target(&target, false) < target && target < scale_target(&target, true));
+    }
+}
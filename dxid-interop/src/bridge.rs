@@ -0,0 +1,337 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use dxid_core::bridge::{
+    release_transaction, BridgeLedger, BridgeRelease, BridgeTransfer, InboundVerifier, ProvenMessage,
+};
+use dxid_core::{ChainState, CrossChainMessage, CrossChainTx, Transaction};
+use dxid_crypto::{address_from_string, SnarkProof};
+use tracing::{info, warn};
+
+use crate::fees::payload_size;
+use crate::inbound::validate;
+use crate::registry::AdapterRegistry;
+use crate::relayer::Relayer;
+use crate::InteropError;
+
+pub use dxid_core::bridge::DXID_CHAIN_ID;
+
+/// Builds the mint transaction for a native lock; see [`BridgeTransfer::mint_message`].
+pub fn mint_message(transfer: &BridgeTransfer) -> CrossChainTx {
+    CrossChainTx { message: transfer.mint_message(), fee: 0, proof: None }
+}
+
+/// Lock-and-mint / burn-and-release on top of the relayer.
+pub struct AssetBridge {
+    registry: Arc<AdapterRegistry>,
+    relayer: Arc<Relayer>,
+}
+
+impl AssetBridge {
+    pub fn new(registry: Arc<AdapterRegistry>, relayer: Arc<Relayer>) -> Self {
+        Self { registry, relayer }
+    }
+
+    /// Queues a mint for every lock in the ledger outbox made by a block at
+    /// or below `final_height`. Later locks wait, since a reorg may still
+    /// drop them. Locks that could not be queued are put back for the next call.
+    pub async fn relay_locks(
+        &self,
+        ledger: &mut BridgeLedger,
+        final_height: u64,
+    ) -> Result<usize, InteropError> {
+        let mut pending = ledger.take_final(final_height).into_iter();
+        let mut queued = 0;
+        while let Some((height, transfer)) = pending.next() {
+            let mut mint = mint_message(&transfer);
+            if self.registry.get(&transfer.dest).is_none() {
+                warn!("lock {} targets unconfigured chain {}", transfer.id, transfer.dest);
+            } else {
+                // Mints are relayed on the bridge's account at the current quote.
+                let quote = self.relayer.quote_fee(&transfer.dest, payload_size(&mint.message)).await;
+                match quote {
+                    Ok(quote) => mint.fee = quote.total,
+                    Err(e) => warn!("could not quote mint for lock {}: {e}", transfer.id),
+                }
+            }
+            if let Err(e) = self.relayer.enqueue_from(mint, &transfer.sender).await {
+                let unsent: Vec<_> = std::iter::once((height, transfer)).chain(pending).collect();
+                ledger.outbox.splice(..0, unsent);
+                return Err(e);
+            }
+            queued += 1;
+        }
+        Ok(queued)
+    }
+
+    /// Checks that `msg` is a burn addressed to dxid and proven by the source
+    /// chain's configured backend.
+    pub fn verify_burn(
+        &self,
+        msg: &CrossChainMessage,
+        proof: &SnarkProof,
+    ) -> Result<BridgeRelease, InteropError> {
+        if msg.dest != DXID_CHAIN_ID {
+            return Err(InteropError::Other(format!("burn addressed to {}, not {DXID_CHAIN_ID}", msg.dest)));
+        }
+        if msg.payload["type"] != "burn" {
+            return Err(InteropError::Other(format!("message {} is not a burn", msg.id)));
+        }
+        let entry = self
+            .registry
+            .get(&msg.source)
+            .ok_or_else(|| InteropError::Other(format!("no adapter for chain {}", msg.source)))?;
+        entry.snark.verify_message(proof, msg).map_err(|e| InteropError::Proof(e.to_string()))?;
+        burn_release(msg)
+    }
+
+    /// Verifies a burn and builds the transaction releasing its tokens from
+    /// the escrow outputs of `state`. The release takes effect once the
+    /// transaction is in a block, where every node checks the burn again.
+    pub fn release_transaction(
+        &self,
+        state: &ChainState,
+        msg: &CrossChainMessage,
+        proof: &SnarkProof,
+    ) -> Result<Transaction, InteropError> {
+        let release = self.verify_burn(msg, proof)?;
+        let proof = serde_json::to_value(proof).map_err(|e| InteropError::Other(e.to_string()))?;
+        let proven = ProvenMessage { message: msg.clone(), proof };
+        let tx =
+            release_transaction(state, &proven, &release).map_err(|e| InteropError::Other(e.to_string()))?;
+        info!("releasing {} from {} escrow for burn {}", release.amount, release.source, release.id);
+        Ok(tx)
+    }
+}
+
+/// The release a burn's payload asks for.
+fn burn_release(msg: &CrossChainMessage) -> Result<BridgeRelease, InteropError> {
+    let recipient = msg.payload["recipient"]
+        .as_str()
+        .ok_or_else(|| InteropError::Other("burn without recipient".into()))
+        .and_then(|s| address_from_string(s).map_err(|e| InteropError::Other(e.to_string())))?;
+    let amount = msg.payload["amount"]
+        .as_u64()
+        .filter(|a| *a > 0)
+        .ok_or_else(|| InteropError::Other("burn without amount".into()))?;
+    Ok(BridgeRelease { id: msg.id, source: msg.source.clone(), nonce: msg.nonce, recipient, amount })
+}
+
+/// Checks bridge transactions for the execution engine with the proof
+/// backend of each message's source chain, so every node configured for
+/// that chain accepts the same ones.
+impl InboundVerifier for AdapterRegistry {
+    fn verify_inbound(
+        &self,
+        msg: &CrossChainMessage,
+        proof: &serde_json::Value,
+    ) -> anyhow::Result<Option<BridgeRelease>> {
+        let entry = self.get(&msg.source).ok_or_else(|| anyhow!("no adapter for chain {}", msg.source))?;
+        let proof: SnarkProof = serde_json::from_value(proof.clone())?;
+        validate(&entry, msg, &proof)?;
+        if msg.payload["type"] != "burn" {
+            return Ok(None);
+        }
+        Ok(Some(burn_release(msg)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relayer::RelayerConfig;
+    use crate::test_support::{registry_with, FlakyAdapter};
+    use dxid_core::bridge::{bridge_escrow_address, lock_id};
+    use dxid_core::fork::ChainTree;
+    use dxid_core::state_tree::state_root;
+    use dxid_core::{
+        Address, BlockHash, BlockHeader, CryptoProvider, ExecutionEngine, HalvingSchedule, TokenEconomics,
+        TxInput, TxOutput,
+    };
+    use dxid_crypto::{address_to_string, DefaultCryptoProvider};
+    use dxid_storage::{BridgeStore, MemoryBridgeStore};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn lock_mint_burn_release() {
+        let registry = registry_with("eth", Arc::new(FlakyAdapter::new(0)));
+        let store = Arc::new(MemoryBridgeStore::new());
+        let relayer = Arc::new(Relayer::new(store.clone(), registry.clone(), RelayerConfig::default()));
+        let bridge = AssetBridge::new(registry.clone(), relayer);
+
+        let escrow = bridge_escrow_address();
+        let mut state = ChainState::default();
+        let locked = TxOutput { address: escrow, amount: 500, condition: None, spendable_after_height: None };
+        state.pending_utxos.insert([7u8; 32], vec![locked]);
+        state.balances.insert(escrow, 500);
+        let lock = BridgeTransfer {
+            id: lock_id(&[7u8; 32], 0),
+            sender: [4u8; 32],
+            dest: "eth".into(),
+            recipient: "0xabc".into(),
+            amount: 500,
+            lock_tx: [7u8; 32],
+        };
+        state.bridge.lock(lock.clone(), 0).unwrap();
+        assert_eq!(bridge.relay_locks(&mut state.bridge, 0).await.unwrap(), 1);
+        let queued = store.get_message(&lock.id).await.unwrap().unwrap();
+        assert_eq!(queued.tx.message.payload["amount"], 500);
+        assert_eq!(queued.tx.message.nonce, 1);
+
+        let burn = CrossChainMessage {
+            id: Uuid::new_v4(),
+            source: "eth".into(),
+            dest: DXID_CHAIN_ID.into(),
+            payload: serde_json::json!({
+                "type": "burn",
+                "recipient": address_to_string(&[3u8; 32]),
+                "amount": 200,
+            }),
+            nonce: 1,
+            timestamp: 0,
+        };
+        let proof = SnarkProof { proof: vec![], public_inputs: vec![] };
+        let tx = bridge.release_transaction(&state, &burn, &proof).unwrap();
+
+        let crypto = DefaultCryptoProvider::new();
+        let economics = TokenEconomics {
+            max_supply: 1_000,
+            base_reward: 0,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let engine = ExecutionEngine::new(&crypto, economics).with_inbound(registry.as_ref());
+        let header = BlockHeader {
+            previous_hash: [0; 32],
+            merkle_root: [0; 32],
+            height: 0,
+            timestamp: 0,
+            difficulty: 0,
+            nonce: 0,
+            validator: [9; 32],
+            stake_weight: 0,
+            state_root: state_root(&state),
+        };
+        let block = engine.complete_block(&state, header.clone(), vec![tx]).unwrap();
+        engine.execute_block(&mut state, &block).unwrap();
+        assert_eq!(state.bridge.outstanding("eth"), 300);
+        assert_eq!((state.balances[&[3u8; 32]], state.balances[&escrow]), (200, 300));
+
+        // The same burn again is refused by the engine.
+        let again = bridge.release_transaction(&state, &burn, &proof).unwrap();
+        let next = BlockHeader { height: 1, state_root: state_root(&state), ..header };
+        assert!(engine.complete_block(&state, next, vec![again]).is_err());
+
+        let foreign = CrossChainMessage { id: Uuid::new_v4(), source: "sol".into(), ..burn };
+        assert!(bridge.verify_burn(&foreign, &proof).is_err());
+        let proof = serde_json::to_value(&proof).unwrap();
+        assert!(registry.verify_inbound(&foreign, &proof).is_err());
+    }
+
+    /// Accepts any signature, so blocks can be built without keys.
+    struct AnyKey;
+
+    impl CryptoProvider for AnyKey {
+        fn address_from_public_key(&self, pk: &[u8]) -> anyhow::Result<Address> {
+            Ok(*blake3::hash(pk).as_bytes())
+        }
+
+        fn verify_signature(&self, _pk: &[u8], _msg: &[u8], _sig: &[u8]) -> anyhow::Result<bool> {
+            Ok(true)
+        }
+
+        fn sign_message(&self, _sk: &[u8], msg: &[u8]) -> anyhow::Result<Vec<u8>> {
+            Ok(msg.to_vec())
+        }
+
+        fn hash_block_header(&self, header: &BlockHeader) -> BlockHash {
+            header.hash()
+        }
+    }
+
+    #[tokio::test]
+    async fn locks_wait_until_final_and_a_fork_drops_them_unminted() {
+        let registry = registry_with("eth", Arc::new(FlakyAdapter::new(0)));
+        let store = Arc::new(MemoryBridgeStore::new());
+        let relayer = Arc::new(Relayer::new(store.clone(), registry.clone(), RelayerConfig::default()));
+        let bridge = AssetBridge::new(registry, relayer);
+
+        let crypto = AnyKey;
+        let economics = TokenEconomics {
+            max_supply: 1_000,
+            base_reward: 0,
+            schedule: HalvingSchedule { target_interval: 0, supply_threshold: 0 },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        };
+        let engine = ExecutionEngine::new(&crypto, economics);
+        let owner = crypto.address_from_public_key(b"owner").unwrap();
+        let mut state = ChainState::default();
+        let coin = TxOutput { address: owner, amount: 500, condition: None, spendable_after_height: None };
+        state.pending_utxos.insert([1u8; 32], vec![coin.clone()]);
+        state.balances.insert(owner, 500);
+        // The block on `parent`, whose chain leaves `state`, and the state after it.
+        let child = |parent: &ChainState, transactions, difficulty| {
+            let header = BlockHeader {
+                previous_hash: parent.tip_hash.unwrap_or([0; 32]),
+                merkle_root: [0; 32],
+                height: parent.next_height(),
+                timestamp: 0,
+                difficulty,
+                nonce: 0,
+                validator: [9; 32],
+                stake_weight: 0,
+                state_root: state_root(parent),
+            };
+            let block = engine.complete_block(parent, header, transactions).unwrap();
+            let mut after = parent.clone();
+            engine.apply_block(&mut after, &block).unwrap();
+            (block, after)
+        };
+        let mut tree = ChainTree::new().with_max_depth(2);
+        let (genesis, on_genesis) = child(&state, vec![], 1);
+        let genesis = tree.insert(&crypto, genesis).unwrap();
+        engine.reorg_to(&mut state, &mut tree, &genesis).unwrap();
+
+        let lock = Transaction {
+            inputs: vec![TxInput {
+                previous_tx: [1u8; 32],
+                output_index: 0,
+                signature: vec![],
+                public_key: b"owner".to_vec(),
+                witness: vec![],
+            }],
+            outputs: vec![TxOutput { address: bridge_escrow_address(), ..coin }],
+            fee: 0,
+            nonce: 0,
+            memo: Some("bridge:eth:0xabc".into()),
+            lock_time: 0,
+        };
+        let locked = lock_id(&lock.hash(), 0);
+        let (a1, _) = child(&on_genesis, vec![lock], 1);
+        let a1 = tree.insert(&crypto, a1).unwrap();
+        engine.reorg_to(&mut state, &mut tree, &a1).unwrap();
+        assert_eq!(state.bridge.outbox.len(), 1);
+        // The lock's block can still be reverted, so its mint waits.
+        let final_height = tree.final_height().unwrap();
+        assert_eq!(bridge.relay_locks(&mut state.bridge, final_height).await.unwrap(), 0);
+        assert_eq!(state.bridge.outbox.len(), 1);
+
+        // A heavier branch without the lock drops it, also once the fork is final.
+        let (b1, mut on_b) = child(&on_genesis, vec![], 5);
+        let mut tip = tree.insert(&crypto, b1).unwrap();
+        for _ in 0..3 {
+            let (block, after) = child(&on_b, vec![], 1);
+            tip = tree.insert(&crypto, block).unwrap();
+            on_b = after;
+        }
+        let reorg = engine.reorg_to(&mut state, &mut tree, &tip).unwrap();
+        assert_eq!(reorg.reverted.len(), 1);
+        assert!(tree.final_height().unwrap() >= 1 && !tree.contains(&a1));
+        let final_height = tree.final_height().unwrap();
+        assert_eq!(bridge.relay_locks(&mut state.bridge, final_height).await.unwrap(), 0);
+        assert!(state.bridge.outbox.is_empty());
+        assert!(store.get_message(&locked).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,44 @@
+use dxid_core::{ChainId, CrossChainMessage};
+use serde::{Deserialize, Serialize};
+
+/// What dxid charges for relaying, on top of destination gas.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BridgeFees {
+    pub base: u64,
+    pub per_byte: u64,
+}
+
+impl Default for BridgeFees {
+    fn default() -> Self {
+        Self { base: 100, per_byte: 1 }
+    }
+}
+
+impl BridgeFees {
+    pub fn charge(&self, payload_size: usize) -> u64 {
+        self.base.saturating_add(self.per_byte.saturating_mul(payload_size as u64))
+    }
+}
+
+/// Fee needed to relay a message, in dxid base units.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeeQuote {
+    pub dest: ChainId,
+    pub payload_size: usize,
+    /// Destination execution cost as estimated by its adapter.
+    pub gas: u64,
+    pub bridge_fee: u64,
+    pub total: u64,
+}
+
+impl FeeQuote {
+    pub fn new(dest: ChainId, payload_size: usize, gas: u64, fees: &BridgeFees) -> Self {
+        let bridge_fee = fees.charge(payload_size);
+        Self { dest, payload_size, gas, bridge_fee, total: gas.saturating_add(bridge_fee) }
+    }
+}
+
+/// Size of `msg` as it is sent to the destination.
+pub fn payload_size(msg: &CrossChainMessage) -> usize {
+    serde_json::to_vec(msg).map(|bytes| bytes.len()).unwrap_or(0)
+}
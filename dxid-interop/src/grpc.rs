@@ -0,0 +1,325 @@
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use async_trait::async_trait;
+use dxid_config::ExternalChainSettings;
+use dxid_core::CrossChainMessage;
+use dxid_crypto::{SnarkProof, WinterfellBackend, ZkSnarkBackend, ZkStarkBackend};
+use serde_json::Value;
+use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue};
+use tonic::transport::{Channel, Endpoint as GrpcEndpoint};
+use tonic::{Code, Request, Status};
+use uuid::Uuid;
+
+use crate::handshake::{Negotiated, SUPPORTED_PROTOCOL_VERSIONS};
+use crate::receipts::{ReceiptVerifier, SignedReceipt};
+use crate::transport::Endpoint;
+use crate::{
+    accept_receipt, bridge, complete_handshake, ChainAdapter, ExternalChainConfig, ExternalChainHandle,
+    ExternalStateQuery, ExternalStateResponse, InboundBatch, InteropError, TxReceipt,
+};
+
+pub mod proto {
+    tonic::include_proto!("dxid.bridge");
+}
+
+use proto::bridge_client::BridgeClient;
+
+/// Adapter for partner chains that expose the `dxid.bridge.Bridge` gRPC
+/// service. The handshake and other generic calls go through `QueryState`.
+pub struct GrpcChainAdapter {
+    target: GrpcEndpoint,
+    channel: OnceLock<Channel>,
+    endpoint: Endpoint,
+    metadata: Vec<(AsciiMetadataKey, AsciiMetadataValue)>,
+    stark: Box<dyn ZkStarkBackend>,
+    snark: Arc<dyn ZkSnarkBackend>,
+    negotiated: RwLock<Option<Negotiated>>,
+    receipts: Option<ReceiptVerifier>,
+}
+
+impl GrpcChainAdapter {
+    pub fn from_settings(
+        settings: &ExternalChainSettings,
+        snark: Arc<dyn ZkSnarkBackend>,
+    ) -> Result<Self, InteropError> {
+        let target = GrpcEndpoint::from_shared(settings.endpoint.clone())
+            .map_err(|e| InteropError::Other(format!("invalid grpc endpoint {}: {e}", settings.endpoint)))?;
+        let mut headers: Vec<(String, String)> =
+            settings.headers.iter().map(|(k, v)| (k.to_ascii_lowercase(), v.clone())).collect();
+        if let Some(token) = &settings.auth_token {
+            headers.push(("authorization".into(), format!("Bearer {token}")));
+        }
+        let metadata = headers
+            .into_iter()
+            .map(|(k, v)| {
+                let key = AsciiMetadataKey::from_str(&k)
+                    .map_err(|e| InteropError::Other(format!("invalid metadata key {k}: {e}")))?;
+                let value = AsciiMetadataValue::try_from(v.as_str())
+                    .map_err(|e| InteropError::Other(format!("invalid metadata value for {k}: {e}")))?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, InteropError>>()?;
+        Ok(Self {
+            target,
+            channel: OnceLock::new(),
+            endpoint: Endpoint::new(settings.endpoint.clone(), settings.http.clone()),
+            metadata,
+            stark: Box::new(WinterfellBackend::new()),
+            snark,
+            negotiated: RwLock::new(None),
+            receipts: ReceiptVerifier::from_settings(settings)?,
+        })
+    }
+
+    /// Protocol version agreed during the last successful `connect`.
+    pub fn protocol_version(&self) -> Option<u32> {
+        self.negotiated.read().unwrap().as_ref().map(|n| n.protocol_version)
+    }
+
+    /// The destination's receipt for `message_id` and its confirmation count.
+    pub async fn get_receipt(&self, message_id: Uuid) -> Result<Option<(SignedReceipt, u64)>, InteropError> {
+        let resp = self
+            .endpoint
+            .call(|| async {
+                let req = self.request(proto::GetReceiptRequest { message_id: message_id.to_string() });
+                self.client().get_receipt(req).await.map_err(|s| self.classify(s))
+            })
+            .await?
+            .into_inner();
+        if !resp.found {
+            return Ok(None);
+        }
+        let receipt = serde_json::from_value(parse_json(&resp.receipt_json)?)
+            .map_err(|e| InteropError::Decode(format!("receipt: {e}")))?;
+        Ok(Some((receipt, resp.confirmations)))
+    }
+
+    fn client(&self) -> BridgeClient<Channel> {
+        // Built on first use so adapters can be constructed outside a runtime.
+        BridgeClient::new(self.channel.get_or_init(|| self.target.connect_lazy()).clone())
+    }
+
+    fn request<T>(&self, body: T) -> Request<T> {
+        let mut req = Request::new(body);
+        for (key, value) in &self.metadata {
+            req.metadata_mut().insert(key.clone(), value.clone());
+        }
+        req
+    }
+
+    fn classify(&self, status: Status) -> InteropError {
+        match status.code() {
+            Code::DeadlineExceeded => InteropError::Timeout(self.endpoint.name().to_string()),
+            Code::Unavailable | Code::ResourceExhausted | Code::Aborted => {
+                InteropError::Transport(format!("{}: {}", status.code(), status.message()))
+            }
+            code => InteropError::Rpc { code: code as i64, message: status.message().to_string() },
+        }
+    }
+
+    async fn query(&self, method: &str, params: &Value) -> Result<Value, InteropError> {
+        let resp = self
+            .endpoint
+            .call(|| async {
+                let req = self.request(proto::QueryStateRequest {
+                    method: method.to_string(),
+                    params_json: params.to_string(),
+                });
+                self.client().query_state(req).await.map_err(|s| self.classify(s))
+            })
+            .await?
+            .into_inner();
+        parse_json(&resp.result_json)
+    }
+}
+
+fn parse_json(raw: &str) -> Result<Value, InteropError> {
+    if raw.is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(raw).map_err(|e| InteropError::Decode(e.to_string()))
+}
+
+#[async_trait]
+impl ChainAdapter for GrpcChainAdapter {
+    async fn connect(&self, config: &ExternalChainConfig) -> Result<ExternalChainHandle, InteropError> {
+        let result = self
+            .query("dxid_handshake", &serde_json::json!({ "protocol_versions": SUPPORTED_PROTOCOL_VERSIONS }))
+            .await?;
+        let (metadata, negotiated) = complete_handshake(self.stark.as_ref(), config, result)?;
+        *self.negotiated.write().unwrap() = Some(negotiated);
+        Ok(ExternalChainHandle { id: Uuid::new_v4(), metadata })
+    }
+
+    async fn send_message(
+        &self,
+        proof: &SnarkProof,
+        msg: &CrossChainMessage,
+    ) -> Result<TxReceipt, InteropError> {
+        self.snark.verify_message(proof, msg).map_err(|e| InteropError::Proof(e.to_string()))?;
+        let message_json = serde_json::to_string(msg).map_err(|e| InteropError::Other(e.to_string()))?;
+        let proof_json = serde_json::to_string(proof).map_err(|e| InteropError::Other(e.to_string()))?;
+        let resp = self
+            .endpoint
+            .call(|| async {
+                let req = self.request(proto::SubmitRequest {
+                    message_json: message_json.clone(),
+                    proof_json: proof_json.clone(),
+                    protocol_version: self.protocol_version().unwrap_or_default(),
+                });
+                self.client().submit(req).await.map_err(|s| self.classify(s))
+            })
+            .await?
+            .into_inner();
+        accept_receipt(self.receipts.as_ref(), parse_json(&resp.receipt_json)?, msg)
+    }
+
+    async fn query_state(&self, query: &ExternalStateQuery) -> Result<ExternalStateResponse, InteropError> {
+        Ok(ExternalStateResponse { result: self.query(&query.method, &query.params).await? })
+    }
+
+    async fn poll_inbound(&self, cursor: u64, limit: usize) -> Result<InboundBatch, InteropError> {
+        let params = serde_json::json!({ "after": cursor, "limit": limit, "dest": bridge::DXID_CHAIN_ID });
+        let result = self.query("dxid_bridgeOutbox", &params).await?;
+        serde_json::from_value(result).map_err(|e| InteropError::Decode(format!("outbox: {e}")))
+    }
+
+    async fn quote_fee(&self, dest_chain: &str, payload_size: usize) -> Result<u64, InteropError> {
+        let params = serde_json::json!({ "dest": dest_chain, "payload_size": payload_size });
+        let result = self.query("dxid_estimateFee", &params).await?;
+        result["fee"].as_u64().ok_or_else(|| InteropError::Decode(format!("fee estimate: {result}")))
+    }
+
+    async fn confirmations(&self, receipt: &TxReceipt) -> Result<u64, InteropError> {
+        Ok(self.get_receipt(receipt.id).await?.map_or(0, |(_, confirmations)| confirmations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::NoopSnark;
+    use proto::bridge_server::{Bridge, BridgeServer};
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+    use tonic::Response;
+
+    struct MockBridge;
+
+    fn authorized<T>(req: &Request<T>) -> bool {
+        req.metadata().get("authorization").and_then(|v| v.to_str().ok()) == Some("Bearer secret")
+    }
+
+    #[tonic::async_trait]
+    impl Bridge for MockBridge {
+        async fn submit(
+            &self,
+            req: Request<proto::SubmitRequest>,
+        ) -> Result<Response<proto::SubmitResponse>, Status> {
+            if !authorized(&req) {
+                return Err(Status::unauthenticated("missing token"));
+            }
+            let msg: CrossChainMessage = serde_json::from_str(&req.get_ref().message_json).unwrap();
+            let receipt = SignedReceipt {
+                message_id: msg.id,
+                dest: msg.dest,
+                accepted: true,
+                height: 5,
+                tx_ref: "0xabc".into(),
+                signatures: vec![],
+            };
+            Ok(Response::new(proto::SubmitResponse {
+                receipt_json: serde_json::to_string(&receipt).unwrap(),
+            }))
+        }
+
+        async fn query_state(
+            &self,
+            req: Request<proto::QueryStateRequest>,
+        ) -> Result<Response<proto::QueryStateResponse>, Status> {
+            if !authorized(&req) {
+                return Err(Status::unauthenticated("missing token"));
+            }
+            match req.get_ref().method.as_str() {
+                "dxid_handshake" => Ok(Response::new(proto::QueryStateResponse {
+                    result_json: serde_json::json!({
+                        "chain_id": "cosmos",
+                        "network": "testnet",
+                        "latest_height": 7,
+                        "protocol_versions": [1]
+                    })
+                    .to_string(),
+                })),
+                other => Err(Status::unimplemented(other)),
+            }
+        }
+
+        async fn get_receipt(
+            &self,
+            req: Request<proto::GetReceiptRequest>,
+        ) -> Result<Response<proto::GetReceiptResponse>, Status> {
+            if !authorized(&req) {
+                return Err(Status::unauthenticated("missing token"));
+            }
+            Ok(Response::new(proto::GetReceiptResponse {
+                found: true,
+                receipt_json: serde_json::json!({
+                    "message_id": req.get_ref().message_id,
+                    "dest": "cosmos",
+                    "accepted": true,
+                    "height": 5,
+                    "tx_ref": "0xabc"
+                })
+                .to_string(),
+                confirmations: 3,
+            }))
+        }
+    }
+
+    fn settings(endpoint: String) -> ExternalChainSettings {
+        serde_json::from_value(serde_json::json!({
+            "chain_id": "cosmos",
+            "kind": "grpc",
+            "endpoint": endpoint,
+            "auth_token": "secret",
+            "allow_unauthenticated_receipts": true
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn submits_and_tracks_over_grpc() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(
+            Server::builder().add_service(BridgeServer::new(MockBridge)).serve_with_incoming(incoming),
+        );
+
+        let adapter =
+            GrpcChainAdapter::from_settings(&settings(endpoint.clone()), Arc::new(NoopSnark)).unwrap();
+        let config =
+            ExternalChainConfig { name: "cosmos".into(), rpc_endpoint: endpoint, metadata: Value::Null };
+        let handle = adapter.connect(&config).await.unwrap();
+        assert_eq!(handle.metadata.latest_height, 7);
+        assert_eq!(adapter.protocol_version(), Some(1));
+
+        let msg = CrossChainMessage {
+            id: Uuid::new_v4(),
+            source: "dxid".into(),
+            dest: "cosmos".into(),
+            payload: serde_json::json!({}),
+            nonce: 1,
+            timestamp: 0,
+        };
+        let proof = SnarkProof { proof: vec![], public_inputs: vec![] };
+        let receipt = adapter.send_message(&proof, &msg).await.unwrap();
+        assert!(receipt.accepted);
+        assert_eq!(adapter.confirmations(&receipt).await.unwrap(), 3);
+
+        let query = ExternalStateQuery { method: "unknown".into(), params: Value::Null };
+        let err = adapter.query_state(&query).await.unwrap_err();
+        assert!(matches!(err, InteropError::Rpc { code, .. } if code == Code::Unimplemented as i64));
+    }
+}
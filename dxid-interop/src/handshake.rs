@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{ExternalChainConfig, InteropError};
+
+/// Bridge protocol versions this node speaks, newest last.
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u32] = &[1];
+
+/// What a remote bridge endpoint reports about itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeInfo {
+    pub chain_id: String,
+    pub network: String,
+    pub latest_height: u64,
+    pub protocol_versions: Vec<u32>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Negotiated {
+    pub protocol_version: u32,
+    pub capabilities: Vec<String>,
+}
+
+/// Checks the remote identity against `config` and picks the highest protocol
+/// version both sides support.
+///
+/// The expected remote chain id is `metadata.remote_chain_id`, falling back to
+/// the configured name; `metadata.network` is checked when present.
+pub fn negotiate(config: &ExternalChainConfig, info: &HandshakeInfo) -> Result<Negotiated, InteropError> {
+    let expected_id = config.metadata["remote_chain_id"].as_str().unwrap_or(&config.name);
+    if info.chain_id != expected_id {
+        return Err(InteropError::Handshake(format!(
+            "{} reports chain id {}, expected {expected_id}",
+            config.rpc_endpoint, info.chain_id
+        )));
+    }
+    if let Some(network) = config.metadata["network"].as_str() {
+        if info.network != network {
+            return Err(InteropError::Handshake(format!(
+                "{} is on network {}, expected {network}",
+                config.rpc_endpoint, info.network
+            )));
+        }
+    }
+    let version = SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .rev()
+        .find(|v| info.protocol_versions.contains(v))
+        .copied()
+        .ok_or_else(|| {
+            InteropError::Handshake(format!(
+                "no common bridge protocol version: local {SUPPORTED_PROTOCOL_VERSIONS:?}, remote {:?}",
+                info.protocol_versions
+            ))
+        })?;
+    Ok(Negotiated { protocol_version: version, capabilities: info.capabilities.clone() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(chain_id: &str, versions: Vec<u32>) -> HandshakeInfo {
+        HandshakeInfo {
+            chain_id: chain_id.into(),
+            network: "sepolia".into(),
+            latest_height: 10,
+            protocol_versions: versions,
+            capabilities: vec!["mint".into()],
+        }
+    }
+
+    #[test]
+    fn negotiation_checks_identity_and_versions() {
+        let config = ExternalChainConfig {
+            name: "eth-sepolia".into(),
+            rpc_endpoint: "http://localhost:8545".into(),
+            metadata: serde_json::json!({ "remote_chain_id": "11155111", "network": "sepolia" }),
+        };
+        let negotiated = negotiate(&config, &info("11155111", vec![0, 1, 9])).unwrap();
+        assert_eq!(negotiated.protocol_version, 1);
+        assert!(matches!(negotiate(&config, &info("1", vec![1])), Err(InteropError::Handshake(_))));
+        assert!(matches!(negotiate(&config, &info("11155111", vec![7])), Err(InteropError::Handshake(_))));
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+
+use dxid_core::ChainId;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::InteropError;
+
+pub type HeaderHash = [u8; 32];
+
+/// Minimal view of an external block header needed for fork choice.
+pub trait ChainHeader: Clone + Send + Sync {
+    fn height(&self) -> u64;
+    fn hash(&self) -> HeaderHash;
+    fn parent_hash(&self) -> HeaderHash;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TrackedHeader {
+    pub height: u64,
+    pub hash: HeaderHash,
+    pub parent_hash: HeaderHash,
+}
+
+impl ChainHeader for TrackedHeader {
+    fn height(&self) -> u64 {
+        self.height
+    }
+
+    fn hash(&self) -> HeaderHash {
+        self.hash
+    }
+
+    fn parent_hash(&self) -> HeaderHash {
+        self.parent_hash
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderEvent<H> {
+    Extended,
+    Duplicate,
+    /// The header replaced the branch above `fork_height`; `dropped` holds the
+    /// headers that are no longer canonical, lowest first.
+    Reorg {
+        fork_height: u64,
+        dropped: Vec<H>,
+    },
+    /// The header does not connect to any tracked header. Callers should fetch
+    /// its parent and retry.
+    Unlinked,
+}
+
+/// Where a block of an external chain stands on the tracked canonical chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finality {
+    /// Buried under the required confirmations.
+    Final,
+    /// Not buried deep enough yet, or not tracked yet.
+    Pending,
+    /// Another block holds its height: a reorg dropped it.
+    Replaced,
+}
+
+/// Keeps the last `window` headers of one external chain and reports reorgs.
+#[derive(Debug, Clone)]
+pub struct HeaderTracker<H> {
+    window: usize,
+    confirmations: u64,
+    headers: VecDeque<H>,
+}
+
+impl<H: ChainHeader> HeaderTracker<H> {
+    pub fn new(window: usize, confirmations: u64) -> Self {
+        Self { window: window.max(1), confirmations, headers: VecDeque::new() }
+    }
+
+    pub fn required_confirmations(&self) -> u64 {
+        self.confirmations
+    }
+
+    pub fn tip(&self) -> Option<&H> {
+        self.headers.back()
+    }
+
+    pub fn base_height(&self) -> Option<u64> {
+        self.headers.front().map(|h| h.height())
+    }
+
+    pub fn get(&self, height: u64) -> Option<&H> {
+        let base = self.base_height()?;
+        height.checked_sub(base).and_then(|i| self.headers.get(i as usize))
+    }
+
+    pub fn ingest(&mut self, header: H) -> HeaderEvent<H> {
+        let Some(tip_height) = self.tip().map(|t| t.height()) else {
+            self.headers.push_back(header);
+            return HeaderEvent::Extended;
+        };
+        if let Some(existing) = self.get(header.height()) {
+            if existing.hash() == header.hash() {
+                return HeaderEvent::Duplicate;
+            }
+        }
+        let links = header
+            .height()
+            .checked_sub(1)
+            .and_then(|h| self.get(h))
+            .is_some_and(|parent| parent.hash() == header.parent_hash());
+        if !links {
+            return HeaderEvent::Unlinked;
+        }
+        if header.height() == tip_height + 1 {
+            self.headers.push_back(header);
+            if self.headers.len() > self.window {
+                self.headers.pop_front();
+            }
+            return HeaderEvent::Extended;
+        }
+        let fork_height = header.height() - 1;
+        let keep = (fork_height - self.base_height().unwrap_or_default() + 1) as usize;
+        let dropped: Vec<H> = self.headers.drain(keep..).collect();
+        warn!("external chain reorg at height {fork_height}, dropped {} headers", dropped.len());
+        self.headers.push_back(header);
+        HeaderEvent::Reorg { fork_height, dropped }
+    }
+
+    /// Confirmations of the block `hash` at `height`, or `None` if it is not on
+    /// the tracked canonical chain.
+    pub fn confirmations(&self, height: u64, hash: &HeaderHash) -> Option<u64> {
+        let header = self.get(height)?;
+        if &header.hash() != hash {
+            return None;
+        }
+        Some(self.tip()?.height() - height + 1)
+    }
+
+    pub fn is_final(&self, height: u64, hash: &HeaderHash) -> bool {
+        self.confirmations(height, hash).is_some_and(|c| c >= self.confirmations)
+    }
+
+    /// Where the block `hash` at `height` stands. With no confirmations
+    /// required, any block not replaced is final.
+    pub fn finality(&self, height: u64, hash: &HeaderHash) -> Finality {
+        match self.get(height) {
+            Some(header) if &header.hash() != hash => Finality::Replaced,
+            _ if self.confirmations == 0 || self.is_final(height, hash) => Finality::Final,
+            _ => Finality::Pending,
+        }
+    }
+}
+
+/// Header trackers for every external chain the node follows.
+#[derive(Default)]
+pub struct HeaderTrackers {
+    trackers: RwLock<HashMap<ChainId, HeaderTracker<TrackedHeader>>>,
+}
+
+impl HeaderTrackers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&self, chain: ChainId, window: usize, confirmations: u64) {
+        self.trackers
+            .write()
+            .unwrap()
+            .entry(chain)
+            .or_insert_with(|| HeaderTracker::new(window, confirmations));
+    }
+
+    pub fn untrack(&self, chain: &str) {
+        self.trackers.write().unwrap().remove(chain);
+    }
+
+    pub fn ingest(
+        &self,
+        chain: &str,
+        header: TrackedHeader,
+    ) -> Result<HeaderEvent<TrackedHeader>, InteropError> {
+        let mut map = self.trackers.write().unwrap();
+        let tracker =
+            map.get_mut(chain).ok_or_else(|| InteropError::Other(format!("chain {chain} is not tracked")))?;
+        Ok(tracker.ingest(header))
+    }
+
+    pub fn tip(&self, chain: &str) -> Option<TrackedHeader> {
+        self.trackers.read().unwrap().get(chain).and_then(|t| t.tip().cloned())
+    }
+
+    /// Whether an inbound event included in `hash` at `height` is buried deep
+    /// enough to act on.
+    pub fn is_final(&self, chain: &str, height: u64, hash: &HeaderHash) -> bool {
+        self.trackers.read().unwrap().get(chain).is_some_and(|t| t.is_final(height, hash))
+    }
+
+    /// Where an inbound event included in `hash` at `height` stands on
+    /// `chain`; pending while the chain is not tracked.
+    pub fn finality(&self, chain: &str, height: u64, hash: &HeaderHash) -> Finality {
+        self.trackers.read().unwrap().get(chain).map_or(Finality::Pending, |t| t.finality(height, hash))
+    }
+
+    /// Confirmations `chain` requires, if it is tracked.
+    pub fn required_confirmations(&self, chain: &str) -> Option<u64> {
+        self.trackers.read().unwrap().get(chain).map(|t| t.required_confirmations())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(height: u64, tag: u8, parent: &TrackedHeader) -> TrackedHeader {
+        TrackedHeader { height, hash: [tag; 32], parent_hash: parent.hash }
+    }
+
+    #[test]
+    fn detects_reorg_and_confirmations() {
+        let mut tracker = HeaderTracker::new(10, 3);
+        let genesis = TrackedHeader { height: 0, hash: [0; 32], parent_hash: [0; 32] };
+        tracker.ingest(genesis.clone());
+        let a1 = header(1, 1, &genesis);
+        let a2 = header(2, 2, &a1);
+        let a3 = header(3, 3, &a2);
+        for h in [&a1, &a2, &a3] {
+            assert_eq!(tracker.ingest(h.clone()), HeaderEvent::Extended);
+        }
+        assert!(tracker.is_final(1, &a1.hash));
+        assert!(!tracker.is_final(2, &a2.hash));
+        assert_eq!(tracker.finality(1, &a1.hash), Finality::Final);
+
+        let b2 = header(2, 22, &a1);
+        match tracker.ingest(b2.clone()) {
+            HeaderEvent::Reorg { fork_height, dropped } => {
+                assert_eq!(fork_height, 1);
+                assert_eq!(dropped, vec![a2.clone(), a3]);
+            }
+            other => panic!("expected reorg, got {other:?}"),
+        }
+        assert_eq!(tracker.confirmations(2, &a2.hash), None);
+        assert_eq!(tracker.finality(2, &a2.hash), Finality::Replaced);
+        // The shorter branch leaves a1 short of its confirmations again.
+        assert_eq!(tracker.finality(2, &b2.hash), Finality::Pending);
+        assert_eq!(tracker.finality(1, &a1.hash), Finality::Pending);
+        assert_eq!(tracker.ingest(header(9, 9, &b2)), HeaderEvent::Unlinked);
+    }
+
+    #[test]
+    fn window_is_bounded() {
+        let mut tracker = HeaderTracker::new(3, 1);
+        let mut prev = TrackedHeader { height: 0, hash: [0; 32], parent_hash: [0; 32] };
+        tracker.ingest(prev.clone());
+        for h in 1..10u8 {
+            let next = header(h as u64, h, &prev);
+            tracker.ingest(next.clone());
+            prev = next;
+        }
+        assert_eq!(tracker.base_height(), Some(7));
+        assert_eq!(tracker.tip().unwrap().height, 9);
+    }
+}
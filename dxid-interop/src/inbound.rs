@@ -0,0 +1,488 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use dxid_core::bridge::ProvenMessage;
+use dxid_core::{now_ts, CrossChainMessage, TxHash};
+use dxid_crypto::SnarkProof;
+use dxid_storage::{BridgeStore, InboundRecord, InboundStatus};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::bridge::DXID_CHAIN_ID;
+use crate::headers::{Finality, HeaderEvent};
+use crate::registry::{AdapterRegistry, RegisteredAdapter};
+use crate::InteropError;
+
+/// Where verified inbound messages go to be included on dxid.
+#[async_trait]
+pub trait DeliverySink: Send + Sync {
+    /// Submits the transaction that carries `proven` on chain, a release
+    /// for a burn and a delivery otherwise, and returns its hash.
+    async fn submit_delivery(&self, proven: ProvenMessage) -> Result<TxHash, InteropError>;
+
+    /// Where the transaction `hash` returned by `submit_delivery` stands.
+    async fn delivery_status(&self, hash: &TxHash) -> Result<DeliveryStatus, InteropError>;
+}
+
+/// Where a submitted delivery transaction stands on dxid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Waiting in the mempool.
+    Pending,
+    /// In the main chain, with `confirmations` blocks on top of its block.
+    Included { confirmations: u64 },
+    /// Neither waiting nor in the main chain: evicted, expired, or reverted
+    /// and not taken back.
+    Missing,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboundConfig {
+    pub poll_interval_ms: u64,
+    /// Messages requested from each chain per poll.
+    pub batch_size: usize,
+    /// Blocks a delivery's block needs on top of it before the message
+    /// counts as delivered.
+    pub confirmations: u64,
+}
+
+impl Default for InboundConfig {
+    fn default() -> Self {
+        Self { poll_interval_ms: 5_000, batch_size: 64, confirmations: 6 }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InboundStats {
+    pub received: usize,
+    pub duplicates: usize,
+    pub rejected: usize,
+    pub submitted: usize,
+    pub delivered: usize,
+    /// Deliveries lost before they were included, submitted again.
+    pub retried: usize,
+    /// Messages whose source block a reorg dropped before it was final.
+    pub dropped: usize,
+}
+
+/// Polls every registered chain for messages addressed to dxid, verifies and
+/// stores them once, and hands them with their proofs to the sink once their
+/// source block is final on the chain's tracked headers.
+pub struct InboundPipeline {
+    store: Arc<dyn BridgeStore>,
+    registry: Arc<AdapterRegistry>,
+    sink: Arc<dyn DeliverySink>,
+    config: InboundConfig,
+}
+
+impl InboundPipeline {
+    pub fn new(
+        store: Arc<dyn BridgeStore>,
+        registry: Arc<AdapterRegistry>,
+        sink: Arc<dyn DeliverySink>,
+        config: InboundConfig,
+    ) -> Self {
+        Self { store, registry, sink, config }
+    }
+
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let interval = Duration::from_millis(self.config.poll_interval_ms);
+            loop {
+                match self.tick().await {
+                    Ok(stats) if stats != InboundStats::default() => debug!("inbound tick {stats:?}"),
+                    Ok(_) => {}
+                    Err(e) => warn!("inbound tick failed: {e}"),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+
+    /// Polls each chain once, settles the submitted deliveries, then submits
+    /// everything still undelivered whose source block is final, and drops
+    /// what a reorg took away.
+    pub async fn tick(&self) -> Result<InboundStats, InteropError> {
+        let mut stats = InboundStats::default();
+        for summary in self.registry.chains() {
+            let Some(entry) = self.registry.get(&summary.chain_id) else {
+                continue;
+            };
+            if let Err(e) = self.poll_chain(&entry, &mut stats).await {
+                warn!("polling {} failed: {e}", summary.chain_id);
+            }
+        }
+        self.settle_submitted(&mut stats).await?;
+        let pending = self
+            .store
+            .list_inbound(InboundStatus::Received, self.config.batch_size as i64)
+            .await
+            .map_err(|e| InteropError::Other(e.to_string()))?;
+        for mut record in pending {
+            match self.finality(&record) {
+                Finality::Pending => continue,
+                Finality::Replaced => {
+                    info!(
+                        "dropped {} from {}: its block was reorged away",
+                        record.message.id, record.message.source
+                    );
+                    record.status = InboundStatus::Dropped;
+                    record.last_error = Some("source block left the canonical chain".into());
+                    stats.dropped += 1;
+                }
+                Finality::Final => {
+                    let proof = record.proof.clone().unwrap_or_default();
+                    let proven = ProvenMessage { message: record.message.clone(), proof };
+                    match self.sink.submit_delivery(proven).await {
+                        Ok(hash) => {
+                            debug!("submitted {} from {}", record.message.id, record.message.source);
+                            record.status = InboundStatus::Submitted;
+                            record.delivery_tx = Some(hex::encode(hash));
+                            record.last_error = None;
+                            stats.submitted += 1;
+                        }
+                        Err(e) => record.last_error = Some(e.to_string()),
+                    }
+                }
+            }
+            record.updated_at = now_ts();
+            self.store.update_inbound(&record).await.map_err(|e| InteropError::Other(e.to_string()))?;
+        }
+        Ok(stats)
+    }
+
+    /// Marks the submitted deliveries buried `confirmations` deep as
+    /// delivered and puts those that left the mempool without being
+    /// included back to be submitted again; the ledger records each
+    /// (source, nonce) once, so a second submission cannot deliver twice.
+    async fn settle_submitted(&self, stats: &mut InboundStats) -> Result<(), InteropError> {
+        let submitted = self
+            .store
+            .list_inbound(InboundStatus::Submitted, self.config.batch_size as i64)
+            .await
+            .map_err(|e| InteropError::Other(e.to_string()))?;
+        for mut record in submitted {
+            let status = match delivery_hash(&record) {
+                Some(hash) => self.sink.delivery_status(&hash).await?,
+                None => DeliveryStatus::Missing,
+            };
+            match status {
+                DeliveryStatus::Included { confirmations } if confirmations >= self.config.confirmations => {
+                    info!("delivered {} from {}", record.message.id, record.message.source);
+                    record.status = InboundStatus::Delivered;
+                    stats.delivered += 1;
+                }
+                DeliveryStatus::Pending | DeliveryStatus::Included { .. } => continue,
+                DeliveryStatus::Missing => {
+                    warn!("delivery of {} was lost, submitting it again", record.message.id);
+                    record.status = InboundStatus::Received;
+                    record.delivery_tx = None;
+                    record.last_error = Some("delivery transaction left the mempool unincluded".into());
+                    stats.retried += 1;
+                }
+            }
+            record.updated_at = now_ts();
+            self.store.update_inbound(&record).await.map_err(|e| InteropError::Other(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Where `record`'s source block stands. A message whose adapter did not
+    /// report its block is only final on a chain requiring no confirmations.
+    fn finality(&self, record: &InboundRecord) -> Finality {
+        let (headers, source) = (self.registry.headers(), &record.message.source);
+        match record.source_block {
+            Some((height, hash)) => headers.finality(source, height, &hash),
+            None if headers.required_confirmations(source) == Some(0) => Finality::Final,
+            None => Finality::Pending,
+        }
+    }
+
+    async fn poll_chain(
+        &self,
+        entry: &RegisteredAdapter,
+        stats: &mut InboundStats,
+    ) -> Result<(), InteropError> {
+        let chain = &entry.settings.chain_id;
+        let cursor =
+            self.store.inbound_cursor(chain).await.map_err(|e| InteropError::Other(e.to_string()))?;
+        let batch = entry.adapter.poll_inbound(cursor, self.config.batch_size).await?;
+        for header in batch.headers {
+            if self.registry.headers().ingest(chain, header)? == HeaderEvent::Unlinked {
+                warn!("header from {chain} does not link to the tracked headers");
+            }
+        }
+        for inbound in batch.messages {
+            if let Err(e) = validate(entry, &inbound.message, &inbound.proof) {
+                // Not persisted: an invalid message must not claim its (source, nonce).
+                warn!("rejected inbound message {} from {chain}: {e}", inbound.message.id);
+                stats.rejected += 1;
+                continue;
+            }
+            let proof = serde_json::to_value(&inbound.proof).ok();
+            let mut record = InboundRecord::new(inbound.message, proof, now_ts());
+            record.source_block = inbound.block;
+            let fresh =
+                self.store.record_inbound(&record).await.map_err(|e| InteropError::Other(e.to_string()))?;
+            if fresh {
+                stats.received += 1;
+            } else {
+                stats.duplicates += 1;
+            }
+        }
+        if batch.cursor != cursor {
+            self.store
+                .set_inbound_cursor(chain, batch.cursor)
+                .await
+                .map_err(|e| InteropError::Other(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+fn delivery_hash(record: &InboundRecord) -> Option<TxHash> {
+    hex::decode(record.delivery_tx.as_deref()?).ok()?.try_into().ok()
+}
+
+/// Checks that `msg` comes from `entry`'s chain, is addressed to dxid and is
+/// proven by `proof` under the chain's backend.
+pub(crate) fn validate(
+    entry: &RegisteredAdapter,
+    msg: &CrossChainMessage,
+    proof: &SnarkProof,
+) -> Result<(), InteropError> {
+    if msg.source != entry.settings.chain_id {
+        return Err(InteropError::Other(format!("claims source {}", msg.source)));
+    }
+    if msg.dest != DXID_CHAIN_ID {
+        return Err(InteropError::Other(format!("addressed to {}", msg.dest)));
+    }
+    entry.snark.verify_message(proof, msg).map_err(|e| InteropError::Proof(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::TrackedHeader;
+    use crate::test_support::registry_with;
+    use crate::{
+        ChainAdapter, ExternalChainConfig, ExternalChainHandle, ExternalStateQuery, ExternalStateResponse,
+        InboundBatch, InboundMessage, TxReceipt,
+    };
+    use dxid_core::bridge::{delivery_transaction, parse_delivery};
+    use dxid_core::Transaction;
+    use dxid_storage::MemoryBridgeStore;
+    use std::collections::HashMap;
+    use tokio::sync::Mutex;
+    use uuid::Uuid;
+
+    /// Serves the same outbox on every poll, like a remote that ignores
+    /// cursors, with whatever headers it is given.
+    struct OutboxAdapter {
+        messages: Vec<InboundMessage>,
+        headers: std::sync::Mutex<Vec<TrackedHeader>>,
+    }
+
+    impl OutboxAdapter {
+        fn new(messages: Vec<InboundMessage>, headers: Vec<TrackedHeader>) -> Self {
+            Self { messages, headers: std::sync::Mutex::new(headers) }
+        }
+    }
+
+    #[async_trait]
+    impl ChainAdapter for OutboxAdapter {
+        async fn connect(&self, _config: &ExternalChainConfig) -> Result<ExternalChainHandle, InteropError> {
+            Err(InteropError::Other("unused".into()))
+        }
+
+        async fn send_message(
+            &self,
+            _proof: &SnarkProof,
+            _msg: &CrossChainMessage,
+        ) -> Result<TxReceipt, InteropError> {
+            Err(InteropError::Other("unused".into()))
+        }
+
+        async fn query_state(
+            &self,
+            _query: &ExternalStateQuery,
+        ) -> Result<ExternalStateResponse, InteropError> {
+            Err(InteropError::Other("unused".into()))
+        }
+
+        async fn poll_inbound(&self, _cursor: u64, _limit: usize) -> Result<InboundBatch, InteropError> {
+            Ok(InboundBatch {
+                messages: self.messages.clone(),
+                cursor: self.messages.len() as u64,
+                headers: self.headers.lock().unwrap().clone(),
+            })
+        }
+    }
+
+    /// Keeps what it is given, each submission waiting until `settle`.
+    #[derive(Default)]
+    struct RecordingSink {
+        txs: Mutex<Vec<Transaction>>,
+        statuses: Mutex<HashMap<TxHash, DeliveryStatus>>,
+    }
+
+    impl RecordingSink {
+        /// Puts the delivery of the message with `nonce` at `status`.
+        async fn settle(&self, nonce: u64, status: DeliveryStatus) {
+            let txs = self.txs.lock().await;
+            let tx = txs.iter().find(|tx| parse_delivery(tx).unwrap().message.nonce == nonce).unwrap();
+            self.statuses.lock().await.insert(tx.hash(), status);
+        }
+    }
+
+    #[async_trait]
+    impl DeliverySink for RecordingSink {
+        async fn submit_delivery(&self, proven: ProvenMessage) -> Result<TxHash, InteropError> {
+            let tx = delivery_transaction(&proven);
+            let hash = tx.hash();
+            self.txs.lock().await.push(tx);
+            self.statuses.lock().await.insert(hash, DeliveryStatus::Pending);
+            Ok(hash)
+        }
+
+        async fn delivery_status(&self, hash: &TxHash) -> Result<DeliveryStatus, InteropError> {
+            Ok(self.statuses.lock().await.get(hash).copied().unwrap_or(DeliveryStatus::Missing))
+        }
+    }
+
+    /// Headers from `from` to `to` on the branch tagged `tag`, the first
+    /// one linked to `parent`.
+    fn branch(parent: [u8; 32], from: u64, to: u64, tag: u8) -> Vec<TrackedHeader> {
+        let mut parent_hash = parent;
+        (from..=to)
+            .map(|height| {
+                let mut hash = [tag; 32];
+                hash[0] = height as u8;
+                let header = TrackedHeader { height, hash, parent_hash };
+                parent_hash = hash;
+                header
+            })
+            .collect()
+    }
+
+    fn inbound(source: &str, dest: &str, nonce: u64) -> InboundMessage {
+        InboundMessage {
+            message: CrossChainMessage {
+                id: Uuid::new_v4(),
+                source: source.into(),
+                dest: dest.into(),
+                payload: serde_json::json!({ "type": "burn" }),
+                nonce,
+                timestamp: 0,
+            },
+            proof: SnarkProof { proof: vec![], public_inputs: vec![] },
+            block: None,
+        }
+    }
+
+    fn included(mut inbound: InboundMessage, header: &TrackedHeader) -> InboundMessage {
+        inbound.block = Some((header.height, header.hash));
+        inbound
+    }
+
+    #[tokio::test]
+    async fn dedups_and_delivers_once() {
+        // Six headers give the first block the six confirmations "eth" needs.
+        let headers = branch([0; 32], 0, 5, 1);
+        let messages = [
+            inbound("eth", DXID_CHAIN_ID, 1),
+            inbound("eth", DXID_CHAIN_ID, 1),
+            inbound("eth", "sol", 2),
+            inbound("sol", DXID_CHAIN_ID, 3),
+        ];
+        let messages = messages.into_iter().map(|m| included(m, &headers[0])).collect();
+        let registry = registry_with("eth", Arc::new(OutboxAdapter::new(messages, headers)));
+        let store = Arc::new(MemoryBridgeStore::new());
+        let sink = Arc::new(RecordingSink::default());
+        let pipeline = InboundPipeline::new(store.clone(), registry, sink.clone(), InboundConfig::default());
+
+        let stats = pipeline.tick().await.unwrap();
+        let expected =
+            InboundStats { received: 1, duplicates: 1, rejected: 2, submitted: 1, ..Default::default() };
+        assert_eq!(stats, expected);
+        assert_eq!(store.inbound_cursor("eth").await.unwrap(), 4);
+
+        sink.settle(1, DeliveryStatus::Included { confirmations: 6 }).await;
+        let stats = pipeline.tick().await.unwrap();
+        assert_eq!((stats.received, stats.submitted, stats.delivered), (0, 0, 1));
+        let stats = pipeline.tick().await.unwrap();
+        assert_eq!(stats, InboundStats { duplicates: 2, rejected: 2, ..Default::default() });
+        let txs = sink.txs.lock().await;
+        assert_eq!(txs.len(), 1);
+        let proven = parse_delivery(&txs[0]).unwrap();
+        assert_eq!(proven.message.nonce, 1);
+        let proof: SnarkProof = serde_json::from_value(proven.proof).unwrap();
+        assert!(proof.proof.is_empty());
+    }
+
+    #[tokio::test]
+    async fn holds_messages_until_their_block_is_final_and_drops_reorged_ones() {
+        let main = branch([0; 32], 0, 2, 1);
+        let messages = vec![
+            included(inbound("eth", DXID_CHAIN_ID, 1), &main[1]),
+            included(inbound("eth", DXID_CHAIN_ID, 2), &main[2]),
+        ];
+        let adapter = Arc::new(OutboxAdapter::new(messages, main.clone()));
+        let registry = registry_with("eth", adapter.clone());
+        let store = Arc::new(MemoryBridgeStore::new());
+        let sink = Arc::new(RecordingSink::default());
+        let pipeline = InboundPipeline::new(store.clone(), registry, sink.clone(), InboundConfig::default());
+
+        let stats = pipeline.tick().await.unwrap();
+        assert_eq!((stats.received, stats.submitted), (2, 0));
+        assert!(sink.txs.lock().await.is_empty());
+
+        // A fork off the first block replaces the second and buries the first.
+        *adapter.headers.lock().unwrap() = branch(main[1].hash, 2, 6, 2);
+        let stats = pipeline.tick().await.unwrap();
+        assert_eq!((stats.submitted, stats.dropped), (1, 1));
+        let txs = sink.txs.lock().await;
+        assert_eq!(txs.len(), 1);
+        assert_eq!(parse_delivery(&txs[0]).unwrap().message.nonce, 1);
+        let dropped = store.list_inbound(InboundStatus::Dropped, 10).await.unwrap();
+        assert_eq!(dropped.iter().map(|r| r.message.nonce).collect::<Vec<_>>(), [2]);
+    }
+    #[tokio::test]
+    async fn delivers_once_buried_and_resubmits_lost_deliveries() {
+        let headers = branch([0; 32], 0, 5, 1);
+        let messages = vec![
+            included(inbound("eth", DXID_CHAIN_ID, 1), &headers[0]),
+            included(inbound("eth", DXID_CHAIN_ID, 2), &headers[0]),
+        ];
+        let registry = registry_with("eth", Arc::new(OutboxAdapter::new(messages, headers)));
+        let store = Arc::new(MemoryBridgeStore::new());
+        let sink = Arc::new(RecordingSink::default());
+        let pipeline = InboundPipeline::new(store.clone(), registry, sink.clone(), InboundConfig::default());
+        let nonces =
+            |records: Vec<InboundRecord>| records.iter().map(|r| r.message.nonce).collect::<Vec<_>>();
+
+        let stats = pipeline.tick().await.unwrap();
+        assert_eq!((stats.submitted, stats.delivered), (2, 0));
+
+        // Accepted is not delivered: the first is still too shallow and the
+        // second was evicted, so it goes out again.
+        sink.settle(1, DeliveryStatus::Included { confirmations: 2 }).await;
+        sink.settle(2, DeliveryStatus::Missing).await;
+        let stats = pipeline.tick().await.unwrap();
+        assert_eq!((stats.delivered, stats.retried, stats.submitted), (0, 1, 1));
+        assert!(store.list_inbound(InboundStatus::Delivered, 10).await.unwrap().is_empty());
+        assert_eq!(nonces(store.list_inbound(InboundStatus::Submitted, 10).await.unwrap()), [1, 2]);
+
+        sink.settle(1, DeliveryStatus::Included { confirmations: 6 }).await;
+        let stats = pipeline.tick().await.unwrap();
+        assert_eq!((stats.delivered, stats.retried, stats.submitted), (1, 0, 0));
+        assert_eq!(nonces(store.list_inbound(InboundStatus::Delivered, 10).await.unwrap()), [1]);
+        let txs = sink.txs.lock().await;
+        assert_eq!(
+            txs.iter().map(|tx| parse_delivery(tx).unwrap().message.nonce).collect::<Vec<_>>(),
+            [1, 2, 2]
+        );
+    }
+}
@@ -1,14 +1,40 @@
+pub mod batch;
+pub mod bitcoin;
+pub mod bridge;
+pub mod fees;
+pub mod grpc;
+pub mod handshake;
+pub mod headers;
+pub mod inbound;
+pub mod metrics;
+pub mod policy;
+pub mod receipts;
+pub mod registry;
+pub mod relayer;
+pub mod transport;
+pub mod webhooks;
+#[cfg(test)]
+mod test_support;
+
+use std::sync::{Arc, RwLock};
+
 use anyhow::Result;
 use async_trait::async_trait;
 use dxid_core::{ChainMetadata, CrossChainMessage};
-use dxid_crypto::{Groth16Backend, StarkProofWrapper, WinterfellBackend, ZkSnarkBackend, ZkStarkBackend};
-use reqwest::Client;
+use dxid_config::{ExternalChainSettings, HttpPolicy};
+use dxid_crypto::{WinterfellBackend, ZkSnarkBackend, ZkStarkBackend};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 use tracing::info;
 use uuid::Uuid;
 
+use batch::{verify_batched, BatchProof, MessageInclusion};
+use handshake::{negotiate, HandshakeInfo, Negotiated, SUPPORTED_PROTOCOL_VERSIONS};
+use headers::{HeaderHash, TrackedHeader};
+use receipts::{ReceiptVerifier, SignedReceipt};
+use transport::{HttpRpcClient, RpcAuth};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExternalChainConfig {
     pub name: String,
@@ -27,6 +53,9 @@ pub struct TxReceipt {
     pub id: Uuid,
     pub accepted: bool,
     pub response: Value,
+    /// Authority-signed receipt backing `accepted`, when the adapter has one.
+    #[serde(default)]
+    pub signed: Option<SignedReceipt>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,16 +69,80 @@ pub struct ExternalStateResponse {
     pub result: Value,
 }
 
+/// A message read from an external chain's outbox, addressed to dxid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboundMessage {
+    pub message: CrossChainMessage,
+    pub proof: dxid_crypto::SnarkProof,
+    /// Height and hash of the source block that included the message. It is
+    /// delivered once that block is final on the chain's tracked headers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<(u64, HeaderHash)>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InboundBatch {
+    pub messages: Vec<InboundMessage>,
+    /// Position to resume from on the next poll.
+    pub cursor: u64,
+    /// Headers of the source chain since the last poll, lowest first, which
+    /// the registry's tracker for the chain follows.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<TrackedHeader>,
+}
+
 #[derive(Debug, Error)]
 pub enum InteropError {
-    #[error("http error: {0}")]
-    Http(String),
+    #[error("request to {0} timed out")]
+    Timeout(String),
+    #[error("transport error: {0}")]
+    Transport(String),
+    #[error("remote returned status {status}: {body}")]
+    Status { status: u16, body: String },
+    #[error("malformed response: {0}")]
+    Decode(String),
+    #[error("circuit open for {0}")]
+    CircuitOpen(String),
+    #[error("handshake failed: {0}")]
+    Handshake(String),
+    #[error("invalid receipt: {0}")]
+    Receipt(String),
     #[error("proof error: {0}")]
     Proof(String),
+    #[error("remote error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("fee {offered} does not cover quote {required}")]
+    InsufficientFee { required: u64, offered: u64 },
+    #[error("refused by bridge policy: {0}")]
+    Policy(String),
     #[error("other: {0}")]
     Other(String),
 }
 
+impl InteropError {
+    /// Whether repeating the same request may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            InteropError::Timeout(_) | InteropError::Transport(_) | InteropError::CircuitOpen(_) => true,
+            InteropError::Status { status, .. } => *status >= 500 || *status == 408 || *status == 429,
+            _ => false,
+        }
+    }
+
+    /// Whether the message itself is at fault, so no amount of retrying helps.
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            InteropError::Proof(_)
+            | InteropError::Decode(_)
+            | InteropError::Handshake(_)
+            | InteropError::Receipt(_)
+            | InteropError::Policy(_) => true,
+            InteropError::Status { .. } => !self.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
 #[async_trait]
 pub trait ChainAdapter: Send + Sync {
     async fn connect(&self, config: &ExternalChainConfig) -> Result<ExternalChainHandle, InteropError>;
@@ -59,41 +152,164 @@ pub trait ChainAdapter: Send + Sync {
         msg: &CrossChainMessage,
     ) -> Result<TxReceipt, InteropError>;
     async fn query_state(&self, query: &ExternalStateQuery) -> Result<ExternalStateResponse, InteropError>;
+
+    /// Reads messages for dxid from the remote outbox after `cursor`.
+    /// Adapters without an outbox report nothing.
+    async fn poll_inbound(&self, cursor: u64, _limit: usize) -> Result<InboundBatch, InteropError> {
+        Ok(InboundBatch { messages: vec![], cursor, headers: vec![] })
+    }
+
+    /// Destination execution cost of a `payload_size`-byte message to
+    /// `dest_chain`, in dxid base units. Adapters without a fee market quote 0.
+    async fn quote_fee(&self, _dest_chain: &str, _payload_size: usize) -> Result<u64, InteropError> {
+        Ok(0)
+    }
+
+    /// Whether the destination accepts messages proven as part of a batch.
+    fn supports_batch_proofs(&self) -> bool {
+        false
+    }
+
+    /// Delivers a message covered by a batch proof and its merkle branch.
+    async fn send_batched(
+        &self,
+        _batch: &BatchProof,
+        _inclusion: &MessageInclusion,
+        msg: &CrossChainMessage,
+    ) -> Result<TxReceipt, InteropError> {
+        Err(InteropError::Other(format!("{} does not accept batched proofs", msg.dest)))
+    }
+
+    /// Confirmations the destination reports for a delivered message.
+    async fn confirmations(&self, receipt: &TxReceipt) -> Result<u64, InteropError> {
+        let query = ExternalStateQuery {
+            method: "dxid_bridgeConfirmations".into(),
+            params: serde_json::json!({ "id": receipt.id }),
+        };
+        let resp = self.query_state(&query).await?;
+        Ok(resp.result["confirmations"].as_u64().unwrap_or(0))
+    }
 }
 
 pub struct HttpJsonRpcAdapter {
-    client: Client,
+    rpc: HttpRpcClient,
     stark: Box<dyn ZkStarkBackend>,
-    snark: Box<dyn ZkSnarkBackend>,
+    snark: Arc<dyn ZkSnarkBackend>,
+    negotiated: RwLock<Option<Negotiated>>,
+    receipts: Option<ReceiptVerifier>,
 }
 
 impl HttpJsonRpcAdapter {
-    pub fn new() -> Self {
+    pub fn new(
+        endpoint: String,
+        auth_token: Option<String>,
+        policy: HttpPolicy,
+        snark: Arc<dyn ZkSnarkBackend>,
+    ) -> Self {
+        let auth = auth_token.map_or(RpcAuth::None, RpcAuth::Bearer);
+        Self::with_client(HttpRpcClient::new(endpoint, auth, policy), snark)
+    }
+
+    fn with_client(rpc: HttpRpcClient, snark: Arc<dyn ZkSnarkBackend>) -> Self {
         Self {
-            client: Client::new(),
+            rpc,
             stark: Box::new(WinterfellBackend::new()),
-            snark: Box::new(Groth16Backend::new().expect("groth16 backend")),
+            snark,
+            negotiated: RwLock::new(None),
+            receipts: None,
         }
     }
+
+    /// Builds the adapter for a `json_rpc` chain: endpoint, credentials, extra
+    /// headers, wire style and method names all come from `settings`.
+    pub fn from_settings(
+        settings: &ExternalChainSettings,
+        snark: Arc<dyn ZkSnarkBackend>,
+    ) -> Result<Self, InteropError> {
+        let auth = match (&settings.auth_token, &settings.username) {
+            (Some(token), _) => RpcAuth::Bearer(token.clone()),
+            (None, Some(user)) => RpcAuth::Basic(user.clone(), settings.password.clone().unwrap_or_default()),
+            (None, None) => RpcAuth::None,
+        };
+        let rpc = HttpRpcClient::new(settings.endpoint.clone(), auth, settings.http.clone())
+            .with_headers(&settings.headers)
+            .with_dialect(settings.style, &settings.methods);
+        let mut adapter = Self::with_client(rpc, snark);
+        adapter.receipts = ReceiptVerifier::from_settings(settings)?;
+        Ok(adapter)
+    }
+
+    /// Requires delivery receipts to be signed by the destination's authorities.
+    pub fn with_receipt_verifier(mut self, verifier: ReceiptVerifier) -> Self {
+        self.receipts = Some(verifier);
+        self
+    }
+
+    /// Protocol version agreed during the last successful `connect`.
+    pub fn protocol_version(&self) -> Option<u32> {
+        self.negotiated.read().unwrap().as_ref().map(|n| n.protocol_version)
+    }
+
+    fn receipt_from(&self, result: Value, msg: &CrossChainMessage) -> Result<TxReceipt, InteropError> {
+        accept_receipt(self.receipts.as_ref(), result, msg)
+    }
+}
+
+/// Parses a destination receipt for `msg` and checks its signatures. Without
+/// a verifier no receipt is accepted.
+pub(crate) fn accept_receipt(
+    verifier: Option<&ReceiptVerifier>,
+    result: Value,
+    msg: &CrossChainMessage,
+) -> Result<TxReceipt, InteropError> {
+    let signed: SignedReceipt =
+        serde_json::from_value(result.clone()).map_err(|e| InteropError::Decode(format!("receipt: {e}")))?;
+    let verifier = verifier.ok_or_else(|| {
+        InteropError::Receipt(format!("no receipt authorities configured for {}", msg.dest))
+    })?;
+    verifier.verify(&signed, msg)?;
+    Ok(TxReceipt { id: msg.id, accepted: signed.accepted, response: result, signed: Some(signed) })
+}
+
+/// Turns a `dxid_handshake` reply into connection metadata, negotiating the
+/// protocol and proving connectivity over the result.
+pub(crate) fn complete_handshake(
+    stark: &dyn ZkStarkBackend,
+    config: &ExternalChainConfig,
+    result: Value,
+) -> Result<(ChainMetadata, Negotiated), InteropError> {
+    let info: HandshakeInfo =
+        serde_json::from_value(result).map_err(|e| InteropError::Decode(format!("handshake: {e}")))?;
+    let negotiated = negotiate(config, &info)?;
+    let metadata = ChainMetadata {
+        chain_id: config.name.clone(),
+        rpc_endpoint: config.rpc_endpoint.clone(),
+        latest_height: info.latest_height,
+        network: info.network.clone(),
+        extra: serde_json::json!({
+            "remote_chain_id": info.chain_id,
+            "protocol_version": negotiated.protocol_version,
+            "capabilities": negotiated.capabilities,
+        }),
+    };
+    let proof = stark.prove_connection(&metadata).map_err(|e| InteropError::Proof(e.to_string()))?;
+    stark.verify_connection(&proof, &metadata).map_err(|e| InteropError::Proof(e.to_string()))?;
+    info!(
+        "connected to {} ({} at height {}), protocol v{}",
+        config.name, metadata.network, metadata.latest_height, negotiated.protocol_version
+    );
+    Ok((metadata, negotiated))
 }
 
 #[async_trait]
 impl ChainAdapter for HttpJsonRpcAdapter {
     async fn connect(&self, config: &ExternalChainConfig) -> Result<ExternalChainHandle, InteropError> {
-        let metadata = ChainMetadata {
-            chain_id: config.name.clone(),
-            rpc_endpoint: config.rpc_endpoint.clone(),
-            latest_height: 0,
-            network: "external".into(),
-            extra: config.metadata.clone(),
-        };
-        let proof = self
-            .stark
-            .prove_connection(&metadata)
-            .map_err(|e| InteropError::Proof(e.to_string()))?;
-        self.stark
-            .verify_connection(&proof, &metadata)
-            .map_err(|e| InteropError::Proof(e.to_string()))?;
+        let result = self
+            .rpc
+            .call("dxid_handshake", &serde_json::json!({ "protocol_versions": SUPPORTED_PROTOCOL_VERSIONS }))
+            .await?;
+        let (metadata, negotiated) = complete_handshake(self.stark.as_ref(), config, result)?;
+        *self.negotiated.write().unwrap() = Some(negotiated);
         Ok(ExternalChainHandle {
             id: Uuid::new_v4(),
             metadata,
@@ -108,44 +324,55 @@ impl ChainAdapter for HttpJsonRpcAdapter {
         self.snark
             .verify_message(proof, msg)
             .map_err(|e| InteropError::Proof(e.to_string()))?;
-        let resp = self
-            .client
-            .post(msg.dest.clone())
-            .json(&serde_json::json!({
-                "method": "dxid_bridge",
-                "params": msg,
-                "proof": proof
-            }))
-            .send()
-            .await
-            .map_err(|e| InteropError::Http(e.to_string()))?;
-        let body = resp
-            .json::<Value>()
-            .await
-            .map_err(|e| InteropError::Http(e.to_string()))?;
-        Ok(TxReceipt {
-            id: msg.id,
-            accepted: true,
-            response: body,
-        })
+        let params = serde_json::json!({
+            "message": msg,
+            "proof": proof,
+            "protocol_version": self.protocol_version()
+        });
+        let result = self.rpc.call("dxid_bridge", &params).await?;
+        self.receipt_from(result, msg)
+    }
+
+    async fn poll_inbound(&self, cursor: u64, limit: usize) -> Result<InboundBatch, InteropError> {
+        let params = serde_json::json!({ "after": cursor, "limit": limit, "dest": bridge::DXID_CHAIN_ID });
+        let result = self.rpc.call("dxid_bridgeOutbox", &params).await?;
+        serde_json::from_value(result).map_err(|e| InteropError::Decode(format!("outbox: {e}")))
+    }
+
+    async fn quote_fee(&self, dest_chain: &str, payload_size: usize) -> Result<u64, InteropError> {
+        let params = serde_json::json!({ "dest": dest_chain, "payload_size": payload_size });
+        let result = self.rpc.call("dxid_estimateFee", &params).await?;
+        result["fee"].as_u64().ok_or_else(|| InteropError::Decode(format!("fee estimate: {result}")))
+    }
+
+    fn supports_batch_proofs(&self) -> bool {
+        self.negotiated
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|n| n.capabilities.iter().any(|c| c == "batch_proofs"))
+    }
+
+    async fn send_batched(
+        &self,
+        batch: &BatchProof,
+        inclusion: &MessageInclusion,
+        msg: &CrossChainMessage,
+    ) -> Result<TxReceipt, InteropError> {
+        verify_batched(self.snark.as_ref(), batch, msg, inclusion)?;
+        let params = serde_json::json!({
+            "message": msg,
+            "batch": batch,
+            "inclusion": inclusion,
+            "protocol_version": self.protocol_version()
+        });
+        let result = self.rpc.call("dxid_bridgeBatched", &params).await?;
+        self.receipt_from(result, msg)
     }
 
     async fn query_state(&self, query: &ExternalStateQuery) -> Result<ExternalStateResponse, InteropError> {
-        let resp = self
-            .client
-            .post("http://placeholder-rpc")
-            .json(&serde_json::json!({
-                "method": query.method,
-                "params": query.params
-            }))
-            .send()
-            .await
-            .map_err(|e| InteropError::Http(e.to_string()))?;
-        let val = resp
-            .json::<Value>()
-            .await
-            .map_err(|e| InteropError::Http(e.to_string()))?;
-        Ok(ExternalStateResponse { result: val })
+        let result = self.rpc.call(&query.method, &query.params).await?;
+        Ok(ExternalStateResponse { result })
     }
 }
 
@@ -155,17 +382,31 @@ mod tests {
 
     #[tokio::test]
     async fn proof_roundtrip() {
-        let adapter = HttpJsonRpcAdapter::new();
+        let endpoint = test_support::serve_json(serde_json::json!({
+            "result": {
+                "chain_id": "demo",
+                "network": "devnet",
+                "latest_height": 42,
+                "protocol_versions": [1]
+            }
+        }))
+        .await;
+        let snark = Arc::new(dxid_crypto::Groth16Backend::new().unwrap());
+        let adapter = HttpJsonRpcAdapter::new(endpoint.clone(), None, HttpPolicy::default(), snark);
         let cfg = ExternalChainConfig {
             name: "demo".into(),
-            rpc_endpoint: "http://localhost:8545".into(),
-            metadata: serde_json::json!({}),
+            rpc_endpoint: endpoint,
+            metadata: serde_json::json!({ "network": "devnet" }),
         };
         let handle = adapter.connect(&cfg).await.unwrap();
+        assert_eq!(handle.metadata.latest_height, 42);
+        assert_eq!(adapter.protocol_version(), Some(1));
+        let mismatched = ExternalChainConfig { metadata: serde_json::json!({ "network": "mainnet" }), ..cfg };
+        assert!(matches!(adapter.connect(&mismatched).await, Err(InteropError::Handshake(_))));
         let msg = CrossChainMessage {
             id: Uuid::new_v4(),
             source: "demo".into(),
-            dest: "http://localhost:8545".into(),
+            dest: "demo".into(),
             payload: serde_json::json!({"ping": true}),
             nonce: 1,
             timestamp: 0,
@@ -174,4 +415,58 @@ mod tests {
         // verify_message is called inside send_message; invoke directly for test
         adapter.snark.verify_message(&proof, &msg).unwrap();
     }
+
+    #[tokio::test]
+    async fn send_message_requires_signed_receipt() {
+        let authority = dxid_crypto::generate_ed25519();
+        let msg = CrossChainMessage {
+            id: Uuid::new_v4(),
+            source: "dxid".into(),
+            dest: "eth".into(),
+            payload: serde_json::json!({}),
+            nonce: 1,
+            timestamp: 0,
+        };
+        let mut receipt = SignedReceipt {
+            message_id: msg.id,
+            dest: "eth".into(),
+            accepted: false,
+            height: 3,
+            tx_ref: "0x01".into(),
+            signatures: vec![],
+        };
+        let verifier = ReceiptVerifier::new(&[hex::encode(&authority.public_key)], 1).unwrap();
+        let proof = dxid_crypto::SnarkProof { proof: vec![], public_inputs: vec![] };
+
+        let unsigned = test_support::serve_json(serde_json::json!({ "result": receipt })).await;
+        let adapter = |verifier: Option<ReceiptVerifier>| {
+            let adapter = HttpJsonRpcAdapter::new(
+                unsigned.clone(),
+                None,
+                HttpPolicy::default(),
+                Arc::new(test_support::NoopSnark),
+            );
+            match verifier {
+                Some(verifier) => adapter.with_receipt_verifier(verifier),
+                None => adapter,
+            }
+        };
+        for verifier in [None, Some(verifier.clone())] {
+            assert!(matches!(
+                adapter(verifier).send_message(&proof, &msg).await,
+                Err(InteropError::Receipt(_))
+            ));
+        }
+        let opted_out = adapter(Some(ReceiptVerifier::unauthenticated()));
+        assert!(opted_out.send_message(&proof, &msg).await.is_ok());
+
+        receipt.sign(&authority.public_key, &authority.secret_key).unwrap();
+        let signed = test_support::serve_json(serde_json::json!({ "result": receipt })).await;
+        let adapter =
+            HttpJsonRpcAdapter::new(signed, None, HttpPolicy::default(), Arc::new(test_support::NoopSnark))
+                .with_receipt_verifier(verifier);
+        let delivered = adapter.send_message(&proof, &msg).await.unwrap();
+        assert!(!delivered.accepted);
+        assert_eq!(delivered.signed.unwrap().height, 3);
+    }
 }
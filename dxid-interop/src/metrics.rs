@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use dxid_core::ChainId;
+use serde::Serialize;
+
+/// Counters for one destination chain since the node started.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct ChainMetrics {
+    /// Messages pending or submitted but not yet confirmed or failed.
+    pub in_flight: u64,
+    pub proofs: u64,
+    pub proof_ms_total: u64,
+    /// Submission attempts, successful or not.
+    pub attempts: u64,
+    pub failed_attempts: u64,
+    pub sent: u64,
+    pub confirmed: u64,
+    /// Messages that failed permanently.
+    pub failed: u64,
+    /// Sum of enqueue-to-confirmation times of confirmed messages.
+    pub confirmation_secs_total: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainMetricsSnapshot {
+    pub chain: ChainId,
+    #[serde(flatten)]
+    pub counters: ChainMetrics,
+    pub avg_confirmation_secs: f64,
+    /// Share of submission attempts that failed.
+    pub failure_rate: f64,
+}
+
+/// Per-chain relay metrics, shared between the relayer and the RPC server.
+#[derive(Debug, Default)]
+pub struct InteropMetrics {
+    chains: RwLock<HashMap<ChainId, ChainMetrics>>,
+}
+
+impl InteropMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&self, chain: &str, f: impl FnOnce(&mut ChainMetrics)) {
+        let mut chains = self.chains.write().unwrap();
+        f(chains.entry(chain.to_string()).or_default());
+    }
+
+    /// Replaces every chain's in-flight gauge; chains missing from `counts`
+    /// have nothing in flight.
+    pub fn set_in_flight(&self, counts: &HashMap<String, u64>) {
+        let mut chains = self.chains.write().unwrap();
+        for (chain, metrics) in chains.iter_mut() {
+            metrics.in_flight = counts.get(chain).copied().unwrap_or(0);
+        }
+        for (chain, count) in counts {
+            chains.entry(chain.clone()).or_default().in_flight = *count;
+        }
+    }
+
+    pub fn get(&self, chain: &str) -> ChainMetrics {
+        self.chains.read().unwrap().get(chain).cloned().unwrap_or_default()
+    }
+
+    pub fn snapshot(&self) -> Vec<ChainMetricsSnapshot> {
+        let chains = self.chains.read().unwrap();
+        let mut out: Vec<_> = chains
+            .iter()
+            .map(|(chain, m)| ChainMetricsSnapshot {
+                chain: chain.clone(),
+                counters: m.clone(),
+                avg_confirmation_secs: ratio(m.confirmation_secs_total, m.confirmed),
+                failure_rate: ratio(m.failed_attempts, m.attempts),
+            })
+            .collect();
+        out.sort_by(|a, b| a.chain.cmp(&b.chain));
+        out
+    }
+}
+
+fn ratio(num: u64, den: u64) -> f64 {
+    if den == 0 {
+        0.0
+    } else {
+        num as f64 / den as f64
+    }
+}
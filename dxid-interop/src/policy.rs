@@ -0,0 +1,163 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use dxid_config::BridgePolicySettings;
+use dxid_core::{Address, CrossChainTx};
+use dxid_crypto::address_to_string;
+use tracing::warn;
+
+use crate::InteropError;
+
+const HOUR_SECS: u64 = 3_600;
+
+#[derive(Debug, Default)]
+struct Usage {
+    height: u64,
+    in_block: u64,
+    /// `(timestamp, amount)` of the last hour's messages, per sender.
+    bridged: HashMap<Address, VecDeque<(u64, u64)>>,
+}
+
+/// Destination allowlist, rate limits and the emergency pause, checked when a
+/// message is queued and again before it is relayed.
+#[derive(Debug)]
+pub struct BridgePolicy {
+    settings: BridgePolicySettings,
+    paused: AtomicBool,
+    usage: Mutex<Usage>,
+}
+
+impl BridgePolicy {
+    pub fn new(settings: BridgePolicySettings) -> Self {
+        let paused = AtomicBool::new(settings.paused);
+        Self { settings, paused, usage: Mutex::new(Usage::default()) }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Flips the emergency switch without a restart.
+    pub fn set_paused(&self, paused: bool) {
+        if paused != self.paused.swap(paused, Ordering::SeqCst) {
+            warn!("bridge {}", if paused { "paused" } else { "resumed" });
+        }
+    }
+
+    /// Starts counting messages against block `height`. The relayer calls
+    /// it for every block its chain events announce.
+    pub fn begin_block(&self, height: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        if height != usage.height {
+            usage.height = height;
+            usage.in_block = 0;
+        }
+    }
+
+    /// Whether anything may be sent to `dest` right now.
+    pub fn check_destination(&self, dest: &str) -> Result<(), InteropError> {
+        if self.is_paused() {
+            return Err(InteropError::Policy("bridge is paused".into()));
+        }
+        let allowed = &self.settings.allowed_destinations;
+        if !allowed.is_empty() && !allowed.iter().any(|chain| chain == dest) {
+            return Err(InteropError::Policy(format!("destination {dest} is not allowed")));
+        }
+        Ok(())
+    }
+
+    /// Checks `tx` against every rule and, if it passes, counts it towards
+    /// the block limit and, when dxid authenticated its `sender`, towards
+    /// that sender's hourly limit.
+    pub fn admit(&self, tx: &CrossChainTx, sender: Option<&Address>, now: u64) -> Result<(), InteropError> {
+        self.check_destination(&tx.message.dest)?;
+        let mut usage = self.usage.lock().unwrap();
+        let max_in_block = self.settings.max_messages_per_block;
+        if max_in_block > 0 && usage.in_block >= max_in_block {
+            return Err(InteropError::Policy(format!(
+                "block {} already holds {max_in_block} bridge messages",
+                usage.height
+            )));
+        }
+        let amount = tx.message.payload["amount"].as_u64().unwrap_or(0);
+        let max_value = self.settings.max_value_per_hour;
+        if let Some(address) = sender.filter(|_| max_value > 0) {
+            let window = usage.bridged.entry(*address).or_default();
+            while window.front().is_some_and(|(at, _)| at + HOUR_SECS <= now) {
+                window.pop_front();
+            }
+            let bridged: u64 = window.iter().map(|(_, amount)| amount).sum();
+            if bridged.saturating_add(amount) > max_value {
+                return Err(InteropError::Policy(format!(
+                    "{} would bridge {} this hour, over the {max_value} limit",
+                    address_to_string(address),
+                    bridged.saturating_add(amount)
+                )));
+            }
+            window.push_back((now, amount));
+        }
+        usage.in_block += 1;
+        Ok(())
+    }
+}
+
+impl Default for BridgePolicy {
+    fn default() -> Self {
+        Self::new(BridgePolicySettings::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dxid_core::CrossChainMessage;
+    use uuid::Uuid;
+
+    fn transfer(dest: &str, amount: u64) -> CrossChainTx {
+        CrossChainTx {
+            message: CrossChainMessage {
+                id: Uuid::new_v4(),
+                source: "dxid".into(),
+                dest: dest.into(),
+                payload: serde_json::json!({ "amount": amount }),
+                nonce: 0,
+                timestamp: 0,
+            },
+            fee: 0,
+            proof: None,
+        }
+    }
+
+    #[test]
+    fn enforces_allowlist_and_limits() {
+        let policy = BridgePolicy::new(BridgePolicySettings {
+            allowed_destinations: vec!["eth".into()],
+            max_messages_per_block: 3,
+            max_value_per_hour: 100,
+            paused: false,
+        });
+        let (a, b) = ([1u8; 32], [2u8; 32]);
+        assert!(matches!(policy.admit(&transfer("sol", 1), Some(&a), 0), Err(InteropError::Policy(_))));
+
+        policy.admit(&transfer("eth", 60), Some(&a), 0).unwrap();
+        assert!(policy.admit(&transfer("eth", 41), Some(&a), 10).is_err());
+        policy.admit(&transfer("eth", 41), Some(&b), 10).unwrap();
+        policy.admit(&transfer("eth", 40), Some(&a), 20).unwrap();
+        assert!(policy.admit(&transfer("eth", 1), None, 20).is_err());
+
+        // The hourly limit follows the authenticated sender, whatever the
+        // payload claims.
+        policy.begin_block(1);
+        let mut spoofed = transfer("eth", 1);
+        spoofed.message.payload["sender"] = "someone-else".into();
+        assert!(policy.admit(&spoofed, Some(&a), 30).is_err());
+        policy.admit(&transfer("eth", 60), Some(&a), HOUR_SECS).unwrap();
+
+        policy.set_paused(true);
+        assert!(policy.admit(&transfer("eth", 1), None, HOUR_SECS).is_err());
+        assert!(policy.check_destination("eth").is_err());
+        policy.set_paused(false);
+        policy.check_destination("eth").unwrap();
+    }
+}
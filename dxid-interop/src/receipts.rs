@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+
+use dxid_config::ExternalChainSettings;
+use dxid_core::{ChainId, CrossChainMessage, CryptoProvider};
+use dxid_crypto::DefaultCryptoProvider;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::InteropError;
+
+const RECEIPT_DOMAIN: &[u8] = b"dxid/bridge-receipt/v1";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReceiptSignature {
+    /// Hex-encoded ed25519 public key of the signer.
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// Delivery receipt issued by the destination chain's bridge authority.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignedReceipt {
+    pub message_id: Uuid,
+    pub dest: ChainId,
+    pub accepted: bool,
+    /// Destination height the message was included at, if accepted.
+    pub height: u64,
+    /// Destination-side transaction reference.
+    pub tx_ref: String,
+    #[serde(default)]
+    pub signatures: Vec<ReceiptSignature>,
+}
+
+impl SignedReceipt {
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(128);
+        out.extend_from_slice(RECEIPT_DOMAIN);
+        out.extend_from_slice(self.message_id.as_bytes());
+        out.extend_from_slice(&(self.dest.len() as u32).to_le_bytes());
+        out.extend_from_slice(self.dest.as_bytes());
+        out.push(self.accepted as u8);
+        out.extend_from_slice(&self.height.to_le_bytes());
+        out.extend_from_slice(&(self.tx_ref.len() as u32).to_le_bytes());
+        out.extend_from_slice(self.tx_ref.as_bytes());
+        out
+    }
+
+    /// Adds a signature; used by dxid when it is the destination.
+    pub fn sign(&mut self, public_key: &[u8], secret_key: &[u8]) -> Result<(), InteropError> {
+        let sig = DefaultCryptoProvider::new()
+            .sign_message(secret_key, &self.signing_bytes())
+            .map_err(|e| InteropError::Receipt(e.to_string()))?;
+        self.signatures
+            .push(ReceiptSignature { public_key: hex::encode(public_key), signature: hex::encode(sig) });
+        Ok(())
+    }
+}
+
+/// Checks receipts against a destination chain's authority set.
+#[derive(Debug, Clone)]
+pub struct ReceiptVerifier {
+    authorities: HashSet<Vec<u8>>,
+    threshold: usize,
+}
+
+impl ReceiptVerifier {
+    pub fn new(authorities: &[String], threshold: usize) -> Result<Self, InteropError> {
+        let authorities = authorities
+            .iter()
+            .map(|k| hex::decode(k).map_err(|e| InteropError::Other(format!("bad authority key {k}: {e}"))))
+            .collect::<Result<HashSet<_>, _>>()?;
+        if threshold == 0 || threshold > authorities.len() {
+            return Err(InteropError::Other(format!(
+                "receipt threshold {threshold} invalid for {} authorities",
+                authorities.len()
+            )));
+        }
+        Ok(Self { authorities, threshold })
+    }
+
+    /// Checks only that a receipt answers the message, for chains that opt
+    /// out of authentication.
+    pub fn unauthenticated() -> Self {
+        Self { authorities: HashSet::new(), threshold: 0 }
+    }
+
+    /// The verifier configured for a chain. A chain listing no authorities
+    /// gets none, so its receipts are refused, unless it sets
+    /// `allow_unauthenticated_receipts`.
+    pub fn from_settings(settings: &ExternalChainSettings) -> Result<Option<Self>, InteropError> {
+        if !settings.receipt_authorities.is_empty() {
+            return Self::new(&settings.receipt_authorities, settings.receipt_threshold).map(Some);
+        }
+        if settings.allow_unauthenticated_receipts {
+            warn!("chain {} has no receipt authorities; deliveries are unauthenticated", settings.chain_id);
+            return Ok(Some(Self::unauthenticated()));
+        }
+        warn!(
+            "chain {} has no receipt authorities; its delivery receipts will be refused",
+            settings.chain_id
+        );
+        Ok(None)
+    }
+
+    /// Verifies that `receipt` answers `msg` and carries at least `threshold`
+    /// valid signatures from distinct authorities.
+    pub fn verify(&self, receipt: &SignedReceipt, msg: &CrossChainMessage) -> Result<(), InteropError> {
+        if receipt.message_id != msg.id || receipt.dest != msg.dest {
+            return Err(InteropError::Receipt(format!(
+                "receipt for {} on {} does not match message {} to {}",
+                receipt.message_id, receipt.dest, msg.id, msg.dest
+            )));
+        }
+        let crypto = DefaultCryptoProvider::new();
+        let payload = receipt.signing_bytes();
+        let mut signers = HashSet::new();
+        for sig in &receipt.signatures {
+            let (Ok(pk), Ok(bytes)) = (hex::decode(&sig.public_key), hex::decode(&sig.signature)) else {
+                continue;
+            };
+            if !self.authorities.contains(&pk) || signers.contains(&pk) {
+                continue;
+            }
+            if crypto.verify_signature(&pk, &payload, &bytes).unwrap_or(false) {
+                signers.insert(pk);
+            }
+        }
+        if signers.len() < self.threshold {
+            return Err(InteropError::Receipt(format!(
+                "receipt for {} has {} of {} required authority signatures",
+                msg.id,
+                signers.len(),
+                self.threshold
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dxid_crypto::generate_ed25519;
+
+    #[test]
+    fn threshold_of_distinct_authorities() {
+        let keys: Vec<_> = (0..3).map(|_| generate_ed25519()).collect();
+        let authorities: Vec<_> = keys.iter().map(|k| hex::encode(&k.public_key)).collect();
+        let verifier = ReceiptVerifier::new(&authorities, 2).unwrap();
+        let msg = CrossChainMessage {
+            id: Uuid::new_v4(),
+            source: "dxid".into(),
+            dest: "eth".into(),
+            payload: serde_json::json!({}),
+            nonce: 1,
+            timestamp: 0,
+        };
+        let mut receipt = SignedReceipt {
+            message_id: msg.id,
+            dest: "eth".into(),
+            accepted: true,
+            height: 9,
+            tx_ref: "0xfeed".into(),
+            signatures: vec![],
+        };
+        receipt.sign(&keys[0].public_key, &keys[0].secret_key).unwrap();
+        receipt.sign(&keys[0].public_key, &keys[0].secret_key).unwrap();
+        assert!(verifier.verify(&receipt, &msg).is_err());
+
+        receipt.sign(&keys[1].public_key, &keys[1].secret_key).unwrap();
+        verifier.verify(&receipt, &msg).unwrap();
+
+        let forged = SignedReceipt { height: 10, ..receipt };
+        assert!(verifier.verify(&forged, &msg).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use dxid_config::{AdapterKind, ExternalChainSettings, InteropConfig, ProofBackendKind};
+use dxid_core::ChainId;
+use dxid_crypto::{Groth16Backend, ZkSnarkBackend};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::bitcoin::{BitcoinConfig, BitcoinSpvAdapter};
+use crate::grpc::GrpcChainAdapter;
+use crate::headers::HeaderTrackers;
+use crate::{ChainAdapter, ExternalChainConfig, ExternalChainHandle, HttpJsonRpcAdapter, InteropError};
+
+/// A configured adapter together with the settings it was built from.
+#[derive(Clone)]
+pub struct RegisteredAdapter {
+    pub settings: ExternalChainSettings,
+    pub adapter: Arc<dyn ChainAdapter>,
+    pub snark: Arc<dyn ZkSnarkBackend>,
+}
+
+impl RegisteredAdapter {
+    pub fn external_config(&self) -> ExternalChainConfig {
+        ExternalChainConfig {
+            name: self.settings.chain_id.clone(),
+            rpc_endpoint: self.settings.endpoint.clone(),
+            metadata: self.settings.options.clone(),
+        }
+    }
+}
+
+/// Public view of a registered chain; credentials are never exposed.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainSummary {
+    pub chain_id: ChainId,
+    pub kind: AdapterKind,
+    pub endpoint: String,
+    pub proof_backend: ProofBackendKind,
+}
+
+/// Maps external chain ids to their configured adapter instances, and
+/// follows each chain's headers for the finality of its inbound events.
+#[derive(Default)]
+pub struct AdapterRegistry {
+    adapters: RwLock<HashMap<ChainId, RegisteredAdapter>>,
+    headers: HeaderTrackers,
+}
+
+impl AdapterRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(cfg: &InteropConfig) -> Result<Self, InteropError> {
+        let registry = Self::new();
+        let mut backends: HashMap<ProofBackendKind, Arc<dyn ZkSnarkBackend>> = HashMap::new();
+        for settings in &cfg.chains {
+            let snark = match backends.get(&settings.proof_backend) {
+                Some(backend) => backend.clone(),
+                None => {
+                    let backend = build_snark(settings.proof_backend)?;
+                    backends.insert(settings.proof_backend, backend.clone());
+                    backend
+                }
+            };
+            registry.register(settings.clone(), build_adapter(settings, snark.clone())?, snark);
+        }
+        Ok(registry)
+    }
+
+    pub fn register(
+        &self,
+        settings: ExternalChainSettings,
+        adapter: Arc<dyn ChainAdapter>,
+        snark: Arc<dyn ZkSnarkBackend>,
+    ) {
+        info!("registered {:?} adapter for chain {}", settings.kind, settings.chain_id);
+        let chain_id = settings.chain_id.clone();
+        self.headers.track(chain_id.clone(), settings.header_window, settings.confirmations);
+        self.adapters.write().unwrap().insert(chain_id, RegisteredAdapter { settings, adapter, snark });
+    }
+
+    pub fn remove(&self, chain_id: &str) -> bool {
+        self.headers.untrack(chain_id);
+        self.adapters.write().unwrap().remove(chain_id).is_some()
+    }
+
+    /// Header trackers of the registered chains, fed by the inbound pipeline.
+    pub fn headers(&self) -> &HeaderTrackers {
+        &self.headers
+    }
+
+    pub fn get(&self, chain_id: &str) -> Option<RegisteredAdapter> {
+        self.adapters.read().unwrap().get(chain_id).cloned()
+    }
+
+    pub fn adapter(&self, chain_id: &str) -> Result<Arc<dyn ChainAdapter>, InteropError> {
+        self.get(chain_id)
+            .map(|entry| entry.adapter)
+            .ok_or_else(|| InteropError::Other(format!("no adapter for chain {chain_id}")))
+    }
+
+    /// Handshakes with every registered chain. Identity or protocol mismatches
+    /// are fatal; unreachable chains are logged and skipped.
+    pub async fn connect_all(&self) -> Result<Vec<ExternalChainHandle>, InteropError> {
+        let entries: Vec<_> = self.adapters.read().unwrap().values().cloned().collect();
+        let mut handles = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match entry.adapter.connect(&entry.external_config()).await {
+                Ok(handle) => handles.push(handle),
+                Err(e @ InteropError::Handshake(_)) => return Err(e),
+                Err(e) => warn!("chain {} unreachable during handshake: {e}", entry.settings.chain_id),
+            }
+        }
+        Ok(handles)
+    }
+
+    pub fn chains(&self) -> Vec<ChainSummary> {
+        let map = self.adapters.read().unwrap();
+        let mut out: Vec<_> = map
+            .values()
+            .map(|entry| ChainSummary {
+                chain_id: entry.settings.chain_id.clone(),
+                kind: entry.settings.kind,
+                endpoint: entry.settings.endpoint.clone(),
+                proof_backend: entry.settings.proof_backend,
+            })
+            .collect();
+        out.sort_by(|a, b| a.chain_id.cmp(&b.chain_id));
+        out
+    }
+}
+
+pub fn build_snark(kind: ProofBackendKind) -> Result<Arc<dyn ZkSnarkBackend>, InteropError> {
+    match kind {
+        ProofBackendKind::Groth16 => {
+            Ok(Arc::new(Groth16Backend::new().map_err(|e| InteropError::Proof(e.to_string()))?))
+        }
+    }
+}
+
+pub fn build_adapter(
+    settings: &ExternalChainSettings,
+    snark: Arc<dyn ZkSnarkBackend>,
+) -> Result<Arc<dyn ChainAdapter>, InteropError> {
+    match settings.kind {
+        AdapterKind::JsonRpc => Ok(Arc::new(HttpJsonRpcAdapter::from_settings(settings, snark)?)),
+        AdapterKind::Grpc => Ok(Arc::new(GrpcChainAdapter::from_settings(settings, snark)?)),
+        AdapterKind::Bitcoin => {
+            let mut value = settings.options.clone();
+            value["endpoint"] = settings.endpoint.clone().into();
+            value["rpc_user"] = settings.username.clone().into();
+            value["rpc_password"] = settings.password.clone().into();
+            value["min_confirmations"] = settings.confirmations.into();
+            value["header_window"] = settings.header_window.into();
+            value["http"] = serde_json::to_value(&settings.http).unwrap_or_default();
+            let config: BitcoinConfig = serde_json::from_value(value).map_err(|e| {
+                InteropError::Other(format!("invalid bitcoin options for {}: {e}", settings.chain_id))
+            })?;
+            Ok(Arc::new(BitcoinSpvAdapter::new(config)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_adapters_from_config() {
+        let cfg: InteropConfig = serde_json::from_value(serde_json::json!({
+            "chains": [
+                { "chain_id": "eth", "kind": "json_rpc", "endpoint": "http://localhost:8545", "auth_token": "secret" },
+                {
+                    "chain_id": "bitcoin",
+                    "kind": "bitcoin",
+                    "endpoint": "127.0.0.1:50001",
+                    "options": {
+                        "backend": "electrum",
+                        "network": "testnet",
+                        "deposit_script": "51",
+                        "start_height": 0,
+                        "checkpoint_hash": "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+                        "checkpoint_bits": 486604799
+                    }
+                }
+            ]
+        }))
+        .unwrap();
+        let registry = AdapterRegistry::from_config(&cfg).unwrap();
+        let chains: Vec<_> = registry.chains().into_iter().map(|c| c.chain_id).collect();
+        assert_eq!(chains, vec!["bitcoin".to_string(), "eth".to_string()]);
+        assert!(registry.adapter("eth").is_ok());
+        assert!(registry.adapter("solana").is_err());
+    }
+}
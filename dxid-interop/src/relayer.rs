@@ -0,0 +1,704 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dxid_core::events::{ChainWaker, EventBus, NodeEvent};
+use dxid_core::{now_ts, Address, CrossChainMessage, CrossChainTx};
+use dxid_crypto::SnarkProof;
+use dxid_storage::{AuditEvent, AuditKind, BridgeMessageRecord, BridgeMessageStatus, BridgeStore};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::batch::{prove_batch, BatchProof, MessageInclusion};
+use crate::fees::{payload_size, BridgeFees, FeeQuote};
+use crate::metrics::InteropMetrics;
+use crate::policy::BridgePolicy;
+use crate::registry::AdapterRegistry;
+use crate::webhooks::Webhooks;
+use crate::{InteropError, TxReceipt};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayerConfig {
+    pub poll_interval_ms: u64,
+    /// Submission attempts before a message is marked failed.
+    pub max_attempts: u32,
+    pub base_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    pub required_confirmations: u64,
+    pub batch_size: i64,
+    /// Prove unproven messages to the same destination with one SNARK when the
+    /// destination accepts batch proofs.
+    pub batch_proofs: bool,
+    #[serde(default)]
+    pub fees: BridgeFees,
+    /// How long a submitted message may go unconfirmed before it counts as
+    /// a failed attempt and is submitted again.
+    #[serde(default = "default_submission_timeout_secs")]
+    pub submission_timeout_secs: u64,
+}
+
+fn default_submission_timeout_secs() -> u64 {
+    3_600
+}
+
+impl Default for RelayerConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 2_000,
+            max_attempts: 8,
+            base_backoff_secs: 2,
+            max_backoff_secs: 600,
+            required_confirmations: 6,
+            batch_size: 64,
+            batch_proofs: true,
+            fees: BridgeFees::default(),
+            submission_timeout_secs: default_submission_timeout_secs(),
+        }
+    }
+}
+
+impl RelayerConfig {
+    /// Exponential backoff after `attempts` failed submissions.
+    pub fn backoff_secs(&self, attempts: u32) -> u64 {
+        let shift = attempts.saturating_sub(1).min(32);
+        self.base_backoff_secs.saturating_mul(1u64 << shift).min(self.max_backoff_secs)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelayStats {
+    pub submitted: usize,
+    pub confirmed: usize,
+    pub retried: usize,
+    pub failed: usize,
+}
+
+/// Drains the outbound queue in the bridge store and drives each message to a
+/// terminal state on its destination chain.
+pub struct Relayer {
+    store: Arc<dyn BridgeStore>,
+    registry: Arc<AdapterRegistry>,
+    config: RelayerConfig,
+    webhooks: Option<Arc<Webhooks>>,
+    metrics: Arc<InteropMetrics>,
+    policy: Arc<BridgePolicy>,
+    /// Wakes the relaying task early when the chain moves.
+    chain_events: Option<EventBus>,
+}
+
+impl Relayer {
+    pub fn new(store: Arc<dyn BridgeStore>, registry: Arc<AdapterRegistry>, config: RelayerConfig) -> Self {
+        Self {
+            store,
+            registry,
+            config,
+            webhooks: None,
+            metrics: Arc::new(InteropMetrics::new()),
+            policy: Arc::new(BridgePolicy::default()),
+            chain_events: None,
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<InteropMetrics> {
+        self.metrics.clone()
+    }
+
+    pub fn policy(&self) -> Arc<BridgePolicy> {
+        self.policy.clone()
+    }
+
+    /// Enforces `policy` on enqueue and relay; the default allows everything.
+    pub fn with_policy(mut self, policy: Arc<BridgePolicy>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Reports status transitions to the configured webhook targets.
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Also runs a pass whenever `bus` announces the chain moved, besides
+    /// every `poll_interval_ms` for the other chains' confirmations, and
+    /// starts the policy's per-block count on every announced block.
+    pub fn with_chain_events(mut self, bus: EventBus) -> Self {
+        self.chain_events = Some(bus);
+        self
+    }
+
+    /// Queues `tx` under the next nonce for its destination and returns that
+    /// nonce. Caller-supplied nonces are ignored, and so are caller proofs,
+    /// which would no longer match. Re-queueing a known message id is a no-op.
+    /// Messages the bridge policy refuses are not queued.
+    pub async fn enqueue(&self, tx: CrossChainTx) -> Result<u64, InteropError> {
+        self.queue(tx, None).await
+    }
+
+    /// Like [`Relayer::enqueue`], also charging the message to the hourly
+    /// limit of `sender`, a dxid address that signed for it on chain.
+    pub async fn enqueue_from(&self, tx: CrossChainTx, sender: &Address) -> Result<u64, InteropError> {
+        self.queue(tx, Some(sender)).await
+    }
+
+    async fn queue(&self, mut tx: CrossChainTx, sender: Option<&Address>) -> Result<u64, InteropError> {
+        if let Some(existing) =
+            self.store.get_message(&tx.message.id).await.map_err(|e| InteropError::Other(e.to_string()))?
+        {
+            return Ok(existing.tx.message.nonce);
+        }
+        self.policy.admit(&tx, sender, now_ts())?;
+        if tx.proof.take().is_some() {
+            debug!("dropping caller proof for {}; it is re-proven under its queued nonce", tx.message.id);
+        }
+        let record = self
+            .store
+            .enqueue_message(&BridgeMessageRecord::new(tx, now_ts()))
+            .await
+            .map_err(|e| InteropError::Other(e.to_string()))?;
+        Ok(record.tx.message.nonce)
+    }
+
+    /// Destination gas for `dest_chain` plus the dxid bridge fee.
+    pub async fn quote_fee(&self, dest_chain: &str, payload_size: usize) -> Result<FeeQuote, InteropError> {
+        let entry = self
+            .registry
+            .get(dest_chain)
+            .ok_or_else(|| InteropError::Other(format!("no adapter for chain {dest_chain}")))?;
+        let gas = entry.adapter.quote_fee(dest_chain, payload_size).await?;
+        Ok(FeeQuote::new(dest_chain.into(), payload_size, gas, &self.config.fees))
+    }
+
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let interval = Duration::from_millis(self.config.poll_interval_ms);
+            let mut waker = self.chain_events.as_ref().map(|bus| ChainWaker::new(Some(bus), interval));
+            loop {
+                match self.tick().await {
+                    Ok(stats) if stats != RelayStats::default() => debug!("relayer tick {stats:?}"),
+                    Ok(_) => {}
+                    Err(e) => warn!("relayer tick failed: {e}"),
+                }
+                match &mut waker {
+                    Some(waker) => tokio::select! {
+                        _ = tokio::time::sleep(interval) => {}
+                        event = waker.wait() => {
+                            if let Some(NodeEvent::Block { block, .. }) = event {
+                                self.policy.begin_block(block.header.height);
+                            }
+                        }
+                    },
+                    None => tokio::time::sleep(interval).await,
+                }
+            }
+        })
+    }
+
+    /// Runs one pass: submits due pending messages, then polls confirmations
+    /// for submitted ones, returning those unconfirmed past
+    /// `submission_timeout_secs` to the queue.
+    pub async fn tick(&self) -> Result<RelayStats, InteropError> {
+        let mut stats = RelayStats::default();
+        let now = now_ts();
+        let mut in_flight: HashMap<String, u64> = HashMap::new();
+        let mut due = Vec::new();
+        let paused = self.policy.is_paused();
+        for record in self.load(BridgeMessageStatus::Pending).await? {
+            // A paused bridge keeps messages queued without spending attempts.
+            if record.next_attempt_at <= now && !paused {
+                due.push(record);
+            } else {
+                *in_flight.entry(record.tx.message.dest.clone()).or_default() += 1;
+            }
+        }
+        let batches = if self.config.batch_proofs { self.prove_batches(&due).await } else { HashMap::new() };
+        for mut record in due {
+            let before = record.status;
+            let dest = record.tx.message.dest.clone();
+            let started = Instant::now();
+            let result = self.submit(&record.tx, batches.get(&record.id())).await;
+            self.metrics.update(&dest, |m| {
+                m.attempts += 1;
+                m.failed_attempts += result.is_err() as u64;
+            });
+            match result {
+                Ok(receipt) => {
+                    info!("relayed message {} to {}", record.id(), record.tx.message.dest);
+                    let detail = serde_json::json!({
+                        "accepted": receipt.accepted,
+                        "height": receipt.signed.as_ref().map(|r| r.height),
+                        "tx_ref": receipt.signed.as_ref().map(|r| r.tx_ref.clone()),
+                    });
+                    self.audit(
+                        AuditKind::MessageSent,
+                        &dest,
+                        Some(record.id()),
+                        started,
+                        serde_json::json!({}),
+                    )
+                    .await;
+                    self.audit(AuditKind::ReceiptReceived, &dest, Some(record.id()), started, detail).await;
+                    self.metrics.update(&dest, |m| m.sent += 1);
+                    record.status = BridgeMessageStatus::Submitted;
+                    record.receipt = serde_json::to_value(&receipt).ok();
+                    record.last_error = None;
+                    stats.submitted += 1;
+                }
+                Err(e) => {
+                    record.attempts += 1;
+                    record.last_error = Some(e.to_string());
+                    let detail = serde_json::json!({ "error": e.to_string(), "attempt": record.attempts });
+                    self.audit(AuditKind::Failure, &dest, Some(record.id()), started, detail).await;
+                    if e.is_terminal() || record.attempts >= self.config.max_attempts {
+                        warn!("message {} failed permanently: {e}", record.id());
+                        record.status = BridgeMessageStatus::Failed;
+                        self.metrics.update(&dest, |m| m.failed += 1);
+                        stats.failed += 1;
+                    } else {
+                        record.next_attempt_at = now + self.config.backoff_secs(record.attempts);
+                        stats.retried += 1;
+                    }
+                }
+            }
+            // Submitted records are counted by the confirmation pass below.
+            if record.status == BridgeMessageStatus::Pending {
+                *in_flight.entry(dest).or_default() += 1;
+            }
+            record.updated_at = now;
+            self.save(&record).await?;
+            if record.status != before {
+                self.notify(&record).await;
+            }
+        }
+        for mut record in self.load(BridgeMessageStatus::Submitted).await? {
+            let dest = record.tx.message.dest.clone();
+            let started = Instant::now();
+            let receipt = record.receipt.clone().and_then(|r| serde_json::from_value::<TxReceipt>(r).ok());
+            let confirmations = match (receipt, self.registry.get(&dest)) {
+                (Some(receipt), Some(entry)) => match entry.adapter.confirmations(&receipt).await {
+                    Ok(n) => Some(n),
+                    Err(e) => {
+                        debug!("confirmation check for {} failed: {e}", record.id());
+                        None
+                    }
+                },
+                _ => None,
+            };
+            match confirmations {
+                Some(n) if n >= self.config.required_confirmations => {
+                    record.status = BridgeMessageStatus::Confirmed;
+                    record.updated_at = now;
+                    self.save(&record).await?;
+                    self.notify(&record).await;
+                    let latency =
+                        if record.created_at > 0 { now.saturating_sub(record.created_at) } else { 0 };
+                    self.metrics.update(&dest, |m| {
+                        m.confirmed += 1;
+                        m.confirmation_secs_total += latency;
+                    });
+                    let detail = serde_json::json!({ "confirmations": n, "latency_secs": latency });
+                    self.audit(AuditKind::Confirmed, &dest, Some(record.id()), started, detail).await;
+                    stats.confirmed += 1;
+                }
+                // Submitted records are saved again only once settled, so
+                // `updated_at` is when this one was submitted.
+                _ if now.saturating_sub(record.updated_at) >= self.config.submission_timeout_secs => {
+                    let error = format!(
+                        "not confirmed within {}s of submission",
+                        self.config.submission_timeout_secs
+                    );
+                    warn!("message {} to {dest} {error}", record.id());
+                    record.attempts += 1;
+                    record.receipt = None;
+                    record.last_error = Some(error.clone());
+                    let detail = serde_json::json!({ "error": error, "attempt": record.attempts });
+                    self.audit(AuditKind::Failure, &dest, Some(record.id()), started, detail).await;
+                    if record.attempts >= self.config.max_attempts {
+                        record.status = BridgeMessageStatus::Failed;
+                        self.metrics.update(&dest, |m| m.failed += 1);
+                        stats.failed += 1;
+                    } else {
+                        record.status = BridgeMessageStatus::Pending;
+                        record.next_attempt_at = now + self.config.backoff_secs(record.attempts);
+                        *in_flight.entry(dest).or_default() += 1;
+                        stats.retried += 1;
+                    }
+                    record.updated_at = now;
+                    self.save(&record).await?;
+                    self.notify(&record).await;
+                }
+                _ => *in_flight.entry(dest).or_default() += 1,
+            }
+        }
+        self.metrics.set_in_flight(&in_flight);
+        Ok(stats)
+    }
+
+    /// Groups unproven messages by destination and proves each group with a
+    /// single SNARK over its merkle root.
+    async fn prove_batches(
+        &self,
+        records: &[BridgeMessageRecord],
+    ) -> HashMap<Uuid, (Arc<BatchProof>, MessageInclusion)> {
+        let mut by_dest: HashMap<&str, Vec<CrossChainMessage>> = HashMap::new();
+        for record in records.iter().filter(|r| r.tx.proof.is_none()) {
+            by_dest.entry(&record.tx.message.dest).or_default().push(record.tx.message.clone());
+        }
+        let mut out = HashMap::new();
+        for (dest, msgs) in by_dest {
+            if msgs.len() < 2 {
+                continue;
+            }
+            let Some(entry) = self.registry.get(dest) else {
+                continue;
+            };
+            if !entry.adapter.supports_batch_proofs() {
+                continue;
+            }
+            let started = Instant::now();
+            match prove_batch(entry.snark.as_ref(), &msgs) {
+                Ok((batch, inclusions)) => {
+                    debug!("proved batch of {} messages to {dest}", msgs.len());
+                    self.proved(dest, None, started, serde_json::json!({ "batch_size": msgs.len() })).await;
+                    let batch = Arc::new(batch);
+                    for (msg, inclusion) in msgs.iter().zip(inclusions) {
+                        out.insert(msg.id, (batch.clone(), inclusion));
+                    }
+                }
+                Err(e) => warn!("batch proof for {dest} failed, proving individually: {e}"),
+            }
+        }
+        out
+    }
+
+    async fn submit(
+        &self,
+        tx: &CrossChainTx,
+        batched: Option<&(Arc<BatchProof>, MessageInclusion)>,
+    ) -> Result<TxReceipt, InteropError> {
+        let entry = self
+            .registry
+            .get(&tx.message.dest)
+            .ok_or_else(|| InteropError::Other(format!("no adapter for chain {}", tx.message.dest)))?;
+        // The allowlist may have shrunk since the message was queued.
+        self.policy.check_destination(&tx.message.dest)?;
+        // Quoted at relay time: destination gas may have moved since enqueue.
+        let quote = self.quote_fee(&tx.message.dest, payload_size(&tx.message)).await?;
+        if tx.fee < quote.total {
+            return Err(InteropError::InsufficientFee { required: quote.total, offered: tx.fee });
+        }
+        let receipt = match (&tx.proof, batched) {
+            (None, Some((batch, inclusion))) => {
+                entry.adapter.send_batched(batch, inclusion, &tx.message).await?
+            }
+            (Some(bytes), _) => {
+                let proof = serde_json::from_slice::<SnarkProof>(bytes)
+                    .map_err(|e| InteropError::Proof(format!("malformed proof: {e}")))?;
+                entry.adapter.send_message(&proof, &tx.message).await?
+            }
+            (None, None) => {
+                let started = Instant::now();
+                let proof =
+                    entry.snark.prove_message(&tx.message).map_err(|e| InteropError::Proof(e.to_string()))?;
+                self.proved(&tx.message.dest, Some(tx.message.id), started, serde_json::json!({})).await;
+                entry.adapter.send_message(&proof, &tx.message).await?
+            }
+        };
+        if !receipt.accepted {
+            return Err(InteropError::Other(format!("destination rejected message: {}", receipt.response)));
+        }
+        Ok(receipt)
+    }
+
+    async fn load(&self, status: BridgeMessageStatus) -> Result<Vec<BridgeMessageRecord>, InteropError> {
+        self.store
+            .list_messages(status, self.config.batch_size)
+            .await
+            .map_err(|e| InteropError::Other(e.to_string()))
+    }
+
+    async fn proved(
+        &self,
+        chain: &str,
+        message_id: Option<Uuid>,
+        started: Instant,
+        detail: serde_json::Value,
+    ) {
+        let ms = started.elapsed().as_millis() as u64;
+        self.metrics.update(chain, |m| {
+            m.proofs += 1;
+            m.proof_ms_total += ms;
+        });
+        self.audit(AuditKind::ProofGenerated, chain, message_id, started, detail).await;
+    }
+
+    /// Appends to the audit log; failures are logged rather than aborting
+    /// the relay pass.
+    async fn audit(
+        &self,
+        kind: AuditKind,
+        chain: &str,
+        message_id: Option<Uuid>,
+        started: Instant,
+        detail: serde_json::Value,
+    ) {
+        let at_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        let event = AuditEvent {
+            kind,
+            chain: chain.to_string(),
+            message_id,
+            at_ms,
+            duration_ms: started.elapsed().as_millis() as u64,
+            detail,
+        };
+        if let Err(e) = self.store.append_audit(&event).await {
+            warn!("could not append {} audit entry: {e}", kind.as_str());
+        }
+    }
+
+    async fn notify(&self, record: &BridgeMessageRecord) {
+        if let Some(webhooks) = &self.webhooks {
+            if let Err(e) = webhooks.notify(record).await {
+                warn!("could not queue webhooks for {}: {e}", record.id());
+            }
+        }
+    }
+
+    async fn save(&self, record: &BridgeMessageRecord) -> Result<(), InteropError> {
+        self.store.update_message(record).await.map_err(|e| InteropError::Other(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{registry_with, FlakyAdapter};
+    use dxid_config::BridgePolicySettings;
+    use dxid_core::{Block, BlockHeader, CrossChainMessage};
+    use dxid_storage::MemoryBridgeStore;
+    use uuid::Uuid;
+
+    fn outbound(dest: &str) -> CrossChainTx {
+        CrossChainTx {
+            message: CrossChainMessage {
+                id: Uuid::new_v4(),
+                source: "dxid".into(),
+                dest: dest.into(),
+                payload: serde_json::json!({}),
+                nonce: 1,
+                timestamp: 0,
+            },
+            fee: 10_000,
+            proof: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_then_confirms() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let adapter = Arc::new(FlakyAdapter::new(1));
+        let config = RelayerConfig { base_backoff_secs: 0, ..RelayerConfig::default() };
+        let relayer = Relayer::new(store.clone(), registry_with("eth", adapter), config);
+        let tx = outbound("eth");
+        let id = tx.message.id;
+        relayer.enqueue(tx).await.unwrap();
+
+        assert_eq!(relayer.tick().await.unwrap().retried, 1);
+        let stats = relayer.tick().await.unwrap();
+        assert_eq!((stats.submitted, stats.confirmed), (1, 1));
+        let record = store.get_message(&id).await.unwrap().unwrap();
+        assert_eq!(record.status, BridgeMessageStatus::Confirmed);
+    }
+
+    #[tokio::test]
+    async fn audits_and_meters_each_step() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let config = RelayerConfig { base_backoff_secs: 0, ..RelayerConfig::default() };
+        let relayer =
+            Relayer::new(store.clone(), registry_with("eth", Arc::new(FlakyAdapter::new(1))), config);
+        let tx = outbound("eth");
+        let id = tx.message.id;
+        relayer.enqueue(tx).await.unwrap();
+        relayer.tick().await.unwrap();
+        assert_eq!(relayer.metrics().get("eth").in_flight, 1);
+        relayer.tick().await.unwrap();
+
+        let kinds: Vec<_> = store.audit_log(&id).await.unwrap().into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                AuditKind::ProofGenerated,
+                AuditKind::Failure,
+                AuditKind::ProofGenerated,
+                AuditKind::MessageSent,
+                AuditKind::ReceiptReceived,
+                AuditKind::Confirmed,
+            ]
+        );
+        let metrics = relayer.metrics().snapshot();
+        assert_eq!(metrics.len(), 1);
+        let eth = &metrics[0];
+        assert_eq!((eth.counters.attempts, eth.counters.failed_attempts), (2, 1));
+        assert_eq!((eth.counters.sent, eth.counters.confirmed, eth.counters.in_flight), (1, 1, 0));
+        assert_eq!(eth.failure_rate, 0.5);
+    }
+
+    #[tokio::test]
+    async fn allocates_monotonic_nonces_per_destination() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let relayer = Relayer::new(store.clone(), Arc::new(AdapterRegistry::new()), RelayerConfig::default());
+        let first = outbound("eth");
+        assert_eq!(relayer.enqueue(first.clone()).await.unwrap(), 1);
+        assert_eq!(relayer.enqueue(outbound("eth")).await.unwrap(), 2);
+        assert_eq!(relayer.enqueue(outbound("sol")).await.unwrap(), 1);
+        assert_eq!(relayer.enqueue(first.clone()).await.unwrap(), 1);
+        let record = store.get_message(&first.message.id).await.unwrap().unwrap();
+        assert_eq!(record.tx.message.nonce, 1);
+        assert_eq!(relayer.enqueue(outbound("eth")).await.unwrap(), 3);
+
+        // Racing enqueues of one message draw one nonce.
+        let racing = outbound("eth");
+        let (a, b) = tokio::join!(relayer.enqueue(racing.clone()), relayer.enqueue(racing));
+        assert_eq!((a.unwrap(), b.unwrap()), (4, 4));
+        assert_eq!(relayer.enqueue(outbound("eth")).await.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn pause_holds_queued_messages_and_refuses_new_ones() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let relayer = Relayer::new(
+            store.clone(),
+            registry_with("eth", Arc::new(FlakyAdapter::new(0))),
+            RelayerConfig::default(),
+        );
+        let tx = outbound("eth");
+        let id = tx.message.id;
+        relayer.enqueue(tx).await.unwrap();
+
+        relayer.policy().set_paused(true);
+        assert!(matches!(relayer.enqueue(outbound("eth")).await, Err(InteropError::Policy(_))));
+        assert_eq!(relayer.tick().await.unwrap(), RelayStats::default());
+        let record = store.get_message(&id).await.unwrap().unwrap();
+        assert_eq!((record.status, record.attempts), (BridgeMessageStatus::Pending, 0));
+
+        relayer.policy().set_paused(false);
+        assert_eq!(relayer.tick().await.unwrap().submitted, 1);
+    }
+
+    #[tokio::test]
+    async fn block_events_start_a_new_policy_block() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let bus = EventBus::new(8);
+        let policy =
+            BridgePolicy::new(BridgePolicySettings { max_messages_per_block: 1, ..Default::default() });
+        let config = RelayerConfig { poll_interval_ms: 3_600_000, ..RelayerConfig::default() };
+        let relayer = Arc::new(
+            Relayer::new(store, Arc::new(AdapterRegistry::new()), config)
+                .with_policy(Arc::new(policy))
+                .with_chain_events(bus.clone()),
+        );
+        let task = relayer.clone().spawn();
+        relayer.enqueue(outbound("eth")).await.unwrap();
+        assert!(matches!(relayer.enqueue(outbound("eth")).await, Err(InteropError::Policy(_))));
+
+        let header = BlockHeader {
+            previous_hash: [0; 32],
+            merkle_root: [0; 32],
+            height: 1,
+            timestamp: 0,
+            difficulty: 0,
+            nonce: 0,
+            validator: [0; 32],
+            stake_weight: 0,
+            state_root: [0; 32],
+        };
+        let block = Block { header, transactions: vec![], pow_hash: [0; 32], validator_signature: vec![] };
+        let mut admitted = false;
+        for _ in 0..100 {
+            // Republished until the task has subscribed; one height resets once.
+            bus.publish(NodeEvent::Block { block: block.clone(), receipts: vec![] });
+            if relayer.enqueue(outbound("eth")).await.is_ok() {
+                admitted = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        task.abort();
+        assert!(admitted);
+    }
+
+    #[tokio::test]
+    async fn batches_messages_to_capable_destinations() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let adapter = Arc::new(FlakyAdapter::batching());
+        let relayer =
+            Relayer::new(store.clone(), registry_with("eth", adapter.clone()), RelayerConfig::default());
+        for _ in 0..3 {
+            relayer.enqueue(outbound("eth")).await.unwrap();
+        }
+        assert_eq!(relayer.tick().await.unwrap().submitted, 3);
+        assert_eq!(adapter.batched(), 3);
+    }
+
+    #[tokio::test]
+    async fn holds_messages_whose_fee_misses_the_quote() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let adapter = Arc::new(FlakyAdapter::with_gas(5_000));
+        let relayer = Relayer::new(store.clone(), registry_with("eth", adapter), RelayerConfig::default());
+        let mut tx = outbound("eth");
+        let quote = relayer.quote_fee("eth", payload_size(&tx.message)).await.unwrap();
+        assert_eq!(quote.gas, 5_000);
+        assert_eq!(quote.total, 5_000 + 100 + quote.payload_size as u64);
+
+        tx.fee = quote.total - 1;
+        let id = tx.message.id;
+        relayer.enqueue(tx).await.unwrap();
+        assert_eq!(relayer.tick().await.unwrap().retried, 1);
+        let record = store.get_message(&id).await.unwrap().unwrap();
+        assert_eq!(record.status, BridgeMessageStatus::Pending);
+        assert!(record.last_error.unwrap().contains("does not cover"));
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_submissions_are_retried_then_failed() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let config = RelayerConfig {
+            base_backoff_secs: 0,
+            max_attempts: 2,
+            required_confirmations: 100,
+            submission_timeout_secs: 0,
+            ..RelayerConfig::default()
+        };
+        let relayer =
+            Relayer::new(store.clone(), registry_with("eth", Arc::new(FlakyAdapter::new(0))), config);
+        let tx = outbound("eth");
+        let id = tx.message.id;
+        relayer.enqueue(tx).await.unwrap();
+
+        let stats = relayer.tick().await.unwrap();
+        assert_eq!((stats.submitted, stats.retried), (1, 1));
+        let record = store.get_message(&id).await.unwrap().unwrap();
+        assert_eq!((record.status, record.attempts), (BridgeMessageStatus::Pending, 1));
+        assert!(record.last_error.unwrap().contains("not confirmed"));
+        assert_eq!(relayer.tick().await.unwrap().failed, 1);
+        let record = store.get_message(&id).await.unwrap().unwrap();
+        assert_eq!((record.status, record.attempts), (BridgeMessageStatus::Failed, 2));
+    }
+
+    #[tokio::test]
+    async fn unknown_chain_exhausts_attempts() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let config = RelayerConfig { base_backoff_secs: 0, max_attempts: 2, ..RelayerConfig::default() };
+        let relayer = Relayer::new(store.clone(), Arc::new(AdapterRegistry::new()), config);
+        let tx = outbound("nowhere");
+        let id = tx.message.id;
+        relayer.enqueue(tx).await.unwrap();
+        relayer.tick().await.unwrap();
+        relayer.tick().await.unwrap();
+        let record = store.get_message(&id).await.unwrap().unwrap();
+        assert_eq!(record.status, BridgeMessageStatus::Failed);
+        assert_eq!(record.attempts, 2);
+    }
+}
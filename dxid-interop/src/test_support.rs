@@ -0,0 +1,150 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dxid_config::{AdapterKind, ExternalChainSettings};
+use dxid_core::CrossChainMessage;
+use dxid_crypto::{SnarkError, SnarkProof, ZkSnarkBackend};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::batch::{verify_batched, BatchProof, MessageInclusion};
+use crate::registry::AdapterRegistry;
+use crate::{
+    ChainAdapter, ExternalChainConfig, ExternalChainHandle, ExternalStateQuery, ExternalStateResponse,
+    InteropError, TxReceipt,
+};
+
+/// Accepts every proof; stands in for Groth16 where only control flow matters.
+pub struct NoopSnark;
+
+impl ZkSnarkBackend for NoopSnark {
+    fn prove_digest(&self, _digest: &[u8; 32]) -> Result<SnarkProof, SnarkError> {
+        Ok(SnarkProof { proof: vec![], public_inputs: vec![] })
+    }
+
+    fn verify_digest(&self, _proof: &SnarkProof, _digest: &[u8; 32]) -> Result<(), SnarkError> {
+        Ok(())
+    }
+}
+
+pub fn registry_with(chain: &str, adapter: Arc<dyn ChainAdapter>) -> Arc<AdapterRegistry> {
+    let registry = AdapterRegistry::new();
+    let settings = ExternalChainSettings {
+        chain_id: chain.into(),
+        kind: AdapterKind::JsonRpc,
+        endpoint: "http://localhost".into(),
+        auth_token: None,
+        username: None,
+        password: None,
+        proof_backend: Default::default(),
+        confirmations: 6,
+        header_window: 16,
+        http: Default::default(),
+        style: Default::default(),
+        headers: Default::default(),
+        methods: Default::default(),
+        receipt_authorities: vec![],
+        receipt_threshold: 1,
+        allow_unauthenticated_receipts: false,
+        options: serde_json::Value::Null,
+    };
+    registry.register(settings, adapter, Arc::new(NoopSnark));
+    Arc::new(registry)
+}
+
+/// Fails the first `failures` sends, then accepts.
+pub struct FlakyAdapter {
+    failures: u32,
+    calls: AtomicU32,
+    accepts_batches: bool,
+    batched: AtomicU32,
+    gas: u64,
+}
+
+impl FlakyAdapter {
+    pub fn new(failures: u32) -> Self {
+        Self {
+            failures,
+            calls: AtomicU32::new(0),
+            accepts_batches: false,
+            batched: AtomicU32::new(0),
+            gas: 0,
+        }
+    }
+
+    /// Never fails and quotes `gas` for every message.
+    pub fn with_gas(gas: u64) -> Self {
+        Self { gas, ..Self::new(0) }
+    }
+
+    /// Never fails and advertises batch proof support.
+    pub fn batching() -> Self {
+        Self { accepts_batches: true, ..Self::new(0) }
+    }
+
+    pub fn batched(&self) -> u32 {
+        self.batched.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl ChainAdapter for FlakyAdapter {
+    async fn connect(&self, _config: &ExternalChainConfig) -> Result<ExternalChainHandle, InteropError> {
+        Err(InteropError::Other("unused".into()))
+    }
+
+    async fn send_message(
+        &self,
+        _proof: &SnarkProof,
+        msg: &CrossChainMessage,
+    ) -> Result<TxReceipt, InteropError> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+            return Err(InteropError::Transport("connection reset".into()));
+        }
+        Ok(TxReceipt { id: msg.id, accepted: true, response: serde_json::json!({}), signed: None })
+    }
+
+    async fn quote_fee(&self, _dest_chain: &str, _payload_size: usize) -> Result<u64, InteropError> {
+        Ok(self.gas)
+    }
+
+    fn supports_batch_proofs(&self) -> bool {
+        self.accepts_batches
+    }
+
+    async fn send_batched(
+        &self,
+        batch: &BatchProof,
+        inclusion: &MessageInclusion,
+        msg: &CrossChainMessage,
+    ) -> Result<TxReceipt, InteropError> {
+        verify_batched(&NoopSnark, batch, msg, inclusion)?;
+        self.batched.fetch_add(1, Ordering::SeqCst);
+        self.send_message(&batch.proof, msg).await
+    }
+
+    async fn query_state(&self, _query: &ExternalStateQuery) -> Result<ExternalStateResponse, InteropError> {
+        Ok(ExternalStateResponse { result: serde_json::json!({ "confirmations": 10 }) })
+    }
+}
+
+/// Serves `body` as the JSON response to every HTTP request; returns the URL.
+pub async fn serve_json(body: serde_json::Value) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let body = body.to_string();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buf = vec![0u8; 64 * 1024];
+            let _ = stream.read(&mut buf).await;
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(reply.as_bytes()).await;
+        }
+    });
+    format!("http://{addr}")
+}
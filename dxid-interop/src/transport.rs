@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use dxid_config::{HttpPolicy, RpcStyle};
+use rand::Rng;
+use reqwest::{Client, Method};
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::InteropError;
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+/// Stops calling an endpoint after repeated failures, then lets a single probe
+/// through once the cooldown has passed.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self { threshold: threshold.max(1), cooldown, state: Mutex::new(BreakerState::default()) }
+    }
+
+    /// Whether a call may proceed. An expired open breaker admits one probe and
+    /// re-arms its cooldown so concurrent callers keep failing fast.
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.opened_at {
+            Some(at) if at.elapsed() < self.cooldown => false,
+            Some(_) => {
+                state.opened_at = Some(Instant::now());
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().opened_at.is_some()
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::default();
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures += 1;
+        if state.failures >= self.threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Applies an [`HttpPolicy`] to calls against a single endpoint.
+#[derive(Debug)]
+pub struct Endpoint {
+    name: String,
+    policy: HttpPolicy,
+    breaker: CircuitBreaker,
+}
+
+impl Endpoint {
+    pub fn new(name: String, policy: HttpPolicy) -> Self {
+        let breaker =
+            CircuitBreaker::new(policy.breaker_threshold, Duration::from_secs(policy.breaker_cooldown_secs));
+        Self { name, policy, breaker }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn policy(&self) -> &HttpPolicy {
+        &self.policy
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    /// Full-jitter exponential backoff before retry number `attempt` (1-based).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let shift = attempt.saturating_sub(1).min(16);
+        let cap = self.policy.base_backoff_ms.saturating_mul(1 << shift).min(self.policy.max_backoff_ms);
+        Duration::from_millis(rand::thread_rng().gen_range(0..=cap))
+    }
+
+    /// Runs `op` under the timeout, retrying retryable failures with backoff.
+    /// Only transport-level failures count against the breaker.
+    pub async fn call<T, F, Fut>(&self, mut op: F) -> Result<T, InteropError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, InteropError>>,
+    {
+        let timeout = Duration::from_millis(self.policy.timeout_ms);
+        let mut attempt = 0;
+        loop {
+            if !self.breaker.allow() {
+                return Err(InteropError::CircuitOpen(self.name.clone()));
+            }
+            let result = match tokio::time::timeout(timeout, op()).await {
+                Ok(result) => result,
+                Err(_) => Err(InteropError::Timeout(self.name.clone())),
+            };
+            let err = match result {
+                Ok(value) => {
+                    self.breaker.record_success();
+                    return Ok(value);
+                }
+                Err(err) if !err.is_retryable() => return Err(err),
+                Err(err) => err,
+            };
+            self.breaker.record_failure();
+            attempt += 1;
+            if attempt > self.policy.max_retries || self.breaker.is_open() {
+                warn!("{} failed after {attempt} attempts: {err}", self.name);
+                return Err(err);
+            }
+            let delay = self.backoff(attempt);
+            debug!("{} attempt {attempt} failed ({err}), retrying in {delay:?}", self.name);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RpcAuth {
+    None,
+    Bearer(String),
+    Basic(String, String),
+}
+
+/// JSON-over-HTTP client for one endpoint with timeouts, retries, and a
+/// circuit breaker.
+pub struct HttpRpcClient {
+    client: Client,
+    url: String,
+    auth: RpcAuth,
+    headers: Vec<(String, String)>,
+    style: RpcStyle,
+    methods: BTreeMap<String, String>,
+    next_id: AtomicU64,
+    endpoint: Endpoint,
+}
+
+impl HttpRpcClient {
+    pub fn new(url: String, auth: RpcAuth, policy: HttpPolicy) -> Self {
+        Self {
+            client: Client::new(),
+            endpoint: Endpoint::new(url.clone(), policy),
+            url,
+            auth,
+            headers: Vec::new(),
+            style: RpcStyle::JsonRpc,
+            methods: BTreeMap::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Sends `headers` with every request.
+    pub fn with_headers(mut self, headers: &BTreeMap<String, String>) -> Self {
+        self.headers = headers.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        self
+    }
+
+    /// Speaks `style`, renaming methods found in `methods`.
+    pub fn with_dialect(mut self, style: RpcStyle, methods: &BTreeMap<String, String>) -> Self {
+        self.style = style;
+        self.methods = methods.clone();
+        self
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// Calls `method` in the configured style and returns its result.
+    pub async fn call(&self, method: &str, params: &Value) -> Result<Value, InteropError> {
+        let remote = self.methods.get(method).map(String::as_str).unwrap_or(method);
+        match self.style {
+            RpcStyle::JsonRpc => {
+                let body = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+                    "method": remote,
+                    "params": params,
+                });
+                let mut resp = self.post_json(&body).await?;
+                let error = resp["error"].take();
+                if !error.is_null() {
+                    return Err(InteropError::Rpc {
+                        code: error["code"].as_i64().unwrap_or(0),
+                        message: error["message"].as_str().map_or_else(|| error.to_string(), String::from),
+                    });
+                }
+                resp.get_mut("result")
+                    .map(Value::take)
+                    .ok_or_else(|| InteropError::Decode(format!("{remote}: response has no result")))
+            }
+            RpcStyle::Rest => {
+                let (verb, path) = rest_route(remote, params);
+                let url = format!("{}{path}", self.url.trim_end_matches('/'));
+                let body = (verb != Method::GET).then_some(params);
+                self.endpoint.call(|| self.send_once(verb.clone(), &url, body)).await
+            }
+        }
+    }
+
+    pub async fn post_json(&self, body: &Value) -> Result<Value, InteropError> {
+        self.endpoint.call(|| self.send_once(Method::POST, &self.url, Some(body))).await
+    }
+
+    async fn send_once(&self, verb: Method, url: &str, body: Option<&Value>) -> Result<Value, InteropError> {
+        let mut req = self.client.request(verb, url);
+        if let Some(body) = body {
+            req = req.json(body);
+        }
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+        let req = match &self.auth {
+            RpcAuth::None => req,
+            RpcAuth::Bearer(token) => req.bearer_auth(token),
+            RpcAuth::Basic(user, pass) => req.basic_auth(user, Some(pass)),
+        };
+        let resp = req.send().await.map_err(|e| classify(url, e))?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(|e| classify(url, e))?;
+        let parsed = serde_json::from_str::<Value>(&text);
+        if !status.is_success() {
+            // JSON-RPC servers such as bitcoind report call errors with a 5xx
+            // status; those are answers, not transport failures.
+            if let Ok(value) = &parsed {
+                if !value["error"].is_null() {
+                    return Ok(value.clone());
+                }
+            }
+            return Err(InteropError::Status { status: status.as_u16(), body: text });
+        }
+        parsed.map_err(|e| InteropError::Decode(e.to_string()))
+    }
+}
+
+/// Resolves a REST route such as `"GET /messages/{id}"` against `params`.
+/// A bare name becomes `POST /<name>`; `{key}` segments are filled from
+/// top-level params.
+pub fn rest_route(route: &str, params: &Value) -> (Method, String) {
+    let (verb, path) = match route.split_once(' ') {
+        Some((verb, path)) => (Method::from_bytes(verb.as_bytes()).unwrap_or(Method::POST), path.trim()),
+        None => (Method::POST, route),
+    };
+    let mut path = if path.starts_with('/') { path.to_string() } else { format!("/{path}") };
+    if let Some(fields) = params.as_object() {
+        for (key, value) in fields {
+            let placeholder = format!("{{{key}}}");
+            if path.contains(&placeholder) {
+                let value = value.as_str().map_or_else(|| value.to_string(), String::from);
+                path = path.replace(&placeholder, &value);
+            }
+        }
+    }
+    (verb, path)
+}
+
+fn classify(url: &str, err: reqwest::Error) -> InteropError {
+    if err.is_timeout() {
+        InteropError::Timeout(url.to_string())
+    } else if err.is_decode() {
+        InteropError::Decode(err.to_string())
+    } else {
+        InteropError::Transport(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy() -> HttpPolicy {
+        HttpPolicy {
+            timeout_ms: 50,
+            max_retries: 2,
+            base_backoff_ms: 1,
+            max_backoff_ms: 2,
+            breaker_threshold: 4,
+            breaker_cooldown_secs: 60,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_retryable_errors_only() {
+        let endpoint = Endpoint::new("test".into(), policy());
+        let calls = AtomicU32::new(0);
+        let result = endpoint
+            .call(|| async {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(InteropError::Transport("reset".into()))
+                } else {
+                    Ok(7)
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<(), _> = endpoint
+            .call(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(InteropError::Status { status: 400, body: "bad request".into() })
+            })
+            .await;
+        assert!(matches!(result, Err(InteropError::Status { status: 400, .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn resolves_rest_routes() {
+        let params = serde_json::json!({ "id": "abc", "height": 7 });
+        assert_eq!(
+            rest_route("GET /messages/{id}/at/{height}", &params),
+            (Method::GET, "/messages/abc/at/7".into())
+        );
+        assert_eq!(rest_route("dxid_bridge", &params), (Method::POST, "/dxid_bridge".into()));
+    }
+
+    #[tokio::test]
+    async fn json_rpc_errors_are_surfaced() {
+        let url = crate::test_support::serve_json(serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32601, "message": "method not found" }
+        }))
+        .await;
+        let client = HttpRpcClient::new(url, RpcAuth::None, policy());
+        let err = client.call("dxid_handshake", &serde_json::json!({})).await.unwrap_err();
+        assert!(matches!(err, InteropError::Rpc { code: -32601, .. }));
+    }
+
+    #[tokio::test]
+    async fn breaker_opens_after_threshold() {
+        let endpoint = Endpoint::new("test".into(), policy());
+        let slow = || async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok::<_, InteropError>(())
+        };
+        assert!(matches!(endpoint.call(slow).await, Err(InteropError::Timeout(_))));
+        assert!(!endpoint.breaker().is_open());
+        assert!(matches!(endpoint.call(slow).await, Err(InteropError::Timeout(_))));
+        assert!(endpoint.breaker().is_open());
+        assert!(matches!(endpoint.call(slow).await, Err(InteropError::CircuitOpen(_))));
+    }
+}
@@ -0,0 +1,256 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use dxid_config::WebhookSettings;
+use dxid_core::now_ts;
+use dxid_storage::{BridgeMessageRecord, BridgeStore, WebhookDelivery, WebhookStatus};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::InteropError;
+
+pub const SIGNATURE_HEADER: &str = "x-dxid-signature";
+pub const TIMESTAMP_HEADER: &str = "x-dxid-timestamp";
+pub const DELIVERY_HEADER: &str = "x-dxid-delivery";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub poll_interval_ms: u64,
+    pub timeout_ms: u64,
+    /// Attempts before a delivery is marked failed.
+    pub max_attempts: u32,
+    pub base_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    pub batch_size: i64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 1_000,
+            timeout_ms: 5_000,
+            max_attempts: 6,
+            base_backoff_secs: 5,
+            max_backoff_secs: 3_600,
+            batch_size: 64,
+        }
+    }
+}
+
+/// `hex(HMAC-SHA256(secret, "<timestamp>.<body>"))`, sent as `sha256=<hex>`.
+pub fn sign_payload(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebhookStats {
+    pub delivered: usize,
+    pub retried: usize,
+    pub failed: usize,
+}
+
+/// Notifies configured endpoints of bridge message status changes and other
+/// node events, such as anomaly alerts. Calls are queued in the bridge
+/// store, so they survive restarts and leave a log.
+pub struct Webhooks {
+    store: Arc<dyn BridgeStore>,
+    targets: Vec<WebhookSettings>,
+    client: Client,
+    config: WebhookConfig,
+}
+
+impl Webhooks {
+    pub fn new(store: Arc<dyn BridgeStore>, targets: Vec<WebhookSettings>, config: WebhookConfig) -> Self {
+        Self { store, targets, client: Client::new(), config }
+    }
+
+    /// Queues one delivery per target subscribed to the record's new status.
+    pub async fn notify(&self, record: &BridgeMessageRecord) -> Result<(), InteropError> {
+        let status = record.status.as_str();
+        let event = serde_json::json!({
+            "message_id": record.id(),
+            "source": record.tx.message.source,
+            "dest": record.tx.message.dest,
+            "status": status,
+            "attempts": record.attempts,
+            "error": record.last_error,
+            "at": record.updated_at,
+        });
+        self.notify_event(record.id(), status, event).await
+    }
+
+    /// Queues `event` for every target subscribed to `name`, logged under
+    /// `subject` (a bridge message id, or an alert's own id).
+    pub async fn notify_event(
+        &self,
+        subject: Uuid,
+        name: &str,
+        event: serde_json::Value,
+    ) -> Result<(), InteropError> {
+        let now = now_ts();
+        for target in &self.targets {
+            if !target.events.is_empty() && !target.events.iter().any(|e| e == name) {
+                continue;
+            }
+            let delivery = WebhookDelivery::new(subject, target.url.clone(), event.clone(), now);
+            self.store.enqueue_webhook(&delivery).await.map_err(|e| InteropError::Other(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let interval = Duration::from_millis(self.config.poll_interval_ms);
+            loop {
+                match self.tick().await {
+                    Ok(stats) if stats != WebhookStats::default() => debug!("webhook tick {stats:?}"),
+                    Ok(_) => {}
+                    Err(e) => warn!("webhook tick failed: {e}"),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+
+    /// Sends every due pending delivery once.
+    pub async fn tick(&self) -> Result<WebhookStats, InteropError> {
+        let mut stats = WebhookStats::default();
+        let now = now_ts();
+        let due = self
+            .store
+            .list_webhooks(WebhookStatus::Pending, self.config.batch_size)
+            .await
+            .map_err(|e| InteropError::Other(e.to_string()))?;
+        for mut delivery in due.into_iter().filter(|d| d.next_attempt_at <= now) {
+            delivery.attempts += 1;
+            match self.send(&delivery).await {
+                Ok(status) => {
+                    delivery.status = WebhookStatus::Delivered;
+                    delivery.response_status = Some(status);
+                    delivery.last_error = None;
+                    stats.delivered += 1;
+                }
+                Err((status, err)) => {
+                    delivery.response_status = status;
+                    delivery.last_error = Some(err);
+                    if delivery.attempts >= self.config.max_attempts {
+                        warn!("webhook {} to {} failed permanently", delivery.id, delivery.url);
+                        delivery.status = WebhookStatus::Failed;
+                        stats.failed += 1;
+                    } else {
+                        delivery.next_attempt_at = now + self.backoff_secs(delivery.attempts);
+                        stats.retried += 1;
+                    }
+                }
+            }
+            delivery.updated_at = now;
+            self.store.update_webhook(&delivery).await.map_err(|e| InteropError::Other(e.to_string()))?;
+        }
+        Ok(stats)
+    }
+
+    fn backoff_secs(&self, attempts: u32) -> u64 {
+        let shift = attempts.saturating_sub(1).min(32);
+        self.config.base_backoff_secs.saturating_mul(1u64 << shift).min(self.config.max_backoff_secs)
+    }
+
+    async fn send(&self, delivery: &WebhookDelivery) -> Result<u16, (Option<u16>, String)> {
+        let Some(target) = self.targets.iter().find(|t| t.url == delivery.url) else {
+            return Err((None, "webhook target is no longer configured".into()));
+        };
+        let body = serde_json::to_vec(&delivery.event).map_err(|e| (None, e.to_string()))?;
+        let timestamp = now_ts();
+        let resp = self
+            .client
+            .post(&delivery.url)
+            .timeout(Duration::from_millis(self.config.timeout_ms))
+            .header("content-type", "application/json")
+            .header(SIGNATURE_HEADER, sign_payload(&target.secret, timestamp, &body))
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(DELIVERY_HEADER, delivery.id.to_string())
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| (None, e.to_string()))?;
+        let status = resp.status();
+        if status.is_success() {
+            Ok(status.as_u16())
+        } else {
+            Err((Some(status.as_u16()), format!("endpoint returned {status}")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::serve_json;
+    use dxid_core::{CrossChainMessage, CrossChainTx};
+    use dxid_storage::{BridgeMessageStatus, MemoryBridgeStore};
+
+    fn record(status: BridgeMessageStatus) -> BridgeMessageRecord {
+        let tx = CrossChainTx {
+            message: CrossChainMessage {
+                id: Uuid::new_v4(),
+                source: "dxid".into(),
+                dest: "eth".into(),
+                payload: serde_json::json!({}),
+                nonce: 1,
+                timestamp: 0,
+            },
+            fee: 0,
+            proof: None,
+        };
+        BridgeMessageRecord { status, ..BridgeMessageRecord::new(tx, 0) }
+    }
+
+    fn target(url: String, events: &[&str]) -> WebhookSettings {
+        WebhookSettings {
+            url,
+            secret: "s3cret".into(),
+            events: events.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_subscribed_events_and_retries_failures() {
+        let store = Arc::new(MemoryBridgeStore::new());
+        let up = serve_json(serde_json::json!({ "ok": true })).await;
+        let targets = vec![
+            target(up.clone(), &["confirmed"]),
+            target(up, &["failed"]),
+            target("http://127.0.0.1:1/hook".into(), &[]),
+        ];
+        let config = WebhookConfig { base_backoff_secs: 0, max_attempts: 2, ..WebhookConfig::default() };
+        let webhooks = Webhooks::new(store.clone(), targets, config);
+        let confirmed = record(BridgeMessageStatus::Confirmed);
+        webhooks.notify(&confirmed).await.unwrap();
+
+        let stats = webhooks.tick().await.unwrap();
+        assert_eq!(stats, WebhookStats { delivered: 1, retried: 1, failed: 0 });
+        let stats = webhooks.tick().await.unwrap();
+        assert_eq!(stats, WebhookStats { delivered: 0, retried: 0, failed: 1 });
+
+        let log = store.webhook_log(&confirmed.id()).await.unwrap();
+        assert_eq!(log.len(), 2);
+        assert!(log.iter().all(|d| d.event["status"] == "confirmed"));
+        assert_eq!(log.iter().filter(|d| d.status == WebhookStatus::Delivered).count(), 1);
+    }
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let sig = sign_payload("key", 10, b"{}");
+        assert!(sig.starts_with("sha256="));
+        assert_ne!(sig, sign_payload("key", 11, b"{}"));
+        assert_ne!(sig, sign_payload("other", 10, b"{}"));
+    }
+}
@@ -1,5 +1,6 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use dxid_core::events::{EventBus, NodeEvent};
 use dxid_core::{Block, Transaction};
 use futures::{channel::mpsc, prelude::*};
 use libp2p::gossipsub::{
@@ -9,16 +10,84 @@ use libp2p::identity::Keypair;
 use libp2p::swarm::{NetworkBehaviour, Swarm, SwarmBuilder, SwarmEvent};
 use libp2p::{identify, mdns, noise, tcp, yamux, Multiaddr, PeerId, Transport};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use crate::DxidBehaviourEvent;
 
+/// Gossiped messages buffered for each subscriber before it lags.
+const EVENT_CAPACITY: usize = 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkConfig {
     pub listen_addr: String,
     pub seed_nodes: Vec<String>,
+    /// The chain's network magic; it names the gossip topics, so peers of
+    /// other chains are not heard.
+    pub magic: u32,
+}
+
+/// A connected peer, as listed by the status API.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    /// Remote address of the first connection.
+    pub address: String,
+    /// Highest block height the peer has gossiped to us.
+    pub best_height: u64,
+}
+
+/// Live counters the swarm task keeps for the status API.
+#[derive(Debug, Default)]
+pub struct NetworkStats {
+    connected: Mutex<HashMap<PeerId, PeerInfo>>,
+    best_height: AtomicU64,
+}
+
+impl NetworkStats {
+    /// Peers with at least one open connection.
+    pub fn peers(&self) -> usize {
+        self.connected().len()
+    }
+
+    /// Connected peers ordered by id.
+    pub fn peer_list(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self.connected().values().cloned().collect();
+        peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        peers
+    }
+
+    /// Highest block height gossiped by any peer.
+    pub fn best_height(&self) -> u64 {
+        self.best_height.load(Ordering::Relaxed)
+    }
+
+    fn connected(&self) -> std::sync::MutexGuard<'_, HashMap<PeerId, PeerInfo>> {
+        self.connected.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn peer_connected(&self, peer: PeerId, address: &Multiaddr) {
+        self.connected().entry(peer).or_insert_with(|| PeerInfo {
+            peer_id: peer.to_string(),
+            address: address.to_string(),
+            best_height: 0,
+        });
+    }
+
+    fn peer_disconnected(&self, peer: &PeerId) {
+        self.connected().remove(peer);
+    }
+
+    fn saw_block(&self, from: &PeerId, height: u64) {
+        self.best_height.fetch_max(height, Ordering::Relaxed);
+        if let Some(peer) = self.connected().get_mut(from) {
+            peer.best_height = peer.best_height.max(height);
+        }
+    }
 }
 
 #[async_trait]
@@ -36,12 +105,49 @@ struct DxidBehaviour {
     mdns: mdns::tokio::Behaviour,
 }
 
+/// A block or transaction a peer gossiped, as the swarm task decoded it.
+#[derive(Debug, Clone)]
+pub enum NetworkEvent {
+    Block { from: PeerId, block: Block },
+    Transaction { from: PeerId, tx: Transaction },
+}
+
+/// Publishes blocks and transactions from any task, through the swarm once
+/// the network is started.
+#[derive(Clone)]
+pub struct Gossip {
+    outbound: mpsc::UnboundedSender<(Topic, Vec<u8>)>,
+    block_topic: Topic,
+    tx_topic: Topic,
+}
+
+impl Gossip {
+    pub fn block(&self, block: &Block) -> Result<()> {
+        self.publish(&self.block_topic, serde_json::to_vec(block)?)
+    }
+
+    pub fn transaction(&self, tx: &Transaction) -> Result<()> {
+        self.publish(&self.tx_topic, serde_json::to_vec(tx)?)
+    }
+
+    fn publish(&self, topic: &Topic, data: Vec<u8>) -> Result<()> {
+        self.outbound.unbounded_send((topic.clone(), data)).map_err(|_| anyhow!("network has stopped"))
+    }
+}
+
 pub struct Libp2pNetwork {
     swarm: Swarm<DxidBehaviour>,
     block_topic: Topic,
     tx_topic: Topic,
     peers: HashSet<PeerId>,
+    stats: Arc<NetworkStats>,
     handle: Option<JoinHandle<()>>,
+    gossip: Gossip,
+    events: broadcast::Sender<NetworkEvent>,
+    /// Node event bus peer changes are announced on, if any.
+    bus: Option<EventBus>,
+    /// Messages to publish, until `start` hands them to the swarm task.
+    outbound: Option<mpsc::UnboundedReceiver<(Topic, Vec<u8>)>>,
 }
 
 impl Libp2pNetwork {
@@ -94,14 +200,62 @@ impl Libp2pNetwork {
             }
         }
 
+        let block_topic = Topic::new(format!("dxid-{:08x}-blocks", config.magic));
+        let tx_topic = Topic::new(format!("dxid-{:08x}-transactions", config.magic));
+        let (sender, outbound) = mpsc::unbounded();
+        let gossip = Gossip {
+            outbound: sender,
+            block_topic: block_topic.clone(),
+            tx_topic: tx_topic.clone(),
+        };
         Ok(Self {
             swarm,
-            block_topic: Topic::new("dxid-blocks"),
-            tx_topic: Topic::new("dxid-transactions"),
+            block_topic,
+            tx_topic,
             peers: HashSet::new(),
+            stats: Arc::new(NetworkStats::default()),
             handle: None,
+            gossip,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            bus: None,
+            outbound: Some(outbound),
         })
     }
+
+    /// Announces peers connecting and disconnecting on `bus`.
+    pub fn with_events(mut self, bus: EventBus) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    pub fn stats(&self) -> Arc<NetworkStats> {
+        self.stats.clone()
+    }
+
+    /// Blocks and transactions gossiped from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<NetworkEvent> {
+        self.events.subscribe()
+    }
+
+    /// The channel gossiped blocks and transactions go out on, for tasks
+    /// that subscribe again when they restart.
+    pub fn events(&self) -> broadcast::Sender<NetworkEvent> {
+        self.events.clone()
+    }
+
+    /// Stops the swarm task [`NetworkService::start`] spawned, closing every
+    /// connection.
+    pub async fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+            let _ = handle.await;
+        }
+    }
+
+    /// Handle that keeps publishing after the network is moved into its task.
+    pub fn gossip(&self) -> Gossip {
+        self.gossip.clone()
+    }
 }
 
 #[async_trait]
@@ -118,9 +272,22 @@ impl NetworkService for Libp2pNetwork {
         let mut swarm = std::mem::replace(&mut self.swarm, build_empty_swarm()?);
         let block_topic = self.block_topic.clone();
         let tx_topic = self.tx_topic.clone();
+        let stats = self.stats.clone();
+        let events = self.events.clone();
+        let bus = self.bus.clone();
+        let mut outbound = self.outbound.take().ok_or_else(|| anyhow!("network already started"))?;
         self.handle = Some(tokio::spawn(async move {
             loop {
-                match swarm.select_next_some().await {
+                let event = tokio::select! {
+                    event = swarm.select_next_some() => event,
+                    Some((topic, data)) = outbound.next() => {
+                        if let Err(err) = swarm.behaviour_mut().gossipsub.publish(topic, data) {
+                            warn!("could not publish: {err}");
+                        }
+                        continue;
+                    }
+                };
+                match event {
                     SwarmEvent::Behaviour(DxidBehaviourEvent::Gossipsub(ev)) => match ev {
                         gossipsub::Event::Message {
                             propagation_source,
@@ -128,6 +295,19 @@ impl NetworkService for Libp2pNetwork {
                             message,
                         } => {
                             debug!("gossip from {propagation_source:?} id {message_id:?} len {}", message.data.len());
+                            // Sending fails only while nothing subscribes.
+                            if message.topic == block_topic.hash() {
+                                if let Ok(block) = serde_json::from_slice::<Block>(&message.data) {
+                                    stats.saw_block(&propagation_source, block.header.height);
+                                    let event = NetworkEvent::Block { from: propagation_source, block };
+                                    let _ = events.send(event);
+                                }
+                            } else if message.topic == tx_topic.hash() {
+                                if let Ok(tx) = serde_json::from_slice::<Transaction>(&message.data) {
+                                    let event = NetworkEvent::Transaction { from: propagation_source, tx };
+                                    let _ = events.send(event);
+                                }
+                            }
                         }
                         gossipsub::Event::Subscribed { peer_id, .. } => {
                             debug!("peer subscribed {peer_id}");
@@ -146,8 +326,19 @@ impl NetworkService for Libp2pNetwork {
                     SwarmEvent::NewListenAddr { address, .. } => {
                         info!("listening on {address}");
                     }
-                    SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                    SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. } => {
                         info!("peer connected {peer_id}");
+                        stats.peer_connected(peer_id, endpoint.get_remote_address());
+                        if let Some(bus) = bus.as_ref().filter(|_| num_established.get() == 1) {
+                            bus.publish(NodeEvent::Peer { peer: peer_id.to_string(), connected: true });
+                        }
+                    }
+                    SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                        info!("peer disconnected {peer_id}");
+                        stats.peer_disconnected(&peer_id);
+                        if let Some(bus) = &bus {
+                            bus.publish(NodeEvent::Peer { peer: peer_id.to_string(), connected: false });
+                        }
                     }
                     _ => {}
                 }
@@ -157,21 +348,11 @@ impl NetworkService for Libp2pNetwork {
     }
 
     async fn broadcast_block(&mut self, block: Block) -> Result<()> {
-        let data = serde_json::to_vec(&block)?;
-        self.swarm
-            .behaviour_mut()
-            .gossipsub
-            .publish(self.block_topic.clone(), data)?;
-        Ok(())
+        self.gossip.block(&block)
     }
 
     async fn broadcast_tx(&mut self, tx: Transaction) -> Result<()> {
-        let data = serde_json::to_vec(&tx)?;
-        self.swarm
-            .behaviour_mut()
-            .gossipsub
-            .publish(self.tx_topic.clone(), data)?;
-        Ok(())
+        self.gossip.transaction(&tx)
     }
 
     fn local_peer_id(&self) -> PeerId {
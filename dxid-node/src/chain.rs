@@ -0,0 +1,213 @@
+//! The node's view of the chain: the state after its tip and the recent
+//! blocks it could still reorg between. At start it is rebuilt from the
+//! genesis file by replaying the stored blocks, with contracts run in a
+//! runtime of its own so replaying does not touch the one serving RPC.
+//! Bridge releases are checked against the configured chains' proofs.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use dxid_contracts::{WasmEngine, WasmRuntime};
+use dxid_core::bridge::{parse_delivery, parse_release, InboundVerifier};
+use dxid_core::error::CoreError;
+use dxid_core::fork::{ChainTree, Reorg};
+use dxid_core::genesis::GenesisConfig;
+use dxid_core::state_tree::state_root;
+use dxid_core::{
+    check_signed_transaction, now_ts, Address, Block, BlockHash, BlockHeader, ChainState, CryptoProvider,
+    ExecutionEngine, TokenEconomics, Transaction, TxHash,
+};
+use dxid_crypto::DefaultCryptoProvider;
+use dxid_storage::{BlockStore, ChainStore, ChainUpdate, PgStore};
+use tracing::info;
+
+pub struct Chain {
+    pub state: ChainState,
+    tree: ChainTree,
+    crypto: Arc<DefaultCryptoProvider>,
+    contracts: WasmRuntime,
+    economics: TokenEconomics,
+    inbound: Arc<dyn InboundVerifier>,
+}
+
+impl Chain {
+    /// The chain `genesis` starts, with the blocks `store` holds applied.
+    /// An empty store gets the genesis block. `inbound` proves the burns
+    /// that release transactions claim.
+    pub async fn load(
+        store: &PgStore,
+        genesis: &GenesisConfig,
+        crypto: Arc<DefaultCryptoProvider>,
+        inbound: Arc<dyn InboundVerifier>,
+    ) -> Result<Self> {
+        let block = genesis.block()?;
+        let mut chain = Self {
+            state: genesis.initial_state()?,
+            tree: ChainTree::new(),
+            crypto,
+            contracts: WasmRuntime::new(WasmEngine::new()?),
+            economics: genesis.economics.clone(),
+            inbound,
+        };
+        let hash = chain.tree.start_at(chain.crypto.as_ref(), block.clone())?;
+        match store.get_block_by_height(0).await? {
+            Some(stored) if chain.crypto.hash_block_header(&stored.header) == hash => {}
+            Some(_) => bail!("the database holds a chain with another genesis block"),
+            None => chain.store_genesis(store, block).await?,
+        }
+        let best = store.best_height().await?.unwrap_or(0);
+        for height in 1..=best {
+            let block = store
+                .get_block_by_height(height as i64)
+                .await?
+                .ok_or_else(|| anyhow!("block {height} is missing from the database"))?;
+            if chain.apply(block)?.is_none() {
+                bail!("stored block {height} does not extend the chain");
+            }
+        }
+        info!("chain loaded at height {}", chain.state.tip_height);
+        Ok(chain)
+    }
+
+    async fn store_genesis(&self, store: &PgStore, block: Block) -> Result<()> {
+        let owners = self.state.staking.bonds.keys().map(|(owner, _)| *owner);
+        let update = ChainUpdate {
+            reverted: Vec::new(),
+            applied: vec![(block, Vec::new())],
+            balances: self.state.balances.iter().map(|(address, amount)| (*address, *amount)).collect(),
+            positions: owners.map(|owner| (owner, self.state.staking.position(&owner))).collect(),
+        };
+        store.commit_chain(&update).await
+    }
+
+    /// The last applied block.
+    pub fn tip(&self) -> &Block {
+        let hash = self.tree.tip().expect("the chain starts at genesis");
+        self.tree.get(&hash).expect("the tip is kept")
+    }
+
+    pub fn tip_hash(&self) -> BlockHash {
+        self.tree.tip().expect("the chain starts at genesis")
+    }
+
+    /// Height up to which the main chain can no longer be reorganized.
+    pub fn final_height(&self) -> u64 {
+        self.tree.final_height().expect("the chain starts at genesis")
+    }
+
+    /// A recent block of any branch.
+    pub fn block(&self, hash: &BlockHash) -> Option<&Block> {
+        self.tree.get(hash)
+    }
+
+    /// Total bonded to each validator after the tip.
+    pub fn stakes(&self) -> BTreeMap<Address, u64> {
+        self.state.staking.validator_stakes()
+    }
+
+    /// Checks `tx`, offered for the next block, like `POST /tx` does: its
+    /// signatures, conditions, locks and coinbase maturity against the state
+    /// after the tip. A bridge release or delivery is checked by the proof of
+    /// its message.
+    pub fn check_offered(&self, tx: &Transaction) -> Result<()> {
+        let bridged = match (parse_release(tx), parse_delivery(tx)) {
+            (Some(proven), _) => Some((proven, true)),
+            (None, Some(proven)) => Some((proven, false)),
+            (None, None) => None,
+        };
+        if let Some((proven, release)) = bridged {
+            let id = proven.message.id;
+            return match (self.inbound.verify_inbound(&proven.message, &proven.proof)?, release) {
+                (Some(_), false) => Err(anyhow!("burn {id} is only carried by a release")),
+                (None, true) => Err(anyhow!("message {id} is not a burn")),
+                _ => Ok(()),
+            };
+        }
+        if tx.inputs.is_empty() || tx.outputs.is_empty() {
+            bail!("transaction needs inputs and outputs");
+        }
+        check_signed_transaction(self.crypto.as_ref(), tx, &self.state)?;
+        Ok(())
+    }
+
+    /// A block `validator` builds on the tip from the `candidates` that
+    /// apply, in order, completed with its coinbase, and the hashes of those
+    /// that do not; just the coinbase if none apply. The header carries the
+    /// validator's stake after the tip as its weight. Contract calls run in
+    /// the node's runtime, which is put back after every trial.
+    pub fn assemble(&self, candidates: Vec<Transaction>, validator: Address) -> (Option<Block>, Vec<TxHash>) {
+        let engine = ExecutionEngine::new(self.crypto.as_ref(), self.economics.clone())
+            .with_contracts(&self.contracts)
+            .with_inbound(self.inbound.as_ref());
+        let header = BlockHeader {
+            previous_hash: self.tip_hash(),
+            merkle_root: [0; 32],
+            height: self.tip().header.height + 1,
+            timestamp: now_ts(),
+            difficulty: 0,
+            nonce: 0,
+            validator,
+            stake_weight: self.stakes().get(&validator).copied().unwrap_or(0),
+            state_root: state_root(&self.state),
+        };
+        let trial = |transactions: &[Transaction]| -> Result<Block, CoreError> {
+            let checkpoint = self.contracts.checkpoint();
+            let block = engine.complete_block(&self.state, header.clone(), transactions.to_vec());
+            self.contracts.restore(checkpoint);
+            block
+        };
+        if let Ok(block) = trial(&candidates) {
+            return (Some(block), Vec::new());
+        }
+        let mut accepted = Vec::new();
+        let mut rejected = Vec::new();
+        let mut block = None;
+        for tx in candidates {
+            accepted.push(tx);
+            match trial(&accepted) {
+                Ok(completed) => block = Some(completed),
+                Err(_) => rejected.push(accepted.pop().expect("just added").hash()),
+            }
+        }
+        if block.is_none() {
+            block = trial(&[]).ok();
+        }
+        (block, rejected)
+    }
+
+    /// Adds `block`, whose parent must be known, and moves the tip to the
+    /// heaviest chain. Returns how the main chain moved, if it did.
+    pub fn accept(&mut self, block: Block) -> Result<Option<ChainUpdate>> {
+        let Some(reorg) = self.apply(block)? else {
+            return Ok(None);
+        };
+        let mut balances: Vec<(Address, u64)> = reorg
+            .prior_balances
+            .iter()
+            .filter_map(|(address, before)| {
+                let amount = self.state.balances.get(address).copied().unwrap_or(0);
+                (before.unwrap_or(0) != amount).then_some((*address, amount))
+            })
+            .collect();
+        balances.sort_unstable();
+        let mut stakers: Vec<Address> = reorg.stakers.into_iter().collect();
+        stakers.sort_unstable();
+        let positions =
+            stakers.into_iter().map(|owner| (owner, self.state.staking.position(&owner))).collect();
+        Ok(Some(ChainUpdate { reverted: reorg.reverted, applied: reorg.applied, balances, positions }))
+    }
+
+    fn apply(&mut self, block: Block) -> Result<Option<Reorg>> {
+        let tip = self.tip_hash();
+        self.tree.insert(self.crypto.as_ref(), block)?;
+        let best = self.tree.best().expect("the tree holds the tip");
+        if best == tip {
+            return Ok(None);
+        }
+        let engine = ExecutionEngine::new(self.crypto.as_ref(), self.economics.clone())
+            .with_contracts(&self.contracts)
+            .with_inbound(self.inbound.as_ref());
+        engine.reorg_to(&mut self.state, &mut self.tree, &best).map(Some)
+    }
+}
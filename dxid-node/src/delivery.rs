@@ -0,0 +1,95 @@
+//! Puts verified inbound messages on chain. A burn becomes a release of
+//! escrowed outputs built against the tip, any other message a delivery;
+//! either is admitted to the mempool, stored as pending and gossiped like
+//! a transaction submitted over RPC. It is followed by its receipt, which
+//! the store keeps only while its block is on the main chain.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use dxid_core::bridge::{delivery_transaction, release_transaction, InboundVerifier, ProvenMessage};
+use dxid_core::events::{EventBus, NodeEvent};
+use dxid_core::mempool::{Mempool, MempoolError};
+use dxid_core::TxHash;
+use dxid_interop::inbound::{DeliverySink, DeliveryStatus};
+use dxid_interop::registry::AdapterRegistry;
+use dxid_interop::InteropError;
+use dxid_network::Gossip;
+use dxid_storage::{PgStore, ReceiptStore, TxStore};
+use tracing::warn;
+
+use crate::sync::ChainSync;
+
+pub struct ChainDelivery {
+    sync: Arc<ChainSync>,
+    registry: Arc<AdapterRegistry>,
+    mempool: Arc<Mutex<Mempool>>,
+    store: Arc<PgStore>,
+    events: EventBus,
+    gossip: Gossip,
+}
+
+impl ChainDelivery {
+    pub fn new(
+        sync: Arc<ChainSync>,
+        registry: Arc<AdapterRegistry>,
+        mempool: Arc<Mutex<Mempool>>,
+        store: Arc<PgStore>,
+        events: EventBus,
+        gossip: Gossip,
+    ) -> Self {
+        Self { sync, registry, mempool, store, events, gossip }
+    }
+}
+
+#[async_trait]
+impl DeliverySink for ChainDelivery {
+    async fn submit_delivery(&self, proven: ProvenMessage) -> Result<TxHash, InteropError> {
+        let release = self
+            .registry
+            .verify_inbound(&proven.message, &proven.proof)
+            .map_err(|e| InteropError::Proof(format!("{e:#}")))?;
+        let (tx, admitted) = {
+            let chain = self.sync.chain().lock().await;
+            let tx = match &release {
+                Some(release) => release_transaction(&chain.state, &proven, release)
+                    .map_err(|e| InteropError::Other(format!("{e:#}")))?,
+                None => delivery_transaction(&proven),
+            };
+            let admitted = self.mempool.lock().unwrap().admit(tx.clone(), &chain.state);
+            (tx, admitted)
+        };
+        let admitted = match admitted {
+            Ok(admitted) => admitted,
+            // Gossiped by a peer that delivered it first.
+            Err(MempoolError::AlreadyKnown) => return Ok(tx.hash()),
+            Err(e) => return Err(InteropError::Other(e.to_string())),
+        };
+        self.store.insert_pending_tx(&tx).await.map_err(|e| InteropError::Other(e.to_string()))?;
+        if !admitted.evicted.is_empty() {
+            if let Err(err) = self.store.remove_pending_txs(&admitted.evicted).await {
+                warn!("could not drop evicted transactions: {err:#}");
+            }
+        }
+        if let Err(err) = self.gossip.transaction(&tx) {
+            warn!("could not gossip delivery of {}: {err:#}", proven.message.id);
+        }
+        self.events.publish(NodeEvent::Transaction { hash: admitted.hash, tx });
+        Ok(admitted.hash)
+    }
+    async fn delivery_status(&self, hash: &TxHash) -> Result<DeliveryStatus, InteropError> {
+        // Chain sync holds the chain while it commits a block and settles the
+        // mempool, so a transaction moving between the two is seen in one.
+        let chain = self.sync.chain().lock().await;
+        if self.mempool.lock().unwrap().contains(hash) {
+            return Ok(DeliveryStatus::Pending);
+        }
+        let receipt = self.store.get_receipt(hash).await.map_err(|e| InteropError::Other(e.to_string()))?;
+        Ok(match receipt {
+            Some(receipt) => {
+                DeliveryStatus::Included { confirmations: chain.state.tip_height - receipt.block_height }
+            }
+            None => DeliveryStatus::Missing,
+        })
+    }
+}
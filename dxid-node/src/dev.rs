@@ -0,0 +1,91 @@
+//! Single-node dev chains, for `--dev`. The node runs the devnet profile as
+//! its only validator, produces a block as soon as a transaction arrives,
+//! and starts from a genesis prefunding accounts whose keys derive from
+//! fixed seeds, so they are the same on every machine.
+
+use anyhow::{Context, Result};
+use dxid_config::DxidConfig;
+use dxid_core::genesis::{GenesisAllocation, GenesisConfig, GenesisValidator};
+use dxid_core::{now_ts, Address, CryptoProvider, HalvingSchedule, TokenEconomics};
+use dxid_crypto::{address_to_string, ed25519_from_secret, DefaultCryptoProvider};
+use tracing::info;
+
+/// Prefunded accounts; the first is also the validator.
+pub const DEV_ACCOUNTS: usize = 10;
+/// What each account holds at genesis.
+pub const DEV_BALANCE: u64 = 10_000_000_000;
+/// What the validator bonds at genesis.
+pub const DEV_STAKE: u64 = 1_000_000_000;
+
+pub struct DevAccount {
+    pub secret: [u8; 32],
+    pub address: Address,
+}
+
+/// The prefunded accounts, in order.
+pub fn accounts() -> Result<Vec<DevAccount>> {
+    let crypto = DefaultCryptoProvider::new();
+    (0..DEV_ACCOUNTS)
+        .map(|index| {
+            let secret: [u8; 32] = blake3::hash(format!("dxid/dev/account/{index}").as_bytes()).into();
+            let key = ed25519_from_secret(&secret);
+            Ok(DevAccount { secret, address: crypto.address_from_public_key(&key.public_key)? })
+        })
+        .collect()
+}
+
+/// Makes `cfg` run a dev chain: no seed nodes, the genesis in the data
+/// directory, and `validator` producing instantly.
+pub fn configure(cfg: &mut DxidConfig, validator: &DevAccount) {
+    cfg.network.seed_nodes.clear();
+    cfg.consensus.genesis = Some(cfg.chain.genesis_path().display().to_string());
+    let producer = &mut cfg.consensus.producer;
+    producer.validator_key = Some(hex::encode(validator.secret));
+    producer.instant = true;
+}
+
+/// Writes the dev genesis to the data directory unless a previous start did.
+pub fn write_genesis(cfg: &DxidConfig, accounts: &[DevAccount]) -> Result<()> {
+    let path = cfg.chain.genesis_path();
+    if path.exists() {
+        return Ok(());
+    }
+    let consensus = &cfg.consensus;
+    let genesis = GenesisConfig {
+        chain_id: cfg.chain.chain_id.clone(),
+        timestamp: now_ts(),
+        economics: TokenEconomics {
+            max_supply: consensus.max_supply,
+            base_reward: consensus.base_reward,
+            schedule: HalvingSchedule {
+                target_interval: consensus.halving_interval,
+                supply_threshold: consensus.max_supply / 2,
+            },
+            treasury_ratio_bps: 0,
+            treasury: None,
+        },
+        allocations: accounts
+            .iter()
+            .map(|account| GenesisAllocation { address: account.address, amount: DEV_BALANCE })
+            .collect(),
+        validators: vec![GenesisValidator { address: accounts[0].address, stake: DEV_STAKE }],
+    };
+    genesis.validate()?;
+    std::fs::create_dir_all(&cfg.chain.data_dir)
+        .with_context(|| format!("creating the data directory {:?}", cfg.chain.data_dir))?;
+    std::fs::write(&path, serde_json::to_vec_pretty(&genesis)?)
+        .with_context(|| format!("writing the dev genesis {path:?}"))?;
+    info!("wrote the dev genesis to {path:?}");
+    Ok(())
+}
+
+/// Logs each account's address and secret, for importing into a wallet.
+pub fn announce(accounts: &[DevAccount]) {
+    for (index, account) in accounts.iter().enumerate() {
+        info!(
+            "dev account {index}: {} secret {}",
+            address_to_string(&account.address),
+            hex::encode(account.secret)
+        );
+    }
+}
@@ -1,26 +1,134 @@
+mod chain;
+mod delivery;
+mod dev;
+mod lifecycle;
+mod producer;
+mod reload;
+mod sync;
+
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use anyhow::Result;
-use dxid_ai_hypervisor::Hypervisor;
-use dxid_config::DxidConfig;
+use anyhow::{anyhow, bail, Context, Result};
+use dxid_ai_hypervisor::dedup::DEDUP_NAMESPACE;
+use dxid_ai_hypervisor::{
+    build_llm, Analytics, AnomalyMonitor, AuditLog, ChainIndexer, ChainTools, ContextBuilder, DedupService,
+    HybridSearch, Hypervisor, OfflineAnswers, Sessions, ToolRunner,
+};
+use dxid_config::{DxidConfig, NetworkProfile};
 use dxid_consensus::{ConsensusConfig, HybridConsensus};
-use dxid_core::{ChainState, TokenEconomics};
+use dxid_contracts::{WasmEngine, WasmRuntime};
+use dxid_core::bridge::BridgeLedger;
+use dxid_core::events::{ChainWaker, EventBus};
+use dxid_core::genesis::GenesisConfig;
+use dxid_core::mempool::{Mempool, MempoolConfig};
+use dxid_core::now_ts;
 use dxid_crypto::DefaultCryptoProvider;
+use dxid_interop::bridge::AssetBridge;
+use dxid_interop::inbound::{InboundConfig, InboundPipeline};
+use dxid_interop::policy::BridgePolicy;
+use dxid_interop::registry::AdapterRegistry;
+use dxid_interop::relayer::{Relayer, RelayerConfig};
+use dxid_interop::webhooks::{WebhookConfig, Webhooks};
 use dxid_network::{Libp2pNetwork, NetworkConfig as P2pConfig, NetworkService};
-use dxid_rpc::start_servers;
-use dxid_storage::PgStore;
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
-
-pub async fn run_node(config_path: PathBuf) -> Result<()> {
-    let cfg = DxidConfig::load(&config_path)?;
-    init_logging();
-    info!("starting dxid node with config {:?}", config_path);
+use dxid_rpc::{start_servers, RateLimiter, RpcState, SyncStatus};
+use dxid_storage::{negotiate_dimensions, PgStore, TxStore};
+use dxid_vectors::{build_embedder, Embedder};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+use crate::chain::Chain;
+use crate::delivery::ChainDelivery;
+use crate::lifecycle::{Lifecycle, Shutdown, Stage};
+use crate::producer::BlockProducer;
+use crate::reload::{LogHandle, Reloadable};
+use crate::sync::ChainSync;
+
+/// How often transactions that waited too long are dropped from the mempool.
+const MEMPOOL_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often bridge locks are relayed once block events stop arriving.
+const LOCK_RELAY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What the node is started with.
+#[derive(Debug, Clone, Default)]
+pub struct NodeOptions {
+    /// The config file; without one every value is the profile's default.
+    pub config: Option<PathBuf>,
+    /// Overrides the file's `chain.profile`.
+    pub profile: Option<NetworkProfile>,
+    /// Runs a single-node devnet with instant blocks and prefunded accounts.
+    pub dev: bool,
+}
+
+impl NodeOptions {
+    /// The validated config the node runs with.
+    pub fn load(&self) -> Result<DxidConfig> {
+        if self.dev && self.profile.is_some_and(|profile| profile != NetworkProfile::Devnet) {
+            bail!("--dev runs the devnet profile, not {}", self.profile.unwrap_or_default());
+        }
+        let profile = if self.dev { Some(NetworkProfile::Devnet) } else { self.profile };
+        let mut cfg = DxidConfig::load_as(self.config.as_deref(), profile)?;
+        if self.dev {
+            dev::configure(&mut cfg, &dev::accounts()?[0]);
+        }
+        cfg.validate()?;
+        Ok(cfg)
+    }
+}
+
+pub async fn run_node(options: NodeOptions) -> Result<()> {
+    let cfg = options.load()?;
+    let log = init_logging(&cfg.log.level)?;
+    info!("starting dxid {} node with config {:?}", cfg.chain.profile, options.config);
+    if options.dev {
+        let accounts = dev::accounts()?;
+        dev::write_genesis(&cfg, &accounts)?;
+        dev::announce(&accounts);
+    }
+    let mut lifecycle = Lifecycle::new(cfg.lifecycle.clone());
+    let events = EventBus::default();
     let store = Arc::new(PgStore::connect(&cfg.db.url, cfg.db.pool_size).await?);
-    let hypervisor = Arc::new(Hypervisor::new(cfg.ai.clone(), store.clone()));
+    let db = store.clone();
+    lifecycle.on_stop(Stage::Storage, "database", async move {
+        db.close().await;
+        Ok(())
+    });
+    let embedder: Option<Arc<dyn Embedder>> = if cfg.ai.dedup.enabled || cfg.ai.chain_index.enabled {
+        let mut embedder = build_embedder(&cfg.ai.embeddings, &cfg.ai.openai_api_key)?;
+        negotiate_dimensions(store.as_ref(), embedder.as_mut()).await?;
+        info!("embedding with {}", embedder.model());
+        Some(Arc::from(embedder))
+    } else {
+        None
+    };
+    let dedup = match &embedder {
+        Some(embedder) if cfg.ai.dedup.enabled => {
+            Some(Arc::new(DedupService::new(embedder.clone(), store.clone(), cfg.ai.dedup.clone())))
+        }
+        _ => None,
+    };
+    let indexer = match &embedder {
+        Some(embedder) if cfg.ai.chain_index.enabled => Some(Arc::new(
+            ChainIndexer::new(store.clone(), store.clone(), embedder.clone(), cfg.ai.chain_index.clone())
+                .with_chain_events(events.clone()),
+        )),
+        _ => None,
+    };
+    if let Some(indexer) = &indexer {
+        lifecycle.abort_on_stop(Stage::Services, "chain indexer", indexer.clone().spawn());
+    }
+    let search = embedder.clone().map(|embedder| {
+        Arc::new(HybridSearch::new(embedder, store.clone(), store.clone(), cfg.ai.ranking.clone()))
+    });
+    let contracts = Arc::new(WasmRuntime::load(WasmEngine::new()?, store.as_ref()).await?);
+    info!("loaded {} deployed contracts", contracts.addresses().len());
     let crypto = Arc::new(DefaultCryptoProvider::new());
-    let _consensus = Arc::new(HybridConsensus::new(
+    let consensus = Arc::new(HybridConsensus::new(
         crypto.clone(),
         ConsensusConfig {
             pow_target_spacing: 30,
@@ -33,20 +141,330 @@ pub async fn run_node(config_path: PathBuf) -> Result<()> {
     let mut network = Libp2pNetwork::new(P2pConfig {
         listen_addr: cfg.network.listen_addr.clone(),
         seed_nodes: cfg.network.seed_nodes.clone(),
-    })?;
-    let network_task = tokio::spawn(async move { network.start().await });
+        magic: cfg.chain.magic,
+    })?
+    .with_events(events.clone());
+    let network_stats = network.stats();
+    let gossip = network.gossip();
+    let network_events = network.events();
+    network.start().await?;
+    lifecycle.on_stop(Stage::Network, "network", async move {
+        network.stop().await;
+        Ok(())
+    });
+
+    let peers = network_stats.clone();
+    let mut context = ContextBuilder::new(
+        store.clone(),
+        store.clone(),
+        store.clone(),
+        store.clone(),
+        cfg.ai.context.clone(),
+    )
+    .with_peer_count(Arc::new(move || peers.peers()));
+    if let Some(embedder) = embedder {
+        let namespaces = vec![cfg.ai.chain_index.namespace.clone(), DEDUP_NAMESPACE.to_string()];
+        context = context.with_retrieval(embedder, store.clone(), namespaces);
+    }
+    let mut hypervisor = match build_llm(&cfg.ai) {
+        Ok(llm) => Hypervisor::with_provider(llm, context),
+        Err(err) if cfg.ai.offline.enabled => {
+            warn!("no AI provider, answering in offline mode: {err:#}");
+            Hypervisor::without_provider(context)
+        }
+        Err(err) => return Err(err),
+    }
+    .with_sessions(Sessions::new(store.clone(), cfg.ai.sessions.clone()));
+    if cfg.ai.offline.enabled {
+        let offline = OfflineAnswers::new(
+            store.clone(),
+            store.clone(),
+            store.clone(),
+            store.clone(),
+            cfg.ai.offline.clone(),
+        );
+        hypervisor = hypervisor.with_offline(offline);
+    }
+    if cfg.ai.analytics.enabled {
+        let analytics = Analytics::new(store.clone(), store.clone(), cfg.ai.analytics.clone());
+        hypervisor = hypervisor.with_analytics(analytics);
+    }
+    let audit = if cfg.ai.audit.enabled {
+        Some(Arc::new(AuditLog::new(store.clone(), cfg.ai.audit.clone(), cfg.secrets())))
+    } else {
+        None
+    };
+    if let Some(audit) = &audit {
+        hypervisor = hypervisor.with_audit(audit.clone());
+    }
+    if let Some(audit) = audit {
+        lifecycle.abort_on_stop(Stage::Services, "AI audit log", audit.spawn());
+    }
+    if cfg.ai.tools.enabled {
+        let mut tools = ChainTools::new(store.clone(), store.clone(), store.clone(), store.clone());
+        if let Some(search) = &search {
+            tools = tools.with_search(search.clone());
+        }
+        hypervisor = hypervisor.with_tools(ToolRunner::new(tools, cfg.ai.tools.clone()));
+    }
+    let hypervisor = Arc::new(hypervisor);
+    let anomalies = if cfg.ai.anomaly.enabled {
+        let mut monitor =
+            AnomalyMonitor::new(store.clone(), cfg.ai.anomaly.clone()).with_chain_events(events.clone());
+        if cfg.ai.anomaly.summarize {
+            match build_llm(&cfg.ai) {
+                Ok(llm) => monitor = monitor.with_summaries(llm),
+                Err(err) => warn!("anomaly alerts will not be summarized: {err:#}"),
+            }
+        }
+        Some(Arc::new(monitor))
+    } else {
+        None
+    };
+    if let Some(monitor) = &anomalies {
+        lifecycle.abort_on_stop(Stage::Services, "anomaly monitor", monitor.clone().spawn());
+    }
 
-    let rpc_task = tokio::spawn(start_servers(&cfg, store.clone(), hypervisor.clone()));
+    let bridge = Arc::new(AdapterRegistry::from_config(&cfg.interop)?);
+    bridge.connect_all().await?;
+    let mut relayer = Relayer::new(store.clone(), bridge.clone(), RelayerConfig::default())
+        .with_policy(Arc::new(BridgePolicy::new(cfg.interop.policy.clone())))
+        .with_chain_events(events.clone());
+    if !cfg.interop.webhooks.is_empty() {
+        let webhooks =
+            Arc::new(Webhooks::new(store.clone(), cfg.interop.webhooks.clone(), WebhookConfig::default()));
+        lifecycle.abort_on_stop(Stage::Services, "webhooks", webhooks.clone().spawn());
+        if let Some(monitor) = &anomalies {
+            lifecycle.abort_on_stop(
+                Stage::Services,
+                "anomaly webhooks",
+                forward_alerts(monitor, webhooks.clone()),
+            );
+        }
+        relayer = relayer.with_webhooks(webhooks);
+    }
+    let relayer = Arc::new(relayer);
+    lifecycle.abort_on_stop(Stage::Services, "relayer", relayer.clone().spawn());
 
-    // Join tasks
-    network_task.await??;
-    rpc_task.await??;
+    let rate_limit = Arc::new(RateLimiter::new(cfg.api.rate_limit.clone()));
+    let reloadable = Reloadable { log, rate_limit: rate_limit.clone(), hypervisor: hypervisor.clone() };
+    if let Some(path) = options.config.clone() {
+        let reload = reloadable.spawn(path, options, cfg.clone());
+        lifecycle.abort_on_stop(Stage::Services, "config reload", reload);
+    }
+
+    let rpc_state = RpcState {
+        store: store.clone(),
+        hypervisor: hypervisor.clone(),
+        bridge: bridge.clone(),
+        bridge_metrics: relayer.metrics(),
+        network: network_stats,
+        contracts: contracts.clone(),
+        dedup,
+        indexer,
+        search,
+        anomalies,
+        rate_limit,
+        mempool: Arc::new(Mutex::new(Mempool::new(MempoolConfig::default()))),
+        sync: Arc::new(SyncStatus::default()),
+        events: events.clone(),
+        gossip: gossip.clone(),
+    };
+    info!("{} transactions waiting in the mempool", rpc_state.restore_mempool().await?);
+    let (mempool, mempool_store) = (rpc_state.mempool.clone(), store.clone());
+    lifecycle.supervise(Stage::Chain, "mempool", move |shutdown| {
+        expire_mempool(mempool.clone(), mempool_store.clone(), shutdown)
+    });
+    lifecycle.on_stop(Stage::Chain, "mempool flush", flush_mempool(rpc_state.mempool.clone(), store.clone()));
+    let producing = cfg.consensus.producer.validator_key.is_some();
+    // Without consensus.genesis, the profile's genesis in the data directory if there is one.
+    let genesis_path = match &cfg.consensus.genesis {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(cfg.chain.genesis_path()).filter(|path| path.exists()),
+    };
+    match genesis_path {
+        Some(path) => {
+            let genesis =
+                std::fs::read(&path).with_context(|| format!("reading the genesis file {path:?}"))?;
+            let genesis: GenesisConfig = serde_json::from_slice(&genesis)?;
+            if genesis.chain_id != cfg.chain.chain_id {
+                bail!("{path:?} is the genesis of {}, not {}", genesis.chain_id, cfg.chain.chain_id);
+            }
+            let chain = Chain::load(store.as_ref(), &genesis, crypto.clone(), bridge.clone()).await?;
+            let sync = Arc::new(ChainSync::new(
+                chain,
+                consensus.clone(),
+                store.clone(),
+                rpc_state.mempool.clone(),
+                rpc_state.sync.clone(),
+                events.clone(),
+                cfg.consensus.producer.block_interval_secs,
+            ));
+            let assets = Arc::new(AssetBridge::new(bridge.clone(), relayer.clone()));
+            let (relaying, relay_events) = (sync.clone(), events.clone());
+            lifecycle.supervise(Stage::Services, "lock relay", move |shutdown| {
+                relay_locks(relaying.clone(), assets.clone(), relay_events.clone(), shutdown)
+            });
+            let delivery = ChainDelivery::new(
+                sync.clone(),
+                bridge.clone(),
+                rpc_state.mempool.clone(),
+                store.clone(),
+                events.clone(),
+                gossip.clone(),
+            );
+            let inbound = InboundPipeline::new(
+                store.clone(),
+                bridge.clone(),
+                Arc::new(delivery),
+                InboundConfig::default(),
+            );
+            lifecycle.abort_on_stop(Stage::Services, "inbound pipeline", Arc::new(inbound).spawn());
+            if producing {
+                let producer = Arc::new(
+                    BlockProducer::new(
+                        sync.clone(),
+                        consensus.clone(),
+                        crypto.clone(),
+                        store.clone(),
+                        rpc_state.mempool.clone(),
+                        gossip,
+                        cfg.consensus.producer.clone(),
+                    )?
+                    .with_arrivals(events),
+                );
+                info!("producing blocks as validator {}", hex::encode(producer.validator()));
+                lifecycle.supervise(Stage::Producer, "producer", move |shutdown| {
+                    let producer = producer.clone();
+                    async move {
+                        producer.run(shutdown).await;
+                        Ok(())
+                    }
+                });
+            }
+            lifecycle.supervise(Stage::Chain, "sync", move |shutdown| {
+                let (sync, events) = (sync.clone(), network_events.subscribe());
+                async move {
+                    sync.run(events, shutdown).await;
+                    Ok(())
+                }
+            });
+        }
+        None if producing => {
+            return Err(anyhow!("producing blocks needs consensus.genesis or {:?}", cfg.chain.genesis_path()))
+        }
+        None => warn!("there is no genesis file, so gossiped blocks are not followed"),
+    }
+    let rpc_cfg = cfg.clone();
+    lifecycle.supervise(Stage::Rpc, "rpc", move |shutdown| {
+        let (cfg, state) = (rpc_cfg.clone(), rpc_state.clone());
+        async move { start_servers(&cfg, state, shutdown.receiver()).await }
+    });
+
+    lifecycle.run().await
+}
+
+/// Drops transactions that have waited too long, from the mempool and the
+/// store, until `shutdown`.
+async fn expire_mempool(
+    mempool: Arc<Mutex<Mempool>>,
+    store: Arc<PgStore>,
+    mut shutdown: Shutdown,
+) -> Result<()> {
+    let mut interval = tokio::time::interval(MEMPOOL_EXPIRY_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.wait() => return Ok(()),
+        }
+        let expired = mempool.lock().unwrap().expire(now_ts());
+        if expired.is_empty() {
+            continue;
+        }
+        info!("{} transactions expired from the mempool", expired.len());
+        if let Err(err) = store.remove_pending_txs(&expired).await {
+            warn!("could not drop expired transactions: {err:#}");
+        }
+    }
+}
+
+/// Queues a mint for the locks of each block the chain applies once it is
+/// final, until `shutdown`: a reorg drops the locks of the blocks it
+/// reverts, so their mints are never queued. Locks that could not be queued
+/// wait for the next block.
+async fn relay_locks(
+    sync: Arc<ChainSync>,
+    bridge: Arc<AssetBridge>,
+    events: EventBus,
+    mut shutdown: Shutdown,
+) -> Result<()> {
+    let mut waker = ChainWaker::new(Some(&events), LOCK_RELAY_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = waker.wait() => {}
+            _ = shutdown.wait() => return Ok(()),
+        }
+        let (outbox, height) = {
+            let mut chain = sync.chain().lock().await;
+            let height = chain.final_height();
+            (chain.state.bridge.take_final(height), height)
+        };
+        if outbox.is_empty() {
+            continue;
+        }
+        // The chain is not held while mints are quoted and queued.
+        let mut ledger = BridgeLedger { outbox, ..BridgeLedger::default() };
+        match bridge.relay_locks(&mut ledger, height).await {
+            Ok(queued) => info!("queued mints for {queued} bridge locks"),
+            Err(err) => {
+                warn!("could not relay bridge locks: {err}");
+                let mut chain = sync.chain().lock().await;
+                ledger.outbox.append(&mut chain.state.bridge.outbox);
+                chain.state.bridge.outbox = ledger.outbox;
+            }
+        }
+    }
+}
+
+/// Stores every transaction the mempool holds, so the next start finds
+/// them all in `pending_txs`.
+async fn flush_mempool(mempool: Arc<Mutex<Mempool>>, store: Arc<PgStore>) -> Result<()> {
+    let transactions: Vec<_> = mempool.lock().unwrap().transactions().cloned().collect();
+    let mut stored = 0;
+    for tx in &transactions {
+        if store.insert_pending_tx(tx).await? {
+            stored += 1;
+        }
+    }
+    info!("{} transactions left in the mempool, {stored} of them newly stored", transactions.len());
     Ok(())
 }
 
-fn init_logging() {
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
-        .finish();
+/// Queues an `anomaly` webhook for every alert the monitor raises.
+fn forward_alerts(monitor: &AnomalyMonitor, webhooks: Arc<Webhooks>) -> JoinHandle<()> {
+    let mut alerts = monitor.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let alert = match alerts.recv().await {
+                Ok(alert) => alert,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("anomaly webhooks missed {missed} alerts");
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            let event = serde_json::json!({ "event": "anomaly", "alert": alert });
+            if let Err(err) = webhooks.notify_event(alert.id, "anomaly", event).await {
+                warn!("could not queue webhooks for alert {}: {err}", alert.id);
+            }
+        }
+    })
+}
+
+/// Logs at `level`, returning the handle a reload changes it through.
+fn init_logging(level: &str) -> Result<LogHandle> {
+    let (filter, handle) = tracing_subscriber::reload::Layer::new(EnvFilter::try_new(level)?);
+    let subscriber = tracing_subscriber::registry().with(filter).with(tracing_subscriber::fmt::layer());
     let _ = tracing::subscriber::set_global_default(subscriber);
+    Ok(handle)
 }
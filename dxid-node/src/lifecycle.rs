@@ -0,0 +1,227 @@
+//! Runs the node's components under supervision and stops them in order.
+//! A component whose task panics is started again as its
+//! [`RestartPolicy`] allows; one that fails, or ends without being told
+//! to, shuts the node down. So do SIGINT and SIGTERM. Shutdown goes
+//! through the [`Stage`]s in order: each stage's components are told to
+//! stop and given `shutdown_timeout_secs` before they are aborted, and its
+//! hooks run after them.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use dxid_config::{LifecycleConfig, RestartPolicy};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// Delay before a component's first restart; it doubles with each one.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// Steps of the shutdown, in the order they are taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Block production, so no block is left half made.
+    Producer,
+    /// The REST and gRPC servers, which finish the requests in flight.
+    Rpc,
+    /// Chain sync and the mempool; the state is committed block by block,
+    /// so this waits out the import in progress and stores the mempool.
+    Chain,
+    /// AI and bridge services.
+    Services,
+    Network,
+    /// The database pool, once nothing uses it.
+    Storage,
+}
+
+/// Tells a component when to stop.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Resolves once the component's stage stops.
+    pub async fn wait(&mut self) {
+        let _ = self.0.wait_for(|stop| *stop).await;
+    }
+
+    pub fn is_set(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// The signal as a channel that turns true, for crates that take one.
+    pub fn receiver(&self) -> watch::Receiver<bool> {
+        self.0.clone()
+    }
+}
+
+type Hook = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+struct StageTasks {
+    stop: watch::Sender<bool>,
+    supervisors: Vec<JoinHandle<()>>,
+    hooks: Vec<(&'static str, Hook)>,
+}
+
+pub struct Lifecycle {
+    config: LifecycleConfig,
+    stages: BTreeMap<Stage, StageTasks>,
+    failures: mpsc::UnboundedSender<String>,
+    failed: mpsc::UnboundedReceiver<String>,
+}
+
+impl Lifecycle {
+    pub fn new(config: LifecycleConfig) -> Self {
+        let (failures, failed) = mpsc::unbounded_channel();
+        Self { config, stages: BTreeMap::new(), failures, failed }
+    }
+
+    /// Runs the task `start` makes until `stage` stops, making a new one
+    /// when it panics if the policy for `name` allows.
+    pub fn supervise<F, Fut>(&mut self, stage: Stage, name: &'static str, start: F)
+    where
+        F: Fn(Shutdown) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let policy = self.config.policy(name);
+        let max_restarts = self.config.max_restarts;
+        let grace = Duration::from_secs(self.config.shutdown_timeout_secs);
+        let failures = self.failures.clone();
+        let tasks = self.stage(stage);
+        let mut shutdown = Shutdown(tasks.stop.subscribe());
+        let supervisor = tokio::spawn(async move {
+            let mut restarts = 0;
+            loop {
+                let mut task = tokio::spawn(start(shutdown.clone()));
+                let result = tokio::select! {
+                    result = &mut task => result,
+                    _ = shutdown.wait() => match tokio::time::timeout(grace, &mut task).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            warn!("{name} did not stop within {grace:?}, aborting it");
+                            task.abort();
+                            return;
+                        }
+                    },
+                };
+                if shutdown.is_set() {
+                    match result {
+                        Ok(Ok(())) => debug!("{name} stopped"),
+                        Ok(Err(err)) => warn!("{name} failed while stopping: {err:#}"),
+                        Err(err) => warn!("{name} failed while stopping: {err}"),
+                    }
+                    return;
+                }
+                let restartable = policy == RestartPolicy::OnPanic && restarts < max_restarts;
+                let failure = match result {
+                    Err(err) if err.is_panic() && restartable => {
+                        let delay = (RESTART_DELAY * 2u32.saturating_pow(restarts)).min(MAX_RESTART_DELAY);
+                        restarts += 1;
+                        warn!("{name} panicked, restarting it in {delay:?} ({restarts} of {max_restarts})");
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => continue,
+                            _ = shutdown.wait() => return,
+                        }
+                    }
+                    Err(err) if err.is_panic() => format!("{name} panicked"),
+                    Err(err) => format!("{name} was cancelled: {err}"),
+                    Ok(Err(err)) => format!("{name} failed: {err:#}"),
+                    Ok(Ok(())) => format!("{name} stopped on its own"),
+                };
+                let _ = failures.send(failure);
+                return;
+            }
+        });
+        tasks.supervisors.push(supervisor);
+    }
+
+    /// Runs `hook` when `stage` stops, after its components have.
+    pub fn on_stop(
+        &mut self,
+        stage: Stage,
+        name: &'static str,
+        hook: impl Future<Output = Result<()>> + Send + 'static,
+    ) {
+        self.stage(stage).hooks.push((name, Box::pin(hook)));
+    }
+
+    /// Aborts `task`, which stops only with the node, when `stage` stops.
+    pub fn abort_on_stop(&mut self, stage: Stage, name: &'static str, task: JoinHandle<()>) {
+        self.on_stop(stage, name, async move {
+            task.abort();
+            let _ = task.await;
+            Ok(())
+        });
+    }
+
+    /// Waits for SIGINT, SIGTERM or a component to fail, and shuts the
+    /// node down. Returns the failure, if one stopped the node.
+    pub async fn run(mut self) -> Result<()> {
+        let failure = tokio::select! {
+            signal = shutdown_signal() => {
+                info!("received {signal}, shutting down");
+                None
+            }
+            Some(failure) = self.failed.recv() => {
+                error!("{failure}, shutting down");
+                Some(failure)
+            }
+        };
+        self.shutdown().await;
+        failure.map_or(Ok(()), |failure| Err(anyhow!(failure)))
+    }
+
+    async fn shutdown(self) {
+        let timeout = Duration::from_secs(self.config.shutdown_timeout_secs);
+        for (stage, tasks) in self.stages {
+            debug!("stopping {stage:?}");
+            let _ = tasks.stop.send(true);
+            for supervisor in tasks.supervisors {
+                let _ = supervisor.await;
+            }
+            for (name, hook) in tasks.hooks {
+                match tokio::time::timeout(timeout, hook).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => warn!("{name} did not stop cleanly: {err:#}"),
+                    Err(_) => warn!("{name} did not stop within {timeout:?}"),
+                }
+            }
+        }
+        info!("node stopped");
+    }
+
+    fn stage(&mut self, stage: Stage) -> &mut StageTasks {
+        self.stages.entry(stage).or_insert_with(|| StageTasks {
+            stop: watch::channel(false).0,
+            supervisors: Vec::new(),
+            hooks: Vec::new(),
+        })
+    }
+}
+
+/// Resolves with the name of the first SIGINT or SIGTERM.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+                _ = terminate.recv() => "SIGTERM",
+            },
+            Err(err) => {
+                warn!("cannot listen for SIGTERM: {err}");
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
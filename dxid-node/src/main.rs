@@ -1,10 +1,40 @@
-use dxid_node::run_node;
+use dxid_node::{run_node, NodeOptions};
 use std::path::PathBuf;
 
+const USAGE: &str = "usage: dxid-node [--config <file>] [--profile mainnet|testnet|devnet] [--dev]";
+
 #[tokio::main]
 async fn main() {
-    let path = std::env::var("DXID_CONFIG").unwrap_or_else(|_| "config/dxid.toml".to_string());
-    if let Err(e) = run_node(PathBuf::from(path)).await {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = run_node(options).await {
         eprintln!("node failed: {e:?}");
     }
 }
+
+/// Without `--config` the file is `DXID_CONFIG`, then `config/dxid.toml`;
+/// a dev chain runs without one.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<NodeOptions, String> {
+    let mut options = NodeOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => options.config = Some(args.next().ok_or("--config needs a file")?.into()),
+            "--profile" => options.profile = Some(args.next().ok_or("--profile needs a name")?.parse()?),
+            "--dev" => options.dev = true,
+            _ => return Err(format!("unknown argument {arg}")),
+        }
+    }
+    if options.config.is_none() {
+        options.config = match std::env::var("DXID_CONFIG") {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) if options.dev => None,
+            Err(_) => Some(PathBuf::from("config/dxid.toml")),
+        };
+    }
+    Ok(options)
+}
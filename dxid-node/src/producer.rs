@@ -0,0 +1,158 @@
+//! Produces blocks when this node's validator is elected to propose the
+//! next one. The mempool's best transactions that apply on the tip are
+//! mined into a block after the coinbase paying this validator, which is
+//! imported like a gossiped one and sent to peers. With nothing to include
+//! the block is just the coinbase, so rewards keep maturing and rounds keep
+//! moving, unless `empty_blocks` is off. With `instant` set it also tries
+//! as soon as a transaction arrives.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use dxid_config::ProducerConfig;
+use dxid_consensus::{elect_proposer, ConsensusEngine, HybridConsensus};
+use dxid_core::events::{EventBus, NodeEvent};
+use dxid_core::mempool::Mempool;
+use dxid_core::{now_ts, Address, CryptoProvider};
+use dxid_crypto::{ed25519_from_secret, DefaultCryptoProvider, KeyMaterial};
+use dxid_network::Gossip;
+use dxid_storage::{PgStore, TxStore};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::chain::Chain;
+use crate::lifecycle::Shutdown;
+use crate::sync::ChainSync;
+
+pub struct BlockProducer {
+    sync: Arc<ChainSync>,
+    consensus: Arc<HybridConsensus<DefaultCryptoProvider>>,
+    store: Arc<PgStore>,
+    mempool: Arc<Mutex<Mempool>>,
+    gossip: Gossip,
+    key: KeyMaterial,
+    validator: Address,
+    config: ProducerConfig,
+    arrivals: Option<EventBus>,
+}
+
+impl BlockProducer {
+    /// A producer signing with `config.validator_key`.
+    pub fn new(
+        sync: Arc<ChainSync>,
+        consensus: Arc<HybridConsensus<DefaultCryptoProvider>>,
+        crypto: Arc<DefaultCryptoProvider>,
+        store: Arc<PgStore>,
+        mempool: Arc<Mutex<Mempool>>,
+        gossip: Gossip,
+        config: ProducerConfig,
+    ) -> Result<Self> {
+        let secret = config.validator_key.as_deref().ok_or_else(|| anyhow!("no validator key"))?;
+        let secret: [u8; 32] =
+            hex::decode(secret)?.try_into().map_err(|_| anyhow!("the validator key is not 32 bytes"))?;
+        let key = ed25519_from_secret(&secret);
+        let validator = crypto.address_from_public_key(&key.public_key)?;
+        Ok(Self { sync, consensus, store, mempool, gossip, key, validator, config, arrivals: None })
+    }
+
+    /// Where the transaction arrivals `config.instant` waits for are published.
+    pub fn with_arrivals(mut self, events: EventBus) -> Self {
+        self.arrivals = Some(events);
+        self
+    }
+
+    pub fn validator(&self) -> Address {
+        self.validator
+    }
+
+    /// Checks every `block_interval_secs`, and on each arrival if instant,
+    /// whether it is this validator's turn, until `shutdown`; a block in the
+    /// making is finished first.
+    pub async fn run(self: Arc<Self>, mut shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.block_interval_secs));
+        let mut arrivals = self.arrivals.as_ref().filter(|_| self.config.instant).map(EventBus::subscribe);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = arrival(&mut arrivals) => {}
+                _ = shutdown.wait() => return,
+            }
+            match self.produce().await {
+                Ok(Some(height)) => info!("produced block {height}"),
+                Ok(None) => {}
+                Err(err) => warn!("could not produce a block: {err:#}"),
+            }
+        }
+    }
+
+    /// Produces the next block if this validator is elected, returning its
+    /// height. Without `empty_blocks` it waits for transactions that apply.
+    async fn produce(&self) -> Result<Option<u64>> {
+        let (previous, template) = {
+            let chain = self.sync.chain().lock().await;
+            if !self.elected(&chain) {
+                return Ok(None);
+            }
+            let candidates = self.mempool.lock().unwrap().select(self.config.max_block_bytes);
+            if candidates.is_empty() && !self.config.empty_blocks {
+                return Ok(None);
+            }
+            let (template, rejected) = chain.assemble(candidates, self.validator);
+            if !rejected.is_empty() {
+                debug!("dropping {} transactions that no longer apply", rejected.len());
+                {
+                    let mut mempool = self.mempool.lock().unwrap();
+                    for hash in &rejected {
+                        mempool.remove(hash);
+                    }
+                }
+                self.store.remove_pending_txs(&rejected).await?;
+            }
+            // When nothing offered applies the block is just the coinbase.
+            let template = template.filter(|block| block.transactions.len() > 1 || self.config.empty_blocks);
+            let Some(template) = template else {
+                return Ok(None);
+            };
+            (chain.tip().header.clone(), template)
+        };
+
+        // Mining takes a while; other blocks may arrive meanwhile.
+        let consensus = self.consensus.clone();
+        let validator = self.validator;
+        let mut block = tokio::task::spawn_blocking(move || {
+            let (stake_weight, state_root) = (template.header.stake_weight, template.header.state_root);
+            consensus.propose_block(&previous, template.transactions, validator, stake_weight, state_root)
+        })
+        .await??;
+        self.consensus.seal(&mut block, &self.key.public_key, &self.key.secret_key)?;
+        if !self.sync.import(block.clone()).await? {
+            debug!("the tip moved while mining block {}", block.header.height);
+            return Ok(None);
+        }
+        if let Err(err) = self.gossip.block(&block) {
+            warn!("could not gossip block {}: {err:#}", block.header.height);
+        }
+        Ok(Some(block.header.height))
+    }
+
+    /// Whether this validator proposes the block after the tip in the
+    /// current round, which advances every `block_interval_secs` the tip
+    /// goes without a successor.
+    fn elected(&self, chain: &Chain) -> bool {
+        let round = now_ts().saturating_sub(chain.tip().header.timestamp) / self.config.block_interval_secs;
+        elect_proposer(&chain.stakes(), &chain.tip_hash(), round) == Some(self.validator)
+    }
+}
+
+/// Resolves when a transaction arrives, or may have; never without events.
+async fn arrival(arrivals: &mut Option<broadcast::Receiver<NodeEvent>>) {
+    while let Some(events) = arrivals {
+        match events.recv().await {
+            Ok(NodeEvent::Transaction { .. }) | Err(RecvError::Lagged(_)) => return,
+            Ok(_) => {}
+            Err(RecvError::Closed) => *arrivals = None,
+        }
+    }
+    std::future::pending().await
+}
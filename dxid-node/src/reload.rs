@@ -0,0 +1,98 @@
+//! Applies edits to the config file while the node runs. The log level,
+//! REST rate limits and AI provider change in place; other changes are
+//! logged as waiting for a restart. An edit that does not load or validate
+//! is logged and the running config kept.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use dxid_ai_hypervisor::{build_llm, Hypervisor};
+use dxid_config::DxidConfig;
+use dxid_rpc::RateLimiter;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::NodeOptions;
+
+/// How often the file's modification time is checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub type LogHandle = reload::Handle<EnvFilter, Registry>;
+
+/// What a reload changes.
+pub struct Reloadable {
+    pub log: LogHandle,
+    pub rate_limit: Arc<RateLimiter>,
+    pub hypervisor: Arc<Hypervisor>,
+}
+
+impl Reloadable {
+    /// Watches `path`, the file of `options`, which loaded as `current`,
+    /// until the task is aborted.
+    pub fn spawn(self, path: PathBuf, options: NodeOptions, mut current: DxidConfig) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut seen = modified(&path);
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let latest = modified(&path);
+                if latest == seen {
+                    continue;
+                }
+                seen = latest;
+                let options = options.clone();
+                // Resolving secrets may read files or call Vault.
+                match tokio::task::spawn_blocking(move || options.load()).await {
+                    Ok(Ok(cfg)) => {
+                        self.apply(&current, &cfg);
+                        current = cfg;
+                    }
+                    Ok(Err(err)) => warn!("not reloading {path:?}: {err:#}"),
+                    Err(err) => warn!("not reloading {path:?}: {err}"),
+                }
+            }
+        })
+    }
+
+    fn apply(&self, old: &DxidConfig, new: &DxidConfig) {
+        let changes = old.changes(new);
+        if changes.log {
+            match self.set_log_level(&new.log.level) {
+                Ok(()) => info!("log level is now {}", new.log.level),
+                Err(err) => warn!("keeping the log level: {err:#}"),
+            }
+        }
+        if changes.rate_limit {
+            self.rate_limit.update(new.api.rate_limit.clone());
+            info!("REST rate limit is now {:?}", new.api.rate_limit);
+        }
+        if changes.ai_provider {
+            match build_llm(&new.ai) {
+                Ok(llm) => {
+                    info!("answering with {} {}", llm.name(), llm.model());
+                    self.hypervisor.set_provider(Some(llm));
+                }
+                Err(err) if new.ai.offline.enabled => {
+                    warn!("no AI provider, answering in offline mode: {err:#}");
+                    self.hypervisor.set_provider(None);
+                }
+                Err(err) => warn!("keeping the AI provider: {err:#}"),
+            }
+        }
+        if !changes.restart.is_empty() {
+            warn!("changes to {} take effect when the node restarts", changes.restart.join(", "));
+        }
+    }
+
+    fn set_log_level(&self, level: &str) -> Result<()> {
+        self.log.reload(EnvFilter::try_new(level)?)?;
+        Ok(())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -0,0 +1,216 @@
+//! Follows the chain from gossiped blocks. Each block is checked by
+//! consensus against its parent, applied, reorganizing onto a heavier
+//! branch where needed, and the move of the main chain is stored in one
+//! database transaction, then announced on the event bus. Blocks whose
+//! parent is unknown wait as orphans. Gossiped transactions enter the
+//! mempool after the checks `POST /tx` makes.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use dxid_consensus::HybridConsensus;
+use dxid_core::events::{EventBus, NodeEvent};
+use dxid_core::mempool::Mempool;
+use dxid_core::{Block, BlockHash, Transaction, TxHash};
+use dxid_crypto::DefaultCryptoProvider;
+use dxid_network::NetworkEvent;
+use dxid_rpc::SyncStatus;
+use dxid_storage::{ChainStore, ChainUpdate, PgStore, TxStore};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::{debug, info, warn};
+
+use crate::chain::Chain;
+use crate::lifecycle::Shutdown;
+
+/// Orphans held before new ones are turned away.
+const MAX_ORPHANS: usize = 256;
+
+pub struct ChainSync {
+    chain: AsyncMutex<Chain>,
+    consensus: Arc<HybridConsensus<DefaultCryptoProvider>>,
+    store: Arc<PgStore>,
+    mempool: Arc<Mutex<Mempool>>,
+    status: Arc<SyncStatus>,
+    events: EventBus,
+    /// Seconds a proposer has before the next round's takes over.
+    round_secs: u64,
+    /// Blocks waiting for their parent, by parent hash.
+    orphans: Mutex<HashMap<BlockHash, Vec<Block>>>,
+}
+
+impl ChainSync {
+    pub fn new(
+        chain: Chain,
+        consensus: Arc<HybridConsensus<DefaultCryptoProvider>>,
+        store: Arc<PgStore>,
+        mempool: Arc<Mutex<Mempool>>,
+        status: Arc<SyncStatus>,
+        events: EventBus,
+        round_secs: u64,
+    ) -> Self {
+        status.follow(chain.state.tip_height);
+        Self {
+            chain: AsyncMutex::new(chain),
+            consensus,
+            store,
+            mempool,
+            status,
+            events,
+            round_secs,
+            orphans: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn chain(&self) -> &AsyncMutex<Chain> {
+        &self.chain
+    }
+
+    /// Imports every block peers gossip until the network stops or
+    /// `shutdown`, finishing the import in progress.
+    pub async fn run(self: Arc<Self>, mut events: Receiver<NetworkEvent>, mut shutdown: Shutdown) {
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = shutdown.wait() => return,
+            };
+            let (from, block) = match event {
+                Ok(NetworkEvent::Block { from, block }) => (from, block),
+                Ok(NetworkEvent::Transaction { from, tx }) => {
+                    if let Err(err) = self.admit(tx).await {
+                        debug!("refused transaction from {from}: {err:#}");
+                    }
+                    continue;
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("chain sync missed {missed} gossiped messages");
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            let height = block.header.height;
+            if let Err(err) = self.import(block).await {
+                warn!("rejected block {height} from {from}: {err:#}");
+            }
+        }
+    }
+
+    /// Admits a gossiped transaction that checks out against the tip, then
+    /// stores it as pending and announces it.
+    async fn admit(&self, tx: Transaction) -> Result<()> {
+        let admitted = {
+            let chain = self.chain.lock().await;
+            chain.check_offered(&tx)?;
+            self.mempool.lock().unwrap().admit(tx.clone(), &chain.state)?
+        };
+        self.store.insert_pending_tx(&tx).await?;
+        let dropped: Vec<TxHash> = admitted.replaced.iter().chain(&admitted.evicted).copied().collect();
+        if !dropped.is_empty() {
+            self.store.remove_pending_txs(&dropped).await?;
+        }
+        self.events.publish(NodeEvent::Transaction { hash: admitted.hash, tx });
+        Ok(())
+    }
+
+    /// Imports `block` and any orphans it is the parent of, returning
+    /// whether the tip moved.
+    pub async fn import(&self, block: Block) -> Result<bool> {
+        let mut chain = self.chain.lock().await;
+        let hash = block.header.hash();
+        if chain.block(&hash).is_some() {
+            return Ok(false);
+        }
+        if chain.block(&block.header.previous_hash).is_none() {
+            self.add_orphan(block);
+            return Ok(false);
+        }
+        let mut moved = self.connect(&mut chain, block).await?;
+        let mut ready = self.take_orphans(&hash);
+        while let Some(block) = ready.pop() {
+            let (hash, height) = (block.header.hash(), block.header.height);
+            match self.connect(&mut chain, block).await {
+                Ok(connected) => {
+                    moved |= connected;
+                    ready.extend(self.take_orphans(&hash));
+                }
+                Err(err) => warn!("dropping orphan block {height}: {err:#}"),
+            }
+        }
+        Ok(moved)
+    }
+
+    /// Applies `block`, whose parent is known, and stores and announces how
+    /// the main chain moved.
+    async fn connect(&self, chain: &mut Chain, block: Block) -> Result<bool> {
+        let parent = chain.block(&block.header.previous_hash).expect("checked by the caller").header.clone();
+        // The state at a side branch's parent is not kept, so its blocks'
+        // election and stake weight are checked against the stake after the tip.
+        self.consensus.verify_successor(&parent, &block, &chain.stakes(), self.round_secs)?;
+        let Some(update) = chain.accept(block)? else {
+            return Ok(false);
+        };
+        self.store.commit_chain(&update).await?;
+        if let Some(lowest) = update.reverted.last() {
+            info!("reorg reverted {} blocks", update.reverted.len());
+            self.status.reorged(update.reverted.len());
+            let reverted = update.reverted.iter().map(|block| block.header.hash()).collect();
+            self.events.publish(NodeEvent::Reorg { fork_height: lowest.header.height - 1, reverted });
+        }
+        self.status.set_height(chain.state.tip_height);
+        for (block, receipts) in &update.applied {
+            self.events.publish(NodeEvent::Block { block: block.clone(), receipts: receipts.clone() });
+        }
+        if let Err(err) = self.settle_mempool(chain, &update).await {
+            warn!("could not update pending transactions: {err:#}");
+        }
+        Ok(true)
+    }
+
+    /// Drops the applied blocks' transactions, and those they conflict
+    /// with, from the mempool and offers it the reverted ones again.
+    async fn settle_mempool(&self, chain: &Chain, update: &ChainUpdate) -> Result<()> {
+        let mut dropped = Vec::new();
+        let mut restored = Vec::new();
+        {
+            let mut mempool = self.mempool.lock().unwrap();
+            for (block, _) in &update.applied {
+                dropped.extend(mempool.remove_block(block));
+            }
+            for tx in update.reverted.iter().rev().flat_map(|block| &block.transactions) {
+                if let Ok(admitted) = mempool.admit(tx.clone(), &chain.state) {
+                    dropped.extend(admitted.replaced.into_iter().chain(admitted.evicted));
+                    restored.push(tx.clone());
+                }
+            }
+        }
+        self.store.remove_pending_txs(&dropped).await?;
+        for tx in restored {
+            self.store.insert_pending_tx(&tx).await?;
+            self.events.publish(NodeEvent::Transaction { hash: tx.hash(), tx });
+        }
+        Ok(())
+    }
+
+    fn add_orphan(&self, block: Block) {
+        let mut orphans = self.orphans.lock().unwrap();
+        let held: usize = orphans.values().map(Vec::len).sum();
+        let hash = block.header.hash();
+        let siblings = orphans.get(&block.header.previous_hash).map_or(&[][..], Vec::as_slice);
+        if held >= MAX_ORPHANS || siblings.iter().any(|orphan| orphan.header.hash() == hash) {
+            debug!("not holding orphan block {}", block.header.height);
+            return;
+        }
+        debug!("block {} waits for its parent", block.header.height);
+        orphans.entry(block.header.previous_hash).or_default().push(block);
+        self.status.set_orphans(held + 1);
+    }
+
+    fn take_orphans(&self, parent: &BlockHash) -> Vec<Block> {
+        let mut orphans = self.orphans.lock().unwrap();
+        let children = orphans.remove(parent).unwrap_or_default();
+        self.status.set_orphans(orphans.values().map(Vec::len).sum());
+        children
+    }
+}
@@ -1,29 +1,130 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::Result;
 use axum::{
-    extract::{Path, State},
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    extract::{Path, Query, State},
+    middleware,
+    response::sse::{Event, KeepAlive, Sse},
+    response::Response,
     routing::{get, post},
     Json, Router,
 };
-use dxid_ai_hypervisor::Hypervisor;
+use dxid_ai_hypervisor::{
+    AnomalyMonitor, AnswerStream, ChainIndexer, DedupService, HybridSearch, Hypervisor, LlmError,
+    SearchQuery,
+};
 use dxid_config::DxidConfig;
-use dxid_core::Address;
-use dxid_crypto::address_from_string;
-use dxid_storage::{BlockStore, PgStore, StateStore};
+use dxid_contracts::WasmRuntime;
+use dxid_core::builder::estimate_fee_rate;
+use dxid_core::contract::{ContractCall, ContractCallEnv, DEFAULT_GAS_PRICE};
+use dxid_core::error::CoreError;
+use dxid_core::events::{ChainWaker, EventBus, NodeEvent};
+use dxid_core::mempool::{Admitted, Mempool, MempoolError, UtxoView};
+use dxid_core::staking::ValidatorStatus;
+use dxid_core::{check_signed_transaction, Address, Identity, IdentityStatus, Transaction, TxHash, TxOutput};
+use dxid_crypto::{address_from_string, DefaultCryptoProvider};
+use dxid_interop::metrics::InteropMetrics;
+use dxid_interop::registry::AdapterRegistry;
+use dxid_interop::ExternalStateQuery;
+use dxid_network::{Gossip, NetworkStats, PeerInfo};
+use dxid_storage::{
+    BlockStore, BridgeMessageStatus, BridgeStore, IdentityStore, PgStore, ReceiptStore, StakingStore, StateStore,
+    TxStore,
+};
+use dxid_wallet::{apply_identity_update, verify_identity_rotation, IdentityKeyRotation, IdentityUpdate};
+use futures::{stream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
 use tonic::{transport::Server, Request, Response, Status};
-use tracing::info;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// How often block subscriptions look for new blocks if the event bus closes.
+const BLOCK_FALLBACK_INTERVAL: Duration = Duration::from_secs(1);
+/// Pending transactions looked at for a fee estimate.
+const FEE_ESTIMATE_SAMPLE: i64 = 500;
+/// Most blocks returned by one `/blocks` page.
+const MAX_BLOCK_PAGE: u64 = 100;
+/// Most identities returned by one `/identities` page.
+const MAX_IDENTITY_PAGE: u64 = 100;
+/// Most duplicate reports returned by one `/identities/duplicates` listing.
+const MAX_DUPLICATE_PAGE: usize = 100;
+/// Most bridge messages of each status returned by one `/bridge/messages` listing.
+const MAX_BRIDGE_PAGE: i64 = 100;
+/// Most results returned by one `/ai/search`.
+const MAX_SEARCH_RESULTS: usize = 100;
+/// Most tool calls returned by one `/ai/tools/audit` listing.
+const MAX_TOOL_AUDIT_PAGE: usize = 200;
+/// Most records returned by one `/ai/audit` listing.
+const MAX_AI_AUDIT_PAGE: usize = 200;
+/// Most alerts returned by one `/ai/anomalies` listing.
+const MAX_ANOMALY_PAGE: usize = 200;
 
 pub mod proto {
     tonic::include_proto!("dxid");
 }
 
+mod rate_limit;
+mod sync_status;
+
+pub use rate_limit::RateLimiter;
+pub use sync_status::{SyncReport, SyncStatus};
+
 #[derive(Clone)]
 pub struct RpcState {
     pub store: Arc<PgStore>,
     pub hypervisor: Arc<Hypervisor>,
+    pub bridge: Arc<AdapterRegistry>,
+    pub bridge_metrics: Arc<InteropMetrics>,
+    pub network: Arc<NetworkStats>,
+    pub contracts: Arc<WasmRuntime>,
+    /// Duplicate identity detection, when `ai.dedup` is enabled.
+    pub dedup: Option<Arc<DedupService>>,
+    /// Chain activity embeddings, when `ai.chain_index` is enabled.
+    pub indexer: Option<Arc<ChainIndexer>>,
+    /// Hybrid vector and metadata search, when an embedder is configured.
+    pub search: Option<Arc<HybridSearch>>,
+    /// Chain anomaly alerts, when `ai.anomaly` is enabled.
+    pub anomalies: Option<Arc<AnomalyMonitor>>,
+    /// Limits on REST requests per client, from `api.rate_limit`.
+    pub rate_limit: Arc<RateLimiter>,
+    /// Submitted transactions waiting for a block.
+    pub mempool: Arc<Mutex<Mempool>>,
+    /// Progress following the chain, once the node does.
+    pub sync: Arc<SyncStatus>,
+    /// The node's event bus, which new transactions are announced on and
+    /// the websocket streams follow.
+    pub events: EventBus,
+    /// Publishes accepted transactions to peers.
+    pub gossip: Gossip,
+}
+
+/// Outputs a submitted transaction spends, read from the store.
+struct StoredSpends {
+    outputs: HashMap<(TxHash, u32), TxOutput>,
+    coinbases: HashMap<TxHash, u64>,
+    height: u64,
+}
+
+impl UtxoView for StoredSpends {
+    fn unspent(&self, tx: &TxHash, index: u32) -> Option<TxOutput> {
+        self.outputs.get(&(*tx, index)).cloned()
+    }
+
+    fn coinbase_height(&self, tx: &TxHash) -> Option<u64> {
+        self.coinbases.get(tx).copied()
+    }
+
+    fn next_height(&self) -> u64 {
+        self.height
+    }
 }
 
 #[derive(Serialize)]
@@ -35,30 +136,163 @@ struct HealthResponse {
 struct StatusResponse {
     height: u64,
     peers: usize,
+    /// Highest height seen on the network; above `height` while syncing.
+    network_height: u64,
+    /// Transactions waiting for inclusion.
+    mempool: u64,
+    /// Present while the node follows the chain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sync: Option<SyncReport>,
 }
 
-pub async fn start_servers(cfg: &DxidConfig, store: Arc<PgStore>, hypervisor: Arc<Hypervisor>) -> Result<()> {
-    let state = RpcState { store, hypervisor };
+impl RpcState {
+    async fn best_height(&self) -> Result<u64, Status> {
+        let height = self.store.best_height().await.map_err(|_| Status::internal("db error"))?;
+        Ok(height.unwrap_or(0))
+    }
+
+    /// Refills the mempool from the transactions stored as pending and
+    /// drops those it no longer admits, returning how many are waiting.
+    pub async fn restore_mempool(&self) -> Result<usize> {
+        let limit = self.mempool.lock().unwrap().config().max_txs;
+        let mut dropped = Vec::new();
+        for tx in self.store.pending_txs(limit as i64).await? {
+            let hash = tx.hash();
+            match self.spends(&tx).await.and_then(|spends| self.admit(tx, &spends)) {
+                Ok(admitted) => dropped.extend(admitted.replaced.into_iter().chain(admitted.evicted)),
+                Err(status) if status.code() == tonic::Code::Internal => {
+                    return Err(anyhow::anyhow!("restoring the mempool: {}", status.message()));
+                }
+                Err(status) => {
+                    warn!("dropping pending transaction {}: {}", hex::encode(hash), status.message());
+                    dropped.push(hash);
+                }
+            }
+        }
+        self.store.remove_pending_txs(&dropped).await?;
+        Ok(self.mempool.lock().unwrap().len())
+    }
+
+    /// Admits `tx` to the mempool against the outputs it spends.
+    fn admit(&self, tx: Transaction, spends: &StoredSpends) -> Result<Admitted, Status> {
+        self.mempool.lock().unwrap().admit(tx, spends).map_err(|err| match err {
+            MempoolError::AlreadyKnown => Status::already_exists(err.to_string()),
+            MempoolError::SenderLimit(_) | MempoolError::Full(_) => {
+                Status::resource_exhausted(err.to_string())
+            }
+            _ => Status::invalid_argument(err.to_string()),
+        })
+    }
+
+    /// The unspent outputs `tx` spends, as stored, for the block after the tip.
+    async fn spends(&self, tx: &Transaction) -> Result<StoredSpends, Status> {
+        let db_error = |_| Status::internal("db error");
+        let height = self.store.best_height().await.map_err(db_error)?.map_or(0, |tip| tip + 1);
+        let mut spends = StoredSpends { outputs: HashMap::new(), coinbases: HashMap::new(), height };
+        for input in &tx.inputs {
+            let (hash, index) = (input.previous_tx, input.output_index);
+            if let Some(output) = self.store.unspent_output(&hash, index).await.map_err(db_error)? {
+                spends.outputs.insert((hash, index), output);
+            }
+            if let Some(mined) = self.store.coinbase_height(&hash).await.map_err(db_error)? {
+                spends.coinbases.insert(hash, mined);
+            }
+        }
+        Ok(spends)
+    }
+
+    /// Checks a changed identity for duplicates and records the change in
+    /// the chain index, in the background so the change is not held up by
+    /// the embedding provider. Changes that leave the attributes as they
+    /// were, like key rotations, skip the duplicate check.
+    fn identity_changed(&self, identity: Identity, change: &'static str, attributes_changed: bool) {
+        let dedup = self.dedup.clone().filter(|_| attributes_changed);
+        let indexer = self.indexer.clone();
+        tokio::spawn(async move {
+            if let Some(dedup) = dedup {
+                match dedup.check(&identity).await {
+                    Ok(found) => {
+                        for report in found {
+                            warn!(
+                                "identity {} may duplicate {} (similarity {:.3})",
+                                report.identity, report.duplicate_of, report.similarity
+                            );
+                        }
+                    }
+                    Err(err) => warn!("duplicate check of identity {} failed: {err:#}", identity.id),
+                }
+            }
+            if let Some(indexer) = indexer {
+                if let Err(err) = indexer.index_identity(&identity, change).await {
+                    warn!("indexing identity {} failed: {err:#}", identity.id);
+                }
+            }
+        });
+    }
+}
+
+/// Serves REST and gRPC until `shutdown` turns true, then stops taking
+/// connections and lets the requests in flight finish.
+pub async fn start_servers(cfg: &DxidConfig, state: RpcState, shutdown: watch::Receiver<bool>) -> Result<()> {
     let rest_addr: SocketAddr = cfg.api.rest_addr.parse()?;
     let grpc_addr: SocketAddr = cfg.api.grpc_addr.parse()?;
-    let rest_handle = tokio::spawn(run_rest(rest_addr, state.clone()));
-    let grpc_handle = tokio::spawn(run_grpc(grpc_addr, state));
-    rest_handle.await??;
-    grpc_handle.await??;
+    tokio::try_join!(
+        run_rest(rest_addr, state.clone(), stopped(shutdown.clone())),
+        run_grpc(grpc_addr, state, stopped(shutdown)),
+    )?;
     Ok(())
 }
 
-async fn run_rest(addr: SocketAddr, state: RpcState) -> Result<()> {
+/// Resolves once `shutdown` turns true or its sender is gone.
+async fn stopped(mut shutdown: watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+async fn run_rest(addr: SocketAddr, state: RpcState, shutdown: impl Future<Output = ()>) -> Result<()> {
     let app = Router::new()
         .route("/health", get(health))
         .route("/status", get(status))
+        .route("/peers", get(peers))
+        .route("/blocks", get(list_blocks))
         .route("/blocks/:height", get(get_block))
         .route("/balance/:address", get(balance))
+        .route("/utxos/:address", get(utxos))
+        .route("/tx", post(submit_tx))
+        .route("/tx/:hash/receipt", get(tx_receipt))
+        .route("/fee/estimate", get(fee_estimate))
+        .route("/contracts/estimate", post(estimate_gas))
+        .route("/contracts/:address", get(contract_info))
+        .route("/staking/:address", get(staking_position))
+        .route("/staking/validators/:address", get(validator_status))
+        .route("/identities", get(list_identities))
+        .route("/identities/duplicates", get(list_duplicates))
+        .route("/identities/:id", get(get_identity).post(update_identity))
+        .route("/identities/:id/rotate", post(rotate_identity))
+        .route("/identities/:id/duplicates", get(identity_duplicates))
+        .route("/ws/duplicates", get(subscribe_duplicates))
+        .route("/ws/blocks", get(subscribe_blocks))
+        .route("/ws/events", get(subscribe_events))
         .route("/ai/query", post(ai_query))
+        .route("/ai/search", post(ai_search))
+        .route("/ai/tools/audit", get(tool_audit))
+        .route("/ai/audit", get(ai_audit))
+        .route("/ai/stream", post(ai_stream))
+        .route("/ai/sessions/:id", get(ai_session))
+        .route("/ws/ai", get(stream_ai))
+        .route("/ai/anomalies", get(list_anomalies))
+        .route("/ws/anomalies", get(subscribe_anomalies))
+        .route("/bridge/chains", get(bridge_chains))
+        .route("/bridge/:chain/query", post(bridge_query))
+        .route("/bridge/metrics", get(bridge_metrics))
+        .route("/bridge/messages", get(bridge_messages))
+        .route("/bridge/messages/:id", get(bridge_message))
+        .route("/bridge/messages/:id/audit", get(bridge_audit))
+        .layer(middleware::from_fn_with_state(state.rate_limit.clone(), rate_limit::limit))
         .with_state(state);
     info!("REST listening on {addr}");
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown)
         .await?;
     Ok(())
 }
@@ -67,17 +301,50 @@ async fn health() -> Json<HealthResponse> {
     Json(HealthResponse { status: "ok" })
 }
 
-async fn status(State(state): State<RpcState>) -> Json<StatusResponse> {
-    // Height derived from block count for demo purposes.
-    let height = state
+/// Local chain height, peers, sync target and mempool size.
+async fn status(State(state): State<RpcState>) -> Result<Json<StatusResponse>, Status> {
+    let height = state.best_height().await?;
+    let mempool = state.store.pending_count().await.map_err(|_| Status::internal("db error"))?;
+    Ok(Json(StatusResponse {
+        height,
+        peers: state.network.peers(),
+        network_height: state.network.best_height().max(height),
+        mempool,
+        sync: state.sync.report(),
+    }))
+}
+
+#[derive(Serialize)]
+struct PeersResponse {
+    peers: Vec<PeerInfo>,
+}
+
+async fn peers(State(state): State<RpcState>) -> Json<PeersResponse> {
+    Json(PeersResponse { peers: state.network.peer_list() })
+}
+
+#[derive(Deserialize)]
+struct BlockPageQuery {
+    before: Option<u64>,
+    limit: Option<u64>,
+}
+
+/// A page of blocks, newest first; `next` is the `before` of the following page.
+async fn list_blocks(
+    State(state): State<RpcState>,
+    Query(query): Query<BlockPageQuery>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_BLOCK_PAGE);
+    let blocks = state
         .store
-        .get_block_by_height(0)
+        .blocks_before(query.before.map(|h| h as i64), limit as i64)
         .await
-        .ok()
-        .flatten()
-        .map(|b| b.header.height)
-        .unwrap_or(0);
-    Json(StatusResponse { height, peers: 0 })
+        .map_err(|_| Status::internal("db error"))?;
+    let next = match blocks.last() {
+        Some(last) if blocks.len() as u64 == limit && last.header.height > 0 => Some(last.header.height),
+        _ => None,
+    };
+    Ok(Json(serde_json::json!({ "blocks": blocks, "next": next })))
 }
 
 async fn get_block(
@@ -105,21 +372,729 @@ async fn balance(
     Ok(Json(serde_json::json!({ "balance": balance })))
 }
 
+/// Bonds, unbonding timeline and pending rewards of an address.
+async fn staking_position(
+    State(state): State<RpcState>,
+    Path(addr): Path<String>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let address = address_from_string(&addr).map_err(|_| Status::invalid_argument("bad address"))?;
+    let position = state
+        .store
+        .get_staking_position(&address)
+        .await
+        .map_err(|_| Status::internal("db error"))?
+        .unwrap_or_default();
+    Ok(Json(serde_json::json!({ "position": position })))
+}
+
+/// Stake bonded to a validator, by itself and by delegators.
+async fn validator_status(
+    State(state): State<RpcState>,
+    Path(addr): Path<String>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let validator = address_from_string(&addr).map_err(|_| Status::invalid_argument("bad address"))?;
+    let positions = state
+        .store
+        .staking_positions_for(&validator)
+        .await
+        .map_err(|_| Status::internal("db error"))?;
+    let status = ValidatorStatus::from_positions(validator, positions.iter().map(|(owner, p)| (*owner, p)));
+    Ok(Json(serde_json::json!({ "validator": status })))
+}
+
+#[derive(Deserialize)]
+struct IdentityPageQuery {
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+async fn list_identities(
+    State(state): State<RpcState>,
+    Query(query): Query<IdentityPageQuery>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_IDENTITY_PAGE);
+    let identities = state
+        .store
+        .list_identities(query.offset.unwrap_or(0) as i64, limit as i64)
+        .await
+        .map_err(|_| Status::internal("db error"))?;
+    Ok(Json(serde_json::json!({ "identities": identities })))
+}
+
+async fn get_identity(
+    State(state): State<RpcState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let identity = state
+        .store
+        .get_identity(&id)
+        .await
+        .map_err(|_| Status::internal("db error"))?
+        .ok_or_else(|| Status::not_found("unknown identity"))?;
+    Ok(Json(serde_json::json!({ "identity": identity })))
+}
+
+/// Registers an identity or changes its attributes or status, given an
+/// update signed by its current key over its current state.
+async fn update_identity(
+    State(state): State<RpcState>,
+    Path(id): Path<Uuid>,
+    Json(update): Json<IdentityUpdate>,
+) -> Result<Json<serde_json::Value>, Status> {
+    if update.identity_id != id {
+        return Err(Status::invalid_argument("update is for another identity"));
+    }
+    let current = state.store.get_identity(&id).await.map_err(|_| Status::internal("db error"))?;
+    let identity = apply_identity_update(current.as_ref(), &update)
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    state
+        .store
+        .put_identity(&identity)
+        .await
+        .map_err(|_| Status::internal("db error"))?;
+    let change = match (&current, &identity.status) {
+        (None, _) => "registered",
+        (Some(_), IdentityStatus::Revoked) => "was revoked",
+        (Some(_), IdentityStatus::Active) => "updated its attributes",
+    };
+    state.identity_changed(identity.clone(), change, true);
+    Ok(Json(serde_json::json!({ "identity": identity })))
+}
+
+/// Moves an identity to a new key, given a rotation signed by its current key.
+async fn rotate_identity(
+    State(state): State<RpcState>,
+    Path(id): Path<Uuid>,
+    Json(rotation): Json<IdentityKeyRotation>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let mut identity = state
+        .store
+        .get_identity(&id)
+        .await
+        .map_err(|_| Status::internal("db error"))?
+        .ok_or_else(|| Status::not_found("unknown identity"))?;
+    if !verify_identity_rotation(&identity, &rotation).unwrap_or(false) {
+        return Err(Status::invalid_argument("rotation is not signed by the identity's current key"));
+    }
+    dxid_core::rotate_identity_key(&mut identity, rotation.new_public_key);
+    state
+        .store
+        .put_identity(&identity)
+        .await
+        .map_err(|_| Status::internal("db error"))?;
+    state.identity_changed(identity.clone(), "rotated its key", false);
+    Ok(Json(serde_json::json!({ "identity": identity })))
+}
+
+#[derive(Deserialize)]
+struct DuplicatesQuery {
+    limit: Option<usize>,
+}
+
+/// Latest possible duplicate identities, newest first; `enabled` is false
+/// when the node does not check for them.
+async fn list_duplicates(
+    State(state): State<RpcState>,
+    Query(query): Query<DuplicatesQuery>,
+) -> Json<serde_json::Value> {
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_DUPLICATE_PAGE);
+    let duplicates = match &state.dedup {
+        Some(dedup) => dedup.reports(limit).await,
+        None => Vec::new(),
+    };
+    Json(serde_json::json!({ "enabled": state.dedup.is_some(), "duplicates": duplicates }))
+}
+
+/// Possible duplicates naming the identity on either side, newest first.
+async fn identity_duplicates(State(state): State<RpcState>, Path(id): Path<Uuid>) -> Json<serde_json::Value> {
+    let duplicates = match &state.dedup {
+        Some(dedup) => dedup.reports_for(&id).await,
+        None => Vec::new(),
+    };
+    Json(serde_json::json!({ "enabled": state.dedup.is_some(), "duplicates": duplicates }))
+}
+
+async fn utxos(
+    State(state): State<RpcState>,
+    Path(addr): Path<String>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let address = address_from_string(&addr).map_err(|_| Status::invalid_argument("bad address"))?;
+    let utxos = state
+        .store
+        .list_utxos(&address)
+        .await
+        .map_err(|_| Status::internal("db error"))?;
+    Ok(Json(serde_json::json!({ "utxos": utxos })))
+}
+
+/// Accepts a signed transaction for inclusion once every input signature
+/// or spending condition checks out and the mempool admits it. Coinbase
+/// maturity, time locks and the transaction's lock time are checked against
+/// the next block.
+async fn submit_tx(
+    State(state): State<RpcState>,
+    Json(tx): Json<Transaction>,
+) -> Result<Json<serde_json::Value>, Status> {
+    if tx.inputs.is_empty() || tx.outputs.is_empty() {
+        return Err(Status::invalid_argument("transaction needs inputs and outputs"));
+    }
+    let spends = state.spends(&tx).await?;
+    check_signed_transaction(&DefaultCryptoProvider::new(), &tx, &spends).map_err(core_status)?;
+    let admitted = state.admit(tx.clone(), &spends)?;
+    let dropped: Vec<TxHash> = admitted.replaced.iter().chain(&admitted.evicted).copied().collect();
+    state
+        .store
+        .insert_pending_tx(&tx)
+        .await
+        .map_err(|_| Status::internal("db error"))?;
+    if !dropped.is_empty() {
+        state
+            .store
+            .remove_pending_txs(&dropped)
+            .await
+            .map_err(|_| Status::internal("db error"))?;
+    }
+    if let Err(err) = state.gossip.transaction(&tx) {
+        warn!("could not gossip transaction {}: {err:#}", hex::encode(admitted.hash));
+    }
+    state.events.publish(NodeEvent::Transaction { hash: admitted.hash, tx });
+    Ok(Json(serde_json::json!({
+        "hash": hex::encode(admitted.hash),
+        "replaced": admitted.replaced.iter().map(hex::encode).collect::<Vec<_>>(),
+    })))
+}
+
+/// The status a transaction or block failing with `err` is refused with:
+/// missing authority is denied, spends the chain does not allow fail a
+/// precondition, and a failing crypto provider is the node's fault.
+fn core_status(err: CoreError) -> Status {
+    let message = err.to_string();
+    match err {
+        CoreError::InvalidSignature
+        | CoreError::MultisigThreshold
+        | CoreError::NotSigner
+        | CoreError::NotMultisigOwner
+        | CoreError::WrongPreimage
+        | CoreError::ConditionNotMet
+        | CoreError::StakeOwnerNotSigner
+        | CoreError::UnsignedContractCall => Status::permission_denied(message),
+        CoreError::DoubleSpend
+        | CoreError::MissingPreviousTx
+        | CoreError::MissingOutput
+        | CoreError::ImmatureCoinbase
+        | CoreError::Timelocked { .. }
+        | CoreError::TransactionLocked { .. }
+        | CoreError::EscrowedInput
+        | CoreError::StakedInput
+        | CoreError::InsufficientInputs { .. }
+        | CoreError::UnexpectedHeight { .. }
+        | CoreError::NotOnTip { .. }
+        | CoreError::WrongStateRoot { .. } => Status::failed_precondition(message),
+        CoreError::Overflow(_) => Status::out_of_range(message),
+        CoreError::ContractsDisabled => Status::unimplemented(message),
+        CoreError::Crypto(_) => Status::internal(message),
+        _ => Status::invalid_argument(message),
+    }
+}
+
+/// Outcome of a transaction once it is in a block, with its contract events.
+async fn tx_receipt(
+    State(state): State<RpcState>,
+    Path(hash): Path<String>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let hash: TxHash = hex::decode(&hash)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Status::invalid_argument("bad tx hash"))?;
+    let receipt = state
+        .store
+        .get_receipt(&hash)
+        .await
+        .map_err(|_| Status::internal("db error"))?
+        .ok_or_else(|| Status::not_found("no receipt for transaction"))?;
+    Ok(Json(serde_json::json!({ "receipt": receipt })))
+}
+
+/// Suggested fee rate, per byte, from the transactions waiting for a block.
+async fn fee_estimate(State(state): State<RpcState>) -> Result<Json<serde_json::Value>, Status> {
+    let pending = state
+        .store
+        .pending_txs(FEE_ESTIMATE_SAMPLE)
+        .await
+        .map_err(|_| Status::internal("db error"))?;
+    Ok(Json(serde_json::json!({ "fee_rate": estimate_fee_rate(&pending), "pending": pending.len() })))
+}
+
+/// The ABI and upgrade policy of a deployed contract.
+async fn contract_info(
+    State(state): State<RpcState>,
+    Path(addr): Path<String>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let address = address_from_string(&addr).map_err(|_| Status::invalid_argument("bad address"))?;
+    let abi = state.contracts.abi(&address).ok_or_else(|| Status::not_found("no contract at address"))?;
+    let policy = state.contracts.upgrade_policy(&address);
+    Ok(Json(serde_json::json!({ "abi": abi, "upgrade_policy": policy })))
+}
+
+#[derive(Deserialize)]
+struct EstimateGasRequest {
+    caller: String,
+    call: ContractCall,
+    #[serde(default)]
+    value: u64,
+}
+
+/// Gas a contract call or deployment would use in the next block, run
+/// against a copy of the contract.
+async fn estimate_gas(
+    State(state): State<RpcState>,
+    Json(request): Json<EstimateGasRequest>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let caller = address_from_string(&request.caller).map_err(|_| Status::invalid_argument("bad caller"))?;
+    let balance = state
+        .store
+        .get_balance(&request.call.contract)
+        .await
+        .map_err(|_| Status::internal("db error"))?;
+    let env = ContractCallEnv {
+        caller,
+        block_height: state.best_height().await? + 1,
+        timestamp: dxid_core::now_ts(),
+        value: request.value,
+        balance: balance.saturating_add(request.value),
+        ..ContractCallEnv::default()
+    };
+    let gas_used = state
+        .contracts
+        .estimate_gas(&env, &request.call)
+        .map_err(|err| Status::invalid_argument(format!("call fails: {err}")))?;
+    Ok(Json(serde_json::json!({ "gas_used": gas_used, "gas_price": DEFAULT_GAS_PRICE })))
+}
+
+#[derive(Deserialize)]
+struct SubscribeQuery {
+    #[serde(default)]
+    from: u64,
+}
+
+/// Websocket stream of blocks from `from` onwards, one `{"block": ..., "receipts": [...]}`
+/// text frame each; the receipts carry the block's contract events. A block
+/// replaced by a reorg is not resent; subscribers notice the broken
+/// `previous_hash` link and resubscribe from the fork point.
+async fn subscribe_blocks(
+    ws: WebSocketUpgrade,
+    State(state): State<RpcState>,
+    Query(query): Query<SubscribeQuery>,
+) -> Response {
+    ws.on_upgrade(move |socket| stream_blocks(socket, state, query.from))
+}
+
+async fn stream_blocks(mut socket: WebSocket, state: RpcState, mut next: u64) {
+    let mut waker = ChainWaker::new(Some(&state.events), BLOCK_FALLBACK_INTERVAL);
+    loop {
+        loop {
+            let block = match state.store.get_block_by_height(next as i64).await {
+                Ok(Some(block)) => block,
+                Ok(None) => break,
+                Err(err) => {
+                    warn!("block subscription stopped at {next}: {err}");
+                    return;
+                }
+            };
+            let receipts = match state.store.block_receipts(next as i64).await {
+                Ok(receipts) => receipts,
+                Err(err) => {
+                    warn!("block subscription stopped at {next}: {err}");
+                    return;
+                }
+            };
+            let frame = serde_json::json!({ "block": block, "receipts": receipts }).to_string();
+            if socket.send(Message::Text(frame)).await.is_err() {
+                return;
+            }
+            next += 1;
+        }
+        waker.wait().await;
+    }
+}
+
+/// Websocket stream of the node's events as they happen, one text frame
+/// each tagged by `event`: `block` (with its receipts), `reorg`,
+/// `transaction` or `peer`. A subscriber too slow to keep up misses events.
+async fn subscribe_events(ws: WebSocketUpgrade, State(state): State<RpcState>) -> Response {
+    ws.on_upgrade(move |socket| stream_events(socket, state))
+}
+
+async fn stream_events(mut socket: WebSocket, state: RpcState) {
+    let mut events = state.events.subscribe();
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("event subscriber missed {missed} events");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let frame = serde_json::json!(event).to_string();
+        if socket.send(Message::Text(frame)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Websocket stream of possible duplicate identities as they are found,
+/// one `PossibleDuplicate` text frame each. Closes at once when the node
+/// does not check for them.
+async fn subscribe_duplicates(ws: WebSocketUpgrade, State(state): State<RpcState>) -> Response {
+    ws.on_upgrade(move |socket| stream_duplicates(socket, state))
+}
+
+async fn stream_duplicates(mut socket: WebSocket, state: RpcState) {
+    let Some(dedup) = state.dedup else {
+        return;
+    };
+    let mut reports = dedup.subscribe();
+    loop {
+        let report = match reports.recv().await {
+            Ok(report) => report,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("duplicate subscriber missed {missed} reports");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let frame = serde_json::json!(report).to_string();
+        if socket.send(Message::Text(frame)).await.is_err() {
+            return;
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct AiRequest {
     prompt: String,
+    /// Continues this conversation, recording the exchange in it.
+    #[serde(default)]
+    session: Option<Uuid>,
 }
 
 async fn ai_query(
     State(state): State<RpcState>,
     Json(req): Json<AiRequest>,
 ) -> Result<Json<serde_json::Value>, Status> {
-    let response = state
+    let answer = state.hypervisor.ask_in(req.session, &req.prompt).await.map_err(ai_status)?;
+    Ok(Json(serde_json::json!(answer)))
+}
+
+/// Every message of a conversation, with the summary of those no longer
+/// sent to the model.
+async fn ai_session(
+    State(state): State<RpcState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let session = state
         .hypervisor
-        .query(&req.prompt)
+        .session(&id)
         .await
-        .map_err(|_| Status::internal("ai error"))?;
-    Ok(Json(serde_json::json!({ "answer": response })))
+        .map_err(|_| Status::internal("db error"))?
+        .ok_or_else(|| Status::not_found("unknown session"))?;
+    Ok(Json(serde_json::json!({ "session": session })))
+}
+
+/// Audits a streamed answer once it has ended and records a complete one in
+/// its session; the answer has already been sent, so a failure is only logged.
+async fn finish(
+    hypervisor: &Hypervisor,
+    req: &AiRequest,
+    answer: &AnswerStream,
+    text: &str,
+    error: Option<String>,
+) {
+    if let Err(err) = hypervisor.finish_stream(req.session, &req.prompt, answer, text, error).await {
+        warn!("recording the streamed answer failed: {err:#}");
+    }
+}
+
+/// Tells callers whether asking again may help; details stay in the log.
+fn ai_status(err: anyhow::Error) -> Status {
+    warn!("ai query failed: {err:#}");
+    match err.downcast_ref::<LlmError>() {
+        Some(LlmError::RateLimited { .. } | LlmError::QuotaExceeded { .. }) => {
+            Status::resource_exhausted("ai provider limit reached")
+        }
+        Some(LlmError::Unavailable { .. } | LlmError::Transport { .. }) => {
+            Status::unavailable("ai provider unavailable")
+        }
+        _ => Status::internal("ai error"),
+    }
+}
+
+#[derive(Deserialize)]
+struct ToolAuditQuery {
+    limit: Option<usize>,
+}
+
+/// The AI's latest tool calls, newest first.
+async fn tool_audit(
+    State(state): State<RpcState>,
+    Query(query): Query<ToolAuditQuery>,
+) -> Json<serde_json::Value> {
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_TOOL_AUDIT_PAGE);
+    let calls = state.hypervisor.tool_audit(limit).await;
+    Json(serde_json::json!({ "enabled": calls.is_some(), "calls": calls.unwrap_or_default() }))
+}
+
+#[derive(Deserialize)]
+struct AiAuditQuery {
+    before: Option<u64>,
+    limit: Option<usize>,
+}
+
+/// Redacted records of the questions put to the AI, newest first; page back
+/// with `before`, the `at` of the oldest record seen.
+async fn ai_audit(
+    State(state): State<RpcState>,
+    Query(query): Query<AiAuditQuery>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_AI_AUDIT_PAGE);
+    let records = state.hypervisor.audit_log(query.before, limit).await.map_err(|err| {
+        warn!("reading the AI audit log failed: {err:#}");
+        Status::internal("db error")
+    })?;
+    Ok(Json(serde_json::json!({ "enabled": records.is_some(), "records": records.unwrap_or_default() })))
+}
+
+#[derive(Deserialize)]
+struct AnomalyQuery {
+    limit: Option<usize>,
+}
+
+/// Latest chain anomaly alerts, newest first; `enabled` is false when the
+/// node does not watch for them.
+async fn list_anomalies(
+    State(state): State<RpcState>,
+    Query(query): Query<AnomalyQuery>,
+) -> Json<serde_json::Value> {
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_ANOMALY_PAGE);
+    let alerts = match &state.anomalies {
+        Some(monitor) => monitor.alerts(limit).await,
+        None => Vec::new(),
+    };
+    Json(serde_json::json!({ "enabled": state.anomalies.is_some(), "alerts": alerts }))
+}
+
+/// Streams chain anomaly alerts as they are raised, one `Alert` text frame
+/// each. Closes at once when the node does not watch for them.
+async fn subscribe_anomalies(ws: WebSocketUpgrade, State(state): State<RpcState>) -> Response {
+    ws.on_upgrade(move |socket| stream_anomalies(socket, state))
+}
+
+async fn stream_anomalies(mut socket: WebSocket, state: RpcState) {
+    let Some(monitor) = state.anomalies else {
+        return;
+    };
+    let mut alerts = monitor.subscribe();
+    loop {
+        let alert = match alerts.recv().await {
+            Ok(alert) => alert,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("anomaly subscriber missed {missed} alerts");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let frame = serde_json::json!(alert).to_string();
+        if socket.send(Message::Text(frame)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Nearest neighbours of `text` in `namespace`, ranked by `[ai.ranking]`
+/// with each result's component scores.
+async fn ai_search(
+    State(state): State<RpcState>,
+    Json(mut query): Json<SearchQuery>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let search = state.search.ok_or_else(|| Status::failed_precondition("no embedder configured"))?;
+    query.limit = query.limit.map(|limit| limit.clamp(1, MAX_SEARCH_RESULTS));
+    let results = search.search(&query).await.map_err(|err| {
+        warn!("search in {} failed: {err:#}", query.namespace);
+        Status::internal("search error")
+    })?;
+    Ok(Json(serde_json::json!({ "results": results })))
+}
+
+/// Streams an answer as server-sent events: `token` events with
+/// `{"token"}`, then `done` with `{"done": true, "sources", "offline",
+/// "analytics"}` (the sources the answer cites and any metrics) or `error`
+/// with `{"error"}`. Disconnecting cancels the query, and a complete answer
+/// is recorded in the request's session.
+async fn ai_stream(
+    State(state): State<RpcState>,
+    Json(req): Json<AiRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Status> {
+    let answer = state.hypervisor.ask_stream_in(req.session, &req.prompt).await.map_err(ai_status)?;
+    let start = (answer, String::new(), state.hypervisor, req);
+    let events = stream::unfold(Some(start), |state| async move {
+        let (mut answer, mut text, hypervisor, req) = state?;
+        let (name, data, next) = match answer.tokens.next().await {
+            Some(Ok(token)) => {
+                text.push_str(&token);
+                let data = serde_json::json!({ "token": token });
+                ("token", data, Some((answer, text, hypervisor, req)))
+            }
+            Some(Err(err)) => {
+                finish(&hypervisor, &req, &answer, &text, Some(err.to_string())).await;
+                ("error", serde_json::json!({ "error": err.to_string() }), None)
+            }
+            None => {
+                finish(&hypervisor, &req, &answer, &text, None).await;
+                let sources = answer.context.cited(&text);
+                let data = serde_json::json!({
+                    "done": true,
+                    "sources": sources,
+                    "offline": answer.offline,
+                    "analytics": answer.analytics,
+                });
+                ("done", data, None)
+            }
+        };
+        Some((Ok::<_, Infallible>(Event::default().event(name).data(data.to_string())), next))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Streams an answer over a websocket: the client sends `{"prompt"}` and an
+/// optional `"session"`, then receives `{"token"}` frames and a final
+/// `{"done": true, "sources", "offline", "analytics"}` or `{"error"}`. Closing the socket
+/// cancels the query.
+async fn stream_ai(ws: WebSocketUpgrade, State(state): State<RpcState>) -> Response {
+    ws.on_upgrade(move |socket| stream_answer(socket, state))
+}
+
+async fn stream_answer(mut socket: WebSocket, state: RpcState) {
+    let req = match socket.recv().await {
+        Some(Ok(Message::Text(text))) => serde_json::from_str::<AiRequest>(&text),
+        _ => return,
+    };
+    let mut text = String::new();
+    let frame = match req {
+        Ok(req) => match state.hypervisor.ask_stream_in(req.session, &req.prompt).await {
+            Ok(mut answer) => loop {
+                tokio::select! {
+                    token = answer.tokens.next() => match token {
+                        Some(Ok(token)) => {
+                            text.push_str(&token);
+                            let frame = serde_json::json!({ "token": token }).to_string();
+                            if socket.send(Message::Text(frame)).await.is_err() {
+                                return;
+                            }
+                        }
+                        Some(Err(err)) => {
+                            finish(&state.hypervisor, &req, &answer, &text, Some(err.to_string())).await;
+                            break serde_json::json!({ "error": err.to_string() });
+                        }
+                        None => {
+                            finish(&state.hypervisor, &req, &answer, &text, None).await;
+                            let sources = answer.context.cited(&text);
+                            break serde_json::json!({
+                                "done": true,
+                                "sources": sources,
+                                "offline": answer.offline,
+                                "analytics": answer.analytics,
+                            });
+                        }
+                    },
+                    message = socket.recv() => match message {
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                        _ => {}
+                    },
+                }
+            },
+            Err(err) => serde_json::json!({ "error": err.to_string() }),
+        },
+        Err(err) => serde_json::json!({ "error": format!("bad request: {err}") }),
+    };
+    let _ = socket.send(Message::Text(frame.to_string())).await;
+}
+
+async fn bridge_chains(State(state): State<RpcState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "chains": state.bridge.chains() }))
+}
+
+async fn bridge_query(
+    State(state): State<RpcState>,
+    Path(chain): Path<String>,
+    Json(query): Json<ExternalStateQuery>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let adapter = state
+        .bridge
+        .adapter(&chain)
+        .map_err(|_| Status::not_found("unknown chain"))?;
+    let resp = adapter
+        .query_state(&query)
+        .await
+        .map_err(|e| Status::unavailable(e.to_string()))?;
+    Ok(Json(serde_json::json!({ "result": resp.result })))
+}
+
+async fn bridge_metrics(State(state): State<RpcState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "chains": state.bridge_metrics.snapshot() }))
+}
+
+#[derive(Deserialize)]
+struct BridgeMessagesQuery {
+    status: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Queued bridge messages in one status (`pending`, `submitted`, `confirmed`
+/// or `failed`), or in each, oldest next attempt first within a status.
+async fn bridge_messages(
+    State(state): State<RpcState>,
+    Query(query): Query<BridgeMessagesQuery>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_BRIDGE_PAGE);
+    let statuses = match query.status.as_deref() {
+        Some(name) => vec![BridgeMessageStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == name)
+            .ok_or_else(|| Status::invalid_argument("unknown status"))?],
+        None => BridgeMessageStatus::ALL.to_vec(),
+    };
+    let mut messages = Vec::new();
+    for status in statuses {
+        let page = state.store.list_messages(status, limit).await.map_err(|_| Status::internal("db error"))?;
+        messages.extend(page);
+    }
+    Ok(Json(serde_json::json!({ "messages": messages })))
+}
+
+async fn bridge_message(
+    State(state): State<RpcState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let record = state
+        .store
+        .get_message(&id)
+        .await
+        .map_err(|_| Status::internal("db error"))?
+        .ok_or_else(|| Status::not_found("unknown message"))?;
+    Ok(Json(serde_json::json!({ "message": record })))
+}
+
+async fn bridge_audit(
+    State(state): State<RpcState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let events = state
+        .store
+        .audit_log(&id)
+        .await
+        .map_err(|_| Status::internal("db error"))?;
+    Ok(Json(serde_json::json!({ "events": events })))
 }
 
 #[derive(Clone)]
@@ -133,18 +1108,10 @@ impl proto::dxid_server::Dxid for GrpcService {
         &self,
         _request: Request<proto::StatusRequest>,
     ) -> Result<Response<proto::StatusResponse>, Status> {
-        let height = self
-            .state
-            .store
-            .get_block_by_height(0)
-            .await
-            .ok()
-            .flatten()
-            .map(|b| b.header.height)
-            .unwrap_or(0);
+        let height = self.state.best_height().await?;
         let reply = proto::StatusResponse {
             height,
-            peers: 0,
+            peers: self.state.network.peers() as u64,
             version: "0.1.0".into(),
         };
         Ok(Response::new(reply))
@@ -185,22 +1152,17 @@ impl proto::dxid_server::Dxid for GrpcService {
         request: Request<proto::AiQueryRequest>,
     ) -> Result<Response<proto::AiQueryResponse>, Status> {
         let prompt = request.into_inner().prompt;
-        let answer = self
-            .state
-            .hypervisor
-            .query(&prompt)
-            .await
-            .map_err(|_| Status::internal("ai error"))?;
+        let answer = self.state.hypervisor.query(&prompt).await.map_err(ai_status)?;
         Ok(Response::new(proto::AiQueryResponse { answer }))
     }
 }
 
-async fn run_grpc(addr: SocketAddr, state: RpcState) -> Result<()> {
+async fn run_grpc(addr: SocketAddr, state: RpcState, shutdown: impl Future<Output = ()>) -> Result<()> {
     info!("gRPC listening on {addr}");
     let svc = GrpcService { state };
     Server::builder()
         .add_service(proto::dxid_server::DxidServer::new(svc))
-        .serve(addr)
+        .serve_with_shutdown(addr, shutdown)
         .await?;
     Ok(())
 }
@@ -0,0 +1,76 @@
+//! Per-client request limits for the REST API, replaceable while the node
+//! runs when `api.rate_limit` is reloaded.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use dxid_config::RateLimitSettings;
+
+/// Clients tracked before idle ones are forgotten.
+const MAX_CLIENTS: usize = 10_000;
+/// A bucket untouched this long is full again and can be dropped.
+const IDLE: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+pub struct RateLimiter {
+    settings: RwLock<RateLimitSettings>,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self { settings: RwLock::new(settings), buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// Applies `settings` to the next request, with every client's bucket full.
+    pub fn update(&self, settings: RateLimitSettings) {
+        *self.settings.write().unwrap() = settings;
+        self.buckets.lock().unwrap().clear();
+    }
+
+    /// Takes a token from `client`'s bucket, if it has one.
+    pub fn allow(&self, client: IpAddr) -> bool {
+        let settings = self.settings.read().unwrap().clone();
+        if settings.requests_per_second == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let burst = settings.burst as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_CLIENTS {
+            buckets.retain(|_, bucket| now.duration_since(bucket.refilled) < IDLE);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket { tokens: burst, refilled: now });
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * settings.requests_per_second as f64).min(burst);
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+/// Middleware answering `429 Too Many Requests` once a client is over its limit.
+pub async fn limit<B>(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !limiter.allow(client.ip()) {
+        return (StatusCode::TOO_MANY_REQUESTS, "rate limit exceeded").into_response();
+    }
+    next.run(request).await
+}
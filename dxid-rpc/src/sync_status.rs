@@ -0,0 +1,61 @@
+//! Chain sync progress the node's sync service keeps for `/status`.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use serde::Serialize;
+
+#[derive(Debug, Default)]
+pub struct SyncStatus {
+    following: AtomicBool,
+    height: AtomicU64,
+    orphans: AtomicU64,
+    reorgs: AtomicU64,
+    deepest_reorg: AtomicU64,
+}
+
+/// What `/status` reports of chain sync.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncReport {
+    /// Height of the tip the node applied.
+    pub height: u64,
+    /// Gossiped blocks waiting for their parent.
+    pub orphans: u64,
+    pub reorgs: u64,
+    /// Most blocks one reorg reverted.
+    pub deepest_reorg: u64,
+}
+
+impl SyncStatus {
+    /// Marks the chain as followed, from a tip at `height`.
+    pub fn follow(&self, height: u64) {
+        self.height.store(height, Ordering::Relaxed);
+        self.following.store(true, Ordering::Relaxed);
+    }
+
+    pub fn set_height(&self, height: u64) {
+        self.height.store(height, Ordering::Relaxed);
+    }
+
+    pub fn set_orphans(&self, orphans: usize) {
+        self.orphans.store(orphans as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a reorg that reverted `depth` blocks.
+    pub fn reorged(&self, depth: usize) {
+        self.reorgs.fetch_add(1, Ordering::Relaxed);
+        self.deepest_reorg.fetch_max(depth as u64, Ordering::Relaxed);
+    }
+
+    /// `None` while the node does not follow the chain.
+    pub fn report(&self) -> Option<SyncReport> {
+        if !self.following.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(SyncReport {
+            height: self.height.load(Ordering::Relaxed),
+            orphans: self.orphans.load(Ordering::Relaxed),
+            reorgs: self.reorgs.load(Ordering::Relaxed),
+            deepest_reorg: self.deepest_reorg.load(Ordering::Relaxed),
+        })
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::Row;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::PgStore;
+
+/// One question put to the AI hypervisor and what it disclosed in answer,
+/// as redacted before it was stored.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AiAuditRecord {
+    pub id: Uuid,
+    pub at: u64,
+    #[serde(default)]
+    pub session: Option<Uuid>,
+    /// The provider that answered, or `offline`.
+    pub provider: String,
+    pub model: String,
+    pub prompt: String,
+    /// The sources given with the question.
+    pub context: Value,
+    pub tool_calls: Value,
+    /// The answer, or what the answer had streamed before `error`.
+    pub response: String,
+    pub offline: bool,
+    pub streamed: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[async_trait]
+pub trait AiAuditStore: Send + Sync {
+    async fn append_ai_audit(&self, record: &AiAuditRecord) -> Result<()>;
+    /// Up to `limit` records from before `before`, or the latest, newest first.
+    async fn ai_audit(&self, before: Option<u64>, limit: i64) -> Result<Vec<AiAuditRecord>>;
+    /// Deletes the records from before `before`, returning how many there were.
+    async fn prune_ai_audit(&self, before: u64) -> Result<u64>;
+}
+
+#[async_trait]
+impl AiAuditStore for PgStore {
+    async fn append_ai_audit(&self, record: &AiAuditRecord) -> Result<()> {
+        sqlx::query("INSERT INTO ai_audit(id, at, data) VALUES ($1, $2, $3)")
+            .bind(record.id)
+            .bind(record.at as i64)
+            .bind(json!(record))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn ai_audit(&self, before: Option<u64>, limit: i64) -> Result<Vec<AiAuditRecord>> {
+        let before = before.map_or(i64::MAX, |at| at as i64);
+        let rows = sqlx::query("SELECT data FROM ai_audit WHERE at < $1 ORDER BY at DESC, id LIMIT $2")
+            .bind(before)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(|row| Ok(serde_json::from_value(row.try_get("data")?)?)).collect()
+    }
+
+    async fn prune_ai_audit(&self, before: u64) -> Result<u64> {
+        let result =
+            sqlx::query("DELETE FROM ai_audit WHERE at < $1").bind(before as i64).execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// In-memory [`AiAuditStore`] for tests and tools.
+#[derive(Default)]
+pub struct MemoryAiAuditStore {
+    records: RwLock<Vec<AiAuditRecord>>,
+}
+
+impl MemoryAiAuditStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AiAuditStore for MemoryAiAuditStore {
+    async fn append_ai_audit(&self, record: &AiAuditRecord) -> Result<()> {
+        self.records.write().await.push(record.clone());
+        Ok(())
+    }
+
+    async fn ai_audit(&self, before: Option<u64>, limit: i64) -> Result<Vec<AiAuditRecord>> {
+        let records = self.records.read().await;
+        Ok(records
+            .iter()
+            .rev()
+            .filter(|record| record.at < before.unwrap_or(u64::MAX))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn prune_ai_audit(&self, before: u64) -> Result<u64> {
+        let mut records = self.records.write().await;
+        let count = records.len();
+        records.retain(|record| record.at >= before);
+        Ok((count - records.len()) as u64)
+    }
+}
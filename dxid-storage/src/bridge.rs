@@ -0,0 +1,632 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dxid_core::{CrossChainMessage, CrossChainTx};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgConnection, Row};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::PgStore;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum BridgeMessageStatus {
+    /// Waiting to be (re)submitted to the destination chain.
+    Pending,
+    /// Accepted by the destination, waiting for confirmations.
+    Submitted,
+    Confirmed,
+    /// Terminal failure; the relayer will not retry.
+    Failed,
+}
+
+impl BridgeMessageStatus {
+    pub const ALL: [BridgeMessageStatus; 4] = [
+        BridgeMessageStatus::Pending,
+        BridgeMessageStatus::Submitted,
+        BridgeMessageStatus::Confirmed,
+        BridgeMessageStatus::Failed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BridgeMessageStatus::Pending => "pending",
+            BridgeMessageStatus::Submitted => "submitted",
+            BridgeMessageStatus::Confirmed => "confirmed",
+            BridgeMessageStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeMessageRecord {
+    pub tx: CrossChainTx,
+    pub status: BridgeMessageStatus,
+    pub attempts: u32,
+    /// Unix seconds before which the relayer should not retry.
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    /// Destination receipt, stored as returned by the adapter.
+    pub receipt: Option<serde_json::Value>,
+    #[serde(default)]
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl BridgeMessageRecord {
+    pub fn new(tx: CrossChainTx, now: u64) -> Self {
+        Self {
+            tx,
+            status: BridgeMessageStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            receipt: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.tx.message.id
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum InboundStatus {
+    /// Verified and stored, delivery transaction not yet accepted or lost
+    /// before a block included it.
+    Received,
+    /// Its delivery transaction, `delivery_tx`, was accepted and waits to be
+    /// buried deep enough in the main chain.
+    Submitted,
+    Delivered,
+    /// Its source block left the source chain's canonical chain before it
+    /// was final; another message may take its `(source, nonce)`.
+    Dropped,
+}
+
+impl InboundStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InboundStatus::Received => "received",
+            InboundStatus::Submitted => "submitted",
+            InboundStatus::Delivered => "delivered",
+            InboundStatus::Dropped => "dropped",
+        }
+    }
+}
+
+/// A message from an external chain addressed to dxid. Unique per
+/// `(source, nonce)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboundRecord {
+    pub message: CrossChainMessage,
+    pub proof: Option<serde_json::Value>,
+    /// Height and hash of the source block that included the message, if
+    /// its adapter reported it.
+    #[serde(default)]
+    pub source_block: Option<(u64, [u8; 32])>,
+    pub status: InboundStatus,
+    /// Hex hash of the delivery transaction once submitted.
+    pub delivery_tx: Option<String>,
+    pub last_error: Option<String>,
+    pub received_at: u64,
+    pub updated_at: u64,
+}
+
+impl InboundRecord {
+    pub fn new(message: CrossChainMessage, proof: Option<serde_json::Value>, now: u64) -> Self {
+        Self {
+            message,
+            proof,
+            source_block: None,
+            status: InboundStatus::Received,
+            delivery_tx: None,
+            last_error: None,
+            received_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum WebhookStatus {
+    Pending,
+    Delivered,
+    /// Gave up after the configured number of attempts.
+    Failed,
+}
+
+impl WebhookStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookStatus::Pending => "pending",
+            WebhookStatus::Delivered => "delivered",
+            WebhookStatus::Failed => "failed",
+        }
+    }
+}
+
+/// One webhook call for one bridge message status change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub message_id: Uuid,
+    pub url: String,
+    pub event: serde_json::Value,
+    pub status: WebhookStatus,
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    /// HTTP status of the last response, if one was received.
+    pub response_status: Option<u16>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl WebhookDelivery {
+    pub fn new(message_id: Uuid, url: String, event: serde_json::Value, now: u64) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            message_id,
+            url,
+            event,
+            status: WebhookStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            response_status: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum AuditKind {
+    ProofGenerated,
+    MessageSent,
+    ReceiptReceived,
+    Confirmed,
+    Failure,
+}
+
+impl AuditKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditKind::ProofGenerated => "proof_generated",
+            AuditKind::MessageSent => "message_sent",
+            AuditKind::ReceiptReceived => "receipt_received",
+            AuditKind::Confirmed => "confirmed",
+            AuditKind::Failure => "failure",
+        }
+    }
+}
+
+/// One append-only entry in the bridge audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub kind: AuditKind,
+    pub chain: String,
+    /// Absent for events covering several messages, such as batch proofs.
+    pub message_id: Option<Uuid>,
+    /// Unix milliseconds.
+    pub at_ms: u64,
+    /// How long the audited operation took.
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub detail: serde_json::Value,
+}
+
+#[async_trait]
+pub trait BridgeStore: Send + Sync {
+    /// Queues `record` under the next nonce for its destination and returns
+    /// it, or returns the queued record if its id is taken. Concurrent
+    /// enqueues of one id allocate a single nonce.
+    async fn enqueue_message(&self, record: &BridgeMessageRecord) -> Result<BridgeMessageRecord>;
+    async fn get_message(&self, id: &Uuid) -> Result<Option<BridgeMessageRecord>>;
+    /// Returns records in `status`, oldest `next_attempt_at` first.
+    async fn list_messages(
+        &self,
+        status: BridgeMessageStatus,
+        limit: i64,
+    ) -> Result<Vec<BridgeMessageRecord>>;
+    async fn update_message(&self, record: &BridgeMessageRecord) -> Result<()>;
+
+    /// Stores an inbound message; returns `false` if `(source, nonce)` was
+    /// already recorded by a message that was not dropped.
+    async fn record_inbound(&self, record: &InboundRecord) -> Result<bool>;
+    async fn list_inbound(&self, status: InboundStatus, limit: i64) -> Result<Vec<InboundRecord>>;
+    async fn update_inbound(&self, record: &InboundRecord) -> Result<()>;
+    /// Adapter-defined position up to which a chain's outbox has been read.
+    async fn inbound_cursor(&self, chain: &str) -> Result<u64>;
+    async fn set_inbound_cursor(&self, chain: &str, cursor: u64) -> Result<()>;
+    /// Next outbound nonce for `dest`, starting at 1. Never repeats.
+    async fn allocate_nonce(&self, dest: &str) -> Result<u64>;
+
+    async fn enqueue_webhook(&self, delivery: &WebhookDelivery) -> Result<()>;
+    /// Returns deliveries in `status`, oldest `next_attempt_at` first.
+    async fn list_webhooks(&self, status: WebhookStatus, limit: i64) -> Result<Vec<WebhookDelivery>>;
+    async fn update_webhook(&self, delivery: &WebhookDelivery) -> Result<()>;
+    /// Every webhook delivery made for `message_id`, oldest first.
+    async fn webhook_log(&self, message_id: &Uuid) -> Result<Vec<WebhookDelivery>>;
+
+    async fn append_audit(&self, event: &AuditEvent) -> Result<()>;
+    /// Audit entries for `message_id`, oldest first.
+    async fn audit_log(&self, message_id: &Uuid) -> Result<Vec<AuditEvent>>;
+}
+
+#[async_trait]
+impl BridgeStore for PgStore {
+    async fn enqueue_message(&self, record: &BridgeMessageRecord) -> Result<BridgeMessageRecord> {
+        let mut record = record.clone();
+        let mut db = self.pool.begin().await?;
+        // The insert takes the id first: a concurrent enqueue of it waits for
+        // this transaction and then finds it taken, without drawing a nonce.
+        let inserted = sqlx::query(
+            "INSERT INTO bridge_messages(id, status, next_attempt_at, data) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(record.id())
+        .bind(record.status.as_str())
+        .bind(record.next_attempt_at as i64)
+        .bind(json!(record))
+        .execute(&mut *db)
+        .await?
+        .rows_affected()
+            == 1;
+        if !inserted {
+            db.rollback().await?;
+            return self
+                .get_message(&record.id())
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("bridge message {} vanished", record.id()));
+        }
+        record.tx.message.nonce = next_nonce(&mut db, &record.tx.message.dest).await?;
+        sqlx::query("UPDATE bridge_messages SET data = $2 WHERE id = $1")
+            .bind(record.id())
+            .bind(json!(record))
+            .execute(&mut *db)
+            .await?;
+        db.commit().await?;
+        Ok(record)
+    }
+
+    async fn get_message(&self, id: &Uuid) -> Result<Option<BridgeMessageRecord>> {
+        let row = sqlx::query("SELECT data FROM bridge_messages WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        if let Some(row) = row {
+            let value: serde_json::Value = row.try_get("data")?;
+            Ok(Some(serde_json::from_value(value)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn list_messages(
+        &self,
+        status: BridgeMessageStatus,
+        limit: i64,
+    ) -> Result<Vec<BridgeMessageRecord>> {
+        let rows = sqlx::query(
+            "SELECT data FROM bridge_messages WHERE status = $1 ORDER BY next_attempt_at LIMIT $2",
+        )
+        .bind(status.as_str())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for row in rows {
+            let value: serde_json::Value = row.try_get("data")?;
+            out.push(serde_json::from_value(value)?);
+        }
+        Ok(out)
+    }
+
+    async fn update_message(&self, record: &BridgeMessageRecord) -> Result<()> {
+        sqlx::query("UPDATE bridge_messages SET status = $2, next_attempt_at = $3, data = $4 WHERE id = $1")
+            .bind(record.id())
+            .bind(record.status.as_str())
+            .bind(record.next_attempt_at as i64)
+            .bind(json!(record))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn record_inbound(&self, record: &InboundRecord) -> Result<bool> {
+        let res = sqlx::query(
+            "INSERT INTO bridge_inbound(source, nonce, id, status, data) VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (source, nonce) DO UPDATE SET id = $3, status = $4, data = $5
+             WHERE bridge_inbound.status = 'dropped'",
+        )
+        .bind(&record.message.source)
+        .bind(record.message.nonce as i64)
+        .bind(record.message.id)
+        .bind(record.status.as_str())
+        .bind(json!(record))
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn list_inbound(&self, status: InboundStatus, limit: i64) -> Result<Vec<InboundRecord>> {
+        let rows = sqlx::query("SELECT data FROM bridge_inbound WHERE status = $1 ORDER BY nonce LIMIT $2")
+            .bind(status.as_str())
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for row in rows {
+            let value: serde_json::Value = row.try_get("data")?;
+            out.push(serde_json::from_value(value)?);
+        }
+        Ok(out)
+    }
+
+    async fn update_inbound(&self, record: &InboundRecord) -> Result<()> {
+        sqlx::query("UPDATE bridge_inbound SET status = $3, data = $4 WHERE source = $1 AND nonce = $2")
+            .bind(&record.message.source)
+            .bind(record.message.nonce as i64)
+            .bind(record.status.as_str())
+            .bind(json!(record))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn inbound_cursor(&self, chain: &str) -> Result<u64> {
+        let row = sqlx::query("SELECT cursor FROM bridge_cursors WHERE chain = $1")
+            .bind(chain)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(match row {
+            Some(row) => row.try_get::<i64, _>("cursor")? as u64,
+            None => 0,
+        })
+    }
+
+    async fn set_inbound_cursor(&self, chain: &str, cursor: u64) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO bridge_cursors(chain, cursor) VALUES ($1, $2)
+             ON CONFLICT (chain) DO UPDATE SET cursor = EXCLUDED.cursor",
+        )
+        .bind(chain)
+        .bind(cursor as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn allocate_nonce(&self, dest: &str) -> Result<u64> {
+        next_nonce(&mut *self.pool.acquire().await?, dest).await
+    }
+
+    async fn enqueue_webhook(&self, delivery: &WebhookDelivery) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO bridge_webhooks(id, message_id, status, next_attempt_at, data)
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(delivery.id)
+        .bind(delivery.message_id)
+        .bind(delivery.status.as_str())
+        .bind(delivery.next_attempt_at as i64)
+        .bind(json!(delivery))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_webhooks(&self, status: WebhookStatus, limit: i64) -> Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query(
+            "SELECT data FROM bridge_webhooks WHERE status = $1 ORDER BY next_attempt_at LIMIT $2",
+        )
+        .bind(status.as_str())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for row in rows {
+            let value: serde_json::Value = row.try_get("data")?;
+            out.push(serde_json::from_value(value)?);
+        }
+        Ok(out)
+    }
+
+    async fn update_webhook(&self, delivery: &WebhookDelivery) -> Result<()> {
+        sqlx::query("UPDATE bridge_webhooks SET status = $2, next_attempt_at = $3, data = $4 WHERE id = $1")
+            .bind(delivery.id)
+            .bind(delivery.status.as_str())
+            .bind(delivery.next_attempt_at as i64)
+            .bind(json!(delivery))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn webhook_log(&self, message_id: &Uuid) -> Result<Vec<WebhookDelivery>> {
+        let rows = sqlx::query(
+            "SELECT data FROM bridge_webhooks WHERE message_id = $1 ORDER BY (data->>'created_at')::bigint",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for row in rows {
+            let value: serde_json::Value = row.try_get("data")?;
+            out.push(serde_json::from_value(value)?);
+        }
+        Ok(out)
+    }
+
+    async fn append_audit(&self, event: &AuditEvent) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO bridge_audit(message_id, chain, kind, at_ms, data) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(event.message_id)
+        .bind(&event.chain)
+        .bind(event.kind.as_str())
+        .bind(event.at_ms as i64)
+        .bind(json!(event))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn audit_log(&self, message_id: &Uuid) -> Result<Vec<AuditEvent>> {
+        let rows = sqlx::query("SELECT data FROM bridge_audit WHERE message_id = $1 ORDER BY seq")
+            .bind(message_id)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for row in rows {
+            let value: serde_json::Value = row.try_get("data")?;
+            out.push(serde_json::from_value(value)?);
+        }
+        Ok(out)
+    }
+}
+
+async fn next_nonce(db: &mut PgConnection, dest: &str) -> Result<u64> {
+    let row = sqlx::query(
+        "INSERT INTO bridge_nonces(chain, last_nonce) VALUES ($1, 1)
+         ON CONFLICT (chain) DO UPDATE SET last_nonce = bridge_nonces.last_nonce + 1
+         RETURNING last_nonce",
+    )
+    .bind(dest)
+    .fetch_one(db)
+    .await?;
+    Ok(row.try_get::<i64, _>("last_nonce")? as u64)
+}
+
+/// Process-local bridge store for tests and Postgres-free tooling.
+#[derive(Default)]
+pub struct MemoryBridgeStore {
+    messages: RwLock<HashMap<Uuid, BridgeMessageRecord>>,
+    inbound: RwLock<HashMap<(String, u64), InboundRecord>>,
+    cursors: RwLock<HashMap<String, u64>>,
+    nonces: RwLock<HashMap<String, u64>>,
+    webhooks: RwLock<HashMap<Uuid, WebhookDelivery>>,
+    audit: RwLock<Vec<AuditEvent>>,
+}
+
+impl MemoryBridgeStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl BridgeStore for MemoryBridgeStore {
+    async fn enqueue_message(&self, record: &BridgeMessageRecord) -> Result<BridgeMessageRecord> {
+        let mut messages = self.messages.write().await;
+        if let Some(existing) = messages.get(&record.id()) {
+            return Ok(existing.clone());
+        }
+        let mut record = record.clone();
+        record.tx.message.nonce = self.allocate_nonce(&record.tx.message.dest).await?;
+        messages.insert(record.id(), record.clone());
+        Ok(record)
+    }
+
+    async fn get_message(&self, id: &Uuid) -> Result<Option<BridgeMessageRecord>> {
+        Ok(self.messages.read().await.get(id).cloned())
+    }
+
+    async fn list_messages(
+        &self,
+        status: BridgeMessageStatus,
+        limit: i64,
+    ) -> Result<Vec<BridgeMessageRecord>> {
+        let map = self.messages.read().await;
+        let mut out: Vec<_> = map.values().filter(|r| r.status == status).cloned().collect();
+        out.sort_by_key(|r| r.next_attempt_at);
+        out.truncate(limit.max(0) as usize);
+        Ok(out)
+    }
+
+    async fn update_message(&self, record: &BridgeMessageRecord) -> Result<()> {
+        self.messages.write().await.insert(record.id(), record.clone());
+        Ok(())
+    }
+
+    async fn record_inbound(&self, record: &InboundRecord) -> Result<bool> {
+        let key = (record.message.source.clone(), record.message.nonce);
+        let mut map = self.inbound.write().await;
+        if map.get(&key).is_some_and(|r| r.status != InboundStatus::Dropped) {
+            return Ok(false);
+        }
+        map.insert(key, record.clone());
+        Ok(true)
+    }
+
+    async fn list_inbound(&self, status: InboundStatus, limit: i64) -> Result<Vec<InboundRecord>> {
+        let map = self.inbound.read().await;
+        let mut out: Vec<_> = map.values().filter(|r| r.status == status).cloned().collect();
+        out.sort_by_key(|r| r.message.nonce);
+        out.truncate(limit.max(0) as usize);
+        Ok(out)
+    }
+
+    async fn update_inbound(&self, record: &InboundRecord) -> Result<()> {
+        let key = (record.message.source.clone(), record.message.nonce);
+        self.inbound.write().await.insert(key, record.clone());
+        Ok(())
+    }
+
+    async fn inbound_cursor(&self, chain: &str) -> Result<u64> {
+        Ok(self.cursors.read().await.get(chain).copied().unwrap_or(0))
+    }
+
+    async fn set_inbound_cursor(&self, chain: &str, cursor: u64) -> Result<()> {
+        self.cursors.write().await.insert(chain.to_string(), cursor);
+        Ok(())
+    }
+
+    async fn allocate_nonce(&self, dest: &str) -> Result<u64> {
+        let mut nonces = self.nonces.write().await;
+        let nonce = nonces.entry(dest.to_string()).or_insert(0);
+        *nonce += 1;
+        Ok(*nonce)
+    }
+
+    async fn enqueue_webhook(&self, delivery: &WebhookDelivery) -> Result<()> {
+        self.webhooks.write().await.insert(delivery.id, delivery.clone());
+        Ok(())
+    }
+
+    async fn list_webhooks(&self, status: WebhookStatus, limit: i64) -> Result<Vec<WebhookDelivery>> {
+        let map = self.webhooks.read().await;
+        let mut out: Vec<_> = map.values().filter(|d| d.status == status).cloned().collect();
+        out.sort_by_key(|d| d.next_attempt_at);
+        out.truncate(limit.max(0) as usize);
+        Ok(out)
+    }
+
+    async fn update_webhook(&self, delivery: &WebhookDelivery) -> Result<()> {
+        self.webhooks.write().await.insert(delivery.id, delivery.clone());
+        Ok(())
+    }
+
+    async fn webhook_log(&self, message_id: &Uuid) -> Result<Vec<WebhookDelivery>> {
+        let map = self.webhooks.read().await;
+        let mut out: Vec<_> = map.values().filter(|d| d.message_id == *message_id).cloned().collect();
+        out.sort_by_key(|d| d.created_at);
+        Ok(out)
+    }
+
+    async fn append_audit(&self, event: &AuditEvent) -> Result<()> {
+        self.audit.write().await.push(event.clone());
+        Ok(())
+    }
+
+    async fn audit_log(&self, message_id: &Uuid) -> Result<Vec<AuditEvent>> {
+        let log = self.audit.read().await;
+        Ok(log.iter().filter(|e| e.message_id.as_ref() == Some(message_id)).cloned().collect())
+    }
+}
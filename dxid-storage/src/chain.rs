@@ -0,0 +1,166 @@
+//! Moves the stored main chain in one database transaction, so a crash
+//! leaves either the old tip or the new one.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dxid_core::coinbase::is_coinbase;
+use dxid_core::contract::TxReceipt;
+use dxid_core::staking::StakingPosition;
+use dxid_core::{Address, Block, Transaction};
+use serde_json::json;
+use sqlx::PgConnection;
+
+use crate::PgStore;
+
+/// How the main chain moved: the blocks taken off and put on it, and the
+/// balances and staking positions that differ afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct ChainUpdate {
+    /// Reverted blocks, old tip first.
+    pub reverted: Vec<Block>,
+    /// Applied blocks, lowest first, with their receipts.
+    pub applied: Vec<(Block, Vec<TxReceipt>)>,
+    pub balances: Vec<(Address, u64)>,
+    pub positions: Vec<(Address, StakingPosition)>,
+}
+
+#[async_trait]
+pub trait ChainStore: Send + Sync {
+    /// Writes `update` whole or not at all.
+    async fn commit_chain(&self, update: &ChainUpdate) -> Result<()>;
+}
+
+#[async_trait]
+impl ChainStore for PgStore {
+    async fn commit_chain(&self, update: &ChainUpdate) -> Result<()> {
+        let mut db = self.pool.begin().await?;
+        for block in &update.reverted {
+            for tx in block.transactions.iter().rev() {
+                unrecord_transaction(&mut db, tx).await?;
+            }
+            let height = block.header.height as i64;
+            sqlx::query("DELETE FROM tx_receipts WHERE block_height = $1")
+                .bind(height)
+                .execute(&mut *db)
+                .await?;
+            sqlx::query("DELETE FROM blocks WHERE height = $1").bind(height).execute(&mut *db).await?;
+        }
+        for (block, receipts) in &update.applied {
+            insert_block(&mut db, block).await?;
+            for tx in &block.transactions {
+                record_transaction(&mut db, tx).await?;
+            }
+            for receipt in receipts {
+                insert_receipt(&mut db, receipt).await?;
+            }
+        }
+        for (address, amount) in &update.balances {
+            set_balance(&mut db, address, *amount).await?;
+        }
+        for (owner, position) in &update.positions {
+            put_staking_position(&mut db, owner, position).await?;
+        }
+        db.commit().await?;
+        Ok(())
+    }
+}
+
+pub(crate) async fn insert_block(db: &mut PgConnection, block: &Block) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO blocks(height, data) VALUES ($1, $2) \
+         ON CONFLICT (height) DO UPDATE SET data = EXCLUDED.data",
+    )
+    .bind(block.header.height as i64)
+    .bind(json!(block))
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Spends `tx`'s inputs, adds its outputs and takes it out of the pending
+/// ones. A coinbase's outputs keep the height of its block, its nonce.
+pub(crate) async fn record_transaction(db: &mut PgConnection, tx: &Transaction) -> Result<()> {
+    let hash = tx.hash();
+    let coinbase_height = is_coinbase(tx).then_some(tx.nonce as i64);
+    for input in &tx.inputs {
+        sqlx::query("UPDATE utxos SET spent = TRUE WHERE tx_hash = $1 AND output_index = $2")
+            .bind(input.previous_tx.as_slice())
+            .bind(input.output_index as i32)
+            .execute(&mut *db)
+            .await?;
+    }
+    for (index, output) in tx.outputs.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO utxos(tx_hash, output_index, address, amount, condition, spendable_after_height,
+             coinbase_height) VALUES ($1, $2, $3, $4, $5, $6, $7)
+             ON CONFLICT (tx_hash, output_index) DO NOTHING",
+        )
+        .bind(hash.as_slice())
+        .bind(index as i32)
+        .bind(output.address.as_slice())
+        .bind(output.amount as i64)
+        .bind(output.condition.as_ref().map(|condition| json!(condition)))
+        .bind(output.spendable_after_height.map(|height| height as i64))
+        .bind(coinbase_height)
+        .execute(&mut *db)
+        .await?;
+    }
+    sqlx::query("DELETE FROM pending_txs WHERE hash = $1").bind(hash.as_slice()).execute(&mut *db).await?;
+    Ok(())
+}
+
+/// Drops `tx`'s outputs and makes its inputs unspent again.
+async fn unrecord_transaction(db: &mut PgConnection, tx: &Transaction) -> Result<()> {
+    sqlx::query("DELETE FROM utxos WHERE tx_hash = $1").bind(tx.hash().as_slice()).execute(&mut *db).await?;
+    for input in &tx.inputs {
+        sqlx::query("UPDATE utxos SET spent = FALSE WHERE tx_hash = $1 AND output_index = $2")
+            .bind(input.previous_tx.as_slice())
+            .bind(input.output_index as i32)
+            .execute(&mut *db)
+            .await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn insert_receipt(db: &mut PgConnection, receipt: &TxReceipt) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO tx_receipts(tx_hash, block_height, tx_index, data) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (tx_hash) DO UPDATE SET block_height = EXCLUDED.block_height, \
+         tx_index = EXCLUDED.tx_index, data = EXCLUDED.data",
+    )
+    .bind(receipt.tx_hash.as_slice())
+    .bind(receipt.block_height as i64)
+    .bind(receipt.index as i32)
+    .bind(json!(receipt))
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn set_balance(db: &mut PgConnection, address: &Address, amount: u64) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO balances(address, amount) VALUES ($1, $2) \
+         ON CONFLICT (address) DO UPDATE SET amount = EXCLUDED.amount",
+    )
+    .bind(address.as_slice())
+    .bind(amount as i64)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn put_staking_position(
+    db: &mut PgConnection,
+    owner: &Address,
+    position: &StakingPosition,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO staking_positions(address, data) VALUES ($1, $2) \
+         ON CONFLICT (address) DO UPDATE SET data = EXCLUDED.data",
+    )
+    .bind(owner.as_slice())
+    .bind(json!(position))
+    .execute(db)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dxid_core::Address;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+use tokio::sync::RwLock;
+
+use crate::PgStore;
+
+/// Code a contract ran before an upgrade; the bytes are in the blob store.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContractVersionRecord {
+    pub code_hash: [u8; 32],
+    pub abi: serde_json::Value,
+    pub replaced_at: u64,
+}
+
+/// What is needed to bring a deployed contract back after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContractRecord {
+    pub address: Address,
+    /// Hash of the contract's code in the blob store; `None` for built-in contracts.
+    pub code_hash: Option<[u8; 32]>,
+    /// Name of the built-in contract, for native ones.
+    #[serde(default)]
+    pub native: Option<String>,
+    pub abi: serde_json::Value,
+    /// Upgrade policy, naming the admin or governance voters.
+    pub upgrade_policy: serde_json::Value,
+    #[serde(default)]
+    pub history: Vec<ContractVersionRecord>,
+}
+
+/// Deployed contracts and a content-addressed store of their code.
+#[async_trait]
+pub trait ContractStore: Send + Sync {
+    async fn put_code(&self, code_hash: &[u8; 32], code: &[u8]) -> Result<()>;
+    async fn get_code(&self, code_hash: &[u8; 32]) -> Result<Option<Vec<u8>>>;
+    async fn put_contract(&self, record: &ContractRecord) -> Result<()>;
+    async fn list_contracts(&self) -> Result<Vec<ContractRecord>>;
+}
+
+#[async_trait]
+impl ContractStore for PgStore {
+    async fn put_code(&self, code_hash: &[u8; 32], code: &[u8]) -> Result<()> {
+        sqlx::query("INSERT INTO contract_code(hash, code) VALUES ($1, $2) ON CONFLICT (hash) DO NOTHING")
+            .bind(code_hash.as_slice())
+            .bind(code)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_code(&self, code_hash: &[u8; 32]) -> Result<Option<Vec<u8>>> {
+        let row = sqlx::query("SELECT code FROM contract_code WHERE hash = $1")
+            .bind(code_hash.as_slice())
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => Ok(Some(row.try_get("code")?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put_contract(&self, record: &ContractRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO contracts(address, data) VALUES ($1, $2) \
+             ON CONFLICT (address) DO UPDATE SET data = EXCLUDED.data",
+        )
+        .bind(record.address.as_slice())
+        .bind(json!(record))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_contracts(&self) -> Result<Vec<ContractRecord>> {
+        let rows = sqlx::query("SELECT data FROM contracts ORDER BY address").fetch_all(&self.pool).await?;
+        rows.into_iter().map(|row| Ok(serde_json::from_value(row.try_get("data")?)?)).collect()
+    }
+}
+
+/// In-memory [`ContractStore`] for tests and tools.
+#[derive(Default)]
+pub struct MemoryContractStore {
+    code: RwLock<HashMap<[u8; 32], Vec<u8>>>,
+    contracts: RwLock<HashMap<Address, ContractRecord>>,
+}
+
+impl MemoryContractStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ContractStore for MemoryContractStore {
+    async fn put_code(&self, code_hash: &[u8; 32], code: &[u8]) -> Result<()> {
+        self.code.write().await.entry(*code_hash).or_insert_with(|| code.to_vec());
+        Ok(())
+    }
+
+    async fn get_code(&self, code_hash: &[u8; 32]) -> Result<Option<Vec<u8>>> {
+        Ok(self.code.read().await.get(code_hash).cloned())
+    }
+
+    async fn put_contract(&self, record: &ContractRecord) -> Result<()> {
+        self.contracts.write().await.insert(record.address, record.clone());
+        Ok(())
+    }
+
+    async fn list_contracts(&self) -> Result<Vec<ContractRecord>> {
+        let mut out: Vec<_> = self.contracts.read().await.values().cloned().collect();
+        out.sort_by_key(|r| r.address);
+        Ok(out)
+    }
+}
@@ -1,16 +1,40 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use dxid_core::{Address, Block, Identity, IdentityId};
-use dxid_vectors::{Embedding, EmbeddingId};
+use dxid_core::builder::Utxo;
+use dxid_core::contract::TxReceipt;
+use dxid_core::staking::StakingPosition;
+use dxid_core::{Address, Block, Identity, IdentityId, Transaction, TxHash, TxOutput};
+use dxid_vectors::{Embedder, Embedding, EmbeddingId};
 use pgvector::Vector;
 use serde_json::json;
 use sqlx::{postgres::PgPoolOptions, PgPool, Row};
 use tracing::info;
 
+mod ai_audit;
+mod bridge;
+mod chain;
+mod contracts;
+mod sessions;
+mod vectors;
+
+pub use ai_audit::{AiAuditRecord, AiAuditStore, MemoryAiAuditStore};
+pub use bridge::{
+    AuditEvent, AuditKind, BridgeMessageRecord, BridgeMessageStatus, BridgeStore, InboundRecord, InboundStatus,
+    MemoryBridgeStore, WebhookDelivery, WebhookStatus,
+};
+pub use chain::{ChainStore, ChainUpdate};
+pub use contracts::{ContractRecord, ContractStore, ContractVersionRecord, MemoryContractStore};
+pub use sessions::{MemorySessionStore, SessionMessage, SessionRecord, SessionRole, SessionStore};
+pub use vectors::MemoryVectorStore;
+
 #[async_trait]
 pub trait BlockStore: Send + Sync {
     async fn insert_block(&self, block: &Block) -> Result<()>;
     async fn get_block_by_height(&self, height: i64) -> Result<Option<Block>>;
+    /// Height of the highest stored block, `None` before genesis is stored.
+    async fn best_height(&self) -> Result<Option<u64>>;
+    /// Up to `limit` blocks below height `before` (from the tip if `None`), newest first.
+    async fn blocks_before(&self, before: Option<i64>, limit: i64) -> Result<Vec<Block>>;
 }
 
 #[async_trait]
@@ -19,16 +43,71 @@ pub trait StateStore: Send + Sync {
     async fn set_balance(&self, addr: &Address, value: u64) -> Result<()>;
 }
 
+/// Unspent outputs by owner and transactions submitted for inclusion.
+#[async_trait]
+pub trait TxStore: Send + Sync {
+    async fn list_utxos(&self, addr: &Address) -> Result<Vec<Utxo>>;
+    /// Output `index` of `tx`, with its spending condition, while it is unspent.
+    async fn unspent_output(&self, tx: &TxHash, index: u32) -> Result<Option<TxOutput>>;
+    /// Height of the block whose coinbase `tx` is, if it is a stored coinbase.
+    async fn coinbase_height(&self, tx: &TxHash) -> Result<Option<u64>>;
+    /// Marks the outputs `tx` spends as spent and records its own outputs.
+    async fn record_transaction(&self, tx: &Transaction) -> Result<()>;
+    /// Stores a submitted transaction; `false` if it was already pending.
+    async fn insert_pending_tx(&self, tx: &Transaction) -> Result<bool>;
+    async fn pending_txs(&self, limit: i64) -> Result<Vec<Transaction>>;
+    /// Drops pending transactions the mempool replaced or evicted.
+    async fn remove_pending_txs(&self, hashes: &[TxHash]) -> Result<()>;
+    /// Transactions waiting for inclusion.
+    async fn pending_count(&self) -> Result<u64>;
+}
+
 #[async_trait]
 pub trait IdentityStore: Send + Sync {
     async fn put_identity(&self, identity: &Identity) -> Result<()>;
     async fn get_identity(&self, id: &IdentityId) -> Result<Option<Identity>>;
+    /// Up to `limit` identities after `offset`, in id order.
+    async fn list_identities(&self, offset: i64, limit: i64) -> Result<Vec<Identity>>;
+}
+
+/// Transaction receipts, stored next to the block that produced them.
+#[async_trait]
+pub trait ReceiptStore: Send + Sync {
+    async fn insert_receipts(&self, receipts: &[TxReceipt]) -> Result<()>;
+    async fn get_receipt(&self, tx_hash: &TxHash) -> Result<Option<TxReceipt>>;
+    /// Receipts of the block at `height`, in transaction order.
+    async fn block_receipts(&self, height: i64) -> Result<Vec<TxReceipt>>;
+}
+
+/// Per-owner staking positions, written as blocks are applied.
+#[async_trait]
+pub trait StakingStore: Send + Sync {
+    async fn put_staking_position(&self, owner: &Address, position: &StakingPosition) -> Result<()>;
+    async fn get_staking_position(&self, owner: &Address) -> Result<Option<StakingPosition>>;
+    /// Positions bonded to or unbonding from `validator`, with their owners.
+    async fn staking_positions_for(&self, validator: &Address) -> Result<Vec<(Address, StakingPosition)>>;
 }
 
 #[async_trait]
 pub trait VectorStore: Send + Sync {
     async fn insert_embedding(&self, embedding: &Embedding) -> Result<()>;
     async fn knn_search(&self, space: &str, query: &[f32], k: i64) -> Result<Vec<Embedding>>;
+    /// Like [`VectorStore::knn_search`], with each result's Euclidean distance from `query`.
+    async fn knn_search_scored(&self, space: &str, query: &[f32], k: i64) -> Result<Vec<(Embedding, f32)>>;
+    /// Length of the vectors the store holds, `None` when it takes any length.
+    async fn dimensions(&self) -> Result<Option<usize>>;
+}
+
+/// Sets `embedder` to the store's vector length, failing when it cannot
+/// produce vectors of that length; returns the length agreed on.
+pub async fn negotiate_dimensions(store: &dyn VectorStore, embedder: &mut dyn Embedder) -> Result<usize> {
+    match store.dimensions().await? {
+        Some(dimensions) => {
+            embedder.set_dimensions(dimensions)?;
+            Ok(dimensions)
+        }
+        None => Ok(embedder.dimensions()),
+    }
 }
 
 #[derive(Clone)]
@@ -47,6 +126,11 @@ impl PgStore {
         Ok(store)
     }
 
+    /// Waits for the connections in use to be returned and closes them all.
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
     async fn migrate(&self) -> Result<()> {
         // Minimal schema creation. In production this would be handled by migration files.
         sqlx::query(
@@ -59,16 +143,103 @@ impl PgStore {
             address BYTEA PRIMARY KEY,
             amount BIGINT NOT NULL
         );
+        CREATE TABLE IF NOT EXISTS utxos(
+            tx_hash BYTEA NOT NULL,
+            output_index INT NOT NULL,
+            address BYTEA NOT NULL,
+            amount BIGINT NOT NULL,
+            spent BOOLEAN NOT NULL DEFAULT FALSE,
+            condition JSONB,
+            spendable_after_height BIGINT,
+            coinbase_height BIGINT,
+            PRIMARY KEY (tx_hash, output_index)
+        );
+        ALTER TABLE utxos ADD COLUMN IF NOT EXISTS condition JSONB;
+        ALTER TABLE utxos ADD COLUMN IF NOT EXISTS spendable_after_height BIGINT;
+        ALTER TABLE utxos ADD COLUMN IF NOT EXISTS coinbase_height BIGINT;
+        CREATE INDEX IF NOT EXISTS utxos_address ON utxos(address) WHERE NOT spent;
+        CREATE TABLE IF NOT EXISTS pending_txs(
+            hash BYTEA PRIMARY KEY,
+            data JSONB NOT NULL,
+            received_at BIGINT NOT NULL
+        );
         CREATE TABLE IF NOT EXISTS identities(
             id UUID PRIMARY KEY,
             data JSONB NOT NULL
         );
+        CREATE TABLE IF NOT EXISTS tx_receipts(
+            tx_hash BYTEA PRIMARY KEY,
+            block_height BIGINT NOT NULL,
+            tx_index INT NOT NULL,
+            data JSONB NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS tx_receipts_block ON tx_receipts(block_height, tx_index);
+        CREATE TABLE IF NOT EXISTS staking_positions(
+            address BYTEA PRIMARY KEY,
+            data JSONB NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS contract_code(
+            hash BYTEA PRIMARY KEY,
+            code BYTEA NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS contracts(
+            address BYTEA PRIMARY KEY,
+            data JSONB NOT NULL
+        );
         CREATE TABLE IF NOT EXISTS embeddings(
             id TEXT PRIMARY KEY,
             namespace TEXT NOT NULL,
             vector VECTOR(1536) NOT NULL,
             metadata JSONB NOT NULL
         );
+        CREATE TABLE IF NOT EXISTS bridge_messages(
+            id UUID PRIMARY KEY,
+            status TEXT NOT NULL,
+            next_attempt_at BIGINT NOT NULL,
+            data JSONB NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS bridge_inbound(
+            source TEXT NOT NULL,
+            nonce BIGINT NOT NULL,
+            id UUID NOT NULL,
+            status TEXT NOT NULL,
+            data JSONB NOT NULL,
+            PRIMARY KEY (source, nonce)
+        );
+        CREATE TABLE IF NOT EXISTS bridge_cursors(
+            chain TEXT PRIMARY KEY,
+            cursor BIGINT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS bridge_nonces(
+            chain TEXT PRIMARY KEY,
+            last_nonce BIGINT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS bridge_audit(
+            seq BIGSERIAL PRIMARY KEY,
+            message_id UUID,
+            chain TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            at_ms BIGINT NOT NULL,
+            data JSONB NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS bridge_webhooks(
+            id UUID PRIMARY KEY,
+            message_id UUID NOT NULL,
+            status TEXT NOT NULL,
+            next_attempt_at BIGINT NOT NULL,
+            data JSONB NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS ai_sessions(
+            id UUID PRIMARY KEY,
+            updated_at BIGINT NOT NULL,
+            data JSONB NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS ai_audit(
+            id UUID PRIMARY KEY,
+            at BIGINT NOT NULL,
+            data JSONB NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS ai_audit_at ON ai_audit(at);
         "#,
         )
         .execute(&self.pool)
@@ -80,12 +251,7 @@ impl PgStore {
 #[async_trait]
 impl BlockStore for PgStore {
     async fn insert_block(&self, block: &Block) -> Result<()> {
-        sqlx::query("INSERT INTO blocks(height, data) VALUES ($1, $2) ON CONFLICT (height) DO UPDATE SET data = EXCLUDED.data")
-            .bind(block.header.height as i64)
-            .bind(json!(block))
-            .execute(&self.pool)
-            .await?;
-        Ok(())
+        chain::insert_block(&mut *self.pool.acquire().await?, block).await
     }
 
     async fn get_block_by_height(&self, height: i64) -> Result<Option<Block>> {
@@ -100,6 +266,23 @@ impl BlockStore for PgStore {
         }
         Ok(None)
     }
+
+    async fn best_height(&self) -> Result<Option<u64>> {
+        let height: Option<i64> =
+            sqlx::query_scalar("SELECT MAX(height) FROM blocks").fetch_one(&self.pool).await?;
+        Ok(height.map(|h| h as u64))
+    }
+
+    async fn blocks_before(&self, before: Option<i64>, limit: i64) -> Result<Vec<Block>> {
+        let rows = sqlx::query("SELECT data FROM blocks WHERE height < $1 ORDER BY height DESC LIMIT $2")
+            .bind(before.unwrap_or(i64::MAX))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.into_iter()
+            .map(|row| Ok(serde_json::from_value(row.try_get::<serde_json::Value, _>("data")?)?))
+            .collect()
+    }
 }
 
 #[async_trait]
@@ -118,15 +301,104 @@ impl StateStore for PgStore {
     }
 
     async fn set_balance(&self, addr: &Address, value: u64) -> Result<()> {
-        sqlx::query(
-            "INSERT INTO balances(address, amount) VALUES ($1, $2) ON CONFLICT (address) DO UPDATE SET amount = EXCLUDED.amount",
+        chain::set_balance(&mut *self.pool.acquire().await?, addr, value).await
+    }
+}
+
+#[async_trait]
+impl TxStore for PgStore {
+    async fn list_utxos(&self, addr: &Address) -> Result<Vec<Utxo>> {
+        let rows = sqlx::query(
+            "SELECT tx_hash, output_index, amount FROM utxos WHERE address = $1 AND NOT spent AND amount > 0",
         )
         .bind(addr.as_slice())
-        .bind(value as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut out = Vec::with_capacity(rows.len());
+        for row in rows {
+            let hash: Vec<u8> = row.try_get("tx_hash")?;
+            let tx_hash: TxHash = hash.try_into().map_err(|_| anyhow::anyhow!("bad tx hash in utxos"))?;
+            let index: i32 = row.try_get("output_index")?;
+            let amount: i64 = row.try_get("amount")?;
+            out.push(Utxo { tx_hash, index: index as u32, address: *addr, amount: amount as u64 });
+        }
+        Ok(out)
+    }
+
+    async fn unspent_output(&self, tx: &TxHash, index: u32) -> Result<Option<TxOutput>> {
+        let row = sqlx::query(
+            "SELECT address, amount, condition, spendable_after_height FROM utxos \
+             WHERE tx_hash = $1 AND output_index = $2 AND NOT spent",
+        )
+        .bind(tx.as_slice())
+        .bind(index as i32)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let address: Vec<u8> = row.try_get("address")?;
+        let amount: i64 = row.try_get("amount")?;
+        let condition: Option<serde_json::Value> = row.try_get("condition")?;
+        let spendable_after_height: Option<i64> = row.try_get("spendable_after_height")?;
+        Ok(Some(TxOutput {
+            address: address.try_into().map_err(|_| anyhow::anyhow!("bad address in utxos"))?,
+            amount: amount as u64,
+            condition: condition.map(serde_json::from_value).transpose()?,
+            spendable_after_height: spendable_after_height.map(|height| height as u64),
+        }))
+    }
+
+    async fn coinbase_height(&self, tx: &TxHash) -> Result<Option<u64>> {
+        let height: Option<i64> = sqlx::query_scalar(
+            "SELECT coinbase_height FROM utxos WHERE tx_hash = $1 AND coinbase_height IS NOT NULL LIMIT 1",
+        )
+        .bind(tx.as_slice())
+        .fetch_optional(&self.pool)
+        .await?
+        .flatten();
+        Ok(height.map(|height| height as u64))
+    }
+
+    async fn record_transaction(&self, tx: &Transaction) -> Result<()> {
+        let mut db = self.pool.begin().await?;
+        chain::record_transaction(&mut db, tx).await?;
+        db.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_pending_tx(&self, tx: &Transaction) -> Result<bool> {
+        let result = sqlx::query(
+            "INSERT INTO pending_txs(hash, data, received_at) VALUES ($1, $2, $3) ON CONFLICT (hash) DO NOTHING",
+        )
+        .bind(tx.hash().as_slice())
+        .bind(json!(tx))
+        .bind(dxid_core::now_ts() as i64)
         .execute(&self.pool)
         .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn pending_txs(&self, limit: i64) -> Result<Vec<Transaction>> {
+        let rows = sqlx::query("SELECT data FROM pending_txs ORDER BY received_at LIMIT $1")
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.into_iter()
+            .map(|row| Ok(serde_json::from_value(row.try_get::<serde_json::Value, _>("data")?)?))
+            .collect()
+    }
+
+    async fn remove_pending_txs(&self, hashes: &[TxHash]) -> Result<()> {
+        let hashes: Vec<&[u8]> = hashes.iter().map(|hash| hash.as_slice()).collect();
+        sqlx::query("DELETE FROM pending_txs WHERE hash = ANY($1)").bind(hashes).execute(&self.pool).await?;
         Ok(())
     }
+
+    async fn pending_count(&self) -> Result<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pending_txs").fetch_one(&self.pool).await?;
+        Ok(count as u64)
+    }
 }
 
 #[async_trait]
@@ -155,6 +427,87 @@ impl IdentityStore for PgStore {
             Ok(None)
         }
     }
+
+    async fn list_identities(&self, offset: i64, limit: i64) -> Result<Vec<Identity>> {
+        let rows = sqlx::query("SELECT data FROM identities ORDER BY id OFFSET $1 LIMIT $2")
+            .bind(offset)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.into_iter()
+            .map(|row| Ok(serde_json::from_value(row.try_get::<serde_json::Value, _>("data")?)?))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ReceiptStore for PgStore {
+    async fn insert_receipts(&self, receipts: &[TxReceipt]) -> Result<()> {
+        let mut db = self.pool.begin().await?;
+        for receipt in receipts {
+            chain::insert_receipt(&mut db, receipt).await?;
+        }
+        db.commit().await?;
+        Ok(())
+    }
+
+    async fn get_receipt(&self, tx_hash: &TxHash) -> Result<Option<TxReceipt>> {
+        let row = sqlx::query("SELECT data FROM tx_receipts WHERE tx_hash = $1")
+            .bind(tx_hash.as_slice())
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => Ok(Some(serde_json::from_value(row.try_get("data")?)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn block_receipts(&self, height: i64) -> Result<Vec<TxReceipt>> {
+        let rows = sqlx::query("SELECT data FROM tx_receipts WHERE block_height = $1 ORDER BY tx_index")
+            .bind(height)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.into_iter()
+            .map(|row| Ok(serde_json::from_value(row.try_get("data")?)?))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl StakingStore for PgStore {
+    async fn put_staking_position(&self, owner: &Address, position: &StakingPosition) -> Result<()> {
+        chain::put_staking_position(&mut *self.pool.acquire().await?, owner, position).await
+    }
+
+    async fn get_staking_position(&self, owner: &Address) -> Result<Option<StakingPosition>> {
+        let row = sqlx::query("SELECT data FROM staking_positions WHERE address = $1")
+            .bind(owner.as_slice())
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => Ok(Some(serde_json::from_value(row.try_get("data")?)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn staking_positions_for(&self, validator: &Address) -> Result<Vec<(Address, StakingPosition)>> {
+        let entry = json!([{ "validator": validator }]);
+        let rows = sqlx::query(
+            "SELECT address, data FROM staking_positions \
+             WHERE data->'bonds' @> $1 OR data->'unbonding' @> $1",
+        )
+        .bind(entry)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                let owner: Vec<u8> = row.try_get("address")?;
+                let owner: Address =
+                    owner.try_into().map_err(|_| anyhow::anyhow!("bad staking owner address"))?;
+                Ok((owner, serde_json::from_value(row.try_get("data")?)?))
+            })
+            .collect()
+    }
 }
 
 #[async_trait]
@@ -174,6 +527,11 @@ impl VectorStore for PgStore {
     }
 
     async fn knn_search(&self, space: &str, query: &[f32], k: i64) -> Result<Vec<Embedding>> {
+        let scored = self.knn_search_scored(space, query, k).await?;
+        Ok(scored.into_iter().map(|(embedding, _)| embedding).collect())
+    }
+
+    async fn knn_search_scored(&self, space: &str, query: &[f32], k: i64) -> Result<Vec<(Embedding, f32)>> {
         let rows = sqlx::query(
             "SELECT id, namespace, metadata, vector <-> $1 as dist FROM embeddings
              WHERE namespace = $2 ORDER BY vector <-> $1 LIMIT $3",
@@ -188,15 +546,28 @@ impl VectorStore for PgStore {
             let id: String = row.try_get("id")?;
             let namespace: String = row.try_get("namespace")?;
             let metadata: serde_json::Value = row.try_get("metadata").unwrap_or_default();
-            out.push(Embedding {
+            let distance: f64 = row.try_get("dist")?;
+            let embedding = Embedding {
                 id: EmbeddingId(id),
                 namespace,
                 values: query.to_vec(), // keep payload lean
                 metadata,
-            });
+            };
+            out.push((embedding, distance as f32));
         }
         Ok(out)
     }
+
+    async fn dimensions(&self) -> Result<Option<usize>> {
+        // pgvector keeps the declared length as the column's type modifier, -1 when undeclared.
+        let typmod: i32 = sqlx::query_scalar(
+            "SELECT atttypmod FROM pg_attribute
+             WHERE attrelid = 'embeddings'::regclass AND attname = 'vector'",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(usize::try_from(typmod).ok().filter(|dims| *dims > 0))
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::PgStore;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionRole {
+    User,
+    Assistant,
+}
+
+impl SessionRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionRole::User => "user",
+            SessionRole::Assistant => "assistant",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionMessage {
+    pub role: SessionRole,
+    pub content: String,
+    pub at: u64,
+}
+
+/// A conversation with the AI hypervisor, resumed by its id.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionRecord {
+    pub id: Uuid,
+    /// Every message, oldest first.
+    pub messages: Vec<SessionMessage>,
+    /// Leading messages no longer sent to the model; `summary` covers them.
+    #[serde(default)]
+    pub summarized: usize,
+    #[serde(default)]
+    pub summary: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl SessionRecord {
+    pub fn new(id: Uuid, now: u64) -> Self {
+        Self { id, messages: Vec::new(), summarized: 0, summary: None, created_at: now, updated_at: now }
+    }
+}
+
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Creates or replaces the session.
+    async fn put_session(&self, session: &SessionRecord) -> Result<()>;
+    async fn get_session(&self, id: &Uuid) -> Result<Option<SessionRecord>>;
+}
+
+#[async_trait]
+impl SessionStore for PgStore {
+    async fn put_session(&self, session: &SessionRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO ai_sessions(id, updated_at, data) VALUES ($1, $2, $3) \
+             ON CONFLICT (id) DO UPDATE SET updated_at = EXCLUDED.updated_at, data = EXCLUDED.data",
+        )
+        .bind(session.id)
+        .bind(session.updated_at as i64)
+        .bind(json!(session))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &Uuid) -> Result<Option<SessionRecord>> {
+        let row = sqlx::query("SELECT data FROM ai_sessions WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => Ok(Some(serde_json::from_value(row.try_get("data")?)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// In-memory [`SessionStore`] for tests and tools.
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: RwLock<HashMap<Uuid, SessionRecord>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn put_session(&self, session: &SessionRecord) -> Result<()> {
+        self.sessions.write().await.insert(session.id, session.clone());
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &Uuid) -> Result<Option<SessionRecord>> {
+        Ok(self.sessions.read().await.get(id).cloned())
+    }
+}
@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dxid_vectors::{Embedding, HnswIndex};
+use tokio::sync::RwLock;
+
+use crate::VectorStore;
+
+/// Similarity search over an in-memory HNSW index, for light nodes and
+/// tests without pgvector. Results carry their stored vectors.
+#[derive(Default)]
+pub struct MemoryVectorStore {
+    index: RwLock<HnswIndex>,
+}
+
+impl MemoryVectorStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves `index`, e.g. one from [`MemoryVectorStore::load`] or built
+    /// with non-default parameters.
+    pub fn with_index(index: HnswIndex) -> Self {
+        Self { index: RwLock::new(index) }
+    }
+
+    /// Reopens an index written by [`MemoryVectorStore::save`].
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::with_index(HnswIndex::load(path)?))
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        self.index.read().await.save(path)
+    }
+}
+
+#[async_trait]
+impl VectorStore for MemoryVectorStore {
+    async fn insert_embedding(&self, embedding: &Embedding) -> Result<()> {
+        self.index.write().await.insert(embedding)
+    }
+
+    async fn knn_search(&self, space: &str, query: &[f32], k: i64) -> Result<Vec<Embedding>> {
+        let neighbors = self.index.read().await.search(space, query, k.max(0) as usize)?;
+        Ok(neighbors.into_iter().map(|neighbor| neighbor.embedding).collect())
+    }
+
+    async fn knn_search_scored(&self, space: &str, query: &[f32], k: i64) -> Result<Vec<(Embedding, f32)>> {
+        let neighbors = self.index.read().await.search(space, query, k.max(0) as usize)?;
+        Ok(neighbors.into_iter().map(|neighbor| (neighbor.embedding, neighbor.distance)).collect())
+    }
+
+    async fn dimensions(&self) -> Result<Option<usize>> {
+        Ok(self.index.read().await.dimensions())
+    }
+}
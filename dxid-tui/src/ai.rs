@@ -0,0 +1,339 @@
+//! AI tab: a conversation with the node's hypervisor, answers rendered token
+//! by token as they stream in over `/ws/ai`, followed by the sources they cite.
+//! The conversation is a session on the node, so follow-up questions are
+//! answered with what was said before.
+//!
+//! Keys: type a prompt and `Enter` to send, `Esc` cancels the answer being
+//! streamed, `Up`/`Down`/`PageUp`/`PageDown` scroll the history. The prompt
+//! `/new` starts a new session and `/resume <id>` continues an earlier one.
+
+use crossterm::event::KeyCode;
+use dxid_wallet::{AiSession, NodeClient};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Frame,
+};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::events::{AppEvent, EventSender};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChatState {
+    /// The node session the conversation is recorded in.
+    pub session: Uuid,
+    pub history: Vec<ChatMessage>,
+    pub input: String,
+    /// Whether an answer is streaming into the last message.
+    pub streaming: bool,
+    /// Lines scrolled up from the bottom of the history.
+    pub scroll_back: u16,
+}
+
+impl ChatState {
+    /// Moves the prompt into the history, opening an empty answer after it.
+    /// Returns the prompt, or `None` if there is nothing to send.
+    pub fn submit(&mut self) -> Option<String> {
+        let prompt = self.input.trim().to_string();
+        if prompt.is_empty() || self.streaming {
+            return None;
+        }
+        self.input.clear();
+        self.history.push(ChatMessage { role: Role::User, text: prompt.clone() });
+        self.history.push(ChatMessage { role: Role::Assistant, text: String::new() });
+        self.streaming = true;
+        self.scroll_back = 0;
+        Some(prompt)
+    }
+
+    /// Switches to `session`, showing its earlier messages.
+    pub fn start(&mut self, session: Uuid, history: Vec<ChatMessage>) {
+        *self = Self { session, history, ..Self::default() };
+    }
+
+    /// Appends a streamed token to the answer in progress.
+    pub fn push_token(&mut self, token: &str) {
+        if let Some(answer) = self.history.last_mut().filter(|_| self.streaming) {
+            answer.text.push_str(token);
+        }
+    }
+
+    /// Lists cited sources and metrics, one per line, under the answer in progress.
+    pub fn add_sources(&mut self, sources: &[String]) {
+        if let Some(answer) = self.history.last_mut().filter(|_| self.streaming && !sources.is_empty()) {
+            answer.text.push('\n');
+            answer.text.push_str(&sources.join("\n"));
+        }
+    }
+
+    /// Ends the answer in progress, noting why if it did not complete.
+    pub fn finish(&mut self, note: Option<&str>) {
+        if !self.streaming {
+            return;
+        }
+        self.streaming = false;
+        if let (Some(answer), Some(note)) = (self.history.last_mut(), note) {
+            if !answer.text.is_empty() {
+                answer.text.push(' ');
+            }
+            answer.text.push_str(&format!("[{note}]"));
+        }
+    }
+
+    pub fn scroll(&mut self, lines: i32) {
+        self.scroll_back = (self.scroll_back as i32 + lines).clamp(0, u16::MAX as i32) as u16;
+    }
+}
+
+/// Progress of a streamed answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AiUpdate {
+    Token(String),
+    /// The sources the complete answer cites, as `[S1] text` lines, then
+    /// its metrics as `metric = value unit` lines.
+    Sources(Vec<String>),
+    /// The answer ended, with an error if it did not complete.
+    Done(Option<String>),
+    /// An earlier session was loaded to continue.
+    Resumed(Uuid, Vec<ChatMessage>),
+}
+
+fn session_history(session: AiSession) -> Vec<ChatMessage> {
+    session
+        .messages
+        .into_iter()
+        .map(|m| {
+            let role = if m.role == "user" { Role::User } else { Role::Assistant };
+            ChatMessage { role, text: m.content }
+        })
+        .collect()
+}
+
+/// The chat and the task streaming its current answer.
+pub struct AiChat {
+    client: NodeClient,
+    events: EventSender,
+    pub state: ChatState,
+    task: Option<JoinHandle<()>>,
+    /// Number of the prompt being answered; updates for earlier ones are stale.
+    request: u64,
+}
+
+impl AiChat {
+    pub fn new(client: NodeClient, events: EventSender) -> Self {
+        let state = ChatState { session: Uuid::new_v4(), ..ChatState::default() };
+        Self { client, events, state, task: None, request: 0 }
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Enter => self.send(),
+            KeyCode::Esc => self.cancel(),
+            KeyCode::Char(c) => self.state.input.push(c),
+            KeyCode::Backspace => {
+                self.state.input.pop();
+            }
+            KeyCode::Up => self.state.scroll(1),
+            KeyCode::Down => self.state.scroll(-1),
+            KeyCode::PageUp => self.state.scroll(10),
+            KeyCode::PageDown => self.state.scroll(-10),
+            _ => {}
+        }
+    }
+
+    pub fn apply(&mut self, request: u64, update: AiUpdate) {
+        if request != self.request {
+            return;
+        }
+        match update {
+            AiUpdate::Token(token) => self.state.push_token(&token),
+            AiUpdate::Sources(sources) => self.state.add_sources(&sources),
+            AiUpdate::Done(error) => self.state.finish(error.as_deref()),
+            AiUpdate::Resumed(session, history) => self.state.start(session, history),
+        }
+    }
+
+    fn send(&mut self) {
+        let Some(prompt) = self.state.submit() else {
+            return;
+        };
+        if prompt == "/new" {
+            self.state.start(Uuid::new_v4(), Vec::new());
+            return;
+        }
+        if let Some(id) = prompt.strip_prefix("/resume") {
+            match id.trim().parse() {
+                Ok(id) => self.resume(id),
+                Err(_) => self.state.finish(Some("usage: /resume <session id>")),
+            }
+            return;
+        }
+        self.request += 1;
+        let (client, events, request) = (self.client.clone(), self.events.clone(), self.request);
+        let session = self.state.session;
+        let send = move |update| events.send(AppEvent::Ai { request, update }).is_ok();
+        self.task = Some(tokio::spawn(async move {
+            let error = match client.ai_stream(&prompt, Some(session)).await {
+                Ok(mut answer) => loop {
+                    match answer.next_token().await {
+                        // The app is gone; dropping the stream closes it.
+                        Ok(Some(token)) => {
+                            if !send(AiUpdate::Token(token)) {
+                                return;
+                            }
+                        }
+                        Ok(None) => {
+                            let metrics = answer.analytics().iter().map(|metric| {
+                                format!("{} = {} {}", metric.metric, metric.value, metric.unit)
+                            });
+                            let sources: Vec<String> = answer
+                                .sources()
+                                .iter()
+                                .map(|source| format!("[{}] {}", source.id, source.text))
+                                .chain(metrics)
+                                .collect();
+                            if !sources.is_empty() && !send(AiUpdate::Sources(sources)) {
+                                return;
+                            }
+                            break None;
+                        }
+                        Err(err) => break Some(format!("{err:#}")),
+                    }
+                },
+                Err(err) => Some(format!("{err:#}")),
+            };
+            send(AiUpdate::Done(error));
+        }));
+    }
+
+    /// Loads session `id` from the node; its messages replace the history.
+    fn resume(&mut self, id: Uuid) {
+        self.request += 1;
+        let (client, events, request) = (self.client.clone(), self.events.clone(), self.request);
+        self.task = Some(tokio::spawn(async move {
+            let update = match client.ai_session(&id).await {
+                Ok(Some(session)) => AiUpdate::Resumed(id, session_history(session)),
+                Ok(None) => AiUpdate::Done(Some("unknown session".into())),
+                Err(err) => AiUpdate::Done(Some(format!("{err:#}"))),
+            };
+            let _ = events.send(AppEvent::Ai { request, update });
+        }));
+    }
+
+    /// Stops the answer being streamed; dropping the stream closes it on the node.
+    fn cancel(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.state.finish(Some("cancelled"));
+    }
+}
+
+/// Rows `text` takes when wrapped to `width` columns.
+fn wrapped_rows(text: &str, width: u16) -> u16 {
+    let width = width.max(1) as usize;
+    text.lines().map(|line| ((line.chars().count().max(1) - 1) / width) as u16 + 1).sum()
+}
+
+pub fn draw(f: &mut Frame, area: Rect, state: &ChatState) {
+    let area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(5), Constraint::Length(3)].as_ref())
+        .split(area);
+
+    let mut lines = Vec::new();
+    for message in &state.history {
+        let (who, color) = match message.role {
+            Role::User => ("you", Color::Cyan),
+            Role::Assistant => ("ai", Color::Green),
+        };
+        let mut text = message.text.lines();
+        let first = text.next().unwrap_or_default().to_string();
+        lines.push(Line::from(vec![
+            Span::styled(format!("{who}> "), Style::default().fg(color)),
+            Span::raw(first),
+        ]));
+        lines.extend(text.map(|line| Line::from(line.to_string())));
+    }
+    let inner_width = area[0].width.saturating_sub(2);
+    let rows: u16 =
+        state.history.iter().map(|m| wrapped_rows(&format!("you> {}", m.text), inner_width)).sum();
+    let bottom = rows.saturating_sub(area[0].height.saturating_sub(2));
+    let title = if state.streaming {
+        format!("AI {} (streaming, Esc to cancel)", state.session)
+    } else {
+        format!("AI {}", state.session)
+    };
+    let history = Paragraph::new(lines)
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .wrap(Wrap { trim: false })
+        .scroll((bottom.saturating_sub(state.scroll_back), 0));
+    f.render_widget(history, area[0]);
+    f.render_widget(
+        Paragraph::new(state.input.clone()).block(Block::default().title("Prompt").borders(Borders::ALL)),
+        area[1],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_stream_into_the_history() {
+        let mut chat = ChatState { input: "  ".into(), ..ChatState::default() };
+        assert_eq!(chat.submit(), None);
+        chat.input = "fee spike?".into();
+        assert_eq!(chat.submit().as_deref(), Some("fee spike?"));
+        chat.input = "next".into();
+        assert_eq!(chat.submit(), None, "one answer at a time");
+        chat.push_token("No");
+        chat.push_token(" spike.");
+        chat.finish(None);
+        assert_eq!(chat.history[1].text, "No spike.");
+
+        chat.input.clear();
+        chat.input.push_str("and now?");
+        chat.submit().unwrap();
+        chat.push_token("Looking");
+        chat.finish(Some("cancelled"));
+        chat.push_token(" late");
+        chat.finish(Some("again"));
+        assert_eq!(chat.history.len(), 4);
+        assert_eq!(chat.history[3].text, "Looking [cancelled]");
+
+        chat.input = "height?".into();
+        chat.submit().unwrap();
+        chat.push_token("42 [S1].");
+        chat.add_sources(&[]);
+        chat.add_sources(&["[S1] The chain is at height 42.".into()]);
+        chat.finish(None);
+        assert_eq!(chat.history[5].text, "42 [S1].\n[S1] The chain is at height 42.");
+
+        let earlier = vec![ChatMessage { role: Role::User, text: "peers?".into() }];
+        chat.scroll(4);
+        chat.start(Uuid::from_u128(7), earlier.clone());
+        assert_eq!((chat.session, chat.history, chat.scroll_back), (Uuid::from_u128(7), earlier, 0));
+
+        chat.scroll(-3);
+        assert_eq!(chat.scroll_back, 0);
+        chat.scroll(12);
+        assert_eq!(chat.scroll_back, 12);
+        assert_eq!(wrapped_rows("abcdef\n\nxyz", 3), 2 + 1 + 1);
+    }
+}
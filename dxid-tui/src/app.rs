@@ -0,0 +1,249 @@
+//! Central app state: every tab's state, updated one [`AppEvent`] at a time
+//! from the event bus and drawn from the same place.
+
+use anyhow::Result;
+use crossterm::event::{KeyCode, KeyEvent};
+use dxid_wallet::{NodeClient, WalletStore};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Span,
+    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Tabs},
+    Frame,
+};
+
+use crate::ai::{self, AiChat};
+use crate::charts::{self, Window};
+use crate::events::{AppEvent, EventSender};
+use crate::explorer::{self, Explorer};
+use crate::identities::{self, IdentitiesTab};
+use crate::logs::{self, LogBuffer, LogPane};
+use crate::node::{NodeView, TuiConfig};
+
+pub const TABS: [&str; 8] =
+    ["Dashboard", "Explorer", "Wallet", "Identities", "Chains", "Bridge", "Mining", "AI"];
+pub const DASHBOARD_TAB: usize = 0;
+pub const EXPLORER_TAB: usize = 1;
+pub const IDENTITIES_TAB: usize = 3;
+pub const AI_TAB: usize = 7;
+
+pub struct App {
+    endpoint: String,
+    pub active: usize,
+    pub node: NodeView,
+    pub explorer: Explorer,
+    pub identities: IdentitiesTab,
+    pub ai: AiChat,
+    logs: LogBuffer,
+    pub log_pane: LogPane,
+    pub chart_window: Window,
+    pub quit: bool,
+}
+
+impl App {
+    /// Tabs that talk to the node send their results to `events`.
+    pub fn new(config: &TuiConfig, logs: LogBuffer, events: EventSender) -> Result<Self> {
+        let client = NodeClient::new(config.endpoint.clone());
+        std::fs::create_dir_all(&config.wallet_dir)?;
+        let store = WalletStore::new(config.wallet_dir.clone())?;
+        Ok(Self {
+            endpoint: config.endpoint.clone(),
+            active: DASHBOARD_TAB,
+            node: NodeView::default(),
+            explorer: Explorer::new(client.clone(), events.clone()),
+            identities: IdentitiesTab::new(client.clone(), store, events.clone()),
+            ai: AiChat::new(client, events),
+            logs,
+            log_pane: LogPane::default(),
+            chart_window: Window::default(),
+            quit: false,
+        })
+    }
+
+    pub fn handle(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Key(key) => self.handle_key(key),
+            AppEvent::Resize | AppEvent::Tick => {}
+            AppEvent::Node(event) => self.node.apply(event),
+            AppEvent::BlockPage { before, result } => {
+                self.explorer.apply_page(before, result);
+                self.explorer.fetch_if_needed();
+            }
+            AppEvent::Ai { request, update } => self.ai.apply(request, update),
+            AppEvent::Identities(result) => self.identities.apply_identities(result),
+            AppEvent::Duplicates(result) => self.identities.apply_duplicates(result),
+            AppEvent::IdentityChanged(result) => self.identities.apply_change(result),
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let active = self.active;
+        match key.code {
+            code if self.log_pane.handle_key(code) => {}
+            // An open prompt takes every key, digits and `q` included.
+            code if active == IDENTITIES_TAB && self.identities.is_prompting() => {
+                self.identities.handle_key(code)
+            }
+            // Once a prompt is being typed, digits and `q` are part of it.
+            KeyCode::Char(c) if active == AI_TAB && !self.ai.state.input.is_empty() => {
+                self.ai.handle_key(KeyCode::Char(c))
+            }
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Char(c @ '1'..='8') => {
+                self.active = c as usize - '1' as usize;
+                match self.active {
+                    EXPLORER_TAB => self.explorer.fetch_if_needed(),
+                    IDENTITIES_TAB => self.identities.reload(),
+                    _ => {}
+                }
+            }
+            KeyCode::Char('w') if active == DASHBOARD_TAB => self.chart_window = self.chart_window.next(),
+            code if active == EXPLORER_TAB => self.explorer.handle_key(code),
+            code if active == IDENTITIES_TAB => self.identities.handle_key(code),
+            code if active == AI_TAB => self.ai.handle_key(code),
+            _ => {}
+        }
+    }
+
+    pub fn draw(&self, f: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(f.size());
+        let titles: Vec<Span> =
+            TABS.iter().map(|t| Span::styled(*t, Style::default().fg(Color::Cyan))).collect();
+        let tabs_widget = Tabs::new(titles)
+            .select(self.active)
+            .block(Block::default().borders(Borders::ALL).title("dxid"))
+            .style(Style::default().fg(Color::White))
+            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
+        f.render_widget(tabs_widget, chunks[0]);
+
+        let mut content = chunks[1];
+        if self.log_pane.open {
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(5), Constraint::Percentage(35)].as_ref())
+                .split(content);
+            content = split[0];
+            logs::draw(f, split[1], &self.log_pane, &self.logs);
+        }
+        match self.active {
+            DASHBOARD_TAB => draw_dashboard(f, content, &self.node, &self.endpoint, self.chart_window),
+            EXPLORER_TAB => explorer::draw(f, content, &self.explorer.state),
+            IDENTITIES_TAB => identities::draw(f, content, &self.identities.state),
+            AI_TAB => ai::draw(f, content, &self.ai.state),
+            _ => {
+                let para = Paragraph::new("Use number keys 1-8 to switch tabs, F2 for logs. q to quit.");
+                f.render_widget(para, content);
+            }
+        }
+    }
+}
+
+fn draw_dashboard(f: &mut Frame, area: Rect, view: &NodeView, endpoint: &str, window: Window) {
+    let area = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [Constraint::Length(6), Constraint::Length(3), Constraint::Length(7), Constraint::Min(3)]
+                .as_ref(),
+        )
+        .split(area);
+
+    let mut lines = match &view.status {
+        Some(status) => {
+            format!("Height: {}\nPeers: {}\nMempool: {} txs", status.height, status.peers, status.mempool)
+        }
+        None => "Height: n/a\nPeers: n/a\nMempool: n/a".to_string(),
+    };
+    if let Some(error) = &view.error {
+        lines.push_str(&format!("\nNode unreachable: {error}"));
+    }
+    let title = format!("Dashboard - {endpoint}");
+    let summary = Paragraph::new(lines).block(Block::default().title(title).borders(Borders::ALL));
+    f.render_widget(summary, area[0]);
+
+    let (ratio, label) = match &view.status {
+        Some(status) => (status.sync_progress(), format!("{} / {}", status.height, status.network_height)),
+        None => (0.0, "waiting for node".to_string()),
+    };
+    let gauge = Gauge::default()
+        .block(Block::default().title("Sync").borders(Borders::ALL))
+        .gauge_style(Style::default().fg(Color::Green))
+        .ratio(ratio)
+        .label(label);
+    f.render_widget(gauge, area[1]);
+
+    let now = dxid_core::now_ts();
+    charts::draw(f, area[2], &view.metrics, window, now);
+    let blocks: Vec<ListItem> = view
+        .recent
+        .iter()
+        .map(|b| {
+            let age = now.saturating_sub(b.timestamp);
+            ListItem::new(format!(
+                "#{:<8} {}..  {:>4} txs  {age}s ago",
+                b.height,
+                &b.hash[..16.min(b.hash.len())],
+                b.transactions
+            ))
+        })
+        .collect();
+    let blocks = List::new(blocks).block(Block::default().title("Recent blocks").borders(Borders::ALL));
+    f.render_widget(blocks, area[3]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBus;
+    use crate::node::NodeEvent;
+    use crossterm::event::KeyModifiers;
+    use dxid_wallet::NodeStatus;
+
+    fn key(code: KeyCode) -> AppEvent {
+        AppEvent::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[tokio::test]
+    async fn events_update_the_app_state() {
+        let mut bus = EventBus::default();
+        let config = TuiConfig {
+            wallet_dir: std::env::temp_dir().join(format!("dxid-tui-app-{}", std::process::id())),
+            ..TuiConfig::default().with_endpoint("http://127.0.0.1:9")
+        };
+        let mut app = App::new(&config, LogBuffer::default(), bus.sender()).unwrap();
+
+        let status = NodeStatus { height: 5, peers: 2, network_height: 8, mempool: 1 };
+        app.handle(AppEvent::Node(NodeEvent::Status(status)));
+        assert_eq!(app.node.status.as_ref().map(|s| s.height), Some(5));
+
+        app.handle(key(KeyCode::Char('2')));
+        assert_eq!(app.active, EXPLORER_TAB);
+        assert!(app.explorer.state.loading);
+        // The unreachable node's answer comes back over the bus.
+        match bus.next().await {
+            Some(event @ AppEvent::BlockPage { .. }) => app.handle(event),
+            other => panic!("expected a block page, got {other:?}"),
+        }
+        assert!(app.explorer.state.error.is_some());
+
+        app.handle(key(KeyCode::Char('8')));
+        for c in "h1q".chars() {
+            app.handle(key(KeyCode::Char(c)));
+        }
+        assert_eq!((app.active, app.ai.state.input.as_str(), app.quit), (AI_TAB, "h1q", false));
+        app.handle(AppEvent::Ai { request: 1, update: ai::AiUpdate::Token("stale".into()) });
+        assert!(app.ai.state.history.is_empty());
+
+        app.handle(key(KeyCode::F(2)));
+        assert!(app.log_pane.open);
+        app.handle(key(KeyCode::Char('1')));
+        for _ in 0..4 {
+            app.handle(key(KeyCode::Backspace));
+        }
+        assert_eq!(app.active, AI_TAB);
+        app.handle(key(KeyCode::Char('q')));
+        assert!(app.quit);
+    }
+}
@@ -0,0 +1,157 @@
+//! Dashboard time series: block time, estimated hash rate and peer count,
+//! sampled by the node feed and drawn as sparklines over a selectable window.
+
+use std::collections::VecDeque;
+
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    widgets::{Block, Borders, Sparkline},
+    Frame,
+};
+
+/// Longest window kept; older samples are dropped.
+pub const MAX_WINDOW_SECS: u64 = 60 * 60;
+
+/// Time span the charts cover; `w` on the Dashboard cycles through them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Window {
+    #[default]
+    FiveMinutes,
+    FifteenMinutes,
+    Hour,
+}
+
+impl Window {
+    pub fn secs(self) -> u64 {
+        match self {
+            Window::FiveMinutes => 5 * 60,
+            Window::FifteenMinutes => 15 * 60,
+            Window::Hour => MAX_WINDOW_SECS,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Window::FiveMinutes => Window::FifteenMinutes,
+            Window::FifteenMinutes => Window::Hour,
+            Window::Hour => Window::FiveMinutes,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Window::FiveMinutes => "5m",
+            Window::FifteenMinutes => "15m",
+            Window::Hour => "1h",
+        }
+    }
+}
+
+/// Timestamped values, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct Series {
+    samples: VecDeque<(u64, u64)>,
+}
+
+impl Series {
+    /// Records `value` at `timestamp`, dropping samples older than
+    /// [`MAX_WINDOW_SECS`] before the newest.
+    pub fn record(&mut self, timestamp: u64, value: u64) {
+        let at = self.samples.iter().rposition(|&(t, _)| t <= timestamp).map_or(0, |i| i + 1);
+        self.samples.insert(at, (timestamp, value));
+        let newest = self.samples.back().map_or(timestamp, |&(t, _)| t);
+        while self.samples.front().is_some_and(|&(t, _)| t + MAX_WINDOW_SECS < newest) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn latest(&self) -> Option<u64> {
+        self.samples.back().map(|&(_, v)| v)
+    }
+
+    /// The `window` seconds up to `now` split into `buckets` equal spans, each
+    /// the average of its samples, or 0 if it has none.
+    pub fn buckets(&self, now: u64, window: u64, buckets: usize) -> Vec<u64> {
+        let buckets = buckets.max(1);
+        let start = now.saturating_sub(window);
+        let mut sums = vec![(0u64, 0u64); buckets];
+        for &(t, v) in self.samples.iter().filter(|&&(t, _)| t > start && t <= now) {
+            let i = (((t - start - 1) * buckets as u64) / window.max(1)) as usize;
+            let (sum, count) = &mut sums[i.min(buckets - 1)];
+            *sum += v;
+            *count += 1;
+        }
+        sums.into_iter().map(|(sum, count)| sum.checked_div(count).unwrap_or(0)).collect()
+    }
+}
+
+/// The series the Dashboard charts.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// Seconds between consecutive blocks, at the later block's timestamp.
+    pub block_time: Series,
+    /// Hashes per second implied by a block's difficulty and block time.
+    pub hash_rate: Series,
+    pub peers: Series,
+}
+
+impl Metrics {
+    /// Records the gap between a block and its parent and the hash rate it implies.
+    pub fn record_block(&mut self, timestamp: u64, parent_timestamp: u64, difficulty: u64) {
+        let secs = timestamp.saturating_sub(parent_timestamp).max(1);
+        self.block_time.record(timestamp, secs);
+        self.hash_rate.record(timestamp, difficulty.saturating_add(1) / secs);
+    }
+}
+
+fn sparkline(f: &mut Frame, area: Rect, title: String, data: &[u64], color: Color) {
+    let chart = Sparkline::default()
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .data(data)
+        .style(Style::default().fg(color));
+    f.render_widget(chart, area);
+}
+
+pub fn draw(f: &mut Frame, area: Rect, metrics: &Metrics, window: Window, now: u64) {
+    let area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 3), Constraint::Ratio(1, 3), Constraint::Ratio(1, 3)].as_ref())
+        .split(area);
+    let latest =
+        |series: &Series, unit: &str| series.latest().map_or("n/a".to_string(), |v| format!("{v}{unit}"));
+    let charts = [
+        ("Block time", &metrics.block_time, "s", Color::Cyan),
+        ("Hash rate", &metrics.hash_rate, " H/s", Color::Magenta),
+        ("Peers", &metrics.peers, "", Color::Green),
+    ];
+    for ((name, series, unit, color), area) in charts.into_iter().zip(area.iter()) {
+        let data = series.buckets(now, window.secs(), area.width.saturating_sub(2) as usize);
+        let title = format!("{name} {} ({}, w)", latest(series, unit), window.label());
+        sparkline(f, *area, title, &data, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_buckets_samples_over_the_window() {
+        let mut series = Series::default();
+        for (t, v) in [(10, 4), (70, 2), (50, 6), (300, 9)] {
+            series.record(t, v);
+        }
+        assert_eq!(series.latest(), Some(9));
+        assert_eq!(series.buckets(300, 300, 5), [5, 2, 0, 0, 9]);
+        assert_eq!(series.buckets(300, 100, 2), [0, 9]);
+
+        series.record(300 + MAX_WINDOW_SECS, 1);
+        assert_eq!(series.samples.len(), 2);
+
+        let mut metrics = Metrics::default();
+        metrics.record_block(120, 100, 999);
+        assert_eq!((metrics.block_time.latest(), metrics.hash_rate.latest()), (Some(20), Some(50)));
+        assert_eq!(Window::Hour.next(), Window::FiveMinutes);
+    }
+}
@@ -0,0 +1,112 @@
+//! The TUI's event bus. Terminal input, ticks and the results of background
+//! work (node polling, block pages, AI tokens, identity changes) all arrive on
+//! one channel, so the draw loop never waits on input or on the network.
+
+use std::time::Duration;
+
+use crossterm::event::{Event, EventStream, KeyEvent, KeyEventKind};
+use dxid_core::{Identity, PossibleDuplicate};
+use dxid_wallet::BlockPage;
+use futures::StreamExt;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::warn;
+
+use crate::ai::AiUpdate;
+use crate::node::NodeEvent;
+
+/// How often the screen redraws when nothing else happens, keeping the log
+/// pane and block ages current.
+pub const TICK_RATE: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+pub enum AppEvent {
+    Key(KeyEvent),
+    Resize,
+    Tick,
+    Node(NodeEvent),
+    /// A page of blocks requested with `before` for the Explorer.
+    BlockPage {
+        before: Option<u64>,
+        result: Result<BlockPage, String>,
+    },
+    /// Progress of the AI answer to prompt number `request`.
+    Ai {
+        request: u64,
+        update: AiUpdate,
+    },
+    /// Published identities, from a reload of the Identities tab.
+    Identities(Result<Vec<Identity>, String>),
+    /// Possible duplicate identities the node reported, fetched with them.
+    Duplicates(Result<Vec<PossibleDuplicate>, String>),
+    /// Outcome of a signed identity change.
+    IdentityChanged(Result<String, String>),
+}
+
+/// Where background tasks send their results.
+pub type EventSender = UnboundedSender<AppEvent>;
+
+pub struct EventBus {
+    tx: EventSender,
+    rx: UnboundedReceiver<AppEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (tx, rx) = unbounded_channel();
+        Self { tx, rx }
+    }
+}
+
+impl EventBus {
+    pub fn sender(&self) -> EventSender {
+        self.tx.clone()
+    }
+
+    /// Waits for the next event. The bus holds a sender itself, so this only
+    /// returns `None` if it is closed.
+    pub async fn next(&mut self) -> Option<AppEvent> {
+        self.rx.recv().await
+    }
+
+    /// An event already queued, without waiting.
+    pub fn try_next(&mut self) -> Option<AppEvent> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Starts forwarding key presses and resizes from the terminal.
+    pub fn spawn_input(&self) {
+        let tx = self.sender();
+        tokio::spawn(async move {
+            let mut input = EventStream::new();
+            while let Some(event) = input.next().await {
+                let event = match event {
+                    Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => AppEvent::Key(key),
+                    Ok(Event::Resize(..)) => AppEvent::Resize,
+                    Ok(_) => continue,
+                    Err(err) => {
+                        warn!("reading terminal input: {err}");
+                        break;
+                    }
+                };
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Starts sending [`AppEvent::Tick`] every `period`.
+    pub fn spawn_ticks(&self, period: Duration) {
+        let tx = self.sender();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(period);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                ticks.tick().await;
+                if tx.send(AppEvent::Tick).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,320 @@
+//! Block explorer tab: a block list paged from the node's `/blocks`, a
+//! selected block's transactions, and one transaction's inputs and outputs.
+//!
+//! Keys: `Up`/`Down` move, `Enter` opens, `Esc` goes back, `r` reloads from
+//! the tip. Older pages load as the selection reaches the end of the list.
+
+use crossterm::event::KeyCode;
+use dxid_core::{Block, Transaction};
+use dxid_wallet::{address_to_string_bech32, BlockPage, NodeClient};
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    widgets::{Block as Panel, Borders, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use crate::events::{AppEvent, EventSender};
+
+/// Blocks fetched per page.
+pub const PAGE_SIZE: u64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Blocks,
+    Transactions,
+    Transaction,
+}
+
+/// Loaded blocks and where the user is in them.
+#[derive(Debug, Clone)]
+pub struct ExplorerState {
+    /// Newest first.
+    pub blocks: Vec<Block>,
+    /// `before` of the next page; `None` once the oldest block is loaded.
+    next: Option<u64>,
+    pub loading: bool,
+    pub error: Option<String>,
+    pub level: Level,
+    pub block: usize,
+    pub tx: usize,
+}
+
+impl Default for ExplorerState {
+    fn default() -> Self {
+        Self {
+            blocks: Vec::new(),
+            next: None,
+            loading: false,
+            error: None,
+            level: Level::Blocks,
+            block: 0,
+            tx: 0,
+        }
+    }
+}
+
+impl ExplorerState {
+    /// Appends a page fetched with `before`; a page from the tip replaces the list.
+    pub fn apply_page(&mut self, before: Option<u64>, page: BlockPage) {
+        if before.is_none() {
+            *self = Self::default();
+        }
+        self.blocks.extend(page.blocks);
+        self.next = page.next;
+        self.loading = false;
+        self.error = None;
+    }
+
+    /// The `before` of the page to fetch next, if one is needed: the first
+    /// page, or the next one once the selection is on the last loaded block.
+    pub fn wants_page(&self) -> Option<Option<u64>> {
+        if self.loading || self.error.is_some() {
+            return None;
+        }
+        if self.blocks.is_empty() {
+            return Some(None);
+        }
+        let at_end = self.level == Level::Blocks && self.block + 1 >= self.blocks.len();
+        self.next.filter(|_| at_end).map(Some)
+    }
+
+    pub fn selected_block(&self) -> Option<&Block> {
+        self.blocks.get(self.block)
+    }
+
+    pub fn selected_tx(&self) -> Option<&Transaction> {
+        self.selected_block()?.transactions.get(self.tx)
+    }
+
+    /// Applies a navigation key; `false` if the explorer does not use it.
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        let len = match self.level {
+            Level::Blocks => self.blocks.len(),
+            Level::Transactions => self.selected_block().map_or(0, |b| b.transactions.len()),
+            Level::Transaction => 0,
+        };
+        let cursor = match self.level {
+            Level::Blocks => &mut self.block,
+            _ => &mut self.tx,
+        };
+        match key {
+            KeyCode::Up => *cursor = cursor.saturating_sub(1),
+            KeyCode::Down if *cursor + 1 < len => *cursor += 1,
+            KeyCode::Down => {}
+            KeyCode::Enter => match self.level {
+                Level::Blocks if self.selected_block().is_some() => {
+                    self.level = Level::Transactions;
+                    self.tx = 0;
+                }
+                Level::Transactions if self.selected_tx().is_some() => self.level = Level::Transaction,
+                _ => {}
+            },
+            KeyCode::Esc => {
+                self.level = match self.level {
+                    Level::Transaction => Level::Transactions,
+                    _ => Level::Blocks,
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// The Explorer tab: its state and the node it fetches pages from.
+pub struct Explorer {
+    client: NodeClient,
+    events: EventSender,
+    pub state: ExplorerState,
+}
+
+impl Explorer {
+    pub fn new(client: NodeClient, events: EventSender) -> Self {
+        Self { client, events, state: ExplorerState::default() }
+    }
+
+    /// Handles a key on the Explorer tab, then fetches a page if one is needed.
+    pub fn handle_key(&mut self, key: KeyCode) {
+        if key == KeyCode::Char('r') {
+            self.state.blocks.clear();
+            self.state.error = None;
+        } else {
+            self.state.handle_key(key);
+        }
+        self.fetch_if_needed();
+    }
+
+    /// Starts fetching the next page in the background if the list needs one;
+    /// it arrives as an [`AppEvent::BlockPage`].
+    pub fn fetch_if_needed(&mut self) {
+        let Some(before) = self.state.wants_page() else { return };
+        self.state.loading = true;
+        let (client, events) = (self.client.clone(), self.events.clone());
+        tokio::spawn(async move {
+            let result = client.blocks(before, PAGE_SIZE).await.map_err(|err| format!("{err:#}"));
+            let _ = events.send(AppEvent::BlockPage { before, result });
+        });
+    }
+
+    pub fn apply_page(&mut self, before: Option<u64>, result: Result<BlockPage, String>) {
+        match result {
+            Ok(page) => self.state.apply_page(before, page),
+            Err(error) => {
+                self.state.loading = false;
+                self.state.error = Some(error);
+            }
+        }
+    }
+}
+
+fn short(hash: &[u8]) -> String {
+    hex::encode(&hash[..8.min(hash.len())])
+}
+
+fn highlighted<'a>(items: Vec<ListItem<'a>>, title: String) -> List<'a> {
+    List::new(items)
+        .block(Panel::default().title(title).borders(Borders::ALL))
+        .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ")
+}
+
+pub fn draw(f: &mut Frame, area: Rect, state: &ExplorerState) {
+    let status = match (&state.error, state.loading) {
+        (Some(error), _) => format!(" - error: {error} (r to retry)"),
+        (None, true) => " - loading".to_string(),
+        _ => String::new(),
+    };
+    match (state.level, state.selected_block(), state.selected_tx()) {
+        (Level::Transactions, Some(block), _) => {
+            let items = block
+                .transactions
+                .iter()
+                .map(|tx| {
+                    let sent: u64 = tx.outputs.iter().map(|o| o.amount).sum();
+                    ListItem::new(format!(
+                        "{}..  {} in  {} out  {sent} sent  fee {}",
+                        short(&tx.hash()),
+                        tx.inputs.len(),
+                        tx.outputs.len(),
+                        tx.fee
+                    ))
+                })
+                .collect();
+            let title = format!("Block #{} transactions (Esc back){status}", block.header.height);
+            let mut list = ListState::default().with_selected(Some(state.tx));
+            f.render_stateful_widget(highlighted(items, title), area, &mut list);
+        }
+        (Level::Transaction, _, Some(tx)) => {
+            let mut lines = vec![
+                format!("Hash: {}", hex::encode(tx.hash())),
+                format!("Fee: {}", tx.fee),
+                format!("Nonce: {}", tx.nonce),
+                format!("Memo: {}", tx.memo.as_deref().unwrap_or("-")),
+                String::new(),
+                format!("Inputs ({}):", tx.inputs.len()),
+            ];
+            for input in &tx.inputs {
+                lines.push(format!("  {}:{}", hex::encode(input.previous_tx), input.output_index));
+            }
+            lines.push(format!("Outputs ({}):", tx.outputs.len()));
+            for output in &tx.outputs {
+                lines.push(format!("  {}  {}", address_to_string_bech32(&output.address), output.amount));
+            }
+            let panel = Panel::default().title("Transaction (Esc back)").borders(Borders::ALL);
+            f.render_widget(Paragraph::new(lines.join("\n")).block(panel), area);
+        }
+        _ => {
+            let items = state
+                .blocks
+                .iter()
+                .map(|b| {
+                    ListItem::new(format!(
+                        "#{:<8} {}..  {:>4} txs  ts {}",
+                        b.header.height,
+                        short(&b.pow_hash),
+                        b.transactions.len(),
+                        b.header.timestamp
+                    ))
+                })
+                .collect();
+            let title = format!("Blocks (Enter open, r reload){status}");
+            let mut list = ListState::default().with_selected(Some(state.block));
+            f.render_stateful_widget(highlighted(items, title), area, &mut list);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dxid_core::{BlockHeader, TxOutput};
+
+    fn block(height: u64, txs: usize) -> Block {
+        let tx = Transaction {
+            inputs: Vec::new(),
+            outputs: vec![TxOutput {
+                address: [1u8; 32],
+                amount: height,
+                condition: None,
+                spendable_after_height: None,
+            }],
+            fee: 1,
+            nonce: 0,
+            memo: None,
+            lock_time: 0,
+        };
+        Block {
+            header: BlockHeader {
+                previous_hash: [0u8; 32],
+                merkle_root: [0u8; 32],
+                height,
+                timestamp: height,
+                difficulty: 1,
+                nonce: 0,
+                validator: [0u8; 32],
+                stake_weight: 0,
+                state_root: [0u8; 32],
+            },
+            transactions: vec![tx; txs],
+            pow_hash: [0u8; 32],
+            validator_signature: Vec::new(),
+        }
+    }
+
+    fn page(heights: std::ops::RangeInclusive<u64>, next: Option<u64>) -> BlockPage {
+        BlockPage { blocks: heights.rev().map(|h| block(h, 2)).collect(), next }
+    }
+
+    #[test]
+    fn explorer_pages_and_drills_down() {
+        let mut state = ExplorerState::default();
+        assert_eq!(state.wants_page(), Some(None));
+        state.apply_page(None, page(3..=4, Some(3)));
+        assert_eq!(state.wants_page(), None);
+
+        state.handle_key(KeyCode::Down);
+        assert_eq!(state.wants_page(), Some(Some(3)));
+        state.apply_page(Some(3), page(1..=2, None));
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Down);
+        assert_eq!((state.block, state.selected_block().unwrap().header.height), (3, 1));
+        assert_eq!(state.wants_page(), None);
+
+        state.handle_key(KeyCode::Enter);
+        state.handle_key(KeyCode::Down);
+        state.handle_key(KeyCode::Down);
+        assert_eq!((state.level, state.tx), (Level::Transactions, 1));
+        state.handle_key(KeyCode::Enter);
+        assert_eq!(state.level, Level::Transaction);
+        assert_eq!(state.selected_tx().unwrap().outputs[0].amount, 1);
+        assert!(!state.handle_key(KeyCode::Char('x')));
+        state.handle_key(KeyCode::Esc);
+        state.handle_key(KeyCode::Esc);
+        assert_eq!((state.level, state.block), (Level::Blocks, 3));
+
+        state.apply_page(None, page(5..=5, None));
+        assert_eq!((state.blocks.len(), state.block), (1, 0));
+    }
+}
@@ -0,0 +1,492 @@
+//! Identities tab: published identities from the node's `/identities`, with
+//! the possible duplicates it reported, and changes to the identity a local
+//! wallet owns, signed with its keys.
+//!
+//! Keys: `Up`/`Down` select, `c` creates and registers an identity for a
+//! wallet, `a` sets an attribute (`key=value`), `k` rotates the identity key,
+//! `x` revokes, `r` reloads. The wallet is unlocked once, on the first change.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use crossterm::event::KeyCode;
+use dxid_core::{Identity, IdentityId, IdentityStatus, PossibleDuplicate};
+use dxid_wallet::{IdentityChange, NodeClient, WalletStore};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use crate::events::{AppEvent, EventSender};
+
+/// Identities fetched per reload.
+pub const PAGE_SIZE: u64 = 100;
+/// Duplicate reports fetched per reload.
+pub const DUPLICATES_PAGE: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Create,
+    SetAttribute,
+    Rotate,
+    Revoke,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Wallet,
+    Password,
+    Attribute,
+    Confirm,
+}
+
+impl Field {
+    fn label(self) -> &'static str {
+        match self {
+            Field::Wallet => "Wallet name",
+            Field::Password => "Password",
+            Field::Attribute => "Attribute (key=value)",
+            Field::Confirm => "Type yes to revoke",
+        }
+    }
+}
+
+/// What the tab needs done after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Idle,
+    Unlock { wallet: String, password: String },
+    Run { action: Action, input: String },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IdentitiesState {
+    pub identities: Vec<Identity>,
+    /// Possible duplicates the node reported, newest first.
+    pub duplicates: Vec<PossibleDuplicate>,
+    pub selected: usize,
+    /// Unlocked wallet whose identity changes are signed with.
+    pub wallet: Option<String>,
+    /// Outcome of the last change or reload.
+    pub message: Option<String>,
+    pub busy: bool,
+    prompt: Option<(Field, String)>,
+    pending: Option<Action>,
+    wallet_name: String,
+}
+
+impl IdentitiesState {
+    pub fn is_prompting(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    pub fn selected_identity(&self) -> Option<&Identity> {
+        self.identities.get(self.selected)
+    }
+
+    /// Identities reported as possible duplicates of `id`, each once with its
+    /// highest similarity, most similar first.
+    pub fn duplicates_of(&self, id: &IdentityId) -> Vec<(IdentityId, f32)> {
+        let mut found: Vec<(IdentityId, f32)> = Vec::new();
+        for report in &self.duplicates {
+            let other = match (report.identity == *id, report.duplicate_of == *id) {
+                (true, _) => report.duplicate_of,
+                (_, true) => report.identity,
+                _ => continue,
+            };
+            match found.iter_mut().find(|(seen, _)| *seen == other) {
+                Some((_, similarity)) => *similarity = similarity.max(report.similarity),
+                None => found.push((other, report.similarity)),
+            }
+        }
+        found.sort_by(|a, b| b.1.total_cmp(&a.1));
+        found
+    }
+
+    /// Begins `action`, asking for whatever it needs first.
+    pub fn start(&mut self, action: Action) -> Step {
+        self.pending = Some(action);
+        self.advance()
+    }
+
+    /// Prompts for the next input the pending action needs, or runs it.
+    pub fn advance(&mut self) -> Step {
+        let Some(action) = self.pending else {
+            return Step::Idle;
+        };
+        let field = match (action, &self.wallet) {
+            (_, None) => Field::Wallet,
+            (Action::SetAttribute, _) => Field::Attribute,
+            (Action::Revoke, _) => Field::Confirm,
+            _ => {
+                self.pending = None;
+                return Step::Run { action, input: String::new() };
+            }
+        };
+        self.prompt = Some((field, String::new()));
+        Step::Idle
+    }
+
+    pub fn cancel(&mut self, message: impl Into<String>) {
+        self.prompt = None;
+        self.pending = None;
+        self.message = Some(message.into());
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> Step {
+        let Some((field, input)) = self.prompt.as_mut() else {
+            return self.handle_list_key(key);
+        };
+        match key {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Esc => self.cancel("cancelled"),
+            KeyCode::Enter => {
+                let (field, input) = (*field, std::mem::take(input));
+                self.prompt = None;
+                return self.submit(field, input);
+            }
+            _ => {}
+        }
+        Step::Idle
+    }
+
+    fn submit(&mut self, field: Field, input: String) -> Step {
+        match field {
+            Field::Wallet => {
+                self.wallet_name = input;
+                self.prompt = Some((Field::Password, String::new()));
+                Step::Idle
+            }
+            Field::Password => Step::Unlock { wallet: self.wallet_name.clone(), password: input },
+            Field::Attribute if !input.contains('=') => {
+                self.message = Some("attributes are entered as key=value".into());
+                self.prompt = Some((Field::Attribute, input));
+                Step::Idle
+            }
+            Field::Confirm if input != "yes" => {
+                self.cancel("revocation cancelled");
+                Step::Idle
+            }
+            Field::Attribute | Field::Confirm => match self.pending.take() {
+                Some(action) => Step::Run { action, input },
+                None => Step::Idle,
+            },
+        }
+    }
+
+    fn handle_list_key(&mut self, key: KeyCode) -> Step {
+        if self.busy && matches!(key, KeyCode::Char('c' | 'a' | 'k' | 'x')) {
+            self.message = Some("still working on the last change".into());
+            return Step::Idle;
+        }
+        match key {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down if self.selected + 1 < self.identities.len() => self.selected += 1,
+            KeyCode::Char('c') => return self.start(Action::Create),
+            KeyCode::Char('a') => return self.start(Action::SetAttribute),
+            KeyCode::Char('k') => return self.start(Action::Rotate),
+            KeyCode::Char('x') => return self.start(Action::Revoke),
+            _ => {}
+        }
+        Step::Idle
+    }
+}
+
+/// The Identities tab: its state and what it signs and publishes with.
+pub struct IdentitiesTab {
+    client: NodeClient,
+    store: Arc<WalletStore>,
+    events: EventSender,
+    pub state: IdentitiesState,
+}
+
+impl IdentitiesTab {
+    pub fn new(client: NodeClient, store: WalletStore, events: EventSender) -> Self {
+        Self { client, store: Arc::new(store), events, state: IdentitiesState::default() }
+    }
+
+    pub fn is_prompting(&self) -> bool {
+        self.state.is_prompting()
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) {
+        if key == KeyCode::Char('r') && !self.is_prompting() {
+            return self.reload();
+        }
+        let step = self.state.handle_key(key);
+        self.perform(step);
+    }
+
+    /// Fetches the published identities and duplicate reports in the
+    /// background; they arrive as [`AppEvent::Identities`] and
+    /// [`AppEvent::Duplicates`].
+    pub fn reload(&self) {
+        let (client, events) = (self.client.clone(), self.events.clone());
+        tokio::spawn(async move {
+            let result = client.identities(0, PAGE_SIZE).await.map_err(|err| format!("{err:#}"));
+            let _ = events.send(AppEvent::Identities(result));
+            let result = client.identity_duplicates(DUPLICATES_PAGE).await.map_err(|err| format!("{err:#}"));
+            let _ = events.send(AppEvent::Duplicates(result));
+        });
+    }
+
+    pub fn apply_identities(&mut self, result: Result<Vec<Identity>, String>) {
+        match result {
+            Ok(identities) => {
+                self.state.selected = self.state.selected.min(identities.len().saturating_sub(1));
+                self.state.identities = identities;
+            }
+            Err(err) => self.state.message = Some(format!("loading identities failed: {err}")),
+        }
+    }
+
+    /// Nodes without duplicate detection serve no reports; that is not worth
+    /// a message, so failures just clear them.
+    pub fn apply_duplicates(&mut self, result: Result<Vec<PossibleDuplicate>, String>) {
+        self.state.duplicates = result.unwrap_or_default();
+    }
+
+    /// Shows the outcome of a change and reloads to pick it up.
+    pub fn apply_change(&mut self, result: Result<String, String>) {
+        self.state.busy = false;
+        self.state.message = Some(result.unwrap_or_else(|err| err));
+        self.reload();
+    }
+
+    fn perform(&mut self, step: Step) {
+        match step {
+            Step::Idle => {}
+            Step::Unlock { wallet, password } => {
+                let unlocked = self.store.load(&wallet).and_then(|w| self.store.unlock(&w, &password));
+                let step = match unlocked {
+                    Ok(()) => {
+                        self.state.wallet = Some(wallet);
+                        self.state.advance()
+                    }
+                    Err(err) => {
+                        self.state.cancel(format!("unlocking {wallet} failed: {err:#}"));
+                        Step::Idle
+                    }
+                };
+                self.perform(step);
+            }
+            Step::Run { action, input } => {
+                self.state.busy = true;
+                self.state.message = Some("working...".into());
+                let wallet = self.state.wallet.clone().unwrap_or_default();
+                let selected = self.state.selected_identity().map(|i| i.id);
+                let (client, store, events) = (self.client.clone(), self.store.clone(), self.events.clone());
+                tokio::spawn(async move {
+                    let result = run(&client, &store, &wallet, selected, action, &input).await;
+                    let _ = events.send(AppEvent::IdentityChanged(result.map_err(|err| format!("{err:#}"))));
+                });
+            }
+        }
+    }
+}
+
+/// Signs and publishes `action` for the identity `wallet` owns.
+async fn run(
+    client: &NodeClient,
+    store: &WalletStore,
+    wallet: &str,
+    selected: Option<IdentityId>,
+    action: Action,
+    input: &str,
+) -> Result<String> {
+    let mut owner = store.load(wallet)?;
+    if action == Action::Create {
+        let identity = match owner.identity.as_ref() {
+            Some(owned) => owned.identity.clone(),
+            None => store.create_identity(&mut owner, "")?,
+        };
+        if client.identity(&identity.id).await?.is_some() {
+            return Err(anyhow!("{wallet} already has identity {}", identity.id));
+        }
+        let register = IdentityChange::Register { public_key: Vec::new() };
+        let update = store.sign_identity_update(&mut owner, "", None, register)?;
+        client.update_identity(&update).await?;
+        return Ok(format!("registered identity {}", identity.id));
+    }
+
+    let id = owner.identity_id().ok_or_else(|| anyhow!("{wallet} owns no identity; press c"))?;
+    if selected.is_some_and(|selected| selected != id) {
+        return Err(anyhow!("the selected identity is not owned by {wallet}"));
+    }
+    let published = client.identity(&id).await?.ok_or_else(|| anyhow!("identity {id} is not registered"))?;
+    let change = match action {
+        Action::Rotate => {
+            let rotation = store.rotate_identity_key(&mut owner, "")?;
+            client.rotate_identity(&rotation).await?;
+            return Ok(format!("rotated the key of {id}"));
+        }
+        Action::SetAttribute => {
+            let (key, value) = input.split_once('=').unwrap_or((input, ""));
+            IdentityChange::SetAttribute { key: key.trim().to_string(), value: value.trim().to_string() }
+        }
+        _ => IdentityChange::Revoke,
+    };
+    let update = store.sign_identity_update(&mut owner, "", Some(&published), change)?;
+    client.update_identity(&update).await?;
+    Ok(format!("updated identity {id}"))
+}
+
+fn badge(status: &IdentityStatus) -> Span<'static> {
+    match status {
+        IdentityStatus::Active => Span::styled("[Active] ", Style::default().fg(Color::Green)),
+        IdentityStatus::Revoked => Span::styled("[Revoked]", Style::default().fg(Color::Red)),
+    }
+}
+
+pub fn draw(f: &mut Frame, area: Rect, state: &IdentitiesState) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)].as_ref())
+        .split(area);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+        .split(rows[0]);
+
+    let items: Vec<ListItem> = state
+        .identities
+        .iter()
+        .map(|identity| {
+            let mut spans = vec![badge(&identity.status), Span::raw(identity.id.to_string())];
+            if !state.duplicates_of(&identity.id).is_empty() {
+                spans.push(Span::styled(" [dup?]", Style::default().fg(Color::Yellow)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title("Identities (c create, a attribute, k rotate, x revoke, r reload)")
+                .borders(Borders::ALL),
+        )
+        .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ");
+    let mut selected = ListState::default().with_selected(Some(state.selected));
+    f.render_stateful_widget(list, columns[0], &mut selected);
+
+    let details = match state.selected_identity() {
+        Some(identity) => {
+            let mut lines = vec![
+                Line::from(vec![Span::raw("Status: "), badge(&identity.status)]),
+                Line::from(format!("Id: {}", identity.id)),
+                Line::from(format!(
+                    "Keys: {} (rotated {} times)",
+                    identity.public_keys.len(),
+                    identity.public_keys.len().saturating_sub(1)
+                )),
+            ];
+            if let Some(key) = identity.public_keys.last() {
+                lines.push(Line::from(format!("Current key: {}", hex::encode(key))));
+            }
+            lines.push(Line::from("Attributes:"));
+            let mut attributes: Vec<_> = identity.attributes.values().collect();
+            attributes.sort_by(|a, b| a.key.cmp(&b.key));
+            lines.extend(attributes.into_iter().map(|a| Line::from(format!("  {} = {}", a.key, a.value))));
+            let duplicates = state.duplicates_of(&identity.id);
+            if !duplicates.is_empty() {
+                lines.push(Line::from(Span::styled(
+                    "Possible duplicates:",
+                    Style::default().fg(Color::Yellow),
+                )));
+                lines.extend(duplicates.into_iter().map(|(other, similarity)| {
+                    Line::from(format!("  {other} (similarity {similarity:.2})"))
+                }));
+            }
+            lines
+        }
+        None => vec![Line::from("No identities published yet.")],
+    };
+    let title = match &state.wallet {
+        Some(wallet) => format!("Details - signing with {wallet}"),
+        None => "Details".to_string(),
+    };
+    f.render_widget(
+        Paragraph::new(details).block(Block::default().title(title).borders(Borders::ALL)),
+        columns[1],
+    );
+
+    let (title, text) = match &state.prompt {
+        Some((Field::Password, input)) => (Field::Password.label(), "*".repeat(input.chars().count())),
+        Some((field, input)) => (field.label(), input.clone()),
+        None => ("Status", state.message.clone().unwrap_or_default()),
+    };
+    f.render_widget(Paragraph::new(text).block(Block::default().title(title).borders(Borders::ALL)), rows[1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_in(state: &mut IdentitiesState, text: &str) -> Step {
+        for c in text.chars() {
+            state.handle_key(KeyCode::Char(c));
+        }
+        state.handle_key(KeyCode::Enter)
+    }
+
+    #[test]
+    fn changes_unlock_a_wallet_once_then_prompt_for_their_input() {
+        let mut state = IdentitiesState::default();
+        assert_eq!(state.handle_key(KeyCode::Char('a')), Step::Idle);
+        assert!(state.is_prompting());
+        assert_eq!(type_in(&mut state, "alice"), Step::Idle);
+        let unlock = type_in(&mut state, "pw1");
+        assert_eq!(unlock, Step::Unlock { wallet: "alice".into(), password: "pw1".into() });
+
+        state.wallet = Some("alice".into());
+        assert_eq!(state.advance(), Step::Idle);
+        assert_eq!(type_in(&mut state, "no equals"), Step::Idle);
+        assert!(state.is_prompting());
+        for _ in 0.."no equals".len() {
+            state.handle_key(KeyCode::Backspace);
+        }
+        let run = type_in(&mut state, "email=a@example.org");
+        assert_eq!(run, Step::Run { action: Action::SetAttribute, input: "email=a@example.org".into() });
+        assert!(!state.is_prompting());
+
+        assert_eq!(
+            state.handle_key(KeyCode::Char('k')),
+            Step::Run { action: Action::Rotate, input: String::new() }
+        );
+        state.handle_key(KeyCode::Char('x'));
+        assert_eq!(type_in(&mut state, "no"), Step::Idle);
+        assert_eq!(state.message.as_deref(), Some("revocation cancelled"));
+        state.handle_key(KeyCode::Char('x'));
+        assert_eq!(type_in(&mut state, "yes"), Step::Run { action: Action::Revoke, input: "yes".into() });
+
+        state.handle_key(KeyCode::Char('a'));
+        state.handle_key(KeyCode::Esc);
+        assert!(!state.is_prompting());
+        assert_eq!(state.advance(), Step::Idle);
+    }
+
+    #[test]
+    fn duplicates_are_listed_once_per_other_identity() {
+        let (a, b, c) = (IdentityId::new_v4(), IdentityId::new_v4(), IdentityId::new_v4());
+        let report = |identity, duplicate_of, similarity| PossibleDuplicate {
+            identity,
+            duplicate_of,
+            similarity,
+            detected_at: 0,
+        };
+        let state = IdentitiesState {
+            duplicates: vec![report(b, a, 0.95), report(a, c, 0.97), report(a, b, 0.93)],
+            ..Default::default()
+        };
+        assert_eq!(state.duplicates_of(&a), [(c, 0.97), (b, 0.95)]);
+        assert_eq!(state.duplicates_of(&b), [(a, 0.95)]);
+        assert!(state.duplicates_of(&IdentityId::new_v4()).is_empty());
+    }
+}
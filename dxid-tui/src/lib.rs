@@ -1,126 +1,87 @@
+pub mod ai;
+pub mod app;
+pub mod charts;
+pub mod events;
+pub mod explorer;
+pub mod identities;
+pub mod logs;
+pub mod node;
+
 use anyhow::Result;
 use crossterm::{
-    event::{self, Event, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use ratatui::{
-    backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
-    text::Span,
-    widgets::{Block, Borders, Paragraph, Tabs},
-    Terminal,
-};
+use ratatui::{backend::CrosstermBackend, Terminal};
 use std::io;
-use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
+use tracing_subscriber::{prelude::*, EnvFilter};
+
+pub use ai::AiChat;
+pub use app::App;
+pub use charts::Window;
+pub use events::{AppEvent, EventBus, EventSender, TICK_RATE};
+pub use explorer::Explorer;
+pub use identities::IdentitiesTab;
+pub use logs::{LogBuffer, LogPane};
+pub use node::{NodeEvent, NodeFeed, NodeView, TuiConfig};
 
+/// Launches the TUI against the node at `DXID_NODE_URL`, or the local default.
 pub fn launch_tui() -> Result<()> {
+    launch_tui_with(TuiConfig::default())
+}
+
+pub fn launch_tui_with(config: TuiConfig) -> Result<()> {
+    let logs = LogBuffer::default();
+    init_logging(logs.clone());
     let rt = Runtime::new()?;
-    rt.block_on(async { run_ui().await })
+    rt.block_on(async { run_ui(config, logs).await })
 }
 
-async fn run_ui() -> Result<()> {
+/// Routes this process's tracing events into the log pane; writing them to the
+/// terminal would tear the UI. `RUST_LOG` overrides the default filter.
+fn init_logging(logs: LogBuffer) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("info,dxid_tui=debug,dxid_wallet=debug"));
+    let _ = tracing::subscriber::set_global_default(tracing_subscriber::registry().with(filter).with(logs));
+}
+
+/// Draws after every batch of events from the bus; input, ticks and node
+/// updates each arrive as events, so none of them waits on another.
+async fn run_ui(config: TuiConfig, logs: LogBuffer) -> Result<()> {
+    let mut bus = EventBus::default();
+    let mut app = App::new(&config, logs, bus.sender())?;
+    bus.spawn_input();
+    bus.spawn_ticks(TICK_RATE);
+    NodeFeed::spawn(&config, bus.sender());
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let tabs = vec![
-        "Dashboard",
-        "Wallet",
-        "Identities",
-        "Chains",
-        "Bridge",
-        "Mining",
-        "AI",
-    ];
-    let mut active = 0usize;
-    let mut input = String::new();
-    let mut ai_output = String::new();
-    let mut last_tick = Instant::now();
-
-    loop {
-        terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
-                .split(f.size());
-            let titles: Vec<Span> = tabs
-                .iter()
-                .map(|t| Span::styled(*t, Style::default().fg(Color::Cyan)))
-                .collect();
-            let tabs_widget = Tabs::new(titles)
-                .select(active)
-                .block(Block::default().borders(Borders::ALL).title("dxid"))
-                .style(Style::default().fg(Color::White))
-                .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
-            f.render_widget(tabs_widget, chunks[0]);
-
-            match active {
-                0 => {
-                    let para = Paragraph::new("Dashboard\nHeight: n/a\nPeers: n/a");
-                    f.render_widget(para, chunks[1]);
-                }
-                6 => {
-                    let area = Layout::default()
-                        .direction(Direction::Vertical)
-                        .constraints([Constraint::Min(5), Constraint::Length(3)].as_ref())
-                        .split(chunks[1]);
-                    f.render_widget(
-                        Paragraph::new(ai_output.clone()).block(Block::default().title("AI")),
-                        area[0],
-                    );
-                    f.render_widget(
-                        Paragraph::new(input.clone())
-                            .block(Block::default().title("Prompt").borders(Borders::ALL)),
-                        area[1],
-                    );
-                }
-                _ => {
-                    let para = Paragraph::new("Use number keys 1-7 to switch tabs. q to quit.");
-                    f.render_widget(para, chunks[1]);
-                }
-            }
-        })?;
-
-        let timeout = Duration::from_millis(250);
-        let poll = event::poll(timeout)?;
-        if poll {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => break,
-                    KeyCode::Char('1') => active = 0,
-                    KeyCode::Char('2') => active = 1,
-                    KeyCode::Char('3') => active = 2,
-                    KeyCode::Char('4') => active = 3,
-                    KeyCode::Char('5') => active = 4,
-                    KeyCode::Char('6') => active = 5,
-                    KeyCode::Char('7') => active = 6,
-                    KeyCode::Enter if active == 6 => {
-                        // Fake AI query for now; integration happens via dxid-ai-hypervisor.
-                        ai_output = format!("Hypervisor would answer: {}", input);
-                        input.clear();
-                    }
-                    KeyCode::Char(c) if active == 6 => input.push(c),
-                    KeyCode::Backspace if active == 6 => {
-                        input.pop();
-                    }
-                    _ => {}
-                }
-            }
-        }
-
-        if last_tick.elapsed() > Duration::from_secs(5) {
-            last_tick = Instant::now();
-        }
-    }
+    let result = event_loop(&mut terminal, &mut app, &mut bus).await;
 
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
     terminal.show_cursor()?;
+    result
+}
+
+async fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+    bus: &mut EventBus,
+) -> Result<()> {
+    while !app.quit {
+        terminal.draw(|f| app.draw(f))?;
+        let Some(event) = bus.next().await else { break };
+        app.handle(event);
+        while let Some(event) = bus.try_next() {
+            app.handle(event);
+        }
+    }
     Ok(())
 }
 
@@ -0,0 +1,253 @@
+//! Log pane: a tracing layer keeping the most recent events in a ring buffer,
+//! shown under the active tab filtered by level and a search string.
+//!
+//! Keys: `F2` shows or hides the pane, `F3` cycles the minimum level, `F4`
+//! edits the search (`Enter` keeps it, `Esc` clears it).
+
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crossterm::event::KeyCode;
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Events kept when no capacity is given.
+pub const LOG_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent {
+    pub timestamp: u64,
+    pub level: Level,
+    pub target: String,
+    /// The event's message followed by its other fields as `name=value`.
+    pub message: String,
+}
+
+/// Ring buffer of recent events, filled as a [`Layer`] of the TUI's subscriber.
+#[derive(Clone)]
+pub struct LogBuffer {
+    events: Arc<Mutex<VecDeque<LogEvent>>>,
+    capacity: usize,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(LOG_CAPACITY)
+    }
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { events: Arc::default(), capacity: capacity.max(1) }
+    }
+
+    /// Oldest first.
+    pub fn events(&self) -> MutexGuard<'_, VecDeque<LogEvent>> {
+        self.events.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn push(&self, event: LogEvent) {
+        let mut events = self.events();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={value}", field.name());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogBuffer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        self.push(LogEvent {
+            timestamp: dxid_core::now_ts(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: visitor.message + visitor.fields.as_str(),
+        });
+    }
+}
+
+/// How the pane is shown and filtered.
+#[derive(Debug, Clone)]
+pub struct LogPane {
+    pub open: bool,
+    /// Least severe level shown.
+    pub level: Level,
+    /// Case-insensitive text events must contain, in their target or message.
+    pub search: String,
+    /// Whether keys are going into `search`.
+    pub editing: bool,
+}
+
+impl Default for LogPane {
+    fn default() -> Self {
+        Self { open: false, level: Level::INFO, search: String::new(), editing: false }
+    }
+}
+
+impl LogPane {
+    /// Applies a key; `false` if the pane does not use it. While the search
+    /// is being edited every key is used.
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        if self.editing {
+            match key {
+                KeyCode::Char(c) => self.search.push(c),
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Enter => self.editing = false,
+                KeyCode::Esc => {
+                    self.search.clear();
+                    self.editing = false;
+                }
+                _ => {}
+            }
+            return true;
+        }
+        match key {
+            KeyCode::F(2) => self.open = !self.open,
+            KeyCode::F(3) if self.open => {
+                self.level = match self.level {
+                    Level::ERROR => Level::WARN,
+                    Level::WARN => Level::INFO,
+                    Level::INFO => Level::DEBUG,
+                    Level::DEBUG => Level::TRACE,
+                    _ => Level::ERROR,
+                }
+            }
+            KeyCode::F(4) => {
+                self.open = true;
+                self.editing = true;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        if event.level > self.level {
+            return false;
+        }
+        let search = self.search.to_lowercase();
+        search.is_empty()
+            || event.message.to_lowercase().contains(&search)
+            || event.target.to_lowercase().contains(&search)
+    }
+
+    /// The newest `limit` events passing the filters, oldest first.
+    pub fn visible(&self, events: &VecDeque<LogEvent>, limit: usize) -> Vec<LogEvent> {
+        let mut shown: Vec<LogEvent> =
+            events.iter().rev().filter(|e| self.matches(e)).take(limit).cloned().collect();
+        shown.reverse();
+        shown
+    }
+}
+
+fn level_color(level: Level) -> Color {
+    match level {
+        Level::ERROR => Color::Red,
+        Level::WARN => Color::Yellow,
+        Level::INFO => Color::Green,
+        Level::DEBUG => Color::Blue,
+        _ => Color::DarkGray,
+    }
+}
+
+pub fn draw(f: &mut Frame, area: Rect, pane: &LogPane, buffer: &LogBuffer) {
+    let events = pane.visible(&buffer.events(), area.height.saturating_sub(2) as usize);
+    let lines: Vec<Line> = events
+        .iter()
+        .map(|e| {
+            Line::from(vec![
+                Span::styled(format!("{:<5} ", e.level), Style::default().fg(level_color(e.level))),
+                Span::styled(format!("{} ", e.target), Style::default().fg(Color::DarkGray)),
+                Span::raw(e.message.clone()),
+            ])
+        })
+        .collect();
+    let search = match (pane.editing, pane.search.is_empty()) {
+        (true, _) => format!(", search: {}_", pane.search),
+        (false, false) => format!(", search: {}", pane.search),
+        (false, true) => String::new(),
+    };
+    let title = format!("Logs (>= {}{search}; F3 level, F4 search, F2 hide)", pane.level);
+    f.render_widget(Paragraph::new(lines).block(Block::default().title(title).borders(Borders::ALL)), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn buffer_keeps_recent_events_and_pane_filters_them() {
+        let buffer = LogBuffer::new(3);
+        let subscriber = tracing_subscriber::registry().with(buffer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("dropped by the ring buffer");
+            tracing::debug!(height = 7, "new block");
+            tracing::warn!("node unreachable");
+            tracing::info!(peer = "a", "peer joined");
+        });
+        let events = buffer.events().clone();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].message, "new block height=7");
+        assert_eq!(events[2].message, "peer joined peer=a");
+
+        let mut pane = LogPane::default();
+        let messages = |pane: &LogPane| -> Vec<String> {
+            pane.visible(&events, 10).into_iter().map(|e| e.message).collect()
+        };
+        assert_eq!(messages(&pane), ["node unreachable", "peer joined peer=a"]);
+        assert!(pane.handle_key(KeyCode::F(2)));
+        pane.handle_key(KeyCode::F(3));
+        assert_eq!(pane.level, Level::DEBUG);
+        assert_eq!(pane.visible(&events, 2).len(), 2);
+
+        pane.handle_key(KeyCode::F(4));
+        for c in "PEER".chars() {
+            assert!(pane.handle_key(KeyCode::Char(c)));
+        }
+        pane.handle_key(KeyCode::Enter);
+        assert_eq!(messages(&pane), ["peer joined peer=a"]);
+        assert!(!pane.handle_key(KeyCode::Char('q')));
+        pane.handle_key(KeyCode::F(4));
+        pane.handle_key(KeyCode::Esc);
+        assert_eq!((pane.search.as_str(), pane.editing), ("", false));
+    }
+}
@@ -0,0 +1,251 @@
+//! Live node data for the dashboard: a background feed that reads the node's
+//! `/status` whenever its event stream announces a change, and follows its
+//! block stream, sending what it sees as events the app applies to its
+//! [`NodeView`].
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use dxid_core::events::NodeEvent as ChainEvent;
+use dxid_core::Block;
+use dxid_wallet::{NodeClient, NodeStatus};
+use tracing::{debug, info};
+
+use crate::charts::Metrics;
+use crate::events::{AppEvent, EventSender};
+
+/// Endpoint used when `DXID_NODE_URL` is unset.
+pub const DEFAULT_NODE_URL: &str = "http://127.0.0.1:8080";
+/// Blocks kept for the dashboard's recent blocks list.
+pub const RECENT_BLOCKS: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct TuiConfig {
+    /// REST endpoint of the node to watch.
+    pub endpoint: String,
+    pub poll_interval: Duration,
+    /// Wallets that sign identity changes; the CLI's `~/.dxid/wallets` by default.
+    pub wallet_dir: PathBuf,
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: std::env::var("DXID_NODE_URL").unwrap_or_else(|_| DEFAULT_NODE_URL.to_string()),
+            poll_interval: Duration::from_secs(2),
+            wallet_dir: dirs::home_dir().unwrap_or(std::env::temp_dir()).join(".dxid").join("wallets"),
+        }
+    }
+}
+
+impl TuiConfig {
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+}
+
+/// A block as the dashboard lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSummary {
+    pub height: u64,
+    pub hash: String,
+    pub timestamp: u64,
+    pub difficulty: u64,
+    pub transactions: usize,
+}
+
+impl From<&Block> for BlockSummary {
+    fn from(block: &Block) -> Self {
+        Self {
+            height: block.header.height,
+            hash: hex::encode(block.pow_hash),
+            timestamp: block.header.timestamp,
+            difficulty: block.header.difficulty,
+            transactions: block.transactions.len(),
+        }
+    }
+}
+
+/// What the node feed reports.
+#[derive(Debug, Clone)]
+pub enum NodeEvent {
+    Status(NodeStatus),
+    Block(BlockSummary),
+    /// A failed request; the feed retries on its own.
+    Error(String),
+}
+
+/// What the dashboard knows about the node.
+#[derive(Debug, Clone, Default)]
+pub struct NodeView {
+    pub status: Option<NodeStatus>,
+    /// Newest first.
+    pub recent: VecDeque<BlockSummary>,
+    /// Last failure talking to the node, cleared by the next success.
+    pub error: Option<String>,
+    pub metrics: Metrics,
+}
+
+impl NodeView {
+    pub fn apply(&mut self, event: NodeEvent) {
+        match event {
+            NodeEvent::Status(status) => {
+                self.metrics.peers.record(dxid_core::now_ts(), status.peers as u64);
+                self.status = Some(status);
+                self.error = None;
+            }
+            NodeEvent::Block(block) => {
+                self.push_block(block);
+                self.error = None;
+            }
+            NodeEvent::Error(error) => self.error = Some(error),
+        }
+    }
+
+    /// Records `block`, replacing any block already listed at its height, and
+    /// charts its gaps to the neighbouring blocks already listed.
+    pub fn push_block(&mut self, block: BlockSummary) {
+        if self.recent.iter().all(|b| b.height != block.height) {
+            for b in &self.recent {
+                if b.height + 1 == block.height {
+                    self.metrics.record_block(block.timestamp, b.timestamp, block.difficulty);
+                } else if b.height == block.height + 1 {
+                    self.metrics.record_block(b.timestamp, block.timestamp, b.difficulty);
+                }
+            }
+        }
+        self.recent.retain(|b| b.height != block.height);
+        let at = self.recent.iter().position(|b| b.height < block.height).unwrap_or(self.recent.len());
+        self.recent.insert(at, block);
+        self.recent.truncate(RECENT_BLOCKS);
+        if let Some(status) = self.status.as_mut() {
+            status.height = status.height.max(self.recent[0].height);
+            status.network_height = status.network_height.max(status.height);
+        }
+    }
+}
+
+/// Background tasks watching the node, reporting over the event bus.
+#[derive(Clone)]
+pub struct NodeFeed {
+    client: NodeClient,
+    events: EventSender,
+}
+
+impl NodeFeed {
+    /// Starts watching and following the node at `config.endpoint` on the
+    /// current tokio runtime.
+    pub fn spawn(config: &TuiConfig, events: EventSender) {
+        let feed = Self { client: NodeClient::new(config.endpoint.clone()), events };
+        tokio::spawn(feed.clone().poll_status(config.poll_interval));
+        tokio::spawn(feed.follow_blocks(config.poll_interval));
+    }
+
+    fn send(&self, event: NodeEvent) {
+        let _ = self.events.send(AppEvent::Node(event));
+    }
+
+    fn record<T>(&self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                debug!("node feed: {err:#}");
+                self.send(NodeEvent::Error(format!("{err:#}")));
+                None
+            }
+        }
+    }
+
+    /// Reads the status again whenever the node announces a block, reorg or
+    /// peer change, and at least every `interval`, which also picks up the
+    /// mempool filling.
+    async fn poll_status(self, interval: Duration) {
+        let mut announcements = None;
+        while !self.events.is_closed() {
+            if let Some(status) = self.record(self.client.status().await) {
+                self.send(NodeEvent::Status(status));
+            }
+            if announcements.is_none() {
+                announcements = match self.client.subscribe_events().await {
+                    Ok(subscription) => Some(subscription),
+                    Err(err) => {
+                        debug!("node events: {err:#}");
+                        None
+                    }
+                };
+            }
+            let Some(subscription) = announcements.as_mut() else {
+                tokio::time::sleep(interval).await;
+                continue;
+            };
+            let deadline = tokio::time::Instant::now() + interval;
+            loop {
+                match tokio::time::timeout_at(deadline, subscription.next_event()).await {
+                    Ok(Ok(Some(ChainEvent::Transaction { .. }))) => {}
+                    Ok(Ok(Some(_))) | Err(_) => break,
+                    Ok(Ok(None) | Err(_)) => {
+                        announcements = None;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Follows the block stream from a few blocks back, reconnecting after
+    /// `retry` whenever the node drops it, from the block after the last seen.
+    async fn follow_blocks(self, retry: Duration) {
+        let mut next = 0;
+        while !self.events.is_closed() {
+            if let Some(status) = self.record(self.client.status().await) {
+                let from = next.max(status.height.saturating_sub(RECENT_BLOCKS as u64 - 1));
+                if let Some(mut blocks) = self.record(self.client.subscribe_blocks(from).await) {
+                    info!(from, "following blocks");
+                    while let Some(Some(block)) = self.record(blocks.next_block().await) {
+                        info!(height = block.header.height, txs = block.transactions.len(), "new block");
+                        next = next.max(block.header.height + 1);
+                        self.send(NodeEvent::Block(BlockSummary::from(&block)));
+                    }
+                }
+            }
+            tokio::time::sleep(retry).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(height: u64) -> BlockSummary {
+        BlockSummary { height, hash: String::new(), timestamp: height * 10, difficulty: 99, transactions: 0 }
+    }
+
+    #[test]
+    fn view_keeps_the_newest_blocks_in_order() {
+        let mut view = NodeView {
+            status: Some(NodeStatus { height: 3, peers: 1, network_height: 3, mempool: 0 }),
+            ..NodeView::default()
+        };
+        for height in [1, 3, 2, 3] {
+            view.push_block(summary(height));
+        }
+        let heights: Vec<u64> = view.recent.iter().map(|b| b.height).collect();
+        assert_eq!(heights, [3, 2, 1]);
+        // 2 -> 1 and 3 -> 2 are charted once each; the repeated 3 is not.
+        assert_eq!(view.metrics.block_time.buckets(30, 30, 3), [0, 10, 10]);
+        assert_eq!(view.metrics.hash_rate.latest(), Some(10));
+
+        for height in 4..20 {
+            view.push_block(summary(height));
+        }
+        assert_eq!(view.recent.len(), RECENT_BLOCKS);
+        assert_eq!((view.recent[0].height, view.recent[RECENT_BLOCKS - 1].height), (19, 10));
+        let status = view.status.unwrap();
+        assert_eq!((status.height, status.network_height), (19, 19));
+        assert_eq!(status.sync_progress(), 1.0);
+    }
+}
@@ -0,0 +1,159 @@
+//! Turning text into vectors. Providers embed a bounded batch per call;
+//! [`embed_all`] splits longer inputs and checks what comes back.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use dxid_config::{EmbeddingProvider, EmbeddingSettings};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::OpenAiEmbedder;
+
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Model name, recorded with the embeddings it produces.
+    fn model(&self) -> &str;
+
+    /// Length of the vectors [`Embedder::embed_batch`] returns.
+    fn dimensions(&self) -> usize;
+
+    /// Most texts [`Embedder::embed_batch`] takes at once.
+    fn max_batch(&self) -> usize;
+
+    /// Switches to vectors of `dimensions`, for models that can produce
+    /// several lengths; others accept only their own.
+    fn set_dimensions(&mut self, dimensions: usize) -> Result<()> {
+        if dimensions != self.dimensions() {
+            return Err(anyhow!(
+                "{} produces {}-dimensional vectors, not {dimensions}",
+                self.model(),
+                self.dimensions()
+            ));
+        }
+        Ok(())
+    }
+
+    /// One vector per text, in order, for at most `max_batch` texts.
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Spaces another embedder's requests evenly, at most `requests_per_minute`,
+/// for long jobs against providers with rate limits.
+pub struct RateLimitedEmbedder {
+    inner: Box<dyn Embedder>,
+    interval: Duration,
+    /// When the next request may start.
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimitedEmbedder {
+    pub fn new(inner: Box<dyn Embedder>, requests_per_minute: u32) -> Self {
+        let interval = Duration::from_secs(60) / requests_per_minute.max(1);
+        Self { inner, interval, next: Mutex::new(None) }
+    }
+}
+
+#[async_trait]
+impl Embedder for RateLimitedEmbedder {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn dimensions(&self) -> usize {
+        self.inner.dimensions()
+    }
+
+    fn max_batch(&self) -> usize {
+        self.inner.max_batch()
+    }
+
+    fn set_dimensions(&mut self, dimensions: usize) -> Result<()> {
+        self.inner.set_dimensions(dimensions)
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        {
+            let mut next = self.next.lock().await;
+            if let Some(at) = *next {
+                tokio::time::sleep_until(at).await;
+            }
+            *next = Some(Instant::now() + self.interval);
+        }
+        self.inner.embed_batch(texts).await
+    }
+}
+
+/// Embeds `texts` in batches of the embedder's `max_batch`, checking that
+/// every text gets a vector of the embedder's length.
+pub async fn embed_all(embedder: &dyn Embedder, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(embedder.max_batch().max(1)) {
+        let embedded = embedder.embed_batch(batch).await?;
+        if embedded.len() != batch.len() {
+            return Err(anyhow!(
+                "{} returned {} vectors for {} texts",
+                embedder.model(),
+                embedded.len(),
+                batch.len()
+            ));
+        }
+        if let Some(bad) = embedded.iter().find(|vector| vector.len() != embedder.dimensions()) {
+            return Err(anyhow!(
+                "{} returned a {}-dimensional vector, expected {}",
+                embedder.model(),
+                bad.len(),
+                embedder.dimensions()
+            ));
+        }
+        vectors.extend(embedded);
+    }
+    Ok(vectors)
+}
+
+/// The embedder `settings` describe; `openai_api_key` authenticates the
+/// `openai` provider.
+pub fn build_embedder(settings: &EmbeddingSettings, openai_api_key: &str) -> Result<Box<dyn Embedder>> {
+    let mut embedder: Box<dyn Embedder> = match settings.provider {
+        EmbeddingProvider::Openai => {
+            let mut embedder = OpenAiEmbedder::openai(openai_api_key, &settings.model)?;
+            if let Some(batch) = settings.batch_size {
+                embedder = embedder.with_max_batch(batch);
+            }
+            Box::new(embedder)
+        }
+        EmbeddingProvider::OpenaiCompatible => {
+            let endpoint = settings
+                .endpoint
+                .as_deref()
+                .ok_or_else(|| anyhow!("an openai_compatible embedder needs an endpoint"))?;
+            let dimensions = settings
+                .dimensions
+                .ok_or_else(|| anyhow!("an openai_compatible embedder needs its model's dimensions"))?;
+            let mut embedder = OpenAiEmbedder::compatible(endpoint, &settings.model, dimensions);
+            if let Some(batch) = settings.batch_size {
+                embedder = embedder.with_max_batch(batch);
+            }
+            Box::new(embedder)
+        }
+        #[cfg(feature = "onnx")]
+        EmbeddingProvider::Onnx => {
+            let tokenizer =
+                settings.tokenizer.as_deref().ok_or_else(|| anyhow!("an onnx embedder needs a tokenizer"))?;
+            let mut embedder = crate::OnnxEmbedder::load(&settings.model, tokenizer)?;
+            if let Some(batch) = settings.batch_size {
+                embedder = embedder.with_max_batch(batch);
+            }
+            Box::new(embedder)
+        }
+        #[cfg(not(feature = "onnx"))]
+        EmbeddingProvider::Onnx => {
+            return Err(anyhow!("dxid-vectors was built without the onnx feature"));
+        }
+    };
+    if let Some(dimensions) = settings.dimensions {
+        embedder.set_dimensions(dimensions)?;
+    }
+    Ok(embedder)
+}
@@ -0,0 +1,424 @@
+//! In-memory approximate nearest-neighbour search (HNSW), for nodes and
+//! tests without pgvector. Like the `embeddings` table it keeps one
+//! embedding per id, searches within a namespace by Euclidean distance and
+//! replaces an id's vector and metadata on re-insert while keeping its
+//! namespace. Replaced vectors stay in the graph as unreachable results.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Embedding, EmbeddingId};
+
+/// Version of the file [`HnswIndex::save`] writes.
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HnswParams {
+    /// Links per node on the upper layers; twice as many on the bottom one.
+    pub m: usize,
+    /// Candidates considered when linking a new node.
+    pub ef_construction: usize,
+    /// Candidates considered per search, at least `k`.
+    pub ef_search: usize,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self { m: 16, ef_construction: 100, ef_search: 64 }
+    }
+}
+
+/// A search result and its Euclidean distance from the query.
+#[derive(Debug, Clone)]
+pub struct Neighbor {
+    pub embedding: Embedding,
+    pub distance: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Node {
+    id: String,
+    values: Vec<f32>,
+    /// JSON text, as the on-disk format cannot hold a [`Value`] directly.
+    metadata: String,
+    /// Neighbours on each layer the node is on, bottom first.
+    links: Vec<Vec<u32>>,
+    /// Replaced by a later insert of the same id.
+    replaced: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Graph {
+    nodes: Vec<Node>,
+    entry: Option<u32>,
+    dimensions: usize,
+    /// Nodes marked `replaced`.
+    replaced: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HnswIndex {
+    params: HnswParams,
+    /// Length every vector must have; any, fixed per namespace by its first vector, when `None`.
+    dimensions: Option<usize>,
+    spaces: BTreeMap<String, Graph>,
+    /// State of the generator that draws node layers, saved so a loaded
+    /// index grows as the original would have.
+    rng: u64,
+    /// Where each id's current node is; rebuilt on load.
+    #[serde(skip)]
+    ids: HashMap<String, (String, u32)>,
+}
+
+/// A node index ordered by distance, nearest first in a `BinaryHeap` of `Reverse`.
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// Squared Euclidean distance, which orders like the distance itself.
+fn distance2(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+impl Graph {
+    fn max_links(params: &HnswParams, layer: usize) -> usize {
+        if layer == 0 {
+            params.m * 2
+        } else {
+            params.m
+        }
+    }
+
+    fn top_layer(&self) -> usize {
+        self.entry.map_or(0, |entry| self.nodes[entry as usize].links.len() - 1)
+    }
+
+    /// The `ef` nodes nearest `query` reachable on `layer` from `entry`, nearest first.
+    fn search_layer(&self, query: &[f32], entry: &[Scored], ef: usize, layer: usize) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = entry.iter().map(|s| s.1).collect();
+        let mut candidates: BinaryHeap<Reverse<Scored>> = entry.iter().map(|s| Reverse(*s)).collect();
+        let mut found: BinaryHeap<Scored> = entry.iter().copied().collect();
+        while let Some(Reverse(nearest)) = candidates.pop() {
+            let furthest = found.peek().map_or(f32::INFINITY, |s| s.0);
+            if nearest.0 > furthest && found.len() >= ef {
+                break;
+            }
+            for &next in &self.nodes[nearest.1 as usize].links[layer] {
+                if !visited.insert(next) {
+                    continue;
+                }
+                let scored = Scored(distance2(query, &self.nodes[next as usize].values), next);
+                if found.len() < ef || scored.0 < found.peek().map_or(f32::INFINITY, |s| s.0) {
+                    candidates.push(Reverse(scored));
+                    found.push(scored);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    /// Nearest node to `query` on `layer`, walking down from the top.
+    fn descend(&self, query: &[f32], to_layer: usize) -> Option<Scored> {
+        let entry = self.entry?;
+        let mut nearest = Scored(distance2(query, &self.nodes[entry as usize].values), entry);
+        for layer in (to_layer + 1..=self.top_layer()).rev() {
+            nearest = self.search_layer(query, &[nearest], 1, layer)[0];
+        }
+        Some(nearest)
+    }
+
+    fn insert(&mut self, params: &HnswParams, node: Node, level: usize) -> u32 {
+        let id = self.nodes.len() as u32;
+        let values = node.values.clone();
+        self.nodes.push(Node { links: vec![Vec::new(); level + 1], ..node });
+        let Some(nearest) = self.descend(&values, level) else {
+            self.entry = Some(id);
+            return id;
+        };
+        let top = self.top_layer();
+        let mut entry = vec![nearest];
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&values, &entry, params.ef_construction, layer);
+            let max = Self::max_links(params, layer);
+            self.nodes[id as usize].links[layer] = found.iter().take(max).map(|s| s.1).collect();
+            for s in found.iter().take(max) {
+                self.link(s.1, id, layer, max);
+            }
+            entry = found;
+        }
+        if level > top {
+            self.entry = Some(id);
+        }
+        id
+    }
+
+    /// Links `from` to `to` on `layer`, keeping `from`'s nearest `max` links.
+    fn link(&mut self, from: u32, to: u32, layer: usize, max: usize) {
+        self.nodes[from as usize].links[layer].push(to);
+        if self.nodes[from as usize].links[layer].len() <= max {
+            return;
+        }
+        let origin = &self.nodes[from as usize].values;
+        let mut scored: Vec<Scored> = self.nodes[from as usize].links[layer]
+            .iter()
+            .map(|&n| Scored(distance2(origin, &self.nodes[n as usize].values), n))
+            .collect();
+        scored.sort();
+        scored.truncate(max);
+        self.nodes[from as usize].links[layer] = scored.into_iter().map(|s| s.1).collect();
+    }
+}
+
+impl Default for HnswIndex {
+    fn default() -> Self {
+        Self::new(HnswParams::default())
+    }
+}
+
+impl HnswIndex {
+    pub fn new(params: HnswParams) -> Self {
+        Self { params, dimensions: None, spaces: BTreeMap::new(), rng: 0x5eed, ids: HashMap::new() }
+    }
+
+    /// Accepts only vectors of `dimensions`.
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    pub fn dimensions(&self) -> Option<usize> {
+        self.dimensions
+    }
+
+    /// Embeddings held, not counting replaced vectors.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Layer of a new node: 0 with probability 1 - 1/m, then geometrically up.
+    fn draw_level(&mut self) -> usize {
+        // splitmix64
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        let uniform = ((z >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let scale = 1.0 / (self.params.m.max(2) as f64).ln();
+        (-uniform.ln() * scale) as usize
+    }
+
+    /// Adds `embedding`, or replaces the vector and metadata of the one with
+    /// its id, which keeps its namespace.
+    pub fn insert(&mut self, embedding: &Embedding) -> Result<()> {
+        let namespace = match self.ids.get(&embedding.id.0) {
+            Some((namespace, _)) => namespace.clone(),
+            None => embedding.namespace.clone(),
+        };
+        let expected = self
+            .dimensions
+            .or_else(|| self.spaces.get(&namespace).filter(|g| g.entry.is_some()).map(|g| g.dimensions));
+        if let Some(expected) = expected {
+            if embedding.values.len() != expected {
+                return Err(anyhow!(
+                    "expected a {expected}-dimensional vector for {namespace}, got {}",
+                    embedding.values.len()
+                ));
+            }
+        }
+        let level = self.draw_level();
+        let params = self.params;
+        let graph = self.spaces.entry(namespace.clone()).or_default();
+        graph.dimensions = embedding.values.len();
+        if let Some((_, old)) = self.ids.get(&embedding.id.0) {
+            graph.nodes[*old as usize].replaced = true;
+            graph.replaced += 1;
+        }
+        let node = Node {
+            id: embedding.id.0.clone(),
+            values: embedding.values.clone(),
+            metadata: embedding.metadata.to_string(),
+            links: Vec::new(),
+            replaced: false,
+        };
+        let index = graph.insert(&params, node, level);
+        self.ids.insert(embedding.id.0.clone(), (namespace, index));
+        Ok(())
+    }
+
+    /// The `k` embeddings of `namespace` nearest `query`, nearest first.
+    pub fn search(&self, namespace: &str, query: &[f32], k: usize) -> Result<Vec<Neighbor>> {
+        let Some(graph) = self.spaces.get(namespace) else {
+            return Ok(Vec::new());
+        };
+        if graph.entry.is_some() && query.len() != graph.dimensions {
+            return Err(anyhow!(
+                "query has {} dimensions, {namespace} holds {}-dimensional vectors",
+                query.len(),
+                graph.dimensions
+            ));
+        }
+        let Some(entry) = graph.descend(query, 0) else {
+            return Ok(Vec::new());
+        };
+        // Replaced nodes are skipped, so look a little wider for them.
+        let ef = self.params.ef_search.max(k) + graph.replaced;
+        let mut neighbors = Vec::with_capacity(k);
+        for scored in graph.search_layer(query, &[entry], ef, 0) {
+            let node = &graph.nodes[scored.1 as usize];
+            if node.replaced {
+                continue;
+            }
+            let metadata: Value = serde_json::from_str(&node.metadata)?;
+            neighbors.push(Neighbor {
+                embedding: Embedding {
+                    id: EmbeddingId(node.id.clone()),
+                    namespace: namespace.to_string(),
+                    values: node.values.clone(),
+                    metadata,
+                },
+                distance: scored.0.sqrt(),
+            });
+            if neighbors.len() == k {
+                break;
+            }
+        }
+        Ok(neighbors)
+    }
+
+    /// Writes the index to `path`, replacing it only once fully written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        {
+            let mut file = BufWriter::new(std::fs::File::create(&tmp)?);
+            bincode::serialize_into(&mut file, &FORMAT_VERSION)?;
+            bincode::serialize_into(&mut file, self)?;
+            std::io::Write::flush(&mut file)?;
+        }
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut file = BufReader::new(std::fs::File::open(path)?);
+        let version: u32 = bincode::deserialize_from(&mut file)?;
+        if version != FORMAT_VERSION {
+            return Err(anyhow!(
+                "{} is a version {version} index, expected {FORMAT_VERSION}",
+                path.display()
+            ));
+        }
+        let mut index: Self = bincode::deserialize_from(&mut file)?;
+        for (namespace, graph) in &index.spaces {
+            for (i, node) in graph.nodes.iter().enumerate().filter(|(_, node)| !node.replaced) {
+                index.ids.insert(node.id.clone(), (namespace.clone(), i as u32));
+            }
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Deterministic pseudo-random vectors.
+    fn vectors(count: usize, dims: usize) -> Vec<Vec<f32>> {
+        let mut state = 7u64;
+        (0..count)
+            .map(|_| {
+                (0..dims)
+                    .map(|_| {
+                        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                        (state >> 40) as f32 / (1u64 << 24) as f32
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_nearest_neighbours_and_survives_reload() {
+        let mut index = HnswIndex::default().with_dimensions(8);
+        let points = vectors(500, 8);
+        for (i, values) in points.iter().enumerate() {
+            let emb = Embedding {
+                id: EmbeddingId(format!("p{i}")),
+                namespace: "space".into(),
+                values: values.clone(),
+                metadata: json!({ "i": i }),
+            };
+            index.insert(&emb).unwrap();
+        }
+        index.insert(&Embedding::new("other".into(), vec![0.0; 8], json!({}))).unwrap();
+        assert!(index.insert(&Embedding::new("space".into(), vec![0.0; 3], json!({}))).is_err());
+
+        let queries = vectors(520, 8).split_off(500);
+        let mut hits = 0;
+        for query in &queries {
+            let mut exact: Vec<_> = (0..points.len()).collect();
+            exact.sort_by(|&a, &b| distance2(query, &points[a]).total_cmp(&distance2(query, &points[b])));
+            let found = index.search("space", query, 5).unwrap();
+            assert_eq!(found.len(), 5);
+            assert!(found.windows(2).all(|w| w[0].distance <= w[1].distance));
+            let exact: HashSet<String> = exact[..5].iter().map(|i| format!("p{i}")).collect();
+            hits += found.iter().filter(|n| exact.contains(&n.embedding.id.0)).count();
+        }
+        assert!(hits >= queries.len() * 5 * 9 / 10, "recall {hits}/100");
+
+        // Re-inserting an id moves it and keeps one copy.
+        let moved = Embedding {
+            id: EmbeddingId("p0".into()),
+            namespace: "elsewhere".into(),
+            values: queries[0].clone(),
+            metadata: json!({ "moved": true }),
+        };
+        index.insert(&moved).unwrap();
+        assert_eq!(index.len(), 501);
+        let nearest = &index.search("space", &queries[0], 1).unwrap()[0];
+        assert_eq!((nearest.embedding.id.0.as_str(), nearest.distance), ("p0", 0.0));
+        assert_eq!(nearest.embedding.metadata["moved"], true);
+        assert!(index.search("missing", &queries[0], 1).unwrap().is_empty());
+
+        let dir = std::env::temp_dir().join(format!("dxid-hnsw-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.bin");
+        index.save(&path).unwrap();
+        let loaded = HnswIndex::load(&path).unwrap();
+        assert_eq!(loaded.len(), index.len());
+        for query in &queries {
+            let ids = |index: &HnswIndex| -> Vec<String> {
+                index.search("space", query, 5).unwrap().into_iter().map(|n| n.embedding.id.0).collect()
+            };
+            assert_eq!(ids(&loaded), ids(&index));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -5,6 +5,18 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
 
+mod embedder;
+mod hnsw;
+#[cfg(feature = "onnx")]
+mod onnx;
+mod openai;
+
+pub use embedder::{build_embedder, embed_all, Embedder, RateLimitedEmbedder};
+pub use hnsw::{HnswIndex, HnswParams, Neighbor};
+#[cfg(feature = "onnx")]
+pub use onnx::OnnxEmbedder;
+pub use openai::OpenAiEmbedder;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct EmbeddingId(pub String);
 
@@ -28,17 +40,20 @@ impl Embedding {
     }
 }
 
-pub fn embed_identity_metadata(identity: &IdentityId, attrs: &[(String, String)]) -> Embedding {
-    let mut acc: f32 = 0.0;
-    for (k, v) in attrs {
-        acc += (k.len() + v.len()) as f32;
-    }
-    let values = vec![acc, acc / 2.0, acc / 3.0];
-    Embedding::new(
+/// Embeds an identity's attributes, one `key: value` line each, in the
+/// `identity:<id>` namespace.
+pub async fn embed_identity_metadata(
+    embedder: &dyn Embedder,
+    identity: &IdentityId,
+    attrs: &[(String, String)],
+) -> Result<Embedding> {
+    let text = attrs.iter().map(|(k, v)| format!("{k}: {v}")).collect::<Vec<_>>().join("\n");
+    let values = embed_all(embedder, &[text]).await?.remove(0);
+    Ok(Embedding::new(
         format!("identity:{}", identity),
         values,
-        serde_json::json!({ "attributes": attrs }),
-    )
+        serde_json::json!({ "attributes": attrs, "model": embedder.model() }),
+    ))
 }
 
 pub fn embed_chain_state(height: u64, peers: usize) -> Embedding {
@@ -50,6 +65,16 @@ pub fn embed_chain_state(height: u64, peers: usize) -> Embedding {
     )
 }
 
+/// Scales `vector` to unit length, so that Euclidean distances between
+/// vectors order like their cosine similarities.
+pub fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|value| *value /= norm);
+    }
+    vector
+}
+
 pub fn random_vector(dim: usize) -> Vec<f32> {
     let mut rng = rand::thread_rng();
     (0..dim).map(|_| rng.gen::<f32>()).collect()
@@ -59,10 +84,53 @@ pub fn random_vector(dim: usize) -> Vec<f32> {
 mod tests {
     use super::*;
 
-    #[test]
-    fn make_embedding() {
+    /// Counts of each letter, in batches of two.
+    struct LetterEmbedder;
+
+    #[async_trait::async_trait]
+    impl Embedder for LetterEmbedder {
+        fn model(&self) -> &str {
+            "letters"
+        }
+
+        fn dimensions(&self) -> usize {
+            26
+        }
+
+        fn max_batch(&self) -> usize {
+            2
+        }
+
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            assert!(texts.len() <= 2);
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let mut counts = vec![0.0; 26];
+                    for b in text.bytes().filter(u8::is_ascii_lowercase) {
+                        counts[(b - b'a') as usize] += 1.0;
+                    }
+                    counts
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn make_embedding() {
         let id = IdentityId::new_v4();
-        let emb = embed_identity_metadata(&id, &[("role".into(), "admin".into())]);
-        assert!(emb.values.len() >= 3);
+        let emb =
+            embed_identity_metadata(&LetterEmbedder, &id, &[("role".into(), "admin".into())]).await.unwrap();
+        assert_eq!(emb.values.len(), 26);
+        assert_eq!(emb.values[0], 1.0);
+        assert_eq!(emb.metadata["model"], "letters");
+
+        let texts: Vec<String> = ["a", "bb", "ccc", "dddd", "eeeee"].map(String::from).to_vec();
+        let vectors = embed_all(&LetterEmbedder, &texts).await.unwrap();
+        assert_eq!(
+            vectors.iter().map(|v| v.iter().sum::<f32>()).collect::<Vec<_>>(),
+            [1.0, 2.0, 3.0, 4.0, 5.0]
+        );
+        assert!(LetterEmbedder.set_dimensions(1536).is_err());
     }
 }
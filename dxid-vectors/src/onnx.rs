@@ -0,0 +1,141 @@
+//! Sentence-embedding models exported to ONNX (e.g. all-MiniLM-L6-v2), run
+//! in-process so nodes can embed without network access. The token vectors
+//! are mean-pooled over the attention mask and normalized to unit length.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ort::session::Session;
+use ort::value::Tensor;
+use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
+
+use crate::{normalize, Embedder};
+
+const DEFAULT_MAX_BATCH: usize = 32;
+/// Tokens kept per text; longer texts are truncated.
+const MAX_TOKENS: usize = 512;
+
+pub struct OnnxEmbedder {
+    model: String,
+    session: Arc<Mutex<Session>>,
+    tokenizer: Arc<Tokenizer>,
+    /// Whether the graph takes `token_type_ids` (BERT-style models do).
+    token_types: bool,
+    dimensions: usize,
+    max_batch: usize,
+}
+
+impl OnnxEmbedder {
+    /// Loads the model at `model_path` with its `tokenizer.json`, and runs it
+    /// once to learn its vector length.
+    pub fn load(model_path: impl AsRef<Path>, tokenizer_path: impl AsRef<Path>) -> Result<Self> {
+        let model_path = model_path.as_ref();
+        let session = Session::builder()?.commit_from_file(model_path)?;
+        let token_types = session.inputs.iter().any(|input| input.name == "token_type_ids");
+        let mut tokenizer = Tokenizer::from_file(tokenizer_path).map_err(|err| anyhow!("{err}"))?;
+        tokenizer.with_padding(Some(PaddingParams::default()));
+        tokenizer
+            .with_truncation(Some(TruncationParams { max_length: MAX_TOKENS, ..Default::default() }))
+            .map_err(|err| anyhow!("{err}"))?;
+        let mut embedder = Self {
+            model: model_path.display().to_string(),
+            session: Arc::new(Mutex::new(session)),
+            tokenizer: Arc::new(tokenizer),
+            token_types,
+            dimensions: 0,
+            max_batch: DEFAULT_MAX_BATCH,
+        };
+        let probe = embedder.run(&["dxid".to_string()])?;
+        embedder.dimensions = probe[0].len();
+        Ok(embedder)
+    }
+
+    pub fn with_max_batch(mut self, max_batch: usize) -> Self {
+        self.max_batch = max_batch.max(1);
+        self
+    }
+
+    fn run(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        run_model(&self.session, &self.tokenizer, self.token_types, texts)
+    }
+}
+
+fn run_model(
+    session: &Mutex<Session>,
+    tokenizer: &Tokenizer,
+    token_types: bool,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>> {
+    let encodings = tokenizer.encode_batch(texts.to_vec(), true).map_err(|err| anyhow!("{err}"))?;
+    let batch = encodings.len();
+    let tokens = encodings.first().map_or(0, |encoding| encoding.len());
+    let flat = |field: fn(&tokenizers::Encoding) -> &[u32]| -> Vec<i64> {
+        encodings.iter().flat_map(|encoding| field(encoding).iter().map(|&id| i64::from(id))).collect()
+    };
+    let mask = flat(tokenizers::Encoding::get_attention_mask);
+    let mut inputs = ort::inputs![
+        "input_ids" => Tensor::from_array(([batch, tokens], flat(tokenizers::Encoding::get_ids)))?,
+        "attention_mask" => Tensor::from_array(([batch, tokens], mask.clone()))?,
+    ];
+    if token_types {
+        let types = Tensor::from_array(([batch, tokens], flat(tokenizers::Encoding::get_type_ids)))?;
+        inputs.push(("token_type_ids".into(), types.into()));
+    }
+    let mut session = session.lock().map_err(|_| anyhow!("onnx session lock poisoned"))?;
+    let outputs = session.run(inputs)?;
+    let (shape, data) = outputs[0].try_extract_tensor::<f32>()?;
+    let vectors = match **shape {
+        // Token vectors: mean-pool those the attention mask keeps.
+        [b, t, dims] if b as usize == batch && t as usize == tokens => {
+            let dims = dims as usize;
+            (0..batch)
+                .map(|i| {
+                    let mut pooled = vec![0f32; dims];
+                    let mut kept = 0f32;
+                    for j in 0..tokens {
+                        if mask[i * tokens + j] == 0 {
+                            continue;
+                        }
+                        let token = &data[(i * tokens + j) * dims..][..dims];
+                        pooled.iter_mut().zip(token).for_each(|(sum, value)| *sum += value);
+                        kept += 1.0;
+                    }
+                    pooled.iter_mut().for_each(|value| *value /= kept.max(1.0));
+                    pooled
+                })
+                .collect::<Vec<_>>()
+        }
+        // Already pooled by the graph.
+        [b, dims] if b as usize == batch => data.chunks(dims as usize).map(<[f32]>::to_vec).collect(),
+        _ => return Err(anyhow!("unexpected model output shape {:?}", &**shape)),
+    };
+    Ok(vectors.into_iter().map(normalize).collect())
+}
+
+#[async_trait]
+impl Embedder for OnnxEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn max_batch(&self) -> usize {
+        self.max_batch
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let session = self.session.clone();
+        let tokenizer = self.tokenizer.clone();
+        let token_types = self.token_types;
+        let texts = texts.to_vec();
+        tokio::task::spawn_blocking(move || run_model(&session, &tokenizer, token_types, &texts)).await?
+    }
+}
@@ -0,0 +1,181 @@
+//! OpenAI's embeddings API, and servers that mimic its `/embeddings` route.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::Embedder;
+
+const OPENAI_API_URL: &str = "https://api.openai.com/v1";
+/// Most inputs OpenAI accepts in one request.
+const OPENAI_MAX_BATCH: usize = 2048;
+/// Batch size for compatible servers, which often run on small hardware.
+const COMPATIBLE_MAX_BATCH: usize = 32;
+
+/// Native vector lengths of OpenAI's embedding models.
+const OPENAI_MODELS: [(&str, usize); 3] =
+    [("text-embedding-3-small", 1536), ("text-embedding-3-large", 3072), ("text-embedding-ada-002", 1536)];
+
+pub struct OpenAiEmbedder {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+    model: String,
+    dimensions: usize,
+    /// The text-embedding-3 models can return shortened vectors when asked.
+    shortenable: bool,
+    /// Sent as `dimensions` once the length differs from the model's own.
+    requested: Option<usize>,
+    max_batch: usize,
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    encoding_format: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl OpenAiEmbedder {
+    /// OpenAI's API with one of its embedding models.
+    pub fn openai(api_key: &str, model: &str) -> Result<Self> {
+        let (_, dimensions) = OPENAI_MODELS
+            .iter()
+            .find(|(name, _)| *name == model)
+            .ok_or_else(|| anyhow!("{model} is not an OpenAI embedding model"))?;
+        Ok(Self {
+            client: Client::new(),
+            url: format!("{OPENAI_API_URL}/embeddings"),
+            api_key: Some(api_key.to_string()),
+            model: model.to_string(),
+            dimensions: *dimensions,
+            shortenable: model.starts_with("text-embedding-3"),
+            requested: None,
+            max_batch: OPENAI_MAX_BATCH,
+        })
+    }
+
+    /// A local server with OpenAI's `/embeddings` route, such as Ollama or
+    /// vLLM. `base_url` is the part before the route, e.g.
+    /// `http://127.0.0.1:11434/v1`; `dimensions` is what `model` returns.
+    pub fn compatible(base_url: &str, model: &str, dimensions: usize) -> Self {
+        Self {
+            client: Client::new(),
+            url: format!("{}/embeddings", base_url.trim_end_matches('/')),
+            api_key: None,
+            model: model.to_string(),
+            dimensions,
+            shortenable: false,
+            requested: None,
+            max_batch: COMPATIBLE_MAX_BATCH,
+        }
+    }
+
+    /// Bearer token for compatible servers that require one.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn with_max_batch(mut self, max_batch: usize) -> Self {
+        self.max_batch = max_batch.max(1);
+        self
+    }
+
+    fn request<'a>(&'a self, texts: &'a [String]) -> EmbeddingRequest<'a> {
+        EmbeddingRequest {
+            model: &self.model,
+            input: texts,
+            encoding_format: "float",
+            dimensions: self.requested,
+        }
+    }
+}
+
+#[async_trait]
+impl Embedder for OpenAiEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn max_batch(&self) -> usize {
+        self.max_batch
+    }
+
+    fn set_dimensions(&mut self, dimensions: usize) -> Result<()> {
+        if dimensions == self.dimensions {
+            return Ok(());
+        }
+        let native = OPENAI_MODELS.iter().find(|(name, _)| *name == self.model).map(|(_, dims)| *dims);
+        match native {
+            Some(native) if self.shortenable && dimensions > 0 && dimensions <= native => {
+                self.dimensions = dimensions;
+                self.requested = (dimensions != native).then_some(dimensions);
+                Ok(())
+            }
+            _ => Err(anyhow!("{} cannot produce {dimensions}-dimensional vectors", self.model)),
+        }
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut request = self.client.post(&self.url).json(&self.request(texts));
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let resp = request.send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("embedding provider returned {status}: {body}"));
+        }
+        let mut data = resp.json::<EmbeddingResponse>().await?.data;
+        data.sort_by_key(|item| item.index);
+        Ok(data.into_iter().map(|item| item.embedding).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortens_only_text_embedding_3_models() {
+        let mut large = OpenAiEmbedder::openai("key", "text-embedding-3-large").unwrap();
+        assert_eq!(large.dimensions(), 3072);
+        large.set_dimensions(1536).unwrap();
+        assert_eq!(large.dimensions(), 1536);
+        let texts = ["hello".to_string()];
+        let body = serde_json::to_value(large.request(&texts)).unwrap();
+        assert_eq!(body["dimensions"], 1536);
+        large.set_dimensions(3072).unwrap();
+        assert!(serde_json::to_value(large.request(&texts)).unwrap().get("dimensions").is_none());
+
+        let mut ada = OpenAiEmbedder::openai("key", "text-embedding-ada-002").unwrap();
+        assert!(ada.set_dimensions(512).is_err());
+        let mut local = OpenAiEmbedder::compatible("http://127.0.0.1:11434/v1/", "nomic-embed-text", 768);
+        assert_eq!(local.url, "http://127.0.0.1:11434/v1/embeddings");
+        assert!(local.set_dimensions(768).is_ok() && local.set_dimensions(1536).is_err());
+        assert!(OpenAiEmbedder::openai("key", "gpt-4o-mini").is_err());
+    }
+}
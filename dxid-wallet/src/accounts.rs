@@ -0,0 +1,126 @@
+//! Named accounts: separate derivation branches of one HD seed, each with
+//! its own addresses and balance.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use dxid_core::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::{derive_hd_address, HdState, Wallet, WalletStore};
+
+/// Name of account 0, which every HD wallet starts with.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HdAccount {
+    pub name: String,
+    /// Hardened account segment of the derivation path.
+    pub index: u32,
+}
+
+impl HdState {
+    /// The wallet's accounts, including the implicit default one of older files.
+    pub fn account_list(&self) -> Vec<HdAccount> {
+        if self.accounts.is_empty() {
+            vec![HdAccount { name: DEFAULT_ACCOUNT.to_string(), index: 0 }]
+        } else {
+            self.accounts.clone()
+        }
+    }
+
+    pub fn account_index(&self, name: &str) -> Option<u32> {
+        self.account_list().into_iter().find(|a| a.name == name).map(|a| a.index)
+    }
+}
+
+impl Wallet {
+    /// Addresses derived under the account called `name`.
+    pub fn account_addresses(&self, name: &str) -> Result<HashSet<Address>> {
+        let hd = self.hd_state()?;
+        let index =
+            hd.account_index(name).ok_or_else(|| anyhow!("wallet {} has no account {name}", self.name))?;
+        Ok(hd.account_addresses(index).map(|a| a.address).collect())
+    }
+
+    fn hd_state(&self) -> Result<&HdState> {
+        self.hd.as_ref().ok_or_else(|| anyhow!("wallet {} is not an HD wallet", self.name))
+    }
+
+    fn hd_state_mut(&mut self) -> Result<&mut HdState> {
+        self.hd.as_mut().ok_or_else(|| anyhow!("wallet {} is not an HD wallet", self.name))
+    }
+}
+
+impl WalletStore {
+    /// Adds an account on the next unused branch, derives its first receive
+    /// address and returns the account index. The active account is unchanged.
+    pub fn add_account(&self, wallet: &mut Wallet, password: &str, name: &str) -> Result<u32> {
+        let hd = wallet.hd_state()?;
+        if hd.account_index(name).is_some() {
+            return Err(anyhow!("wallet {} already has an account {name}", wallet.name));
+        }
+        let mut accounts = hd.account_list();
+        let index = accounts.iter().map(|a| a.index + 1).max().unwrap_or(0);
+        self.upgrade_kdf(wallet, password)?;
+        let seed = self.unlock_seed(wallet, password)?;
+        let (address, _) = derive_hd_address(&seed, index, false, 0)?;
+        accounts.push(HdAccount { name: name.to_string(), index });
+        let hd = wallet.hd_state_mut()?;
+        hd.accounts = accounts;
+        hd.record(address);
+        self.save(wallet)?;
+        Ok(index)
+    }
+
+    /// Makes `name` the account new addresses and payments come from.
+    pub fn use_account(&self, wallet: &mut Wallet, name: &str) -> Result<()> {
+        let index = wallet
+            .hd_state()?
+            .account_index(name)
+            .ok_or_else(|| anyhow!("wallet {} has no account {name}", wallet.name))?;
+        wallet.hd_state_mut()?.account = index;
+        self.save(wallet)
+    }
+
+    /// Sum of the unspent outputs the node reports for one account.
+    pub async fn account_balance(&self, wallet: &Wallet, name: &str) -> Result<u64> {
+        let node = self.node()?;
+        let mut total = 0u64;
+        for address in wallet.account_addresses(name)? {
+            for utxo in node.utxos(&address).await? {
+                total = total.saturating_add(utxo.amount);
+            }
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounts_derive_separate_branches() {
+        let store = crate::tests::test_store();
+        let mut wallet = store.create_hd("accounts-owner", "pass").unwrap().0;
+        let savings = store.add_account(&mut wallet, "pass", "savings").unwrap();
+        assert_eq!(savings, 1);
+        assert!(store.add_account(&mut wallet, "pass", "savings").is_err());
+
+        let default = wallet.account_addresses(DEFAULT_ACCOUNT).unwrap();
+        let saved = wallet.account_addresses("savings").unwrap();
+        assert!(default.is_disjoint(&saved));
+        assert!(wallet.spend_addresses().iter().all(|a| default.contains(a)));
+
+        store.use_account(&mut wallet, "savings").unwrap();
+        let fresh = store.next_address(&mut wallet, "pass", false).unwrap();
+        assert_eq!((fresh.account, fresh.index), (1, 1));
+        let reloaded = store.load("accounts-owner").unwrap();
+        assert_eq!(reloaded.spend_addresses().len(), 2);
+        assert!(reloaded.spend_addresses().iter().all(|a| !default.contains(a)));
+
+        let keys = store.unlock_keys(&reloaded, "pass").unwrap();
+        assert!(reloaded.addresses().iter().all(|a| keys.contains_key(a)));
+    }
+}
@@ -0,0 +1,185 @@
+//! Per-wallet address book and `dxid:` payment requests.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use dxid_core::{Address, IdentityId};
+use dxid_crypto::{address_from_string, address_to_string};
+use serde::{Deserialize, Serialize};
+
+use crate::{SentTx, Wallet, WalletStore};
+
+const URI_SCHEME: &str = "dxid:";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Contact {
+    pub label: String,
+    pub address: Address,
+    /// dxid identity the address belongs to, when known.
+    #[serde(default)]
+    pub identity: Option<IdentityId>,
+}
+
+impl Wallet {
+    pub fn contact(&self, label: &str) -> Option<&Contact> {
+        self.contacts.iter().find(|c| c.label == label)
+    }
+
+    /// A contact label or an address string.
+    pub fn resolve(&self, to: &str) -> Result<Address> {
+        match self.contact(to) {
+            Some(contact) => Ok(contact.address),
+            None => address_from_string(to).map_err(|_| anyhow!("{to} is neither a contact nor an address")),
+        }
+    }
+}
+
+/// A request for payment, shared as a `dxid:<address>?amount=..&memo=..&label=..`
+/// URI. The URI string is also the QR payload.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaymentRequest {
+    pub address: Address,
+    pub amount: Option<u64>,
+    pub memo: Option<String>,
+    /// Who is asking, suggested as the contact label.
+    pub label: Option<String>,
+}
+
+impl PaymentRequest {
+    pub fn new(address: Address) -> Self {
+        Self { address, ..Self::default() }
+    }
+
+    pub fn to_uri(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{URI_SCHEME}{}", address_to_string(&self.address))?;
+        let mut params = Vec::new();
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={amount}"));
+        }
+        if let Some(memo) = &self.memo {
+            params.push(format!("memo={}", percent_encode(memo)));
+        }
+        if let Some(label) = &self.label {
+            params.push(format!("label={}", percent_encode(label)));
+        }
+        if !params.is_empty() {
+            write!(f, "?{}", params.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentRequest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rest = s.trim().strip_prefix(URI_SCHEME).ok_or_else(|| anyhow!("not a {URI_SCHEME} URI"))?;
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut request = Self::new(address_from_string(address)?);
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "amount" => request.amount = Some(value.parse().map_err(|_| anyhow!("bad amount {value}"))?),
+                "memo" => request.memo = Some(percent_decode(value)?),
+                "label" => request.label = Some(percent_decode(value)?),
+                // Unknown parameters are for newer wallets; ignore them.
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+fn percent_decode(s: &str) -> Result<String> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next().unwrap_or(0), bytes.next().unwrap_or(0)];
+            let hex = std::str::from_utf8(&hex).map_err(|_| anyhow!("bad escape in {s}"))?;
+            out.push(u8::from_str_radix(hex, 16).map_err(|_| anyhow!("bad escape in {s}"))?);
+        } else {
+            out.push(byte);
+        }
+    }
+    String::from_utf8(out).map_err(|_| anyhow!("{s} is not UTF-8"))
+}
+
+impl WalletStore {
+    /// Adds or replaces the contact with `contact.label`.
+    pub fn add_contact(&self, wallet: &mut Wallet, contact: Contact) -> Result<()> {
+        wallet.contacts.retain(|c| c.label != contact.label);
+        wallet.contacts.push(contact);
+        self.save(wallet)
+    }
+
+    pub fn remove_contact(&self, wallet: &mut Wallet, label: &str) -> Result<bool> {
+        let before = wallet.contacts.len();
+        wallet.contacts.retain(|c| c.label != label);
+        self.save(wallet)?;
+        Ok(wallet.contacts.len() < before)
+    }
+
+    /// Pays `request`, carrying its memo on the transaction.
+    pub async fn pay_request(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        request: &PaymentRequest,
+        fee_rate: u64,
+    ) -> Result<SentTx> {
+        let amount = request.amount.ok_or_else(|| anyhow!("payment request has no amount"))?;
+        self.send_payment(wallet, password, &[(request.address, amount)], fee_rate, request.memo.clone())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payment_request_uri_roundtrip() {
+        let request = PaymentRequest {
+            address: [7u8; 32],
+            amount: Some(12_500),
+            memo: Some("invoice #42 & co".into()),
+            label: Some("Café Layer0".into()),
+        };
+        let uri = request.to_uri();
+        assert!(!uri.contains(' '));
+        assert_eq!(uri.parse::<PaymentRequest>().unwrap(), request);
+
+        let bare: PaymentRequest = format!("dxid:{}", address_to_string(&[7u8; 32])).parse().unwrap();
+        assert_eq!(bare, PaymentRequest::new([7u8; 32]));
+        assert!("bitcoin:abc".parse::<PaymentRequest>().is_err());
+
+        let store = crate::tests::test_store();
+        let mut wallet = store.create("book-owner", "pass").unwrap();
+        store
+            .add_contact(&mut wallet, Contact { label: "shop".into(), address: [7u8; 32], identity: None })
+            .unwrap();
+        let reloaded = store.load("book-owner").unwrap();
+        assert_eq!(reloaded.resolve("shop").unwrap(), [7u8; 32]);
+        assert!(reloaded.resolve("nobody").is_err());
+    }
+}
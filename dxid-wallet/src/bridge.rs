@@ -0,0 +1,171 @@
+//! Bridge transfers out of a wallet: lock native tokens in the escrow and
+//! follow the relayer until the mint lands on the destination chain.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use dxid_core::bridge::{bridge_escrow_address, lock_id, BridgeTransfer, BRIDGE_MEMO_PREFIX};
+use dxid_core::CrossChainMessage;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{SentTx, Wallet, WalletStore};
+
+/// A submitted lock and the mint message the node relays for it.
+#[derive(Debug, Clone)]
+pub struct BridgeSend {
+    pub sent: SentTx,
+    pub transfer: BridgeTransfer,
+    /// The message the relayer proves and delivers. Its id is the lock id;
+    /// the nonce is assigned by the relayer.
+    pub message: CrossChainMessage,
+}
+
+/// Relay status of a bridge message, as stored by the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum BridgeTransferState {
+    Pending,
+    Submitted,
+    Confirmed,
+    Failed,
+}
+
+impl BridgeTransferState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BridgeTransferState::Pending => "pending",
+            BridgeTransferState::Submitted => "submitted",
+            BridgeTransferState::Confirmed => "confirmed",
+            BridgeTransferState::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for BridgeTransferState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(BridgeTransferState::Pending),
+            "submitted" => Ok(BridgeTransferState::Submitted),
+            "confirmed" => Ok(BridgeTransferState::Confirmed),
+            "failed" => Ok(BridgeTransferState::Failed),
+            other => Err(anyhow!("unknown status {other}; expected pending, submitted, confirmed or failed")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BridgeTransferStatus {
+    pub state: BridgeTransferState,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// Destination receipt once the mint was accepted.
+    pub receipt: Option<serde_json::Value>,
+    /// SNARK proof attached to the message, when the node kept it.
+    pub proof: Option<Vec<u8>>,
+}
+
+impl WalletStore {
+    /// Locks `amount` in the bridge escrow for `recipient` on chain `dest`.
+    /// The node queues the mint once the lock is in a block.
+    pub async fn bridge_send(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        dest: &str,
+        recipient: &str,
+        amount: u64,
+        fee_rate: u64,
+    ) -> Result<BridgeSend> {
+        let memo = bridge_memo(dest, recipient)?;
+        let escrow = bridge_escrow_address();
+        let sent = self.send_payment(wallet, password, &[(escrow, amount)], fee_rate, Some(memo)).await?;
+        let transfer = lock_transfer(&sent, dest, recipient)?;
+        let message = transfer.mint_message();
+        Ok(BridgeSend { sent, transfer, message })
+    }
+
+    /// Relay status of transfer `id`; `None` until the node has queued it.
+    pub async fn bridge_status(&self, id: &Uuid) -> Result<Option<BridgeTransferStatus>> {
+        self.node()?.bridge_status(id).await
+    }
+
+    /// Polls transfer `id` every `interval` until it is delivered, failing
+    /// if the relayer gives up on it.
+    pub async fn await_bridge_delivery(&self, id: &Uuid, interval: Duration) -> Result<BridgeTransferStatus> {
+        loop {
+            match self.bridge_status(id).await? {
+                Some(status) if status.state == BridgeTransferState::Confirmed => return Ok(status),
+                Some(status) if status.state == BridgeTransferState::Failed => {
+                    let reason = status.last_error.unwrap_or_else(|| "no error recorded".into());
+                    return Err(anyhow!("bridge transfer {id} failed: {reason}"));
+                }
+                _ => tokio::time::sleep(interval).await,
+            }
+        }
+    }
+}
+
+fn bridge_memo(dest: &str, recipient: &str) -> Result<String> {
+    if dest.is_empty() || dest.contains(':') || recipient.is_empty() {
+        return Err(anyhow!("bad bridge destination {dest}:{recipient}"));
+    }
+    Ok(format!("{BRIDGE_MEMO_PREFIX}{dest}:{recipient}"))
+}
+
+/// The transfer the node records for the escrow output of `sent`.
+fn lock_transfer(sent: &SentTx, dest: &str, recipient: &str) -> Result<BridgeTransfer> {
+    let escrow = bridge_escrow_address();
+    let (index, output) = sent
+        .built
+        .tx
+        .outputs
+        .iter()
+        .enumerate()
+        .find(|(_, o)| o.address == escrow)
+        .ok_or_else(|| anyhow!("lock transaction pays nothing into the escrow"))?;
+    // The node charges the lock to the owner of the first input.
+    let sender = sent.built.spent.first().ok_or_else(|| anyhow!("lock transaction spends nothing"))?.address;
+    Ok(BridgeTransfer {
+        id: lock_id(&sent.hash, index as u32),
+        sender,
+        dest: dest.to_string(),
+        recipient: recipient.to_string(),
+        amount: output.amount,
+        lock_tx: sent.hash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dxid_core::bridge::parse_bridge_memo;
+    use dxid_core::builder::Utxo;
+
+    #[test]
+    fn lock_transaction_matches_the_relayed_message() {
+        let store = crate::tests::test_store();
+        let mut wallet = store.create("bridger", "pass").unwrap();
+        let utxo = Utxo { tx_hash: [5u8; 32], index: 0, address: wallet.address, amount: 50_000 };
+        let memo = bridge_memo("eth", "0xabc").unwrap();
+        assert!(bridge_memo("eth:x", "0xabc").is_err());
+
+        let built = store
+            .sign_payment(&mut wallet, "pass", vec![utxo], &[(bridge_escrow_address(), 1_000)], 1, Some(memo))
+            .unwrap();
+        assert_eq!(
+            parse_bridge_memo(built.tx.memo.as_deref().unwrap()),
+            Some(("eth".into(), "0xabc".into()))
+        );
+        let sent = SentTx { hash: [6u8; 32], built };
+        let transfer = lock_transfer(&sent, "eth", "0xabc").unwrap();
+        assert_eq!(transfer.sender, wallet.address);
+        let index = sent.built.tx.outputs.iter().position(|o| o.address == bridge_escrow_address()).unwrap();
+        assert_eq!(transfer.id, lock_id(&[6u8; 32], index as u32));
+        assert_eq!(transfer.amount, 1_000);
+        let message = transfer.mint_message();
+        assert_eq!((message.id, message.dest.as_str()), (transfer.id, "eth"));
+        assert_eq!(message.payload["recipient"], "0xabc");
+    }
+}
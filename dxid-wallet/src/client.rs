@@ -0,0 +1,609 @@
+use anyhow::{anyhow, Context, Result};
+use dxid_core::builder::Utxo;
+use dxid_core::contract::{ContractCall, TxReceipt};
+use dxid_core::events::NodeEvent;
+use dxid_core::staking::{StakingPosition, ValidatorStatus};
+use dxid_core::{Address, Block, CrossChainTx, Identity, IdentityId, PossibleDuplicate, Transaction, TxHash};
+use dxid_crypto::address_to_string;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+use crate::bridge::{BridgeTransferState, BridgeTransferStatus};
+use crate::identity::{IdentityKeyRotation, IdentityUpdate};
+
+/// Minimal client for the node's REST API.
+#[derive(Debug, Clone)]
+pub struct NodeClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+#[derive(Deserialize)]
+struct UtxosResponse {
+    utxos: Vec<Utxo>,
+}
+
+#[derive(Deserialize)]
+struct SubmitResponse {
+    hash: String,
+}
+
+#[derive(Deserialize)]
+struct FeeEstimateResponse {
+    fee_rate: u64,
+}
+
+#[derive(Deserialize)]
+struct ReceiptResponse {
+    receipt: TxReceipt,
+}
+
+/// What the node expects a contract call to cost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct GasEstimate {
+    pub gas_used: u64,
+    pub gas_price: u64,
+}
+
+impl GasEstimate {
+    /// Fee that buys the estimated gas.
+    pub fn fee(&self) -> u64 {
+        self.gas_used.saturating_mul(self.gas_price)
+    }
+}
+
+#[derive(Deserialize)]
+struct ContractResponse {
+    abi: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct StakingResponse {
+    position: StakingPosition,
+}
+
+#[derive(Deserialize)]
+struct ValidatorResponse {
+    validator: ValidatorStatus,
+}
+
+#[derive(Deserialize)]
+struct BridgeMessageResponse {
+    message: BridgeMessage,
+}
+
+#[derive(Deserialize)]
+struct AiSessionResponse {
+    session: AiSession,
+}
+
+#[derive(Deserialize)]
+struct BridgeMessagesResponse {
+    messages: Vec<BridgeMessage>,
+}
+
+#[derive(Deserialize)]
+struct BridgeAuditResponse {
+    events: Vec<BridgeAuditEvent>,
+}
+
+#[derive(Deserialize)]
+struct BridgeChainsResponse {
+    chains: Vec<BridgeChain>,
+}
+
+#[derive(Deserialize)]
+struct BridgeMetricsResponse {
+    chains: Vec<BridgeChainMetrics>,
+}
+
+/// A message in the node's bridge relay queue.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BridgeMessage {
+    pub tx: CrossChainTx,
+    pub status: BridgeTransferState,
+    pub attempts: u32,
+    /// Unix seconds before which the relayer will not retry.
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    pub receipt: Option<serde_json::Value>,
+    #[serde(default)]
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// An entry of a bridge message's audit log.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BridgeAuditEvent {
+    /// `ProofGenerated`, `MessageSent`, `ReceiptReceived`, `Confirmed` or `Failure`.
+    pub kind: String,
+    pub chain: String,
+    /// Unix milliseconds.
+    pub at_ms: u64,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub detail: serde_json::Value,
+}
+
+/// An external chain the node relays to.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BridgeChain {
+    pub chain_id: String,
+    pub kind: String,
+    pub endpoint: String,
+}
+
+/// Relay counters of one chain since the node started.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BridgeChainMetrics {
+    pub chain: String,
+    pub in_flight: u64,
+    pub attempts: u64,
+    pub sent: u64,
+    pub confirmed: u64,
+    pub failed: u64,
+    pub avg_confirmation_secs: f64,
+    pub failure_rate: f64,
+}
+
+/// The node's `/status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct NodeStatus {
+    pub height: u64,
+    pub peers: usize,
+    /// Highest height the node has seen on the network.
+    pub network_height: u64,
+    /// Transactions waiting for inclusion.
+    pub mempool: u64,
+}
+
+impl NodeStatus {
+    /// Fraction of the network's chain the node has, from 0 to 1.
+    pub fn sync_progress(&self) -> f64 {
+        if self.network_height == 0 {
+            return 1.0;
+        }
+        (self.height as f64 / self.network_height as f64).min(1.0)
+    }
+}
+
+/// A peer from the node's `/peers`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    pub address: String,
+    /// Highest block height the peer has gossiped to the node.
+    pub best_height: u64,
+}
+
+#[derive(Deserialize)]
+struct PeersResponse {
+    peers: Vec<PeerInfo>,
+}
+
+#[derive(Deserialize)]
+struct BlockResponse {
+    block: Option<Block>,
+}
+
+/// One page of the node's `/blocks` listing.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockPage {
+    /// Newest first.
+    pub blocks: Vec<Block>,
+    /// Pass as `before` to fetch the next, older page; `None` on the last page.
+    pub next: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct IdentityResponse {
+    identity: Identity,
+}
+
+#[derive(Deserialize)]
+struct IdentitiesResponse {
+    identities: Vec<Identity>,
+}
+
+#[derive(Deserialize)]
+struct DuplicatesResponse {
+    duplicates: Vec<PossibleDuplicate>,
+}
+
+#[derive(Deserialize)]
+struct BlockFrame {
+    block: Block,
+}
+
+/// Stream of blocks from the node's `/ws/blocks` websocket, in height order.
+pub struct BlockSubscription {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl BlockSubscription {
+    /// The next block, or `None` once the node closes the stream.
+    pub async fn next_block(&mut self) -> Result<Option<Block>> {
+        while let Some(message) = self.socket.next().await {
+            match message? {
+                Message::Text(text) => return Ok(Some(serde_json::from_str::<BlockFrame>(&text)?.block)),
+                Message::Close(_) => return Ok(None),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Stream of the node's events from its `/ws/events` websocket.
+pub struct EventSubscription {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl EventSubscription {
+    /// The next event, or `None` once the node closes the stream.
+    pub async fn next_event(&mut self) -> Result<Option<NodeEvent>> {
+        while let Some(message) = self.socket.next().await {
+            match message? {
+                Message::Text(text) => return Ok(Some(serde_json::from_str(&text)?)),
+                Message::Close(_) => return Ok(None),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A piece of chain context an AI answer cites as `[id]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AiSource {
+    /// `S1`, `S2`, ...
+    pub id: String,
+    /// `status`, `block`, `activity`, `address` or `identity`.
+    pub kind: String,
+    pub reference: String,
+    pub text: String,
+}
+
+/// A metric computed for a question with an analytic intent, returned
+/// next to the answer.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AiMetric {
+    /// `chain_height`, `block_time`, `throughput`, `average_fee` or `mempool_size`.
+    pub metric: String,
+    pub value: f64,
+    pub unit: String,
+    /// Heights of the blocks the value was computed from.
+    pub evidence: Vec<u64>,
+}
+
+/// A message in an AI conversation.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AiSessionMessage {
+    /// `user` or `assistant`.
+    pub role: String,
+    pub content: String,
+    pub at: u64,
+}
+
+/// An AI conversation as the node keeps it, resumed by its id.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AiSession {
+    pub id: Uuid,
+    /// Every message, oldest first.
+    pub messages: Vec<AiSessionMessage>,
+    /// What the model is told of messages that no longer fit its window.
+    pub summary: Option<String>,
+}
+
+/// An AI answer streamed from the node's `/ws/ai`. Dropping it cancels the query.
+pub struct AiAnswerStream {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    sources: Vec<AiSource>,
+    analytics: Vec<AiMetric>,
+}
+
+#[derive(Deserialize)]
+struct AiFrame {
+    token: Option<String>,
+    error: Option<String>,
+    #[serde(default)]
+    sources: Vec<AiSource>,
+    #[serde(default)]
+    analytics: Vec<AiMetric>,
+}
+
+impl AiAnswerStream {
+    /// The next token, or `None` once the answer is complete.
+    pub async fn next_token(&mut self) -> Result<Option<String>> {
+        while let Some(message) = self.socket.next().await {
+            let text = match message? {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let frame: AiFrame = serde_json::from_str(&text)?;
+            if let Some(error) = frame.error {
+                return Err(anyhow!("AI query failed: {error}"));
+            }
+            if frame.token.is_none() {
+                self.sources = frame.sources;
+                self.analytics = frame.analytics;
+            }
+            return Ok(frame.token);
+        }
+        Ok(None)
+    }
+
+    /// The sources the answer cites, known once it is complete.
+    pub fn sources(&self) -> &[AiSource] {
+        &self.sources
+    }
+
+    /// Metrics for the question's analytic intents, known once it is complete.
+    pub fn analytics(&self) -> &[AiMetric] {
+        &self.analytics
+    }
+}
+
+impl NodeClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self { http: reqwest::Client::new(), base_url: base_url.into().trim_end_matches('/').to_string() }
+    }
+
+    pub async fn status(&self) -> Result<NodeStatus> {
+        let url = format!("{}/status", self.base_url);
+        Ok(self.http.get(&url).send().await?.error_for_status()?.json().await?)
+    }
+
+    /// Peers the node is connected to.
+    pub async fn peers(&self) -> Result<Vec<PeerInfo>> {
+        let url = format!("{}/peers", self.base_url);
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<PeersResponse>().await?.peers)
+    }
+
+    /// The block at `height`, or `None` if the node has not stored it.
+    pub async fn block(&self, height: u64) -> Result<Option<Block>> {
+        let url = format!("{}/blocks/{height}", self.base_url);
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<BlockResponse>().await?.block)
+    }
+
+    /// Up to `limit` blocks below height `before`, or from the tip if `None`.
+    pub async fn blocks(&self, before: Option<u64>, limit: u64) -> Result<BlockPage> {
+        let mut url = format!("{}/blocks?limit={limit}", self.base_url);
+        if let Some(before) = before {
+            url.push_str(&format!("&before={before}"));
+        }
+        Ok(self.http.get(&url).send().await?.error_for_status()?.json().await?)
+    }
+
+    pub async fn utxos(&self, address: &Address) -> Result<Vec<Utxo>> {
+        let url = format!("{}/utxos/{}", self.base_url, address_to_string(address));
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<UtxosResponse>().await?.utxos)
+    }
+
+    /// Streams blocks starting at height `from`, then each new one as the node stores it.
+    pub async fn subscribe_blocks(&self, from: u64) -> Result<BlockSubscription> {
+        let url = format!("{}/ws/blocks?from={from}", self.base_url.replacen("http", "ws", 1));
+        let (socket, _) =
+            connect_async(url.as_str()).await.with_context(|| format!("connecting to {url}"))?;
+        Ok(BlockSubscription { socket })
+    }
+
+    /// Subscribes to the node's events: new blocks, reorgs, transactions
+    /// entering its mempool and peer changes.
+    pub async fn subscribe_events(&self) -> Result<EventSubscription> {
+        let url = format!("{}/ws/events", self.base_url.replacen("http", "ws", 1));
+        let (socket, _) =
+            connect_async(url.as_str()).await.with_context(|| format!("connecting to {url}"))?;
+        Ok(EventSubscription { socket })
+    }
+
+    /// Asks the node's AI hypervisor `prompt`, streaming the answer. With a
+    /// `session` the question continues that conversation and is recorded in it.
+    pub async fn ai_stream(&self, prompt: &str, session: Option<Uuid>) -> Result<AiAnswerStream> {
+        let url = format!("{}/ws/ai", self.base_url.replacen("http", "ws", 1));
+        let (mut socket, _) =
+            connect_async(url.as_str()).await.with_context(|| format!("connecting to {url}"))?;
+        let request = serde_json::json!({ "prompt": prompt, "session": session }).to_string();
+        socket.send(Message::Text(request)).await?;
+        Ok(AiAnswerStream { socket, sources: Vec::new(), analytics: Vec::new() })
+    }
+
+    /// AI conversation `id`, or `None` if nothing has been asked in it.
+    pub async fn ai_session(&self, id: &Uuid) -> Result<Option<AiSession>> {
+        let url = format!("{}/ai/sessions/{id}", self.base_url);
+        let resp = self.http.get(&url).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?.json::<AiSessionResponse>().await?.session))
+    }
+
+    /// The node's suggested fee rate per byte.
+    pub async fn fee_estimate(&self) -> Result<u64> {
+        let url = format!("{}/fee/estimate", self.base_url);
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<FeeEstimateResponse>().await?.fee_rate)
+    }
+
+    /// Outcome of transaction `hash`, or `None` until it is in a block.
+    pub async fn tx_receipt(&self, hash: &TxHash) -> Result<Option<TxReceipt>> {
+        let url = format!("{}/tx/{}/receipt", self.base_url, hex::encode(hash));
+        let resp = self.http.get(&url).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?.json::<ReceiptResponse>().await?.receipt))
+    }
+
+    /// ABI of the contract deployed at `address`, as JSON, or `None` if there is none.
+    pub async fn contract_abi(&self, address: &Address) -> Result<Option<serde_json::Value>> {
+        let url = format!("{}/contracts/{}", self.base_url, address_to_string(address));
+        let resp = self.http.get(&url).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?.json::<ContractResponse>().await?.abi))
+    }
+
+    /// Gas `call` would use if `caller` sent it with `value` in the next block.
+    pub async fn estimate_gas(
+        &self,
+        caller: &Address,
+        call: &ContractCall,
+        value: u64,
+    ) -> Result<GasEstimate> {
+        let url = format!("{}/contracts/estimate", self.base_url);
+        let body = serde_json::json!({ "caller": address_to_string(caller), "call": call, "value": value });
+        let resp = self.http.post(&url).json(&body).send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("node could not estimate the call ({status}): {body}"));
+        }
+        Ok(resp.json().await?)
+    }
+
+    /// Bonds, unbonding stake and pending rewards of `address`.
+    pub async fn staking_position(&self, address: &Address) -> Result<StakingPosition> {
+        let url = format!("{}/staking/{}", self.base_url, address_to_string(address));
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<StakingResponse>().await?.position)
+    }
+
+    /// Stake bonded to `validator`, by itself and by delegators.
+    pub async fn validator_status(&self, validator: &Address) -> Result<ValidatorStatus> {
+        let url = format!("{}/staking/validators/{}", self.base_url, address_to_string(validator));
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<ValidatorResponse>().await?.validator)
+    }
+
+    /// Relay status of bridge message `id`, or `None` if the node has not queued it.
+    pub async fn bridge_status(&self, id: &Uuid) -> Result<Option<BridgeTransferStatus>> {
+        let Some(record) = self.bridge_message(id).await? else {
+            return Ok(None);
+        };
+        Ok(Some(BridgeTransferStatus {
+            state: record.status,
+            attempts: record.attempts,
+            last_error: record.last_error,
+            receipt: record.receipt,
+            proof: record.tx.proof,
+        }))
+    }
+
+    /// Bridge message `id` as queued, or `None` if the node has not queued it.
+    pub async fn bridge_message(&self, id: &Uuid) -> Result<Option<BridgeMessage>> {
+        let url = format!("{}/bridge/messages/{id}", self.base_url);
+        let resp = self.http.get(&url).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?.json::<BridgeMessageResponse>().await?.message))
+    }
+
+    /// Up to `limit` queued bridge messages of each status, or of `status` only.
+    pub async fn bridge_messages(
+        &self,
+        status: Option<BridgeTransferState>,
+        limit: u64,
+    ) -> Result<Vec<BridgeMessage>> {
+        let mut url = format!("{}/bridge/messages?limit={limit}", self.base_url);
+        if let Some(status) = status {
+            url.push_str(&format!("&status={}", status.as_str()));
+        }
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<BridgeMessagesResponse>().await?.messages)
+    }
+
+    /// Audit log of bridge message `id`, oldest first.
+    pub async fn bridge_audit(&self, id: &Uuid) -> Result<Vec<BridgeAuditEvent>> {
+        let url = format!("{}/bridge/messages/{id}/audit", self.base_url);
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<BridgeAuditResponse>().await?.events)
+    }
+
+    /// External chains the node has adapters for.
+    pub async fn bridge_chains(&self) -> Result<Vec<BridgeChain>> {
+        let url = format!("{}/bridge/chains", self.base_url);
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<BridgeChainsResponse>().await?.chains)
+    }
+
+    /// Relay metrics of each chain the node has relayed to.
+    pub async fn bridge_metrics(&self) -> Result<Vec<BridgeChainMetrics>> {
+        let url = format!("{}/bridge/metrics", self.base_url);
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<BridgeMetricsResponse>().await?.chains)
+    }
+
+    /// Published identities in id order, `limit` at a time from `offset`.
+    pub async fn identities(&self, offset: u64, limit: u64) -> Result<Vec<Identity>> {
+        let url = format!("{}/identities?offset={offset}&limit={limit}", self.base_url);
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<IdentitiesResponse>().await?.identities)
+    }
+
+    /// Identity `id` as published, or `None` if it is not registered.
+    pub async fn identity(&self, id: &IdentityId) -> Result<Option<Identity>> {
+        let url = format!("{}/identities/{id}", self.base_url);
+        let resp = self.http.get(&url).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(resp.error_for_status()?.json::<IdentityResponse>().await?.identity))
+    }
+
+    /// The node's latest `limit` possible duplicate identities, newest first;
+    /// empty when it does not check for them.
+    pub async fn identity_duplicates(&self, limit: u64) -> Result<Vec<PossibleDuplicate>> {
+        let url = format!("{}/identities/duplicates?limit={limit}", self.base_url);
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
+        Ok(resp.json::<DuplicatesResponse>().await?.duplicates)
+    }
+
+    /// Publishes a signed identity update and returns the identity it produced.
+    pub async fn update_identity(&self, update: &IdentityUpdate) -> Result<Identity> {
+        let url = format!("{}/identities/{}", self.base_url, update.identity_id);
+        self.post_identity(&url, update).await
+    }
+
+    /// Publishes an identity key rotation and returns the rotated identity.
+    pub async fn rotate_identity(&self, rotation: &IdentityKeyRotation) -> Result<Identity> {
+        let url = format!("{}/identities/{}/rotate", self.base_url, rotation.identity_id);
+        self.post_identity(&url, rotation).await
+    }
+
+    async fn post_identity(&self, url: &str, body: &impl serde::Serialize) -> Result<Identity> {
+        let resp = self.http.post(url).json(body).send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("node rejected identity change ({status}): {body}"));
+        }
+        Ok(resp.json::<IdentityResponse>().await?.identity)
+    }
+
+    /// Submits a signed transaction through `POST /tx` and returns its hash.
+    pub async fn submit_tx(&self, tx: &Transaction) -> Result<TxHash> {
+        let url = format!("{}/tx", self.base_url);
+        let resp = self.http.post(&url).json(tx).send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("node rejected transaction ({status}): {body}"));
+        }
+        let hash = resp.json::<SubmitResponse>().await?.hash;
+        hex::decode(&hash)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .with_context(|| format!("node returned malformed tx hash {hash}"))
+    }
+}
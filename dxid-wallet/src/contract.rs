@@ -0,0 +1,33 @@
+//! Contract calls from a wallet. Only the main address's coins fund them, so
+//! the chain takes it as the caller and refunds unused gas to it.
+
+use anyhow::{anyhow, Result};
+use dxid_core::contract::ContractCall;
+
+use crate::{SentTx, Wallet, WalletStore};
+
+impl WalletStore {
+    /// Submits `call`, paying `value` to the contract and `gas_fee` on top of
+    /// the per-byte fee for its gas; the chain refunds the gas it does not use.
+    pub async fn call_contract(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        call: &ContractCall,
+        value: u64,
+        gas_fee: u64,
+        fee_rate: u64,
+    ) -> Result<SentTx> {
+        if !wallet.has_secret() {
+            return Err(anyhow!("wallet {} holds no secret", wallet.name));
+        }
+        let caller = wallet.address;
+        let utxos = self.node()?.utxos(&caller).await?;
+        let recipients = if value > 0 { vec![(call.contract, value)] } else { Vec::new() };
+        self.upgrade_kdf(wallet, password)?;
+        let keys = self.unlock_keys(wallet, password)?;
+        let payment = self.payment(caller, utxos, &recipients, fee_rate, Some(call.to_memo()));
+        let built = self.sign_with(&keys, payment.extra_fee(gas_fee).build()?)?;
+        self.submit(wallet, built).await
+    }
+}
@@ -0,0 +1,152 @@
+//! Moving keys out of and into wallets: the mnemonic of an HD wallet, raw
+//! ed25519 secrets, and keyfiles encrypted under a password of their own.
+
+use anyhow::{anyhow, Result};
+use bip39::Mnemonic;
+use dxid_crypto::{address_to_string, ed25519_from_secret};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    build_address_from_public_key, decrypt_secret, derive_hd_address, encrypt_secret, Kdf, Wallet,
+    WalletStore,
+};
+
+const KEYFILE_VERSION: u32 = 1;
+
+/// A wallet's secrets encrypted under the keyfile's own password, written
+/// as JSON for moving a wallet to another machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keyfile {
+    pub version: u32,
+    /// Main address of the exported wallet, readable without the password.
+    pub address: String,
+    pub kdf: Kdf,
+    pub ciphertext: Vec<u8>,
+    pub nonce: [u8; 12],
+}
+
+/// The plaintext of a keyfile: the main key, plus the seed and mnemonic
+/// entropy of HD wallets.
+#[derive(Serialize, Deserialize)]
+struct KeyfileSecrets {
+    secret: Vec<u8>,
+    #[serde(default)]
+    seed: Option<Vec<u8>>,
+    #[serde(default)]
+    entropy: Option<Vec<u8>>,
+}
+
+impl WalletStore {
+    /// The mnemonic an HD wallet was created or restored from.
+    pub fn export_mnemonic(&self, wallet: &Wallet, password: &str) -> Result<String> {
+        let entropy = self
+            .unlock_entropy(wallet, password)?
+            .ok_or_else(|| anyhow!("wallet {} did not keep its mnemonic", wallet.name))?;
+        let mnemonic = Mnemonic::from_entropy(&entropy).map_err(|e| anyhow!("mnemonic: {e}"))?;
+        Ok(mnemonic.to_string())
+    }
+
+    /// Encrypts `wallet`'s secrets under `keyfile_password`.
+    pub fn export_keyfile(&self, wallet: &Wallet, password: &str, keyfile_password: &str) -> Result<Keyfile> {
+        let (seed, entropy) = match wallet.hd {
+            Some(_) => (
+                Some(self.unlock_seed(wallet, password)?),
+                self.unlock_entropy(wallet, password)?.map(|entropy| entropy.to_vec()),
+            ),
+            None => (None, None),
+        };
+        let secrets = KeyfileSecrets { secret: self.unlock_secret(wallet, password)?, seed, entropy };
+        let plaintext = Zeroizing::new(serde_json::to_vec(&secrets)?);
+        let (ciphertext, nonce) = encrypt_secret(&plaintext, keyfile_password, &self.kdf)?;
+        Ok(Keyfile {
+            version: KEYFILE_VERSION,
+            address: address_to_string(&wallet.address),
+            kdf: self.kdf,
+            ciphertext,
+            nonce,
+        })
+    }
+
+    /// Saves the wallet a keyfile holds as `name`, encrypted under `password`.
+    /// HD wallets come back with their first receive address; scan for the rest.
+    pub fn import_keyfile(
+        &self,
+        name: &str,
+        keyfile: &Keyfile,
+        keyfile_password: &str,
+        password: &str,
+    ) -> Result<Wallet> {
+        if keyfile.version != KEYFILE_VERSION {
+            return Err(anyhow!("unsupported keyfile version {}", keyfile.version));
+        }
+        let plaintext = Zeroizing::new(decrypt_secret(
+            &keyfile.ciphertext,
+            &keyfile.nonce,
+            keyfile_password,
+            &keyfile.kdf,
+        )?);
+        let secrets: KeyfileSecrets = serde_json::from_slice(&plaintext)?;
+        let address = match &secrets.seed {
+            Some(seed) => derive_hd_address(seed, 0, false, 0)?.0.address,
+            None => build_address_from_public_key(&ed25519_public_key(&secrets.secret)?)?,
+        };
+        if address_to_string(&address) != keyfile.address {
+            return Err(anyhow!("keyfile secrets do not match its address {}", keyfile.address));
+        }
+        match &secrets.seed {
+            Some(seed) => self.new_hd(name, seed, secrets.entropy.as_deref(), password),
+            None => self.import_secret(name, &secrets.secret, password),
+        }
+    }
+
+    /// Saves a single-key wallet for a raw 32-byte ed25519 secret.
+    pub fn import_secret(&self, name: &str, secret: &[u8], password: &str) -> Result<Wallet> {
+        let public_key = ed25519_public_key(secret)?;
+        self.new_single_key(name, public_key, secret, password)
+    }
+
+    fn unlock_entropy(&self, wallet: &Wallet, password: &str) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        let full = self.hydrate(wallet)?;
+        let hd = full.hd.as_ref().ok_or_else(|| anyhow!("wallet {} is not an HD wallet", wallet.name))?;
+        let Some(nonce) = hd.entropy_nonce else {
+            return Ok(None);
+        };
+        let entropy = decrypt_secret(&hd.encrypted_entropy, &nonce, password, &wallet.kdf)?;
+        Ok(Some(Zeroizing::new(entropy)))
+    }
+}
+
+fn ed25519_public_key(secret: &[u8]) -> Result<Vec<u8>> {
+    let secret: &[u8; 32] =
+        secret.try_into().map_err(|_| anyhow!("ed25519 secrets are 32 bytes, got {}", secret.len()))?;
+    Ok(ed25519_from_secret(secret).public_key)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn keys_round_trip_through_mnemonics_keyfiles_and_hex() {
+        let store = crate::tests::test_store();
+        let (hd, phrase) = store.create_hd("export-hd", "pass").unwrap();
+        assert_eq!(store.export_mnemonic(&hd, "pass").unwrap(), phrase);
+        assert!(store.export_mnemonic(&hd, "wrong").is_err());
+
+        let keyfile = store.export_keyfile(&hd, "pass", "file-pass").unwrap();
+        assert!(store.import_keyfile("export-hd-copy", &keyfile, "wrong", "new").is_err());
+        let copy = store.import_keyfile("export-hd-copy", &keyfile, "file-pass", "new").unwrap();
+        assert_eq!(copy.address, hd.address);
+        assert_eq!(store.export_mnemonic(&copy, "new").unwrap(), phrase);
+
+        let single = store.create("export-single", "pass").unwrap();
+        let keyfile = store.export_keyfile(&single, "pass", "file-pass").unwrap();
+        let copy = store.import_keyfile("export-single-copy", &keyfile, "file-pass", "new").unwrap();
+        assert_eq!((copy.address, copy.hd.is_none()), (single.address, true));
+        assert!(store.export_mnemonic(&single, "pass").is_err());
+
+        let secret = store.unlock_secret(&single, "pass").unwrap();
+        let imported = store.import_secret("export-hex", &secret, "other").unwrap();
+        assert_eq!(imported.address, single.address);
+        assert!(store.import_secret("export-hex", &secret[..31], "other").is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//! SLIP-0010 ed25519 key derivation from a BIP-39 seed.
+//!
+//! ed25519 only supports hardened children, so every path segment is
+//! hardened: `m/44'/COIN_TYPE'/0'/chain'/index'`, with chain 0 for receive
+//! and 1 for change addresses.
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+
+/// Coin type used in dxid derivation paths.
+pub const COIN_TYPE: u32 = 7_741;
+pub(crate) const HARDENED: u32 = 0x8000_0000;
+/// Account branch reserved for identity keys, out of reach of named accounts.
+pub const IDENTITY_ACCOUNT: u32 = HARDENED - 1;
+
+/// Addresses derived past the last used one before a scan stops.
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+#[derive(Clone)]
+pub struct ExtendedKey {
+    pub secret: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    pub fn master(seed: &[u8]) -> Result<Self> {
+        Self::from_hmac(b"ed25519 seed", seed)
+    }
+
+    /// Hardened child `index` (the hardened bit is added here).
+    pub fn child(&self, index: u32) -> Result<Self> {
+        let mut data = Vec::with_capacity(37);
+        data.push(0);
+        data.extend_from_slice(&self.secret);
+        data.extend_from_slice(&(index | HARDENED).to_be_bytes());
+        Self::from_hmac(&self.chain_code, &data)
+    }
+
+    fn from_hmac(key: &[u8], data: &[u8]) -> Result<Self> {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key).map_err(|e| anyhow!("hmac key: {e}"))?;
+        mac.update(data);
+        let out = mac.finalize().into_bytes();
+        let mut secret = [0u8; 32];
+        let mut chain_code = [0u8; 32];
+        secret.copy_from_slice(&out[..32]);
+        chain_code.copy_from_slice(&out[32..]);
+        Ok(Self { secret, chain_code })
+    }
+}
+
+/// Secret key at `m/44'/COIN_TYPE'/account'/chain'/index'`.
+pub fn derive_secret(seed: &[u8], account: u32, change: bool, index: u32) -> Result<[u8; 32]> {
+    let key = ExtendedKey::master(seed)?
+        .child(44)?
+        .child(COIN_TYPE)?
+        .child(account)?
+        .child(change as u32)?
+        .child(index)?;
+    Ok(key.secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_slip10_test_vector() {
+        // SLIP-0010 ed25519 test vector 1, chain m/0'.
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedKey::master(&seed).unwrap();
+        assert_eq!(
+            hex::encode(master.secret),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex::encode(master.child(0).unwrap().secret),
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3"
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! The dxid identity a wallet owns. Its keys come from the wallet: the
+//! single key of a plain wallet, or a dedicated branch of an HD seed so
+//! the identity key can be rotated without touching any coins.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use dxid_core::{
+    add_attribute, new_identity, oauth_like_proof_message, revoke_identity, rotate_identity_key,
+    CryptoProvider, Identity, IdentityAttribute, IdentityId, IdentityStatus, OAuthLikeProofRequest,
+    OAuthLikeProofResponse,
+};
+use dxid_crypto::DefaultCryptoProvider;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::hd::IDENTITY_ACCOUNT;
+use crate::{derive_hd_address, KeyPair, Wallet, WalletStore};
+
+const ROTATION_DOMAIN: &[u8] = b"dxid/identity-rotation/v1";
+const UPDATE_DOMAIN: &[u8] = b"dxid/identity-update/v1";
+/// Rotations searched when linking an existing identity to an HD wallet.
+const LINK_SEARCH_LIMIT: u32 = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletIdentity {
+    pub identity: Identity,
+    /// Derivation index of the current identity key in HD wallets; `None`
+    /// when it is the wallet's single key.
+    pub key_index: Option<u32>,
+}
+
+/// A new identity key, signed by the key it replaces.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdentityKeyRotation {
+    pub identity_id: IdentityId,
+    pub new_public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// A change to a published identity.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IdentityChange {
+    /// Publishes a new identity keyed by `public_key`.
+    Register {
+        public_key: Vec<u8>,
+    },
+    SetAttribute {
+        key: String,
+        value: String,
+    },
+    RemoveAttribute {
+        key: String,
+    },
+    Revoke,
+}
+
+/// An identity change signed by the identity's current key (the registered
+/// key for `Register`) over the state it applies to, so it cannot be
+/// replayed once that state has moved on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdentityUpdate {
+    pub identity_id: IdentityId,
+    pub change: IdentityChange,
+    pub signature: Vec<u8>,
+}
+
+/// Commitment to an identity's keys, attributes and status; all zeros for
+/// an identity not yet registered.
+pub fn identity_digest(identity: Option<&Identity>) -> [u8; 32] {
+    let Some(identity) = identity else {
+        return [0u8; 32];
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(identity.id.as_bytes());
+    for key in &identity.public_keys {
+        hasher.update((key.len() as u64).to_le_bytes());
+        hasher.update(key);
+    }
+    let mut attributes: Vec<_> = identity.attributes.values().collect();
+    attributes.sort_by(|a, b| a.key.cmp(&b.key));
+    for attribute in attributes {
+        for field in [&attribute.key, &attribute.value] {
+            hasher.update((field.len() as u64).to_le_bytes());
+            hasher.update(field.as_bytes());
+        }
+    }
+    hasher.update([matches!(identity.status, IdentityStatus::Revoked) as u8]);
+    hasher.finalize().into()
+}
+
+fn update_message(identity_id: &IdentityId, change: &IdentityChange, state: &[u8; 32]) -> Result<Vec<u8>> {
+    let mut out = UPDATE_DOMAIN.to_vec();
+    out.extend_from_slice(identity_id.as_bytes());
+    out.extend_from_slice(state);
+    out.extend_from_slice(&serde_json::to_vec(change)?);
+    Ok(out)
+}
+
+/// Checks `update` against the published identity it changes (`None` to
+/// register a new one) and returns the identity after the change.
+pub fn apply_identity_update(current: Option<&Identity>, update: &IdentityUpdate) -> Result<Identity> {
+    let (signer, mut next) = match (current, &update.change) {
+        (None, IdentityChange::Register { public_key }) => {
+            let mut identity = new_identity(public_key.clone());
+            identity.id = update.identity_id;
+            (public_key.clone(), identity)
+        }
+        (Some(_), IdentityChange::Register { .. }) => {
+            return Err(anyhow!("identity {} is already registered", update.identity_id))
+        }
+        (None, _) => return Err(anyhow!("identity {} is not registered", update.identity_id)),
+        (Some(identity), _) => {
+            if identity.id != update.identity_id {
+                return Err(anyhow!("update is for identity {}, not {}", update.identity_id, identity.id));
+            }
+            if identity.status != IdentityStatus::Active {
+                return Err(anyhow!("identity {} is revoked", identity.id));
+            }
+            let key = identity.public_keys.last().ok_or_else(|| anyhow!("identity has no keys"))?;
+            (key.clone(), identity.clone())
+        }
+    };
+    let message = update_message(&update.identity_id, &update.change, &identity_digest(current))?;
+    if !DefaultCryptoProvider::new().verify_signature(&signer, &message, &update.signature)? {
+        return Err(anyhow!("update is not signed by the identity's current key over its current state"));
+    }
+    match &update.change {
+        IdentityChange::Register { .. } => {}
+        IdentityChange::SetAttribute { key, value } => add_attribute(
+            &mut next,
+            IdentityAttribute { key: key.clone(), value: value.clone(), embedding_ref: None },
+        ),
+        IdentityChange::RemoveAttribute { key } => {
+            next.attributes.remove(key);
+        }
+        IdentityChange::Revoke => revoke_identity(&mut next),
+    }
+    Ok(next)
+}
+
+fn rotation_message(identity_id: &IdentityId, new_public_key: &[u8]) -> Vec<u8> {
+    let mut out = ROTATION_DOMAIN.to_vec();
+    out.extend_from_slice(identity_id.as_bytes());
+    out.extend_from_slice(new_public_key);
+    out
+}
+
+/// Whether `rotation` was signed by the current key of `identity`, as held
+/// before the rotation is applied.
+pub fn verify_identity_rotation(identity: &Identity, rotation: &IdentityKeyRotation) -> Result<bool> {
+    let Some(current) = identity.public_keys.last() else {
+        return Ok(false);
+    };
+    if rotation.identity_id != identity.id || identity.status != IdentityStatus::Active {
+        return Ok(false);
+    }
+    DefaultCryptoProvider::new().verify_signature(
+        current,
+        &rotation_message(&rotation.identity_id, &rotation.new_public_key),
+        &rotation.signature,
+    )
+}
+
+impl Wallet {
+    pub fn identity_id(&self) -> Option<IdentityId> {
+        self.identity.as_ref().map(|owned| owned.identity.id)
+    }
+
+    fn owned_identity(&self) -> Result<&WalletIdentity> {
+        self.identity.as_ref().ok_or_else(|| anyhow!("wallet {} owns no identity", self.name))
+    }
+}
+
+impl WalletStore {
+    /// Creates an identity keyed by this wallet and records it in the wallet.
+    pub fn create_identity(&self, wallet: &mut Wallet, password: &str) -> Result<Identity> {
+        if let Some(id) = wallet.identity_id() {
+            return Err(anyhow!("wallet {} already owns identity {id}", wallet.name));
+        }
+        let key_index = wallet.hd.as_ref().map(|_| 0);
+        let (public_key, _) = self.identity_key(wallet, password, key_index)?;
+        let identity = new_identity(public_key);
+        wallet.identity = Some(WalletIdentity { identity: identity.clone(), key_index });
+        self.save(wallet)?;
+        Ok(identity)
+    }
+
+    /// Adopts an existing identity whose current key is one of this wallet's.
+    pub fn link_identity(&self, wallet: &mut Wallet, password: &str, identity: Identity) -> Result<()> {
+        let current = identity.public_keys.last().ok_or_else(|| anyhow!("identity has no keys"))?;
+        let key_index = if wallet.hd.is_some() {
+            let seed = self.unlock_seed(wallet, password)?;
+            let found = (0..LINK_SEARCH_LIMIT).find(|&index| {
+                derive_hd_address(&seed, IDENTITY_ACCOUNT, false, index)
+                    .is_ok_and(|(key, _)| &key.public_key == current)
+            });
+            Some(found.ok_or_else(|| anyhow!("identity {} is not keyed by this wallet", identity.id))?)
+        } else if current == &wallet.public_key {
+            None
+        } else {
+            return Err(anyhow!("identity {} is not keyed by this wallet", identity.id));
+        };
+        wallet.identity = Some(WalletIdentity { identity, key_index });
+        self.save(wallet)
+    }
+
+    pub fn set_identity_attribute(&self, wallet: &mut Wallet, key: &str, value: &str) -> Result<()> {
+        let owned = wallet.identity.as_mut().ok_or_else(|| anyhow!("wallet owns no identity"))?;
+        add_attribute(
+            &mut owned.identity,
+            IdentityAttribute { key: key.to_string(), value: value.to_string(), embedding_ref: None },
+        );
+        self.save(wallet)
+    }
+
+    /// Moves the identity to the next key on the wallet's identity branch and
+    /// returns the rotation signed by the previous key, for publishing.
+    pub fn rotate_identity_key(&self, wallet: &mut Wallet, password: &str) -> Result<IdentityKeyRotation> {
+        let owned = wallet.owned_identity()?;
+        let index = owned.key_index.ok_or_else(|| anyhow!("rotating identity keys needs an HD wallet"))?;
+        let identity_id = owned.identity.id;
+        let (_, old_secret) = self.identity_key(wallet, password, Some(index))?;
+        let (new_public_key, _) = self.identity_key(wallet, password, Some(index + 1))?;
+        let signature =
+            self.crypto.sign_message(&old_secret, &rotation_message(&identity_id, &new_public_key))?;
+
+        let owned = wallet.identity.as_mut().ok_or_else(|| anyhow!("wallet owns no identity"))?;
+        rotate_identity_key(&mut owned.identity, new_public_key.clone());
+        owned.key_index = Some(index + 1);
+        self.save(wallet)?;
+        Ok(IdentityKeyRotation { identity_id, new_public_key, signature })
+    }
+
+    /// Signs `change` to the wallet's identity as `published` on the node
+    /// (`None` to register it) and records the changed identity in the wallet.
+    pub fn sign_identity_update(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        published: Option<&Identity>,
+        change: IdentityChange,
+    ) -> Result<IdentityUpdate> {
+        let owned = wallet.owned_identity()?;
+        let identity_id = owned.identity.id;
+        if published.is_some_and(|p| p.id != identity_id) {
+            return Err(anyhow!("wallet {} does not own that identity", wallet.name));
+        }
+        let (public_key, secret) = self.identity_key(wallet, password, owned.key_index)?;
+        let change = match change {
+            IdentityChange::Register { .. } => IdentityChange::Register { public_key: public_key.clone() },
+            change => change,
+        };
+        if published.is_some_and(|p| p.public_keys.last() != Some(&public_key)) {
+            return Err(anyhow!(
+                "published identity {identity_id} is keyed by another key than the wallet's"
+            ));
+        }
+        let message = update_message(&identity_id, &change, &identity_digest(published))?;
+        let update =
+            IdentityUpdate { identity_id, change, signature: self.crypto.sign_message(&secret, &message)? };
+        let next = apply_identity_update(published, &update)?;
+        if let Some(owned) = wallet.identity.as_mut() {
+            owned.identity = next;
+        }
+        self.save(wallet)?;
+        Ok(update)
+    }
+
+    /// Answers `request` with the identity's current key, disclosing only the
+    /// `disclose` attributes, each of which must have been requested.
+    pub fn answer_proof_request(
+        &self,
+        wallet: &Wallet,
+        password: &str,
+        request: &OAuthLikeProofRequest,
+        disclose: &[&str],
+    ) -> Result<OAuthLikeProofResponse> {
+        let owned = wallet.owned_identity()?;
+        let identity = &owned.identity;
+        if identity.status != IdentityStatus::Active {
+            return Err(anyhow!("identity {} is revoked", identity.id));
+        }
+        let mut disclosed_attributes = HashMap::new();
+        for &key in disclose {
+            if !request.scope.iter().any(|s| s == key) {
+                return Err(anyhow!("{} did not ask for {key}", request.audience));
+            }
+            let attr =
+                identity.attributes.get(key).ok_or_else(|| anyhow!("identity has no attribute {key}"))?;
+            disclosed_attributes.insert(key.to_string(), attr.value.clone());
+        }
+        let issued_at = Utc::now();
+        let (_, secret) = self.identity_key(wallet, password, owned.key_index)?;
+        let message = oauth_like_proof_message(request, &identity.id, &issued_at, &disclosed_attributes);
+        Ok(OAuthLikeProofResponse {
+            identity_id: identity.id,
+            issued_at,
+            signature: self.crypto.sign_message(&secret, &message)?,
+            disclosed_attributes,
+        })
+    }
+
+    fn identity_key(&self, wallet: &Wallet, password: &str, index: Option<u32>) -> Result<KeyPair> {
+        if !wallet.has_secret() {
+            return Err(anyhow!("wallet {} holds no secret", wallet.name));
+        }
+        match index {
+            None => Ok((wallet.public_key.clone(), self.unlock_secret(wallet, password)?)),
+            Some(index) => {
+                let seed = self.unlock_seed(wallet, password)?;
+                let (key, secret) = derive_hd_address(&seed, IDENTITY_ACCOUNT, false, index)?;
+                Ok((key.public_key, secret.to_vec()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dxid_core::{build_oauth_like_challenge, verify_oauth_like_proof};
+
+    #[test]
+    fn identity_rotates_and_answers_proofs() {
+        let store = crate::tests::test_store();
+        let (mut wallet, _) = store.create_hd("identity-owner", "pass").unwrap();
+        let created = store.create_identity(&mut wallet, "pass").unwrap();
+        assert!(store.create_identity(&mut wallet, "pass").is_err());
+        store.set_identity_attribute(&mut wallet, "email", "a@example.org").unwrap();
+        store.set_identity_attribute(&mut wallet, "country", "NZ").unwrap();
+
+        let before = wallet.identity.clone().unwrap().identity;
+        let rotation = store.rotate_identity_key(&mut wallet, "pass").unwrap();
+        assert!(verify_identity_rotation(&before, &rotation).unwrap());
+        let identity = store.load("identity-owner").unwrap().identity.unwrap().identity;
+        assert_eq!(identity.id, created.id);
+        assert_eq!(identity.public_keys.last(), Some(&rotation.new_public_key));
+
+        let request = build_oauth_like_challenge("shop".into(), vec!["email".into()]);
+        assert!(store.answer_proof_request(&wallet, "pass", &request, &["country"]).is_err());
+        let response = store.answer_proof_request(&wallet, "pass", &request, &["email"]).unwrap();
+        assert_eq!(response.disclosed_attributes.len(), 1);
+        let crypto = DefaultCryptoProvider::new();
+        assert!(verify_oauth_like_proof(&crypto, &identity, &request, &response).unwrap());
+        assert!(!verify_oauth_like_proof(&crypto, &before, &request, &response).unwrap());
+
+        let mut relinked = store.load("identity-owner").unwrap();
+        relinked.identity = None;
+        store.link_identity(&mut relinked, "pass", identity).unwrap();
+        assert_eq!(relinked.identity.unwrap().key_index, Some(1));
+    }
+
+    #[test]
+    fn identity_updates_are_signed_over_the_published_state() {
+        let store = crate::tests::test_store();
+        let (mut wallet, _) = store.create_hd("publisher", "pass").unwrap();
+        let created = store.create_identity(&mut wallet, "pass").unwrap();
+        let register = IdentityChange::Register { public_key: Vec::new() };
+        let update = store.sign_identity_update(&mut wallet, "pass", None, register).unwrap();
+        let published = apply_identity_update(None, &update).unwrap();
+        assert_eq!(published.public_keys, created.public_keys);
+        assert!(apply_identity_update(Some(&published), &update).is_err());
+
+        let change = IdentityChange::SetAttribute { key: "email".into(), value: "a@example.org".into() };
+        let update = store.sign_identity_update(&mut wallet, "pass", Some(&published), change).unwrap();
+        let with_email = apply_identity_update(Some(&published), &update).unwrap();
+        assert_eq!(with_email.attributes["email"].value, "a@example.org");
+        assert!(apply_identity_update(Some(&with_email), &update).is_err(), "replayed against a newer state");
+        let mut forged = update.clone();
+        forged.change = IdentityChange::SetAttribute { key: "email".into(), value: "b@example.org".into() };
+        assert!(apply_identity_update(Some(&published), &forged).is_err());
+
+        let update = store
+            .sign_identity_update(&mut wallet, "pass", Some(&with_email), IdentityChange::Revoke)
+            .unwrap();
+        let revoked = apply_identity_update(Some(&with_email), &update).unwrap();
+        assert_eq!(revoked.status, IdentityStatus::Revoked);
+        assert_eq!(wallet.identity.as_ref().unwrap().identity.status, IdentityStatus::Revoked);
+        let remove = IdentityChange::RemoveAttribute { key: "email".into() };
+        assert!(store.sign_identity_update(&mut wallet, "pass", Some(&revoked), remove).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::{anyhow, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use pbkdf2::pbkdf2_hmac;
+use serde::{Deserialize, Serialize};
+
+/// Password key derivation for a wallet's encrypted secrets, stored in the
+/// wallet file next to the ciphertext (whose first 16 bytes are the salt).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum Kdf {
+    Pbkdf2 { iterations: u32 },
+    Argon2id { memory_kib: u32, iterations: u32, parallelism: u32 },
+}
+
+impl Kdf {
+    /// What wallet files written before the KDF was recorded use.
+    pub fn legacy() -> Self {
+        Kdf::Pbkdf2 { iterations: 10_000 }
+    }
+
+    pub(crate) fn derive_key(&self, password: &str, salt: &[u8]) -> Result<[u8; 32]> {
+        let mut key = [0u8; 32];
+        match *self {
+            Kdf::Pbkdf2 { iterations } => {
+                pbkdf2_hmac::<sha2::Sha256>(password.as_bytes(), salt, iterations, &mut key)
+            }
+            Kdf::Argon2id { memory_kib, iterations, parallelism } => {
+                let params = Params::new(memory_kib, iterations, parallelism, Some(key.len()))
+                    .map_err(|e| anyhow!("invalid argon2 parameters: {e}"))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password.as_bytes(), salt, &mut key)
+                    .map_err(|e| anyhow!("argon2: {e}"))?;
+            }
+        }
+        Ok(key)
+    }
+}
+
+/// Argon2id with 19 MiB of memory and two passes, per current OWASP guidance.
+impl Default for Kdf {
+    fn default() -> Self {
+        Kdf::Argon2id { memory_kib: 19 * 1024, iterations: 2, parallelism: 1 }
+    }
+}
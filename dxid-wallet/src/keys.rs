@@ -0,0 +1,169 @@
+//! Where a wallet's encrypted secrets live: inline in the wallet file, or in
+//! a [`KeyBackend`] such as the OS keychain.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{Wallet, WalletStore};
+
+const SECRET_SLOT: &str = "secret";
+const SEED_SLOT: &str = "seed";
+const ENTROPY_SLOT: &str = "entropy";
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyLocation {
+    /// Ciphertext is stored in the wallet's JSON file.
+    #[default]
+    File,
+    /// Ciphertext is held by the store's [`KeyBackend`]; the file has none.
+    Backend,
+}
+
+/// Secret storage outside the wallet directory. Values are the already
+/// encrypted secrets, so a backend only adds protection on top of the password.
+pub trait KeyBackend: Send + Sync {
+    fn put(&self, wallet: &str, slot: &str, data: &[u8]) -> Result<()>;
+    fn get(&self, wallet: &str, slot: &str) -> Result<Vec<u8>>;
+    fn delete(&self, wallet: &str, slot: &str) -> Result<()>;
+}
+
+/// In-process backend, for tests and embedders with their own persistence.
+#[derive(Debug, Default)]
+pub struct MemoryKeyBackend {
+    entries: Mutex<HashMap<(String, String), Vec<u8>>>,
+}
+
+impl KeyBackend for MemoryKeyBackend {
+    fn put(&self, wallet: &str, slot: &str, data: &[u8]) -> Result<()> {
+        self.entries.lock().insert((wallet.to_string(), slot.to_string()), data.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, wallet: &str, slot: &str) -> Result<Vec<u8>> {
+        self.entries
+            .lock()
+            .get(&(wallet.to_string(), slot.to_string()))
+            .cloned()
+            .ok_or_else(|| anyhow!("no {slot} stored for wallet {wallet}"))
+    }
+
+    fn delete(&self, wallet: &str, slot: &str) -> Result<()> {
+        self.entries.lock().remove(&(wallet.to_string(), slot.to_string()));
+        Ok(())
+    }
+}
+
+/// The platform credential store: macOS Keychain, Windows Credential
+/// Manager or the Linux secret-service.
+#[cfg(feature = "keyring")]
+#[derive(Debug, Clone)]
+pub struct OsKeyring {
+    service: String,
+}
+
+#[cfg(feature = "keyring")]
+impl OsKeyring {
+    pub fn new(service: impl Into<String>) -> Self {
+        Self { service: service.into() }
+    }
+
+    fn entry(&self, wallet: &str, slot: &str) -> Result<keyring::Entry> {
+        Ok(keyring::Entry::new(&self.service, &format!("{wallet}/{slot}"))?)
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl Default for OsKeyring {
+    fn default() -> Self {
+        Self::new("dxid-wallet")
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl KeyBackend for OsKeyring {
+    fn put(&self, wallet: &str, slot: &str, data: &[u8]) -> Result<()> {
+        Ok(self.entry(wallet, slot)?.set_secret(data)?)
+    }
+
+    fn get(&self, wallet: &str, slot: &str) -> Result<Vec<u8>> {
+        Ok(self.entry(wallet, slot)?.get_secret()?)
+    }
+
+    fn delete(&self, wallet: &str, slot: &str) -> Result<()> {
+        match self.entry(wallet, slot)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl WalletStore {
+    /// Backend for wallets moved out of their files with [`WalletStore::set_key_location`].
+    pub fn with_key_backend(mut self, backend: Arc<dyn KeyBackend>) -> Self {
+        self.key_backend = Some(backend);
+        self
+    }
+
+    /// Moves `wallet`'s encrypted secrets between its file and the key backend.
+    pub fn set_key_location(&self, wallet: &mut Wallet, location: KeyLocation) -> Result<()> {
+        if !wallet.has_secret() {
+            return Err(anyhow!("wallet {} holds no secret", wallet.name));
+        }
+        if wallet.key_location == location {
+            return Ok(());
+        }
+        let mut full = self.hydrate(wallet)?;
+        let previous = full.key_location;
+        full.key_location = location;
+        *wallet = self.persist(full)?;
+        if previous == KeyLocation::Backend {
+            let backend = self.key_backend()?;
+            backend.delete(&wallet.name, SECRET_SLOT)?;
+            backend.delete(&wallet.name, SEED_SLOT)?;
+            backend.delete(&wallet.name, ENTROPY_SLOT)?;
+        }
+        Ok(())
+    }
+
+    fn key_backend(&self) -> Result<&dyn KeyBackend> {
+        self.key_backend.as_deref().ok_or_else(|| anyhow!("wallet store has no key backend configured"))
+    }
+
+    /// `wallet` with its encrypted secrets filled in from wherever they live.
+    pub(crate) fn hydrate(&self, wallet: &Wallet) -> Result<Wallet> {
+        let mut full = wallet.clone();
+        if wallet.key_location == KeyLocation::Backend {
+            let backend = self.key_backend()?;
+            full.encrypted_secret = backend.get(&wallet.name, SECRET_SLOT)?;
+            if let Some(hd) = full.hd.as_mut() {
+                hd.encrypted_seed = backend.get(&wallet.name, SEED_SLOT)?;
+                if hd.entropy_nonce.is_some() {
+                    hd.encrypted_entropy = backend.get(&wallet.name, ENTROPY_SLOT)?;
+                }
+            }
+        }
+        Ok(full)
+    }
+
+    /// Saves a hydrated wallet, first moving its secrets into the backend if
+    /// that is where they belong. Returns the wallet as saved.
+    pub(crate) fn persist(&self, mut wallet: Wallet) -> Result<Wallet> {
+        if wallet.key_location == KeyLocation::Backend {
+            let backend = self.key_backend()?;
+            backend.put(&wallet.name, SECRET_SLOT, &std::mem::take(&mut wallet.encrypted_secret))?;
+            if let Some(hd) = wallet.hd.as_mut() {
+                backend.put(&wallet.name, SEED_SLOT, &std::mem::take(&mut hd.encrypted_seed))?;
+                if hd.entropy_nonce.is_some() {
+                    backend.put(&wallet.name, ENTROPY_SLOT, &std::mem::take(&mut hd.encrypted_entropy))?;
+                }
+            }
+        }
+        self.save(&wallet)?;
+        Ok(wallet)
+    }
+}
@@ -0,0 +1,223 @@
+//! Signing on a Ledger running the dxid app.
+//!
+//! The device derives keys at the wallet's SLIP-0010 path and signs only
+//! after the user confirms each output and the fee on screen. It is sent
+//! the whole unsigned transaction and recomputes the signing message
+//! itself, so the host cannot get a signature over anything it did not show.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use dxid_core::{Address, Transaction};
+
+use crate::hd::{COIN_TYPE, HARDENED};
+use crate::signer::TxSigner;
+use crate::{build_address_from_public_key, Wallet, WalletStore, WatchSource};
+
+const INS_GET_PUBLIC_KEY: u8 = 0x02;
+const INS_SIGN_TX: u8 = 0x03;
+/// P1 of the first chunk of a message and of every following one.
+const P1_FIRST: u8 = 0x00;
+const P1_MORE: u8 = 0x80;
+/// P2 when more chunks follow, and on the last one.
+const P2_MORE: u8 = 0x80;
+const P2_LAST: u8 = 0x00;
+const MAX_CHUNK: usize = 255;
+
+const SW_OK: u16 = 0x9000;
+const SW_DENIED: u16 = 0x6985;
+const SW_APP_CLOSED: [u16; 2] = [0x6d00, 0x6e00];
+
+/// Moves APDUs to and from a device.
+pub trait LedgerTransport: Send + Sync {
+    /// Sends one command and returns the response data and status word.
+    fn exchange(&self, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<(Vec<u8>, u16)>;
+}
+
+/// A Ledger over USB HID.
+#[cfg(feature = "ledger")]
+pub struct HidTransport {
+    inner: ledger_transport_hid::TransportNativeHID,
+}
+
+#[cfg(feature = "ledger")]
+impl HidTransport {
+    const CLA: u8 = 0xe0;
+
+    /// Opens the first Ledger found.
+    pub fn open() -> Result<Self> {
+        let api = ledger_transport_hid::hidapi::HidApi::new()?;
+        Ok(Self { inner: ledger_transport_hid::TransportNativeHID::new(&api)? })
+    }
+}
+
+#[cfg(feature = "ledger")]
+impl LedgerTransport for HidTransport {
+    fn exchange(&self, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<(Vec<u8>, u16)> {
+        let command = ledger_transport::APDUCommand { cla: Self::CLA, ins, p1, p2, data };
+        let answer = self.inner.exchange(&command)?;
+        Ok((answer.data().to_vec(), answer.retcode()))
+    }
+}
+
+/// [`TxSigner`] for a key that lives on a Ledger.
+pub struct HardwareSigner {
+    transport: Arc<dyn LedgerTransport>,
+    path: [u32; 5],
+    public_key: Vec<u8>,
+}
+
+impl HardwareSigner {
+    /// Reads the public key at `m/44'/COIN_TYPE'/account'/chain'/index'`.
+    pub async fn connect(
+        transport: Arc<dyn LedgerTransport>,
+        account: u32,
+        change: bool,
+        index: u32,
+    ) -> Result<Self> {
+        let path = [44, COIN_TYPE, account, change as u32, index].map(|segment| segment | HARDENED);
+        let public_key = send(transport.clone(), INS_GET_PUBLIC_KEY, encode_path(&path)).await?;
+        if public_key.len() != 32 {
+            return Err(anyhow!("device returned a {}-byte public key", public_key.len()));
+        }
+        Ok(Self { transport, path, public_key })
+    }
+
+    #[cfg(feature = "ledger")]
+    pub async fn open_hid(account: u32, change: bool, index: u32) -> Result<Self> {
+        let transport = tokio::task::spawn_blocking(HidTransport::open).await??;
+        Self::connect(Arc::new(transport), account, change, index).await
+    }
+
+    pub fn address(&self) -> Result<Address> {
+        build_address_from_public_key(&self.public_key)
+    }
+}
+
+#[async_trait]
+impl TxSigner for HardwareSigner {
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    async fn sign_input(&self, tx: &Transaction, input: usize, _message: &[u8]) -> Result<Vec<u8>> {
+        let mut unsigned = tx.clone();
+        for input in &mut unsigned.inputs {
+            input.signature.clear();
+        }
+        let mut payload = encode_path(&self.path);
+        payload.extend_from_slice(&(input as u32).to_be_bytes());
+        payload.extend_from_slice(&serde_json::to_vec(&unsigned)?);
+        send(self.transport.clone(), INS_SIGN_TX, payload).await
+    }
+}
+
+impl WalletStore {
+    /// Saves a watch-only wallet for the device key behind `signer`; spend
+    /// from it with [`crate::sign_built_with`].
+    pub fn import_hardware(&self, name: &str, signer: &HardwareSigner) -> Result<Wallet> {
+        self.import_watch_only(name, WatchSource::PublicKey(signer.public_key.clone()))
+    }
+}
+
+fn encode_path(path: &[u32]) -> Vec<u8> {
+    let mut out = vec![path.len() as u8];
+    for segment in path {
+        out.extend_from_slice(&segment.to_be_bytes());
+    }
+    out
+}
+
+/// Sends `payload` in as many chunks as it needs, off the async runtime
+/// since the device blocks until the user answers.
+async fn send(transport: Arc<dyn LedgerTransport>, ins: u8, payload: Vec<u8>) -> Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || {
+        let chunks: Vec<&[u8]> = payload.chunks(MAX_CHUNK).collect();
+        let mut response = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let p1 = if i == 0 { P1_FIRST } else { P1_MORE };
+            let p2 = if i + 1 < chunks.len() { P2_MORE } else { P2_LAST };
+            let (data, status) = transport.exchange(ins, p1, p2, chunk)?;
+            match status {
+                SW_OK => response = data,
+                SW_DENIED => return Err(anyhow!("rejected on the device")),
+                s if SW_APP_CLOSED.contains(&s) => return Err(anyhow!("open the dxid app on the device")),
+                s => return Err(anyhow!("device returned status {s:#06x}")),
+            }
+        }
+        Ok(response)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dxid_core::builder::{TxBuilder, Utxo};
+    use dxid_core::{signing_message, CryptoProvider};
+    use dxid_crypto::{generate_ed25519, DefaultCryptoProvider};
+    use parking_lot::Mutex;
+
+    /// Stands in for the device app: reassembles chunks and signs what it parsed.
+    struct FakeDevice {
+        public_key: Vec<u8>,
+        secret_key: Vec<u8>,
+        buffer: Mutex<Vec<u8>>,
+        approve: bool,
+    }
+
+    impl LedgerTransport for FakeDevice {
+        fn exchange(&self, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<(Vec<u8>, u16)> {
+            let mut buffer = self.buffer.lock();
+            if p1 == P1_FIRST {
+                buffer.clear();
+            }
+            buffer.extend_from_slice(data);
+            if p2 == P2_MORE {
+                return Ok((vec![], SW_OK));
+            }
+            let path_len = 1 + 4 * buffer[0] as usize;
+            match ins {
+                INS_GET_PUBLIC_KEY => Ok((self.public_key.clone(), SW_OK)),
+                INS_SIGN_TX if !self.approve => Ok((vec![], SW_DENIED)),
+                INS_SIGN_TX => {
+                    let index = u32::from_be_bytes(buffer[path_len..path_len + 4].try_into()?) as usize;
+                    let tx: Transaction = serde_json::from_slice(&buffer[path_len + 4..])?;
+                    let message = signing_message(&tx.inputs[index], &tx.signing_hash());
+                    Ok((DefaultCryptoProvider::new().sign_message(&self.secret_key, &message)?, SW_OK))
+                }
+                _ => Ok((vec![], 0x6d00)),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn signs_chunked_transactions_on_device() {
+        let keys = generate_ed25519();
+        let device = Arc::new(FakeDevice {
+            public_key: keys.public_key.clone(),
+            secret_key: keys.secret_key.clone(),
+            buffer: Mutex::new(vec![]),
+            approve: true,
+        });
+        let signer = HardwareSigner::connect(device, 0, false, 0).await.unwrap();
+        let owner = signer.address().unwrap();
+
+        let utxos = (0..4).map(|n| Utxo { tx_hash: [n; 32], index: 0, address: owner, amount: 10_000 });
+        let built =
+            TxBuilder::new(owner).utxos(utxos).pay([9u8; 32], 35_000).memo("x".repeat(300)).build().unwrap();
+        let signed = crate::sign_built_with(&signer, built).await.unwrap();
+        assert_eq!(signed.tx.inputs.len(), 4);
+
+        let refusing = Arc::new(FakeDevice {
+            public_key: keys.public_key,
+            secret_key: keys.secret_key,
+            buffer: Mutex::new(vec![]),
+            approve: false,
+        });
+        let signer = HardwareSigner::connect(refusing, 0, false, 0).await.unwrap();
+        let err = crate::sign_built_with(&signer, signed).await.unwrap_err();
+        assert!(err.to_string().contains("rejected"));
+    }
+}
@@ -1,16 +1,80 @@
 use aes_gcm::aead::{Aead, KeyInit, OsRng};
 use aes_gcm::{Aes256Gcm, Nonce};
 use anyhow::{anyhow, Result};
-use dxid_core::Address;
-use dxid_crypto::{address_from_string, address_to_string, generate_ed25519, DefaultCryptoProvider};
-use pbkdf2::pbkdf2_hmac;
+use bip39::Mnemonic;
+use dxid_core::builder::{sign_inputs_with, BuiltTx, CoinSelector, LargestFirst, TxBuilder, Utxo};
+use dxid_core::multisig::{MultisigOutput, MultisigPolicy};
+use dxid_core::{Address, CryptoProvider, TxHash};
+use dxid_crypto::{
+    address_from_string, address_to_string, ed25519_from_secret, generate_ed25519, DefaultCryptoProvider,
+};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::sync::Arc;
 
+use session::SessionCache;
+
+mod accounts;
+mod book;
+mod bridge;
+mod client;
+mod contract;
+mod export;
+pub mod hd;
+mod identity;
+mod kdf;
+mod keys;
+mod ledger;
+mod message;
+mod multisig;
+mod signer;
+mod staking;
+mod session;
+mod sync;
+mod watch;
+
+pub use accounts::{HdAccount, DEFAULT_ACCOUNT};
+pub use book::{Contact, PaymentRequest};
+pub use bridge::{BridgeSend, BridgeTransferState, BridgeTransferStatus};
+pub use client::{
+    AiAnswerStream, AiMetric, AiSession, AiSessionMessage, AiSource, BlockPage, BlockSubscription, BridgeAuditEvent,
+    BridgeChain, BridgeChainMetrics, BridgeMessage, EventSubscription, GasEstimate, NodeClient, NodeStatus,
+    PeerInfo,
+};
+pub use export::Keyfile;
+pub use identity::{
+    apply_identity_update, identity_digest, verify_identity_rotation, IdentityChange, IdentityKeyRotation,
+    IdentityUpdate, WalletIdentity,
+};
+pub use kdf::Kdf;
+#[cfg(feature = "keyring")]
+pub use keys::OsKeyring;
+pub use keys::{KeyBackend, KeyLocation, MemoryKeyBackend};
+#[cfg(feature = "ledger")]
+pub use ledger::HidTransport;
+pub use ledger::{HardwareSigner, LedgerTransport};
+pub use message::{message_payload, verify_message, MessageSignature};
+pub use multisig::{
+    descriptor, output_descriptor, parse_descriptor, parse_output_descriptor, Cosigners, PartiallySignedTx,
+};
+pub use signer::{sign_built_with, LocalSigner, TxSigner};
+pub use session::DEFAULT_SESSION_TIMEOUT;
+pub use sync::{BlockOutcome, HistoryEntry, PendingSpend, SyncDb, WalletSync, MAX_REORG_DEPTH};
+pub use watch::{WatchSource, WatchState, WatchedAddress};
+
+/// Previous versions of each wallet file kept under `backups/`.
+pub const DEFAULT_BACKUPS: usize = 3;
+
+/// `(public, secret)` ed25519 key bytes.
+type KeyPair = (Vec<u8>, Vec<u8>);
+
+/// A wallet file. For HD wallets `address`, `public_key` and
+/// `encrypted_secret` hold the first receive key, so single-key callers
+/// keep working. Watch-only and multisig wallets have no secret at all.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Wallet {
     pub name: String,
@@ -18,11 +82,128 @@ pub struct Wallet {
     pub public_key: Vec<u8>,
     pub encrypted_secret: Vec<u8>,
     pub nonce: [u8; 12],
+    /// How the secrets were encrypted; files without it predate Argon2id.
+    #[serde(default = "Kdf::legacy")]
+    pub kdf: Kdf,
+    /// Whether the ciphertexts are in this file or the store's key backend.
+    #[serde(default)]
+    pub key_location: KeyLocation,
+    #[serde(default)]
+    pub hd: Option<HdState>,
+    #[serde(default)]
+    pub watch: Option<WatchState>,
+    /// Shared M-of-N policy; `address` is the policy address.
+    #[serde(default)]
+    pub multisig: Option<MultisigPolicy>,
+    /// Shared M-of-N output over cosigner addresses; `address` is its address.
+    #[serde(default)]
+    pub multisig_output: Option<MultisigOutput>,
+    #[serde(default)]
+    pub contacts: Vec<Contact>,
+    /// Identity keyed by this wallet.
+    #[serde(default)]
+    pub identity: Option<WalletIdentity>,
+}
+
+impl Wallet {
+    /// Every address this wallet tracks.
+    pub fn addresses(&self) -> Vec<Address> {
+        match (&self.hd, &self.watch) {
+            (Some(hd), _) => hd.addresses.iter().map(|a| a.address).collect(),
+            (None, Some(watch)) => watch.addresses.iter().map(|a| a.address).collect(),
+            (None, None) => vec![self.address],
+        }
+    }
+
+    /// Addresses whose coins payments are made from: the active account's
+    /// for HD wallets, every address otherwise.
+    pub fn spend_addresses(&self) -> Vec<Address> {
+        match &self.hd {
+            Some(hd) => hd.account_addresses(hd.account).map(|a| a.address).collect(),
+            None => self.addresses(),
+        }
+    }
+
+    pub fn is_watch_only(&self) -> bool {
+        self.watch.is_some()
+    }
+
+    /// Whether the file holds a secret this wallet can sign with.
+    pub fn has_secret(&self) -> bool {
+        self.watch.is_none() && self.multisig.is_none() && self.multisig_output.is_none()
+    }
+}
+
+/// An address derived from an HD wallet's seed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HdAddress {
+    pub address: Address,
+    pub public_key: Vec<u8>,
+    #[serde(default)]
+    pub account: u32,
+    pub change: bool,
+    pub index: u32,
+}
+
+/// Encrypted seed and derivation progress of an HD wallet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HdState {
+    pub encrypted_seed: Vec<u8>,
+    pub seed_nonce: [u8; 12],
+    /// Active account: new addresses and payments come from it.
+    pub account: u32,
+    /// Named accounts. Files from before accounts list none and only use
+    /// account 0, which is then [`DEFAULT_ACCOUNT`].
+    #[serde(default)]
+    pub accounts: Vec<HdAccount>,
+    /// Derived addresses of every account.
+    pub addresses: Vec<HdAddress>,
+    /// Encrypted BIP-39 entropy, so the mnemonic can be shown again. Wallets
+    /// created before it was kept have no `entropy_nonce`.
+    #[serde(default)]
+    pub encrypted_entropy: Vec<u8>,
+    #[serde(default)]
+    pub entropy_nonce: Option<[u8; 12]>,
+}
+
+impl HdState {
+    fn record(&mut self, address: HdAddress) {
+        if !self.addresses.iter().any(|a| a.address == address.address) {
+            self.addresses.push(address);
+        }
+    }
+
+    /// Next unused receive or change index of `account`.
+    pub fn next_index(&self, account: u32, change: bool) -> u32 {
+        self.account_addresses(account)
+            .filter(|a| a.change == change)
+            .map(|a| a.index + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn account_addresses(&self, account: u32) -> impl Iterator<Item = &HdAddress> {
+        self.addresses.iter().filter(move |a| a.account == account)
+    }
+}
+
+/// A signed transaction and what it costs.
+#[derive(Debug, Clone)]
+pub struct SentTx {
+    pub hash: TxHash,
+    pub built: BuiltTx,
 }
 
 pub struct WalletStore {
     root: PathBuf,
     crypto: DefaultCryptoProvider,
+    node: Option<NodeClient>,
+    kdf: Kdf,
+    key_backend: Option<Arc<dyn KeyBackend>>,
+    coin_selector: Arc<dyn CoinSelector>,
+    sessions: SessionCache,
+    backups: usize,
+    overwrite: bool,
 }
 
 impl WalletStore {
@@ -31,31 +212,369 @@ impl WalletStore {
         Ok(Self {
             root,
             crypto: DefaultCryptoProvider::new(),
+            node: None,
+            kdf: Kdf::default(),
+            key_backend: None,
+            coin_selector: Arc::new(LargestFirst),
+            sessions: SessionCache::new(DEFAULT_SESSION_TIMEOUT),
+            backups: DEFAULT_BACKUPS,
+            overwrite: false,
         })
     }
 
+    /// Node used to look up coins and broadcast transactions.
+    pub fn with_node(mut self, node: NodeClient) -> Self {
+        self.node = Some(node);
+        self
+    }
+
+    /// How coins are picked when building transactions.
+    pub fn with_coin_selector(mut self, selector: Arc<dyn CoinSelector>) -> Self {
+        self.coin_selector = selector;
+        self
+    }
+
+    /// How many previous versions of each wallet file to keep; 0 keeps none.
+    pub fn with_backups(mut self, count: usize) -> Self {
+        self.backups = count;
+        self
+    }
+
+    /// Lets create, restore and import replace a wallet of the same name
+    /// (the replaced file is kept as a backup). Off by default.
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// KDF for new wallets; older ones are re-encrypted with it on unlock.
+    pub fn with_kdf(mut self, kdf: Kdf) -> Self {
+        self.kdf = kdf;
+        self
+    }
+
     pub fn create(&self, name: &str, password: &str) -> Result<Wallet> {
         let kp = generate_ed25519();
-        let address = self.crypto.address_from_public_key(&kp.public_key)?;
-        let (encrypted_secret, nonce) = encrypt_secret(&kp.secret_key, password)?;
+        self.new_single_key(name, kp.public_key, &kp.secret_key, password)
+    }
+
+    /// Saves a single-key wallet holding `secret`.
+    fn new_single_key(
+        &self,
+        name: &str,
+        public_key: Vec<u8>,
+        secret: &[u8],
+        password: &str,
+    ) -> Result<Wallet> {
+        self.check_new(name)?;
+        let address = self.crypto.address_from_public_key(&public_key)?;
+        let (encrypted_secret, nonce) = encrypt_secret(secret, password, &self.kdf)?;
         let wallet = Wallet {
             name: name.to_string(),
             address,
-            public_key: kp.public_key,
+            public_key,
+            encrypted_secret,
+            nonce,
+            kdf: self.kdf,
+            key_location: KeyLocation::File,
+            hd: None,
+            watch: None,
+            multisig: None,
+            multisig_output: None,
+            contacts: Vec::new(),
+            identity: None,
+        };
+        self.save(&wallet)?;
+        Ok(wallet)
+    }
+
+    /// Creates an HD wallet from a fresh 24-word mnemonic, which is returned
+    /// for the user to back up.
+    pub fn create_hd(&self, name: &str, password: &str) -> Result<(Wallet, String)> {
+        let mut entropy = [0u8; 32];
+        OsRng.fill_bytes(&mut entropy);
+        let mnemonic = Mnemonic::from_entropy(&entropy).map_err(|e| anyhow!("mnemonic: {e}"))?;
+        let wallet = self.restore_hd(name, &mnemonic.to_string(), password)?;
+        Ok((wallet, mnemonic.to_string()))
+    }
+
+    /// Recreates an HD wallet from its mnemonic with only the first receive
+    /// address; use [`WalletStore::restore_from_mnemonic`] to find the rest.
+    pub fn restore_hd(&self, name: &str, phrase: &str, password: &str) -> Result<Wallet> {
+        let mnemonic = Mnemonic::parse(phrase).map_err(|e| anyhow!("invalid mnemonic: {e}"))?;
+        self.new_hd(name, &mnemonic.to_seed(""), Some(&mnemonic.to_entropy()), password)
+    }
+
+    /// Saves an HD wallet over `seed` with only its first receive address,
+    /// keeping the mnemonic `entropy` when it is known.
+    fn new_hd(&self, name: &str, seed: &[u8], entropy: Option<&[u8]>, password: &str) -> Result<Wallet> {
+        self.check_new(name)?;
+        let (first, secret) = derive_hd_address(seed, 0, false, 0)?;
+        let (encrypted_secret, nonce) = encrypt_secret(&secret, password, &self.kdf)?;
+        let (encrypted_seed, seed_nonce) = encrypt_secret(seed, password, &self.kdf)?;
+        let (encrypted_entropy, entropy_nonce) = match entropy {
+            Some(entropy) => {
+                let (ciphertext, nonce) = encrypt_secret(entropy, password, &self.kdf)?;
+                (ciphertext, Some(nonce))
+            }
+            None => (Vec::new(), None),
+        };
+        let mut hd = HdState {
+            encrypted_seed,
+            seed_nonce,
+            account: 0,
+            accounts: vec![HdAccount { name: DEFAULT_ACCOUNT.to_string(), index: 0 }],
+            addresses: vec![],
+            encrypted_entropy,
+            entropy_nonce,
+        };
+        hd.record(first.clone());
+        let wallet = Wallet {
+            name: name.to_string(),
+            address: first.address,
+            public_key: first.public_key,
             encrypted_secret,
             nonce,
+            kdf: self.kdf,
+            key_location: KeyLocation::File,
+            hd: Some(hd),
+            watch: None,
+            multisig: None,
+            multisig_output: None,
+            contacts: Vec::new(),
+            identity: None,
         };
-        let path = self.root.join(format!("{name}.json"));
-        fs::write(path, serde_json::to_vec_pretty(&wallet)?)?;
+        self.save(&wallet)?;
         Ok(wallet)
     }
 
+    /// Restores an HD wallet and scans the node for its used addresses.
+    pub async fn restore_from_mnemonic(
+        &self,
+        name: &str,
+        phrase: &str,
+        password: &str,
+        gap_limit: u32,
+    ) -> Result<Wallet> {
+        let mut wallet = self.restore_hd(name, phrase, password)?;
+        self.scan(&mut wallet, password, gap_limit).await?;
+        Ok(wallet)
+    }
+
+    /// Derives, records and saves the next receive or change address of the
+    /// active account.
+    pub fn next_address(&self, wallet: &mut Wallet, password: &str, change: bool) -> Result<HdAddress> {
+        self.upgrade_kdf(wallet, password)?;
+        let seed = self.unlock_seed(wallet, password)?;
+        let hd = wallet
+            .hd
+            .as_mut()
+            .ok_or_else(|| anyhow!("wallet {} is not an HD wallet", wallet.name))?;
+        let index = hd.next_index(hd.account, change);
+        let (address, _) = derive_hd_address(&seed, hd.account, change, index)?;
+        hd.record(address.clone());
+        self.save(wallet)?;
+        Ok(address)
+    }
+
+    /// Walks the receive and change chains of every account until
+    /// `gap_limit` consecutive addresses hold no coins on the node, recording
+    /// every address up to the last one that does. Returns how many used
+    /// addresses were found.
+    pub async fn scan(&self, wallet: &mut Wallet, password: &str, gap_limit: u32) -> Result<usize> {
+        let node = self.node()?;
+        self.upgrade_kdf(wallet, password)?;
+        let seed = self.unlock_seed(wallet, password)?;
+        let hd = wallet
+            .hd
+            .as_mut()
+            .ok_or_else(|| anyhow!("wallet {} is not an HD wallet", wallet.name))?;
+        let mut accounts: Vec<u32> = hd.accounts.iter().map(|a| a.index).collect();
+        if accounts.is_empty() {
+            accounts.push(0);
+        }
+        let mut used = 0;
+        for (account, change) in accounts.into_iter().flat_map(|a| [(a, false), (a, true)]) {
+            let mut derived = Vec::new();
+            let mut last_used = None;
+            let mut index = 0;
+            while index - last_used.map_or(0, |i: u32| i + 1) < gap_limit {
+                let (address, _) = derive_hd_address(&seed, account, change, index)?;
+                if !node.utxos(&address.address).await?.is_empty() {
+                    last_used = Some(index);
+                    used += 1;
+                }
+                derived.push(address);
+                index += 1;
+            }
+            let keep = last_used.map_or(0, |i| i as usize + 1);
+            for address in derived.into_iter().take(keep) {
+                hd.record(address);
+            }
+        }
+        self.save(wallet)?;
+        Ok(used)
+    }
+
+    fn wallet_path(&self, name: &str) -> PathBuf {
+        self.root.join(format!("{name}.json"))
+    }
+
+    fn backup_path(&self, name: &str, generation: usize) -> PathBuf {
+        self.root.join("backups").join(format!("{name}.json.{generation}"))
+    }
+
+    /// Fails if a wallet called `name` exists and overwriting is off.
+    fn check_new(&self, name: &str) -> Result<()> {
+        if !self.overwrite && self.wallet_path(name).exists() {
+            return Err(anyhow!("wallet {name} already exists"));
+        }
+        Ok(())
+    }
+
+    /// Writes the wallet file through a synced temporary file and a rename,
+    /// so a crash leaves either the old or the new file, never a torn one.
+    /// The old file is first rotated into `backups/`.
+    fn save(&self, wallet: &Wallet) -> Result<()> {
+        let path = self.wallet_path(&wallet.name);
+        let tmp = path.with_extension("json.tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(wallet)?)?;
+        file.sync_all()?;
+        drop(file);
+        if path.exists() {
+            self.rotate_backups(&wallet.name, &path)?;
+        }
+        fs::rename(tmp, path)?;
+        #[cfg(unix)]
+        fs::File::open(&self.root)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Shifts `backups/<name>.json.1..N` up by one and copies the current
+    /// file in as generation 1.
+    fn rotate_backups(&self, name: &str, current: &Path) -> Result<()> {
+        if self.backups == 0 {
+            return Ok(());
+        }
+        fs::create_dir_all(self.root.join("backups"))?;
+        for generation in (1..self.backups).rev() {
+            let backup = self.backup_path(name, generation);
+            if backup.exists() {
+                fs::rename(backup, self.backup_path(name, generation + 1))?;
+            }
+        }
+        fs::copy(current, self.backup_path(name, 1))?;
+        Ok(())
+    }
+
+    /// Previous versions of wallet `name`, newest first.
+    pub fn backups(&self, name: &str) -> Result<Vec<Wallet>> {
+        let mut out = Vec::new();
+        for generation in 1..=self.backups {
+            match fs::read(self.backup_path(name, generation)) {
+                Ok(bytes) => out.push(serde_json::from_slice(&bytes)?),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(out)
+    }
+
+    fn node(&self) -> Result<&NodeClient> {
+        self.node
+            .as_ref()
+            .ok_or_else(|| anyhow!("wallet store has no node configured"))
+    }
+
+    fn unlock_seed(&self, wallet: &Wallet, password: &str) -> Result<Vec<u8>> {
+        if let Some(seed) = self.sessions.seed(&wallet.name) {
+            return Ok(seed);
+        }
+        let full = self.hydrate(wallet)?;
+        let hd = full
+            .hd
+            .as_ref()
+            .ok_or_else(|| anyhow!("wallet {} is not an HD wallet", wallet.name))?;
+        let seed = decrypt_secret(&hd.encrypted_seed, &hd.seed_nonce, password, &wallet.kdf)?;
+        self.upgrade_kdf(&mut wallet.clone(), password)?;
+        Ok(seed)
+    }
+
+    /// Re-encrypts `wallet`'s secrets with the store's KDF, and saves it, if
+    /// they were written with a different one.
+    fn upgrade_kdf(&self, wallet: &mut Wallet, password: &str) -> Result<()> {
+        if !wallet.has_secret() || wallet.kdf == self.kdf || self.sessions.is_unlocked(&wallet.name) {
+            return Ok(());
+        }
+        *wallet = self.persist(self.reencrypt(wallet, password, password)?)?;
+        Ok(())
+    }
+
+    /// Changes the password of wallet `name`. Secrets are re-encrypted under
+    /// the store's KDF with fresh salts and nonces, and the file is replaced
+    /// in one rename. Locks the wallet if it was unlocked. Returns the
+    /// wallet as saved.
+    pub fn rekey(&self, name: &str, old_password: &str, new_password: &str) -> Result<Wallet> {
+        let wallet = self.load(name)?;
+        if !wallet.has_secret() {
+            return Err(anyhow!("wallet {} holds no secret", wallet.name));
+        }
+        self.lock(name);
+        self.persist(self.reencrypt(&wallet, old_password, new_password)?)
+    }
+
+    /// Hydrated copy of `wallet` with its secrets encrypted under `new_password`.
+    fn reencrypt(&self, wallet: &Wallet, old_password: &str, new_password: &str) -> Result<Wallet> {
+        let mut out = self.hydrate(wallet)?;
+        let secret = decrypt_secret(&out.encrypted_secret, &wallet.nonce, old_password, &wallet.kdf)?;
+        (out.encrypted_secret, out.nonce) = encrypt_secret(&secret, new_password, &self.kdf)?;
+        if let Some(hd) = out.hd.as_mut() {
+            let seed = decrypt_secret(&hd.encrypted_seed, &hd.seed_nonce, old_password, &wallet.kdf)?;
+            (hd.encrypted_seed, hd.seed_nonce) = encrypt_secret(&seed, new_password, &self.kdf)?;
+            if let Some(nonce) = hd.entropy_nonce {
+                let entropy = decrypt_secret(&hd.encrypted_entropy, &nonce, old_password, &wallet.kdf)?;
+                let (ciphertext, nonce) = encrypt_secret(&entropy, new_password, &self.kdf)?;
+                (hd.encrypted_entropy, hd.entropy_nonce) = (ciphertext, Some(nonce));
+            }
+        }
+        out.kdf = self.kdf;
+        Ok(out)
+    }
+
+    /// `(public, secret)` key pairs for every address of `wallet`.
+    fn unlock_keys(&self, wallet: &Wallet, password: &str) -> Result<HashMap<Address, KeyPair>> {
+        if !wallet.has_secret() {
+            return Err(anyhow!("wallet {} holds no secret", wallet.name));
+        }
+        let Some(hd) = &wallet.hd else {
+            let secret = self.unlock_secret(wallet, password)?;
+            return Ok(HashMap::from([(wallet.address, (wallet.public_key.clone(), secret))]));
+        };
+        let seed = self.unlock_seed(wallet, password)?;
+        hd.addresses
+            .iter()
+            .map(|a| {
+                let secret = hd::derive_secret(&seed, a.account, a.change, a.index)?;
+                Ok((a.address, (a.public_key.clone(), secret.to_vec())))
+            })
+            .collect()
+    }
+
     pub fn list(&self) -> Result<Vec<Wallet>> {
         let mut out = Vec::new();
         for entry in fs::read_dir(&self.root)? {
             let entry = entry?;
-            if entry.file_type()?.is_file() {
-                let bytes = fs::read(entry.path())?;
+            let path = entry.path();
+            if entry.file_type()?.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                let bytes = fs::read(path)?;
                 let wallet: Wallet = serde_json::from_slice(&bytes)?;
                 out.push(wallet);
             }
@@ -64,38 +583,225 @@ impl WalletStore {
     }
 
     pub fn load(&self, name: &str) -> Result<Wallet> {
-        let path = self.root.join(format!("{name}.json"));
-        let bytes = fs::read(path)?;
+        let bytes = fs::read(self.wallet_path(name))?;
         Ok(serde_json::from_slice(&bytes)?)
     }
 
     pub fn unlock_secret(&self, wallet: &Wallet, password: &str) -> Result<Vec<u8>> {
-        decrypt_secret(&wallet.encrypted_secret, &wallet.nonce, password)
+        if !wallet.has_secret() {
+            return Err(anyhow!("wallet {} holds no secret", wallet.name));
+        }
+        if let Some(secret) = self.sessions.secret(&wallet.name) {
+            return Ok(secret);
+        }
+        let full = self.hydrate(wallet)?;
+        let secret = decrypt_secret(&full.encrypted_secret, &wallet.nonce, password, &wallet.kdf)?;
+        self.upgrade_kdf(&mut wallet.clone(), password)?;
+        Ok(secret)
+    }
+
+    /// Sum of the unspent outputs the node reports for `wallet`'s addresses.
+    pub async fn balance(&self, wallet: &Wallet) -> Result<u64> {
+        let node = self.node()?;
+        let mut total = 0u64;
+        for address in wallet.addresses() {
+            for utxo in node.utxos(&address).await? {
+                total = total.saturating_add(utxo.amount);
+            }
+        }
+        Ok(total)
+    }
+
+    /// Pays `recipients` from `wallet`'s coins on the node, returning change
+    /// to the wallet (a fresh change address for HD wallets), and broadcasts
+    /// the signed transaction.
+    pub async fn build_and_sign_tx(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+    ) -> Result<SentTx> {
+        self.send_payment(wallet, password, recipients, fee_rate, None).await
+    }
+
+    /// Pays `recipients`, with an optional memo, and submits the transaction.
+    pub async fn send_payment(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+        memo: Option<String>,
+    ) -> Result<SentTx> {
+        let sources = wallet.spend_addresses();
+        self.send_from(wallet, password, &sources, recipients, fee_rate, memo).await
+    }
+
+    /// Like `send_payment`, spending only coins held by `sources`.
+    async fn send_from(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        sources: &[Address],
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+        memo: Option<String>,
+    ) -> Result<SentTx> {
+        let node = self.node()?;
+        let mut utxos = Vec::new();
+        for address in sources {
+            utxos.extend(node.utxos(address).await?);
+        }
+        let built = self.sign_payment(wallet, password, utxos, recipients, fee_rate, memo)?;
+        self.submit(wallet, built).await
+    }
+
+    /// Submits a signed transaction, tracking the coins it spends as pending.
+    async fn submit(&self, wallet: &Wallet, built: BuiltTx) -> Result<SentTx> {
+        let hash = self.node()?.submit_tx(&built.tx).await?;
+        let mut db = SyncDb::open(self.sync_path(wallet))?;
+        db.track_pending(&built.tx);
+        db.save()?;
+        Ok(SentTx { hash, built })
+    }
+
+    /// Selects from `utxos`, adds change and signs every input, without
+    /// touching the network.
+    pub fn sign_tx(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        utxos: Vec<Utxo>,
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+    ) -> Result<BuiltTx> {
+        let spendable = wallet.spend_addresses();
+        let utxos = utxos.into_iter().filter(|u| spendable.contains(&u.address)).collect();
+        self.sign_payment(wallet, password, utxos, recipients, fee_rate, None)
+    }
+
+    /// What [`WalletStore::send_payment`] would submit, fetching the
+    /// wallet's coins from the node but sending nothing.
+    pub async fn preview_payment(
+        &self,
+        wallet: &Wallet,
+        password: &str,
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+        memo: Option<String>,
+    ) -> Result<BuiltTx> {
+        let node = self.node()?;
+        let mut utxos = Vec::new();
+        for address in wallet.spend_addresses() {
+            utxos.extend(node.utxos(&address).await?);
+        }
+        self.preview_tx(wallet, password, utxos, recipients, fee_rate, memo)
+    }
+
+    /// Builds and signs a payment from `utxos` without saving anything. Change
+    /// goes to the wallet's main address instead of a new HD change address;
+    /// every address is the same size, so the fee and change match the real
+    /// payment's.
+    pub fn preview_tx(
+        &self,
+        wallet: &Wallet,
+        password: &str,
+        utxos: Vec<Utxo>,
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+        memo: Option<String>,
+    ) -> Result<BuiltTx> {
+        let keys = self.unlock_keys(wallet, password)?;
+        let owned = utxos.into_iter().filter(|u| keys.contains_key(&u.address)).collect();
+        self.sign_with(&keys, self.payment(wallet.address, owned, recipients, fee_rate, memo).build()?)
+    }
+
+    fn sign_payment(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        utxos: Vec<Utxo>,
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+        memo: Option<String>,
+    ) -> Result<BuiltTx> {
+        self.upgrade_kdf(wallet, password)?;
+        let keys = self.unlock_keys(wallet, password)?;
+        let owned = utxos.into_iter().filter(|u| keys.contains_key(&u.address)).collect();
+        let change = match wallet.hd {
+            Some(_) => self.next_address(wallet, password, true)?.address,
+            None => wallet.address,
+        };
+        self.sign_with(&keys, self.payment(change, owned, recipients, fee_rate, memo).build()?)
+    }
+
+    fn payment(
+        &self,
+        change: Address,
+        utxos: Vec<Utxo>,
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+        memo: Option<String>,
+    ) -> TxBuilder {
+        let mut builder = TxBuilder::new(change)
+            .utxos(utxos)
+            .fee_rate(fee_rate)
+            .nonce(dxid_core::random_nonce())
+            .selector(self.coin_selector.clone());
+        for (address, amount) in recipients {
+            builder = builder.pay(*address, *amount);
+        }
+        if let Some(memo) = memo {
+            builder = builder.memo(memo);
+        }
+        builder
+    }
+
+    /// Signs a transaction built elsewhere, e.g. by a watch-only copy of
+    /// this wallet. Every input must spend one of `wallet`'s addresses.
+    pub fn sign_built(&self, wallet: &Wallet, password: &str, built: BuiltTx) -> Result<BuiltTx> {
+        let keys = self.unlock_keys(wallet, password)?;
+        self.sign_with(&keys, built)
+    }
+
+    fn sign_with(&self, keys: &HashMap<Address, KeyPair>, mut built: BuiltTx) -> Result<BuiltTx> {
+        let input_keys = built
+            .spent
+            .iter()
+            .map(|u| {
+                keys.get(&u.address)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("no key for input address {}", address_to_string(&u.address)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        sign_inputs_with(&self.crypto, &mut built.tx, &input_keys)?;
+        Ok(built)
     }
 }
 
-fn encrypt_secret(secret: &[u8], password: &str) -> Result<(Vec<u8>, [u8; 12])> {
+fn encrypt_secret(secret: &[u8], password: &str, kdf: &Kdf) -> Result<(Vec<u8>, [u8; 12])> {
     let mut salt = [0u8; 16];
     OsRng.fill_bytes(&mut salt);
-    let mut key = [0u8; 32];
-    pbkdf2_hmac::<sha2::Sha256>(password.as_bytes(), &salt, 10_000, &mut key);
+    let key = kdf.derive_key(password, &salt)?;
     let cipher = Aes256Gcm::new_from_slice(&key)?;
     let mut nonce_bytes = [0u8; 12];
     OsRng.fill_bytes(&mut nonce_bytes);
     let nonce = Nonce::from_slice(&nonce_bytes);
-    let ciphertext = cipher.encrypt(nonce, secret)?;
+    let ciphertext = cipher
+        .encrypt(nonce, secret)
+        .map_err(|e| anyhow!(format!("encrypt failed: {e}")))?;
     let mut out = salt.to_vec();
     out.extend_from_slice(&ciphertext);
     Ok((out, nonce_bytes))
 }
 
-fn decrypt_secret(ciphertext: &[u8], nonce: &[u8; 12], password: &str) -> Result<Vec<u8>> {
+fn decrypt_secret(ciphertext: &[u8], nonce: &[u8; 12], password: &str, kdf: &Kdf) -> Result<Vec<u8>> {
     if ciphertext.len() < 16 {
         return Err(anyhow!("ciphertext too short"));
     }
     let (salt, ct) = ciphertext.split_at(16);
-    let mut key = [0u8; 32];
-    pbkdf2_hmac::<sha2::Sha256>(password.as_bytes(), salt, 10_000, &mut key);
+    let key = kdf.derive_key(password, salt)?;
     let cipher = Aes256Gcm::new_from_slice(&key)?;
     let plaintext = cipher
         .decrypt(Nonce::from_slice(nonce), ct)
@@ -103,6 +809,23 @@ fn decrypt_secret(ciphertext: &[u8], nonce: &[u8; 12], password: &str) -> Result
     Ok(plaintext)
 }
 
+/// The address at `m/44'/COIN_TYPE'/account'/change'/index'` and its secret key.
+fn derive_hd_address(seed: &[u8], account: u32, change: bool, index: u32) -> Result<(HdAddress, [u8; 32])> {
+    let secret = hd::derive_secret(seed, account, change, index)?;
+    let keys = ed25519_from_secret(&secret);
+    let address = build_address_from_public_key(&keys.public_key)?;
+    Ok((
+        HdAddress {
+            address,
+            public_key: keys.public_key,
+            account,
+            change,
+            index,
+        },
+        secret,
+    ))
+}
+
 pub fn build_address_from_public_key(pk: &[u8]) -> Result<Address> {
     DefaultCryptoProvider::new().address_from_public_key(pk)
 }
@@ -119,11 +842,242 @@ pub fn address_from_bech32(s: &str) -> Result<Address> {
 mod tests {
     use super::*;
 
+    /// Store over the shared test directory, where every run recreates the
+    /// same wallet names.
+    pub(crate) fn test_store() -> WalletStore {
+        WalletStore::new(std::env::temp_dir().join("dxid-wallet-test")).unwrap().with_overwrite(true)
+    }
+
     #[test]
     fn wallet_roundtrip() {
-        let store = WalletStore::new(std::env::temp_dir().join("dxid-wallet-test")).unwrap();
+        let store = test_store();
         let wallet = store.create("test", "pass").unwrap();
         let secret = store.unlock_secret(&wallet, "pass").unwrap();
         assert!(!secret.is_empty());
     }
+
+    #[test]
+    fn signed_inputs_verify() {
+        let store = test_store();
+        let mut wallet = store.create("spender", "pass").unwrap();
+        let utxo = Utxo { tx_hash: [7u8; 32], index: 1, address: wallet.address, amount: 100_000 };
+        let built = store
+            .sign_tx(&mut wallet, "pass", vec![utxo], &[([3u8; 32], 40_000)], 1)
+            .unwrap();
+        assert_eq!(built.tx.outputs.len(), 2);
+        let sighash = built.tx.signing_hash();
+        let input = &built.tx.inputs[0];
+        let msg = dxid_core::signing_message(input, &sighash);
+        assert!(DefaultCryptoProvider::new()
+            .verify_signature(&input.public_key, &msg, &input.signature)
+            .unwrap());
+        assert!(store.sign_tx(&mut wallet, "wrong", vec![], &[([3u8; 32], 1)], 1).is_err());
+    }
+
+    #[test]
+    fn watch_only_wallet_tracks_without_secrets() {
+        let store = test_store();
+        let (mut signer, _) = store.create_hd("cold", "pass").unwrap();
+        store.next_address(&mut signer, "pass", false).unwrap();
+
+        let xpub = store.export_xpub(&signer).unwrap();
+        let watch = store.import_watch_only("cold-watch", WatchSource::Xpub(xpub)).unwrap();
+        assert_eq!(watch.addresses(), signer.addresses());
+        assert!(watch.encrypted_secret.is_empty());
+        assert!(store.unlock_secret(&watch, "pass").is_err());
+
+        let utxo = Utxo { tx_hash: [4u8; 32], index: 0, address: watch.addresses()[1], amount: 50_000 };
+        let unsigned = TxBuilder::new(watch.address)
+            .utxos(vec![utxo])
+            .pay([3u8; 32], 10_000)
+            .build()
+            .unwrap();
+        assert!(store.sign_built(&watch, "pass", unsigned.clone()).is_err());
+        let signed = store.sign_built(&signer, "pass", unsigned).unwrap();
+        assert_eq!(signed.tx.inputs[0].public_key, signer.hd.unwrap().addresses[1].public_key);
+
+        let by_address = store
+            .import_watch_only("addr-watch", WatchSource::Addresses(vec![[5u8; 32]]))
+            .unwrap();
+        assert_eq!(by_address.addresses(), vec![[5u8; 32]]);
+        assert!(store.import_watch_only("bad", WatchSource::Xpub("dxpubnope".into())).is_err());
+    }
+
+    #[test]
+    fn legacy_pbkdf2_wallets_upgrade_on_unlock() {
+        let root = std::env::temp_dir().join("dxid-wallet-test");
+        let legacy = WalletStore::new(root.clone()).unwrap().with_overwrite(true).with_kdf(Kdf::legacy());
+        let wallet = legacy.create("legacy-kdf", "pass").unwrap();
+        let secret = legacy.unlock_secret(&wallet, "pass").unwrap();
+
+        let mut json = serde_json::to_value(&wallet).unwrap();
+        json.as_object_mut().unwrap().remove("kdf");
+        let old: Wallet = serde_json::from_value(json).unwrap();
+        assert_eq!(old.kdf, Kdf::legacy());
+
+        let store = WalletStore::new(root).unwrap();
+        assert!(store.unlock_secret(&old, "wrong").is_err());
+        assert_eq!(store.unlock_secret(&old, "pass").unwrap(), secret);
+        let upgraded = store.load("legacy-kdf").unwrap();
+        assert_eq!(upgraded.kdf, Kdf::default());
+        assert_ne!(upgraded.encrypted_secret, wallet.encrypted_secret);
+        assert_eq!(store.unlock_secret(&upgraded, "pass").unwrap(), secret);
+    }
+
+    #[test]
+    fn saves_keep_backups_and_refuse_overwrites() {
+        let root = std::env::temp_dir().join(format!("dxid-wallet-backups-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let store = WalletStore::new(root.clone()).unwrap().with_backups(2);
+        let first = store.create("kept", "pass").unwrap();
+        assert!(store.create("kept", "pass").is_err());
+        assert!(store.backups("kept").unwrap().is_empty());
+
+        let store = store.with_overwrite(true);
+        let second = store.create("kept", "pass").unwrap();
+        let third = store.create("kept", "pass").unwrap();
+        store.create("kept", "pass").unwrap();
+        let backups: Vec<Address> = store.backups("kept").unwrap().iter().map(|w| w.address).collect();
+        assert_eq!(backups, vec![third.address, second.address]);
+        assert!(!backups.contains(&first.address));
+        assert_eq!(store.list().unwrap().len(), 1);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn rekey_changes_the_password() {
+        let store = test_store();
+        let (wallet, _) = store.create_hd("rekeyed", "old").unwrap();
+        let secret = store.unlock_secret(&wallet, "old").unwrap();
+
+        assert!(store.rekey("rekeyed", "wrong", "new").is_err());
+        let rekeyed = store.rekey("rekeyed", "old", "new").unwrap();
+        assert_ne!(rekeyed.encrypted_secret[..16], wallet.encrypted_secret[..16]);
+        let on_disk = store.load("rekeyed").unwrap();
+        assert!(store.unlock_secret(&on_disk, "old").is_err());
+        assert_eq!(store.unlock_secret(&on_disk, "new").unwrap(), secret);
+        assert_eq!(store.unlock_seed(&on_disk, "new").unwrap(), store.unlock_seed(&wallet, "old").unwrap());
+    }
+
+    #[test]
+    fn backend_wallets_keep_no_ciphertext_on_disk() {
+        let backend = Arc::new(MemoryKeyBackend::default());
+        let store = test_store().with_key_backend(backend.clone());
+        let (mut wallet, _) = store.create_hd("keyring-hd", "pass").unwrap();
+        let secret = store.unlock_secret(&wallet, "pass").unwrap();
+
+        store.set_key_location(&mut wallet, KeyLocation::Backend).unwrap();
+        let on_disk = store.load("keyring-hd").unwrap();
+        assert!(on_disk.encrypted_secret.is_empty());
+        assert!(on_disk.hd.as_ref().unwrap().encrypted_seed.is_empty());
+        assert_eq!(store.unlock_secret(&on_disk, "pass").unwrap(), secret);
+        store.next_address(&mut wallet, "pass", false).unwrap();
+
+        store.set_key_location(&mut wallet, KeyLocation::File).unwrap();
+        assert!(backend.get("keyring-hd", "secret").is_err());
+        assert_eq!(store.unlock_secret(&store.load("keyring-hd").unwrap(), "pass").unwrap(), secret);
+    }
+
+    #[test]
+    fn multisig_needs_threshold_cosigners() {
+        let store = test_store();
+        let cosigners: Vec<Wallet> =
+            (0..3).map(|i| store.create(&format!("cosigner-{i}"), "pass").unwrap()).collect();
+        let keys = cosigners.iter().map(|w| w.public_key.clone()).collect();
+        let shared = store.create_multisig("shared", 2, keys).unwrap();
+        let policy = shared.multisig.clone().unwrap();
+        assert_eq!(parse_descriptor(&descriptor(&policy)).unwrap(), policy);
+        assert!(store.unlock_secret(&shared, "pass").is_err());
+
+        let utxo = Utxo { tx_hash: [6u8; 32], index: 0, address: shared.address, amount: 90_000 };
+        let built = TxBuilder::new(shared.address)
+            .utxos(vec![utxo])
+            .pay([3u8; 32], 20_000)
+            .build()
+            .unwrap();
+        let mut first = PartiallySignedTx::new(policy.clone(), built).unwrap();
+        let mut second = first.clone();
+        store.cosign(&cosigners[0], "pass", &mut first).unwrap();
+        assert!(first.clone().finalize().is_err());
+        store.cosign(&cosigners[2], "pass", &mut second).unwrap();
+        first.merge(&second).unwrap();
+        assert!(first.is_complete());
+
+        let tx = first.finalize().unwrap();
+        let input = &tx.inputs[0];
+        let msg = dxid_core::signing_message(input, &tx.signing_hash());
+        assert_eq!(MultisigPolicy::decode(&input.public_key), Some(policy.clone()));
+        assert!(policy
+            .verify(&DefaultCryptoProvider::new(), &msg, &input.signature)
+            .unwrap());
+    }
+
+    #[test]
+    fn multisig_output_wallet_is_signed_by_its_cosigners_keys() {
+        let store = test_store();
+        let cosigners: Vec<Wallet> =
+            (0..3).map(|i| store.create(&format!("cosigner-{i}"), "pass").unwrap()).collect();
+        let addresses = cosigners.iter().map(|w| w.address).collect();
+        let shared = store.create_multisig_output("shared", 2, addresses).unwrap();
+        let output = shared.multisig_output.clone().unwrap();
+        assert_eq!(shared.address, output.address());
+        assert_eq!(parse_output_descriptor(&output_descriptor(&output)).unwrap(), output);
+        assert!(!shared.has_secret());
+        let outsider = store.create("outsider", "pass").unwrap();
+
+        let utxo = Utxo { tx_hash: [6u8; 32], index: 0, address: shared.address, amount: 90_000 };
+        let built = TxBuilder::new(shared.address)
+            .utxos(vec![utxo])
+            .pay([3u8; 32], 20_000)
+            .build()
+            .unwrap();
+        let mut first = PartiallySignedTx::new(output.clone(), built).unwrap();
+        let mut second = first.clone();
+        assert!(store.cosign(&outsider, "pass", &mut first).is_err());
+        store.cosign(&cosigners[1], "pass", &mut first).unwrap();
+        assert!(first.clone().finalize().is_err());
+        store.cosign(&cosigners[2], "pass", &mut second).unwrap();
+        first.merge(&second).unwrap();
+
+        let tx = first.finalize().unwrap();
+        let input = &tx.inputs[0];
+        let msg = dxid_core::signing_message(input, &tx.signing_hash());
+        assert_eq!(MultisigOutput::decode(&input.public_key), Some(output.clone()));
+        assert!(output
+            .verify(&DefaultCryptoProvider::new(), &msg, &input.signature)
+            .unwrap());
+    }
+
+    #[test]
+    fn hd_wallet_restores_and_spends_from_derived_addresses() {
+        let store = test_store();
+        let (mut wallet, phrase) = store.create_hd("hd", "pass").unwrap();
+        assert_eq!(phrase.split_whitespace().count(), 24);
+        let second = store.next_address(&mut wallet, "pass", false).unwrap();
+        assert_eq!((second.index, second.change), (1, false));
+
+        let restored = store.restore_hd("hd-restored", &phrase, "other").unwrap();
+        assert_eq!(restored.address, wallet.address);
+        assert!(store.restore_hd("bad", "not a mnemonic", "pass").is_err());
+
+        let utxos = vec![
+            Utxo { tx_hash: [1u8; 32], index: 0, address: wallet.address, amount: 30_000 },
+            Utxo { tx_hash: [2u8; 32], index: 0, address: second.address, amount: 30_000 },
+        ];
+        let preview =
+            store.preview_tx(&wallet, "pass", utxos.clone(), &[([3u8; 32], 50_000)], 1, None).unwrap();
+        assert_eq!(wallet.hd.as_ref().unwrap().addresses.len(), 2, "a preview reserves no change address");
+        let built = store.sign_tx(&mut wallet, "pass", utxos, &[([3u8; 32], 50_000)], 1).unwrap();
+        assert_eq!((preview.fee, preview.change), (built.fee, built.change));
+        let change = &wallet.hd.as_ref().unwrap().addresses[2];
+        assert_eq!((change.change, change.index), (true, 0));
+        assert_eq!(built.tx.outputs[1].address, change.address);
+        let sighash = built.tx.signing_hash();
+        for input in &built.tx.inputs {
+            let msg = dxid_core::signing_message(input, &sighash);
+            assert!(DefaultCryptoProvider::new()
+                .verify_signature(&input.public_key, &msg, &input.signature)
+                .unwrap());
+        }
+    }
 }
@@ -0,0 +1,106 @@
+//! Off-chain proof of address ownership: an ed25519 signature over
+//! domain-separated text, so it can never be replayed as a transaction
+//! signature.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use dxid_core::{Address, CryptoProvider};
+use dxid_crypto::{address_to_string, DefaultCryptoProvider};
+
+use crate::{build_address_from_public_key, Wallet, WalletStore};
+
+const MESSAGE_DOMAIN: &[u8] = b"dxid/signed-message/v1";
+const PUBLIC_KEY_LEN: usize = 32;
+
+/// A signature together with the public key that made it, written as one
+/// base58 string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSignature {
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl fmt::Display for MessageSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = self.public_key.clone();
+        bytes.extend_from_slice(&self.signature);
+        f.write_str(&bs58::encode(bytes).into_string())
+    }
+}
+
+impl FromStr for MessageSignature {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = bs58::decode(s.trim()).into_vec().map_err(|e| anyhow!("bad signature encoding: {e}"))?;
+        if bytes.len() <= PUBLIC_KEY_LEN {
+            return Err(anyhow!("signature too short"));
+        }
+        let (public_key, signature) = bytes.split_at(PUBLIC_KEY_LEN);
+        Ok(Self { public_key: public_key.to_vec(), signature: signature.to_vec() })
+    }
+}
+
+/// Bytes actually signed: the domain tag, the claimed address and the text.
+pub fn message_payload(address: &Address, message: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(MESSAGE_DOMAIN.len() + 40 + message.len());
+    out.extend_from_slice(MESSAGE_DOMAIN);
+    out.extend_from_slice(address);
+    out.extend_from_slice(&(message.len() as u64).to_le_bytes());
+    out.extend_from_slice(message.as_bytes());
+    out
+}
+
+/// Whether `signature` was made over `message` by the key behind `address`.
+pub fn verify_message(address: &Address, message: &str, signature: &MessageSignature) -> Result<bool> {
+    if build_address_from_public_key(&signature.public_key)? != *address {
+        return Ok(false);
+    }
+    DefaultCryptoProvider::new().verify_signature(
+        &signature.public_key,
+        &message_payload(address, message),
+        &signature.signature,
+    )
+}
+
+impl WalletStore {
+    /// Signs `message` with the key of `address`, or of the wallet's main
+    /// address when `None`.
+    pub fn sign_message(
+        &self,
+        wallet: &Wallet,
+        password: &str,
+        address: Option<Address>,
+        message: &str,
+    ) -> Result<MessageSignature> {
+        let address = address.unwrap_or(wallet.address);
+        let keys = self.unlock_keys(wallet, password)?;
+        let (public_key, secret_key) = keys.get(&address).ok_or_else(|| {
+            anyhow!("wallet {} has no key for {}", wallet.name, address_to_string(&address))
+        })?;
+        let signature = self.crypto.sign_message(secret_key, &message_payload(&address, message))?;
+        Ok(MessageSignature { public_key: public_key.clone(), signature })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proves_address_ownership() {
+        let store = crate::tests::test_store();
+        let wallet = store.create("message-signer", "pass").unwrap();
+        let other = store.create("message-other", "pass").unwrap();
+        let text = "I control this address for the 2026 council vote";
+
+        let sig = store.sign_message(&wallet, "pass", None, text).unwrap();
+        let parsed: MessageSignature = sig.to_string().parse().unwrap();
+        assert!(verify_message(&wallet.address, text, &parsed).unwrap());
+        assert!(!verify_message(&wallet.address, "something else", &parsed).unwrap());
+        assert!(!verify_message(&other.address, text, &parsed).unwrap());
+        assert!(store.sign_message(&wallet, "pass", Some(other.address), text).is_err());
+    }
+}
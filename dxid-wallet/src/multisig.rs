@@ -0,0 +1,306 @@
+//! M-of-N shared wallets and partially signed transactions.
+//!
+//! A multisig wallet is a descriptor, `multi(<m>,<hex key>,...)`, built from
+//! the cosigners' public keys. Any cosigner proposes a transaction, each one
+//! adds signatures with [`WalletStore::cosign`], the copies are merged, and
+//! the transaction is finalized once `m` signatures are present on every
+//! input. A wallet may instead name its cosigners by address,
+//! `multi_addr(<m>,<address>,...)`, paying to a [`MultisigOutput`]; each
+//! cosigner then reveals its key with its signatures.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use dxid_core::builder::BuiltTx;
+use dxid_core::multisig::{encode_cosignatures, encode_signatures, MultisigOutput, MultisigPolicy};
+use dxid_core::{signing_message, Address, CryptoProvider, Transaction};
+use dxid_crypto::{address_from_string, address_to_string};
+use serde::{Deserialize, Serialize};
+
+use crate::{KeyLocation, Wallet, WalletStore};
+
+/// `multi(<threshold>,<hex key>,...)` for `policy`.
+pub fn descriptor(policy: &MultisigPolicy) -> String {
+    let keys: Vec<String> = policy.public_keys.iter().map(hex::encode).collect();
+    format!("multi({},{})", policy.threshold, keys.join(","))
+}
+
+pub fn parse_descriptor(descriptor: &str) -> Result<MultisigPolicy> {
+    let body = descriptor
+        .trim()
+        .strip_prefix("multi(")
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| anyhow!("expected multi(<m>,<key>,...)"))?;
+    let mut parts = body.split(',').map(str::trim);
+    let threshold = parts.next().unwrap_or_default().parse::<u8>()?;
+    let keys = parts.map(hex::decode).collect::<Result<Vec<_>, _>>()?;
+    MultisigPolicy::new(threshold, keys)
+}
+
+/// `multi_addr(<threshold>,<address>,...)` for `output`.
+pub fn output_descriptor(output: &MultisigOutput) -> String {
+    let addresses: Vec<String> = output.cosigners.iter().map(address_to_string).collect();
+    format!("multi_addr({},{})", output.threshold, addresses.join(","))
+}
+
+pub fn parse_output_descriptor(descriptor: &str) -> Result<MultisigOutput> {
+    let body = descriptor
+        .trim()
+        .strip_prefix("multi_addr(")
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| anyhow!("expected multi_addr(<m>,<address>,...)"))?;
+    let mut parts = body.split(',').map(str::trim);
+    let threshold = parts.next().unwrap_or_default().parse::<u8>()?;
+    let addresses = parts.map(address_from_string).collect::<Result<Vec<_>>>()?;
+    MultisigOutput::new(threshold, addresses)
+}
+
+/// Who cosigns a shared wallet's spends.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Cosigners {
+    /// A policy of cosigner keys.
+    Keys(MultisigPolicy),
+    /// An output committing to cosigner addresses.
+    Addresses(MultisigOutput),
+}
+
+impl Cosigners {
+    pub fn address(&self) -> Address {
+        match self {
+            Cosigners::Keys(policy) => policy.address(),
+            Cosigners::Addresses(output) => output.address(),
+        }
+    }
+
+    pub fn threshold(&self) -> u8 {
+        match self {
+            Cosigners::Keys(policy) => policy.threshold,
+            Cosigners::Addresses(output) => output.threshold,
+        }
+    }
+
+    /// What the spending inputs reveal in `public_key`.
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Cosigners::Keys(policy) => policy.encode(),
+            Cosigners::Addresses(output) => output.encode(),
+        }
+    }
+
+    /// Index of the cosigner signing with `public_key`, if it is one.
+    pub fn index<C: CryptoProvider>(&self, crypto: &C, public_key: &[u8]) -> Option<u8> {
+        match self {
+            Cosigners::Keys(policy) => policy.key_index(public_key),
+            Cosigners::Addresses(output) => {
+                output.cosigner_index(&crypto.address_from_public_key(public_key).ok()?)
+            }
+        }
+    }
+}
+
+impl From<MultisigPolicy> for Cosigners {
+    fn from(policy: MultisigPolicy) -> Self {
+        Cosigners::Keys(policy)
+    }
+}
+
+impl From<MultisigOutput> for Cosigners {
+    fn from(output: MultisigOutput) -> Self {
+        Cosigners::Addresses(output)
+    }
+}
+
+/// A multisig spend being passed between cosigners.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartiallySignedTx {
+    pub built: BuiltTx,
+    pub cosigners: Cosigners,
+    /// Per input, signatures collected so far keyed by cosigner index.
+    pub signatures: Vec<BTreeMap<u8, Vec<u8>>>,
+    /// Keys the signing cosigners revealed, for a [`MultisigOutput`].
+    #[serde(default)]
+    pub keys: BTreeMap<u8, Vec<u8>>,
+}
+
+impl PartiallySignedTx {
+    /// Wraps a transaction whose inputs all spend the cosigners' address.
+    pub fn new(cosigners: impl Into<Cosigners>, mut built: BuiltTx) -> Result<Self> {
+        let cosigners = cosigners.into();
+        let address = cosigners.address();
+        if built.spent.iter().any(|utxo| utxo.address != address) {
+            return Err(anyhow!("transaction spends coins outside the multisig address"));
+        }
+        // The policy is part of what every cosigner signs.
+        let witness = cosigners.encode();
+        for input in &mut built.tx.inputs {
+            input.public_key = witness.clone();
+            input.signature.clear();
+        }
+        let signatures = vec![BTreeMap::new(); built.tx.inputs.len()];
+        Ok(Self { built, cosigners, signatures, keys: BTreeMap::new() })
+    }
+
+    /// Adds signatures from one cosigner key to every input.
+    pub fn sign<C: CryptoProvider>(
+        &mut self,
+        crypto: &C,
+        public_key: &[u8],
+        secret_key: &[u8],
+    ) -> Result<()> {
+        let index = self
+            .cosigners
+            .index(crypto, public_key)
+            .ok_or_else(|| anyhow!("key is not a cosigner of this wallet"))?;
+        let sighash = self.built.tx.signing_hash();
+        for (input, collected) in self.built.tx.inputs.iter().zip(&mut self.signatures) {
+            collected.insert(index, crypto.sign_message(secret_key, &signing_message(input, &sighash))?);
+        }
+        if let Cosigners::Addresses(_) = self.cosigners {
+            self.keys.insert(index, public_key.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Takes the signatures another cosigner added to a copy of this transaction.
+    pub fn merge(&mut self, other: &PartiallySignedTx) -> Result<()> {
+        let same_tx = other.built.tx.signing_hash() == self.built.tx.signing_hash();
+        if other.cosigners != self.cosigners || !same_tx {
+            return Err(anyhow!("cannot merge signatures for a different transaction"));
+        }
+        for (mine, theirs) in self.signatures.iter_mut().zip(&other.signatures) {
+            for (index, sig) in theirs {
+                mine.entry(*index).or_insert_with(|| sig.clone());
+            }
+        }
+        for (index, key) in &other.keys {
+            self.keys.entry(*index).or_insert_with(|| key.clone());
+        }
+        Ok(())
+    }
+
+    /// Signatures present on every input.
+    pub fn collected(&self) -> usize {
+        self.signatures.iter().map(BTreeMap::len).min().unwrap_or(0)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.collected() >= self.cosigners.threshold() as usize
+    }
+
+    /// Writes `threshold` signatures into each input's witness, with their
+    /// keys for a [`MultisigOutput`].
+    pub fn finalize(self) -> Result<Transaction> {
+        let threshold = self.cosigners.threshold() as usize;
+        if !self.is_complete() {
+            return Err(anyhow!("{} of {threshold} signatures collected", self.collected()));
+        }
+        let mut tx = self.built.tx;
+        for (input, collected) in tx.inputs.iter_mut().zip(self.signatures) {
+            let chosen = collected.into_iter().take(threshold);
+            input.signature = match self.cosigners {
+                Cosigners::Keys(_) => encode_signatures(&chosen.collect::<Vec<_>>()),
+                Cosigners::Addresses(_) => {
+                    let signed = chosen
+                        .map(|(index, sig)| {
+                            let key = self
+                                .keys
+                                .get(&index)
+                                .ok_or_else(|| anyhow!("no key for cosigner {index}"))?;
+                            Ok((index, key.clone(), sig))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    encode_cosignatures(&signed)
+                }
+            };
+        }
+        Ok(tx)
+    }
+}
+
+impl WalletStore {
+    /// Saves a shared wallet for `cosigners`; it holds no secrets itself.
+    pub fn create_multisig(&self, name: &str, threshold: u8, cosigners: Vec<Vec<u8>>) -> Result<Wallet> {
+        self.check_new(name)?;
+        let policy = MultisigPolicy::new(threshold, cosigners)?;
+        let wallet = Wallet {
+            name: name.to_string(),
+            address: policy.address(),
+            public_key: vec![],
+            encrypted_secret: vec![],
+            nonce: [0u8; 12],
+            kdf: self.kdf,
+            key_location: KeyLocation::File,
+            hd: None,
+            watch: None,
+            multisig: Some(policy),
+            multisig_output: None,
+            contacts: Vec::new(),
+            identity: None,
+        };
+        self.save(&wallet)?;
+        Ok(wallet)
+    }
+
+    /// Saves a shared wallet paying to a [`MultisigOutput`] over the
+    /// cosigners' addresses; it holds no secrets itself.
+    pub fn create_multisig_output(
+        &self,
+        name: &str,
+        threshold: u8,
+        cosigners: Vec<Address>,
+    ) -> Result<Wallet> {
+        self.check_new(name)?;
+        let output = MultisigOutput::new(threshold, cosigners)?;
+        let wallet = Wallet {
+            name: name.to_string(),
+            address: output.address(),
+            public_key: vec![],
+            encrypted_secret: vec![],
+            nonce: [0u8; 12],
+            kdf: self.kdf,
+            key_location: KeyLocation::File,
+            hd: None,
+            watch: None,
+            multisig: None,
+            multisig_output: Some(output),
+            contacts: Vec::new(),
+            identity: None,
+        };
+        self.save(&wallet)?;
+        Ok(wallet)
+    }
+
+    /// Builds an unsigned spend from the shared wallet for cosigners to sign.
+    pub async fn propose_multisig_tx(
+        &self,
+        wallet: &Wallet,
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+    ) -> Result<PartiallySignedTx> {
+        let cosigners = match (&wallet.multisig, &wallet.multisig_output) {
+            (Some(policy), _) => Cosigners::Keys(policy.clone()),
+            (None, Some(output)) => Cosigners::Addresses(output.clone()),
+            (None, None) => return Err(anyhow!("wallet {} is not a multisig wallet", wallet.name)),
+        };
+        let built = self.build_unsigned_tx(wallet, recipients, fee_rate).await?;
+        PartiallySignedTx::new(cosigners, built)
+    }
+
+    /// Signs `psbt` with every key of `signer` that is a cosigner; returns how
+    /// many keys signed.
+    pub fn cosign(&self, signer: &Wallet, password: &str, psbt: &mut PartiallySignedTx) -> Result<usize> {
+        let keys = self.unlock_keys(signer, password)?;
+        let mut signed = 0;
+        for (public_key, secret_key) in keys.values() {
+            if psbt.cosigners.index(&self.crypto, public_key).is_some() {
+                psbt.sign(&self.crypto, public_key, secret_key)?;
+                signed += 1;
+            }
+        }
+        if signed == 0 {
+            return Err(anyhow!("wallet {} holds no cosigner key", signer.name));
+        }
+        Ok(signed)
+    }
+}
@@ -0,0 +1,151 @@
+//! Unlocked wallets kept in memory for a while, so a long-running frontend
+//! asks for the password once rather than on every operation.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use zeroize::Zeroizing;
+
+use crate::{Wallet, WalletStore};
+
+/// Idle time after which an unlocked wallet locks itself.
+pub const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(300);
+
+struct Session {
+    secret: Zeroizing<Vec<u8>>,
+    seed: Option<Zeroizing<Vec<u8>>>,
+    last_used: Instant,
+}
+
+/// Decrypted wallet secrets by wallet name. Secrets are wiped when a session
+/// is locked, expires or is replaced.
+pub(crate) struct SessionCache {
+    timeout: Duration,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl SessionCache {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self { timeout, sessions: Mutex::new(HashMap::new()) }
+    }
+
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn insert(&self, name: &str, secret: Vec<u8>, seed: Option<Vec<u8>>) {
+        let session = Session {
+            secret: Zeroizing::new(secret),
+            seed: seed.map(Zeroizing::new),
+            last_used: Instant::now(),
+        };
+        self.sessions.lock().insert(name.to_string(), session);
+    }
+
+    /// Runs `f` on a live session, refreshing its idle timer.
+    fn with<T>(&self, name: &str, f: impl FnOnce(&Session) -> T) -> Option<T> {
+        let mut sessions = self.sessions.lock();
+        let now = Instant::now();
+        sessions.retain(|_, s| now.duration_since(s.last_used) < self.timeout);
+        let session = sessions.get_mut(name)?;
+        session.last_used = now;
+        Some(f(session))
+    }
+
+    pub(crate) fn secret(&self, name: &str) -> Option<Vec<u8>> {
+        self.with(name, |s| s.secret.to_vec())
+    }
+
+    pub(crate) fn seed(&self, name: &str) -> Option<Vec<u8>> {
+        self.with(name, |s| s.seed.as_ref().map(|seed| seed.to_vec())).flatten()
+    }
+
+    pub(crate) fn is_unlocked(&self, name: &str) -> bool {
+        self.with(name, |_| ()).is_some()
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> bool {
+        self.sessions.lock().remove(name).is_some()
+    }
+
+    fn clear(&self) {
+        self.sessions.lock().clear();
+    }
+
+    fn expire(&self) {
+        let now = Instant::now();
+        self.sessions.lock().retain(|_, s| now.duration_since(s.last_used) < self.timeout);
+    }
+}
+
+impl WalletStore {
+    /// Idle time after which unlocked wallets lock again.
+    pub fn with_session_timeout(mut self, timeout: Duration) -> Self {
+        self.sessions.set_timeout(timeout);
+        self
+    }
+
+    /// Checks `password` and keeps `wallet`'s secrets in memory until
+    /// [`WalletStore::lock`] or the session timeout. While unlocked,
+    /// operations on the wallet use the cached secrets and do not check
+    /// the password they are given.
+    pub fn unlock(&self, wallet: &Wallet, password: &str) -> Result<()> {
+        if !wallet.has_secret() {
+            return Err(anyhow!("wallet {} holds no secret", wallet.name));
+        }
+        self.sessions.remove(&wallet.name);
+        let secret = self.unlock_secret(wallet, password)?;
+        let seed = match wallet.hd {
+            Some(_) => Some(self.unlock_seed(wallet, password)?),
+            None => None,
+        };
+        self.sessions.insert(&wallet.name, secret, seed);
+        Ok(())
+    }
+
+    /// Wipes the cached secrets of wallet `name`. Returns whether it was unlocked.
+    pub fn lock(&self, name: &str) -> bool {
+        self.sessions.remove(name)
+    }
+
+    pub fn lock_all(&self) {
+        self.sessions.clear();
+    }
+
+    /// Wipes sessions past their timeout. Expiry is also checked on every
+    /// use, so calling this on a timer only shortens how long idle secrets
+    /// stay in memory.
+    pub fn lock_expired(&self) {
+        self.sessions.expire();
+    }
+
+    pub fn is_unlocked(&self, name: &str) -> bool {
+        self.sessions.is_unlocked(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_skip_the_password_until_locked() {
+        let store = crate::tests::test_store();
+        let (mut wallet, _) = store.create_hd("session-owner", "pass").unwrap();
+        let secret = store.unlock_secret(&wallet, "pass").unwrap();
+
+        assert!(store.unlock(&wallet, "wrong").is_err());
+        store.unlock(&wallet, "pass").unwrap();
+        assert_eq!(store.unlock_secret(&wallet, "").unwrap(), secret);
+        store.next_address(&mut wallet, "", false).unwrap();
+
+        assert!(store.lock("session-owner"));
+        assert!(store.unlock_secret(&wallet, "").is_err());
+
+        let store = store.with_session_timeout(Duration::ZERO);
+        store.unlock(&wallet, "pass").unwrap();
+        assert!(!store.is_unlocked("session-owner"));
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use dxid_core::builder::BuiltTx;
+use dxid_core::{signing_message, CryptoProvider, Transaction};
+use dxid_crypto::DefaultCryptoProvider;
+
+/// Signs transaction inputs for one key, wherever that key is held.
+#[async_trait]
+pub trait TxSigner: Send + Sync {
+    fn public_key(&self) -> &[u8];
+
+    /// Signs `message`, the signing message of `tx.inputs[input]`. `tx` is
+    /// passed so signers with a screen can show what is being paid.
+    async fn sign_input(&self, tx: &Transaction, input: usize, message: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// A key held in host memory.
+pub struct LocalSigner {
+    public_key: Vec<u8>,
+    secret_key: Vec<u8>,
+}
+
+impl LocalSigner {
+    pub fn new(public_key: Vec<u8>, secret_key: Vec<u8>) -> Self {
+        Self { public_key, secret_key }
+    }
+}
+
+#[async_trait]
+impl TxSigner for LocalSigner {
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    async fn sign_input(&self, _tx: &Transaction, _input: usize, message: &[u8]) -> Result<Vec<u8>> {
+        DefaultCryptoProvider::new().sign_message(&self.secret_key, message)
+    }
+}
+
+/// Signs every input of `built` with `signer`, checking each signature
+/// before accepting it.
+pub async fn sign_built_with(signer: &dyn TxSigner, mut built: BuiltTx) -> Result<BuiltTx> {
+    let public_key = signer.public_key().to_vec();
+    for input in &mut built.tx.inputs {
+        input.public_key = public_key.clone();
+    }
+    let sighash = built.tx.signing_hash();
+    let crypto = DefaultCryptoProvider::new();
+    for index in 0..built.tx.inputs.len() {
+        let message = signing_message(&built.tx.inputs[index], &sighash);
+        let signature = signer.sign_input(&built.tx, index, &message).await?;
+        if !crypto.verify_signature(&public_key, &message, &signature)? {
+            return Err(anyhow!("signer returned an invalid signature for input {index}"));
+        }
+        built.tx.inputs[index].signature = signature;
+    }
+    Ok(built)
+}
@@ -0,0 +1,108 @@
+//! Staking from a wallet. The wallet's main address owns its stake and
+//! funds every staking transaction, so the chain can tie the action to a
+//! key that signed it.
+
+use anyhow::{anyhow, Result};
+use dxid_core::staking::{staking_pool_address, StakeAction, StakeMemo, StakingPosition};
+use dxid_core::Address;
+
+use crate::{SentTx, Wallet, WalletStore};
+
+impl WalletStore {
+    /// Bonds `amount` to the wallet's own validator.
+    pub async fn stake(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        amount: u64,
+        fee_rate: u64,
+    ) -> Result<SentTx> {
+        self.send_staking(wallet, password, StakeAction::Stake, amount, fee_rate).await
+    }
+
+    /// Bonds `amount` to `validator`.
+    pub async fn delegate(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        validator: Address,
+        amount: u64,
+        fee_rate: u64,
+    ) -> Result<SentTx> {
+        self.send_staking(wallet, password, StakeAction::Delegate { validator }, amount, fee_rate).await
+    }
+
+    /// Starts unbonding `amount` from `validator`; it can be withdrawn once
+    /// the unbonding period has passed.
+    pub async fn unstake(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        validator: Address,
+        amount: u64,
+        fee_rate: u64,
+    ) -> Result<SentTx> {
+        self.send_staking(wallet, password, StakeAction::Unstake { validator, amount }, 0, fee_rate).await
+    }
+
+    /// Withdraws pending rewards and fully unbonded stake to the main address.
+    pub async fn withdraw_rewards(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        fee_rate: u64,
+    ) -> Result<SentTx> {
+        let position = self.staking_position(wallet).await?;
+        if position.withdrawable() == 0 {
+            return Err(anyhow!("wallet {} has nothing to withdraw", wallet.name));
+        }
+        self.send_staking(wallet, password, StakeAction::WithdrawRewards, 0, fee_rate).await
+    }
+
+    /// The wallet's bonds, unbonding timeline and pending rewards.
+    pub async fn staking_position(&self, wallet: &Wallet) -> Result<StakingPosition> {
+        self.node()?.staking_position(&wallet.address).await
+    }
+
+    async fn send_staking(
+        &self,
+        wallet: &mut Wallet,
+        password: &str,
+        action: StakeAction,
+        pooled: u64,
+        fee_rate: u64,
+    ) -> Result<SentTx> {
+        if !wallet.has_secret() {
+            return Err(anyhow!("wallet {} holds no secret", wallet.name));
+        }
+        let owner = wallet.address;
+        let memo = StakeMemo { owner, action }.to_memo();
+        let recipients: Vec<(Address, u64)> =
+            if pooled > 0 { vec![(staking_pool_address(), pooled)] } else { Vec::new() };
+        self.send_from(wallet, password, &[owner], &recipients, fee_rate, Some(memo)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dxid_core::builder::Utxo;
+    use dxid_core::staking::parse_stake_memo;
+
+    #[test]
+    fn staking_transactions_name_the_signing_owner() {
+        let store = crate::tests::test_store();
+        let mut wallet = store.create("staker", "pass").unwrap();
+        let utxo = Utxo { tx_hash: [4u8; 32], index: 0, address: wallet.address, amount: 50_000 };
+        let memo = StakeMemo {
+            owner: wallet.address,
+            action: StakeAction::Unstake { validator: [9u8; 32], amount: 10 },
+        };
+
+        let built =
+            store.sign_payment(&mut wallet, "pass", vec![utxo], &[], 1, Some(memo.to_memo())).unwrap();
+        assert_eq!(built.tx.outputs.len(), 1);
+        assert_eq!(built.tx.outputs[0].address, wallet.address);
+        assert_eq!(parse_stake_memo(built.tx.memo.as_deref().unwrap()), Some(memo));
+    }
+}
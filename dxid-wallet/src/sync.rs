@@ -0,0 +1,421 @@
+//! Follows the node's block stream and keeps a per-wallet record of coins,
+//! unconfirmed spends and transaction history.
+
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use dxid_core::builder::Utxo;
+use dxid_core::{Address, Block, BlockHash, CryptoProvider, Transaction, TxHash};
+use dxid_crypto::DefaultCryptoProvider;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{NodeClient, Wallet, WalletStore};
+
+/// Blocks kept with undo data; a deeper reorg forces a rescan from genesis.
+pub const MAX_REORG_DEPTH: usize = 100;
+
+/// A transaction that touched the wallet, from the wallet's point of view.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub hash: TxHash,
+    pub height: u64,
+    pub timestamp: u64,
+    /// Paid to the wallet's addresses.
+    pub received: u64,
+    /// Taken from the wallet's coins.
+    pub sent: u64,
+    pub fee: u64,
+    /// Wallet addresses the transaction paid or spent from.
+    #[serde(default)]
+    pub addresses: Vec<Address>,
+}
+
+/// A broadcast transaction not yet seen in a block.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingSpend {
+    pub hash: TxHash,
+    pub spends: Vec<(TxHash, u32)>,
+}
+
+/// What applying a block changed, kept so it can be undone on a reorg.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AppliedBlock {
+    height: u64,
+    hash: BlockHash,
+    created: Vec<Utxo>,
+    spent: Vec<Utxo>,
+    history: usize,
+    confirmed: Vec<PendingSpend>,
+}
+
+/// Outcome of feeding one block to [`SyncDb::apply_block`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockOutcome {
+    Applied,
+    /// Already applied; nothing changed.
+    Known,
+    /// The block does not extend the synced tip; roll back and refetch.
+    Reorg,
+}
+
+/// Local wallet database, stored as JSON next to the wallet files.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncDb {
+    #[serde(skip)]
+    path: PathBuf,
+    utxos: Vec<Utxo>,
+    pending: Vec<PendingSpend>,
+    history: Vec<HistoryEntry>,
+    recent: VecDeque<AppliedBlock>,
+}
+
+impl SyncDb {
+    /// Opens the database at `path`, starting empty if it does not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut db: Self = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => return Err(err.into()),
+        };
+        db.path = path;
+        Ok(db)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Height and hash of the last applied block.
+    pub fn tip(&self) -> Option<(u64, BlockHash)> {
+        self.recent.back().map(|b| (b.height, b.hash))
+    }
+
+    pub fn next_height(&self) -> u64 {
+        self.tip().map_or(0, |(height, _)| height + 1)
+    }
+
+    pub fn utxos(&self) -> &[Utxo] {
+        &self.utxos
+    }
+
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
+
+    pub fn pending(&self) -> &[PendingSpend] {
+        &self.pending
+    }
+
+    /// Sum of the wallet's coins in the synced chain.
+    pub fn confirmed_balance(&self) -> u64 {
+        self.utxos.iter().map(|u| u.amount).sum()
+    }
+
+    /// Confirmed balance held by `addresses`, e.g. one account's.
+    pub fn balance_for(&self, addresses: &HashSet<Address>) -> u64 {
+        self.utxos.iter().filter(|u| addresses.contains(&u.address)).map(|u| u.amount).sum()
+    }
+
+    /// History entries that touched any of `addresses`.
+    pub fn history_for<'a>(
+        &'a self,
+        addresses: &'a HashSet<Address>,
+    ) -> impl Iterator<Item = &'a HistoryEntry> + 'a {
+        self.history.iter().filter(|e| e.addresses.iter().any(|a| addresses.contains(a)))
+    }
+
+    /// Part of the confirmed balance already spent by pending transactions.
+    pub fn unconfirmed_spent(&self) -> u64 {
+        self.utxos.iter().filter(|u| self.is_pending_spend(u)).map(|u| u.amount).sum()
+    }
+
+    /// Coins not claimed by a pending transaction.
+    pub fn spendable(&self) -> Vec<Utxo> {
+        self.utxos.iter().filter(|u| !self.is_pending_spend(u)).cloned().collect()
+    }
+
+    /// Records a broadcast transaction so its inputs stop counting as spendable.
+    pub fn track_pending(&mut self, tx: &Transaction) {
+        let hash = tx.hash();
+        if self.pending.iter().all(|p| p.hash != hash) {
+            let spends = tx.inputs.iter().map(|i| (i.previous_tx, i.output_index)).collect();
+            self.pending.push(PendingSpend { hash, spends });
+        }
+    }
+
+    fn is_pending_spend(&self, utxo: &Utxo) -> bool {
+        self.pending.iter().any(|p| p.spends.contains(&(utxo.tx_hash, utxo.index)))
+    }
+
+    /// Applies `block` if it extends the tip, crediting outputs to
+    /// `addresses` and debiting the wallet coins its inputs spend.
+    pub fn apply_block(&mut self, block: &Block, addresses: &HashSet<Address>) -> Result<BlockOutcome> {
+        let height = block.header.height;
+        let hash = DefaultCryptoProvider::new().hash_block_header(&block.header);
+        if let Some((tip_height, tip_hash)) = self.tip() {
+            if height <= tip_height {
+                let known = self.recent.iter().any(|b| b.height == height && b.hash == hash);
+                return Ok(if known { BlockOutcome::Known } else { BlockOutcome::Reorg });
+            }
+            if height != tip_height + 1 {
+                return Err(anyhow!("expected block {}, got {height}", tip_height + 1));
+            }
+            if block.header.previous_hash != tip_hash {
+                return Ok(BlockOutcome::Reorg);
+            }
+        } else if height != 0 {
+            return Err(anyhow!("sync must start at genesis, got block {height}"));
+        }
+
+        let mut applied = AppliedBlock {
+            height,
+            hash,
+            created: Vec::new(),
+            spent: Vec::new(),
+            history: 0,
+            confirmed: Vec::new(),
+        };
+        for tx in &block.transactions {
+            let tx_hash = tx.hash();
+            let mut sent = 0u64;
+            let mut touched = Vec::new();
+            for input in &tx.inputs {
+                let outpoint = (input.previous_tx, input.output_index);
+                if let Some(pos) = self.utxos.iter().position(|u| (u.tx_hash, u.index) == outpoint) {
+                    let utxo = self.utxos.remove(pos);
+                    sent = sent.saturating_add(utxo.amount);
+                    touched.push(utxo.address);
+                    applied.spent.push(utxo);
+                }
+                // Confirmed, or double-spent by something else: either way no longer pending.
+                while let Some(pos) =
+                    self.pending.iter().position(|p| p.hash == tx_hash || p.spends.contains(&outpoint))
+                {
+                    applied.confirmed.push(self.pending.remove(pos));
+                }
+            }
+            let mut received = 0u64;
+            for (index, output) in tx.outputs.iter().enumerate() {
+                if addresses.contains(&output.address) {
+                    received = received.saturating_add(output.amount);
+                    touched.push(output.address);
+                    let utxo =
+                        Utxo { tx_hash, index: index as u32, address: output.address, amount: output.amount };
+                    self.utxos.push(utxo.clone());
+                    applied.created.push(utxo);
+                }
+            }
+            if sent > 0 || received > 0 {
+                touched.sort();
+                touched.dedup();
+                self.history.push(HistoryEntry {
+                    hash: tx_hash,
+                    height,
+                    timestamp: block.header.timestamp,
+                    received,
+                    sent,
+                    fee: if sent > 0 { tx.fee } else { 0 },
+                    addresses: touched,
+                });
+                applied.history += 1;
+            }
+        }
+        self.recent.push_back(applied);
+        while self.recent.len() > MAX_REORG_DEPTH {
+            self.recent.pop_front();
+        }
+        Ok(BlockOutcome::Applied)
+    }
+
+    /// Undoes the tip block. Transactions it confirmed become pending again
+    /// if the wallet broadcast them. Returns `false` once undo data runs out.
+    pub fn rollback(&mut self) -> bool {
+        let Some(block) = self.recent.pop_back() else {
+            return false;
+        };
+        self.utxos.retain(|u| !block.created.contains(u));
+        self.utxos.extend(block.spent);
+        self.history.truncate(self.history.len() - block.history);
+        self.pending.extend(block.confirmed);
+        true
+    }
+
+    /// Forgets everything so the next sync starts again from genesis.
+    /// Pending spends are kept; they are still in flight.
+    pub fn reset(&mut self) {
+        self.utxos.clear();
+        self.history.clear();
+        self.recent.clear();
+    }
+}
+
+/// Keeps one wallet's [`SyncDb`] in step with a node's block stream.
+pub struct WalletSync {
+    db: SyncDb,
+    addresses: HashSet<Address>,
+}
+
+impl WalletSync {
+    pub fn new(db: SyncDb, addresses: impl IntoIterator<Item = Address>) -> Self {
+        Self { db, addresses: addresses.into_iter().collect() }
+    }
+
+    pub fn db(&self) -> &SyncDb {
+        &self.db
+    }
+
+    /// Picks up addresses derived since the sync started.
+    pub fn watch(&mut self, addresses: impl IntoIterator<Item = Address>) {
+        self.addresses.extend(addresses);
+    }
+
+    /// Applies one block from the stream, rolling back one block on a fork
+    /// (or everything, past [`MAX_REORG_DEPTH`]). Returns whether the caller
+    /// must resubscribe from [`SyncDb::next_height`].
+    pub fn handle_block(&mut self, block: &Block) -> Result<bool> {
+        match self.db.apply_block(block, &self.addresses)? {
+            BlockOutcome::Applied => {
+                self.db.save()?;
+                Ok(false)
+            }
+            BlockOutcome::Known => Ok(false),
+            BlockOutcome::Reorg => {
+                warn!(height = block.header.height, "chain reorganised; rolling back wallet tip");
+                if !self.db.rollback() {
+                    warn!("reorg deeper than {MAX_REORG_DEPTH} blocks; rescanning from genesis");
+                    self.db.reset();
+                }
+                self.db.save()?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Follows `node`'s block subscription until the node closes it.
+    pub async fn follow(&mut self, node: &NodeClient) -> Result<()> {
+        'subscribe: loop {
+            let from = self.db.next_height();
+            info!(from, "subscribing to blocks");
+            let mut blocks = node.subscribe_blocks(from).await?;
+            while let Some(block) = blocks.next_block().await? {
+                if self.handle_block(&block)? {
+                    continue 'subscribe;
+                }
+            }
+            return Ok(());
+        }
+    }
+}
+
+impl WalletStore {
+    /// The sync engine for `wallet`, resuming from its local database.
+    pub fn open_sync(&self, wallet: &Wallet) -> Result<WalletSync> {
+        let db = SyncDb::open(self.sync_path(wallet))?;
+        Ok(WalletSync::new(db, wallet.addresses()))
+    }
+
+    pub(crate) fn sync_path(&self, wallet: &Wallet) -> PathBuf {
+        self.root.join("sync").join(format!("{}.json", wallet.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dxid_core::{BlockHeader, TxInput, TxOutput};
+
+    fn block(height: u64, previous_hash: BlockHash, transactions: Vec<Transaction>) -> Block {
+        Block {
+            header: BlockHeader {
+                previous_hash,
+                merkle_root: [0u8; 32],
+                height,
+                timestamp: height,
+                difficulty: 0,
+                nonce: 0,
+                validator: [0u8; 32],
+                stake_weight: 0,
+                state_root: [0u8; 32],
+            },
+            transactions,
+            pow_hash: [0u8; 32],
+            validator_signature: vec![],
+        }
+    }
+
+    fn hash(block: &Block) -> BlockHash {
+        DefaultCryptoProvider::new().hash_block_header(&block.header)
+    }
+
+    fn pay(from: Option<(TxHash, u32)>, to: Address, amount: u64, nonce: u64) -> Transaction {
+        let inputs = from
+            .map(|(previous_tx, output_index)| TxInput {
+                previous_tx,
+                output_index,
+                signature: vec![],
+                public_key: vec![],
+                witness: vec![],
+            })
+            .into_iter()
+            .collect();
+        Transaction {
+            inputs,
+            outputs: vec![TxOutput { address: to, amount, condition: None, spendable_after_height: None }],
+            fee: 1,
+            nonce,
+            memo: None,
+            lock_time: 0,
+        }
+    }
+
+    #[test]
+    fn tracks_history_and_rolls_back_on_reorg() {
+        let mine = [7u8; 32];
+        let addresses = HashSet::from([mine]);
+        let path = std::env::temp_dir().join(format!("dxid-sync-{}.json", std::process::id()));
+        let mut db = SyncDb::open(&path).unwrap();
+
+        let funding = pay(None, mine, 500, 0);
+        let genesis = block(0, [0u8; 32], vec![funding.clone()]);
+        assert_eq!(db.apply_block(&genesis, &addresses).unwrap(), BlockOutcome::Applied);
+        assert_eq!(db.confirmed_balance(), 500);
+
+        let spend = pay(Some((funding.hash(), 0)), [9u8; 32], 499, 1);
+        db.track_pending(&spend);
+        assert_eq!(db.unconfirmed_spent(), 500);
+        assert!(db.spendable().is_empty());
+
+        let one = block(1, hash(&genesis), vec![spend]);
+        db.apply_block(&one, &addresses).unwrap();
+        assert_eq!((db.confirmed_balance(), db.pending().len()), (0, 0));
+        assert_eq!(
+            db.history().iter().map(|h| (h.received, h.sent)).collect::<Vec<_>>(),
+            [(500, 0), (0, 500)]
+        );
+        db.save().unwrap();
+
+        // A competing block 1 that drops the spend.
+        let mut db = SyncDb::open(&path).unwrap();
+        let mut fork = block(1, hash(&genesis), vec![]);
+        fork.header.nonce = 1;
+        assert_eq!(db.apply_block(&fork, &addresses).unwrap(), BlockOutcome::Reorg);
+        assert!(db.rollback());
+        assert_eq!(db.apply_block(&fork, &addresses).unwrap(), BlockOutcome::Applied);
+        assert_eq!((db.confirmed_balance(), db.unconfirmed_spent()), (500, 500));
+        assert_eq!(db.history().len(), 1);
+
+        let orphan = block(2, hash(&one), vec![]);
+        assert_eq!(db.apply_block(&orphan, &addresses).unwrap(), BlockOutcome::Reorg);
+        let _ = fs::remove_file(path);
+    }
+}
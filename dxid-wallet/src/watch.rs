@@ -0,0 +1,146 @@
+//! Watch-only wallets: addresses and public keys without secret material.
+//!
+//! ed25519 HD keys only derive hardened children, so there is no public
+//! derivation; the `dxpub` export lists the derived public keys instead.
+
+use anyhow::{anyhow, Result};
+use dxid_core::builder::{BuiltTx, TxBuilder};
+use dxid_core::{Address, Transaction, TxHash};
+use serde::{Deserialize, Serialize};
+
+use crate::{build_address_from_public_key, KeyLocation, Wallet, WalletStore};
+
+const XPUB_PREFIX: &str = "dxpub";
+
+/// What a watch-only wallet is imported from.
+#[derive(Debug, Clone)]
+pub enum WatchSource {
+    PublicKey(Vec<u8>),
+    Addresses(Vec<Address>),
+    /// A `dxpub` string from [`WalletStore::export_xpub`].
+    Xpub(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WatchedAddress {
+    pub address: Address,
+    /// Known when imported from a key; lets external signers match inputs.
+    #[serde(default)]
+    pub public_key: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchState {
+    pub addresses: Vec<WatchedAddress>,
+}
+
+impl WalletStore {
+    /// Saves a wallet that can track and build transactions for `source`
+    /// but holds no secrets.
+    pub fn import_watch_only(&self, name: &str, source: WatchSource) -> Result<Wallet> {
+        self.check_new(name)?;
+        let addresses = match source {
+            WatchSource::PublicKey(public_key) => vec![WatchedAddress {
+                address: build_address_from_public_key(&public_key)?,
+                public_key: Some(public_key),
+            }],
+            WatchSource::Addresses(addresses) => {
+                addresses.into_iter().map(|address| WatchedAddress { address, public_key: None }).collect()
+            }
+            WatchSource::Xpub(xpub) => decode_xpub(&xpub)?,
+        };
+        let first = addresses.first().ok_or_else(|| anyhow!("nothing to watch"))?;
+        let wallet = Wallet {
+            name: name.to_string(),
+            address: first.address,
+            public_key: first.public_key.clone().unwrap_or_default(),
+            encrypted_secret: vec![],
+            nonce: [0u8; 12],
+            kdf: self.kdf,
+            key_location: KeyLocation::File,
+            hd: None,
+            watch: Some(WatchState { addresses }),
+            multisig: None,
+            multisig_output: None,
+            contacts: Vec::new(),
+            identity: None,
+        };
+        self.save(&wallet)?;
+        Ok(wallet)
+    }
+
+    /// Public keys of every address `wallet` has derived, for importing as
+    /// watch-only elsewhere.
+    pub fn export_xpub(&self, wallet: &Wallet) -> Result<String> {
+        let addresses: Vec<WatchedAddress> = match (&wallet.hd, &wallet.watch) {
+            (Some(hd), _) => hd
+                .addresses
+                .iter()
+                .map(|a| WatchedAddress { address: a.address, public_key: Some(a.public_key.clone()) })
+                .collect(),
+            (None, Some(watch)) => watch.addresses.clone(),
+            (None, None) => {
+                vec![WatchedAddress { address: wallet.address, public_key: Some(wallet.public_key.clone()) }]
+            }
+        };
+        let encoded = bs58::encode(serde_json::to_vec(&addresses)?).into_string();
+        Ok(format!("{XPUB_PREFIX}{encoded}"))
+    }
+
+    /// Builds an unsigned transaction from the node's view of `wallet`'s
+    /// coins, for signing by whoever holds the keys.
+    pub async fn build_unsigned_tx(
+        &self,
+        wallet: &Wallet,
+        recipients: &[(Address, u64)],
+        fee_rate: u64,
+    ) -> Result<BuiltTx> {
+        let node = self.node()?;
+        let mut builder = TxBuilder::new(wallet.address)
+            .fee_rate(fee_rate)
+            .nonce(dxid_core::random_nonce())
+            .selector(self.coin_selector.clone());
+        for address in wallet.addresses() {
+            builder = builder.utxos(node.utxos(&address).await?);
+        }
+        for (address, amount) in recipients {
+            builder = builder.pay(*address, *amount);
+        }
+        builder.build()
+    }
+
+    /// Builds `wallet`'s payment to `recipients` without signing or sending
+    /// it, to show the fee and change up front. Uses the node's fee estimate
+    /// unless `fee_rate` is given.
+    pub async fn dry_run(
+        &self,
+        wallet: &Wallet,
+        recipients: &[(Address, u64)],
+        fee_rate: Option<u64>,
+    ) -> Result<BuiltTx> {
+        let fee_rate = match fee_rate {
+            Some(rate) => rate,
+            None => self.node()?.fee_estimate().await?,
+        };
+        self.build_unsigned_tx(wallet, recipients, fee_rate).await
+    }
+
+    /// Submits a transaction signed elsewhere.
+    pub async fn broadcast(&self, tx: &Transaction) -> Result<TxHash> {
+        self.node()?.submit_tx(tx).await
+    }
+}
+
+fn decode_xpub(xpub: &str) -> Result<Vec<WatchedAddress>> {
+    let body = xpub.strip_prefix(XPUB_PREFIX).ok_or_else(|| anyhow!("not a {XPUB_PREFIX} string"))?;
+    let bytes = bs58::decode(body).into_vec()?;
+    let addresses: Vec<WatchedAddress> = serde_json::from_slice(&bytes)?;
+    for watched in &addresses {
+        if let Some(pk) = &watched.public_key {
+            if build_address_from_public_key(pk)? != watched.address {
+                return Err(anyhow!("{XPUB_PREFIX} key does not match its address"));
+            }
+        }
+    }
+    Ok(addresses)
+}